@@ -6,11 +6,10 @@
 // of this source tree.
 
 //! An implementation of an append-only zero knowledge set
-use crate::errors::ParallelismError;
-use crate::errors::TreeNodeError;
+use crate::errors::{StorageError, TreeNodeError};
 use crate::helper_structs::LookupInfo;
 use crate::storage::manager::StorageManager;
-use crate::storage::types::StorageType;
+use crate::storage::types::{DbRecord, StorageType};
 use crate::{
     errors::{AkdError, DirectoryError},
     storage::{Database, Storable},
@@ -19,13 +18,18 @@ use crate::{
     NonMembershipProof, SingleAppendOnlyProof, ARITY, DIRECTIONS, EMPTY_LABEL,
 };
 
+#[cfg(feature = "protobuf")]
+use crate::errors::AuditorError;
+use crate::logging::info;
 use akd_core::hash::EMPTY_DIGEST;
 use akd_core::SizeOf;
 use async_recursion::async_recursion;
-use log::info;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::marker::Sync;
 use std::ops::Deref;
+#[cfg(feature = "protobuf")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 /// The default azks key
 pub const DEFAULT_AZKS_KEY: u8 = 1u8;
@@ -35,6 +39,12 @@ pub const DEFAULT_AZKS_KEY: u8 = 1u8;
 #[cfg(feature = "parallel_insert")]
 pub const DEFAULT_AVAILABLE_PARALLELISM: usize = 32;
 
+/// The minimum number of leaves of a subtree made only of new leaves for it to
+/// be built and hashed on the blocking thread pool during a batch insertion,
+/// rather than node by node on the inserting task
+#[cfg(feature = "parallel_hashing")]
+pub const PARALLEL_HASHING_MIN_LEAVES: usize = 256;
+
 async fn tic_toc<T>(f: impl core::future::Future<Output = T>) -> (T, Option<f64>) {
     #[cfg(feature = "runtime_metrics")]
     {
@@ -282,12 +292,56 @@ impl Azks {
     }
 
     /// Insert a batch of new leaves.
+    ///
+    /// The modified tree nodes are written atomically. If the caller already
+    /// has a storage transaction active, they are added to it. Otherwise a
+    /// transaction is started here and committed along with the updated azks
+    /// record, so that a failure part way through the insertion leaves both
+    /// the stored tree and this azks untouched.
+    ///
+    /// Returns the number of node hashes computed. The ancestors shared by
+    /// several of the new leaves are only hashed once, reusing the children just
+    /// updated by the insertion rather than reading them back from storage.
     pub async fn batch_insert_nodes<S: Database + 'static>(
         &mut self,
         storage: &StorageManager<S>,
         nodes: Vec<Node>,
         insert_mode: InsertMode,
-    ) -> Result<(), AkdError> {
+    ) -> Result<u64, AkdError> {
+        if !storage.begin_transaction() {
+            // part of a transaction managed by the caller
+            return self
+                .batch_insert_nodes_helper(storage, nodes, insert_mode)
+                .await;
+        }
+
+        let original = self.clone();
+        let mut result = self
+            .batch_insert_nodes_helper(storage, nodes, insert_mode)
+            .await;
+        if result.is_ok() {
+            if let Err(err) = storage.set(DbRecord::Azks(self.clone())).await {
+                result = Err(AkdError::from(err));
+            }
+        }
+        if result.is_ok() {
+            if let Err(err) = storage.commit_transaction().await {
+                result = Err(AkdError::from(err));
+            }
+        }
+        if result.is_err() {
+            let _ = storage.rollback_transaction();
+            *self = original;
+        }
+        result
+    }
+
+    async fn batch_insert_nodes_helper<S: Database + 'static>(
+        &mut self,
+        storage: &StorageManager<S>,
+        nodes: Vec<Node>,
+        insert_mode: InsertMode,
+    ) -> Result<u64, AkdError> {
         let node_set = NodeSet::from(nodes);
 
         // preload the nodes that we will visit during the insertion
@@ -299,42 +353,62 @@ impl Azks {
         // increment the current epoch
         self.increment_epoch();
 
+        let mut num_hashed = 0;
         if !node_set.is_empty() {
             // call recursive batch insert on the root
-            let (root_node, is_new, num_inserted) = Self::recursive_batch_insert_nodes(
-                storage,
-                Some(NodeLabel::root()),
-                node_set,
-                self.latest_epoch,
-                insert_mode,
-                get_parallel_levels(),
-            )
-            .await?;
+            let (root_node, is_new, num_inserted, num_subtree_hashed) =
+                Self::recursive_batch_insert_nodes(
+                    storage,
+                    Some(NodeLabel::root()),
+                    node_set,
+                    self.latest_epoch,
+                    self.latest_epoch - 1,
+                    insert_mode,
+                    get_parallel_levels(),
+                )
+                .await?;
             root_node.write_to_storage(storage, is_new).await?;
 
             // update the number of nodes
             self.num_nodes += num_inserted;
+            num_hashed = num_subtree_hashed;
 
-            info!("Batch insert completed ({} new nodes)", num_inserted);
+            info!(
+                "Batch insert completed ({} new nodes, {} node hashes computed)",
+                num_inserted, num_hashed
+            );
         }
 
-        Ok(())
+        Ok(num_hashed)
     }
 
     /// Inserts a batch of leaves recursively from a given node label. Note: it
     /// is the caller's responsibility to write the returned node to storage.
     /// This is done so that the caller may set the 'parent' field of a node
     /// before it is written to storage. The is_new flag indicates whether the
-    /// returned node is new or not.
+    /// returned node is new or not. The node is returned along with the number
+    /// of nodes inserted and the number of node hashes computed in the subtree.
+    /// The existing nodes are read as of `read_epoch`, which is the last
+    /// committed epoch when inserting a new epoch: a node stored at the epoch
+    /// being inserted can then only be left over from an insertion which
+    /// crashed before committing, and must not be built upon.
     #[async_recursion]
     pub(crate) async fn recursive_batch_insert_nodes<S: Database + 'static>(
         storage: &StorageManager<S>,
         node_label: Option<NodeLabel>,
         node_set: NodeSet,
         epoch: u64,
+        read_epoch: u64,
         insert_mode: InsertMode,
         parallel_levels: Option<u8>,
-    ) -> Result<(TreeNode, bool, u64), AkdError> {
+    ) -> Result<(TreeNode, bool, u64, u64), AkdError> {
+        // A subtree made only of new leaves doesn't depend on anything in
+        // storage, so a large one is built in memory off the async task
+        #[cfg(feature = "parallel_hashing")]
+        if node_label.is_none() && node_set.len() >= PARALLEL_HASHING_MIN_LEAVES {
+            return Self::insert_new_subtree(storage, node_set, epoch, insert_mode).await;
+        }
+
         // Phase 1: Obtain the current root node of this subtree. If the node is
         // new, mark it as so and count it towards the number of inserted nodes.
         let mut current_node;
@@ -346,7 +420,7 @@ impl Azks {
                 // Case 1: The node label is not None, meaning that there was an
                 // existing node at this level of the tree.
                 let mut existing_node =
-                    TreeNode::get_from_storage(storage, &NodeKey(node_label), epoch).await?;
+                    TreeNode::get_from_storage(storage, &NodeKey(node_label), read_epoch).await?;
 
                 // compute the longest common prefix between all nodes in the
                 // node set and the current node, and check if new nodes
@@ -396,6 +470,9 @@ impl Azks {
         // nodes are located in with respect to the current node and call this
         // function recursively on the left and right child nodes. The current
         // node is updated with the new child nodes.
+        let mut num_hashed = 0;
+        let mut left_child = None;
+        let mut right_child = None;
         let (left_node_set, right_node_set) = node_set.partition(current_node.label);
         let child_parallel_levels =
             parallel_levels.and_then(|x| if x <= 1 { None } else { Some(x - 1) });
@@ -410,6 +487,7 @@ impl Azks {
                     left_child_label,
                     left_node_set,
                     epoch,
+                    read_epoch,
                     insert_mode,
                     child_parallel_levels,
                 )
@@ -419,14 +497,17 @@ impl Azks {
             if parallel_levels.is_some() {
                 // spawn a task and return the handle if there are still levels
                 // to be processed in parallel
-                Some(tokio::task::spawn(left_future))
+                Some(crate::runtime::spawn(left_future))
             } else {
                 // else handle the left child in the current task
-                let (mut left_node, left_is_new, left_num_inserted) = left_future.await?;
+                let (mut left_node, left_is_new, left_num_inserted, left_num_hashed) =
+                    left_future.await?;
 
                 current_node.set_child(&mut left_node)?;
                 left_node.write_to_storage(storage, left_is_new).await?;
                 num_inserted += left_num_inserted;
+                num_hashed += left_num_hashed;
+                left_child = Some(left_node);
                 None
             }
         } else {
@@ -436,12 +517,13 @@ impl Azks {
         // handle the right child in the current task
         if !right_node_set.is_empty() {
             let right_child_label = current_node.get_child_label(Direction::Right)?;
-            let (mut right_node, right_is_new, right_num_inserted) =
+            let (mut right_node, right_is_new, right_num_inserted, right_num_hashed) =
                 Azks::recursive_batch_insert_nodes(
                     storage,
                     right_child_label,
                     right_node_set,
                     epoch,
+                    read_epoch,
                     insert_mode,
                     child_parallel_levels,
                 )
@@ -450,25 +532,178 @@ impl Azks {
             current_node.set_child(&mut right_node)?;
             right_node.write_to_storage(storage, right_is_new).await?;
             num_inserted += right_num_inserted;
+            num_hashed += right_num_hashed;
+            right_child = Some(right_node);
         }
 
         // join on the handle for the left child, if present
         if let Some(handle) = maybe_handle {
-            let (mut left_node, left_is_new, left_num_inserted) = handle
-                .await
-                .map_err(|e| AkdError::Parallelism(ParallelismError::JoinErr(e.to_string())))??;
+            let (mut left_node, left_is_new, left_num_inserted, left_num_hashed) =
+                handle.join().await??;
             current_node.set_child(&mut left_node)?;
             left_node.write_to_storage(storage, left_is_new).await?;
             num_inserted += left_num_inserted;
+            num_hashed += left_num_hashed;
+            left_child = Some(left_node);
         }
 
         // Phase 3: Update the hash of the current node and return it along with
-        // the number of nodes inserted.
-        current_node
-            .update_node_hash::<_>(storage, NodeHashingMode::from(insert_mode))
+        // the number of nodes inserted. The children updated above are reused, and
+        // only the ones the insertion didn't touch are read from storage.
+        if current_node.node_type != NodeType::Leaf {
+            if left_child.is_none() {
+                left_child = current_node
+                    .get_child_node(storage, Direction::Left, read_epoch)
+                    .await?;
+            }
+            if right_child.is_none() {
+                right_child = current_node
+                    .get_child_node(storage, Direction::Right, read_epoch)
+                    .await?;
+            }
+            current_node.set_hash_from_children(
+                &left_child,
+                &right_child,
+                NodeHashingMode::from(insert_mode),
+            );
+            num_hashed += 1;
+        }
+
+        Ok((current_node, is_new, num_inserted, num_hashed))
+    }
+
+    /// Bulk-builds the tree from a set of leaves, for an azks which has no
+    /// leaves yet. Rather than inserting the leaves one level at a time and
+    /// re-reading ancestors from storage, the tree is assembled bottom-up in
+    /// memory (in sorted label order) so that each node is hashed exactly once,
+    /// and the resulting nodes are written to storage in batches of
+    /// `batch_size` records. The root node is written last.
+    pub async fn bulk_insert_nodes<S: Database + 'static>(
+        &mut self,
+        storage: &StorageManager<S>,
+        nodes: Vec<Node>,
+        insert_mode: InsertMode,
+        batch_size: usize,
+    ) -> Result<(), AkdError> {
+        if self.latest_epoch != 0 || self.num_nodes != 1 {
+            return Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+                "Bulk insertion requires an empty tree, but the tree is at epoch {} with {} nodes",
+                self.latest_epoch, self.num_nodes
+            ))));
+        }
+
+        self.increment_epoch();
+        let epoch = self.latest_epoch;
+        let hash_mode = NodeHashingMode::from(insert_mode);
+
+        let mut root_node =
+            TreeNode::get_from_storage(storage, &NodeKey(NodeLabel::root()), epoch - 1).await?;
+        let mut built_nodes = Vec::<TreeNode>::new();
+
+        let node_set = NodeSet::from(nodes);
+        if !node_set.is_empty() {
+            let (left_node_set, right_node_set) = node_set.partition(root_node.label);
+            let ((mut left_child, left_nodes), (mut right_child, right_nodes)) =
+                futures_util::future::try_join(
+                    Self::build_new_subtree(left_node_set, epoch, hash_mode),
+                    Self::build_new_subtree(right_node_set, epoch, hash_mode),
+                )
+                .await?;
+            built_nodes.extend(left_nodes);
+            built_nodes.extend(right_nodes);
+            for child in left_child.iter_mut().chain(right_child.iter_mut()) {
+                root_node.set_child(child)?;
+                built_nodes.push(child.clone());
+            }
+            root_node.set_hash_from_children(&left_child, &right_child, hash_mode);
+        }
+        let num_inserted = built_nodes.len() as u64;
+
+        for chunk in built_nodes.chunks(std::cmp::max(batch_size, 1)) {
+            storage.batch_set(new_tree_node_records(chunk)).await?;
+        }
+        root_node.write_to_storage(storage, false).await?;
+
+        self.num_nodes += num_inserted;
+        info!("Bulk insert completed ({} new nodes)", num_inserted);
+
+        Ok(())
+    }
+
+    /// Builds the subtree holding the given new leaves (see
+    /// [Azks::bulk_build_subtree]), returning its root along with its other
+    /// nodes. With the `parallel_hashing` feature, the subtree is built on the
+    /// blocking thread pool, so that hashing a large subtree doesn't hold up the
+    /// async runtime.
+    async fn build_new_subtree(
+        node_set: NodeSet,
+        epoch: u64,
+        hash_mode: NodeHashingMode,
+    ) -> Result<(Option<TreeNode>, Vec<TreeNode>), AkdError> {
+        let build = move || {
+            let mut built_nodes = Vec::new();
+            Self::bulk_build_subtree(node_set, epoch, hash_mode, &mut built_nodes)
+                .map(|subtree_root| (subtree_root, built_nodes))
+        };
+
+        #[cfg(feature = "parallel_hashing")]
+        {
+            crate::runtime::spawn_blocking(build).await?
+        }
+        #[cfg(not(feature = "parallel_hashing"))]
+        build()
+    }
+
+    /// Inserts a subtree made only of new leaves, building it in memory and
+    /// writing all of its nodes but the subtree root to storage in a single
+    /// batch. The subtree root is returned as by
+    /// [Azks::recursive_batch_insert_nodes].
+    #[cfg(feature = "parallel_hashing")]
+    async fn insert_new_subtree<S: Database + 'static>(
+        storage: &StorageManager<S>,
+        node_set: NodeSet,
+        epoch: u64,
+        insert_mode: InsertMode,
+    ) -> Result<(TreeNode, bool, u64, u64), AkdError> {
+        // each interior node of the subtree is hashed once
+        let num_hashed = node_set.len() as u64 - 1;
+        let (subtree_root, built_nodes) =
+            Self::build_new_subtree(node_set, epoch, NodeHashingMode::from(insert_mode)).await?;
+        // the node set is never empty, so neither is the subtree
+        let subtree_root = subtree_root.expect("Subtree built from an empty node set");
+        storage
+            .batch_set(new_tree_node_records(&built_nodes))
             .await?;
+        Ok((subtree_root, true, built_nodes.len() as u64 + 1, num_hashed))
+    }
+
+    /// Builds the subtree holding the given leaves entirely in memory, pushing
+    /// all of its nodes except the subtree root into `built_nodes`. The
+    /// returned subtree root has its hash computed but its parent unset.
+    fn bulk_build_subtree(
+        node_set: NodeSet,
+        epoch: u64,
+        hash_mode: NodeHashingMode,
+        built_nodes: &mut Vec<TreeNode>,
+    ) -> Result<Option<TreeNode>, AkdError> {
+        let mut current_node = match &node_set[..] {
+            [] => return Ok(None),
+            [node] => return Ok(Some(new_leaf_node(node.label, &node.hash, epoch))),
+            _ => new_interior_node(node_set.get_longest_common_prefix(), epoch),
+        };
 
-        Ok((current_node, is_new, num_inserted))
+        let (left_node_set, right_node_set) = node_set.partition(current_node.label);
+        let mut left_child =
+            Self::bulk_build_subtree(left_node_set, epoch, hash_mode, built_nodes)?;
+        let mut right_child =
+            Self::bulk_build_subtree(right_node_set, epoch, hash_mode, built_nodes)?;
+        for child in left_child.iter_mut().chain(right_child.iter_mut()) {
+            current_node.set_child(child)?;
+            built_nodes.push(child.clone());
+        }
+        current_node.set_hash_from_children(&left_child, &right_child, hash_mode);
+
+        Ok(Some(current_node))
     }
 
     pub(crate) async fn preload_lookup_nodes<S: Database + Send + Sync>(
@@ -476,19 +711,31 @@ impl Azks {
         storage: &StorageManager<S>,
         lookup_infos: &[LookupInfo],
     ) -> Result<u64, AkdError> {
-        // Collect lookup labels needed and convert them into Nodes for preloading.
-        let lookup_nodes: Vec<Node> = lookup_infos
+        // Collect lookup labels needed for preloading.
+        let lookup_labels: Vec<NodeLabel> = lookup_infos
             .iter()
             .flat_map(|li| vec![li.existent_label, li.marker_label, li.non_existent_label])
+            .collect();
+
+        self.preload_labels(storage, &lookup_labels).await
+    }
+
+    /// Preloads the nodes on the paths to the given labels, in a single breadth-first search
+    pub(crate) async fn preload_labels<S: Database>(
+        &self,
+        storage: &StorageManager<S>,
+        labels: &[NodeLabel],
+    ) -> Result<u64, AkdError> {
+        let nodes: Vec<Node> = labels
+            .iter()
             .map(|l| Node {
-                label: l,
+                label: *l,
                 hash: EMPTY_DIGEST,
             })
             .collect();
 
         // Load nodes.
-        self.preload_nodes(storage, &NodeSet::from(lookup_nodes))
-            .await
+        self.preload_nodes(storage, &NodeSet::from(nodes)).await
     }
 
     /// Preloads given nodes using breadth-first search.
@@ -517,20 +764,7 @@ impl Azks {
             current_nodes = nodes
                 .iter()
                 .filter(|node| node_set.contains_prefix(&node.label))
-                .flat_map(|node| {
-                    DIRECTIONS
-                        .iter()
-                        .filter_map(|dir| {
-                            // TODO (Issue #314): Migrate away from a panic in favor of a compile-time
-                            // error for an invalid directional state.
-                            node.get_child_label(*dir)
-                                .unwrap_or_else(|_| {
-                                    panic!("Attempted to load an invalid direction: {:?}", dir)
-                                })
-                                .map(NodeKey)
-                        })
-                        .collect::<Vec<NodeKey>>()
-                })
+                .flat_map(|node| node.children().map(NodeKey))
                 .collect();
         }
 
@@ -551,10 +785,48 @@ impl Azks {
         Ok(pf)
     }
 
-    // EOZ: There is a needless_range_loop warning by Clippy for `for i in 0..ARITY`
-    // and the suggestion is to use `for (i, <item>) in longest_prefix_children.iter_mut().enumerate().take(ARITY)`
-    // but I think this is inaccurate
-    #[allow(clippy::needless_range_loop)]
+    /// Returns the Merkle membership proof of a label against the root hash of the
+    /// given past epoch. Only possible while the states of the tree nodes at the
+    /// epoch are retained, which is in general the case for the two most recent
+    /// epochs, and fails with [DirectoryError::InvalidEpoch] otherwise.
+    pub async fn get_membership_proof_at_epoch<S: Database>(
+        &self,
+        storage: &StorageManager<S>,
+        label: NodeLabel,
+        epoch: u64,
+    ) -> Result<MembershipProof, AkdError> {
+        let nodes = self.load_proof_nodes(storage, label, epoch).await?;
+        let (pf, _) = Self::get_loaded_membership_proof_and_node(&nodes, label)?;
+        Ok(pf)
+    }
+
+    /// Returns the label of the leaf reached by descending the tree from the root
+    /// along the bits of the target label, taking the only child of a node with a
+    /// single child, or None if the tree has no leaves. See [crate::SampleAuditProof].
+    pub(crate) async fn get_sample_leaf<S: Database>(
+        &self,
+        storage: &StorageManager<S>,
+        target: NodeLabel,
+    ) -> Result<Option<NodeLabel>, AkdError> {
+        let mut label = NodeLabel::root();
+        loop {
+            let node =
+                TreeNode::get_from_storage(storage, &NodeKey(label), self.get_latest_epoch())
+                    .await?;
+            if node.node_type == NodeType::Leaf {
+                return Ok(Some(node.label));
+            }
+            let (toward, away) = match target.get_bit_at(node.label.get_len()) {
+                0u8 => (node.left(), node.right()),
+                _ => (node.right(), node.left()),
+            };
+            match toward.or(away) {
+                Some(child) => label = child,
+                None => return Ok(None),
+            }
+        }
+    }
+
     /// In a compressed trie, the proof consists of the longest prefix
     /// of the label that is included in the trie, as well as its children, to show that
     /// none of the children is equal to the given label.
@@ -563,11 +835,26 @@ impl Azks {
         storage: &StorageManager<S>,
         label: NodeLabel,
     ) -> Result<NonMembershipProof, AkdError> {
+        self.get_non_membership_proof_at_epoch(storage, label, self.get_latest_epoch())
+            .await
+    }
+
+    // EOZ: There is a needless_range_loop warning by Clippy for `for i in 0..ARITY`
+    // and the suggestion is to use `for (i, <item>) in longest_prefix_children.iter_mut().enumerate().take(ARITY)`
+    // but I think this is inaccurate
+    #[allow(clippy::needless_range_loop)]
+    /// Returns the non-membership proof of a label against the root hash of the
+    /// given past epoch, see [Azks::get_membership_proof_at_epoch].
+    pub async fn get_non_membership_proof_at_epoch<S: Database>(
+        &self,
+        storage: &StorageManager<S>,
+        label: NodeLabel,
+        epoch: u64,
+    ) -> Result<NonMembershipProof, AkdError> {
+        let nodes = self.load_proof_nodes(storage, label, epoch).await?;
         let (longest_prefix_membership_proof, lcp_node_label) =
-            self.get_membership_proof_and_node(storage, label).await?;
-        let lcp_node: TreeNode =
-            TreeNode::get_from_storage(storage, &NodeKey(lcp_node_label), self.get_latest_epoch())
-                .await?;
+            Self::get_loaded_membership_proof_and_node(&nodes, label)?;
+        let lcp_node = get_loaded_node(&nodes, lcp_node_label)?;
         let longest_prefix = lcp_node.label;
         // load with placeholder nodes, to be replaced in the loop below
         let mut longest_prefix_children = [Node {
@@ -575,25 +862,11 @@ impl Azks {
             hash: crate::utils::empty_node_hash(),
         }; ARITY];
         for dir in DIRECTIONS {
-            let child = lcp_node
-                .get_child_node(storage, dir, self.latest_epoch)
-                .await?;
-            match child {
-                None => {
-                    continue;
-                }
-                Some(child) => {
-                    let unwrapped_child: TreeNode = TreeNode::get_from_storage(
-                        storage,
-                        &NodeKey(child.label),
-                        self.get_latest_epoch(),
-                    )
-                    .await?;
-                    longest_prefix_children[dir as usize] = Node {
-                        label: unwrapped_child.label,
-                        hash: optional_child_state_hash(&Some(unwrapped_child)),
-                    };
-                }
+            if let Some(child) = get_loaded_child_node(&nodes, lcp_node, dir)? {
+                longest_prefix_children[dir as usize] = Node {
+                    label: child.label,
+                    hash: optional_child_state_hash(&Some(child.clone())),
+                };
             }
         }
 
@@ -635,14 +908,15 @@ impl Azks {
         .await?;
 
         for ep in start_epoch..end_epoch {
-            let (fallable_load_count, time_s) = tic_toc(self.gather_audit_proof_nodes::<_>(
+            let (fallable_loaded_nodes, time_s) = tic_toc(self.gather_audit_proof_nodes::<_>(
                 vec![node.clone()],
                 storage,
                 ep,
                 ep + 1,
             ))
             .await;
-            let load_count = fallable_load_count?;
+            let loaded_nodes = fallable_loaded_nodes?;
+            let load_count = loaded_nodes.len();
             if let Some(time) = time_s {
                 info!(
                     "Preload of nodes for audit ({} objects loaded), took {} s",
@@ -656,9 +930,8 @@ impl Azks {
             }
             storage.log_metrics(log::Level::Info).await;
 
-            let (unchanged, leaves) = self
-                .get_append_only_proof_helper::<_>(storage, node.clone(), ep, ep + 1)
-                .await?;
+            let (unchanged, leaves) =
+                Self::get_append_only_proof_helper(&loaded_nodes, &node, ep, ep + 1)?;
             proofs.push(SingleAppendOnlyProof {
                 inserted: leaves,
                 unchanged_nodes: unchanged,
@@ -686,28 +959,26 @@ impl Azks {
             return vec![];
         }
 
-        match (node.left_child, node.right_child) {
-            (Some(lc), None) => vec![lc],
-            (None, Some(rc)) => vec![rc],
-            (Some(lc), Some(rc)) => vec![lc, rc],
-            _ => vec![],
-        }
+        node.children().collect()
     }
 
+    /// Loads all the nodes which are visited when generating an append-only
+    /// proof between the given epochs, with one batch retrieval per level of
+    /// the tree
     async fn gather_audit_proof_nodes<S: Database>(
         &self,
         nodes: Vec<TreeNode>,
         storage: &StorageManager<S>,
         start_epoch: u64,
         end_epoch: u64,
-    ) -> Result<u64, AkdError> {
+    ) -> Result<ProofNodes, AkdError> {
         let mut children_to_fetch: Vec<NodeKey> = nodes
             .iter()
             .flat_map(|node| Self::determine_retrieval_nodes(node, start_epoch, end_epoch))
             .map(NodeKey)
             .collect();
 
-        let mut element_count = 0u64;
+        let mut loaded_nodes = ProofNodes::new();
         while !children_to_fetch.is_empty() {
             let got = TreeNode::batch_get_from_storage(
                 storage,
@@ -715,21 +986,19 @@ impl Azks {
                 self.get_latest_epoch(),
             )
             .await?;
-            element_count += got.len() as u64;
             children_to_fetch = got
                 .iter()
                 .flat_map(|node| Self::determine_retrieval_nodes(node, start_epoch, end_epoch))
                 .map(NodeKey)
                 .collect();
+            loaded_nodes.extend(got.into_iter().map(|node| (node.label, node)));
         }
-        Ok(element_count)
+        Ok(loaded_nodes)
     }
 
-    #[async_recursion]
-    async fn get_append_only_proof_helper<S: Database>(
-        &self,
-        storage: &StorageManager<S>,
-        node: TreeNode,
+    fn get_append_only_proof_helper(
+        loaded_nodes: &ProofNodes,
+        node: &TreeNode,
         start_epoch: u64,
         end_epoch: u64,
     ) -> Result<AppendOnlyHelper, AkdError> {
@@ -743,7 +1012,7 @@ impl Azks {
             }
             unchanged.push(Node {
                 label: node.label,
-                hash: optional_child_state_hash(&Some(node)),
+                hash: optional_child_state_hash(&Some(node.clone())),
             });
 
             return Ok((unchanged, leaves));
@@ -759,33 +1028,154 @@ impl Azks {
                 hash: node.hash,
             });
         } else {
-            for child_label in [node.left_child, node.right_child] {
-                match child_label {
-                    None => {
-                        continue;
+            for child_label in node.children() {
+                let child_node = get_loaded_node(loaded_nodes, child_label)?;
+                let (mut inner_unchanged, mut inner_leaf) = Self::get_append_only_proof_helper(
+                    loaded_nodes,
+                    child_node,
+                    start_epoch,
+                    end_epoch,
+                )?;
+                unchanged.append(&mut inner_unchanged);
+                leaves.append(&mut inner_leaf);
+            }
+        }
+        Ok((unchanged, leaves))
+    }
+
+    /// Writes the append-only proof for going from `start_epoch` to `end_epoch` to
+    /// a sink, in the format read by [crate::auditor::read_append_only_proof]. The
+    /// proof is the same as generated by [Azks::get_append_only_proof], but the tree
+    /// is walked in label order, loading the nodes in batches and writing out the
+    /// nodes of the proof as it goes, so that at most (approximately)
+    /// `max_memory_bytes` of nodes are held in memory.
+    #[cfg(feature = "protobuf")]
+    pub async fn write_append_only_proof<S: Database, W: AsyncWrite + Unpin>(
+        &self,
+        storage: &StorageManager<S>,
+        start_epoch: u64,
+        end_epoch: u64,
+        max_memory_bytes: usize,
+        writer: &mut W,
+    ) -> Result<(), AkdError> {
+        let root = TreeNode::get_from_storage(
+            storage,
+            &NodeKey(NodeLabel::root()),
+            self.get_latest_epoch(),
+        )
+        .await?;
+        // half of the budget holds the loaded tree nodes, and the other half the
+        // nodes of the proof waiting to be written
+        let max_loaded_nodes = std::cmp::max(max_memory_bytes / 2 / root.size_of(), 2);
+        let proof_node_size = NodeLabel::root().size_of() + crate::DIGEST_BYTES;
+        let max_proof_nodes = std::cmp::max(max_memory_bytes / 2 / proof_node_size, 1);
+
+        for ep in start_epoch..end_epoch {
+            let mut loaded_nodes = ProofNodes::new();
+            let mut proof = SingleAppendOnlyProof {
+                inserted: vec![],
+                unchanged_nodes: vec![],
+            };
+            // the nodes left to visit, in reverse label order
+            let mut to_visit = vec![root.clone()];
+            while let Some(node) = to_visit.pop() {
+                if node.get_latest_epoch() <= ep {
+                    // the root being unchanged since the last epoch isn't in the proof
+                    if node.node_type != NodeType::Root {
+                        proof.unchanged_nodes.push(Node {
+                            label: node.label,
+                            hash: optional_child_state_hash(&Some(node.clone())),
+                        });
                     }
-                    Some(label) => {
-                        let child_node = TreeNode::get_from_storage(
+                } else if node.min_descendant_epoch > ep + 1 {
+                    continue;
+                } else if node.node_type == NodeType::Leaf {
+                    proof.inserted.push(Node {
+                        label: node.label,
+                        hash: node.hash,
+                    });
+                } else {
+                    if node
+                        .children()
+                        .any(|child| !loaded_nodes.contains_key(&child))
+                    {
+                        self.load_audit_proof_nodes(
+                            &node,
                             storage,
-                            &NodeKey(label),
-                            self.get_latest_epoch(),
+                            ep,
+                            ep + 1,
+                            max_loaded_nodes,
+                            &mut loaded_nodes,
                         )
                         .await?;
-                        let (mut inner_unchanged, mut inner_leaf) = self
-                            .get_append_only_proof_helper::<_>(
-                                storage,
-                                child_node,
-                                start_epoch,
-                                end_epoch,
-                            )
-                            .await?;
-                        unchanged.append(&mut inner_unchanged);
-                        leaves.append(&mut inner_leaf);
                     }
+                    let children = node
+                        .children()
+                        .map(|child| {
+                            get_loaded_node(&loaded_nodes, child)?;
+                            Ok(loaded_nodes.remove(&child).unwrap())
+                        })
+                        .collect::<Result<Vec<_>, AkdError>>()?;
+                    to_visit.extend(children.into_iter().rev());
+                }
+
+                if proof.inserted.len() + proof.unchanged_nodes.len() >= max_proof_nodes {
+                    let full_proof = std::mem::replace(
+                        &mut proof,
+                        SingleAppendOnlyProof {
+                            inserted: vec![],
+                            unchanged_nodes: vec![],
+                        },
+                    );
+                    crate::auditor::write_append_only_frame(writer, ep, &full_proof).await?;
                 }
             }
+            // the last frame of an epoch is written even if empty, so that every
+            // epoch is present in the output
+            crate::auditor::write_append_only_frame(writer, ep, &proof).await?;
         }
-        Ok((unchanged, leaves))
+        writer
+            .flush()
+            .await
+            .map_err(|err| AkdError::AuditErr(AuditorError::Stream(err.to_string())))
+    }
+
+    /// Loads the nodes which are visited below the given node when generating an
+    /// append-only proof between the given epochs, one level at a time, until
+    /// about `max_loaded_nodes` nodes are loaded
+    #[cfg(feature = "protobuf")]
+    async fn load_audit_proof_nodes<S: Database>(
+        &self,
+        node: &TreeNode,
+        storage: &StorageManager<S>,
+        start_epoch: u64,
+        end_epoch: u64,
+        max_loaded_nodes: usize,
+        loaded_nodes: &mut ProofNodes,
+    ) -> Result<(), AkdError> {
+        let mut children_to_fetch: Vec<NodeKey> =
+            Self::determine_retrieval_nodes(node, start_epoch, end_epoch)
+                .into_iter()
+                .map(NodeKey)
+                .collect();
+        while !children_to_fetch.is_empty() {
+            let got = TreeNode::batch_get_from_storage(
+                storage,
+                &children_to_fetch,
+                self.get_latest_epoch(),
+            )
+            .await?;
+            children_to_fetch = got
+                .iter()
+                .flat_map(|node| Self::determine_retrieval_nodes(node, start_epoch, end_epoch))
+                .map(NodeKey)
+                .collect();
+            loaded_nodes.extend(got.into_iter().map(|node| (node.label, node)));
+            if loaded_nodes.len() + children_to_fetch.len() > max_loaded_nodes {
+                break;
+            }
+        }
+        Ok(())
     }
 
     // FIXME: these functions below should be moved into higher-level API
@@ -833,6 +1223,7 @@ impl Azks {
     }
 
     /// Gets the sibling node of the passed node's child in the "opposite" of the passed direction.
+    #[cfg(test)]
     async fn get_sibling_node<S: Database>(
         &self,
         storage: &StorageManager<S>,
@@ -840,9 +1231,23 @@ impl Azks {
         other_dir: Direction,
         latest_epoch: u64,
     ) -> Result<Option<Node>, AkdError> {
-        let child = curr_node
-            .get_child_node(storage, other_dir, latest_epoch)
-            .await?;
+        let mut nodes = ProofNodes::new();
+        for dir in DIRECTIONS {
+            if let Some(child) = curr_node.get_child_node(storage, dir, latest_epoch).await? {
+                nodes.insert(child.label, child);
+            }
+        }
+        Self::get_loaded_sibling_node(&nodes, curr_node, other_dir)
+    }
+
+    /// Gets the sibling node of the passed node's child in the "opposite" of
+    /// the passed direction, out of a set of already loaded nodes.
+    fn get_loaded_sibling_node(
+        nodes: &ProofNodes,
+        curr_node: &TreeNode,
+        other_dir: Direction,
+    ) -> Result<Option<Node>, AkdError> {
+        let child = get_loaded_child_node(nodes, curr_node, other_dir)?;
         if child.is_none() {
             return Ok(None);
         }
@@ -852,9 +1257,7 @@ impl Azks {
             if i_dir == other_dir {
                 continue;
             }
-            let sibling = curr_node
-                .get_child_node(storage, i_dir, latest_epoch)
-                .await?;
+            let sibling = get_loaded_child_node(nodes, curr_node, i_dir)?.cloned();
             return Ok(Some(Node {
                 label: optional_child_state_to_label(&sibling),
                 hash: optional_child_state_hash(&sibling),
@@ -864,6 +1267,47 @@ impl Azks {
         Ok(None)
     }
 
+    /// Loads all the nodes needed for a membership or non-membership proof of
+    /// the given label: the nodes on the path from the root towards the label,
+    /// along with the children of each of them. Since the labels on the path
+    /// are not known ahead of time in a compressed trie, this issues one batch
+    /// retrieval per level of the tree rather than individual retrievals for
+    /// every node and sibling. The nodes are loaded in their state at the given
+    /// epoch, failing if that state is no longer retained.
+    async fn load_proof_nodes<S: Database>(
+        &self,
+        storage: &StorageManager<S>,
+        label: NodeLabel,
+        epoch: u64,
+    ) -> Result<ProofNodes, AkdError> {
+        let mut nodes = ProofNodes::new();
+        let mut to_fetch = vec![NodeKey(NodeLabel::root())];
+
+        while !to_fetch.is_empty() {
+            let got = TreeNode::batch_get_from_storage(storage, &to_fetch, epoch).await?;
+            to_fetch = vec![];
+            for node in got {
+                // only the latest and previous states of a node are retained, so
+                // the previous state may itself be newer than the epoch
+                if node.last_epoch > epoch {
+                    return Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+                        "The state of {:?} at epoch {} is no longer retained",
+                        NodeKey(node.label),
+                        epoch
+                    ))));
+                }
+                // the traversal continues past any node which is a strict
+                // prefix of the label, so both of its children are needed
+                if node.label != label && node.label.get_dir(label) != Direction::None {
+                    to_fetch.extend(node.children().map(NodeKey));
+                }
+                nodes.insert(node.label, node);
+            }
+        }
+
+        Ok(nodes)
+    }
+
     /// This function returns the node label for the node whose label is the longest common
     /// prefix for the queried label. It also returns a membership proof for said label.
     /// This is meant to be used in both getting membership proofs and getting non-membership proofs.
@@ -871,13 +1315,23 @@ impl Azks {
         &self,
         storage: &StorageManager<S>,
         label: NodeLabel,
+    ) -> Result<(MembershipProof, NodeLabel), AkdError> {
+        let nodes = self
+            .load_proof_nodes(storage, label, self.get_latest_epoch())
+            .await?;
+        Self::get_loaded_membership_proof_and_node(&nodes, label)
+    }
+
+    /// Same as [Azks::get_membership_proof_and_node], out of a set of nodes
+    /// loaded with [Azks::load_proof_nodes].
+    fn get_loaded_membership_proof_and_node(
+        nodes: &ProofNodes,
+        label: NodeLabel,
     ) -> Result<(MembershipProof, NodeLabel), AkdError> {
         let mut layer_proofs = Vec::new();
-        let latest_epoch = self.get_latest_epoch();
 
         // Perform a traversal from the root to the node corresponding to the queried label
-        let mut curr_node =
-            TreeNode::get_from_storage(storage, &NodeKey(NodeLabel::root()), latest_epoch).await?;
+        let mut curr_node = get_loaded_node(nodes, NodeLabel::root())?;
 
         let mut dir = curr_node.label.get_dir(label);
         let mut equal = label == curr_node.label;
@@ -887,10 +1341,7 @@ impl Azks {
 
             // Find the sibling node. Note that for ARITY = 2, this does not need to be
             // an array, as it can just be a single node.
-            match self
-                .get_sibling_node(storage, &curr_node, dir, latest_epoch)
-                .await?
-            {
+            match Self::get_loaded_sibling_node(nodes, curr_node, dir)? {
                 None => break,
                 Some(sibling_node) => {
                     layer_proofs.push(LayerProof {
@@ -901,22 +1352,18 @@ impl Azks {
                 }
             };
 
-            curr_node = TreeNode::get_from_storage(
-                storage,
-                &NodeKey(curr_node.get_child_label(dir)?.ok_or(AkdError::TreeNode(
+            curr_node = get_loaded_node(
+                nodes,
+                curr_node.get_child_label(dir)?.ok_or(AkdError::TreeNode(
                     TreeNodeError::NoDirection(curr_node.label, None),
-                ))?),
-                latest_epoch,
-            )
-            .await?;
+                ))?,
+            )?;
             dir = curr_node.label.get_dir(label);
             equal = label == curr_node.label;
         }
 
         if !equal {
-            let new_curr_node: TreeNode =
-                TreeNode::get_from_storage(storage, &NodeKey(prev_node), latest_epoch).await?;
-            curr_node = new_curr_node;
+            curr_node = get_loaded_node(nodes, prev_node)?;
 
             layer_proofs.pop();
         }
@@ -937,6 +1384,47 @@ impl Azks {
     }
 }
 
+/// Tree nodes loaded ahead of proof generation, keyed by their label
+type ProofNodes = HashMap<NodeLabel, TreeNode>;
+
+/// The records of tree nodes which are new in this epoch: as none of them
+/// existed before, there is no previous value to retain
+fn new_tree_node_records(nodes: &[TreeNode]) -> Vec<DbRecord> {
+    nodes
+        .iter()
+        .map(|node| {
+            DbRecord::TreeNode(TreeNodeWithPreviousValue {
+                label: node.label,
+                latest_node: node.clone(),
+                previous_node: None,
+            })
+        })
+        .collect()
+}
+
+fn get_loaded_node(nodes: &ProofNodes, label: NodeLabel) -> Result<&TreeNode, AkdError> {
+    nodes.get(&label).ok_or_else(|| {
+        AkdError::Storage(StorageError::NotFound(format!(
+            "TreeNode {:?}",
+            NodeKey(label)
+        )))
+    })
+}
+
+/// Gets the child of a node in the given direction out of the loaded nodes, or
+/// `None` if the node has no child there. A child which the node names, but which
+/// wasn't loaded, is an error.
+fn get_loaded_child_node<'a>(
+    nodes: &'a ProofNodes,
+    node: &TreeNode,
+    direction: Direction,
+) -> Result<Option<&'a TreeNode>, AkdError> {
+    match node.get_child_label(direction)? {
+        Some(child_label) => get_loaded_node(nodes, child_label).map(Some),
+        None => Ok(None),
+    }
+}
+
 type AppendOnlyHelper = (Vec<Node>, Vec<Node>);
 
 #[cfg(test)]
@@ -971,11 +1459,12 @@ mod tests {
             let hash = crate::hash::hash(&input);
             let node = Node { label, hash };
             node_set.push(node);
-            let (root_node, is_new, _) = Azks::recursive_batch_insert_nodes(
+            let (root_node, is_new, _, _) = Azks::recursive_batch_insert_nodes(
                 &db,
                 Some(NodeLabel::root()),
                 NodeSet::from(vec![node]),
                 1,
+                1,
                 InsertMode::Directory,
                 None,
             )
@@ -1079,11 +1568,12 @@ mod tests {
             rng.fill_bytes(&mut hash);
             let node = Node { label, hash };
             node_set.push(node);
-            let (root_node, is_new, _) = Azks::recursive_batch_insert_nodes(
+            let (root_node, is_new, _, _) = Azks::recursive_batch_insert_nodes(
                 &db,
                 Some(NodeLabel::root()),
                 NodeSet::from(vec![node]),
                 1,
+                1,
                 InsertMode::Directory,
                 None,
             )
@@ -1111,6 +1601,65 @@ mod tests {
         Ok(())
     }
 
+    // A batch large enough for its new subtrees to be built in memory should
+    // produce the same tree as inserting the leaves one at a time
+    #[tokio::test]
+    async fn test_batch_insert_large() -> Result<(), AkdError> {
+        let num_nodes = 1000;
+        let mut rng = OsRng;
+        let database1 = AsyncInMemoryDatabase::new();
+        let db1 = StorageManager::new_no_cache(database1.clone());
+        let mut azks1 = Azks::new::<_>(&db1).await?;
+        azks1.increment_epoch();
+        let mut node_set: Vec<Node> = vec![];
+
+        for _ in 0..num_nodes {
+            let label = crate::utils::random_label(&mut rng);
+            let mut hash = crate::hash::EMPTY_DIGEST;
+            rng.fill_bytes(&mut hash);
+            let node = Node { label, hash };
+            node_set.push(node);
+            let (root_node, is_new, num_inserted, _) = Azks::recursive_batch_insert_nodes(
+                &db1,
+                Some(NodeLabel::root()),
+                NodeSet::from(vec![node]),
+                1,
+                1,
+                InsertMode::Directory,
+                None,
+            )
+            .await?;
+            root_node.write_to_storage(&db1, is_new).await?;
+            azks1.num_nodes += num_inserted;
+        }
+
+        let database2 = AsyncInMemoryDatabase::new();
+        let db2 = StorageManager::new_no_cache(database2.clone());
+        let mut azks2 = Azks::new(&db2).await?;
+        azks2
+            .batch_insert_nodes(&db2, node_set, InsertMode::Directory)
+            .await?;
+
+        assert_eq!(
+            azks1.get_root_hash::<_>(&db1).await?,
+            azks2.get_root_hash::<_>(&db2).await?,
+            "Batch insert doesn't match individual insert"
+        );
+        assert_eq!(azks1.num_nodes, azks2.num_nodes);
+        assert_eq!(
+            database1
+                .batch_get_type_direct::<TreeNodeWithPreviousValue>()
+                .await?
+                .len(),
+            database2
+                .batch_get_type_direct::<TreeNodeWithPreviousValue>()
+                .await?
+                .len()
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_insert_num_nodes() -> Result<(), AkdError> {
         let database = AsyncInMemoryDatabase::new();
@@ -1193,6 +1742,99 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_batch_insert_hash_count() -> Result<(), AkdError> {
+        let database = AsyncInMemoryDatabase::new();
+        let db = StorageManager::new_no_cache(database);
+        let mut azks = Azks::new::<_>(&db).await?;
+
+        let to_nodes = |labels: Vec<u64>| {
+            labels
+                .into_iter()
+                .map(|label| Node {
+                    label: NodeLabel::new(byte_arr_from_u64(label << 60), 64),
+                    hash: EMPTY_DIGEST,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // the interior nodes are the root, 0 and 1
+        let num_hashed = azks
+            .batch_insert_nodes(
+                &db,
+                to_nodes(vec![0b0000, 0b0100, 0b1000, 0b1100]),
+                InsertMode::Directory,
+            )
+            .await?;
+        assert_eq!(3, num_hashed);
+
+        // the two new leaves share the ancestors 00, 0 and the root, which are
+        // only hashed once, along with the new interior node 000
+        let num_hashed = azks
+            .batch_insert_nodes(&db, to_nodes(vec![0b0001, 0b0010]), InsertMode::Directory)
+            .await?;
+        assert_eq!(4, num_hashed);
+        assert_eq!(11, azks.num_nodes);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_batch_insert_transaction() -> Result<(), AkdError> {
+        let database = AsyncInMemoryDatabase::new();
+        let db = StorageManager::new_no_cache(database.clone());
+        let mut azks = Azks::new::<_>(&db).await?;
+
+        let to_nodes = |labels: Vec<u64>| {
+            labels
+                .into_iter()
+                .map(|label| Node {
+                    label: NodeLabel::new(byte_arr_from_u64(label << 60), 64),
+                    hash: EMPTY_DIGEST,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // without a transaction from the caller, the nodes are committed
+        // along with the azks record
+        azks.batch_insert_nodes(&db, to_nodes(vec![0b0110, 0b0111]), InsertMode::Directory)
+            .await?;
+        assert!(!db.is_transaction_active());
+        assert_eq!(
+            DbRecord::Azks(azks.clone()),
+            database.get::<Azks>(&DEFAULT_AZKS_KEY).await?
+        );
+        let num_stored_nodes = database
+            .batch_get_type_direct::<TreeNodeWithPreviousValue>()
+            .await?
+            .len() as u64;
+        assert_eq!(azks.num_nodes, num_stored_nodes);
+
+        // within a transaction from the caller, nothing is written until the
+        // caller commits
+        assert!(db.begin_transaction());
+        azks.batch_insert_nodes(&db, to_nodes(vec![0b1000]), InsertMode::Directory)
+            .await?;
+        assert!(db.is_transaction_active());
+        assert_eq!(
+            num_stored_nodes,
+            database
+                .batch_get_type_direct::<TreeNodeWithPreviousValue>()
+                .await?
+                .len() as u64
+        );
+        db.set(DbRecord::Azks(azks.clone())).await?;
+        db.commit_transaction().await?;
+        assert_eq!(
+            azks.num_nodes,
+            database
+                .batch_get_type_direct::<TreeNodeWithPreviousValue>()
+                .await?
+                .len() as u64
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_preload_nodes_accuracy() {
         let database = AsyncInMemoryDatabase::new();
@@ -1599,6 +2241,42 @@ mod tests {
         Ok(())
     }
 
+    // A proof isn't generated from a tree missing a node, which would otherwise be
+    // taken for an empty child of its parent
+    #[tokio::test]
+    async fn test_nonmembership_proof_missing_child() -> Result<(), AkdError> {
+        let num_nodes = 10;
+
+        let node_set = gen_nodes(num_nodes);
+        let database = AsyncInMemoryDatabase::new();
+        let db = StorageManager::new_no_cache(database);
+        let mut azks = Azks::new::<_>(&db).await?;
+        let search_label = node_set[num_nodes - 1].label;
+        azks.batch_insert_nodes::<_>(
+            &db,
+            node_set.clone()[0..num_nodes - 1].to_vec(),
+            InsertMode::Directory,
+        )
+        .await?;
+
+        // the children of the longest prefix are only loaded for the proof
+        let proof = azks.get_non_membership_proof(&db, search_label).await?;
+        let child = proof
+            .longest_prefix_children
+            .iter()
+            .find(|child| child.label != EMPTY_LABEL)
+            .map(|child| NodeKey(child.label))
+            .expect("The longest prefix has a child");
+        db.batch_delete::<TreeNodeWithPreviousValue>(&[child])
+            .await?;
+        assert!(matches!(
+            azks.get_non_membership_proof(&db, search_label).await,
+            Err(AkdError::Storage(StorageError::NotFound(_)))
+        ));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_append_only_proof_very_tiny() -> Result<(), AkdError> {
         let database = AsyncInMemoryDatabase::new();
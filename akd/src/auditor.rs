@@ -11,9 +11,24 @@ use crate::{
     append_only_zks::InsertMode,
     errors::{AkdError, AuditorError, AzksError},
     storage::{manager::StorageManager, memory::AsyncInMemoryDatabase},
-    AppendOnlyProof, Azks, Digest, SingleAppendOnlyProof,
+    tree_node::{NodeKey, TreeNode},
+    AppendOnlyManifest, AppendOnlyProof, AppendOnlyProofSegment, AppendOnlySegmentSummary,
+    AppendOnlyTransitionManifest, Azks, Digest, EpochHash, Node, NodeLabel,
+    SegmentedAppendOnlyProof, SingleAppendOnlyProof,
 };
 
+#[cfg(feature = "protobuf")]
+use akd_core::proto::envelope::VersionedProof;
+use std::collections::{BTreeMap, HashSet};
+#[cfg(feature = "protobuf")]
+use std::convert::TryInto;
+#[cfg(feature = "protobuf")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// The maximum length (in bits) of the label prefixes segmenting an append-only
+/// proof, which splits each epoch transition into at most 65,536 segments
+pub const MAX_SEGMENT_PREFIX_BITS: u32 = 16;
+
 /// Verifies an audit proof, given start and end hashes for a merkle patricia tree.
 pub async fn audit_verify(hashes: Vec<Digest>, proof: AppendOnlyProof) -> Result<(), AkdError> {
     if proof.epochs.len() + 1 != hashes.len() {
@@ -25,18 +40,163 @@ pub async fn audit_verify(hashes: Vec<Digest>, proof: AppendOnlyProof) -> Result
             hashes.len()
         ))));
     }
-    if proof.epochs.len() != proof.proofs.len() {
+    let mut verifier = AuditVerifier::new(hashes);
+    verifier.feed(proof).await?;
+    Ok(())
+}
+
+/// The progress of an [AuditVerifier]. This can be persisted as a checkpoint
+/// and handed to [AuditVerifier::resume] to continue a partially verified audit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct AuditProgress {
+    /// The number of consecutive epoch transitions verified so far
+    pub verified: usize,
+    /// The total number of epoch transitions covered by the audited hashes
+    pub total: usize,
+    /// The starting epoch of the last verified transition, if any
+    pub last_epoch: Option<u64>,
+}
+
+impl AuditProgress {
+    /// Whether all the epoch transitions have been verified
+    pub fn is_complete(&self) -> bool {
+        self.verified == self.total
+    }
+}
+
+type ProgressCallback = Box<dyn FnMut(&AuditProgress) + Send>;
+
+/// Verifies an audit incrementally, one chunk of append-only proofs at a time,
+/// so that large audits don't need to be held in memory at once and can be
+/// checkpointed and resumed. A chunk is an [AppendOnlyProof] covering the
+/// epoch transitions which immediately follow the last verified one.
+pub struct AuditVerifier {
+    hashes: Vec<Digest>,
+    progress: AuditProgress,
+    callback: Option<ProgressCallback>,
+}
+
+impl AuditVerifier {
+    /// Creates a verifier for the given root hashes, one per audited epoch
+    pub fn new(hashes: Vec<Digest>) -> Self {
+        let total = hashes.len().saturating_sub(1);
+        Self {
+            hashes,
+            progress: AuditProgress {
+                verified: 0,
+                total,
+                last_epoch: None,
+            },
+            callback: None,
+        }
+    }
+
+    /// Creates a verifier for the given root hashes which continues from a
+    /// previously recorded checkpoint
+    pub fn resume(hashes: Vec<Digest>, checkpoint: AuditProgress) -> Result<Self, AkdError> {
+        let mut verifier = Self::new(hashes);
+        if checkpoint.total != verifier.progress.total || checkpoint.verified > checkpoint.total {
+            return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+                "The checkpoint ({} of {} verified) does not match the {} provided hashes",
+                checkpoint.verified,
+                checkpoint.total,
+                verifier.hashes.len()
+            ))));
+        }
+        verifier.progress = checkpoint;
+        Ok(verifier)
+    }
+
+    /// Registers a callback which is invoked with the updated progress after
+    /// every verified epoch transition
+    pub fn with_progress_callback(
+        mut self,
+        callback: impl FnMut(&AuditProgress) + Send + 'static,
+    ) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// The current progress of the audit
+    pub fn progress(&self) -> AuditProgress {
+        self.progress
+    }
+
+    /// Verifies the next chunk of the audit. On failure, the progress is left
+    /// at the last successfully verified epoch transition.
+    pub async fn feed(&mut self, chunk: AppendOnlyProof) -> Result<AuditProgress, AkdError> {
+        if chunk.epochs.len() != chunk.proofs.len() {
+            return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+                "The proof has {} epochs and {} proofs. These should be equal!",
+                chunk.epochs.len(),
+                chunk.proofs.len()
+            ))));
+        }
+        if self.progress.verified + chunk.proofs.len() > self.progress.total {
+            return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+                "The proof covers {} more epochs than remain to be verified ({})",
+                chunk.proofs.len(),
+                self.progress.total - self.progress.verified
+            ))));
+        }
+
+        for (proof, epoch) in chunk.proofs.iter().zip(chunk.epochs) {
+            if let Some(last_epoch) = self.progress.last_epoch {
+                if epoch != last_epoch + 1 {
+                    return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+                        "Expected a proof starting at epoch {}, but got epoch {}",
+                        last_epoch + 1,
+                        epoch
+                    ))));
+                }
+            }
+            let i = self.progress.verified;
+            verify_consecutive_append_only(proof, self.hashes[i], self.hashes[i + 1], epoch + 1)
+                .await?;
+
+            self.progress.verified += 1;
+            self.progress.last_epoch = Some(epoch);
+            if let Some(callback) = self.callback.as_mut() {
+                callback(&self.progress);
+            }
+        }
+
+        Ok(self.progress)
+    }
+}
+
+/// Verifies that the tree with root hash `end.hash()` is an append-only
+/// extension of the tree with root hash `start.hash()`, given the proofs for
+/// every epoch in between. Unlike [audit_verify], this doesn't require the root
+/// hashes of the intermediate epochs: these are computed from the proofs
+/// themselves, each proof having to start from the root which the previous
+/// one ended at.
+pub async fn verify_append_only_chain(
+    start: EpochHash,
+    end: EpochHash,
+    proof: AppendOnlyProof,
+) -> Result<(), AkdError> {
+    let expected_epochs = (start.epoch()..end.epoch()).collect::<Vec<_>>();
+    if proof.epochs != expected_epochs || proof.proofs.len() != proof.epochs.len() {
         return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
-            "The proof has {} epochs and {} proofs. These should be equal!",
-            proof.epochs.len(),
+            "The proof should cover the epochs {} to {}, but covers the epochs {:?} with {} proofs",
+            start.epoch(),
+            end.epoch(),
+            proof.epochs,
             proof.proofs.len()
         ))));
     }
-    for i in 0..hashes.len() - 1 {
-        let start_hash = hashes[i];
-        let end_hash = hashes[i + 1];
-        verify_consecutive_append_only(&proof.proofs[i], start_hash, end_hash, proof.epochs[i] + 1)
-            .await?;
+
+    let mut hash = start.hash();
+    for (single_proof, epoch) in proof.proofs.iter().zip(proof.epochs) {
+        hash = compute_consecutive_append_only_end_hash(single_proof, hash, epoch + 1).await?;
+    }
+    if hash != end.hash() {
+        return Err(AkdError::AzksErr(AzksError::VerifyAppendOnlyProof));
     }
     Ok(())
 }
@@ -48,6 +208,21 @@ pub async fn verify_consecutive_append_only(
     end_hash: Digest,
     epoch: u64,
 ) -> Result<(), AkdError> {
+    let computed_end_root_hash =
+        compute_consecutive_append_only_end_hash(proof, start_hash, epoch).await?;
+    if computed_end_root_hash != end_hash {
+        return Err(AkdError::AzksErr(AzksError::VerifyAppendOnlyProof));
+    }
+    Ok(())
+}
+
+/// Checks that the unchanged nodes of an append-only proof hash to the start
+/// hash, and returns the root hash after inserting the new leaves at the epoch
+async fn compute_consecutive_append_only_end_hash(
+    proof: &SingleAppendOnlyProof,
+    start_hash: Digest,
+    epoch: u64,
+) -> Result<Digest, AkdError> {
     // FIXME: Need to get rid of the clone here. Will need modifications to the functions called here.
     let unchanged_nodes = proof.unchanged_nodes.clone();
     let inserted = proof.inserted.clone();
@@ -59,22 +234,456 @@ pub async fn verify_consecutive_append_only(
     azks.batch_insert_nodes::<_>(&manager, unchanged_nodes, InsertMode::Auditor)
         .await?;
     let computed_start_root_hash: Digest = azks.get_root_hash::<_>(&manager).await?;
-    let mut verified = computed_start_root_hash == start_hash;
+    if computed_start_root_hash != start_hash {
+        return Err(AkdError::AzksErr(AzksError::VerifyAppendOnlyProof));
+    }
     azks.latest_epoch = epoch - 1;
-    let updated_inserted = inserted
+    let updated_inserted = with_insertion_epoch(&inserted, epoch);
+    azks.batch_insert_nodes::<_>(&manager, updated_inserted, InsertMode::Auditor)
+        .await?;
+    let computed_end_root_hash: Digest = azks.get_root_hash::<_>(&manager).await?;
+    Ok(computed_end_root_hash)
+}
+
+/// Mixes the epoch at which the leaves were inserted into their hashes
+fn with_insertion_epoch(inserted: &[Node], epoch: u64) -> Vec<Node> {
+    inserted
         .iter()
         .map(|x| {
             let mut y = *x;
             y.hash = akd_core::hash::merge_with_int(x.hash, epoch);
             y
         })
-        .collect();
-    azks.batch_insert_nodes::<_>(&manager, updated_inserted, InsertMode::Auditor)
-        .await?;
-    let computed_end_root_hash: Digest = azks.get_root_hash::<_>(&manager).await?;
-    verified = verified && (computed_end_root_hash == end_hash);
-    if !verified {
+        .collect()
+}
+
+/// Verifies a protobuf-encoded [AppendOnlyProof] (bare or in a versioned envelope),
+/// given the root hashes of all the audited epochs, without deserializing it.
+///
+/// Unlike [audit_verify], which first decodes every node of the proof and then
+/// rebuilds the trees in memory, this walks the serialized buffer directly and
+/// hashes the nodes as they're decoded, so that auditing a large epoch takes
+/// memory proportional to the depth of the tree rather than to the size of the
+/// proof. This requires the nodes of each proof to be serialized in left-to-right
+/// order, as produced by [Azks::get_append_only_proof]. The verification is
+/// synchronous, see [akd_core::verify::audit].
+#[cfg(feature = "protobuf")]
+pub fn audit_verify_serialized(hashes: &[Digest], proof: &[u8]) -> Result<(), AkdError> {
+    akd_core::verify::audit_verify_serialized(hashes, proof).map_err(audit_verification_error)
+}
+
+/// Verifies a protobuf-encoded [SingleAppendOnlyProof] (e.g. the contents of an
+/// audit blob) without deserializing it. See [audit_verify_serialized].
+#[cfg(feature = "protobuf")]
+pub fn verify_consecutive_append_only_serialized(
+    proof: &[u8],
+    start_hash: Digest,
+    end_hash: Digest,
+    epoch: u64,
+) -> Result<(), AkdError> {
+    akd_core::verify::verify_consecutive_append_only_serialized(proof, start_hash, end_hash, epoch)
+        .map_err(audit_verification_error)
+}
+
+#[cfg(feature = "protobuf")]
+fn audit_verification_error(err: akd_core::verify::VerificationError) -> AkdError {
+    AkdError::AuditErr(AuditorError::VerifyAuditProof(err.to_string()))
+}
+
+/// The length of the header of a frame of a streamed append-only proof: the epoch
+/// of the frame followed by the length of its payload, both as big-endian u64s
+#[cfg(feature = "protobuf")]
+const APPEND_ONLY_FRAME_HEADER_LEN: usize = 16;
+
+#[cfg(feature = "protobuf")]
+fn stream_error(err: std::io::Error) -> AkdError {
+    AkdError::AuditErr(AuditorError::Stream(err.to_string()))
+}
+
+/// Writes a frame of a streamed append-only proof: a header followed by the nodes of
+/// the proof in a versioned envelope. The consecutive frames of an epoch each hold
+/// part of the nodes of its [SingleAppendOnlyProof], in order.
+#[cfg(feature = "protobuf")]
+pub(crate) async fn write_append_only_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    epoch: u64,
+    proof: &SingleAppendOnlyProof,
+) -> Result<(), AkdError> {
+    let payload = proof
+        .to_versioned_bytes()
+        .map_err(|err| AkdError::AuditErr(AuditorError::Stream(err.to_string())))?;
+    let mut frame = Vec::with_capacity(APPEND_ONLY_FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&epoch.to_be_bytes());
+    frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    frame.extend(payload);
+    writer.write_all(&frame).await.map_err(stream_error)
+}
+
+/// Reads an append-only proof streamed by [crate::Directory::audit_to_writer],
+/// reassembling the proof of each epoch from its frames. The whole proof is held
+/// in memory, so that it can then be verified with [audit_verify].
+#[cfg(feature = "protobuf")]
+pub async fn read_append_only_proof<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> Result<AppendOnlyProof, AkdError> {
+    let mut proof = AppendOnlyProof {
+        proofs: vec![],
+        epochs: vec![],
+    };
+    let mut header = [0u8; APPEND_ONLY_FRAME_HEADER_LEN];
+    loop {
+        // the stream may only end between frames
+        let read = reader.read(&mut header).await.map_err(stream_error)?;
+        if read == 0 {
+            return Ok(proof);
+        }
+        reader
+            .read_exact(&mut header[read..])
+            .await
+            .map_err(stream_error)?;
+        let epoch = u64::from_be_bytes(header[..8].try_into().unwrap());
+        let len = u64::from_be_bytes(header[8..].try_into().unwrap());
+
+        let mut payload = vec![];
+        reader
+            .take(len)
+            .read_to_end(&mut payload)
+            .await
+            .map_err(stream_error)?;
+        if payload.len() as u64 != len {
+            return Err(AkdError::AuditErr(AuditorError::Stream(format!(
+                "Truncated frame of epoch {}",
+                epoch
+            ))));
+        }
+        let mut frame_proof =
+            SingleAppendOnlyProof::from_versioned_bytes(&payload).map_err(malformed_proof)?;
+
+        if proof.epochs.last() == Some(&epoch) {
+            let last = proof.proofs.last_mut().unwrap();
+            last.inserted.append(&mut frame_proof.inserted);
+            last.unchanged_nodes
+                .append(&mut frame_proof.unchanged_nodes);
+        } else {
+            proof.epochs.push(epoch);
+            proof.proofs.push(frame_proof);
+        }
+    }
+}
+
+#[cfg(feature = "protobuf")]
+fn malformed_proof(err: akd_core::proto::ConversionError) -> AkdError {
+    AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+        "Malformed serialized proof: {}",
+        err
+    )))
+}
+
+/// Splits an append-only proof into segments, by the first `prefix_bits` bits of the
+/// labels of its nodes, and computes the manifest linking the segments to the root
+/// hashes. The unchanged nodes with shorter labels are kept in the manifest.
+pub async fn segment_append_only_proof(
+    proof: AppendOnlyProof,
+    prefix_bits: u32,
+) -> Result<SegmentedAppendOnlyProof, AkdError> {
+    check_segment_prefix_bits(prefix_bits)?;
+    if proof.epochs.len() != proof.proofs.len() {
+        return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+            "The proof has {} epochs and {} proofs. These should be equal!",
+            proof.epochs.len(),
+            proof.proofs.len()
+        ))));
+    }
+
+    let mut transitions = vec![];
+    let mut segments = vec![];
+    for (single_proof, epoch) in proof.proofs.into_iter().zip(proof.epochs) {
+        let mut shared_nodes = vec![];
+        let mut by_prefix = BTreeMap::<NodeLabel, SingleAppendOnlyProof>::new();
+        for node in single_proof.unchanged_nodes {
+            if node.label.label_len < prefix_bits {
+                shared_nodes.push(node);
+            } else {
+                segment_for(&mut by_prefix, &node, prefix_bits)
+                    .unchanged_nodes
+                    .push(node);
+            }
+        }
+        for node in single_proof.inserted {
+            if node.label.label_len < prefix_bits {
+                return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+                    "The inserted leaf {:?} is shorter than the segment prefixes",
+                    node.label
+                ))));
+            }
+            segment_for(&mut by_prefix, &node, prefix_bits)
+                .inserted
+                .push(node);
+        }
+
+        let mut summaries = vec![];
+        for (prefix, proof) in by_prefix {
+            let (start, end) = compute_segment_top_nodes(&proof, epoch + 1).await?;
+            summaries.push(AppendOnlySegmentSummary { prefix, start, end });
+            segments.push(AppendOnlyProofSegment {
+                epoch,
+                prefix,
+                proof,
+            });
+        }
+        transitions.push(AppendOnlyTransitionManifest {
+            epoch,
+            shared_nodes,
+            segments: summaries,
+        });
+    }
+
+    Ok(SegmentedAppendOnlyProof {
+        manifest: AppendOnlyManifest {
+            prefix_bits,
+            transitions,
+        },
+        segments,
+    })
+}
+
+fn segment_for<'a>(
+    by_prefix: &'a mut BTreeMap<NodeLabel, SingleAppendOnlyProof>,
+    node: &Node,
+    prefix_bits: u32,
+) -> &'a mut SingleAppendOnlyProof {
+    by_prefix
+        .entry(node.label.get_prefix(prefix_bits))
+        .or_insert_with(|| SingleAppendOnlyProof {
+            inserted: vec![],
+            unchanged_nodes: vec![],
+        })
+}
+
+fn check_segment_prefix_bits(prefix_bits: u32) -> Result<(), AkdError> {
+    if prefix_bits == 0 || prefix_bits > MAX_SEGMENT_PREFIX_BITS {
+        return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+            "The segment prefixes must be between 1 and {} bits long, got {}",
+            MAX_SEGMENT_PREFIX_BITS, prefix_bits
+        ))));
+    }
+    Ok(())
+}
+
+/// Verifies an audit proof which was split into segments, given the root hashes of
+/// all the audited epochs. This verifies the manifest, and then every segment it
+/// lists, in the order they're given.
+pub async fn audit_verify_segmented(
+    hashes: Vec<Digest>,
+    proof: SegmentedAppendOnlyProof,
+) -> Result<(), AkdError> {
+    verify_append_only_manifest(&hashes, &proof.manifest).await?;
+
+    let mut verified = HashSet::new();
+    for segment in proof.segments.iter() {
+        if !verified.insert((segment.epoch, segment.prefix)) {
+            return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+                "The segment {:?} of epoch {} is duplicated",
+                segment.prefix, segment.epoch
+            ))));
+        }
+        verify_append_only_segment(&proof.manifest, segment).await?;
+    }
+
+    // every verified segment matches a distinct summary of the manifest
+    let expected: usize = proof
+        .manifest
+        .transitions
+        .iter()
+        .map(|transition| transition.segments.len())
+        .sum();
+    if verified.len() != expected {
+        return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+            "The manifest lists {} segments, but only {} were provided",
+            expected,
+            verified.len()
+        ))));
+    }
+    Ok(())
+}
+
+/// Verifies that the manifest of a segmented audit proof links the segments of
+/// every epoch transition to the given root hashes, one per audited epoch. Once
+/// the manifest is verified, each of its segments must be verified with
+/// [verify_append_only_segment], in any order and possibly by different machines.
+pub async fn verify_append_only_manifest(
+    hashes: &[Digest],
+    manifest: &AppendOnlyManifest,
+) -> Result<(), AkdError> {
+    check_segment_prefix_bits(manifest.prefix_bits)?;
+    if manifest.transitions.len() + 1 != hashes.len() {
+        return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+            "The manifest covers {} epoch transitions, but {} hashes were provided",
+            manifest.transitions.len(),
+            hashes.len()
+        ))));
+    }
+
+    let mut previous_epoch = None;
+    for (i, transition) in manifest.transitions.iter().enumerate() {
+        if let Some(previous_epoch) = previous_epoch {
+            if transition.epoch != previous_epoch + 1 {
+                return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+                    "Expected a transition starting at epoch {}, but got epoch {}",
+                    previous_epoch + 1,
+                    transition.epoch
+                ))));
+            }
+        }
+        previous_epoch = Some(transition.epoch);
+        check_transition_manifest(transition, manifest.prefix_bits)?;
+
+        let mut start_nodes = transition.shared_nodes.clone();
+        start_nodes.extend(
+            transition
+                .segments
+                .iter()
+                .filter_map(|summary| summary.start),
+        );
+        let mut end_nodes = transition.shared_nodes.clone();
+        end_nodes.extend(transition.segments.iter().map(|summary| summary.end));
+        if compute_root_hash(start_nodes).await? != hashes[i]
+            || compute_root_hash(end_nodes).await? != hashes[i + 1]
+        {
+            return Err(AkdError::AzksErr(AzksError::VerifyAppendOnlyProof));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the segments of a transition have distinct prefixes of the right
+/// length, that their topmost nodes are below their prefixes, and that the shared
+/// nodes are above all the prefixes
+fn check_transition_manifest(
+    transition: &AppendOnlyTransitionManifest,
+    prefix_bits: u32,
+) -> Result<(), AkdError> {
+    let malformed = |reason: String| {
+        Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+            "Malformed manifest for epoch {}: {}",
+            transition.epoch, reason
+        ))))
+    };
+    if let Some(node) = transition
+        .shared_nodes
+        .iter()
+        .find(|node| node.label.label_len >= prefix_bits)
+    {
+        return malformed(format!("the shared node {:?} is too long", node.label));
+    }
+    let mut prefixes = HashSet::new();
+    for summary in transition.segments.iter() {
+        if summary.prefix.label_len != prefix_bits || !prefixes.insert(summary.prefix) {
+            return malformed(format!("invalid segment prefix {:?}", summary.prefix));
+        }
+        let tops = summary.start.iter().chain(core::iter::once(&summary.end));
+        if let Some(node) = tops
+            .into_iter()
+            .find(|node| !summary.prefix.is_prefix_of(&node.label))
+        {
+            return malformed(format!(
+                "the node {:?} is not below the prefix {:?}",
+                node.label, summary.prefix
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Verifies a single segment of a segmented audit proof against its manifest, which
+/// must have been verified with [verify_append_only_manifest]
+pub async fn verify_append_only_segment(
+    manifest: &AppendOnlyManifest,
+    segment: &AppendOnlyProofSegment,
+) -> Result<(), AkdError> {
+    let summary = manifest
+        .transitions
+        .iter()
+        .find(|transition| transition.epoch == segment.epoch)
+        .and_then(|transition| {
+            transition
+                .segments
+                .iter()
+                .find(|summary| summary.prefix == segment.prefix)
+        })
+        .ok_or_else(|| {
+            AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+                "The segment {:?} of epoch {} is not in the manifest",
+                segment.prefix, segment.epoch
+            )))
+        })?;
+
+    let nodes = segment
+        .proof
+        .unchanged_nodes
+        .iter()
+        .chain(segment.proof.inserted.iter());
+    if let Some(node) = nodes
+        .into_iter()
+        .find(|node| !segment.prefix.is_prefix_of(&node.label))
+    {
+        return Err(AkdError::AuditErr(AuditorError::VerifyAuditProof(format!(
+            "The node {:?} is not below the segment prefix {:?}",
+            node.label, segment.prefix
+        ))));
+    }
+
+    let (start, end) = compute_segment_top_nodes(&segment.proof, segment.epoch + 1).await?;
+    if start != summary.start || end != summary.end {
         return Err(AkdError::AzksErr(AzksError::VerifyAppendOnlyProof));
     }
     Ok(())
 }
+
+/// Computes the topmost node of a segment before and after the leaves are inserted
+async fn compute_segment_top_nodes(
+    proof: &SingleAppendOnlyProof,
+    epoch: u64,
+) -> Result<(Option<Node>, Node), AkdError> {
+    let start = compute_top_node(proof.unchanged_nodes.clone()).await?;
+    let mut end_nodes = proof.unchanged_nodes.clone();
+    end_nodes.extend(with_insertion_epoch(&proof.inserted, epoch));
+    let end = compute_top_node(end_nodes)
+        .await?
+        .ok_or(AkdError::AzksErr(AzksError::VerifyAppendOnlyProof))?;
+    Ok((start, end))
+}
+
+/// Builds the tree holding the given nodes (which share a non-empty prefix), and
+/// returns its only child of the root
+async fn compute_top_node(nodes: Vec<Node>) -> Result<Option<Node>, AkdError> {
+    if nodes.is_empty() {
+        return Ok(None);
+    }
+    let manager = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    let mut azks = Azks::new::<_>(&manager).await?;
+    azks.batch_insert_nodes::<_>(&manager, nodes, InsertMode::Auditor)
+        .await?;
+
+    let epoch = azks.get_latest_epoch();
+    let root = TreeNode::get_from_storage(&manager, &NodeKey(NodeLabel::root()), epoch).await?;
+    match (root.left_child, root.right_child) {
+        (Some(label), None) | (None, Some(label)) => {
+            let top = TreeNode::get_from_storage(&manager, &NodeKey(label), epoch).await?;
+            Ok(Some(Node {
+                label: top.label,
+                hash: top.hash,
+            }))
+        }
+        _ => Err(AkdError::AzksErr(AzksError::VerifyAppendOnlyProof)),
+    }
+}
+
+/// Computes the root hash of the tree holding the given nodes
+async fn compute_root_hash(nodes: Vec<Node>) -> Result<Digest, AkdError> {
+    let manager = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    let mut azks = Azks::new::<_>(&manager).await?;
+    azks.batch_insert_nodes::<_>(&manager, nodes, InsertMode::Auditor)
+        .await?;
+    azks.get_root_hash::<_>(&manager).await
+}
@@ -9,3 +9,242 @@
 
 // Just re-export the verification calls here
 pub use akd_core::verify::*;
+
+use crate::errors::{AkdError, StorageError};
+use crate::{
+    AkdLabel, Digest, EpochHash, HistoryProof, LookupProof, LookupWithConsistencyProof,
+    VerifyResult,
+};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Verifies a [LookupWithConsistencyProof]: that the lookup proof is valid
+/// against the current root hash, and that the current root hash is an
+/// append-only extension of the root hash pinned by the client
+pub async fn lookup_with_consistency_verify(
+    vrf_public_key: &[u8],
+    pinned: EpochHash,
+    current: EpochHash,
+    akd_label: AkdLabel,
+    proof: LookupWithConsistencyProof,
+) -> Result<VerifyResult, AkdError> {
+    let current_hash = current.hash();
+    crate::auditor::verify_append_only_chain(pinned, current, proof.consistency_proof).await?;
+    Ok(lookup_verify(
+        vrf_public_key,
+        current_hash,
+        akd_label,
+        proof.lookup_proof,
+    )?)
+}
+
+/// A persistent record of the last epoch and root hash a client verified for each
+/// directory it talks to, identified by an id chosen by the client. Checking every
+/// newly verified root hash against it with [TrustStore::record], as
+/// [lookup_verify_with_trust_store] and [key_history_verify_with_trust_store] do
+/// for every proof they verify, catches a server
+/// serving an epoch older than the last one the client verified, or a different root
+/// hash for that same epoch, across restarts of the client. It doesn't catch a fork
+/// on its own: a client only ever served one branch of a forked directory sees a
+/// consistent history, which only comparing root hashes with other clients or an
+/// auditor reveals.
+pub trait TrustStore: Send + Sync {
+    /// Retrieves the last verified epoch and root hash of the directory, if any
+    fn get(&self, directory_id: &str) -> Result<Option<EpochHash>, AkdError>;
+
+    /// Records a newly verified epoch and root hash of the directory, failing with
+    /// [VerificationError::Rollback] if [check_rollback] rejects it against the last
+    /// verified one. Implementations must check and update the stored state as a
+    /// single atomic step, so concurrent records can't move it backwards.
+    fn record(&self, directory_id: &str, verified: EpochHash) -> Result<(), AkdError>;
+}
+
+/// Checks a newly verified epoch and root hash of a directory against the last one
+/// verified, if any, failing with [VerificationError::Rollback] if it is older, or has
+/// a different root hash at the same epoch. Returns whether the newly verified one
+/// should replace the last one in a [TrustStore].
+pub fn check_rollback(
+    directory_id: &str,
+    last: Option<&EpochHash>,
+    verified: &EpochHash,
+) -> Result<bool, AkdError> {
+    let last = match last {
+        Some(last) => last,
+        None => return Ok(true),
+    };
+    if verified.epoch() < last.epoch() {
+        return Err(VerificationError::Rollback(format!(
+            "Directory {} served epoch {}, after epoch {} was verified",
+            directory_id,
+            verified.epoch(),
+            last.epoch()
+        ))
+        .into());
+    }
+    if verified.epoch() == last.epoch() {
+        if verified.hash() != last.hash() {
+            return Err(VerificationError::Rollback(format!(
+                "Directory {} served a different root hash for the verified epoch {}",
+                directory_id,
+                verified.epoch()
+            ))
+            .into());
+        }
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Verifies a lookup proof as [lookup_verify], against the root hash of the epoch the
+/// lookup was served at, and records that epoch and root hash in the trust store once
+/// the proof verifies. Fails with [VerificationError::Rollback] if the directory served
+/// an epoch older than the last one verified, or a different root hash for it.
+pub fn lookup_verify_with_trust_store(
+    store: &dyn TrustStore,
+    directory_id: &str,
+    vrf_public_key: &[u8],
+    epoch_hash: EpochHash,
+    akd_label: AkdLabel,
+    proof: LookupProof,
+) -> Result<VerifyResult, AkdError> {
+    let result = lookup_verify(vrf_public_key, epoch_hash.hash(), akd_label, proof)?;
+    store.record(directory_id, epoch_hash)?;
+    Ok(result)
+}
+
+/// Verifies a key history proof as [key_history_verify], and records the epoch and
+/// root hash it was served at in the trust store once it verifies, see
+/// [lookup_verify_with_trust_store]
+pub fn key_history_verify_with_trust_store(
+    store: &dyn TrustStore,
+    directory_id: &str,
+    vrf_public_key: &[u8],
+    epoch_hash: EpochHash,
+    akd_label: AkdLabel,
+    proof: HistoryProof,
+    params: HistoryVerificationParams,
+) -> Result<Vec<VerifyResult>, AkdError> {
+    let results = key_history_verify(
+        vrf_public_key,
+        epoch_hash.hash(),
+        epoch_hash.epoch(),
+        akd_label,
+        proof,
+        params,
+    )?;
+    store.record(directory_id, epoch_hash)?;
+    Ok(results)
+}
+
+/// A [TrustStore] persisted to a file, with one line per directory holding its id,
+/// the last verified epoch and the hex-encoded root hash. The file is rewritten to
+/// a temporary file first, which is synced to disk before being moved over the
+/// previous one, so a failed write or a crash leaves the previous state intact.
+/// Directory ids must not contain whitespace.
+///
+/// The records are only atomic across the threads sharing one instance: the file
+/// isn't locked, so separate instances (in the same process or not) persisting to
+/// the same file can overwrite each other's records, and must not be used at once.
+#[derive(Debug)]
+pub struct FileTrustStore {
+    path: PathBuf,
+    /// Serializes the accesses to the file by the threads sharing this instance
+    lock: Mutex<()>,
+}
+
+impl FileTrustStore {
+    /// Creates a store persisted to the given file, which is created on the first
+    /// update if it doesn't exist
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read_all(&self) -> Result<BTreeMap<String, EpochHash>, AkdError> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(err) => return Err(self.io_error("read", err)),
+        };
+        let corrupted = |line: &str| {
+            AkdError::Storage(StorageError::Corruption(format!(
+                "Malformed trust store entry in {}: {}",
+                self.path.display(),
+                line
+            )))
+        };
+        let mut entries = BTreeMap::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let parts = line.split_whitespace().collect::<Vec<_>>();
+            let (directory_id, epoch, hash) = match parts[..] {
+                [directory_id, epoch, hash] => (directory_id, epoch, hash),
+                _ => return Err(corrupted(line)),
+            };
+            let epoch = epoch.parse::<u64>().map_err(|_| corrupted(line))?;
+            let hash: Digest = hex::decode(hash)
+                .ok()
+                .and_then(|hash| crate::hash::try_parse_digest(&hash).ok())
+                .ok_or_else(|| corrupted(line))?;
+            entries.insert(directory_id.to_string(), EpochHash(epoch, hash));
+        }
+        Ok(entries)
+    }
+
+    fn io_error(&self, action: &str, err: std::io::Error) -> AkdError {
+        AkdError::Storage(StorageError::Other(format!(
+            "Failed to {} trust store {}: {}",
+            action,
+            self.path.display(),
+            err
+        )))
+    }
+}
+
+impl TrustStore for FileTrustStore {
+    fn get(&self, directory_id: &str) -> Result<Option<EpochHash>, AkdError> {
+        let _guard = self.lock.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(self.read_all()?.remove(directory_id))
+    }
+
+    fn record(&self, directory_id: &str, verified: EpochHash) -> Result<(), AkdError> {
+        if directory_id.is_empty() || directory_id.contains(char::is_whitespace) {
+            return Err(AkdError::Storage(StorageError::Other(format!(
+                "Invalid trust store directory id {:?}",
+                directory_id
+            ))));
+        }
+        // the check and the update happen under the same lock, so a concurrent
+        // record can't be overwritten by an older epoch checked before it
+        let _guard = self.lock.lock().unwrap_or_else(|err| err.into_inner());
+        let mut entries = self.read_all()?;
+        if !check_rollback(directory_id, entries.get(directory_id), &verified)? {
+            return Ok(());
+        }
+        entries.insert(directory_id.to_string(), verified);
+        let contents = entries
+            .iter()
+            .map(|(directory_id, verified)| {
+                format!(
+                    "{} {} {}\n",
+                    directory_id,
+                    verified.epoch(),
+                    hex::encode(verified.hash())
+                )
+            })
+            .collect::<String>();
+
+        let mut tmp_path = self.path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        std::fs::File::create(&tmp_path)
+            .and_then(|mut file| {
+                file.write_all(contents.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|()| std::fs::rename(&tmp_path, &self.path))
+            .map_err(|err| self.io_error("write", err))
+    }
+}
@@ -8,23 +8,51 @@
 //! Implementation of a auditable key directory
 
 use crate::append_only_zks::{Azks, InsertMode};
-use crate::ecvrf::{VRFKeyStorage, VRFPublicKey};
+use crate::ecvrf::{BatchProof, Proof, VRFKeyStorage, VRFPublicKey, VrfError};
 use crate::errors::{AkdError, DirectoryError, StorageError};
 use crate::helper_structs::LookupInfo;
+use crate::integrity::IntegrityReport;
+use crate::proof_cache::LookupProofCache;
 use crate::storage::manager::StorageManager;
-use crate::storage::types::{DbRecord, ValueState, ValueStateRetrievalFlag};
+use crate::storage::types::{
+    DbRecord, LabelMappingRecord, RootHashRecord, StorageType, ValueState, ValueStateRetrievalFlag,
+    LABEL_MAPPING_KEY,
+};
 use crate::storage::Database;
+use crate::tree_node::{NodeKey, TreeNode};
 use crate::{
-    AkdLabel, AkdValue, AppendOnlyProof, Digest, EpochHash, HistoryProof, LookupProof, Node,
-    NonMembershipProof, UpdateProof,
+    AbsenceProof, AkdLabel, AkdValue, AppendOnlyProof, BatchLookupProof, Digest, EpochHash,
+    HistoryProof, LayerProof, LookupProof, LookupWithConsistencyProof, MembershipProof, Node,
+    NodeLabel, NonMembershipProof, SampleAuditProof, SegmentedAppendOnlyProof, SignedTreeHead,
+    UpdateProof, ValueDisclosure,
 };
 
-use akd_core::utils::{commit_value, get_commitment_nonce};
-use akd_core::VersionFreshness;
-use log::{error, info};
-use std::collections::HashMap;
+use crate::logging::{error, info};
+use akd_core::commitment::{NonceCommitment, ValueCommitment};
+use akd_core::label_mapper::{DefaultLabelMapper, LabelMapper};
+use akd_core::utils::{bind_expiry, get_future_marker_powers};
+use akd_core::{SizeOf, VersionFreshness};
+use futures_util::StreamExt;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::ops::{Bound, RangeBounds};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+use std::time::Instant;
+use tokio::sync::{broadcast, watch, RwLock};
+use tokio_util::sync::CancellationToken;
+
+/// The number of tree nodes added to the storage transaction in a single batch
+/// by [Directory::bulk_initialize]
+pub const BULK_INITIALIZE_BATCH_SIZE: usize = 10_000;
+
+/// The number of [DirectoryEvent]s which are buffered for each subscriber
+pub const DIRECTORY_EVENT_CAPACITY: usize = 1024;
+
+/// How often [Directory::publish_with_progress] reports the number of nodes
+/// written while inserting the new leaves
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+pub const PUBLISH_PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
 
 /// The representation of a auditable key directory
 pub struct Directory<S: Database, V> {
@@ -38,6 +66,20 @@ pub struct Directory<S: Database, V> {
     /// (in this case we do utilize the write() lock which can only occur 1
     /// at a time and gates further read() locks being acquired during write()).
     cache_lock: Arc<RwLock<()>>,
+    /// Broadcasts [DirectoryEvent]s to any subscribers
+    events: broadcast::Sender<DirectoryEvent>,
+    /// The scheme committing to the values stored in the tree
+    commitment: Arc<dyn ValueCommitment>,
+    /// An optional cache of the lookup proofs served at the current epoch
+    proof_cache: Option<Arc<LookupProofCache>>,
+    /// The latest epoch and root hash, see [Directory::watch_epoch_hash]
+    epoch_hash: Arc<watch::Sender<EpochHash>>,
+    /// The mapping of the labels to the inputs of the VRF
+    label_mapper: Arc<dyn LabelMapper>,
+    /// The maximum length of a label, in bytes, see [Directory::with_max_label_length]
+    max_label_length: Option<usize>,
+    /// The size absence proofs are padded to, see [Directory::with_absence_proof_padding]
+    absence_proof_size: Option<usize>,
 }
 
 // Manual implementation of Clone, see: https://github.com/rust-lang/rust/issues/41481
@@ -48,6 +90,13 @@ impl<S: Database, V: VRFKeyStorage> Clone for Directory<S, V> {
             vrf: self.vrf.clone(),
             read_only: self.read_only,
             cache_lock: self.cache_lock.clone(),
+            events: self.events.clone(),
+            commitment: self.commitment.clone(),
+            proof_cache: self.proof_cache.clone(),
+            epoch_hash: self.epoch_hash.clone(),
+            label_mapper: self.label_mapper.clone(),
+            max_label_length: self.max_label_length,
+            absence_proof_size: self.absence_proof_size,
         }
     }
 }
@@ -55,54 +104,450 @@ impl<S: Database, V: VRFKeyStorage> Clone for Directory<S, V> {
 impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
     /// Creates a new (stateless) instance of a auditable key directory.
     /// Takes as input a pointer to the storage being used for this instance.
-    /// The state is stored in the storage.
+    /// The state is stored in the storage. Multiple independent directories may
+    /// share a single storage backend, provided each is given a storage handle
+    /// scoped to its own namespace (see [Database::with_namespace]).
     pub async fn new(
         storage: StorageManager<S>,
         vrf: V,
         read_only: bool,
     ) -> Result<Self, AkdError> {
-        let azks = Directory::<S, V>::get_azks_from_storage(&storage, false).await;
+        Self::new_with_label_mapper(storage, vrf, read_only, DefaultLabelMapper).await
+    }
 
-        if read_only && azks.is_err() {
-            return Err(AkdError::Directory(DirectoryError::ReadOnlyDirectory(
-                format!(
-                    "Cannot start directory in read-only mode when AZKS is missing, error: {:?}",
-                    azks.err().take()
-                ),
-            )));
-        } else if azks.is_err() {
-            // generate a new azks if one is not found
-            let azks = Azks::new::<_>(&storage).await?;
-            // store it
-            storage.set(DbRecord::Azks(azks.clone())).await?;
-        }
+    /// Creates a new directory whose labels are mapped to the inputs of the VRF
+    /// with the given [LabelMapper]. The id of the mapper is persisted when the
+    /// directory is created, and opening the directory with a different mapper
+    /// fails with [DirectoryError::LabelMapperMismatch]. A directory created
+    /// without a mapper uses the [DefaultLabelMapper].
+    pub async fn new_with_label_mapper<M: LabelMapper + 'static>(
+        storage: StorageManager<S>,
+        vrf: V,
+        read_only: bool,
+        label_mapper: M,
+    ) -> Result<Self, AkdError> {
+        let azks = match Directory::<S, V>::get_azks_from_storage(&storage, false).await {
+            Ok(azks) => azks,
+            Err(AkdError::Storage(StorageError::NotFound(err))) if read_only => {
+                return Err(AkdError::Directory(DirectoryError::ReadOnlyDirectory(
+                    format!(
+                        "Cannot start directory in read-only mode when AZKS is missing, error: {:?}",
+                        err
+                    ),
+                )));
+            }
+            Err(AkdError::Storage(StorageError::NotFound(_))) => {
+                // generate a new azks if one is not found
+                let azks = Azks::new::<_>(&storage).await?;
+                // store it, unless another directory concurrently stored one
+                match storage
+                    .compare_and_set(DbRecord::Azks(azks.clone()), None, vec![])
+                    .await
+                {
+                    Ok(()) => azks,
+                    Err(StorageError::Conflict(_)) => {
+                        Directory::<S, V>::get_azks_from_storage(&storage, false).await?
+                    }
+                    Err(err) => return Err(AkdError::Storage(err)),
+                }
+            }
+            Err(err) => return Err(err),
+        };
+        Directory::<S, V>::check_label_mapper(&storage, &azks, read_only, &label_mapper).await?;
+        let epoch_hash = EpochHash(azks.get_latest_epoch(), azks.get_root_hash(&storage).await?);
 
         Ok(Directory {
             storage,
             read_only,
             cache_lock: Arc::new(RwLock::new(())),
             vrf,
+            events: broadcast::channel(DIRECTORY_EVENT_CAPACITY).0,
+            commitment: Arc::new(NonceCommitment),
+            proof_cache: None,
+            epoch_hash: Arc::new(watch::channel(epoch_hash).0),
+            label_mapper: Arc::new(label_mapper),
+            max_label_length: None,
+            absence_proof_size: None,
         })
     }
 
-    /// Updates the directory to include the updated key-value pairs.
+    /// Checks the mapper against the id persisted in the storage, persisting it
+    /// if the directory has none yet. Directories which predate the persisted id
+    /// used the [DefaultLabelMapper], so may only be opened with it once published.
+    async fn check_label_mapper<M: LabelMapper>(
+        storage: &StorageManager<S>,
+        azks: &Azks,
+        read_only: bool,
+        label_mapper: &M,
+    ) -> Result<(), AkdError> {
+        let mapper_id = label_mapper.id();
+        let stored_id = match storage.get::<LabelMappingRecord>(&LABEL_MAPPING_KEY).await {
+            Ok(DbRecord::LabelMapping(record)) => Some(record.mapper_id),
+            Ok(_) => {
+                return Err(AkdError::Storage(StorageError::TypeMismatch(
+                    "Expected a label mapping record".to_string(),
+                )))
+            }
+            Err(StorageError::NotFound(_)) => None,
+            Err(err) => return Err(AkdError::Storage(err)),
+        };
+
+        let expected_id = match &stored_id {
+            Some(stored_id) => Some(stored_id.clone()),
+            None if azks.get_latest_epoch() > 0 => Some(DefaultLabelMapper.id()),
+            None => None,
+        };
+        if let Some(expected_id) = expected_id {
+            if expected_id != mapper_id {
+                return Err(AkdError::Directory(DirectoryError::LabelMapperMismatch(
+                    format!(
+                        "the directory's labels are mapped by {}, not {}",
+                        String::from_utf8_lossy(&expected_id),
+                        String::from_utf8_lossy(&mapper_id)
+                    ),
+                )));
+            }
+        }
+        if stored_id.is_none() && !read_only {
+            storage
+                .set(DbRecord::LabelMapping(
+                    DbRecord::build_label_mapping_record(mapper_id),
+                ))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// The VRF of the directory, over the labels mapped by its [LabelMapper]
+    fn label_vrf(&self) -> MappedVrf<'_, V> {
+        MappedVrf {
+            vrf: &self.vrf,
+            label_mapper: self.label_mapper.as_ref(),
+        }
+    }
+
+    /// Sets the scheme committing to the values stored in the tree, which
+    /// defaults to [NonceCommitment]. The scheme must be the same every time a
+    /// directory is opened over the same storage, since the proofs for existing
+    /// leaves re-derive their commitment nonces with it.
+    pub fn with_value_commitment<C: ValueCommitment + 'static>(mut self, commitment: C) -> Self {
+        self.commitment = Arc::new(commitment);
+        self
+    }
+
+    /// Enables caching of the proofs served by [Directory::lookup], holding up to
+    /// (approximately) `limit_bytes` of proofs. The cache is shared by the clones
+    /// of this directory, and emptied whenever a new epoch is published.
+    pub fn with_lookup_proof_cache(mut self, limit_bytes: usize) -> Self {
+        self.proof_cache = Some(Arc::new(LookupProofCache::new(limit_bytes)));
+        self
+    }
+
+    /// Limits the length of the labels the directory accepts to `max_bytes`. Publishing,
+    /// looking up, or requesting the history of a longer label fails with
+    /// [DirectoryError::LabelTooLong], before the label is hashed or any storage is
+    /// accessed. Labels are unbounded by default.
+    pub fn with_max_label_length(mut self, max_bytes: usize) -> Self {
+        self.max_label_length = Some(max_bytes);
+        self
+    }
+
+    /// Hardens [Directory::lookup_nonexistent] against the enumeration of the labels
+    /// from the proofs served: the [AbsenceProof]s are padded to `proof_size` bytes
+    /// (as given by [akd_core::SizeOf]), and the nodes of two more paths are fetched
+    /// from storage for each of them, as many as a [Directory::lookup] of an existing
+    /// label fetches, so that neither the size of the proof nor the storage accesses
+    /// depend on where the label would be in the tree.
+    ///
+    /// Proofs larger than `proof_size` are left as is, so it should be at least
+    /// [AbsenceProof::size_at_depth] of the number of ancestors of the deepest leaf
+    /// of the tree, with a margin for the tree to grow: as the labels are
+    /// pseudorandom, the leaves are rarely more than a few levels deeper than
+    /// log2 of their number (each published version adding two leaves).
+    pub fn with_absence_proof_padding(mut self, proof_size: usize) -> Self {
+        self.absence_proof_size = Some(proof_size);
+        self
+    }
+
+    /// Checks the length of a label against [Directory::with_max_label_length]
+    fn check_label_length(&self, label: &AkdLabel) -> Result<(), AkdError> {
+        match self.max_label_length {
+            Some(max_length) if label.len() > max_length => Err(AkdError::Directory(
+                DirectoryError::LabelTooLong(label.len(), max_length),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The cache of lookup proofs, if enabled
+    pub fn lookup_proof_cache(&self) -> Option<&LookupProofCache> {
+        self.proof_cache.as_deref()
+    }
+
+    /// Subscribes to the [DirectoryEvent]s emitted by this directory (and its
+    /// clones) from this point on. A subscriber which falls more than
+    /// [DIRECTORY_EVENT_CAPACITY] events behind will miss the oldest events.
+    pub fn subscribe(&self) -> broadcast::Receiver<DirectoryEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, event: DirectoryEvent) {
+        // an error only means there are no subscribers at the moment
+        let _ = self.events.send(event);
+    }
+
+    /// The latest epoch and root hash of this directory, without reading the
+    /// Azks record from storage. This is updated by [Directory::publish] (and
+    /// [Directory::bulk_initialize]) on this directory or its clones, and, for a
+    /// directory reading the storage written by another one, by
+    /// [Directory::poll_for_azks_changes].
+    pub fn get_epoch_hash(&self) -> EpochHash {
+        self.epoch_hash.borrow().clone()
+    }
+
+    /// Watches the latest epoch and root hash of this directory (see
+    /// [Directory::get_epoch_hash]). The receiver always holds the latest value,
+    /// and is notified of every change.
+    pub fn watch_epoch_hash(&self) -> watch::Receiver<EpochHash> {
+        self.epoch_hash.subscribe()
+    }
+
+    fn set_epoch_hash(&self, epoch_hash: EpochHash) {
+        if let Some(cache) = &self.proof_cache {
+            cache.invalidate();
+        }
+        self.epoch_hash.send_replace(epoch_hash.clone());
+        self.emit(DirectoryEvent::EpochPublished(epoch_hash));
+    }
+
+    /// Updates the directory to include the updated key-value pairs. A batch
+    /// with several updates for the same label is rejected (see
+    /// [Directory::publish_with_options]).
     pub async fn publish(&self, updates: Vec<(AkdLabel, AkdValue)>) -> Result<EpochHash, AkdError> {
+        self.publish_with_options(updates, PublishOptions::default())
+            .await
+    }
+
+    /// Updates the directory to include the updated key-value pairs, as
+    /// [Directory::publish], with the given options.
+    pub async fn publish_with_options(
+        &self,
+        updates: Vec<(AkdLabel, AkdValue)>,
+        options: PublishOptions,
+    ) -> Result<EpochHash, AkdError> {
+        let (progress, _) = watch::channel(PublishProgress::default());
+        self.publish_with_progress(updates, options, &progress, &CancellationToken::new())
+            .await
+    }
+
+    /// Updates the directory to include the updated key-value pairs, as
+    /// [Directory::publish_with_options], reporting the [PublishProgress] to the
+    /// given channel. The publish is aborted with [DirectoryError::PublishCancelled]
+    /// if the cancellation token is cancelled before the publish starts committing,
+    /// in which case nothing is written to storage. The token is checked between the
+    /// phases of the publish, so a phase which has started runs to completion first.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "publish", level = "info", skip_all, fields(num_updates = updates.len(), epoch = tracing::field::Empty))
+    )]
+    pub async fn publish_with_progress(
+        &self,
+        updates: Vec<(AkdLabel, AkdValue)>,
+        options: PublishOptions,
+        progress: &watch::Sender<PublishProgress>,
+        cancellation: &CancellationToken,
+    ) -> Result<EpochHash, AkdError> {
         if self.read_only {
             return Err(AkdError::Directory(DirectoryError::ReadOnlyDirectory(
                 "Cannot publish while in read-only mode".to_string(),
             )));
         }
 
+        for (label, _) in updates.iter() {
+            self.check_label_length(label)?;
+        }
+        let updates = dedup_updates(updates, options.duplicate_labels)?;
+
         // The guard will be dropped at the end of the publish
         let _guard = self.cache_lock.read().await;
 
-        let mut update_set = Vec::<Node>::new();
-        let mut user_data_update_set = Vec::<ValueState>::new();
+        let mut current_azks = self.retrieve_current_azks().await?;
+        let current_epoch = current_azks.get_latest_epoch();
+        let next_epoch = current_epoch + 1;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("epoch", next_epoch);
+
+        check_publish_cancelled(cancellation, next_epoch)?;
+        set_publish_phase(progress, PublishPhase::ComputingLabels);
+        let (update_set, user_data_update_set) = self
+            .build_update_set(updates, &options.ttls, current_epoch, next_epoch)
+            .await?;
+
+        if update_set.is_empty() {
+            info!("After filtering for duplicated user information, there is no publish which is necessary (0 updates)");
+            // The AZKS has not been updated/mutated at this point, so we can just return the root hash from before
+            let root_hash = current_azks.get_root_hash::<_>(&self.storage).await?;
+            set_publish_phase(progress, PublishPhase::Done);
+            return Ok(EpochHash(current_epoch, root_hash));
+        }
+
+        let transaction = match TransactionGuard::begin(&self.storage) {
+            Some(transaction) => transaction,
+            None => {
+                error!("Transaction is already active");
+                return Err(AkdError::Storage(StorageError::TransactionInProgress));
+            }
+        };
+        info!("Starting inserting new leaves");
+
+        let num_leaves = update_set.len() as u64;
+        let inserted = async {
+            check_publish_cancelled(cancellation, next_epoch)?;
+            set_publish_phase(progress, PublishPhase::InsertingLeaves);
+            let hashes_computed = self
+                .report_nodes_written(
+                    current_azks.batch_insert_nodes::<_>(
+                        &self.storage,
+                        update_set,
+                        InsertMode::Directory,
+                    ),
+                    progress,
+                )
+                .await?;
+            progress.send_modify(|progress| {
+                progress.items_inserted = num_leaves;
+                progress.nodes_written = self.storage.transaction_count() as u64;
+                progress.hashes_computed = hashes_computed;
+            });
+
+            check_publish_cancelled(cancellation, next_epoch)?;
+            set_publish_phase(progress, PublishPhase::SigningTreeHead);
+            let tree_head = self.sign_new_tree_head(&current_azks, next_epoch).await?;
+
+            check_publish_cancelled(cancellation, next_epoch)?;
+            Ok::<_, AkdError>(tree_head)
+        };
+        // If we fail to do the batch-leaf insert, the transaction is rolled back as it's
+        // dropped, so we can try again cleanly
+        let tree_head = inserted.await?;
+        set_publish_phase(progress, PublishPhase::Committing);
+
+        // batch all the inserts into a single write to storage (in this case it insert's into the transaction log)
+        let mut root_hash_record = DbRecord::build_root_hash_record(
+            next_epoch,
+            tree_head.root_hash,
+            tree_head.timestamp,
+            user_data_update_set.len() as u64,
+        );
+        root_hash_record.metadata = options.metadata;
+        let mut updates = vec![
+            DbRecord::Azks(current_azks.clone()),
+            DbRecord::RootHash(root_hash_record),
+            DbRecord::TreeHead(tree_head.clone()),
+        ];
+        for update in user_data_update_set.into_iter() {
+            updates.push(DbRecord::ValueState(update));
+        }
+        self.storage.batch_set(updates).await?;
+
+        // Commit the transaction, unless another publisher committed this epoch first
+        info!("Committing transaction");
+        transaction.commit_if_epoch(current_epoch).await?;
+        info!("Transaction committed");
+
+        let epoch_hash = EpochHash(next_epoch, tree_head.root_hash);
+        self.set_epoch_hash(epoch_hash.clone());
+        set_publish_phase(progress, PublishPhase::Done);
+        Ok(epoch_hash)
+        // At the moment the tree root is not being written anywhere. Eventually we
+        // want to change this to call a write operation to post to a blockchain or some such thing
+    }
+
+    /// Previews a [Directory::publish] of the given updates without committing
+    /// anything, returning the labels whose value would change, along with the root
+    /// hash the publish would result in and the number of tree nodes it would write.
+    /// The new leaves are inserted into a storage transaction which is then rolled
+    /// back, so a publish can't run concurrently with the preview.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(num_updates = updates.len()))
+    )]
+    pub async fn preview_publish(
+        &self,
+        updates: Vec<(AkdLabel, AkdValue)>,
+    ) -> Result<PublishPreview, AkdError> {
+        for (label, _) in updates.iter() {
+            self.check_label_length(label)?;
+        }
+        let updates = dedup_updates(updates, PublishOptions::default().duplicate_labels)?;
+
+        // The guard will be dropped at the end of the preview
+        let _guard = self.cache_lock.read().await;
 
         let mut current_azks = self.retrieve_current_azks().await?;
         let current_epoch = current_azks.get_latest_epoch();
         let next_epoch = current_epoch + 1;
 
+        let (update_set, user_data_update_set) = self
+            .build_update_set(updates, &HashMap::new(), current_epoch, next_epoch)
+            .await?;
+        let mut changed_labels = user_data_update_set
+            .into_iter()
+            .map(|state| state.username)
+            .collect::<Vec<_>>();
+        changed_labels.sort_by(|a, b| a.cmp(b));
+
+        if update_set.is_empty() {
+            let root_hash = current_azks.get_root_hash::<_>(&self.storage).await?;
+            return Ok(PublishPreview {
+                epoch: current_epoch,
+                root_hash,
+                changed_labels,
+                estimated_node_writes: 0,
+            });
+        }
+
+        let transaction = match TransactionGuard::begin(&self.storage) {
+            Some(transaction) => transaction,
+            None => {
+                error!("Transaction is already active");
+                return Err(AkdError::Storage(StorageError::TransactionInProgress));
+            }
+        };
+        let preview = async {
+            current_azks
+                .batch_insert_nodes::<_>(&self.storage, update_set, InsertMode::Directory)
+                .await?;
+            let estimated_node_writes = self.storage.transaction_count();
+            let root_hash = current_azks
+                .get_root_hash_safe::<_>(&self.storage, next_epoch)
+                .await?;
+            Ok::<_, AkdError>(PublishPreview {
+                epoch: next_epoch,
+                root_hash,
+                changed_labels,
+                estimated_node_writes,
+            })
+        }
+        .await;
+        // Nothing is committed, whether the preview succeeded or not
+        transaction.rollback()?;
+        preview
+    }
+
+    /// Computes the leaves to insert into the tree and the user states to write to
+    /// publish the given (deduplicated) updates at `next_epoch`, skipping the updates
+    /// re-publishing the latest value of a label
+    async fn build_update_set(
+        &self,
+        updates: Vec<(AkdLabel, AkdValue)>,
+        ttls: &HashMap<AkdLabel, u64>,
+        current_epoch: u64,
+        next_epoch: u64,
+    ) -> Result<(Vec<Node>, Vec<ValueState>), AkdError> {
+        let mut update_set = Vec::<Node>::new();
+        let mut user_data_update_set = Vec::<ValueState>::new();
+
         let mut keys: Vec<AkdLabel> = updates.iter().map(|(uname, _val)| uname.clone()).collect();
         // sort the keys, as inserting in primary-key order is more efficient for MySQL
         keys.sort_by(|a, b| a.cmp(b));
@@ -134,7 +579,7 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
             .collect::<Vec<_>>();
 
         let vrf_map = self
-            .vrf
+            .label_vrf()
             .get_node_labels(&vrf_computations)
             .await?
             .into_iter()
@@ -143,6 +588,7 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         let commitment_key = self.derive_commitment_key().await?;
 
         for (uname, val) in updates {
+            let expiry_epoch = ttls.get(&uname).map(|ttl| next_epoch.saturating_add(*ttl));
             match all_user_versions_retrieved.get(&uname) {
                 None => {
                     // no data found for the user
@@ -155,16 +601,21 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                             )
                         })?;
 
-                    let value_to_add = commit_value(&commitment_key, &label, latest_version, &val);
+                    let value_to_add = bind_expiry(
+                        self.commitment
+                            .commit(&commitment_key, &label, latest_version, &val),
+                        expiry_epoch,
+                    );
                     update_set.push(Node {
                         label,
                         hash: value_to_add,
                     });
-                    let latest_state =
+                    let mut latest_state =
                         ValueState::new(uname, val, latest_version, label, next_epoch);
+                    latest_state.expiry_epoch = expiry_epoch;
                     user_data_update_set.push(latest_state);
                 }
-                Some((_, previous_value)) if val == *previous_value => {
+                Some((_, previous_value)) if val == *previous_value && expiry_epoch.is_none() => {
                     // skip this version because the user is trying to re-publish the already most recent value
                     // Issue #197: https://github.com/novifinancial/akd/issues/197
                 }
@@ -186,8 +637,11 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                             )
                         })?;
                     let stale_value_to_add = crate::hash::hash(&crate::EMPTY_VALUE);
-                    let fresh_value_to_add =
-                        commit_value(&commitment_key, &fresh_label, latest_version, &val);
+                    let fresh_value_to_add = bind_expiry(
+                        self.commitment
+                            .commit(&commitment_key, &fresh_label, latest_version, &val),
+                        expiry_epoch,
+                    );
                     update_set.push(Node {
                         label: stale_label,
                         hash: stale_value_to_add,
@@ -196,78 +650,367 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                         label: fresh_label,
                         hash: fresh_value_to_add,
                     });
-                    let new_state =
+                    let mut new_state =
                         ValueState::new(uname, val, latest_version, fresh_label, next_epoch);
+                    new_state.expiry_epoch = expiry_epoch;
                     user_data_update_set.push(new_state);
                 }
             }
         }
 
-        if update_set.is_empty() {
-            info!("After filtering for duplicated user information, there is no publish which is necessary (0 updates)");
-            // The AZKS has not been updated/mutated at this point, so we can just return the root hash from before
-            let root_hash = current_azks.get_root_hash::<_>(&self.storage).await?;
-            return Ok(EpochHash(current_epoch, root_hash));
-        }
+        Ok((update_set, user_data_update_set))
+    }
 
-        if let false = self.storage.begin_transaction() {
-            error!("Transaction is already active");
-            return Err(AkdError::Storage(StorageError::Transaction(
-                "Transaction is already active".to_string(),
+    /// Populates an empty directory with an initial set of users in a single
+    /// epoch. This produces the same tree as a [Directory::publish] of the
+    /// same entries, but the tree is built bottom-up in sorted label order,
+    /// hashing each node once, and its nodes are added to the storage transaction
+    /// in batches of [BULK_INITIALIZE_BATCH_SIZE]. The transaction is committed
+    /// like a publish's, only if no other epoch was published meanwhile, so
+    /// readers will not observe the new epoch until all nodes and user states
+    /// are persisted.
+    ///
+    /// Fails if the directory has already been published to.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(epoch = tracing::field::Empty))
+    )]
+    pub async fn bulk_initialize<I>(&self, entries: I) -> Result<EpochHash, AkdError>
+    where
+        I: IntoIterator<Item = (AkdLabel, AkdValue)>,
+    {
+        if self.read_only {
+            return Err(AkdError::Directory(DirectoryError::ReadOnlyDirectory(
+                "Cannot bulk initialize while in read-only mode".to_string(),
             )));
         }
-        info!("Starting inserting new leaves");
 
-        if let Err(err) = current_azks
-            .batch_insert_nodes::<_>(&self.storage, update_set, InsertMode::Directory)
-            .await
-        {
-            // If we fail to do the batch-leaf insert, we should rollback the transaction so we can try again cleanly.
-            // Only fails if transaction is not currently active.
-            let _ = self.storage.rollback_transaction();
-            // bubble up the err
-            return Err(err);
+        // The guard will be dropped at the end of the initialization
+        let _guard = self.cache_lock.read().await;
+
+        let mut current_azks = self.retrieve_current_azks().await?;
+        let current_epoch = current_azks.get_latest_epoch();
+        if current_epoch != 0 {
+            return Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+                "Cannot bulk initialize a directory which is already at epoch {}",
+                current_epoch
+            ))));
         }
+        let next_epoch = current_epoch + 1;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("epoch", next_epoch);
 
-        // batch all the inserts into a single write to storage (in this case it insert's into the transaction log)
-        let mut updates = vec![DbRecord::Azks(current_azks.clone())];
-        for update in user_data_update_set.into_iter() {
-            updates.push(DbRecord::ValueState(update));
+        let mut entries =
+            dedup_updates(entries.into_iter().collect(), DuplicateLabelPolicy::Reject)?;
+        for (label, _) in entries.iter() {
+            self.check_label_length(label)?;
         }
-        self.storage.batch_set(updates).await?;
+        // sort the keys, as inserting in primary-key order is more efficient for MySQL
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
 
-        // Commit the transaction
-        info!("Committing transaction");
-        if let Err(err) = self.storage.commit_transaction().await {
-            let _ = self.storage.rollback_transaction();
-            return Err(AkdError::Storage(err));
-        } else {
-            info!("Transaction committed");
+        let vrf_computations = entries
+            .iter()
+            .map(|(label, _)| (label.clone(), VersionFreshness::Fresh, 1u64))
+            .collect::<Vec<_>>();
+        let vrf_map = self
+            .label_vrf()
+            .get_node_labels(&vrf_computations)
+            .await?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        let commitment_key = self.derive_commitment_key().await?;
+
+        let mut update_set = Vec::<Node>::with_capacity(entries.len());
+        let mut user_data_update_set = Vec::<DbRecord>::with_capacity(entries.len());
+        for (uname, val) in entries {
+            let label = *vrf_map
+                .get(&(uname.clone(), VersionFreshness::Fresh, 1))
+                .ok_or_else(|| {
+                    crate::ecvrf::VrfError::SigningKey(
+                        "Failed to generate VRF for given username".to_string(),
+                    )
+                })?;
+            update_set.push(Node {
+                label,
+                hash: self.commitment.commit(&commitment_key, &label, 1, &val),
+            });
+            user_data_update_set.push(DbRecord::ValueState(ValueState::new(
+                uname, val, 1, label, next_epoch,
+            )));
         }
 
-        let root_hash = current_azks
-            .get_root_hash_safe::<_>(&self.storage, next_epoch)
+        let transaction = match TransactionGuard::begin(&self.storage) {
+            Some(transaction) => transaction,
+            None => return Err(AkdError::Storage(StorageError::TransactionInProgress)),
+        };
+        info!("Starting bulk insertion of {} leaves", update_set.len());
+        current_azks
+            .bulk_insert_nodes::<_>(
+                &self.storage,
+                update_set,
+                InsertMode::Directory,
+                BULK_INITIALIZE_BATCH_SIZE,
+            )
             .await?;
+        let tree_head = self.sign_new_tree_head(&current_azks, next_epoch).await?;
+
+        let mut updates = vec![
+            DbRecord::Azks(current_azks.clone()),
+            DbRecord::RootHash(DbRecord::build_root_hash_record(
+                next_epoch,
+                tree_head.root_hash,
+                tree_head.timestamp,
+                user_data_update_set.len() as u64,
+            )),
+            DbRecord::TreeHead(tree_head.clone()),
+        ];
+        updates.extend(user_data_update_set);
+        self.storage.batch_set(updates).await?;
 
-        Ok(EpochHash(next_epoch, root_hash))
-        // At the moment the tree root is not being written anywhere. Eventually we
-        // want to change this to call a write operation to post to a blockchain or some such thing
+        // Commit the transaction, unless another publisher committed this epoch first
+        transaction.commit_if_epoch(current_epoch).await?;
+        info!("Bulk initialization completed");
+
+        let epoch_hash = EpochHash(next_epoch, tree_head.root_hash);
+        self.set_epoch_hash(epoch_hash.clone());
+        Ok(epoch_hash)
     }
 
     /// Provides proof for correctness of latest version
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(label = %crate::logging::label_prefix(&uname.0), epoch = tracing::field::Empty))
+    )]
     pub async fn lookup(&self, uname: AkdLabel) -> Result<(LookupProof, EpochHash), AkdError> {
         // The guard will be dropped at the end of the proof generation
         let _guard = self.cache_lock.read().await;
 
         let current_azks = self.retrieve_current_azks().await?;
         let current_epoch = current_azks.get_latest_epoch();
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("epoch", current_epoch);
+        if let Some(cached) = self
+            .proof_cache
+            .as_ref()
+            .and_then(|cache| cache.get(&uname, current_epoch))
+        {
+            self.emit(DirectoryEvent::LookupServed(cached.1.clone()));
+            return Ok(cached);
+        }
+        let lookup_info = self.get_lookup_info(uname.clone(), current_epoch).await?;
+
+        let root_hash = EpochHash(current_epoch, self.get_root_hash(&current_azks).await?);
+
+        let proof = self
+            .lookup_with_info(
+                uname.clone(),
+                &current_azks,
+                current_epoch,
+                lookup_info,
+                true,
+            )
+            .await?;
+        if let Some(cache) = &self.proof_cache {
+            cache.insert(uname, proof.clone(), root_hash.clone());
+        }
+        self.emit(DirectoryEvent::LookupServed(root_hash.clone()));
+        Ok((proof, root_hash))
+    }
+
+    /// Like [Directory::lookup], but fails with [DirectoryError::DeadlineExceeded]
+    /// if the proof isn't generated by the deadline. The deadline is passed down to
+    /// the storage (see [StorageManager::with_deadline]), so that no read is started
+    /// past it, and the pending reads are abandoned when it passes. A lookup whose
+    /// deadline has already passed isn't started.
+    #[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+    pub async fn lookup_with_deadline(
+        &self,
+        uname: AkdLabel,
+        deadline: Instant,
+    ) -> Result<(LookupProof, EpochHash), AkdError> {
+        self.with_deadline("lookup", deadline, |directory| async move {
+            directory.lookup(uname).await
+        })
+        .await
+    }
+
+    /// Provides proof that a label has never been published, for a lookup of a label
+    /// which doesn't exist, see [crate::client::lookup_nonexistent_verify]. Fails
+    /// with [DirectoryError::LabelExists] if the label exists, in which case it can be
+    /// looked up with [Directory::lookup], and with [DirectoryError::InvalidEpoch]
+    /// before the first publish.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(label = %crate::logging::label_prefix(&uname.0), epoch = tracing::field::Empty)
+        )
+    )]
+    pub async fn lookup_nonexistent(
+        &self,
+        uname: AkdLabel,
+    ) -> Result<(AbsenceProof, EpochHash), AkdError> {
+        self.check_label_length(&uname)?;
+        // The guard will be dropped at the end of the proof generation
+        let _guard = self.cache_lock.read().await;
+
+        let current_azks = self.retrieve_current_azks().await?;
+        let current_epoch = current_azks.get_latest_epoch();
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("epoch", current_epoch);
+        if current_epoch == 0 {
+            // the root of an empty tree isn't hashed from its children, so a
+            // non-membership proof can't be verified against it
+            return Err(AkdError::Directory(DirectoryError::InvalidEpoch(
+                "Cannot prove the absence of a label before the first publish".to_string(),
+            )));
+        }
+        if let Some(cached) = self
+            .proof_cache
+            .as_ref()
+            .and_then(|cache| cache.get_absence(&uname, current_epoch))
+        {
+            self.emit(DirectoryEvent::LookupServed(cached.1.clone()));
+            return Ok(cached);
+        }
+
+        match self
+            .storage
+            .get_user_state(&uname, ValueStateRetrievalFlag::LeqEpoch(current_epoch))
+            .await
+        {
+            Ok(_) => return Err(AkdError::Directory(DirectoryError::LabelExists(uname))),
+            Err(StorageError::NotFound(_)) => {}
+            Err(err) => return Err(AkdError::Storage(err)),
+        }
+
+        // every published label has its first version in the tree
+        let label = self
+            .label_vrf()
+            .get_node_label(&uname, VersionFreshness::Fresh, 1)
+            .await?;
+        // the labels of versions which don't exist, whose paths are fetched in place
+        // of the existence and marker paths a lookup of an existing label fetches
+        let dummy_labels = match self.absence_proof_size {
+            Some(_) => self
+                .label_vrf()
+                .get_node_labels(&[
+                    (uname.clone(), VersionFreshness::Stale, 1),
+                    (uname.clone(), VersionFreshness::Fresh, 2),
+                ])
+                .await?
+                .into_iter()
+                .map(|(_, dummy_label)| dummy_label)
+                .collect(),
+            None => vec![],
+        };
+        if !dummy_labels.is_empty() {
+            // preload the three paths together, as a lookup does
+            let mut labels = dummy_labels.clone();
+            labels.push(label);
+            current_azks.preload_labels(&self.storage, &labels).await?;
+        }
+
+        let mut proof = AbsenceProof {
+            vrf_proof: self
+                .label_vrf()
+                .get_label_proof(&uname, VersionFreshness::Fresh, 1)
+                .await?
+                .to_bytes()
+                .to_vec(),
+            non_membership_proof: current_azks
+                .get_non_membership_proof(&self.storage, label)
+                .await?,
+            padding: vec![],
+        };
+        if let Some(proof_size) = self.absence_proof_size {
+            // the preloaded nodes serve these proofs, and without a cache the nodes
+            // are fetched one path at a time, as a lookup fetches them
+            for dummy_label in dummy_labels.into_iter() {
+                current_azks
+                    .get_non_membership_proof(&self.storage, dummy_label)
+                    .await?;
+            }
+            proof.padding = vec![0u8; proof_size.saturating_sub(proof.size_of())];
+        }
+        let root_hash = EpochHash(current_epoch, self.get_root_hash(&current_azks).await?);
+
+        if let Some(cache) = &self.proof_cache {
+            cache.insert_absence(uname, proof.clone(), root_hash.clone());
+        }
+        self.emit(DirectoryEvent::LookupServed(root_hash.clone()));
+        Ok((proof, root_hash))
+    }
+
+    /// Provides proof for correctness of latest version, along with a proof
+    /// that the current root hash is an append-only extension of the root hash
+    /// at `pinned_epoch`, for clients which have pinned an older root hash
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(label = %crate::logging::label_prefix(&uname.0), pinned_epoch))
+    )]
+    pub async fn lookup_with_consistency(
+        &self,
+        uname: AkdLabel,
+        pinned_epoch: u64,
+    ) -> Result<(LookupWithConsistencyProof, EpochHash), AkdError> {
+        // The guard will be dropped at the end of the proof generation
+        let _guard = self.cache_lock.read().await;
+
+        let current_azks = self.retrieve_current_azks().await?;
+        let current_epoch = current_azks.get_latest_epoch();
+        if pinned_epoch > current_epoch {
+            return Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+                "Pinned epoch {} is greater than the current epoch {}",
+                pinned_epoch, current_epoch
+            ))));
+        }
         let lookup_info = self.get_lookup_info(uname.clone(), current_epoch).await?;
 
         let root_hash = EpochHash(current_epoch, self.get_root_hash(&current_azks).await?);
 
+        let lookup_proof = self
+            .lookup_with_info(uname, &current_azks, current_epoch, lookup_info, true)
+            .await?;
+        let consistency_proof = current_azks
+            .get_append_only_proof::<_>(&self.storage, pinned_epoch, current_epoch)
+            .await?;
+        self.emit(DirectoryEvent::LookupServed(root_hash.clone()));
+        Ok((
+            LookupWithConsistencyProof {
+                lookup_proof,
+                consistency_proof,
+            },
+            root_hash,
+        ))
+    }
+
+    /// Provides proof of the version of a label in effect at a past epoch, verified
+    /// against the root hash archived for the epoch (see [Directory::get_root_hash_at_epoch])
+    /// with [crate::client::lookup_verify_at_epoch]. The proof can only be generated while
+    /// the states of the tree at the epoch are retained, which is in general the case for
+    /// the two most recent epochs, and fails with [DirectoryError::InvalidEpoch] otherwise.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(label = %crate::logging::label_prefix(&uname.0), epoch))
+    )]
+    pub async fn lookup_at(
+        &self,
+        uname: AkdLabel,
+        epoch: u64,
+    ) -> Result<(LookupProof, EpochHash), AkdError> {
+        // The guard will be dropped at the end of the proof generation
+        let _guard = self.cache_lock.read().await;
+
+        let current_azks = self.retrieve_current_azks().await?;
+        let root_hash = EpochHash(epoch, self.archived_root_hash(&current_azks, epoch).await?);
+        let lookup_info = self.get_lookup_info(uname.clone(), epoch).await?;
         let proof = self
-            .lookup_with_info(uname, &current_azks, current_epoch, lookup_info)
+            .lookup_with_info(uname, &current_azks, epoch, lookup_info, true)
             .await?;
+        self.emit(DirectoryEvent::LookupServed(root_hash.clone()));
         Ok((proof, root_hash))
     }
 
@@ -275,8 +1018,9 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         &self,
         uname: AkdLabel,
         current_azks: &Azks,
-        current_epoch: u64,
+        epoch: u64,
         lookup_info: LookupInfo,
+        with_vrf_proofs: bool,
     ) -> Result<LookupProof, AkdError> {
         // Preload nodes needed for lookup.
         current_azks
@@ -286,44 +1030,56 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         let current_version = lookup_info.value_state.version;
         let commitment_key = self.derive_commitment_key().await?;
         let plaintext_value = lookup_info.value_state.plaintext_val;
-        let existence_vrf = self
-            .vrf
-            .get_label_proof(&uname, VersionFreshness::Fresh, current_version)
-            .await?;
-        let commitment_label = self.vrf.get_node_label_from_vrf_proof(existence_vrf).await;
+        // the VRF proofs are left out when they are batched by the caller
+        let (existence_vrf_proof, marker_vrf_proof, freshness_vrf_proof) = if with_vrf_proofs {
+            (
+                self.label_vrf()
+                    .get_label_proof(&uname, VersionFreshness::Fresh, current_version)
+                    .await?
+                    .to_bytes()
+                    .to_vec(),
+                self.label_vrf()
+                    .get_label_proof(&uname, VersionFreshness::Fresh, lookup_info.marker_version)
+                    .await?
+                    .to_bytes()
+                    .to_vec(),
+                self.label_vrf()
+                    .get_label_proof(&uname, VersionFreshness::Stale, current_version)
+                    .await?
+                    .to_bytes()
+                    .to_vec(),
+            )
+        } else {
+            (vec![], vec![], vec![])
+        };
+        let commitment_label = lookup_info.existent_label;
         let lookup_proof = LookupProof {
             epoch: lookup_info.value_state.epoch,
             plaintext_value: plaintext_value.clone(),
             version: lookup_info.value_state.version,
-            existence_vrf_proof: existence_vrf.to_bytes().to_vec(),
+            existence_vrf_proof,
             existence_proof: current_azks
-                .get_membership_proof(&self.storage, lookup_info.existent_label, current_epoch)
+                .get_membership_proof_at_epoch(&self.storage, lookup_info.existent_label, epoch)
                 .await?,
-            marker_vrf_proof: self
-                .vrf
-                .get_label_proof(&uname, VersionFreshness::Fresh, lookup_info.marker_version)
-                .await?
-                .to_bytes()
-                .to_vec(),
+            marker_vrf_proof,
             marker_proof: current_azks
-                .get_membership_proof(&self.storage, lookup_info.marker_label, current_epoch)
+                .get_membership_proof_at_epoch(&self.storage, lookup_info.marker_label, epoch)
                 .await?,
-            freshness_vrf_proof: self
-                .vrf
-                .get_label_proof(&uname, VersionFreshness::Stale, current_version)
-                .await?
-                .to_bytes()
-                .to_vec(),
+            freshness_vrf_proof,
             freshness_proof: current_azks
-                .get_non_membership_proof(&self.storage, lookup_info.non_existent_label)
+                .get_non_membership_proof_at_epoch(
+                    &self.storage,
+                    lookup_info.non_existent_label,
+                    epoch,
+                )
                 .await?,
-            commitment_proof: get_commitment_nonce(
+            commitment_proof: self.commitment.get_nonce(
                 &commitment_key,
                 &commitment_label,
                 lookup_info.value_state.version,
                 &plaintext_value,
-            )
-            .to_vec(),
+            ),
+            expiry_epoch: lookup_info.value_state.expiry_epoch,
         };
 
         Ok(lookup_proof)
@@ -331,6 +1087,10 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
 
     // TODO(eoz): Call proof generations async
     /// Allows efficient batch lookups by preloading necessary nodes for the lookups.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(num_labels = unames.len()))
+    )]
     pub async fn batch_lookup(
         &self,
         unames: &[AkdLabel],
@@ -338,6 +1098,56 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         // The guard will be dropped at the end of the proof generation
         let _guard = self.cache_lock.read().await;
 
+        let (lookup_proofs, root_hash) = self.batch_lookup_with_infos(unames, true).await?;
+        Ok((lookup_proofs, root_hash))
+    }
+
+    /// Batch lookups as in [Directory::batch_lookup], where the VRF proofs of all the
+    /// lookups are replaced by a single batch VRF proof, which is much smaller and
+    /// faster to verify, see [crate::client::batch_lookup_verify].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(num_labels = unames.len()))
+    )]
+    pub async fn batch_lookup_compact(
+        &self,
+        unames: &[AkdLabel],
+    ) -> Result<(BatchLookupProof, EpochHash), AkdError> {
+        // The guard will be dropped at the end of the proof generation
+        let _guard = self.cache_lock.read().await;
+
+        let (lookup_proofs, root_hash) = self.batch_lookup_with_infos(unames, false).await?;
+        let inputs = unames
+            .iter()
+            .zip(lookup_proofs.iter())
+            .flat_map(|(uname, proof)| {
+                let marker_version = 1 << get_marker_version(proof.version);
+                [
+                    (uname.clone(), VersionFreshness::Fresh, proof.version),
+                    (uname.clone(), VersionFreshness::Fresh, marker_version),
+                    (uname.clone(), VersionFreshness::Stale, proof.version),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let vrf_proof = self
+            .label_vrf()
+            .get_label_batch_proof(&inputs)
+            .await?
+            .to_bytes();
+        Ok((
+            BatchLookupProof {
+                lookup_proofs,
+                vrf_proof,
+            },
+            root_hash,
+        ))
+    }
+
+    async fn batch_lookup_with_infos(
+        &self,
+        unames: &[AkdLabel],
+        with_vrf_proofs: bool,
+    ) -> Result<(Vec<LookupProof>, EpochHash), AkdError> {
         let current_azks = self.retrieve_current_azks().await?;
         let current_epoch = current_azks.get_latest_epoch();
 
@@ -367,21 +1177,24 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                     &current_azks,
                     current_epoch,
                     lookup_infos[i].clone(),
+                    with_vrf_proofs,
                 )
                 .await?,
             );
+            self.emit(DirectoryEvent::LookupServed(root_hash.clone()));
         }
 
         Ok((lookup_proofs, root_hash))
     }
 
     async fn get_lookup_info(&self, uname: AkdLabel, epoch: u64) -> Result<LookupInfo, AkdError> {
+        self.check_label_length(&uname)?;
         match self
             .storage
             .get_user_state(&uname, ValueStateRetrievalFlag::LeqEpoch(epoch))
             .await
         {
-            Err(_) => {
+            Err(StorageError::NotFound(_)) => {
                 // Need to throw an error
                 match std::str::from_utf8(&uname) {
                     Ok(name) => Err(AkdError::Storage(StorageError::NotFound(format!(
@@ -400,15 +1213,15 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                 let version = latest_st.version;
                 let marker_version = 1 << get_marker_version(version);
                 let existent_label = self
-                    .vrf
+                    .label_vrf()
                     .get_node_label(&uname, VersionFreshness::Fresh, version)
                     .await?;
                 let marker_label = self
-                    .vrf
+                    .label_vrf()
                     .get_node_label(&uname, VersionFreshness::Fresh, marker_version)
                     .await?;
                 let non_existent_label = self
-                    .vrf
+                    .label_vrf()
                     .get_node_label(&uname, VersionFreshness::Stale, version)
                     .await?;
                 Ok(LookupInfo {
@@ -419,6 +1232,7 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                     non_existent_label,
                 })
             }
+            Err(err) => Err(AkdError::Storage(err)),
         }
     }
 
@@ -432,11 +1246,49 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         uname: &AkdLabel,
         params: HistoryParams,
     ) -> Result<(HistoryProof, EpochHash), AkdError> {
+        self.key_history_with_disclosure(uname, params, ValueDisclosure::All)
+            .await
+    }
+
+    /// Like [Directory::key_history], but fails with [DirectoryError::DeadlineExceeded]
+    /// if the proof isn't generated by the deadline, see [Directory::lookup_with_deadline]
+    #[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+    pub async fn key_history_with_deadline(
+        &self,
+        uname: &AkdLabel,
+        params: HistoryParams,
+        deadline: Instant,
+    ) -> Result<(HistoryProof, EpochHash), AkdError> {
+        self.with_deadline("key history", deadline, |directory| async move {
+            directory.key_history(uname, params).await
+        })
+        .await
+    }
+
+    /// Returns the history of a label as in [Directory::key_history], but only opening
+    /// the values selected by `disclosure`. The other values are redacted: they remain
+    /// committed to by their existence proofs, but neither the plaintext values nor
+    /// their commitment proofs are disclosed (see [UpdateProof::is_redacted]). The
+    /// verifier states which values must be opened with
+    /// [crate::client::HistoryVerificationPolicy::require_opened].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "key_history", level = "info", skip_all, fields(label = %crate::logging::label_prefix(&uname.0), epoch = tracing::field::Empty))
+    )]
+    pub async fn key_history_with_disclosure(
+        &self,
+        uname: &AkdLabel,
+        params: HistoryParams,
+        disclosure: ValueDisclosure,
+    ) -> Result<(HistoryProof, EpochHash), AkdError> {
+        self.check_label_length(uname)?;
         // The guard will be dropped at the end of the proof generation
         let _guard = self.cache_lock.read().await;
 
         let current_azks = self.retrieve_current_azks().await?;
         let current_epoch = current_azks.get_latest_epoch();
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("epoch", current_epoch);
         let mut user_data = self.storage.get_user_data(uname).await?.states;
 
         // reverse sort from highest epoch to lowest
@@ -471,7 +1323,10 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         for user_state in user_data {
             // Ignore states in storage that are ahead of current directory epoch
             if user_state.epoch <= current_epoch {
-                let proof = self.create_single_update_proof(uname, &user_state).await?;
+                let redact = !disclosure.is_opened(update_proofs.len(), user_state.epoch);
+                let proof = self
+                    .create_single_update_proof(uname, &user_state, redact)
+                    .await?;
                 update_proofs.push(proof);
                 last_version = if user_state.version > last_version {
                     user_state.version
@@ -480,15 +1335,15 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                 };
             }
         }
-        let next_marker = get_marker_version(last_version) + 1;
-        let final_marker = get_marker_version(current_epoch);
+        let future_markers = get_future_marker_powers(last_version, current_epoch);
+        let next_marker = *future_markers.start();
 
         let mut next_few_vrf_proofs = Vec::<Vec<u8>>::new();
         let mut non_existence_of_next_few = Vec::<NonMembershipProof>::new();
 
         for ver in last_version + 1..(1 << next_marker) {
             let label_for_ver = self
-                .vrf
+                .label_vrf()
                 .get_node_label(uname, VersionFreshness::Fresh, ver)
                 .await?;
             let non_existence_of_ver = current_azks
@@ -496,7 +1351,7 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                 .await?;
             non_existence_of_next_few.push(non_existence_of_ver);
             next_few_vrf_proofs.push(
-                self.vrf
+                self.label_vrf()
                     .get_label_proof(uname, VersionFreshness::Fresh, ver)
                     .await?
                     .to_bytes()
@@ -507,10 +1362,10 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         let mut future_marker_vrf_proofs = Vec::<Vec<u8>>::new();
         let mut non_existence_of_future_markers = Vec::<NonMembershipProof>::new();
 
-        for marker_power in next_marker..final_marker + 1 {
+        for marker_power in future_markers {
             let ver = 1 << marker_power;
             let label_for_ver = self
-                .vrf
+                .label_vrf()
                 .get_node_label(uname, VersionFreshness::Fresh, ver)
                 .await?;
             let non_existence_of_ver = current_azks
@@ -518,7 +1373,7 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                 .await?;
             non_existence_of_future_markers.push(non_existence_of_ver);
             future_marker_vrf_proofs.push(
-                self.vrf
+                self.label_vrf()
                     .get_label_proof(uname, VersionFreshness::Fresh, ver)
                     .await?
                     .to_bytes()
@@ -540,6 +1395,89 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         ))
     }
 
+    /// Looks up a label at each of the given epochs. Returns a [HistoryProof] of the
+    /// updates since the one in effect at the earliest of the epochs, which proves
+    /// whether the value changed across the epochs and is verified with
+    /// [crate::client::lookup_at_epochs_verify]. This is cheaper than a complete
+    /// [Directory::key_history] for a label with a long history.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(label = %crate::logging::label_prefix(&uname.0), num_epochs = epochs.len()))
+    )]
+    pub async fn lookup_at_epochs(
+        &self,
+        uname: &AkdLabel,
+        epochs: &[u64],
+    ) -> Result<(HistoryProof, EpochHash), AkdError> {
+        let earliest = match (epochs.iter().min(), epochs.iter().max()) {
+            (Some(&earliest), Some(&latest)) => {
+                self.check_epoch_not_in_future(latest).await?;
+                earliest
+            }
+            _ => {
+                return Err(AkdError::Directory(DirectoryError::InvalidEpoch(
+                    "No epochs to look up were given".to_string(),
+                )))
+            }
+        };
+
+        // the history starts at the update in effect at the earliest epoch, if any
+        let since_epoch = self
+            .storage
+            .get_user_data(uname)
+            .await?
+            .states
+            .iter()
+            .map(|state| state.epoch)
+            .filter(|epoch| *epoch <= earliest)
+            .max()
+            .unwrap_or(earliest);
+        self.key_history(uname, HistoryParams::SinceEpoch(since_epoch))
+            .await
+    }
+
+    /// Proves how many times a label was updated in the epochs from `start_epoch` to
+    /// `end_epoch` (inclusive), without disclosing its values. Returns a [HistoryProof]
+    /// of the updates since the one in effect before `start_epoch`, with all the values
+    /// redacted, which is verified with [crate::client::update_count_verify].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(label = %crate::logging::label_prefix(&uname.0), start_epoch, end_epoch))
+    )]
+    pub async fn update_count(
+        &self,
+        uname: &AkdLabel,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> Result<(HistoryProof, EpochHash), AkdError> {
+        if start_epoch > end_epoch {
+            return Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+                "Start epoch {} is after the end epoch {}",
+                start_epoch, end_epoch
+            ))));
+        }
+        self.check_epoch_not_in_future(end_epoch).await?;
+
+        // the history starts at the update in effect before the start epoch, if any,
+        // which proves that no update in the range was left out
+        let since_epoch = self
+            .storage
+            .get_user_data(uname)
+            .await?
+            .states
+            .iter()
+            .map(|state| state.epoch)
+            .filter(|epoch| *epoch < start_epoch)
+            .max()
+            .unwrap_or(start_epoch);
+        self.key_history_with_disclosure(
+            uname,
+            HistoryParams::SinceEpoch(since_epoch),
+            ValueDisclosure::None,
+        )
+        .await
+    }
+
     /// Poll for changes in the epoch number of the AZKS struct
     /// stored in the storage layer. If an epoch change is detected,
     /// the object cache (if present) is flushed immediately so
@@ -550,9 +1488,12 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
     /// NOTE: Due to the use of std::thread::sleep(.) this will BLOCK
     /// the polling thread, and should be allocated it's own thread since it won't
     /// yield
+    ///
+    /// Requires the `tokio_runtime` or `async_std_runtime` feature.
+    #[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
     pub async fn poll_for_azks_changes(
         &self,
-        period: tokio::time::Duration,
+        period: std::time::Duration,
         change_detected: Option<tokio::sync::mpsc::Sender<()>>,
     ) -> Result<(), AkdError> {
         // Retrieve the same AZKS that all the other calls see (i.e. the version that could be cached
@@ -561,7 +1502,7 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
 
         loop {
             // loop forever polling for changes
-            tokio::time::sleep(period).await;
+            crate::runtime::sleep(period).await;
 
             let latest = Directory::<S, V>::get_azks_from_storage(&self.storage, true).await?;
             if latest.latest_epoch > last.latest_epoch {
@@ -574,53 +1515,270 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                     // re-fetch the azks to load it into cache so when we release the cache lock
                     // others will see the new AZKS loaded up and ready
                     last = Directory::<S, V>::get_azks_from_storage(&self.storage, false).await?;
+                    self.epoch_hash.send_replace(EpochHash(
+                        last.get_latest_epoch(),
+                        last.get_root_hash(&self.storage).await?,
+                    ));
+
+                    // notify change occurred
+                    if let Some(channel) = &change_detected {
+                        channel.send(()).await.map_err(|send_err| {
+                            AkdError::Storage(StorageError::Connection(format!(
+                                "Tokio MPSC sender failed to publish notification with error {:?}",
+                                send_err
+                            )))
+                        })?;
+                    }
+                    // drop the guard
+                }
+            }
+        }
+
+        #[allow(unreachable_code)]
+        Ok(())
+    }
+
+    /// Returns an AppendOnlyProof for the leaves inserted into the underlying tree between
+    /// the epochs audit_start_ep and audit_end_ep.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(start_epoch = audit_start_ep, end_epoch = audit_end_ep))
+    )]
+    pub async fn audit(
+        &self,
+        audit_start_ep: u64,
+        audit_end_ep: u64,
+    ) -> Result<AppendOnlyProof, AkdError> {
+        // The guard will be dropped at the end of the proof generation
+        let _guard = self.cache_lock.read().await;
+
+        let current_azks = self
+            .retrieve_azks_for_audit(audit_start_ep, audit_end_ep)
+            .await?;
+        current_azks
+            .get_append_only_proof::<_>(&self.storage, audit_start_ep, audit_end_ep)
+            .await
+    }
+
+    /// Like [Directory::audit], but fails with [DirectoryError::DeadlineExceeded] if
+    /// the proof isn't generated by the deadline, see [Directory::lookup_with_deadline]
+    #[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+    pub async fn audit_with_deadline(
+        &self,
+        audit_start_ep: u64,
+        audit_end_ep: u64,
+        deadline: Instant,
+    ) -> Result<AppendOnlyProof, AkdError> {
+        self.with_deadline("audit", deadline, |directory| async move {
+            directory.audit(audit_start_ep, audit_end_ep).await
+        })
+        .await
+    }
+
+    /// Writes the audit proof between the given epochs to a sink as it is generated,
+    /// holding at most (approximately) `max_memory_bytes` of nodes in memory, rather
+    /// than the whole proof as [Directory::audit] does. The proof is written as a
+    /// sequence of frames, each holding part of the proof of an epoch, which can be
+    /// read back with [crate::auditor::read_append_only_proof].
+    #[cfg(feature = "protobuf")]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(start_epoch = audit_start_ep, end_epoch = audit_end_ep, max_memory_bytes))
+    )]
+    pub async fn audit_to_writer<W: tokio::io::AsyncWrite + Unpin>(
+        &self,
+        audit_start_ep: u64,
+        audit_end_ep: u64,
+        max_memory_bytes: usize,
+        writer: &mut W,
+    ) -> Result<(), AkdError> {
+        // The guard will be dropped at the end of the proof generation
+        let _guard = self.cache_lock.read().await;
+
+        let current_azks = self
+            .retrieve_azks_for_audit(audit_start_ep, audit_end_ep)
+            .await?;
+        current_azks
+            .write_append_only_proof::<_, _>(
+                &self.storage,
+                audit_start_ep,
+                audit_end_ep,
+                max_memory_bytes,
+                writer,
+            )
+            .await
+    }
+
+    /// Estimates the size (as given by [akd_core::SizeOf]) of the proof a
+    /// [Directory::lookup] of the label would return, from the size of the tree
+    /// rather than by generating the proof, so that serving layers can size the
+    /// response or apply size limits up front. The paths of the proof are assumed to
+    /// have the expected depth of a leaf in a tree of the current size, so the size
+    /// of the actual proof varies slightly around the estimate.
+    pub async fn estimate_lookup_proof_size(&self, uname: AkdLabel) -> Result<usize, AkdError> {
+        // The guard will be dropped at the end of the estimation
+        let _guard = self.cache_lock.read().await;
 
-                    // notify change occurred
-                    if let Some(channel) = &change_detected {
-                        channel.send(()).await.map_err(|send_err| {
-                            AkdError::Storage(StorageError::Connection(format!(
-                                "Tokio MPSC sender failed to publish notification with error {:?}",
-                                send_err
-                            )))
-                        })?;
-                    }
-                    // drop the guard
-                }
-            }
-        }
+        let current_azks = self.retrieve_current_azks().await?;
+        let current_epoch = current_azks.get_latest_epoch();
+        let lookup_info = self.get_lookup_info(uname, current_epoch).await?;
+        let commitment_key = self.derive_commitment_key().await?;
+        let commitment_proof = self.commitment.get_nonce(
+            &commitment_key,
+            &lookup_info.existent_label,
+            lookup_info.value_state.version,
+            &lookup_info.value_state.plaintext_val,
+        );
 
-        #[allow(unreachable_code)]
-        Ok(())
+        let depth = estimated_leaf_depth(current_azks.num_nodes);
+        let estimate = LookupProof {
+            epoch: lookup_info.value_state.epoch,
+            plaintext_value: lookup_info.value_state.plaintext_val,
+            version: lookup_info.value_state.version,
+            existence_vrf_proof: vec![0u8; crate::ecvrf::PROOF_LENGTH],
+            existence_proof: sample_membership_proof(depth),
+            marker_vrf_proof: vec![0u8; crate::ecvrf::PROOF_LENGTH],
+            marker_proof: sample_membership_proof(depth),
+            freshness_vrf_proof: vec![0u8; crate::ecvrf::PROOF_LENGTH],
+            freshness_proof: NonMembershipProof {
+                label: NodeLabel::root(),
+                longest_prefix: NodeLabel::root(),
+                longest_prefix_children: [sample_node(), sample_node()],
+                longest_prefix_membership_proof: sample_membership_proof(depth - 1),
+            },
+            commitment_proof,
+            expiry_epoch: lookup_info.value_state.expiry_epoch,
+        };
+        Ok(estimate.size_of())
     }
 
-    /// Returns an AppendOnlyProof for the leaves inserted into the underlying tree between
-    /// the epochs audit_start_ep and audit_end_ep.
-    pub async fn audit(
+    /// Estimates the size of the proof a [Directory::audit] between the given epochs
+    /// would return, from the number of updates published at each audited epoch
+    /// rather than by generating the proof, so that serving layers can apply size
+    /// limits or route large audits to a batch pipeline. Each update is assumed to
+    /// insert a new leaf along with the stale leaf of its previous version, and to
+    /// require one unchanged node per level of the tree, so the estimate is generally
+    /// an upper bound. Epochs with no archived [RootHashRecord] are estimated as empty.
+    pub async fn estimate_audit_proof_size(
         &self,
         audit_start_ep: u64,
         audit_end_ep: u64,
-    ) -> Result<AppendOnlyProof, AkdError> {
+    ) -> Result<usize, AkdError> {
+        let current_azks = self.retrieve_current_azks().await?;
+        check_audit_epochs(
+            current_azks.get_latest_epoch(),
+            audit_start_ep,
+            audit_end_ep,
+        )?;
+
+        let depth = estimated_leaf_depth(current_azks.num_nodes) as u64;
+        let node_size = sample_node().size_of() as u64;
+        let epoch_size = std::mem::size_of::<u64>() as u64;
+        let estimate = self
+            .root_hash_history(audit_start_ep + 1..=audit_end_ep)
+            .await?
+            .iter()
+            .map(|record| {
+                let inserted = record.batch_size.saturating_mul(2);
+                let unchanged = inserted.saturating_mul(depth).min(current_azks.num_nodes);
+                inserted.saturating_add(unchanged).saturating_mul(node_size)
+            })
+            .fold(
+                (audit_end_ep - audit_start_ep).saturating_mul(epoch_size),
+                u64::saturating_add,
+            );
+        Ok(usize::try_from(estimate).unwrap_or(usize::MAX))
+    }
+
+    /// Retrieves the current azks, checking that an audit can be generated between
+    /// the given epochs
+    async fn retrieve_azks_for_audit(
+        &self,
+        audit_start_ep: u64,
+        audit_end_ep: u64,
+    ) -> Result<Azks, AkdError> {
+        let current_azks = self.retrieve_current_azks().await?;
+        check_audit_epochs(
+            current_azks.get_latest_epoch(),
+            audit_start_ep,
+            audit_end_ep,
+        )?;
+        self.emit(DirectoryEvent::AuditRequested {
+            start_epoch: audit_start_ep,
+            end_epoch: audit_end_ep,
+        });
+        Ok(current_azks)
+    }
+
+    /// Generates an audit proof between the given epochs split into segments, by the
+    /// first `prefix_bits` bits of the node labels, so that it can be verified in
+    /// parallel. See [crate::auditor::audit_verify_segmented].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(start_epoch = audit_start_ep, end_epoch = audit_end_ep, prefix_bits))
+    )]
+    pub async fn audit_segmented(
+        &self,
+        audit_start_ep: u64,
+        audit_end_ep: u64,
+        prefix_bits: u32,
+    ) -> Result<SegmentedAppendOnlyProof, AkdError> {
+        let proof = self.audit(audit_start_ep, audit_end_ep).await?;
+        crate::auditor::segment_append_only_proof(proof, prefix_bits).await
+    }
+
+    /// Samples `k` leaves of the tree at the given epoch, deterministically from a
+    /// public seed, and returns a membership proof for each of them. Third parties
+    /// can verify the sample against the root hash of the epoch with
+    /// [crate::verify::verify_sample_audit], as a cheap probabilistic audit.
+    ///
+    /// Only the latest epoch can be sampled, since the tree nodes only retain their
+    /// latest state for proof generation.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(epoch, num_samples = k))
+    )]
+    pub async fn sample_audit(
+        &self,
+        epoch: u64,
+        seed: &[u8],
+        k: usize,
+    ) -> Result<SampleAuditProof, AkdError> {
         // The guard will be dropped at the end of the proof generation
         let _guard = self.cache_lock.read().await;
 
         let current_azks = self.retrieve_current_azks().await?;
         let current_epoch = current_azks.get_latest_epoch();
+        if epoch != current_epoch {
+            return Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+                "Only the latest epoch {} can be sampled, not epoch {}",
+                current_epoch, epoch
+            ))));
+        }
 
-        if audit_start_ep >= audit_end_ep {
-            Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
-                "Start epoch {} is greater than or equal the end epoch {}",
-                audit_start_ep, audit_end_ep
-            ))))
-        } else if current_epoch < audit_end_ep {
-            Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
-                "End epoch {} is greater than the current epoch {}",
-                audit_end_ep, current_epoch
-            ))))
-        } else {
-            current_azks
-                .get_append_only_proof::<_>(&self.storage, audit_start_ep, audit_end_ep)
-                .await
+        let mut samples = Vec::with_capacity(k);
+        for index in 0..k {
+            let target = SampleAuditProof::sample_target(seed, epoch, index as u64);
+            let leaf = current_azks
+                .get_sample_leaf(&self.storage, target)
+                .await?
+                .ok_or_else(|| {
+                    AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+                        "The tree has no leaves to sample at epoch {}",
+                        epoch
+                    )))
+                })?;
+            samples.push(
+                current_azks
+                    .get_membership_proof(&self.storage, leaf, epoch)
+                    .await?,
+            );
         }
+        Ok(SampleAuditProof {
+            epoch,
+            seed: seed.to_vec(),
+            samples,
+        })
     }
 
     /// Retrieves the current azks
@@ -643,15 +1801,146 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         };
         match got {
             DbRecord::Azks(azks) => Ok(azks),
-            _ => {
-                error!("No AZKS can be found. You should re-initialize the directory to create a new one");
-                Err(AkdError::Storage(StorageError::NotFound(
-                    "AZKS not found".to_string(),
-                )))
+            other => {
+                error!("The AZKS record is of the wrong type. You should re-initialize the directory to create a new one");
+                Err(AkdError::Storage(StorageError::TypeMismatch(format!(
+                    "Expected Azks, got {:?}",
+                    other.data_type()
+                ))))
+            }
+        }
+    }
+
+    /// Retrieves the [SignedTreeHead] produced when the given epoch was published.
+    /// Epochs published before tree heads were introduced have none.
+    pub async fn get_tree_head(&self, epoch: u64) -> Result<SignedTreeHead, AkdError> {
+        let latest_epoch = self.retrieve_current_azks().await?.get_latest_epoch();
+        if epoch == 0 || epoch > latest_epoch {
+            return Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+                "No tree head exists for epoch {} (latest epoch is {})",
+                epoch, latest_epoch
+            ))));
+        }
+        match self.storage.get::<SignedTreeHead>(&epoch).await? {
+            DbRecord::TreeHead(tree_head) => Ok(tree_head),
+            other => Err(AkdError::Storage(StorageError::TypeMismatch(format!(
+                "Expected tree head for epoch {}, got {:?}",
+                epoch,
+                other.data_type()
+            )))),
+        }
+    }
+
+    /// Retrieves the [SignedTreeHead] of the latest epoch
+    pub async fn get_latest_tree_head(&self) -> Result<SignedTreeHead, AkdError> {
+        let latest_epoch = self.retrieve_current_azks().await?.get_latest_epoch();
+        self.get_tree_head(latest_epoch).await
+    }
+
+    /// Retrieves the root hash of the tree at the given epoch from the archived
+    /// [RootHashRecord] of the epoch. Epochs with no archived record (the empty
+    /// epoch 0, and those published before root hashes were archived) fall back
+    /// to reconstructing the root hash from the tree, which is only possible for
+    /// the two most recent epochs.
+    pub async fn get_root_hash_at_epoch(&self, epoch: u64) -> Result<Digest, AkdError> {
+        // The guard will be dropped at the end of the retrieval
+        let _guard = self.cache_lock.read().await;
+        let current_azks = self.retrieve_current_azks().await?;
+        self.archived_root_hash(&current_azks, epoch).await
+    }
+
+    async fn archived_root_hash(
+        &self,
+        current_azks: &Azks,
+        epoch: u64,
+    ) -> Result<Digest, AkdError> {
+        let latest_epoch = current_azks.get_latest_epoch();
+        if epoch > latest_epoch {
+            return Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+                "Cannot retrieve the root hash of epoch {} (latest epoch is {})",
+                epoch, latest_epoch
+            ))));
+        }
+        match self.storage.get::<RootHashRecord>(&epoch).await {
+            Ok(DbRecord::RootHash(record)) => Ok(record.root_hash),
+            Ok(other) => Err(AkdError::Storage(StorageError::TypeMismatch(format!(
+                "Expected root hash for epoch {}, got {:?}",
+                epoch,
+                other.data_type()
+            )))),
+            Err(StorageError::NotFound(_)) => {
+                current_azks
+                    .get_root_hash_safe::<_>(&self.storage, epoch)
+                    .await
             }
+            Err(err) => Err(AkdError::Storage(err)),
         }
     }
 
+    /// Retrieves the metadata attached to the given epoch when it was published
+    /// (see [PublishOptions::with_metadata]). Epochs with no archived
+    /// [RootHashRecord] have no metadata.
+    pub async fn get_epoch_metadata(
+        &self,
+        epoch: u64,
+    ) -> Result<BTreeMap<String, String>, AkdError> {
+        let latest_epoch = self.retrieve_current_azks().await?.get_latest_epoch();
+        if epoch > latest_epoch {
+            return Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+                "Cannot retrieve the metadata of epoch {} (latest epoch is {})",
+                epoch, latest_epoch
+            ))));
+        }
+        match self.storage.get::<RootHashRecord>(&epoch).await {
+            Ok(DbRecord::RootHash(record)) => Ok(record.metadata),
+            Ok(other) => Err(AkdError::Storage(StorageError::TypeMismatch(format!(
+                "Expected root hash for epoch {}, got {:?}",
+                epoch,
+                other.data_type()
+            )))),
+            Err(StorageError::NotFound(_)) => Ok(BTreeMap::new()),
+            Err(err) => Err(AkdError::Storage(err)),
+        }
+    }
+
+    /// Retrieves the archived [RootHashRecord]s of the epochs in the given range,
+    /// in increasing epoch order. The range is capped at the latest epoch, and
+    /// epochs with no archived record are skipped.
+    pub async fn root_hash_history<R: RangeBounds<u64>>(
+        &self,
+        range: R,
+    ) -> Result<Vec<RootHashRecord>, AkdError> {
+        let latest_epoch = self.retrieve_current_azks().await?.get_latest_epoch();
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => (*end).min(latest_epoch),
+            Bound::Excluded(0) => return Ok(vec![]),
+            Bound::Excluded(end) => (*end - 1).min(latest_epoch),
+            Bound::Unbounded => latest_epoch,
+        };
+        if start > end {
+            return Ok(vec![]);
+        }
+
+        let epochs = (start..=end).collect::<Vec<_>>();
+        let mut records = self
+            .storage
+            .batch_get::<RootHashRecord>(&epochs)
+            .await?
+            .into_iter()
+            .filter_map(|record| match record {
+                DbRecord::RootHash(record) => Some(record),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        records.sort_by_key(|record| record.epoch);
+        Ok(records)
+    }
+
     /// HELPERS ///
 
     /// Use this function to retrieve the VRF public key for this AKD.
@@ -659,23 +1948,30 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         Ok(self.vrf.get_vrf_public_key().await?)
     }
 
+    /// Use this function to retrieve the public key verifying the [SignedTreeHead]s
+    /// of this AKD (see [crate::verify::verify_tree_head]).
+    pub async fn get_tree_head_public_key(&self) -> Result<ed25519_dalek::PublicKey, AkdError> {
+        Ok(self.derive_tree_head_keypair().await?.public)
+    }
+
     async fn create_single_update_proof(
         &self,
         uname: &AkdLabel,
         user_state: &ValueState,
+        redact: bool,
     ) -> Result<UpdateProof, AkdError> {
         let epoch = user_state.epoch;
         let plaintext_value = &user_state.plaintext_val;
         let version = user_state.version;
 
         let label_at_ep = self
-            .vrf
+            .label_vrf()
             .get_node_label(uname, VersionFreshness::Fresh, version)
             .await?;
 
         let current_azks = self.retrieve_current_azks().await?;
         let existence_vrf = self
-            .vrf
+            .label_vrf()
             .get_label_proof(uname, VersionFreshness::Fresh, version)
             .await?;
         let existence_vrf_proof = existence_vrf.to_bytes().to_vec();
@@ -687,7 +1983,7 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         let mut previous_version_vrf_proof = Option::None;
         if version > 1 {
             let prev_label_at_ep = self
-                .vrf
+                .label_vrf()
                 .get_node_label(uname, VersionFreshness::Stale, version - 1)
                 .await?;
             previous_version_stale_at_ep = Option::Some(
@@ -696,7 +1992,7 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                     .await?,
             );
             previous_version_vrf_proof = Option::Some(
-                self.vrf
+                self.label_vrf()
                     .get_label_proof(uname, VersionFreshness::Stale, version - 1)
                     .await?
                     .to_bytes()
@@ -704,20 +2000,37 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
             );
         }
 
-        let commitment_key = self.derive_commitment_key().await?;
-        let commitment_proof =
-            get_commitment_nonce(&commitment_key, &existence_label, version, plaintext_value)
-                .to_vec();
+        let (plaintext_value, commitment_proof, value_commitment) = if redact {
+            // the leaf of the version holds the commitment to its value
+            let leaf =
+                TreeNode::get_from_storage(&self.storage, &NodeKey(label_at_ep), epoch).await?;
+            (AkdValue(vec![]), vec![], Some(leaf.hash))
+        } else {
+            let commitment_key = self.derive_commitment_key().await?;
+            let commitment_proof = self.commitment.get_nonce(
+                &commitment_key,
+                &existence_label,
+                version,
+                plaintext_value,
+            );
+            (plaintext_value.clone(), commitment_proof, None)
+        };
 
         Ok(UpdateProof {
             epoch,
             version,
-            plaintext_value: plaintext_value.clone(),
+            plaintext_value,
             existence_vrf_proof,
             existence_at_ep,
             previous_version_vrf_proof,
             previous_version_stale_at_ep,
             commitment_proof,
+            value_commitment,
+            expiry_epoch: if redact {
+                None
+            } else {
+                user_state.expiry_epoch
+            },
         })
     }
 
@@ -741,6 +2054,86 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         current_azks.get_root_hash::<_>(&self.storage).await
     }
 
+    /// Walks the stored tree at the given epoch, recomputing the hashes of all the
+    /// interior nodes, checking the links between parents and children, and looking
+    /// for stored nodes which aren't reachable from the root. This is a diagnostic
+    /// for operators, which reads the whole tree from storage.
+    ///
+    /// Only the two most recent states of each node are retained, so the tree can
+    /// only be fully checked at the latest epoch, or the one before it.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(epoch))
+    )]
+    pub async fn check_integrity(&self, epoch: u64) -> Result<IntegrityReport, AkdError> {
+        // The guard will be dropped at the end of the check
+        let _guard = self.cache_lock.read().await;
+        let latest_epoch = self.check_epoch_not_in_future(epoch).await?;
+
+        let (mut report, reached) =
+            crate::integrity::check_tree(&self.storage, epoch, latest_epoch).await?;
+        crate::integrity::find_orphans(&self.storage, &mut report, &reached).await?;
+        if report.is_ok() {
+            info!(
+                "Checked {} tree nodes at epoch {}, no issues found",
+                report.nodes_checked, epoch
+            );
+        } else {
+            error!(
+                "Checked {} tree nodes at epoch {}, found {} issues",
+                report.nodes_checked,
+                epoch,
+                report.issues.len()
+            );
+        }
+        Ok(report)
+    }
+
+    /// Exports the nodes of the subtree rooted at the given label, as of the given
+    /// epoch, ordered by label. The nodes are read with a single prefix scan of the
+    /// storage layer, see [Database::iter_by_prefix].
+    pub async fn export_subtree(
+        &self,
+        root: NodeLabel,
+        epoch: u64,
+    ) -> Result<Vec<TreeNode>, AkdError> {
+        // The guard will be dropped at the end of the export
+        let _guard = self.cache_lock.read().await;
+        self.check_epoch_not_in_future(epoch).await?;
+
+        // the storage is scanned by whole bytes of the label, the remaining bits are filtered here
+        let key_prefix = &root.label_val[..(root.label_len as usize / 8).min(32)];
+        let mut records = self
+            .storage
+            .iter_by_prefix(StorageType::TreeNode, key_prefix);
+        let mut nodes = vec![];
+        while let Some(record) = records.next().await {
+            if let DbRecord::TreeNode(node) = record? {
+                if !root.is_prefix_of(&node.label) {
+                    continue;
+                }
+                if let Ok(node) = node.determine_node_to_get(epoch) {
+                    nodes.push(node);
+                }
+            }
+        }
+        nodes.sort_by_key(|node| node.label);
+        Ok(nodes)
+    }
+
+    /// Returns the latest epoch, or an error if the given epoch is after it
+    async fn check_epoch_not_in_future(&self, epoch: u64) -> Result<u64, AkdError> {
+        let current_azks = self.retrieve_current_azks().await?;
+        let latest_epoch = current_azks.get_latest_epoch();
+        if epoch > latest_epoch {
+            return Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+                "Epoch {} is after the latest epoch {}",
+                epoch, latest_epoch
+            ))));
+        }
+        Ok(latest_epoch)
+    }
+
     // FIXME (Issue #184): This should be derived properly. Instead of hashing the VRF private
     // key, we should derive this properly from a server secret.
     async fn derive_commitment_key(&self) -> Result<Digest, AkdError> {
@@ -748,6 +2141,110 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         let commitment_key = crate::hash::hash(&raw_key);
         Ok(commitment_key)
     }
+
+    // The tree head signing key is derived from the VRF private key (similarly to the
+    // commitment key), separated from the other uses of the key by a domain tag.
+    async fn derive_tree_head_keypair(&self) -> Result<ed25519_dalek::Keypair, AkdError> {
+        let raw_key = self.vrf.retrieve().await?;
+        let seed = crate::hash::hash(&[akd_core::TREE_HEAD_SIGNATURE_DOMAIN, &raw_key].concat());
+        let secret =
+            ed25519_dalek::SecretKey::from_bytes(&seed[..ed25519_dalek::SECRET_KEY_LENGTH])
+                .map_err(|err| crate::ecvrf::VrfError::SigningKey(err.to_string()))?;
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        Ok(ed25519_dalek::Keypair { secret, public })
+    }
+
+    /// Runs a directory operation until the deadline at the latest, on a handle to
+    /// the directory whose storage reads are bound by the deadline
+    #[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+    async fn with_deadline<T, F, Fut>(
+        &self,
+        operation: &str,
+        deadline: Instant,
+        f: F,
+    ) -> Result<T, AkdError>
+    where
+        F: FnOnce(Self) -> Fut,
+        Fut: std::future::Future<Output = Result<T, AkdError>>,
+    {
+        let exceeded = || {
+            Err(AkdError::Directory(DirectoryError::DeadlineExceeded(
+                operation.to_string(),
+            )))
+        };
+        if Instant::now() >= deadline {
+            return exceeded();
+        }
+        let mut directory = self.clone();
+        directory.storage = self.storage.with_deadline(deadline);
+        match crate::runtime::timeout_at(deadline, f(directory)).await {
+            None => exceeded(),
+            // a read failing on the deadline of the storage
+            Some(Err(AkdError::Storage(StorageError::Timeout(_))))
+                if Instant::now() >= deadline =>
+            {
+                exceeded()
+            }
+            Some(result) => result,
+        }
+    }
+
+    /// Runs the insertion of a publish, reporting the number of nodes written to
+    /// the transaction every [PUBLISH_PROGRESS_INTERVAL] until it completes
+    #[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+    async fn report_nodes_written<T, F: std::future::Future<Output = Result<T, AkdError>>>(
+        &self,
+        insertion: F,
+        progress: &watch::Sender<PublishProgress>,
+    ) -> Result<T, AkdError> {
+        let ticks = async {
+            loop {
+                crate::runtime::sleep(PUBLISH_PROGRESS_INTERVAL).await;
+                let nodes_written = self.storage.transaction_count() as u64;
+                progress.send_modify(|progress| progress.nodes_written = nodes_written);
+            }
+        };
+        futures_util::pin_mut!(insertion);
+        futures_util::pin_mut!(ticks);
+        match futures_util::future::select(insertion, ticks).await {
+            futures_util::future::Either::Left((result, _)) => result,
+            futures_util::future::Either::Right((never, _)) => never,
+        }
+    }
+
+    /// Runs the insertion of a publish. Without a runtime, the number of
+    /// nodes written is only reported once the insertion completes.
+    #[cfg(not(any(feature = "tokio_runtime", feature = "async_std_runtime")))]
+    async fn report_nodes_written<T, F: std::future::Future<Output = Result<T, AkdError>>>(
+        &self,
+        insertion: F,
+        _progress: &watch::Sender<PublishProgress>,
+    ) -> Result<T, AkdError> {
+        insertion.await
+    }
+
+    /// Signs the summary of the epoch being published, whose nodes must already
+    /// have been inserted in the tree (or the transaction)
+    async fn sign_new_tree_head(
+        &self,
+        azks: &Azks,
+        epoch: u64,
+    ) -> Result<SignedTreeHead, AkdError> {
+        let root_hash = azks.get_root_hash_safe::<_>(&self.storage, epoch).await?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let keypair = self.derive_tree_head_keypair().await?;
+        let message = SignedTreeHead::signing_message(epoch, &root_hash, timestamp);
+        let signature = ed25519_dalek::Signer::sign(&keypair, &message);
+        Ok(DbRecord::build_tree_head(
+            epoch,
+            root_hash,
+            timestamp,
+            signature.to_bytes().to_vec(),
+        ))
+    }
 }
 
 /// The parameters that dictate how much of the history proof to return to the consumer
@@ -769,8 +2266,330 @@ impl Default for HistoryParams {
     }
 }
 
+/// How [Directory::publish_with_options] handles a batch with several updates
+/// for the same label
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateLabelPolicy {
+    /// The batch is rejected with [DirectoryError::DuplicateLabel]
+    #[default]
+    Reject,
+    /// Only the last update for each label in the batch is published
+    LastWriteWins,
+}
+
+/// The options of [Directory::publish_with_options]
+#[derive(Debug, Clone, Default)]
+pub struct PublishOptions {
+    /// How several updates for the same label in a batch are handled
+    pub duplicate_labels: DuplicateLabelPolicy,
+    /// The number of epochs the values of some labels of the batch are valid for
+    pub ttls: HashMap<AkdLabel, u64>,
+    /// The metadata attached to the published epoch
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl PublishOptions {
+    /// Sets how several updates for the same label in a batch are handled
+    pub fn with_duplicate_labels(mut self, policy: DuplicateLabelPolicy) -> Self {
+        self.duplicate_labels = policy;
+        self
+    }
+
+    /// Sets the number of epochs the value published for a label is valid for. The
+    /// value expires `ttl` epochs after the epoch it's published at, and its expiry
+    /// epoch is committed to along with it (see [akd_core::utils::bind_expiry]), so
+    /// that clients verifying its proofs learn when it expires (see
+    /// [crate::VerifyResult::is_expired]). Values are valid indefinitely by default.
+    /// Republishing the current value of a label with a TTL publishes a new version
+    /// with the new expiry epoch, while republishing it without one is skipped as
+    /// usual, leaving the expiry epoch of the current version in place.
+    pub fn with_ttl(mut self, label: AkdLabel, ttl: u64) -> Self {
+        self.ttls.insert(label, ttl);
+        self
+    }
+
+    /// Attaches an operator-provided entry (e.g. a release tag, a change ticket or
+    /// the identity of the publisher) to the metadata of the published epoch. The
+    /// metadata is stored in the [RootHashRecord] of the epoch, and can be
+    /// retrieved with [Directory::get_epoch_metadata].
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// The outcome of a [Directory::preview_publish]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishPreview {
+    /// The epoch the publish would result in, which is the current epoch if none
+    /// of the updates changes a value
+    pub epoch: u64,
+    /// The root hash the publish would result in
+    pub root_hash: Digest,
+    /// The labels whose value would change, in sorted order
+    pub changed_labels: Vec<AkdLabel>,
+    /// The number of tree nodes the publish would write
+    pub estimated_node_writes: usize,
+}
+
+/// The phases of a [Directory::publish_with_progress], in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PublishPhase {
+    /// The publish has not started yet
+    #[default]
+    NotStarted,
+    /// The node labels of the updates are being computed with the VRF
+    ComputingLabels,
+    /// The new leaves are being inserted into the tree
+    InsertingLeaves,
+    /// The tree head of the new epoch is being signed
+    SigningTreeHead,
+    /// The new epoch is being committed to storage, and can no longer be cancelled
+    Committing,
+    /// The publish completed
+    Done,
+}
+
+/// The progress of a [Directory::publish_with_progress]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PublishProgress {
+    /// The phase the publish is in
+    pub phase: PublishPhase,
+    /// The number of leaves inserted into the tree, known once the leaves
+    /// have all been inserted
+    pub items_inserted: u64,
+    /// The number of records written to the storage transaction so far
+    pub nodes_written: u64,
+    /// The number of tree node hashes computed to insert the leaves, known once
+    /// the leaves have all been inserted
+    pub hashes_computed: u64,
+}
+
+/// The events a [Directory] emits to its subscribers, see [Directory::subscribe]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirectoryEvent {
+    /// A new epoch was published, with the resulting root hash
+    EpochPublished(EpochHash),
+    /// A lookup proof was served against the given epoch and root hash
+    LookupServed(EpochHash),
+    /// An audit proof was requested between two epochs
+    AuditRequested {
+        /// The epoch the audit starts at
+        start_epoch: u64,
+        /// The epoch the audit ends at
+        end_epoch: u64,
+    },
+}
+
 /// Helpers
 
+fn set_publish_phase(progress: &watch::Sender<PublishProgress>, phase: PublishPhase) {
+    progress.send_modify(|progress| progress.phase = phase);
+}
+
+fn check_publish_cancelled(cancellation: &CancellationToken, epoch: u64) -> Result<(), AkdError> {
+    if cancellation.is_cancelled() {
+        return Err(AkdError::Directory(DirectoryError::PublishCancelled(epoch)));
+    }
+    Ok(())
+}
+
+/// A transaction of the directory's storage, which is rolled back when dropped
+/// before being committed. The transaction is shared by all the clones of the
+/// storage, so an operation abandoned part way, e.g. as its future is dropped,
+/// would otherwise leave it active, and every later write would fail with
+/// [StorageError::TransactionInProgress].
+struct TransactionGuard<'a, S: Database> {
+    storage: &'a StorageManager<S>,
+    active: bool,
+}
+
+impl<'a, S: Database> TransactionGuard<'a, S> {
+    /// Begins a transaction, returning `None` if one is already active
+    fn begin(storage: &'a StorageManager<S>) -> Option<Self> {
+        storage.begin_transaction().then(|| Self {
+            storage,
+            active: true,
+        })
+    }
+
+    /// Commits the transaction if the stored azks is still at `expected_epoch`, see
+    /// [StorageManager::commit_transaction_if_epoch], and rolls it back otherwise
+    async fn commit_if_epoch(mut self, expected_epoch: u64) -> Result<(), StorageError> {
+        // the transaction is no longer active once the commit has started
+        self.active = false;
+        let committed = self
+            .storage
+            .commit_transaction_if_epoch(expected_epoch)
+            .await;
+        if committed.is_err() {
+            // ignore any rollback error(s)
+            let _ = self.storage.rollback_transaction();
+        }
+        committed
+    }
+
+    /// Rolls back the transaction
+    fn rollback(mut self) -> Result<(), StorageError> {
+        self.active = false;
+        self.storage.rollback_transaction()
+    }
+}
+
+impl<'a, S: Database> Drop for TransactionGuard<'a, S> {
+    fn drop(&mut self) {
+        if self.active {
+            // ignore any rollback error(s)
+            let _ = self.storage.rollback_transaction();
+        }
+    }
+}
+
+/// The VRF of a directory, evaluated on the labels mapped by its [LabelMapper]
+struct MappedVrf<'a, V> {
+    vrf: &'a V,
+    label_mapper: &'a dyn LabelMapper,
+}
+
+impl<'a, V: VRFKeyStorage> MappedVrf<'a, V> {
+    async fn get_node_label(
+        &self,
+        label: &AkdLabel,
+        freshness: VersionFreshness,
+        version: u64,
+    ) -> Result<NodeLabel, VrfError> {
+        let label = self.label_mapper.map_label(label);
+        self.vrf.get_node_label(&label, freshness, version).await
+    }
+
+    /// Computes the node labels of several labels, keyed by the labels as given
+    /// (rather than as mapped)
+    async fn get_node_labels(
+        &self,
+        labels: &[(AkdLabel, VersionFreshness, u64)],
+    ) -> Result<Vec<((AkdLabel, VersionFreshness, u64), NodeLabel)>, VrfError> {
+        let mut originals = HashMap::with_capacity(labels.len());
+        let mapped = labels
+            .iter()
+            .map(|(label, freshness, version)| {
+                let mapped = self.label_mapper.map_label(label);
+                originals.insert(mapped.clone(), label.clone());
+                (mapped, *freshness, *version)
+            })
+            .collect::<Vec<_>>();
+        Ok(self
+            .vrf
+            .get_node_labels(&mapped)
+            .await?
+            .into_iter()
+            .map(|((mapped, freshness, version), node_label)| {
+                ((originals[&mapped].clone(), freshness, version), node_label)
+            })
+            .collect())
+    }
+
+    async fn get_label_proof(
+        &self,
+        label: &AkdLabel,
+        freshness: VersionFreshness,
+        version: u64,
+    ) -> Result<Proof, VrfError> {
+        let label = self.label_mapper.map_label(label);
+        self.vrf.get_label_proof(&label, freshness, version).await
+    }
+
+    async fn get_label_batch_proof(
+        &self,
+        labels: &[(AkdLabel, VersionFreshness, u64)],
+    ) -> Result<BatchProof, VrfError> {
+        let mapped = labels
+            .iter()
+            .map(|(label, freshness, version)| {
+                (self.label_mapper.map_label(label), *freshness, *version)
+            })
+            .collect::<Vec<_>>();
+        self.vrf.get_label_batch_proof(&mapped).await
+    }
+}
+
+/// Applies the policy to the updates for the same label in a batch, keeping
+/// the position of the first update of each label
+fn dedup_updates(
+    updates: Vec<(AkdLabel, AkdValue)>,
+    policy: DuplicateLabelPolicy,
+) -> Result<Vec<(AkdLabel, AkdValue)>, AkdError> {
+    let mut positions = HashMap::<AkdLabel, usize>::with_capacity(updates.len());
+    let mut deduped = Vec::<(AkdLabel, AkdValue)>::with_capacity(updates.len());
+    for (label, value) in updates {
+        match (positions.get(&label), policy) {
+            (Some(_), DuplicateLabelPolicy::Reject) => {
+                return Err(AkdError::Directory(DirectoryError::DuplicateLabel(label)));
+            }
+            (Some(position), DuplicateLabelPolicy::LastWriteWins) => {
+                deduped[*position].1 = value;
+            }
+            (None, _) => {
+                positions.insert(label.clone(), deduped.len());
+                deduped.push((label, value));
+            }
+        }
+    }
+    Ok(deduped)
+}
+
+/// Checks that an audit can be generated between the given epochs
+fn check_audit_epochs(
+    current_epoch: u64,
+    audit_start_ep: u64,
+    audit_end_ep: u64,
+) -> Result<(), AkdError> {
+    if audit_start_ep >= audit_end_ep {
+        Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+            "Start epoch {} is greater than or equal the end epoch {}",
+            audit_start_ep, audit_end_ep
+        ))))
+    } else if current_epoch < audit_end_ep {
+        Err(AkdError::Directory(DirectoryError::InvalidEpoch(format!(
+            "End epoch {} is greater than the current epoch {}",
+            audit_end_ep, current_epoch
+        ))))
+    } else {
+        Ok(())
+    }
+}
+
+/// The expected depth of a leaf in a tree with the given number of nodes, each
+/// branch node having two children and the labels of the leaves being random
+fn estimated_leaf_depth(num_nodes: u64) -> usize {
+    let num_leaves = (num_nodes / 2).max(1);
+    let depth = (u64::BITS - (num_leaves - 1).leading_zeros()) as usize + 1;
+    depth.min(crate::MAX_TREE_DEPTH)
+}
+
+fn sample_node() -> Node {
+    Node {
+        label: NodeLabel::root(),
+        hash: crate::hash::EMPTY_DIGEST,
+    }
+}
+
+/// A membership proof with as many layers as the given depth, of the same size as
+/// a real proof of a leaf at that depth
+fn sample_membership_proof(depth: usize) -> MembershipProof {
+    MembershipProof {
+        label: NodeLabel::root(),
+        hash_val: crate::hash::EMPTY_DIGEST,
+        layer_proofs: vec![
+            LayerProof {
+                label: NodeLabel::root(),
+                siblings: [sample_node()],
+                direction: crate::Direction::Left,
+            };
+            depth
+        ],
+    }
+}
+
 pub(crate) fn get_marker_version(version: u64) -> u64 {
     (64 - version.leading_zeros() - 1).into()
 }
@@ -819,7 +2638,7 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
             // In the malicious case, sometimes the server may not mark the old version stale immediately.
             // If this is the case, it may want to do this marking at a later time.
             let stale_label = self
-                .vrf
+                .label_vrf()
                 .get_node_label(uname, VersionFreshness::Stale, version_number)
                 .await?;
             let stale_value_to_add = crate::hash::hash(&crate::EMPTY_VALUE);
@@ -862,16 +2681,13 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                     // no data found for the user
                     let latest_version = 1;
                     let label = self
-                        .vrf
+                        .label_vrf()
                         .get_node_label(&uname, VersionFreshness::Fresh, latest_version)
                         .await?;
 
-                    let value_to_add = akd_core::utils::commit_value(
-                        &commitment_key,
-                        &label,
-                        latest_version,
-                        &val,
-                    );
+                    let value_to_add =
+                        self.commitment
+                            .commit(&commitment_key, &label, latest_version, &val);
                     update_set.push(Node {
                         label,
                         hash: value_to_add,
@@ -888,20 +2704,17 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
                     // Data found for the given user
                     let latest_version = *previous_version + 1;
                     let stale_label = self
-                        .vrf
+                        .label_vrf()
                         .get_node_label(&uname, VersionFreshness::Stale, *previous_version)
                         .await?;
                     let fresh_label = self
-                        .vrf
+                        .label_vrf()
                         .get_node_label(&uname, VersionFreshness::Fresh, latest_version)
                         .await?;
                     let stale_value_to_add = crate::hash::hash(&crate::EMPTY_VALUE);
-                    let fresh_value_to_add = akd_core::utils::commit_value(
-                        &commitment_key,
-                        &fresh_label,
-                        latest_version,
-                        &val,
-                    );
+                    let fresh_value_to_add =
+                        self.commitment
+                            .commit(&commitment_key, &fresh_label, latest_version, &val);
                     match &corruption {
                         // Some malicious server might not want to mark an old and compromised key as stale.
                         // Thus, you only push the key if either the corruption is for some other username,
@@ -941,9 +2754,7 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
 
         if let false = self.storage.begin_transaction() {
             error!("Transaction is already active");
-            return Err(AkdError::Storage(StorageError::Transaction(
-                "Transaction is already active".to_string(),
-            )));
+            return Err(AkdError::Storage(StorageError::TransactionInProgress));
         }
         info!("Starting database insertion");
 
@@ -959,7 +2770,11 @@ impl<S: Database + 'static, V: VRFKeyStorage> Directory<S, V> {
         self.storage.batch_set(updates).await?;
 
         // now commit the transaction
-        if let Err(err) = self.storage.commit_transaction().await {
+        if let Err(err) = self
+            .storage
+            .commit_transaction_if_epoch(current_epoch)
+            .await
+        {
             // ignore any rollback error(s)
             let _ = self.storage.rollback_transaction();
             return Err(AkdError::Storage(err));
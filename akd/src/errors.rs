@@ -35,6 +35,116 @@ pub enum AkdError {
 
 impl std::error::Error for AkdError {}
 
+impl AkdError {
+    /// The machine-readable code of this error, see [ErrorCode]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AkdError::TreeNode(_) | AkdError::Parallelism(_) | AkdError::TestErr(_) => {
+                ErrorCode::Internal
+            }
+            AkdError::Directory(DirectoryError::Verification(_)) => ErrorCode::InvalidProof,
+            AkdError::Directory(DirectoryError::InvalidEpoch(_)) => ErrorCode::InvalidEpoch,
+            AkdError::Directory(DirectoryError::ReadOnlyDirectory(_)) => ErrorCode::ReadOnly,
+            AkdError::Directory(
+                DirectoryError::DuplicateLabel(_)
+                | DirectoryError::LabelExists(_)
+                | DirectoryError::LabelMapperMismatch(_)
+                | DirectoryError::LabelTooLong(..),
+            ) => ErrorCode::InvalidRequest,
+            AkdError::Directory(DirectoryError::PublishCancelled(_)) => ErrorCode::Cancelled,
+            AkdError::Directory(DirectoryError::DeadlineExceeded(_)) => ErrorCode::DeadlineExceeded,
+            AkdError::AzksErr(AzksError::NoEpochGiven) => ErrorCode::Internal,
+            AkdError::AuditErr(AuditorError::Stream(_)) => ErrorCode::Internal,
+            AkdError::AzksErr(_) | AkdError::AuditErr(_) => ErrorCode::InvalidProof,
+            AkdError::Vrf(_) => ErrorCode::Vrf,
+            AkdError::Storage(StorageError::NotFound(_)) => ErrorCode::NotFound,
+            AkdError::Storage(StorageError::TransactionInProgress | StorageError::Conflict(_)) => {
+                ErrorCode::Busy
+            }
+            AkdError::Storage(
+                StorageError::Connection(_) | StorageError::Transient(_) | StorageError::Timeout(_),
+            ) => ErrorCode::StorageUnavailable,
+            AkdError::Storage(
+                StorageError::TypeMismatch(_)
+                | StorageError::Corruption(_)
+                | StorageError::Transaction(_)
+                | StorageError::Encryption(_)
+                | StorageError::Other(_),
+            ) => ErrorCode::Storage,
+        }
+    }
+
+    /// Whether the failed operation may succeed if retried later, without any
+    /// change to its inputs
+    pub fn is_retryable(&self) -> bool {
+        self.code().is_retryable()
+    }
+}
+
+/// A machine-readable classification of an [AkdError], allowing callers to react to
+/// errors without inspecting their messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The requested data doesn't exist
+    NotFound,
+    /// The storage layer is (temporarily) unreachable
+    StorageUnavailable,
+    /// The storage layer failed in a way which isn't expected to resolve itself
+    Storage,
+    /// Another operation which must run exclusively, such as a publish, is in progress
+    Busy,
+    /// A proof failed to verify
+    InvalidProof,
+    /// The requested epoch or epoch range is invalid
+    InvalidEpoch,
+    /// The operation isn't allowed on a read-only directory
+    ReadOnly,
+    /// The request is malformed, e.g. a batch with duplicate labels
+    InvalidRequest,
+    /// The VRF failed to evaluate or verify
+    Vrf,
+    /// The operation was cancelled by the caller
+    Cancelled,
+    /// The operation didn't complete by the deadline set by the caller
+    DeadlineExceeded,
+    /// An internal invariant was violated
+    Internal,
+}
+
+impl ErrorCode {
+    /// A stable string identifying the code, e.g. for metrics or error responses
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NotFound => "not_found",
+            Self::StorageUnavailable => "storage_unavailable",
+            Self::Storage => "storage",
+            Self::Busy => "busy",
+            Self::InvalidProof => "invalid_proof",
+            Self::InvalidEpoch => "invalid_epoch",
+            Self::ReadOnly => "read_only",
+            Self::InvalidRequest => "invalid_request",
+            Self::Vrf => "vrf",
+            Self::Cancelled => "cancelled",
+            Self::DeadlineExceeded => "deadline_exceeded",
+            Self::Internal => "internal",
+        }
+    }
+
+    /// Whether errors with this code are transient, see [AkdError::is_retryable]
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::StorageUnavailable | Self::Busy | Self::DeadlineExceeded
+        )
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 impl From<TreeNodeError> for AkdError {
     fn from(error: TreeNodeError) -> Self {
         Self::TreeNode(error)
@@ -231,6 +341,19 @@ pub enum DirectoryError {
     InvalidEpoch(String),
     /// AZKS not found in read-only directory mode
     ReadOnlyDirectory(String),
+    /// A batch to publish has several updates for the label
+    DuplicateLabel(crate::AkdLabel),
+    /// The label exists in the directory, so its absence can't be proven
+    LabelExists(crate::AkdLabel),
+    /// The directory was opened with a different label mapper than it was created with
+    LabelMapperMismatch(String),
+    /// The publish of the given epoch was cancelled before it was committed
+    PublishCancelled(u64),
+    /// A label was longer than the directory's maximum label length, given as
+    /// (length, maximum), both in bytes
+    LabelTooLong(usize, usize),
+    /// The given operation didn't complete by the deadline set by the caller
+    DeadlineExceeded(String),
 }
 
 impl std::error::Error for DirectoryError {}
@@ -247,6 +370,30 @@ impl fmt::Display for DirectoryError {
             Self::ReadOnlyDirectory(inner_message) => {
                 write!(f, "Directory in read-only mode: {}", inner_message)
             }
+            Self::DuplicateLabel(label) => match std::str::from_utf8(label) {
+                Ok(name) => write!(f, "Duplicate label {} in the batch", name),
+                Err(_) => write!(f, "Duplicate label {:?} in the batch", label),
+            },
+            Self::LabelExists(label) => match std::str::from_utf8(label) {
+                Ok(name) => write!(f, "Label {} exists in the directory", name),
+                Err(_) => write!(f, "Label {:?} exists in the directory", label),
+            },
+            Self::LabelMapperMismatch(inner_message) => {
+                write!(f, "Label mapper mismatch: {}", inner_message)
+            }
+            Self::PublishCancelled(epoch) => {
+                write!(f, "The publish of epoch {} was cancelled", epoch)
+            }
+            Self::LabelTooLong(length, max_length) => {
+                write!(
+                    f,
+                    "Label of {} bytes exceeds the maximum length of {} bytes",
+                    length, max_length
+                )
+            }
+            Self::DeadlineExceeded(operation) => {
+                write!(f, "The {} didn't complete by its deadline", operation)
+            }
         }
     }
 }
@@ -263,10 +410,25 @@ impl From<akd_core::verify::VerificationError> for DirectoryError {
 pub enum StorageError {
     /// Data wasn't found in the storage layer
     NotFound(String),
+    /// A record was found, but it isn't of the type requested
+    TypeMismatch(String),
+    /// A record was found, but it couldn't be decoded
+    Corruption(String),
+    /// The storage layer failed in a way which may resolve itself, such as a deadlock
+    Transient(String),
+    /// A storage operation didn't complete in time
+    Timeout(String),
     /// A transaction error
     Transaction(String),
+    /// A transaction is already in progress
+    TransactionInProgress,
+    /// A record was written concurrently by another writer, see
+    /// [crate::storage::Database::compare_and_set]
+    Conflict(String),
     /// Some kind of storage connection error occurred
     Connection(String),
+    /// A value couldn't be encrypted or decrypted, see [crate::storage::encrypted]
+    Encryption(String),
     /// Some other storage-layer error occurred
     Other(String),
 }
@@ -282,9 +444,30 @@ impl fmt::Display for StorageError {
             StorageError::Transaction(inner) => {
                 write!(f, "Transaction: {}", inner)
             }
+            StorageError::TransactionInProgress => {
+                write!(f, "Transaction is already active")
+            }
+            StorageError::Conflict(inner) => {
+                write!(f, "Concurrent write conflict: {}", inner)
+            }
             StorageError::NotFound(inner) => {
                 write!(f, "Data not found: {}", inner)
             }
+            StorageError::TypeMismatch(inner) => {
+                write!(f, "Record type mismatch: {}", inner)
+            }
+            StorageError::Corruption(inner) => {
+                write!(f, "Corrupted record: {}", inner)
+            }
+            StorageError::Transient(inner) => {
+                write!(f, "Transient storage error: {}", inner)
+            }
+            StorageError::Timeout(inner) => {
+                write!(f, "Storage timeout: {}", inner)
+            }
+            StorageError::Encryption(inner) => {
+                write!(f, "Value encryption: {}", inner)
+            }
             StorageError::Other(inner) => {
                 write!(f, "Other storage error: {}", inner)
             }
@@ -298,6 +481,8 @@ impl fmt::Display for StorageError {
 pub enum AuditorError {
     /// A general auditor error
     VerifyAuditProof(String),
+    /// A streamed audit proof couldn't be written or read
+    Stream(String),
 }
 
 impl std::error::Error for AuditorError {}
@@ -308,6 +493,9 @@ impl fmt::Display for AuditorError {
             Self::VerifyAuditProof(err_string) => {
                 write!(f, "Failed to verify audit {}", err_string)
             }
+            Self::Stream(err_string) => {
+                write!(f, "Failed to stream audit proof {}", err_string)
+            }
         }
     }
 }
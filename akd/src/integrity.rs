@@ -0,0 +1,275 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Diagnostics checking the integrity of the tree stored by a [crate::Directory].
+//!
+//! The tree is walked from the root at a given epoch, recomputing the hash of every
+//! interior node from its children and checking that every child is linked to its
+//! parent consistently. Leaves hold commitments to the values, so their hashes can't
+//! be recomputed from the tree alone and are only checked through their parents.
+//! Finally, all the stored nodes are scanned for any which aren't reachable from the root.
+
+use crate::errors::AkdError;
+use crate::storage::manager::StorageManager;
+use crate::storage::types::{DbRecord, StorageType};
+use crate::storage::Database;
+use crate::tree_node::{
+    merge_digest_with_label_hash, NodeHashingMode, NodeKey, NodeType, TreeNode,
+    TreeNodeWithPreviousValue,
+};
+use crate::{Digest, Direction, NodeLabel};
+
+use futures_util::StreamExt;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// An inconsistency found in the stored tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityIssue {
+    /// The stored hash of a non-leaf node doesn't match the hash of its children
+    HashMismatch {
+        /// The label of the node
+        label: NodeLabel,
+        /// The hash stored for the node
+        stored: Digest,
+        /// The hash recomputed from the children of the node
+        computed: Digest,
+    },
+    /// A child doesn't point back to the node referencing it
+    ParentMismatch {
+        /// The label of the child
+        label: NodeLabel,
+        /// The parent stored in the child
+        parent: NodeLabel,
+        /// The node referencing the child
+        expected_parent: NodeLabel,
+    },
+    /// A child's label doesn't extend its parent's label in the direction
+    /// it is attached to
+    LabelMismatch {
+        /// The label of the child
+        label: NodeLabel,
+        /// The label of the parent
+        parent: NodeLabel,
+        /// The direction the child is attached to
+        direction: Direction,
+    },
+    /// A node references a child which doesn't exist in storage at the epoch
+    MissingChild {
+        /// The label of the missing child
+        label: NodeLabel,
+        /// The label of the node referencing it
+        parent: NodeLabel,
+    },
+    /// A node exists in storage at the epoch, but isn't reachable from the root
+    Orphaned {
+        /// The label of the node
+        label: NodeLabel,
+    },
+}
+
+impl fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HashMismatch {
+                label,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "Node {:?} has hash {} but its children hash to {}",
+                label,
+                hex::encode(stored),
+                hex::encode(computed)
+            ),
+            Self::ParentMismatch {
+                label,
+                parent,
+                expected_parent,
+            } => write!(
+                f,
+                "Node {:?} has parent {:?} but is a child of {:?}",
+                label, parent, expected_parent
+            ),
+            Self::LabelMismatch {
+                label,
+                parent,
+                direction,
+            } => write!(
+                f,
+                "Node {:?} is not a valid {:?} child of {:?}",
+                label, direction, parent
+            ),
+            Self::MissingChild { label, parent } => {
+                write!(
+                    f,
+                    "Node {:?} is a child of {:?} but is missing",
+                    label, parent
+                )
+            }
+            Self::Orphaned { label } => {
+                write!(f, "Node {:?} is not reachable from the root", label)
+            }
+        }
+    }
+}
+
+/// The outcome of checking the integrity of the tree at an epoch
+#[derive(Debug, Clone)]
+pub struct IntegrityReport {
+    /// The epoch the tree was checked at
+    pub epoch: u64,
+    /// The root hash of the tree at the epoch, as stored
+    pub root_hash: Digest,
+    /// The number of nodes reachable from the root
+    pub nodes_checked: usize,
+    /// The inconsistencies found
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Whether the tree is consistent
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Walks the tree from the root at the given epoch (up to the latest epoch), one level
+/// at a time. Returns the report along with the labels of all the nodes reached.
+pub(crate) async fn check_tree<S: Database>(
+    storage: &StorageManager<S>,
+    epoch: u64,
+    latest_epoch: u64,
+) -> Result<(IntegrityReport, HashSet<NodeLabel>), AkdError> {
+    let root = TreeNode::get_from_storage(storage, &NodeKey(NodeLabel::root()), epoch).await?;
+    let mut report = IntegrityReport {
+        epoch,
+        root_hash: merge_digest_with_label_hash(&root.hash, root.label),
+        nodes_checked: 0,
+        issues: vec![],
+    };
+    let mut reached = HashSet::new();
+    reached.insert(root.label);
+
+    let mut level = vec![root];
+    while !level.is_empty() {
+        let child_keys = level
+            .iter()
+            .flat_map(|node| [node.left_child, node.right_child])
+            .flatten()
+            .map(NodeKey)
+            .collect::<Vec<_>>();
+        let children = storage
+            .batch_get::<TreeNodeWithPreviousValue>(&child_keys)
+            .await?
+            .into_iter()
+            .filter_map(|record| match record {
+                DbRecord::TreeNode(node) => node.determine_node_to_get(epoch).ok(),
+                _ => None,
+            })
+            .map(|node| (node.label, node))
+            .collect::<HashMap<_, _>>();
+
+        let mut next_level = vec![];
+        for node in level {
+            report.nodes_checked += 1;
+            let mut complete = true;
+            let mut resolved = [None, None];
+            let links = [
+                (node.left_child, Direction::Left),
+                (node.right_child, Direction::Right),
+            ];
+            for (slot, (child_label, direction)) in resolved.iter_mut().zip(links) {
+                let child_label = match child_label {
+                    Some(label) => label,
+                    None => continue,
+                };
+                let child = match children.get(&child_label) {
+                    Some(child) => child,
+                    None => {
+                        report.issues.push(IntegrityIssue::MissingChild {
+                            label: child_label,
+                            parent: node.label,
+                        });
+                        complete = false;
+                        continue;
+                    }
+                };
+                if node.label.get_dir(child.label) != direction {
+                    report.issues.push(IntegrityIssue::LabelMismatch {
+                        label: child.label,
+                        parent: node.label,
+                        direction,
+                    });
+                }
+                // the parent of a node isn't versioned, so it may have been replaced by
+                // an interior node inserted between them after the epoch
+                let parent_ok = if epoch == latest_epoch {
+                    child.parent == node.label
+                } else {
+                    node.label.is_prefix_of(&child.parent)
+                        && child.parent.is_prefix_of(&child.label)
+                };
+                if !parent_ok {
+                    report.issues.push(IntegrityIssue::ParentMismatch {
+                        label: child.label,
+                        parent: child.parent,
+                        expected_parent: node.label,
+                    });
+                }
+                // guard against cycles, which would otherwise be walked forever
+                if reached.insert(child.label) {
+                    next_level.push(child.clone());
+                }
+                *slot = Some(child.clone());
+            }
+
+            // the hash can't be recomputed without all the children
+            if node.node_type != NodeType::Leaf && complete {
+                let mut recomputed = node.clone();
+                let [left, right] = resolved;
+                recomputed.set_hash_from_children(&left, &right, NodeHashingMode::WithLeafEpoch);
+                if recomputed.hash != node.hash {
+                    report.issues.push(IntegrityIssue::HashMismatch {
+                        label: node.label,
+                        stored: node.hash,
+                        computed: recomputed.hash,
+                    });
+                }
+            }
+        }
+        level = next_level;
+    }
+
+    Ok((report, reached))
+}
+
+/// Reports the stored nodes existing at the epoch which weren't reached by the walk
+pub(crate) async fn find_orphans<S: Database>(
+    storage: &StorageManager<S>,
+    report: &mut IntegrityReport,
+    reached: &HashSet<NodeLabel>,
+) -> Result<(), AkdError> {
+    let mut orphans = vec![];
+    let mut records = storage.iter_by_prefix(StorageType::TreeNode, &[]);
+    while let Some(record) = records.next().await {
+        if let DbRecord::TreeNode(node) = record? {
+            if let Ok(node) = node.determine_node_to_get(report.epoch) {
+                if !reached.contains(&node.label) {
+                    orphans.push(node.label);
+                }
+            }
+        }
+    }
+    orphans.sort();
+    report.issues.extend(
+        orphans
+            .into_iter()
+            .map(|label| IntegrityIssue::Orphaned { label }),
+    );
+    Ok(())
+}
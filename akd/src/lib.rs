@@ -178,11 +178,46 @@
 //!         epoch: 1,
 //!         version: 1,
 //!         value: AkdValue::from_utf8_str("first value"),
+//!         expiry_epoch: None,
 //!     },
 //! );
 //! # });
 //! ```
 //!
+//! The Merkle proofs making up a lookup proof can also be checked on their own, which is
+//! useful when embedding AKD's tree in a custom protocol. [`verify::verify_membership`]
+//! checks that a [`MembershipProof`] leads to a root hash, and [`verify::verify_nonmembership`]
+//! checks that a [`NonMembershipProof`] shows a label is absent from the tree with that root
+//! hash. Neither checks the VRF proofs binding the node labels to a key, nor the value
+//! commitments, which are left to the caller.
+//! ```
+//! # use akd::storage::StorageManager;
+//! # use akd::storage::memory::AsyncInMemoryDatabase;
+//! # use akd::ecvrf::HardCodedAkdVRF;
+//! # use akd::directory::Directory;
+//! # use akd::{AkdLabel, AkdValue};
+//! #
+//! # let entries = vec![
+//! #     (AkdLabel::from_utf8_str("first entry"), AkdValue::from_utf8_str("first value")),
+//! # ];
+//! # let db = AsyncInMemoryDatabase::new();
+//! # let storage_manager = StorageManager::new_no_cache(db);
+//! #
+//! # tokio_test::block_on(async {
+//! #     let vrf = HardCodedAkdVRF{};
+//! #     let mut akd = Directory::<_, _>::new(storage_manager, vrf, false).await.unwrap();
+//! #     let _ = akd.publish(entries)
+//! #         .await.expect("Error with publishing");
+//! #     let (lookup_proof, epoch_hash) = akd.lookup(
+//! #         AkdLabel::from_utf8_str("first entry")
+//! #     ).await.expect("Could not generate proof");
+//! akd::verify::verify_membership(epoch_hash.hash(), &lookup_proof.existence_proof)
+//!     .expect("Could not verify membership proof");
+//! akd::verify::verify_nonmembership(epoch_hash.hash(), &lookup_proof.freshness_proof)
+//!     .expect("Could not verify non-membership proof");
+//! # });
+//! ```
+//!
 //! ## History Proofs
 //! As mentioned above, the security is defined by consistent views of the value for a key at any epoch.
 //! To this end, a server running an AKD needs to provide a way to check the history of a key. Note that in this case,
@@ -279,11 +314,13 @@
 //!             epoch: 2,
 //!             version: 2,
 //!             value: AkdValue::from_utf8_str("updated value"),
+//!             expiry_epoch: None,
 //!         },
 //!         akd::VerifyResult {
 //!             epoch: 1,
 //!             version: 1,
 //!             value: AkdValue::from_utf8_str("first value"),
+//!             expiry_epoch: None,
 //!         },
 //!     ],
 //! );
@@ -376,6 +413,11 @@
 //! # });
 //! ```
 //!
+//! For very large epochs, [`Directory::audit_segmented`] splits the audit proof into segments
+//! by label prefix, along with a manifest linking them to the root hashes. Once the manifest is
+//! verified with [`auditor::verify_append_only_manifest`], the segments can be verified in any
+//! order, possibly by several machines, with [`auditor::verify_append_only_segment`].
+//!
 //! # Compilation Features
 //!
 //! The `akd` crate supports multiple compilation features:
@@ -414,6 +456,10 @@ pub mod client;
 pub mod directory;
 pub mod errors;
 pub mod helper_structs;
+pub mod integrity;
+pub mod proof_cache;
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+pub mod publish_scheduler;
 pub mod storage;
 pub mod tree_node;
 
@@ -425,12 +471,17 @@ pub use akd_core::hash::DIGEST_BYTES;
 pub use akd_core::verify;
 pub use akd_core::*;
 
+mod logging;
+mod runtime;
 mod utils;
 
 // ========== Type re-exports which are commonly used ========== //
 pub use append_only_zks::Azks;
 pub use client::HistoryVerificationParams;
-pub use directory::{Directory, HistoryParams};
+pub use directory::{
+    Directory, DirectoryEvent, DuplicateLabelPolicy, HistoryParams, PublishOptions, PublishPhase,
+    PublishPreview, PublishProgress,
+};
 pub use helper_structs::EpochHash;
 
 // ========== Constants and type aliases ========== //
@@ -160,6 +160,17 @@ impl AuditBlob {
             local_proof,
         ))
     }
+
+    /// Verify the proof of the AuditBlob against the root hashes in its name, directly
+    /// from its protobuf encoding (see [crate::auditor::verify_consecutive_append_only_serialized])
+    pub fn verify(&self) -> Result<(), crate::errors::AkdError> {
+        crate::auditor::verify_consecutive_append_only_serialized(
+            &self.data,
+            self.name.previous_hash,
+            self.name.current_hash,
+            self.name.epoch + 1,
+        )
+    }
 }
 
 /// Convert an append-only proof to "Audit Blobs" which are to be stored in a publicly readable storage medium
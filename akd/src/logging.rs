@@ -0,0 +1,31 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! The logging macros used throughout the crate.
+//!
+//! With the `tracing` feature, the directory operations (publish, lookups, key
+//! histories and audits) are instrumented with `tracing` spans, and the crate's
+//! logs are emitted as `tracing` events within them. The `tracing_log` feature
+//! additionally forwards the events to `log`, for applications which only install
+//! a `log` logger. Without the `tracing` feature, plain `log` records are emitted.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, error, info, trace, warn};
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use log::{debug, error, info, trace, warn};
+
+/// The number of leading bytes of a label recorded in the spans, enough to tell
+/// the operations apart without logging complete labels
+#[cfg(feature = "tracing")]
+const LABEL_PREFIX_BYTES: usize = 4;
+
+/// The hex-encoded prefix of a label, recorded in the spans
+#[cfg(feature = "tracing")]
+pub(crate) fn label_prefix(label: &[u8]) -> String {
+    hex::encode(&label[..label.len().min(LABEL_PREFIX_BYTES)])
+}
@@ -0,0 +1,232 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! A cache of the lookup proofs served by a [crate::Directory], keyed by the label
+//! and the epoch the proof was generated at. The proofs of the lookups of labels
+//! which don't exist (see [crate::Directory::lookup_nonexistent]) are cached alike.
+//!
+//! A lookup proof is fully determined by the label and the epoch, so a cached proof
+//! never goes stale. However proofs for past epochs are no longer served, so the
+//! cache is emptied whenever a new epoch is published (or observed) and, within an
+//! epoch, the least recently used proofs are evicted once the cache exceeds its
+//! memory limit.
+
+use crate::logging::debug;
+use crate::{AbsenceProof, AkdLabel, EpochHash, LookupProof};
+use akd_core::SizeOf;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// The default memory limit of the proof cache (64 MB)
+pub const DEFAULT_PROOF_CACHE_LIMIT_BYTES: usize = 64 * 1024 * 1024;
+
+/// When the memory limit is exceeded, proofs are evicted until the cache is
+/// back under this fraction of the limit, so that evictions aren't triggered
+/// by every subsequent insertion
+const EVICTION_TARGET: f64 = 0.9;
+
+#[derive(Clone)]
+enum Proof {
+    Lookup(Box<LookupProof>),
+    Absence(Box<AbsenceProof>),
+}
+
+impl SizeOf for Proof {
+    fn size_of(&self) -> usize {
+        match self {
+            Proof::Lookup(proof) => proof.size_of(),
+            Proof::Absence(proof) => proof.size_of(),
+        }
+    }
+}
+
+struct CachedProof {
+    proof: Proof,
+    root_hash: EpochHash,
+    size: usize,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    epoch: u64,
+    entries: HashMap<(AkdLabel, u64), CachedProof>,
+    size: usize,
+    clock: u64,
+}
+
+impl CacheState {
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+
+    /// Clears the cache if it holds proofs for an epoch prior to `epoch`
+    fn advance_to(&mut self, epoch: u64) {
+        if epoch > self.epoch {
+            self.clear();
+            self.epoch = epoch;
+        }
+    }
+
+    fn evict(&mut self, limit_bytes: usize) {
+        let target = (limit_bytes as f64 * EVICTION_TARGET) as usize;
+        let mut by_age = self
+            .entries
+            .iter()
+            .map(|(key, cached)| (cached.last_used, key.clone()))
+            .collect::<Vec<_>>();
+        by_age.sort_unstable_by_key(|(last_used, _)| *last_used);
+
+        let mut num_evicted = 0;
+        for (_, key) in by_age {
+            if self.size <= target {
+                break;
+            }
+            if let Some(cached) = self.entries.remove(&key) {
+                self.size -= cached.size;
+                num_evicted += 1;
+            }
+        }
+        debug!("Evicted {} proofs from the lookup proof cache", num_evicted);
+    }
+}
+
+/// A memory-bounded cache of lookup proofs, shared by all the clones of a directory
+pub struct LookupProofCache {
+    limit_bytes: usize,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl LookupProofCache {
+    /// Creates an empty cache holding at most (approximately) `limit_bytes` of proofs
+    pub fn new(limit_bytes: usize) -> Self {
+        Self {
+            limit_bytes,
+            state: Mutex::new(CacheState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Retrieves the proof for a label at the given epoch, along with the root
+    /// hash it was generated against
+    pub fn get(&self, label: &AkdLabel, epoch: u64) -> Option<(LookupProof, EpochHash)> {
+        match self.get_proof(label, epoch) {
+            Some((Proof::Lookup(proof), root_hash)) => Some((*proof, root_hash)),
+            _ => None,
+        }
+    }
+
+    /// Retrieves the proof that a label doesn't exist at the given epoch, along
+    /// with the root hash it was generated against
+    pub fn get_absence(&self, label: &AkdLabel, epoch: u64) -> Option<(AbsenceProof, EpochHash)> {
+        match self.get_proof(label, epoch) {
+            Some((Proof::Absence(proof), root_hash)) => Some((*proof, root_hash)),
+            _ => None,
+        }
+    }
+
+    /// Caches the proof for a label, generated against the given root hash
+    pub fn insert(&self, label: AkdLabel, proof: LookupProof, root_hash: EpochHash) {
+        self.insert_proof(label, Proof::Lookup(Box::new(proof)), root_hash)
+    }
+
+    /// Caches the proof that a label doesn't exist, generated against the given
+    /// root hash
+    pub fn insert_absence(&self, label: AkdLabel, proof: AbsenceProof, root_hash: EpochHash) {
+        self.insert_proof(label, Proof::Absence(Box::new(proof)), root_hash)
+    }
+
+    fn get_proof(&self, label: &AkdLabel, epoch: u64) -> Option<(Proof, EpochHash)> {
+        let mut state = self.state.lock().unwrap();
+        state.advance_to(epoch);
+        state.clock += 1;
+        let clock = state.clock;
+
+        match state.entries.get_mut(&(label.clone(), epoch)) {
+            Some(cached) => {
+                cached.last_used = clock;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some((cached.proof.clone(), cached.root_hash.clone()))
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn insert_proof(&self, label: AkdLabel, proof: Proof, root_hash: EpochHash) {
+        let epoch = root_hash.epoch();
+        let size = label.size_of() + proof.size_of() + root_hash.1.len();
+        if size > self.limit_bytes {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.advance_to(epoch);
+        if epoch < state.epoch {
+            // a newer epoch was observed since this proof was generated
+            return;
+        }
+        state.clock += 1;
+        let cached = CachedProof {
+            proof,
+            root_hash,
+            size,
+            last_used: state.clock,
+        };
+        if let Some(previous) = state.entries.insert((label, epoch), cached) {
+            state.size -= previous.size;
+        }
+        state.size += size;
+
+        if state.size > self.limit_bytes {
+            state.evict(self.limit_bytes);
+        }
+    }
+
+    /// Removes all the cached proofs
+    pub fn invalidate(&self) {
+        self.state.lock().unwrap().clear();
+    }
+
+    /// The number of cached proofs
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// Whether the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The (approximate) memory used by the cached proofs, in bytes
+    pub fn size_bytes(&self) -> usize {
+        self.state.lock().unwrap().size
+    }
+
+    /// The number of lookups served from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// The number of lookups which missed the cache
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for LookupProofCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_PROOF_CACHE_LIMIT_BYTES)
+    }
+}
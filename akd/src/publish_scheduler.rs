@@ -0,0 +1,192 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! A scheduler which batches key updates in memory and publishes them to a
+//! [Directory] on a fixed cadence, or as soon as enough updates are pending.
+//!
+//! Updates to the same label which are enqueued within the same batch are
+//! collapsed, with the most recently enqueued value being published.
+//!
+//! The scheduler requires the `tokio_runtime` or `async_std_runtime` feature.
+
+use crate::ecvrf::VRFKeyStorage;
+use crate::errors::AkdError;
+use crate::runtime::{self, JoinHandle};
+use crate::storage::Database;
+use crate::{AkdLabel, AkdValue, Directory, EpochHash};
+
+use crate::logging::{error, info};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Publish the pending updates every 10s by default
+pub const DEFAULT_PUBLISH_INTERVAL: Duration = Duration::from_secs(10);
+/// Publish as soon as 10,000 updates are pending by default
+pub const DEFAULT_MAX_PENDING_UPDATES: usize = 10_000;
+
+/// Controls when the [PublishScheduler] publishes the pending updates
+#[derive(Debug, Clone)]
+pub struct PublishSchedulerConfig {
+    /// The pending updates are published at least this often
+    pub interval: Duration,
+    /// The pending updates are published as soon as there are this many of them
+    pub max_pending: usize,
+}
+
+impl Default for PublishSchedulerConfig {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_PUBLISH_INTERVAL,
+            max_pending: DEFAULT_MAX_PENDING_UPDATES,
+        }
+    }
+}
+
+struct SchedulerState<S: Database, V> {
+    directory: Directory<S, V>,
+    config: PublishSchedulerConfig,
+    pending: Mutex<HashMap<AkdLabel, AkdValue>>,
+    /// Serializes the publishes, as a directory only supports one at a time
+    publish_lock: tokio::sync::Mutex<()>,
+    wakeup: Notify,
+    shutdown: AtomicBool,
+}
+
+impl<S: Database + 'static, V: VRFKeyStorage> SchedulerState<S, V> {
+    async fn flush(&self) -> Result<Option<EpochHash>, AkdError> {
+        let _guard = self.publish_lock.lock().await;
+        let updates = std::mem::take(&mut *self.pending.lock().unwrap());
+        if updates.is_empty() {
+            return Ok(None);
+        }
+
+        let num_updates = updates.len();
+        let batch = updates
+            .iter()
+            .map(|(label, value)| (label.clone(), value.clone()))
+            .collect();
+        match self.directory.publish(batch).await {
+            Ok(epoch_hash) => {
+                info!(
+                    "Published {} scheduled updates in epoch {}",
+                    num_updates,
+                    epoch_hash.epoch()
+                );
+                Ok(Some(epoch_hash))
+            }
+            Err(err) => {
+                // put the updates back, unless they were superseded in the meantime
+                let mut pending = self.pending.lock().unwrap();
+                for (label, value) in updates {
+                    pending.entry(label).or_insert(value);
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Batches key updates in memory, and publishes them to a [Directory] from a
+/// background task every [PublishSchedulerConfig::interval], or as soon as
+/// [PublishSchedulerConfig::max_pending] updates are pending.
+///
+/// The pending updates are only held in memory, so [PublishScheduler::shutdown]
+/// should be called to publish them before the scheduler is dropped.
+pub struct PublishScheduler<S: Database + 'static, V: VRFKeyStorage + 'static> {
+    state: Arc<SchedulerState<S, V>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<S: Database + 'static, V: VRFKeyStorage + 'static> PublishScheduler<S, V> {
+    /// Starts a scheduler publishing to the given directory. Must be called from
+    /// within the runtime enabled by the `tokio_runtime` or `async_std_runtime`
+    /// feature, i.e. a tokio or an async-std runtime.
+    pub fn start(directory: Directory<S, V>, config: PublishSchedulerConfig) -> Self {
+        let state = Arc::new(SchedulerState {
+            directory,
+            config,
+            pending: Mutex::new(HashMap::new()),
+            publish_lock: tokio::sync::Mutex::new(()),
+            wakeup: Notify::new(),
+            shutdown: AtomicBool::new(false),
+        });
+
+        let worker_state = state.clone();
+        let worker = runtime::spawn(async move {
+            loop {
+                // wakes up either when the interval elapses, or when enough updates are pending
+                let _ =
+                    runtime::timeout(worker_state.config.interval, worker_state.wakeup.notified())
+                        .await;
+                if worker_state.shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Err(err) = worker_state.flush().await {
+                    error!("Scheduled publish failed, will retry: {}", err);
+                }
+            }
+        });
+
+        Self {
+            state,
+            worker: Some(worker),
+        }
+    }
+
+    /// Queues an update for the next publish, replacing any pending update for the
+    /// same label
+    pub fn enqueue(&self, label: AkdLabel, value: AkdValue) {
+        let num_pending = {
+            let mut pending = self.state.pending.lock().unwrap();
+            pending.insert(label, value);
+            pending.len()
+        };
+        if num_pending >= self.state.config.max_pending {
+            self.state.wakeup.notify_one();
+        }
+    }
+
+    /// The number of updates waiting to be published
+    pub fn pending_count(&self) -> usize {
+        self.state.pending.lock().unwrap().len()
+    }
+
+    /// Publishes the pending updates right away, returning the resulting epoch and
+    /// root hash, or `None` if there was nothing to publish. On failure, the
+    /// updates are kept for the next publish.
+    pub async fn flush(&self) -> Result<Option<EpochHash>, AkdError> {
+        self.state.flush().await
+    }
+
+    /// Stops the background publishing, and publishes any remaining updates
+    pub async fn shutdown(mut self) -> Result<Option<EpochHash>, AkdError> {
+        self.stop_worker();
+        if let Some(worker) = self.worker.take() {
+            // the worker only stops between publishes
+            let _ = worker.join().await;
+        }
+        self.state.flush().await
+    }
+
+    fn stop_worker(&self) {
+        self.state.shutdown.store(true, Ordering::SeqCst);
+        self.state.wakeup.notify_one();
+    }
+}
+
+impl<S: Database + 'static, V: VRFKeyStorage + 'static> Drop for PublishScheduler<S, V> {
+    fn drop(&mut self) {
+        self.stop_worker();
+        if let Some(worker) = self.worker.take() {
+            // rather than aborting a publish, the worker stops after it
+            worker.detach();
+        }
+    }
+}
@@ -0,0 +1,270 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! A shim over the async runtime, isolating the places where the directory spawns
+//! tasks or waits on timers. The synchronization primitives used throughout the
+//! crate (from `tokio::sync`) don't depend on a runtime.
+//!
+//! With the `tokio_runtime` feature (enabled by default), tasks are spawned onto the
+//! tokio runtime, which must then drive the directory. With the `async_std_runtime`
+//! feature instead, they are spawned onto async-std's runtime. If both are enabled,
+//! tokio is used. A spawned task is aborted when its [JoinHandle] is dropped before
+//! it completes, unless it was detached, so that a task can't outlive the operation
+//! it was spawned for when that operation is abandoned or fails.
+//!
+//! Without either, a spawned task is run in place when it is joined and no timer is
+//! available, so that the directory can be driven by any executor. The functionality
+//! requiring timers (the [crate::publish_scheduler],
+//! [crate::Directory::poll_for_azks_changes] and the operations with a deadline) is
+//! then unavailable. The `parallel_vrf` feature
+//! requires the tokio runtime, and the `parallel_insert` and `parallel_hashing`
+//! features require one of the two runtimes, failing to compile without one rather
+//! than silently running in place.
+
+#[cfg(all(
+    feature = "parallel_insert",
+    not(any(feature = "tokio_runtime", feature = "async_std_runtime"))
+))]
+compile_error!(
+    "The `parallel_insert` feature requires a runtime: enable `tokio_runtime` or `async_std_runtime`"
+);
+
+#[cfg(all(
+    feature = "parallel_hashing",
+    not(any(feature = "tokio_runtime", feature = "async_std_runtime"))
+))]
+compile_error!(
+    "The `parallel_hashing` feature requires a runtime: enable `tokio_runtime` or `async_std_runtime`"
+);
+
+#[cfg(feature = "tokio_runtime")]
+mod imp {
+    use crate::errors::{AkdError, ParallelismError};
+    use core::future::Future;
+    use std::time::{Duration, Instant};
+
+    /// A handle to a task started with [spawn], aborting it when dropped
+    pub(crate) struct JoinHandle<T> {
+        inner: tokio::task::JoinHandle<T>,
+        detached: bool,
+    }
+
+    impl<T> JoinHandle<T> {
+        /// Waits for the task to complete, returning its output
+        pub(crate) async fn join(mut self) -> Result<T, AkdError> {
+            (&mut self.inner)
+                .await
+                .map_err(|e| AkdError::Parallelism(ParallelismError::JoinErr(e.to_string())))
+        }
+
+        /// Lets the task run to completion in the background
+        pub(crate) fn detach(mut self) {
+            self.detached = true;
+        }
+    }
+
+    impl<T> Drop for JoinHandle<T> {
+        fn drop(&mut self) {
+            if !self.detached {
+                self.inner.abort();
+            }
+        }
+    }
+
+    /// Starts running a future in the background
+    pub(crate) fn spawn<F>(future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        JoinHandle {
+            inner: tokio::task::spawn(future),
+            detached: false,
+        }
+    }
+
+    /// Runs a blocking computation on the runtime's blocking thread pool
+    #[cfg(feature = "parallel_hashing")]
+    pub(crate) async fn spawn_blocking<F, T>(f: F) -> Result<T, AkdError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        tokio::task::spawn_blocking(f)
+            .await
+            .map_err(|e| AkdError::Parallelism(ParallelismError::JoinErr(e.to_string())))
+    }
+
+    /// Waits for the given duration
+    pub(crate) async fn sleep(duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+
+    /// Runs a future for at most the given duration, returning `None` if it didn't
+    /// complete in time
+    pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+        tokio::time::timeout(duration, future).await.ok()
+    }
+
+    /// Runs a future until the given deadline at the latest, returning `None` if it
+    /// didn't complete in time
+    pub(crate) async fn timeout_at<F: Future>(deadline: Instant, future: F) -> Option<F::Output> {
+        tokio::time::timeout_at(tokio::time::Instant::from_std(deadline), future)
+            .await
+            .ok()
+    }
+}
+
+#[cfg(all(feature = "async_std_runtime", not(feature = "tokio_runtime")))]
+mod imp {
+    use crate::errors::{AkdError, ParallelismError};
+    use core::future::Future;
+    use futures_util::future::{AbortHandle, Abortable, Aborted};
+    use std::time::{Duration, Instant};
+
+    /// A handle to a task started with [spawn], aborting it when dropped
+    pub(crate) struct JoinHandle<T> {
+        inner: async_std::task::JoinHandle<Result<T, Aborted>>,
+        abort: Option<AbortHandle>,
+    }
+
+    impl<T> JoinHandle<T> {
+        /// Waits for the task to complete, returning its output. A panic of the
+        /// task is propagated by async-std.
+        pub(crate) async fn join(mut self) -> Result<T, AkdError> {
+            (&mut self.inner).await.map_err(|_| {
+                AkdError::Parallelism(ParallelismError::JoinErr(
+                    "The task was aborted".to_string(),
+                ))
+            })
+        }
+
+        /// Lets the task run to completion in the background
+        pub(crate) fn detach(mut self) {
+            self.abort = None;
+        }
+    }
+
+    impl<T> Drop for JoinHandle<T> {
+        fn drop(&mut self) {
+            // dropping async-std's handle detaches the task, which stops at its next
+            // poll once aborted
+            if let Some(abort) = &self.abort {
+                abort.abort();
+            }
+        }
+    }
+
+    /// Starts running a future in the background
+    pub(crate) fn spawn<F>(future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (abort, registration) = AbortHandle::new_pair();
+        JoinHandle {
+            inner: async_std::task::spawn(Abortable::new(future, registration)),
+            abort: Some(abort),
+        }
+    }
+
+    /// Runs a blocking computation on the runtime's blocking thread pool
+    #[cfg(feature = "parallel_hashing")]
+    pub(crate) async fn spawn_blocking<F, T>(f: F) -> Result<T, AkdError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        Ok(async_std::task::spawn_blocking(f).await)
+    }
+
+    /// Waits for the given duration
+    pub(crate) async fn sleep(duration: Duration) {
+        async_std::task::sleep(duration).await
+    }
+
+    /// Runs a future for at most the given duration, returning `None` if it didn't
+    /// complete in time
+    pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+        async_std::future::timeout(duration, future).await.ok()
+    }
+
+    /// Runs a future until the given deadline at the latest, returning `None` if it
+    /// didn't complete in time
+    pub(crate) async fn timeout_at<F: Future>(deadline: Instant, future: F) -> Option<F::Output> {
+        timeout(deadline.saturating_duration_since(Instant::now()), future).await
+    }
+}
+
+#[cfg(not(any(feature = "tokio_runtime", feature = "async_std_runtime")))]
+mod imp {
+    use crate::errors::AkdError;
+    use core::future::Future;
+    use core::pin::Pin;
+
+    /// A handle to a task started with [spawn], which is dropped along with it
+    pub(crate) struct JoinHandle<T> {
+        inner: Pin<Box<dyn Future<Output = T> + Send>>,
+    }
+
+    impl<T> JoinHandle<T> {
+        /// Runs the task to completion, returning its output
+        pub(crate) async fn join(self) -> Result<T, AkdError> {
+            Ok(self.inner.await)
+        }
+    }
+
+    /// Defers running a future until its handle is joined, the task never running
+    /// if the handle is dropped instead
+    pub(crate) fn spawn<F>(future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        JoinHandle {
+            inner: Box::pin(future),
+        }
+    }
+}
+
+pub(crate) use imp::*;
+
+#[cfg(all(test, any(feature = "tokio_runtime", feature = "async_std_runtime")))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn spawn_flag_setter(flag: Arc<AtomicBool>) -> JoinHandle<()> {
+        spawn(async move {
+            sleep(Duration::from_millis(50)).await;
+            flag.store(true, Ordering::SeqCst);
+        })
+    }
+
+    // A task should be aborted when its handle is dropped, and run to completion
+    // when it's joined or detached
+    #[tokio::test]
+    async fn test_dropped_task_is_aborted() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let joined = Arc::new(AtomicBool::new(false));
+        let detached = Arc::new(AtomicBool::new(false));
+
+        drop(spawn_flag_setter(dropped.clone()));
+        spawn_flag_setter(detached.clone()).detach();
+        spawn_flag_setter(joined.clone())
+            .join()
+            .await
+            .expect("Failed to join the task");
+        sleep(Duration::from_millis(200)).await;
+
+        assert!(!dropped.load(Ordering::SeqCst));
+        assert!(joined.load(Ordering::SeqCst));
+        assert!(detached.load(Ordering::SeqCst));
+    }
+}
@@ -9,15 +9,15 @@
 //! objects
 
 use super::{CachedItem, DEFAULT_CACHE_CLEAN_FREQUENCY_MS, DEFAULT_ITEM_LIFETIME_MS};
+#[cfg(not(feature = "runtime_metrics"))]
+use crate::logging::debug;
+use crate::logging::info;
+#[cfg(feature = "runtime_metrics")]
+use crate::logging::{debug, error, warn};
 use crate::storage::DbRecord;
 use crate::storage::Storable;
 use akd_core::SizeOf;
 use dashmap::DashMap;
-#[cfg(not(feature = "runtime_metrics"))]
-use log::debug;
-use log::info;
-#[cfg(feature = "runtime_metrics")]
-use log::{debug, error, warn};
 
 #[cfg(feature = "runtime_metrics")]
 use std::sync::atomic::AtomicU64;
@@ -240,6 +240,17 @@ impl TimedCache {
         }
     }
 
+    /// Remove a batch of items from the cache
+    pub async fn batch_remove<St: Storable>(&self, keys: &[St::StorageKey]) {
+        if St::data_type() == crate::storage::types::StorageType::Azks {
+            *(self.azks.write().await) = None;
+            return;
+        }
+        for key in keys.iter() {
+            self.map.remove(&St::get_full_binary_key_id(key));
+        }
+    }
+
     /// Flush the cache
     pub async fn flush(&self) {
         self.map.clear();
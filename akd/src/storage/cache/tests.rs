@@ -31,6 +31,7 @@ async fn test_cache_put_and_expires() {
         },
         plaintext_val: AkdValue::from_utf8_str("some value"),
         username: AkdLabel::from_utf8_str("user"),
+        expiry_epoch: None,
     });
     let key = ValueStateKey(AkdLabel::from_utf8_str("user").0.to_vec(), 1);
     cache.put(&value_state).await;
@@ -57,6 +58,7 @@ async fn test_cache_overwrite() {
         },
         plaintext_val: AkdValue::from_utf8_str("some value"),
         username: AkdLabel::from_utf8_str("user"),
+        expiry_epoch: None,
     };
     let key = ValueStateKey(AkdLabel::from_utf8_str("user").0.to_vec(), 1);
 
@@ -69,6 +71,7 @@ async fn test_cache_overwrite() {
         },
         plaintext_val: AkdValue::from_utf8_str("some value"),
         username: AkdLabel::from_utf8_str("user"),
+        expiry_epoch: None,
     };
     cache.put(&DbRecord::ValueState(value_state)).await;
     cache
@@ -96,6 +99,7 @@ async fn test_cache_memory_pressure() {
         },
         plaintext_val: AkdValue::from_utf8_str("some value"),
         username: AkdLabel::from_utf8_str("user"),
+        expiry_epoch: None,
     });
     let key = ValueStateKey(AkdLabel::from_utf8_str("user").0.to_vec(), 1);
     cache.put(&value_state).await;
@@ -127,6 +131,7 @@ async fn test_many_memory_pressure() {
             },
             plaintext_val: AkdValue::from_utf8_str("test"),
             username: AkdLabel::from_utf8_str("user"),
+            expiry_epoch: None,
         })
         .map(DbRecord::ValueState)
         .collect::<Vec<_>>();
@@ -0,0 +1,267 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Encryption at rest of the values stored in the directory.
+//!
+//! An [EncryptedDatabase] wraps a [Database], encrypting the value of every
+//! [ValueState] before it is written to the wrapped database, and decrypting it
+//! when it is read back. The directory above it only ever sees plaintext values,
+//! so the commitments and hashes (and hence the proofs) are computed over the
+//! plaintext, exactly as without encryption. The tree nodes only hold hashes, and
+//! are stored as is.
+//!
+//! The encryption itself, along with the management of the keys, is provided by an
+//! implementation of [ValueEncryption]. Typically, it encrypts each value with a
+//! fresh data key under an AEAD (e.g. AES-GCM), and wraps the data key with a key
+//! encryption key held in a key management service, storing the wrapped data key
+//! (and the id of the key encryption key, to support rotating it) in the envelope
+//! alongside the ciphertext. Each value is encrypted with the label and epoch of its
+//! state as associated data, so that a ciphertext can't be moved to another record.
+
+use crate::errors::StorageError;
+use crate::storage::types::{DbRecord, KeyData, StorageType, ValueState, ValueStateRetrievalFlag};
+use crate::storage::{Database, DbSetState, RecordStream, Storable, StorageUtil};
+use crate::{AkdLabel, AkdValue};
+
+use async_trait::async_trait;
+use futures_util::future;
+use futures_util::stream::StreamExt;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Encrypts and decrypts the values stored by an [EncryptedDatabase]
+#[async_trait]
+pub trait ValueEncryption: Send + Sync {
+    /// Encrypts a value, returning the envelope to store. The associated data must
+    /// be authenticated, but not stored in the envelope.
+    async fn encrypt(
+        &self,
+        plaintext: &[u8],
+        associated_data: &[u8],
+    ) -> Result<Vec<u8>, StorageError>;
+
+    /// Decrypts an envelope returned by [ValueEncryption::encrypt], failing if it
+    /// wasn't produced with the same associated data
+    async fn decrypt(
+        &self,
+        envelope: &[u8],
+        associated_data: &[u8],
+    ) -> Result<Vec<u8>, StorageError>;
+}
+
+/// A [Database] encrypting the stored values, see the module documentation
+pub struct EncryptedDatabase<Db: Database> {
+    db: Db,
+    encryption: Arc<dyn ValueEncryption>,
+}
+
+impl<Db: Database> Clone for EncryptedDatabase<Db> {
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+            encryption: self.encryption.clone(),
+        }
+    }
+}
+
+impl<Db: Database> EncryptedDatabase<Db> {
+    /// Wraps a database, encrypting the values stored in it with the given encryption
+    pub fn new<E: ValueEncryption + 'static>(db: Db, encryption: E) -> Self {
+        Self {
+            db,
+            encryption: Arc::new(encryption),
+        }
+    }
+
+    /// The wrapped database, which holds the encrypted values
+    pub fn inner(&self) -> &Db {
+        &self.db
+    }
+
+    /// The associated data of a value: the label and epoch of its state
+    fn associated_data(username: &AkdLabel, epoch: u64) -> Vec<u8> {
+        let mut data = username.0.clone();
+        data.extend_from_slice(&epoch.to_be_bytes());
+        data
+    }
+
+    async fn encrypt_state(&self, mut state: ValueState) -> Result<ValueState, StorageError> {
+        let associated_data = Self::associated_data(&state.username, state.epoch);
+        state.plaintext_val = AkdValue(
+            self.encryption
+                .encrypt(&state.plaintext_val, &associated_data)
+                .await?,
+        );
+        Ok(state)
+    }
+
+    async fn decrypt_state(&self, mut state: ValueState) -> Result<ValueState, StorageError> {
+        let associated_data = Self::associated_data(&state.username, state.epoch);
+        state.plaintext_val = AkdValue(
+            self.encryption
+                .decrypt(&state.plaintext_val, &associated_data)
+                .await?,
+        );
+        Ok(state)
+    }
+
+    async fn encrypt_record(&self, record: DbRecord) -> Result<DbRecord, StorageError> {
+        match record {
+            DbRecord::ValueState(state) => {
+                Ok(DbRecord::ValueState(self.encrypt_state(state).await?))
+            }
+            other => Ok(other),
+        }
+    }
+
+    async fn decrypt_record(&self, record: DbRecord) -> Result<DbRecord, StorageError> {
+        match record {
+            DbRecord::ValueState(state) => {
+                Ok(DbRecord::ValueState(self.decrypt_state(state).await?))
+            }
+            other => Ok(other),
+        }
+    }
+
+    async fn decrypt_records(&self, records: Vec<DbRecord>) -> Result<Vec<DbRecord>, StorageError> {
+        future::try_join_all(
+            records
+                .into_iter()
+                .map(|record| self.decrypt_record(record)),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<Db: Database + 'static> Database for EncryptedDatabase<Db> {
+    async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+        let record = self.encrypt_record(record).await?;
+        self.db.set(record).await
+    }
+
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        let records = future::try_join_all(
+            records
+                .into_iter()
+                .map(|record| self.encrypt_record(record)),
+        )
+        .await?;
+        self.db.batch_set(records, state).await
+    }
+
+    async fn compare_and_set(
+        &self,
+        record: DbRecord,
+        expected_version: Option<u64>,
+        others: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        let record = self.encrypt_record(record).await?;
+        let others =
+            future::try_join_all(others.into_iter().map(|record| self.encrypt_record(record)))
+                .await?;
+        self.db
+            .compare_and_set(record, expected_version, others, state)
+            .await
+    }
+
+    async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
+        let record = self.db.get::<St>(id).await?;
+        self.decrypt_record(record).await
+    }
+
+    async fn batch_get<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        let records = self.db.batch_get::<St>(ids).await?;
+        self.decrypt_records(records).await
+    }
+
+    async fn batch_delete<St: Storable>(&self, ids: &[St::StorageKey]) -> Result<(), StorageError> {
+        self.db.batch_delete::<St>(ids).await
+    }
+
+    async fn get_user_data(&self, username: &AkdLabel) -> Result<KeyData, StorageError> {
+        let data = self.db.get_user_data(username).await?;
+        let states = future::try_join_all(
+            data.states
+                .into_iter()
+                .map(|state| self.decrypt_state(state)),
+        )
+        .await?;
+        Ok(KeyData { states })
+    }
+
+    async fn get_user_state(
+        &self,
+        username: &AkdLabel,
+        flag: ValueStateRetrievalFlag,
+    ) -> Result<ValueState, StorageError> {
+        let state = self.db.get_user_state(username, flag).await?;
+        self.decrypt_state(state).await
+    }
+
+    async fn get_user_state_versions(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+    ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        let versions = self.db.get_user_state_versions(usernames, flag).await?;
+        // the epochs of the states aren't returned, so the states themselves are
+        // retrieved to decrypt the values
+        let decrypted = future::try_join_all(versions.into_iter().map(
+            |(username, (version, _))| async move {
+                let state = self
+                    .db
+                    .get_user_state(&username, ValueStateRetrievalFlag::SpecificVersion(version))
+                    .await?;
+                let state = self.decrypt_state(state).await?;
+                Ok::<_, StorageError>((username, (version, state.plaintext_val)))
+            },
+        ))
+        .await?;
+        Ok(decrypted.into_iter().collect())
+    }
+
+    fn iter_by_prefix(&self, storage_type: StorageType, key_prefix: &[u8]) -> RecordStream<'_> {
+        self.db
+            .iter_by_prefix(storage_type, key_prefix)
+            .then(move |record| async move {
+                match record {
+                    Ok(record) => self.decrypt_record(record).await,
+                    Err(err) => Err(err),
+                }
+            })
+            .boxed()
+    }
+
+    async fn with_namespace(&self, namespace: &[u8]) -> Result<Self, StorageError> {
+        Ok(Self {
+            db: self.db.with_namespace(namespace).await?,
+            encryption: self.encryption.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl<Db: StorageUtil + 'static> StorageUtil for EncryptedDatabase<Db> {
+    async fn batch_get_type_direct<St: Storable>(&self) -> Result<Vec<DbRecord>, StorageError> {
+        let records = self.db.batch_get_type_direct::<St>().await?;
+        self.decrypt_records(records).await
+    }
+
+    async fn batch_get_all_direct(&self) -> Result<Vec<DbRecord>, StorageError> {
+        let records = self.db.batch_get_all_direct().await?;
+        self.decrypt_records(records).await
+    }
+}
@@ -13,21 +13,23 @@ use crate::storage::cache::TimedCache;
 use crate::storage::transaction::Transaction;
 use crate::storage::types::DbRecord;
 use crate::storage::types::KeyData;
+use crate::storage::types::StorageType;
 use crate::storage::types::ValueState;
 use crate::storage::types::ValueStateKey;
 use crate::storage::Database;
 use crate::storage::DbSetState;
+use crate::storage::RecordStream;
 use crate::storage::Storable;
 use crate::storage::StorageError;
 use crate::AkdLabel;
 use crate::AkdValue;
 
-use log::{debug, error, info, warn};
+use crate::logging::{debug, error, info, warn};
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use super::types::ValueStateRetrievalFlag;
 
@@ -59,6 +61,8 @@ pub struct StorageManager<Db: Database> {
     pub db: Db,
 
     metrics: [Arc<AtomicU64>; NUM_METRICS],
+    /// The instant after which reads from the data layer fail, see [StorageManager::with_deadline]
+    deadline: Option<Instant>,
 }
 
 unsafe impl<Db: Database> Sync for StorageManager<Db> {}
@@ -72,6 +76,7 @@ impl<Db: Database> StorageManager<Db> {
             transaction: Transaction::new(),
             db,
             metrics: [0; NUM_METRICS].map(|_| Arc::new(AtomicU64::new(0))),
+            deadline: None,
         }
     }
 
@@ -91,6 +96,7 @@ impl<Db: Database> StorageManager<Db> {
             transaction: Transaction::new(),
             db,
             metrics: [0; NUM_METRICS].map(|_| Arc::new(AtomicU64::new(0))),
+            deadline: None,
         }
     }
 
@@ -99,6 +105,18 @@ impl<Db: Database> StorageManager<Db> {
         self.cache.is_some()
     }
 
+    /// Creates a handle to the same storage (sharing its cache and transaction) whose
+    /// reads from the data layer fail with [StorageError::Timeout] once the deadline
+    /// has passed. A read isn't started past the deadline, and with the
+    /// `tokio_runtime` or `async_std_runtime` feature, a pending read is abandoned
+    /// when the deadline passes.
+    pub fn with_deadline(&self, deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..self.clone()
+        }
+    }
+
     /// Log metrics from the storage manager (cache, transaction, and storage hit rates etc)
     pub async fn log_metrics(&self, level: log::Level) {
         if let Some(cache) = &self.cache {
@@ -169,8 +187,27 @@ impl<Db: Database> StorageManager<Db> {
 
     /// Commit a transaction in the database
     pub async fn commit_transaction(&self) -> Result<(), StorageError> {
+        self.commit_transaction_checked(None).await
+    }
+
+    /// Commit a transaction in the database, as [StorageManager::commit_transaction],
+    /// only if the stored azks is still at `expected_epoch`. Of two writers which
+    /// both read the azks at the same epoch and update it, only the first one to
+    /// commit succeeds, the other failing with [StorageError::Conflict] without
+    /// writing any of its records (see [Database::compare_and_set]).
+    pub async fn commit_transaction_if_epoch(
+        &self,
+        expected_epoch: u64,
+    ) -> Result<(), StorageError> {
+        self.commit_transaction_checked(Some(expected_epoch)).await
+    }
+
+    async fn commit_transaction_checked(
+        &self,
+        expected_epoch: Option<u64>,
+    ) -> Result<(), StorageError> {
         // this retrieves all the trans operations, and "de-activates" the transaction flag
-        let records = self.transaction.commit_transaction()?;
+        let mut records = self.transaction.commit_transaction()?;
 
         // The transaction is now complete (or reverted) and therefore we can re-enable
         // the cache cleaning status
@@ -183,26 +220,91 @@ impl<Db: Database> StorageManager<Db> {
             return Ok(());
         }
 
-        let _epoch = match records.last() {
-            Some(DbRecord::Azks(azks)) => Ok(azks.latest_epoch),
-            other => Err(StorageError::Transaction(format!(
-                "The last record in the transaction log is NOT an Azks record {:?}",
-                other
-            ))),
-        }?;
+        let azks = match records.pop() {
+            Some(azks @ DbRecord::Azks(_)) => azks,
+            other => {
+                return Err(StorageError::Transaction(format!(
+                    "The last record in the transaction log is NOT an Azks record {:?}",
+                    other
+                )))
+            }
+        };
 
-        // update the cache
-        if let Some(cache) = &self.cache {
-            cache.batch_put(&records).await;
+        // Write to the database
+        match expected_epoch {
+            Some(expected_epoch) => {
+                self.compare_and_set_helper(
+                    azks,
+                    Some(expected_epoch),
+                    records,
+                    DbSetState::TransactionCommit,
+                )
+                .await
+            }
+            None => {
+                records.push(azks);
+
+                // update the cache
+                if let Some(cache) = &self.cache {
+                    cache.batch_put(&records).await;
+                }
+
+                self.tic_toc(
+                    METRIC_WRITE_TIME,
+                    self.db.batch_set(records, DbSetState::TransactionCommit),
+                )
+                .await?;
+                self.increment_metric(METRIC_BATCH_SET);
+                Ok(())
+            }
         }
+    }
 
-        // Write to the database
-        self.tic_toc(
-            METRIC_WRITE_TIME,
-            self.db.batch_set(records, DbSetState::TransactionCommit),
-        )
-        .await?;
+    /// Sets a versioned record along with other records, unless the stored
+    /// version of the record isn't the expected one, see
+    /// [Database::compare_and_set]. This can't be called while a transaction is
+    /// active, since the version is checked against the data layer.
+    pub async fn compare_and_set(
+        &self,
+        record: DbRecord,
+        expected_version: Option<u64>,
+        others: Vec<DbRecord>,
+    ) -> Result<(), StorageError> {
+        if self.is_transaction_active() {
+            return Err(StorageError::TransactionInProgress);
+        }
+        self.compare_and_set_helper(record, expected_version, others, DbSetState::General)
+            .await
+    }
+
+    async fn compare_and_set_helper(
+        &self,
+        record: DbRecord,
+        expected_version: Option<u64>,
+        mut others: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        let written = self
+            .tic_toc(
+                METRIC_WRITE_TIME,
+                self.db
+                    .compare_and_set(record.clone(), expected_version, others.clone(), state),
+            )
+            .await;
+        if let Err(StorageError::Conflict(_)) = &written {
+            // another writer updated the record, so the cached records may be stale
+            if let Some(cache) = &self.cache {
+                cache.flush().await;
+            }
+        }
+        written?;
         self.increment_metric(METRIC_BATCH_SET);
+
+        // update the cache
+        if let Some(cache) = &self.cache {
+            others.push(record);
+            cache.batch_put(&others).await;
+        }
         Ok(())
     }
 
@@ -222,6 +324,11 @@ impl<Db: Database> StorageManager<Db> {
         self.transaction.is_transaction_active()
     }
 
+    /// The number of records pending in the active transaction
+    pub fn transaction_count(&self) -> usize {
+        self.transaction.count()
+    }
+
     /// Store a record in the database
     pub async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
         // we're in a transaction, set the item in the transaction
@@ -269,19 +376,48 @@ impl<Db: Database> StorageManager<Db> {
         Ok(())
     }
 
+    /// Delete a batch of records from the database and the cache. Records can't be
+    /// deleted while a transaction is active.
+    pub async fn batch_delete<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<(), StorageError> {
+        if ids.is_empty() {
+            // nothing to do, save the cycles
+            return Ok(());
+        }
+
+        if self.is_transaction_active() {
+            return Err(StorageError::TransactionInProgress);
+        }
+
+        // update the cache
+        if let Some(cache) = &self.cache {
+            cache.batch_remove::<St>(ids).await;
+        }
+
+        // delete from the database
+        self.tic_toc(METRIC_WRITE_TIME, self.db.batch_delete::<St>(ids))
+            .await
+    }
+
     /// Retrieve a stored record directly from the data layer, ignoring any caching or transaction processes
     pub async fn get_direct<St: Storable>(
         &self,
         id: &St::StorageKey,
     ) -> Result<DbRecord, StorageError> {
         // cache miss, read direct from db
-        let record = self
-            .tic_toc(METRIC_READ_TIME, self.db.get::<St>(id))
-            .await?;
+        let record = self.timed_read(self.db.get::<St>(id)).await?;
         self.increment_metric(METRIC_GET);
         Ok(record)
     }
 
+    /// Stream the records of a type whose key starts with the given prefix directly from
+    /// the data layer, ignoring any caching or transaction processes. See [Database::iter_by_prefix]
+    pub fn iter_by_prefix(&self, storage_type: StorageType, key_prefix: &[u8]) -> RecordStream<'_> {
+        self.db.iter_by_prefix(storage_type, key_prefix)
+    }
+
     /// Retrieve a stored record from the database
     pub async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
         // we're in a transaction, meaning the object _might_ be newer and therefore we should try and read if from the transaction
@@ -302,9 +438,7 @@ impl<Db: Database> StorageManager<Db> {
         // cache miss, read direct from db
         self.increment_metric(METRIC_GET);
 
-        let record = self
-            .tic_toc(METRIC_READ_TIME, self.db.get::<St>(id))
-            .await?;
+        let record = self.timed_read(self.db.get::<St>(id)).await?;
         if let Some(cache) = &self.cache {
             // cache the result
             cache.put(&record).await;
@@ -352,9 +486,7 @@ impl<Db: Database> StorageManager<Db> {
         if !key_set.is_empty() {
             // these are items to be retrieved from the backing database (not in pending transaction or in the object cache)
             let keys = key_set.into_iter().collect::<Vec<_>>();
-            let mut results = self
-                .tic_toc(METRIC_READ_TIME, self.db.batch_get::<St>(&keys))
-                .await?;
+            let mut results = self.timed_read(self.db.batch_get::<St>(&keys)).await?;
 
             // cache the db returned results
             if let Some(cache) = &self.cache {
@@ -391,6 +523,7 @@ impl<Db: Database> StorageManager<Db> {
                     plaintext_val: crate::AkdValue(crate::TOMBSTONE.to_vec()),
                     username: value_state.username,
                     version: value_state.version,
+                    expiry_epoch: value_state.expiry_epoch,
                 }));
             }
         }
@@ -410,7 +543,7 @@ impl<Db: Database> StorageManager<Db> {
         flag: ValueStateRetrievalFlag,
     ) -> Result<ValueState, StorageError> {
         let maybe_db_state = match self
-            .tic_toc(METRIC_READ_TIME, self.db.get_user_state(username, flag))
+            .timed_read(self.db.get_user_state(username, flag))
             .await
         {
             Err(StorageError::NotFound(_)) => Ok(None),
@@ -453,10 +586,7 @@ impl<Db: Database> StorageManager<Db> {
 
     /// Retrieve all values states for a given user
     pub async fn get_user_data(&self, username: &AkdLabel) -> Result<KeyData, StorageError> {
-        let maybe_db_data = match self
-            .tic_toc(METRIC_READ_TIME, self.db.get_user_data(username))
-            .await
-        {
+        let maybe_db_data = match self.timed_read(self.db.get_user_data(username)).await {
             Err(StorageError::NotFound(_)) => Ok(None),
             Ok(something) => Ok(Some(something)),
             Err(other) => Err(other),
@@ -505,10 +635,7 @@ impl<Db: Database> StorageManager<Db> {
         flag: ValueStateRetrievalFlag,
     ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
         let mut data = self
-            .tic_toc(
-                METRIC_READ_TIME,
-                self.db.get_user_state_versions(usernames, flag),
-            )
+            .timed_read(self.db.get_user_state_versions(usernames, flag))
             .await?;
         self.increment_metric(METRIC_GET_USER_STATE_VERSIONS);
 
@@ -580,6 +707,34 @@ impl<Db: Database> StorageManager<Db> {
         }
     }
 
+    /// Reads from the data layer, within the deadline of the storage manager if any
+    async fn timed_read<T>(
+        &self,
+        f: impl std::future::Future<Output = Result<T, StorageError>>,
+    ) -> Result<T, StorageError> {
+        let read = self.tic_toc(METRIC_READ_TIME, f);
+        let deadline = match self.deadline {
+            None => return read.await,
+            Some(deadline) => deadline,
+        };
+        let exceeded = || {
+            Err(StorageError::Timeout(
+                "The deadline of the read passed".to_string(),
+            ))
+        };
+        if Instant::now() >= deadline {
+            return exceeded();
+        }
+        #[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+        {
+            crate::runtime::timeout_at(deadline, read)
+                .await
+                .unwrap_or_else(exceeded)
+        }
+        #[cfg(not(any(feature = "tokio_runtime", feature = "async_std_runtime")))]
+        read.await
+    }
+
     async fn tic_toc<T>(&self, _metric: Metric, f: impl std::future::Future<Output = T>) -> T {
         #[cfg(feature = "runtime_metrics")]
         {
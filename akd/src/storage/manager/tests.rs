@@ -287,3 +287,45 @@ async fn test_storage_manager_cache_populated_by_batch_get() {
             .await
     );
 }
+
+#[tokio::test]
+async fn test_storage_manager_deadline() {
+    let db = AsyncInMemoryDatabase::new();
+    let storage_manager = StorageManager::new_no_cache(db);
+    let azks = Azks {
+        latest_epoch: 1,
+        num_nodes: 1,
+    };
+
+    let passed = storage_manager.with_deadline(std::time::Instant::now());
+    // writes aren't bound by the deadline
+    passed
+        .set(DbRecord::Azks(azks.clone()))
+        .await
+        .expect("Failed to set the azks");
+    assert!(matches!(
+        passed
+            .get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+            .await,
+        Err(StorageError::Timeout(_))
+    ));
+    assert!(matches!(
+        passed
+            .get_user_data(&AkdLabel::from_utf8_str("hello"))
+            .await,
+        Err(StorageError::Timeout(_))
+    ));
+
+    // the deadline only applies to the handle it was set on
+    let later = storage_manager
+        .with_deadline(std::time::Instant::now() + std::time::Duration::from_secs(60));
+    for storage in [&storage_manager, &later] {
+        assert_eq!(
+            DbRecord::Azks(azks.clone()),
+            storage
+                .get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+                .await
+                .expect("Failed to get the azks")
+        );
+    }
+}
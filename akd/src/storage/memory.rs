@@ -13,24 +13,75 @@ use crate::errors::StorageError;
 use crate::storage::types::{
     DbRecord, KeyData, StorageType, ValueState, ValueStateKey, ValueStateRetrievalFlag,
 };
-use crate::storage::{Database, Storable, StorageUtil};
+use crate::storage::{
+    check_version, namespaced_key, strip_namespace, Database, RecordStream, Storable, StorageUtil,
+};
+use crate::tree_node::TreeNodeWithPreviousValue;
 use crate::{AkdLabel, AkdValue};
 use async_trait::async_trait;
-use std::collections::HashMap;
+use futures_util::stream::{self, StreamExt};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
 type Epoch = u64;
 type UserValueMap = HashMap<Epoch, ValueState>;
 type UserStates = HashMap<Vec<u8>, UserValueMap>;
+type Namespace = Vec<u8>;
+
+/// The contents of an [AsyncInMemoryDatabase], as written to disk by
+/// [AsyncInMemoryDatabase::save_snapshot]
+#[cfg(feature = "memory_snapshots")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Snapshot {
+    records: Vec<(Vec<u8>, StoredRecord)>,
+    user_states: Vec<(Namespace, Vec<ValueState>)>,
+}
+
+/// A record held by an [AsyncInMemoryDatabase]. Tree nodes, which make up most
+/// of the records, are held in the compact binary encoding of
+/// [TreeNodeWithPreviousValue::to_bytes].
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "memory_snapshots",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+enum StoredRecord {
+    TreeNode(Vec<u8>),
+    Other(Box<DbRecord>),
+}
+
+impl From<DbRecord> for StoredRecord {
+    fn from(record: DbRecord) -> Self {
+        match record {
+            DbRecord::TreeNode(node) => Self::TreeNode(node.to_bytes()),
+            other => Self::Other(Box::new(other)),
+        }
+    }
+}
+
+impl StoredRecord {
+    fn decode(&self) -> Result<DbRecord, StorageError> {
+        match self {
+            Self::TreeNode(bytes) => Ok(DbRecord::TreeNode(TreeNodeWithPreviousValue::from_bytes(
+                bytes,
+            )?)),
+            Self::Other(record) => Ok(record.as_ref().clone()),
+        }
+    }
+}
 
 // ===== Basic In-Memory database ==== //
 
 /// This struct represents a basic in-memory database.
+///
+/// Handles created with [Database::with_namespace] share the
+/// underlying storage, but only see records within their own namespace.
 #[derive(Debug)]
 pub struct AsyncInMemoryDatabase {
-    db: Arc<RwLock<HashMap<Vec<u8>, DbRecord>>>,
-    user_info: Arc<RwLock<UserStates>>,
+    db: Arc<RwLock<BTreeMap<Vec<u8>, StoredRecord>>>,
+    user_info: Arc<RwLock<HashMap<Namespace, UserStates>>>,
+    namespace: Namespace,
 }
 
 unsafe impl Send for AsyncInMemoryDatabase {}
@@ -40,9 +91,130 @@ impl AsyncInMemoryDatabase {
     /// Creates a new in memory db
     pub fn new() -> Self {
         Self {
-            db: Arc::new(RwLock::new(HashMap::new())),
+            db: Arc::new(RwLock::new(BTreeMap::new())),
             user_info: Arc::new(RwLock::new(HashMap::new())),
+            namespace: vec![],
+        }
+    }
+
+    fn insert_records(
+        &self,
+        u_guard: &mut UserStates,
+        guard: &mut BTreeMap<Vec<u8>, StoredRecord>,
+        records: Vec<DbRecord>,
+    ) {
+        for record in records.into_iter() {
+            if let DbRecord::ValueState(value_state) = &record {
+                let username = value_state.username.to_vec();
+                match u_guard.get(&username) {
+                    Some(old_states) => {
+                        let mut new_states = old_states.clone();
+                        new_states.insert(value_state.epoch, value_state.clone());
+                        u_guard.insert(username, new_states);
+                    }
+                    None => {
+                        let mut new_map = HashMap::new();
+                        new_map.insert(value_state.epoch, value_state.clone());
+                        u_guard.insert(username, new_map);
+                    }
+                }
+            } else {
+                guard.insert(
+                    record.get_full_binary_id_in_namespace(&self.namespace),
+                    record.into(),
+                );
+            }
+        }
+    }
+
+    /// Writes a snapshot of the whole database (all the namespaces sharing it) to
+    /// the given file, which can be loaded back with
+    /// [AsyncInMemoryDatabase::load_snapshot], so that the database survives a
+    /// restart. The snapshot is written to a temporary file first, and then moved
+    /// over any previous snapshot, so a failed write leaves the previous one intact.
+    #[cfg(feature = "memory_snapshots")]
+    pub async fn save_snapshot<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+    ) -> Result<(), StorageError> {
+        let snapshot = {
+            let u_guard = self.user_info.read().await;
+            let guard = self.db.read().await;
+            Snapshot {
+                records: guard
+                    .iter()
+                    .map(|(key, record)| (key.clone(), record.clone()))
+                    .collect(),
+                user_states: u_guard
+                    .iter()
+                    .map(|(namespace, states)| {
+                        let states = states
+                            .values()
+                            .flat_map(|states| states.values())
+                            .cloned()
+                            .collect();
+                        (namespace.clone(), states)
+                    })
+                    .collect(),
+            }
+        };
+        let bytes = bincode::serialize(&snapshot)
+            .map_err(|err| StorageError::Other(format!("Failed to encode snapshot: {}", err)))?;
+
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, bytes)
+            .and_then(|()| std::fs::rename(&tmp_path, path))
+            .map_err(|err| {
+                StorageError::Other(format!(
+                    "Failed to write snapshot to {}: {}",
+                    path.display(),
+                    err
+                ))
+            })
+    }
+
+    /// Creates an in memory db holding the records of the snapshot written to the
+    /// given file by [AsyncInMemoryDatabase::save_snapshot], or an empty one if
+    /// there is no such file (e.g. on the first start)
+    #[cfg(feature = "memory_snapshots")]
+    pub fn load_snapshot<P: AsRef<std::path::Path>>(path: P) -> Result<Self, StorageError> {
+        let path = path.as_ref();
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(err) => {
+                return Err(StorageError::Other(format!(
+                    "Failed to read snapshot from {}: {}",
+                    path.display(),
+                    err
+                )))
+            }
+        };
+        let snapshot: Snapshot = bincode::deserialize(&bytes).map_err(|err| {
+            StorageError::Corruption(format!(
+                "Failed to decode snapshot {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+
+        let mut user_info = HashMap::<Namespace, UserStates>::new();
+        for (namespace, states) in snapshot.user_states.into_iter() {
+            let u_guard = user_info.entry(namespace).or_default();
+            for state in states.into_iter() {
+                u_guard
+                    .entry(state.username.to_vec())
+                    .or_default()
+                    .insert(state.epoch, state);
+            }
         }
+        Ok(Self {
+            db: Arc::new(RwLock::new(snapshot.records.into_iter().collect())),
+            user_info: Arc::new(RwLock::new(user_info)),
+            namespace: vec![],
+        })
     }
 
     #[cfg(test)]
@@ -63,6 +235,7 @@ impl Clone for AsyncInMemoryDatabase {
         Self {
             db: self.db.clone(),
             user_info: self.user_info.clone(),
+            namespace: self.namespace.clone(),
         }
     }
 }
@@ -71,7 +244,8 @@ impl Clone for AsyncInMemoryDatabase {
 impl Database for AsyncInMemoryDatabase {
     async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
         if let DbRecord::ValueState(value_state) = &record {
-            let mut u_guard = self.user_info.write().await;
+            let mut ns_guard = self.user_info.write().await;
+            let u_guard = ns_guard.entry(self.namespace.clone()).or_default();
             let username = value_state.username.to_vec();
             match u_guard.get(&username) {
                 Some(old_states) => {
@@ -87,7 +261,10 @@ impl Database for AsyncInMemoryDatabase {
             }
         } else {
             let mut guard = self.db.write().await;
-            guard.insert(record.get_full_binary_id(), record);
+            guard.insert(
+                record.get_full_binary_id_in_namespace(&self.namespace),
+                record.into(),
+            );
         }
 
         Ok(())
@@ -102,28 +279,33 @@ impl Database for AsyncInMemoryDatabase {
             // nothing to do, save the cycles
             return Ok(());
         }
-        let mut u_guard = self.user_info.write().await;
+        let mut ns_guard = self.user_info.write().await;
+        let u_guard = ns_guard.entry(self.namespace.clone()).or_default();
         let mut guard = self.db.write().await;
+        self.insert_records(u_guard, &mut guard, records);
+        Ok(())
+    }
 
-        for record in records.into_iter() {
-            if let DbRecord::ValueState(value_state) = &record {
-                let username = value_state.username.to_vec();
-                match u_guard.get(&username) {
-                    Some(old_states) => {
-                        let mut new_states = old_states.clone();
-                        new_states.insert(value_state.epoch, value_state.clone());
-                        u_guard.insert(username, new_states);
-                    }
-                    None => {
-                        let mut new_map = HashMap::new();
-                        new_map.insert(value_state.epoch, value_state.clone());
-                        u_guard.insert(username, new_map);
-                    }
-                }
-            } else {
-                guard.insert(record.get_full_binary_id(), record);
-            }
-        }
+    async fn compare_and_set(
+        &self,
+        record: DbRecord,
+        expected_version: Option<u64>,
+        mut others: Vec<DbRecord>,
+        _state: crate::storage::DbSetState,
+    ) -> Result<(), StorageError> {
+        // both locks are held while checking the version, so that no other write
+        // can be interleaved
+        let mut ns_guard = self.user_info.write().await;
+        let u_guard = ns_guard.entry(self.namespace.clone()).or_default();
+        let mut guard = self.db.write().await;
+        let stored = guard
+            .get(&record.get_full_binary_id_in_namespace(&self.namespace))
+            .map(StoredRecord::decode)
+            .transpose()?;
+        check_version(&record, expected_version, stored.as_ref())?;
+
+        others.push(record);
+        self.insert_records(u_guard, &mut guard, others);
         Ok(())
     }
 
@@ -134,7 +316,10 @@ impl Database for AsyncInMemoryDatabase {
         if St::data_type() == StorageType::ValueState {
             if let Ok(ValueStateKey(username, epoch)) = ValueState::key_from_full_binary(&bin_id) {
                 let u_guard = self.user_info.read().await;
-                if let Some(state) = (*u_guard).get(&username).cloned() {
+                if let Some(state) = u_guard
+                    .get(&self.namespace)
+                    .and_then(|states| states.get(&username))
+                {
                     if let Some(found) = state.get(&epoch) {
                         return Ok(DbRecord::ValueState(found.clone()));
                     }
@@ -144,8 +329,9 @@ impl Database for AsyncInMemoryDatabase {
         }
         // fallback to regular get/set db
         let guard = self.db.read().await;
-        if let Some(result) = (*guard).get(&bin_id).cloned() {
-            Ok(result)
+        let bin_id = namespaced_key(&self.namespace, bin_id);
+        if let Some(result) = (*guard).get(&bin_id) {
+            result.decode()
         } else {
             Err(StorageError::NotFound(format!(
                 "{:?} {:?}",
@@ -162,18 +348,53 @@ impl Database for AsyncInMemoryDatabase {
     ) -> Result<Vec<DbRecord>, StorageError> {
         let mut records = Vec::new();
         for key in ids.iter() {
-            if let Ok(result) = self.get::<St>(key).await {
-                records.push(result);
+            match self.get::<St>(key).await {
+                Ok(result) => records.push(result),
+                // missing records are skipped
+                Err(StorageError::NotFound(_)) => {}
+                Err(err) => return Err(err),
             }
-            // swallow errors (i.e. not found)
         }
         Ok(records)
     }
 
+    /// Delete a batch of records, skipping the ones which don't exist
+    async fn batch_delete<St: Storable>(&self, ids: &[St::StorageKey]) -> Result<(), StorageError> {
+        // value states are removed from the value state set
+        if St::data_type() == StorageType::ValueState {
+            let mut ns_guard = self.user_info.write().await;
+            if let Some(u_guard) = ns_guard.get_mut(&self.namespace) {
+                for id in ids.iter() {
+                    let bin_id = St::get_full_binary_key_id(id);
+                    if let Ok(ValueStateKey(username, epoch)) =
+                        ValueState::key_from_full_binary(&bin_id)
+                    {
+                        if let Some(states) = u_guard.get_mut(&username) {
+                            states.remove(&epoch);
+                        }
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let mut guard = self.db.write().await;
+        for id in ids.iter() {
+            guard.remove(&namespaced_key(
+                &self.namespace,
+                St::get_full_binary_key_id(id),
+            ));
+        }
+        Ok(())
+    }
+
     /// Retrieve the user data for a given user
     async fn get_user_data(&self, username: &AkdLabel) -> Result<KeyData, StorageError> {
         let guard = self.user_info.read().await;
-        if let Some(result) = guard.get(&username.0) {
+        if let Some(result) = guard
+            .get(&self.namespace)
+            .and_then(|states| states.get(&username.0))
+        {
             let mut results: Vec<ValueState> = result.values().cloned().collect::<Vec<_>>();
             // return ordered by epoch (from smallest -> largest)
             results.sort_by(|a, b| a.epoch.cmp(&b.epoch));
@@ -266,6 +487,56 @@ impl Database for AsyncInMemoryDatabase {
         }
         Ok(map)
     }
+
+    fn iter_by_prefix(&self, storage_type: StorageType, key_prefix: &[u8]) -> RecordStream<'_> {
+        let key_prefix = key_prefix.to_vec();
+        stream::once(async move {
+            let records = if storage_type == StorageType::ValueState {
+                let u_guard = self.user_info.read().await;
+                u_guard
+                    .get(&self.namespace)
+                    .into_iter()
+                    .flat_map(|states| states.iter())
+                    .filter(|(username, _)| username.starts_with(&key_prefix))
+                    .flat_map(|(_, states)| states.values().cloned())
+                    .map(|state| Ok(DbRecord::ValueState(state)))
+                    .collect::<Vec<_>>()
+            } else {
+                // the keys of the records of a type, which start with its byte, are
+                // contiguous within the namespace
+                let start = namespaced_key(&self.namespace, vec![storage_type as u8]);
+                let end = namespaced_key(&self.namespace, vec![storage_type as u8 + 1]);
+                // the label value of a tree node follows its label length in the key,
+                // so the nodes outside of the prefix are skipped without decoding them
+                let label_val_offset = start.len() + 4;
+                let guard = self.db.read().await;
+                guard
+                    .range(start..end)
+                    .filter(|(key, _)| {
+                        storage_type != StorageType::TreeNode
+                            || matches!(key.get(label_val_offset..),
+                                Some(label_val) if label_val.starts_with(&key_prefix))
+                    })
+                    .map(|(_, record)| record.decode())
+                    .filter(|record| match record {
+                        Ok(record) => record.prefix_key().starts_with(&key_prefix),
+                        Err(_) => true,
+                    })
+                    .collect::<Vec<_>>()
+            };
+            stream::iter(records)
+        })
+        .flatten()
+        .boxed()
+    }
+
+    async fn with_namespace(&self, namespace: &[u8]) -> Result<Self, StorageError> {
+        Ok(Self {
+            db: self.db.clone(),
+            user_info: self.user_info.clone(),
+            namespace: namespace.to_vec(),
+        })
+    }
 }
 
 #[async_trait]
@@ -279,6 +550,9 @@ impl StorageUtil for AsyncInMemoryDatabase {
                 DbRecord::Azks(_) => St::data_type() == StorageType::Azks,
                 DbRecord::TreeNode(_) => St::data_type() == StorageType::TreeNode,
                 DbRecord::ValueState(_) => St::data_type() == StorageType::ValueState,
+                DbRecord::TreeHead(_) => St::data_type() == StorageType::TreeHead,
+                DbRecord::RootHash(_) => St::data_type() == StorageType::RootHash,
+                DbRecord::LabelMapping(_) => St::data_type() == StorageType::LabelMapping,
             })
             .collect();
 
@@ -289,15 +563,20 @@ impl StorageUtil for AsyncInMemoryDatabase {
         // get value states
         let u_guard = self.user_info.read().await;
         let u_records = u_guard
-            .values()
+            .get(&self.namespace)
+            .into_iter()
+            .flat_map(|states| states.values())
             .cloned()
             .flat_map(|v| v.into_values())
             .map(DbRecord::ValueState);
 
-        // get other records and collect
+        // get other records (within this namespace) and collect
         let guard = self.db.read().await;
-        let records = guard.values().cloned().chain(u_records).collect();
-
-        Ok(records)
+        guard
+            .iter()
+            .filter(|(key, _)| strip_namespace(&self.namespace, key).is_some())
+            .map(|(_, record)| record.decode())
+            .chain(u_records.map(Ok))
+            .collect()
     }
 }
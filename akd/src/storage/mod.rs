@@ -12,6 +12,7 @@ use crate::storage::types::{DbRecord, StorageType};
 use crate::{AkdLabel, AkdValue};
 
 use async_trait::async_trait;
+use futures_util::stream::BoxStream;
 #[cfg(feature = "serde_serialization")]
 use serde::{de::DeserializeOwned, Serialize};
 use std::collections::HashMap;
@@ -19,6 +20,7 @@ use std::hash::Hash;
 use std::marker::{Send, Sync};
 
 pub mod cache;
+pub mod encrypted;
 pub mod transaction;
 pub mod types;
 
@@ -27,6 +29,7 @@ Various implementations supported by the library are imported here and usable at
 */
 pub mod manager;
 pub mod memory;
+pub mod tiered;
 
 pub use manager::StorageManager;
 
@@ -41,6 +44,74 @@ pub enum DbSetState {
     General,
 }
 
+/// The leading byte of a namespaced binary key. Un-namespaced keys always
+/// begin with a [StorageType] byte, which is never zero.
+pub const NAMESPACE_KEY_PREFIX: u8 = 0u8;
+
+/// Scopes a full binary key to the given namespace, which allows multiple
+/// independent directories to share a single storage backend. The empty
+/// namespace leaves the key unchanged, so that existing data remains
+/// addressable.
+pub fn namespaced_key(namespace: &[u8], key: Vec<u8>) -> Vec<u8> {
+    if namespace.is_empty() {
+        return key;
+    }
+    let mut result = Vec::with_capacity(1 + 4 + namespace.len() + key.len());
+    result.push(NAMESPACE_KEY_PREFIX);
+    result.extend_from_slice(&(namespace.len() as u32).to_be_bytes());
+    result.extend_from_slice(namespace);
+    result.extend(key);
+    result
+}
+
+/// Returns the un-namespaced portion of a full binary key if it belongs to the
+/// given namespace, or None otherwise. This is the inverse of [namespaced_key].
+pub fn strip_namespace<'a>(namespace: &[u8], bin: &'a [u8]) -> Option<&'a [u8]> {
+    match bin.first() {
+        Some(&NAMESPACE_KEY_PREFIX) if bin.len() >= 5 => {
+            let mut len_bytes = [0u8; 4];
+            len_bytes.copy_from_slice(&bin[1..5]);
+            let len = u32::from_be_bytes(len_bytes) as usize;
+            let end = 5usize.checked_add(len)?;
+            match bin.get(5..end) {
+                Some(candidate) if !namespace.is_empty() && candidate == namespace => {
+                    Some(&bin[end..])
+                }
+                _ => None,
+            }
+        }
+        Some(&NAMESPACE_KEY_PREFIX) => None,
+        _ if namespace.is_empty() => Some(bin),
+        _ => None,
+    }
+}
+
+/// Checks the version of the stored record (if any) against the version a
+/// [Database::compare_and_set] of `record` expects, for the implementations of
+/// the data layer
+pub fn check_version(
+    record: &DbRecord,
+    expected_version: Option<u64>,
+    stored: Option<&DbRecord>,
+) -> Result<(), StorageError> {
+    if record.version().is_none() {
+        return Err(StorageError::Other(format!(
+            "{:?} records aren't versioned",
+            record.data_type()
+        )));
+    }
+    let stored_version = stored.and_then(DbRecord::version);
+    if stored_version != expected_version {
+        return Err(StorageError::Conflict(format!(
+            "Expected version {:?} of the {:?} record, but version {:?} is stored",
+            expected_version,
+            record.data_type(),
+            stored_version
+        )));
+    }
+    Ok(())
+}
+
 /// Storable represents an _item_ which can be stored in the storage layer
 #[cfg(feature = "serde_serialization")]
 pub trait Storable: Clone + Serialize + DeserializeOwned + Sync {
@@ -62,6 +133,16 @@ pub trait Storable: Clone + Serialize + DeserializeOwned + Sync {
     /// Retrieve the full binary version of a key (for comparisons)
     fn get_full_binary_key_id(key: &Self::StorageKey) -> Vec<u8>;
 
+    /// Retrieve the full binary version of a key, scoped to the given namespace
+    fn get_full_binary_key_id_in_namespace(key: &Self::StorageKey, namespace: &[u8]) -> Vec<u8> {
+        namespaced_key(namespace, Self::get_full_binary_key_id(key))
+    }
+
+    /// Retrieve the full binary version of this item's key, scoped to the given namespace
+    fn get_full_binary_id_in_namespace(&self, namespace: &[u8]) -> Vec<u8> {
+        Self::get_full_binary_key_id_in_namespace(&self.get_id(), namespace)
+    }
+
     /// Reformat a key from the full-binary specification
     fn key_from_full_binary(bin: &[u8]) -> Result<Self::StorageKey, String>;
 }
@@ -87,10 +168,23 @@ pub trait Storable: Clone + Sync {
     /// Retrieve the full binary version of a key (for comparisons)
     fn get_full_binary_key_id(key: &Self::StorageKey) -> Vec<u8>;
 
+    /// Retrieve the full binary version of a key, scoped to the given namespace
+    fn get_full_binary_key_id_in_namespace(key: &Self::StorageKey, namespace: &[u8]) -> Vec<u8> {
+        namespaced_key(namespace, Self::get_full_binary_key_id(key))
+    }
+
+    /// Retrieve the full binary version of this item's key, scoped to the given namespace
+    fn get_full_binary_id_in_namespace(&self, namespace: &[u8]) -> Vec<u8> {
+        Self::get_full_binary_key_id_in_namespace(&self.get_id(), namespace)
+    }
+
     /// Reformat a key from the full-binary specification
     fn key_from_full_binary(bin: &[u8]) -> Result<Self::StorageKey, String>;
 }
 
+/// A stream of records read from the data layer, see [Database::iter_by_prefix]
+pub type RecordStream<'a> = BoxStream<'a, Result<DbRecord, StorageError>>;
+
 /// A database implementation backing storage for the AKD
 #[async_trait]
 pub trait Database: Clone + Send + Sync {
@@ -104,6 +198,20 @@ pub trait Database: Clone + Send + Sync {
         state: DbSetState,
     ) -> Result<(), StorageError>;
 
+    /// Set a versioned record (see [DbRecord::version]) along with other records,
+    /// as [Database::batch_set], only if the version of the stored record with the
+    /// same key is `expected_version`, or if no such record is stored when it's
+    /// None. The check and the writes are atomic, so that of several writers
+    /// expecting the same version only one succeeds, and the others fail with
+    /// [StorageError::Conflict] without writing anything.
+    async fn compare_and_set(
+        &self,
+        record: DbRecord,
+        expected_version: Option<u64>,
+        others: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError>;
+
     /// Retrieve a stored record from the database
     async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError>;
 
@@ -113,6 +221,10 @@ pub trait Database: Clone + Send + Sync {
         ids: &[St::StorageKey],
     ) -> Result<Vec<DbRecord>, StorageError>;
 
+    /// Delete a batch of records by id from the database, ignoring the ids which
+    /// aren't stored
+    async fn batch_delete<St: Storable>(&self, ids: &[St::StorageKey]) -> Result<(), StorageError>;
+
     /* User data searching */
 
     /// Retrieve the user data for a given user
@@ -131,6 +243,24 @@ pub trait Database: Clone + Send + Sync {
         usernames: &[AkdLabel],
         flag: types::ValueStateRetrievalFlag,
     ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError>;
+
+    /// Streams the records of the given type whose key (see [DbRecord::prefix_key])
+    /// starts with the given prefix, in no particular order. This reads directly from
+    /// the data layer, which should serve it with a range or prefix scan, so that a
+    /// subtree of the tree (or all the states of the users sharing a prefix) can be
+    /// read without knowing the labels of the records upfront.
+    fn iter_by_prefix(&self, storage_type: StorageType, key_prefix: &[u8]) -> RecordStream<'_>;
+
+    /// Creates a handle to the same storage, scoped to the given namespace: the
+    /// handle only sees the records written through handles of the same namespace,
+    /// which allows multiple independent directories to share a single storage
+    /// backend. The empty namespace is the one of the records written without
+    /// any namespace. Backends which don't support namespaces return an error.
+    async fn with_namespace(&self, _namespace: &[u8]) -> Result<Self, StorageError> {
+        Err(StorageError::Other(
+            "Namespaces are not supported by this storage backend".to_string(),
+        ))
+    }
 }
 
 /// Optional storage layer utility functions for debug and test purposes
@@ -9,13 +9,14 @@
 
 use crate::errors::StorageError;
 use crate::storage::types::*;
-use crate::storage::Database;
 use crate::storage::StorageManager;
+use crate::storage::{Database, DbSetState};
 use crate::tree_node::*;
 use crate::utils::byte_arr_from_u64;
 use crate::NodeLabel;
 use crate::{AkdLabel, AkdValue};
 
+use futures_util::StreamExt;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use std::time::{Duration, Instant};
@@ -29,6 +30,7 @@ type PvTreeNode = crate::tree_node::TreeNodeWithPreviousValue;
 #[cfg(test)]
 mod memory_storage_tests {
     use crate::storage::memory::AsyncInMemoryDatabase;
+    use crate::storage::Database;
     use serial_test::serial;
 
     #[tokio::test]
@@ -37,6 +39,300 @@ mod memory_storage_tests {
         let db = AsyncInMemoryDatabase::new();
         crate::storage::tests::run_test_cases_for_storage_impl(&db).await;
     }
+
+    // the records of a namespace are kept apart from those of the storage shared
+    // with it, including when they're read by prefix
+    #[tokio::test]
+    #[serial]
+    async fn test_namespaced_in_memory_db() {
+        let db = AsyncInMemoryDatabase::new();
+        crate::storage::tests::run_test_cases_for_storage_impl(&db).await;
+        let namespaced = db.with_namespace(b"tenant").await.unwrap();
+        crate::storage::tests::run_test_cases_for_storage_impl(&namespaced).await;
+    }
+}
+
+#[cfg(test)]
+mod tiered_storage_tests {
+    use super::Azks;
+    use crate::errors::StorageError;
+    use crate::storage::memory::AsyncInMemoryDatabase;
+    use crate::storage::tiered::TieredDatabase;
+    use crate::storage::types::{
+        DbRecord, KeyData, StorageType, ValueState, ValueStateRetrievalFlag,
+    };
+    use crate::storage::{Database, DbSetState, RecordStream, Storable};
+    use crate::{AkdLabel, AkdValue};
+    use async_trait::async_trait;
+    use serial_test::serial;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::Notify;
+
+    fn azks(latest_epoch: u64) -> DbRecord {
+        DbRecord::Azks(Azks {
+            latest_epoch,
+            num_nodes: 1,
+        })
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_tiered_db() {
+        let db = TieredDatabase::new(AsyncInMemoryDatabase::new(), AsyncInMemoryDatabase::new())
+            .with_write_behind_threshold(4);
+        crate::storage::tests::run_test_cases_for_storage_impl(&db).await;
+    }
+
+    /// An in-memory database whose writes fail while the flag is set, and whose
+    /// reads of a record, once done, wait for the read gate to be unlocked
+    #[derive(Clone)]
+    struct FailingDatabase {
+        db: AsyncInMemoryDatabase,
+        fail_writes: Arc<AtomicBool>,
+        read_done: Arc<Notify>,
+        read_gate: Arc<tokio::sync::Mutex<()>>,
+    }
+
+    impl FailingDatabase {
+        fn new() -> Self {
+            Self {
+                db: AsyncInMemoryDatabase::new(),
+                fail_writes: Arc::new(AtomicBool::new(false)),
+                read_done: Arc::new(Notify::new()),
+                read_gate: Arc::new(tokio::sync::Mutex::new(())),
+            }
+        }
+
+        fn check(&self) -> Result<(), StorageError> {
+            if self.fail_writes.load(Ordering::SeqCst) {
+                return Err(StorageError::Connection("The write failed".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Database for FailingDatabase {
+        async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+            self.check()?;
+            self.db.set(record).await
+        }
+
+        async fn batch_set(
+            &self,
+            records: Vec<DbRecord>,
+            state: DbSetState,
+        ) -> Result<(), StorageError> {
+            self.check()?;
+            self.db.batch_set(records, state).await
+        }
+
+        async fn compare_and_set(
+            &self,
+            record: DbRecord,
+            expected_version: Option<u64>,
+            others: Vec<DbRecord>,
+            state: DbSetState,
+        ) -> Result<(), StorageError> {
+            self.check()?;
+            self.db
+                .compare_and_set(record, expected_version, others, state)
+                .await
+        }
+
+        async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
+            let record = self.db.get::<St>(id).await;
+            self.read_done.notify_one();
+            let _gate = self.read_gate.lock().await;
+            record
+        }
+
+        async fn batch_get<St: Storable>(
+            &self,
+            ids: &[St::StorageKey],
+        ) -> Result<Vec<DbRecord>, StorageError> {
+            let records = self.db.batch_get::<St>(ids).await;
+            self.read_done.notify_one();
+            let _gate = self.read_gate.lock().await;
+            records
+        }
+
+        async fn batch_delete<St: Storable>(
+            &self,
+            ids: &[St::StorageKey],
+        ) -> Result<(), StorageError> {
+            self.check()?;
+            self.db.batch_delete::<St>(ids).await
+        }
+
+        async fn get_user_data(&self, username: &AkdLabel) -> Result<KeyData, StorageError> {
+            self.db.get_user_data(username).await
+        }
+
+        async fn get_user_state(
+            &self,
+            username: &AkdLabel,
+            flag: ValueStateRetrievalFlag,
+        ) -> Result<ValueState, StorageError> {
+            self.db.get_user_state(username, flag).await
+        }
+
+        async fn get_user_state_versions(
+            &self,
+            usernames: &[AkdLabel],
+            flag: ValueStateRetrievalFlag,
+        ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+            self.db.get_user_state_versions(usernames, flag).await
+        }
+
+        fn iter_by_prefix(&self, storage_type: StorageType, key_prefix: &[u8]) -> RecordStream<'_> {
+            self.db.iter_by_prefix(storage_type, key_prefix)
+        }
+    }
+
+    // A commit which the slow backend fails to write shouldn't reach the fast store,
+    // nor be written behind later, while the writes between commits are kept pending
+    // until they are written
+    #[tokio::test]
+    async fn test_tiered_db_failed_commit() {
+        let slow = FailingDatabase::new();
+        let fail_writes = slow.fail_writes.clone();
+        let db = TieredDatabase::new(AsyncInMemoryDatabase::new(), slow.clone());
+        let get_azks = |db: &TieredDatabase<AsyncInMemoryDatabase, FailingDatabase>| {
+            let db = db.clone();
+            async move {
+                db.get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+                    .await
+                    .expect("Failed to get the azks")
+            }
+        };
+
+        db.batch_set(vec![azks(1)], DbSetState::TransactionCommit)
+            .await
+            .expect("Failed to commit");
+        fail_writes.store(true, Ordering::SeqCst);
+        assert!(db
+            .batch_set(vec![azks(2)], DbSetState::TransactionCommit)
+            .await
+            .is_err());
+        assert_eq!(azks(1), get_azks(&db).await);
+        assert_eq!(0, db.pending_count());
+
+        // a write between commits is written behind, and kept pending on failure
+        let state =
+            DbRecord::build_user_state(b"user".to_vec(), b"value".to_vec(), 1, 256, [1u8; 32], 1);
+        db.set(DbRecord::ValueState(state.clone()))
+            .await
+            .expect("Failed to set the state");
+        assert_eq!(1, db.pending_count());
+        assert!(db.flush().await.is_err());
+        assert_eq!(1, db.pending_count());
+
+        fail_writes.store(false, Ordering::SeqCst);
+        db.flush().await.expect("Failed to flush");
+        assert_eq!(0, db.pending_count());
+        assert_eq!(azks(1), get_azks(&db).await);
+        assert_eq!(
+            azks(1),
+            slow.get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+                .await
+                .expect("Failed to get the azks")
+        );
+        assert_eq!(
+            state,
+            slow.get_user_state(
+                &AkdLabel::from_utf8_str("user"),
+                ValueStateRetrievalFlag::MaxEpoch
+            )
+            .await
+            .expect("Failed to get the state")
+        );
+    }
+
+    // A read-through racing with an epoch commit mustn't overwrite the committed
+    // records in the fast store with the older ones it read from the slow backend
+    #[tokio::test]
+    async fn test_tiered_db_read_through_during_commit() {
+        let slow = FailingDatabase::new();
+        slow.set(azks(1)).await.expect("Failed to set the azks");
+        let db = TieredDatabase::new(AsyncInMemoryDatabase::new(), slow.clone());
+
+        // the read-through reads the azks at epoch 1, and is held before filling the
+        // fast store
+        let gate = slow.read_gate.lock().await;
+        let reader = {
+            let db = db.clone();
+            tokio::spawn(async move {
+                db.get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+                    .await
+                    .expect("Failed to get the azks")
+            })
+        };
+        slow.read_done.notified().await;
+
+        // the commit of epoch 2 completes before the read-through fills the fast store
+        db.batch_set(vec![azks(2)], DbSetState::TransactionCommit)
+            .await
+            .expect("Failed to commit");
+        drop(gate);
+        assert_eq!(azks(1), reader.await.expect("Failed to join the reader"));
+
+        assert_eq!(
+            azks(2),
+            db.fast()
+                .get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+                .await
+                .expect("Failed to get the azks")
+        );
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod encrypted_storage_tests {
+    use crate::errors::StorageError;
+    use crate::storage::encrypted::{EncryptedDatabase, ValueEncryption};
+    use crate::storage::memory::AsyncInMemoryDatabase;
+    use async_trait::async_trait;
+    use serial_test::serial;
+
+    /// A toy encryption for the tests, XOR-ing the values with a key and
+    /// prefixing the envelope with the associated data
+    pub(crate) struct XorEncryption(pub(crate) u8);
+
+    #[async_trait]
+    impl ValueEncryption for XorEncryption {
+        async fn encrypt(
+            &self,
+            plaintext: &[u8],
+            associated_data: &[u8],
+        ) -> Result<Vec<u8>, StorageError> {
+            let mut envelope = associated_data.to_vec();
+            envelope.extend(plaintext.iter().map(|byte| byte ^ self.0));
+            Ok(envelope)
+        }
+
+        async fn decrypt(
+            &self,
+            envelope: &[u8],
+            associated_data: &[u8],
+        ) -> Result<Vec<u8>, StorageError> {
+            match envelope.strip_prefix(associated_data) {
+                Some(ciphertext) => Ok(ciphertext.iter().map(|byte| byte ^ self.0).collect()),
+                None => Err(StorageError::Encryption(
+                    "Associated data mismatch".to_string(),
+                )),
+            }
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_encrypted_db() {
+        let db = EncryptedDatabase::new(AsyncInMemoryDatabase::new(), XorEncryption(0x5a));
+        crate::storage::tests::run_test_cases_for_storage_impl(&db).await;
+    }
 }
 
 // *** Run the test cases for a given data-layer impl *** //
@@ -48,6 +344,9 @@ pub async fn run_test_cases_for_storage_impl<S: Database>(db: &S) {
     test_user_data(db).await;
     test_transactions(db).await;
     test_batch_get_items(db).await;
+    test_iter_by_prefix(db).await;
+    test_batch_delete(db).await;
+    test_compare_and_set(db).await;
 
     let manager = StorageManager::new_no_cache(db.clone());
     test_tombstoning_data(&manager).await.unwrap();
@@ -128,6 +427,7 @@ async fn test_get_and_set_item<Ns: Database>(storage: &Ns) {
         label: NodeLabel::new(byte_arr_from_u64(1), 1),
         version: 1,
         plaintext_val: AkdValue::from_utf8_str("abc123"),
+        expiry_epoch: Some(5),
     };
     let set_result = storage.set(DbRecord::ValueState(value.clone())).await;
     assert_eq!(Ok(()), set_result);
@@ -139,9 +439,104 @@ async fn test_get_and_set_item<Ns: Database>(storage: &Ns) {
         assert_eq!(got_state.label, value.label);
         assert_eq!(got_state.plaintext_val, value.plaintext_val);
         assert_eq!(got_state.version, value.version);
+        assert_eq!(got_state.expiry_epoch, value.expiry_epoch);
     } else {
         panic!("Failed to retrieve history node state");
     }
+
+    // === SignedTreeHead storage === //
+    let tree_heads = (1..=2u64)
+        .map(|epoch| {
+            DbRecord::build_tree_head(
+                epoch,
+                [epoch as u8; crate::DIGEST_BYTES],
+                1000 + epoch,
+                vec![7u8; 64],
+            )
+        })
+        .collect::<Vec<_>>();
+    let set_result = storage
+        .batch_set(
+            tree_heads.iter().cloned().map(DbRecord::TreeHead).collect(),
+            DbSetState::General,
+        )
+        .await;
+    assert_eq!(Ok(()), set_result);
+
+    let get_result = storage.get::<crate::SignedTreeHead>(&2).await;
+    assert_eq!(Ok(DbRecord::TreeHead(tree_heads[1].clone())), get_result);
+
+    let mut got = storage
+        .batch_get::<crate::SignedTreeHead>(&[1, 2, 3])
+        .await
+        .unwrap();
+    got.sort_by_key(|record| record.get_full_binary_id());
+    assert_eq!(
+        tree_heads
+            .into_iter()
+            .map(DbRecord::TreeHead)
+            .collect::<Vec<_>>(),
+        got
+    );
+
+    // === RootHashRecord storage === //
+    let root_hashes = (1..=2u64)
+        .map(|epoch| {
+            let mut record = DbRecord::build_root_hash_record(
+                epoch,
+                [epoch as u8; crate::DIGEST_BYTES],
+                1000 + epoch,
+                10 * epoch,
+            );
+            if epoch == 2 {
+                record
+                    .metadata
+                    .insert("release".to_string(), "v2".to_string());
+            }
+            record
+        })
+        .collect::<Vec<_>>();
+    let set_result = storage
+        .batch_set(
+            root_hashes
+                .iter()
+                .cloned()
+                .map(DbRecord::RootHash)
+                .collect(),
+            DbSetState::General,
+        )
+        .await;
+    assert_eq!(Ok(()), set_result);
+
+    let get_result = storage
+        .get::<crate::storage::types::RootHashRecord>(&2)
+        .await;
+    assert_eq!(Ok(DbRecord::RootHash(root_hashes[1].clone())), get_result);
+
+    let mut got = storage
+        .batch_get::<crate::storage::types::RootHashRecord>(&[1, 2, 3])
+        .await
+        .unwrap();
+    got.sort_by_key(|record| record.get_full_binary_id());
+    assert_eq!(
+        root_hashes
+            .into_iter()
+            .map(DbRecord::RootHash)
+            .collect::<Vec<_>>(),
+        got
+    );
+
+    // === LabelMappingRecord storage === //
+    let label_mapping = DbRecord::build_label_mapping_record(b"domain-separated:test".to_vec());
+    let set_result = storage
+        .set(DbRecord::LabelMapping(label_mapping.clone()))
+        .await;
+    assert_eq!(Ok(()), set_result);
+
+    let get_result = storage
+        .get::<crate::storage::types::LabelMappingRecord>(&crate::storage::types::LABEL_MAPPING_KEY)
+        .await;
+    assert_eq!(Ok(DbRecord::LabelMapping(label_mapping)), get_result);
 }
 
 async fn test_batch_get_items<Ns: Database>(storage: &Ns) {
@@ -169,6 +564,7 @@ async fn test_batch_get_items<Ns: Database>(storage: &Ns) {
                 },
                 epoch,
                 username: AkdLabel(user.clone()),
+                expiry_epoch: None,
             }));
         }
         epoch += 1;
@@ -312,6 +708,245 @@ async fn test_batch_get_items<Ns: Database>(storage: &Ns) {
     }
 }
 
+async fn test_iter_by_prefix<S: Database>(db: &S) {
+    async fn keys_with_prefix<S: Database>(
+        db: &S,
+        storage_type: StorageType,
+        key_prefix: &[u8],
+    ) -> Vec<Vec<u8>> {
+        let mut keys = db
+            .iter_by_prefix(storage_type, key_prefix)
+            .map(|record| record.unwrap().prefix_key().to_vec())
+            .collect::<Vec<_>>()
+            .await;
+        keys.sort();
+        keys
+    }
+
+    // tree nodes in and around the subtree below the bytes [0xa5, 0x5a]
+    let labels = [
+        vec![0xa5, 0x5a, 0x00],
+        vec![0xa5, 0x5a, 0xff],
+        vec![0xa5, 0x5b, 0x00],
+        vec![0xa4, 0xff, 0xff],
+    ];
+    for label in labels.iter() {
+        let mut label_val = [0u8; 32];
+        label_val[..label.len()].copy_from_slice(label);
+        let node = TreeNode {
+            label: NodeLabel::new(label_val, 256),
+            last_epoch: 1,
+            min_descendant_epoch: 1,
+            parent: NodeLabel::root(),
+            node_type: NodeType::Leaf,
+            left_child: None,
+            right_child: None,
+            hash: [0; crate::DIGEST_BYTES],
+        };
+        db.set(DbRecord::TreeNode(PvTreeNode::from_tree_node(node)))
+            .await
+            .unwrap();
+    }
+
+    let padded = |label: &Vec<u8>| {
+        let mut label_val = vec![0u8; 32];
+        label_val[..label.len()].copy_from_slice(label);
+        label_val
+    };
+    assert_eq!(
+        vec![padded(&labels[0]), padded(&labels[1])],
+        keys_with_prefix(db, StorageType::TreeNode, &[0xa5, 0x5a]).await
+    );
+    assert_eq!(
+        3,
+        keys_with_prefix(db, StorageType::TreeNode, &[0xa5])
+            .await
+            .len()
+    );
+    assert!(keys_with_prefix(db, StorageType::TreeNode, &[0xa5; 33])
+        .await
+        .is_empty());
+
+    // value states of users sharing a prefix
+    for (epoch, username) in ["prefix_test/a", "prefix_test/b", "prefix_tesu"]
+        .iter()
+        .enumerate()
+    {
+        let state = ValueState {
+            username: AkdLabel::from_utf8_str(username),
+            epoch: epoch as u64 + 1,
+            label: NodeLabel::new(byte_arr_from_u64(1), 1),
+            version: 1,
+            plaintext_val: AkdValue::from_utf8_str("value"),
+            expiry_epoch: None,
+        };
+        db.set(DbRecord::ValueState(state)).await.unwrap();
+    }
+    assert_eq!(
+        vec![b"prefix_test/a".to_vec(), b"prefix_test/b".to_vec()],
+        keys_with_prefix(db, StorageType::ValueState, b"prefix_test/").await
+    );
+
+    // the azks has an empty key
+    assert_eq!(1, keys_with_prefix(db, StorageType::Azks, &[]).await.len());
+    assert!(keys_with_prefix(db, StorageType::Azks, &[0xa5])
+        .await
+        .is_empty());
+}
+
+async fn test_batch_delete<S: Database>(db: &S) {
+    let labels = (0..3)
+        .map(|i| NodeLabel::new(byte_arr_from_u64(0xde1e7e00 + i), 256))
+        .collect::<Vec<_>>();
+    for label in labels.iter() {
+        let node = TreeNode {
+            label: *label,
+            last_epoch: 1,
+            min_descendant_epoch: 1,
+            parent: NodeLabel::root(),
+            node_type: NodeType::Leaf,
+            left_child: None,
+            right_child: None,
+            hash: [0; crate::DIGEST_BYTES],
+        };
+        db.set(DbRecord::TreeNode(PvTreeNode::from_tree_node(node)))
+            .await
+            .unwrap();
+    }
+    let state = ValueState {
+        username: AkdLabel::from_utf8_str("delete_test"),
+        epoch: 1,
+        label: labels[0],
+        version: 1,
+        plaintext_val: AkdValue::from_utf8_str("value"),
+        expiry_epoch: None,
+    };
+    db.set(DbRecord::ValueState(state)).await.unwrap();
+
+    // missing records are ignored
+    let missing = NodeKey(NodeLabel::new(byte_arr_from_u64(0xde1e7eff), 256));
+    db.batch_delete::<PvTreeNode>(&[NodeKey(labels[0]), NodeKey(labels[1]), missing])
+        .await
+        .unwrap();
+    let keys = labels
+        .iter()
+        .map(|label| NodeKey(*label))
+        .collect::<Vec<_>>();
+    let remaining = db.batch_get::<PvTreeNode>(&keys).await.unwrap();
+    assert_eq!(1, remaining.len());
+    assert!(matches!(
+        &remaining[0],
+        DbRecord::TreeNode(node) if node.label == labels[2]
+    ));
+
+    let key = ValueStateKey(b"delete_test".to_vec(), 1);
+    db.batch_delete::<ValueState>(std::slice::from_ref(&key))
+        .await
+        .unwrap();
+    assert!(matches!(
+        db.get::<ValueState>(&key).await,
+        Err(StorageError::NotFound(_))
+    ));
+}
+
+async fn test_compare_and_set<S: Database>(db: &S) {
+    let azks_key = crate::append_only_zks::DEFAULT_AZKS_KEY;
+    let epoch = match db.get::<Azks>(&azks_key).await {
+        Ok(DbRecord::Azks(azks)) => azks.latest_epoch,
+        _ => {
+            let azks = Azks {
+                latest_epoch: 0,
+                num_nodes: 1,
+            };
+            assert_eq!(
+                Ok(()),
+                db.compare_and_set(DbRecord::Azks(azks), None, vec![], DbSetState::General)
+                    .await
+            );
+            0
+        }
+    };
+    let azks_at = |latest_epoch| {
+        DbRecord::Azks(Azks {
+            latest_epoch,
+            num_nodes: 1,
+        })
+    };
+    let state_at = |epoch| {
+        DbRecord::ValueState(ValueState {
+            username: AkdLabel::from_utf8_str("cas_test"),
+            epoch,
+            label: NodeLabel::new(byte_arr_from_u64(epoch), 256),
+            version: 1,
+            plaintext_val: AkdValue::from_utf8_str("value"),
+            expiry_epoch: None,
+        })
+    };
+
+    // a stale version is rejected without writing anything
+    for expected_version in [None, Some(epoch + 1)] {
+        assert!(matches!(
+            db.compare_and_set(
+                azks_at(epoch + 1),
+                expected_version,
+                vec![state_at(epoch + 1)],
+                DbSetState::General
+            )
+            .await,
+            Err(StorageError::Conflict(_))
+        ));
+    }
+    assert!(matches!(
+        db.get::<ValueState>(&ValueStateKey(b"cas_test".to_vec(), epoch + 1))
+            .await,
+        Err(StorageError::NotFound(_))
+    ));
+
+    assert_eq!(
+        Ok(()),
+        db.compare_and_set(
+            azks_at(epoch + 1),
+            Some(epoch),
+            vec![state_at(epoch + 1)],
+            DbSetState::General
+        )
+        .await
+    );
+    assert_eq!(Ok(azks_at(epoch + 1)), db.get::<Azks>(&azks_key).await);
+    assert_eq!(
+        Ok(state_at(epoch + 1)),
+        db.get::<ValueState>(&ValueStateKey(b"cas_test".to_vec(), epoch + 1))
+            .await
+    );
+
+    // of two writers having read the same epoch, the second one to commit fails
+    let manager = StorageManager::new_no_cache(db.clone());
+    assert!(manager.begin_transaction());
+    manager.set(state_at(epoch + 2)).await.unwrap();
+    manager.set(azks_at(epoch + 2)).await.unwrap();
+    assert!(matches!(
+        manager.commit_transaction_if_epoch(epoch).await,
+        Err(StorageError::Conflict(_))
+    ));
+    assert!(matches!(
+        db.get::<ValueState>(&ValueStateKey(b"cas_test".to_vec(), epoch + 2))
+            .await,
+        Err(StorageError::NotFound(_))
+    ));
+    assert!(manager.begin_transaction());
+    manager.set(state_at(epoch + 2)).await.unwrap();
+    manager.set(azks_at(epoch + 2)).await.unwrap();
+    assert_eq!(Ok(()), manager.commit_transaction_if_epoch(epoch + 1).await);
+    assert_eq!(Ok(azks_at(epoch + 2)), db.get::<Azks>(&azks_key).await);
+
+    // only versioned records can be compare-and-set
+    assert!(matches!(
+        db.compare_and_set(state_at(epoch + 3), None, vec![], DbSetState::General)
+            .await,
+        Err(StorageError::Other(_))
+    ));
+}
+
 async fn test_transactions<S: Database>(db: &S) {
     let storage = crate::storage::manager::StorageManager::new_no_cache(db.clone());
 
@@ -339,6 +974,7 @@ async fn test_transactions<S: Database>(db: &S) {
                 },
                 epoch,
                 username: AkdLabel(user.clone()),
+                expiry_epoch: None,
             }));
         }
         epoch += 1;
@@ -418,6 +1054,7 @@ async fn test_user_data<S: Database>(storage: &S) {
         },
         epoch: 1u64,
         username: AkdLabel(rand_user),
+        expiry_epoch: None,
     };
     let mut sample_state_2 = sample_state.clone();
     sample_state_2.username = AkdLabel::from_utf8_str("test_user");
@@ -491,6 +1128,7 @@ async fn test_user_data<S: Database>(storage: &S) {
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             plaintext_val: AkdValue(rand_value.clone()),
             username: sample_state.username.clone(),
+            expiry_epoch: None,
         }),
         specific_result
     );
@@ -506,6 +1144,7 @@ async fn test_user_data<S: Database>(storage: &S) {
                 label: NodeLabel::new(byte_arr_from_u64(1), 1),
                 plaintext_val: AkdValue(rand_value.clone()),
                 username: sample_state.username.clone(),
+                expiry_epoch: None,
             },
             state
         );
@@ -534,6 +1173,7 @@ async fn test_user_data<S: Database>(storage: &S) {
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             plaintext_val: AkdValue(rand_value.clone()),
             username: sample_state.username.clone(),
+            expiry_epoch: None,
         }),
         specific_result
     );
@@ -548,6 +1188,7 @@ async fn test_user_data<S: Database>(storage: &S) {
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             plaintext_val: AkdValue(rand_value.clone()),
             username: sample_state.username.clone(),
+            expiry_epoch: None,
         }),
         specific_result
     );
@@ -562,6 +1203,7 @@ async fn test_user_data<S: Database>(storage: &S) {
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             plaintext_val: AkdValue(rand_value.clone()),
             username: sample_state.username.clone(),
+            expiry_epoch: None,
         }),
         specific_result
     );
@@ -614,6 +1256,7 @@ async fn test_tombstoning_data<S: Database>(
         },
         epoch: 1u64,
         username: AkdLabel(rand_user.clone()),
+        expiry_epoch: None,
     };
     let mut sample_state2 = sample_state.clone();
     sample_state2.username = AkdLabel::from_utf8_str("tombstone_test_user");
@@ -0,0 +1,454 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! A two-tier database, fronting a slow durable backend (e.g. MySQL) with a fast
+//! local store (e.g. in-memory).
+//!
+//! Records are read through the fast store: a record missing from it is read from
+//! the slow backend, and then kept in the fast store, unless the fast store was
+//! written to in the meantime, as the record read may then be older than the one
+//! written (e.g. by an epoch commit racing with a lookup). Writes only go to the fast
+//! store in-line, and are written behind to the slow backend in batches. Every
+//! epoch commit (the commit of a transaction, or a write of the [Azks] record) is a
+//! durability barrier: all the pending writes are written to the slow backend, and
+//! then the commit is written through to it, before the commit completes, so that
+//! the slow backend always holds every committed epoch. The fast store is only
+//! updated once the slow backend holds the commit, so that a failed commit leaves
+//! no trace in it, and if updating it fails, it's emptied, to be populated again
+//! from the slow backend.
+//!
+//! The queries on user data, along with the prefix scans and deletions, need a
+//! complete view of the records, so they first write the pending records to the slow
+//! backend, and are then served by it.
+//!
+//! A compare-and-set is checked against the slow backend, which is the one shared
+//! with other writers. If it fails with a conflict, another writer has committed an
+//! epoch the fast store doesn't know of, so the fast store is emptied, to be
+//! populated again from the slow backend.
+//!
+//! The [Azks] record, which holds the latest epoch, is always read from the slow
+//! backend, so that a directory (e.g. a read-only one polling for changes) sees the
+//! epochs committed by other writers. If it differs from the one in the fast store,
+//! another writer committed an epoch, whose records the fast store may hold older
+//! versions of, so the fast store is emptied as well.
+//!
+//! [Azks]: crate::append_only_zks::Azks
+
+use crate::append_only_zks::Azks;
+use crate::errors::StorageError;
+use crate::storage::types::{
+    DbRecord, KeyData, LabelMappingRecord, RootHashRecord, StorageType, ValueState,
+    ValueStateRetrievalFlag,
+};
+use crate::storage::{Database, DbSetState, RecordStream, Storable, StorageUtil};
+use crate::tree_node::TreeNodeWithPreviousValue;
+use crate::{AkdLabel, AkdValue, SignedTreeHead};
+
+use crate::logging::debug;
+use async_trait::async_trait;
+use futures_util::stream::{self, StreamExt};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// Write the pending records to the slow backend in the background once there are
+/// this many of them by default
+pub const DEFAULT_WRITE_BEHIND_THRESHOLD: usize = 10_000;
+
+/// A [Database] fronting a slow backend with a fast local store, see the module
+/// documentation
+pub struct TieredDatabase<Fast: Database, Slow: Database> {
+    fast: Fast,
+    slow: Slow,
+    /// The records written to the fast store but not yet to the slow backend, in
+    /// the order they were written
+    pending: Arc<Mutex<Vec<DbRecord>>>,
+    /// Serializes the writes to the slow backend, so that they are applied in order
+    flush_lock: Arc<tokio::sync::Mutex<()>>,
+    /// The number of writes to the fast store, locked while writing to it, so that
+    /// a read-through only keeps the records it read if nothing was written since
+    fast_writes: Arc<tokio::sync::Mutex<u64>>,
+    write_behind_threshold: usize,
+}
+
+impl<Fast: Database, Slow: Database> Clone for TieredDatabase<Fast, Slow> {
+    fn clone(&self) -> Self {
+        Self {
+            fast: self.fast.clone(),
+            slow: self.slow.clone(),
+            pending: self.pending.clone(),
+            flush_lock: self.flush_lock.clone(),
+            fast_writes: self.fast_writes.clone(),
+            write_behind_threshold: self.write_behind_threshold,
+        }
+    }
+}
+
+impl<Fast: Database + 'static, Slow: Database + 'static> TieredDatabase<Fast, Slow> {
+    /// Creates a tiered database, given an empty fast store and the slow backend
+    pub fn new(fast: Fast, slow: Slow) -> Self {
+        Self {
+            fast,
+            slow,
+            pending: Arc::new(Mutex::new(Vec::new())),
+            flush_lock: Arc::new(tokio::sync::Mutex::new(())),
+            fast_writes: Arc::new(tokio::sync::Mutex::new(0)),
+            write_behind_threshold: DEFAULT_WRITE_BEHIND_THRESHOLD,
+        }
+    }
+
+    /// Sets the number of pending records at which they are written to the slow
+    /// backend in the background, ahead of the next epoch commit. Writing behind
+    /// in the background requires the `tokio_runtime` or `async_std_runtime` feature,
+    /// without which the
+    /// pending records are only written at the epoch commits.
+    pub fn with_write_behind_threshold(mut self, threshold: usize) -> Self {
+        self.write_behind_threshold = threshold;
+        self
+    }
+
+    /// The fast local store
+    pub fn fast(&self) -> &Fast {
+        &self.fast
+    }
+
+    /// The slow backend
+    pub fn slow(&self) -> &Slow {
+        &self.slow
+    }
+
+    /// The number of records not yet written to the slow backend
+    pub fn pending_count(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Writes all the pending records to the slow backend. On failure, the records
+    /// are kept pending, to be written by the next flush.
+    pub async fn flush(&self) -> Result<(), StorageError> {
+        let _guard = self.flush_lock.lock().await;
+        self.flush_locked().await
+    }
+
+    /// Writes all the pending records to the slow backend, the flush lock being held
+    async fn flush_locked(&self) -> Result<(), StorageError> {
+        let records = std::mem::take(&mut *self.pending.lock().unwrap());
+        if records.is_empty() {
+            return Ok(());
+        }
+        let num_records = records.len();
+        if let Err(err) = self
+            .slow
+            .batch_set(records.clone(), DbSetState::General)
+            .await
+        {
+            // put the records back ahead of the ones written in the meantime
+            let mut pending = self.pending.lock().unwrap();
+            let newer = std::mem::replace(&mut *pending, records);
+            pending.extend(newer);
+            return Err(err);
+        }
+        debug!("Wrote {} records behind to the slow backend", num_records);
+        Ok(())
+    }
+
+    fn enqueue(&self, records: &[DbRecord]) {
+        let num_pending = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.extend(records.iter().cloned());
+            pending.len()
+        };
+        #[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+        if num_pending >= self.write_behind_threshold {
+            let db = self.clone();
+            // the flush completes in the background, and any failure is retried by
+            // the next flush
+            crate::runtime::spawn(async move {
+                if let Err(err) = db.flush().await {
+                    crate::logging::warn!("Background write to the slow backend failed: {}", err);
+                }
+            })
+            .detach();
+        }
+        #[cfg(not(any(feature = "tokio_runtime", feature = "async_std_runtime")))]
+        let _ = num_pending;
+    }
+
+    /// Removes all the records from the fast store
+    async fn evict_fast(&self) -> Result<(), StorageError> {
+        let mut fast_writes = self.fast_writes.lock().await;
+        *fast_writes += 1;
+        self.evict_fast_locked().await
+    }
+
+    /// Removes all the records from the fast store, the fast store being locked
+    async fn evict_fast_locked(&self) -> Result<(), StorageError> {
+        self.evict_fast_type::<Azks>().await?;
+        self.evict_fast_type::<TreeNodeWithPreviousValue>().await?;
+        self.evict_fast_type::<ValueState>().await?;
+        self.evict_fast_type::<SignedTreeHead>().await?;
+        self.evict_fast_type::<RootHashRecord>().await?;
+        self.evict_fast_type::<LabelMappingRecord>().await
+    }
+
+    async fn evict_fast_type<St: Storable>(&self) -> Result<(), StorageError> {
+        let mut ids = vec![];
+        let mut records = self.fast.iter_by_prefix(St::data_type(), &[]);
+        while let Some(record) = records.next().await {
+            let id = St::key_from_full_binary(&record?.get_full_binary_id())
+                .map_err(StorageError::Corruption)?;
+            ids.push(id);
+        }
+        drop(records);
+        self.fast.batch_delete::<St>(&ids).await
+    }
+
+    async fn write(&self, records: Vec<DbRecord>, state: DbSetState) -> Result<(), StorageError> {
+        let barrier = matches!(state, DbSetState::TransactionCommit)
+            || records
+                .iter()
+                .any(|record| matches!(record, DbRecord::Azks(_)));
+        if !barrier {
+            {
+                let mut fast_writes = self.fast_writes.lock().await;
+                *fast_writes += 1;
+                self.fast.batch_set(records.clone(), state).await?;
+            }
+            self.enqueue(&records);
+            return Ok(());
+        }
+
+        {
+            // the commit is written after the pending records, and before any record
+            // written behind in the meantime
+            let _guard = self.flush_lock.lock().await;
+            self.flush_locked().await?;
+            self.slow.batch_set(records.clone(), state).await?;
+        }
+        self.write_fast(records).await
+    }
+
+    /// Writes records already held by the slow backend to the fast store, emptying
+    /// the fast store if that fails, so that it doesn't serve stale records
+    async fn write_fast(&self, records: Vec<DbRecord>) -> Result<(), StorageError> {
+        let mut fast_writes = self.fast_writes.lock().await;
+        *fast_writes += 1;
+        if let Err(err) = self.fast.batch_set(records, DbSetState::General).await {
+            crate::logging::warn!(
+                "Failed to write to the fast store, which is emptied: {}",
+                err
+            );
+            self.evict_fast_locked().await?;
+        }
+        Ok(())
+    }
+
+    /// The number of writes to the fast store so far, to be given to
+    /// [TieredDatabase::fill_fast] by a read-through started afterwards
+    async fn fast_writes(&self) -> u64 {
+        *self.fast_writes.lock().await
+    }
+
+    /// Keeps the records read through from the slow backend in the fast store,
+    /// unless the fast store was written to since the read-through started, in
+    /// which case they may be older than the records written
+    async fn fill_fast(
+        &self,
+        records: Vec<DbRecord>,
+        fast_writes: u64,
+    ) -> Result<(), StorageError> {
+        let current = self.fast_writes.lock().await;
+        if *current != fast_writes || records.is_empty() {
+            return Ok(());
+        }
+        self.fast.batch_set(records, DbSetState::General).await
+    }
+
+    /// Reads [Azks] records from the slow backend, emptying the fast store if they
+    /// differ from the ones it holds, as another writer then committed an epoch. As
+    /// with [TieredDatabase::fill_fast], the fast store is left alone if it was
+    /// written to since the read started, which an epoch commit of this writer does.
+    async fn read_azks<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        let fast_writes = self.fast_writes().await;
+        let records = self.slow.batch_get::<St>(ids).await?;
+        let cached = self.fast.batch_get::<St>(ids).await?;
+        if records.iter().all(|record| cached.contains(record)) {
+            return Ok(records);
+        }
+        let mut current = self.fast_writes.lock().await;
+        if *current == fast_writes {
+            debug!("Another writer committed an epoch, emptying the fast store");
+            *current += 1;
+            self.evict_fast_locked().await?;
+            self.fast
+                .batch_set(records.clone(), DbSetState::General)
+                .await?;
+        }
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl<Fast: Database + 'static, Slow: Database + 'static> Database for TieredDatabase<Fast, Slow> {
+    async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+        self.write(vec![record], DbSetState::General).await
+    }
+
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        self.write(records, state).await
+    }
+
+    async fn compare_and_set(
+        &self,
+        record: DbRecord,
+        expected_version: Option<u64>,
+        others: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        // the slow backend is the one shared with other writers, so the version is
+        // checked there, and the records are written through rather than behind
+        let written = {
+            let _guard = self.flush_lock.lock().await;
+            self.flush_locked().await?;
+            self.slow
+                .compare_and_set(record.clone(), expected_version, others.clone(), state)
+                .await
+        };
+        if let Err(StorageError::Conflict(_)) = &written {
+            // the records of the epoch committed by the other writer are read
+            // through again
+            self.evict_fast().await?;
+        }
+        written?;
+        let mut records = others;
+        records.push(record);
+        self.write_fast(records).await
+    }
+
+    async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
+        if St::data_type() == StorageType::Azks {
+            return match self.read_azks::<St>(std::slice::from_ref(id)).await?.pop() {
+                Some(record) => Ok(record),
+                // not stored, for the slow backend to report
+                None => self.slow.get::<St>(id).await,
+            };
+        }
+        let fast_writes = self.fast_writes().await;
+        match self.fast.get::<St>(id).await {
+            Err(StorageError::NotFound(_)) => {
+                let record = self.slow.get::<St>(id).await?;
+                self.fill_fast(vec![record.clone()], fast_writes).await?;
+                Ok(record)
+            }
+            other => other,
+        }
+    }
+
+    async fn batch_get<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        if St::data_type() == StorageType::Azks {
+            return self.read_azks::<St>(ids).await;
+        }
+        let fast_writes = self.fast_writes().await;
+        let mut records = self.fast.batch_get::<St>(ids).await?;
+        if records.len() < ids.len() {
+            let found = records
+                .iter()
+                .map(|record| record.get_full_binary_id())
+                .collect::<HashSet<_>>();
+            let missing = ids
+                .iter()
+                .filter(|id| !found.contains(&St::get_full_binary_key_id(id)))
+                .cloned()
+                .collect::<Vec<_>>();
+            let read_through = self.slow.batch_get::<St>(&missing).await?;
+            self.fill_fast(read_through.clone(), fast_writes).await?;
+            records.extend(read_through);
+        }
+        Ok(records)
+    }
+
+    async fn batch_delete<St: Storable>(&self, ids: &[St::StorageKey]) -> Result<(), StorageError> {
+        self.flush().await?;
+        {
+            let mut fast_writes = self.fast_writes.lock().await;
+            *fast_writes += 1;
+            self.fast.batch_delete::<St>(ids).await?;
+        }
+        self.slow.batch_delete::<St>(ids).await
+    }
+
+    async fn get_user_data(&self, username: &AkdLabel) -> Result<KeyData, StorageError> {
+        self.flush().await?;
+        self.slow.get_user_data(username).await
+    }
+
+    async fn get_user_state(
+        &self,
+        username: &AkdLabel,
+        flag: ValueStateRetrievalFlag,
+    ) -> Result<ValueState, StorageError> {
+        self.flush().await?;
+        self.slow.get_user_state(username, flag).await
+    }
+
+    async fn get_user_state_versions(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+    ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        self.flush().await?;
+        self.slow.get_user_state_versions(usernames, flag).await
+    }
+
+    fn iter_by_prefix(&self, storage_type: StorageType, key_prefix: &[u8]) -> RecordStream<'_> {
+        let key_prefix = key_prefix.to_vec();
+        stream::once(async move {
+            match self.flush().await {
+                Ok(()) => self.slow.iter_by_prefix(storage_type, &key_prefix),
+                Err(err) => stream::iter(vec![Err(err)]).boxed(),
+            }
+        })
+        .flatten()
+        .boxed()
+    }
+
+    /// The namespaced handle has its own pending records, as they are written to
+    /// the namespace of the slow backend they were read from
+    async fn with_namespace(&self, namespace: &[u8]) -> Result<Self, StorageError> {
+        Ok(Self {
+            fast: self.fast.with_namespace(namespace).await?,
+            slow: self.slow.with_namespace(namespace).await?,
+            pending: Arc::new(Mutex::new(Vec::new())),
+            flush_lock: Arc::new(tokio::sync::Mutex::new(())),
+            fast_writes: Arc::new(tokio::sync::Mutex::new(0)),
+            write_behind_threshold: self.write_behind_threshold,
+        })
+    }
+}
+
+#[async_trait]
+impl<Fast: Database + 'static, Slow: StorageUtil + 'static> StorageUtil
+    for TieredDatabase<Fast, Slow>
+{
+    async fn batch_get_type_direct<St: Storable>(&self) -> Result<Vec<DbRecord>, StorageError> {
+        self.flush().await?;
+        self.slow.batch_get_type_direct::<St>().await
+    }
+
+    async fn batch_get_all_direct(&self) -> Result<Vec<DbRecord>, StorageError> {
+        self.flush().await?;
+        self.slow.batch_get_all_direct().await
+    }
+}
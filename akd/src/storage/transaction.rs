@@ -13,8 +13,8 @@ use crate::storage::types::ValueState;
 use crate::storage::types::ValueStateRetrievalFlag;
 use crate::storage::Storable;
 
+use crate::logging::{debug, error, info, trace, warn};
 use dashmap::DashMap;
-use log::{debug, error, info, trace, warn};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
@@ -314,6 +314,7 @@ mod tests {
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             version: 1,
             plaintext_val: AkdValue::from_utf8_str("abc123"),
+            expiry_epoch: None,
         });
         let value2 = DbRecord::ValueState(ValueState {
             username: AkdLabel::from_utf8_str("test"),
@@ -321,6 +322,7 @@ mod tests {
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             version: 2,
             plaintext_val: AkdValue::from_utf8_str("abc1234"),
+            expiry_epoch: None,
         });
 
         let records = vec![azks, node1, node2, value1, value2];
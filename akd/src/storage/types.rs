@@ -9,8 +9,9 @@
 
 use crate::storage::Storable;
 use crate::tree_node::{NodeType, TreeNode, TreeNodeWithPreviousValue};
-use crate::{AkdLabel, AkdValue};
+use crate::{AkdLabel, AkdValue, SignedTreeHead};
 use crate::{Azks, NodeLabel};
+use std::collections::BTreeMap;
 use std::convert::TryInto;
 
 /// Various elements that can be stored
@@ -24,6 +25,12 @@ pub enum StorageType {
     /// Better to keep ValueState = 4 as is?
     /// ValueState
     ValueState = 4,
+    /// SignedTreeHead
+    TreeHead = 5,
+    /// RootHashRecord
+    RootHash = 6,
+    /// LabelMappingRecord
+    LabelMapping = 7,
 }
 
 /// State for a value at a given version for that key
@@ -52,6 +59,9 @@ pub struct ValueState {
     pub epoch: u64,
     /// The username associated to this value state (username + epoch is the record key)
     pub username: AkdLabel,
+    /// The epoch at which the value expires, if it was published with one
+    #[cfg_attr(feature = "serde_serialization", serde(default))]
+    pub expiry_epoch: Option<u64>,
 }
 
 impl akd_core::SizeOf for ValueState {
@@ -61,6 +71,7 @@ impl akd_core::SizeOf for ValueState {
             + self.label.size_of()
             + std::mem::size_of::<u64>()
             + self.username.size_of()
+            + std::mem::size_of::<Option<u64>>()
     }
 }
 
@@ -98,6 +109,209 @@ impl crate::storage::Storable for ValueState {
     }
 }
 
+impl crate::storage::Storable for SignedTreeHead {
+    type StorageKey = u64;
+
+    fn data_type() -> StorageType {
+        StorageType::TreeHead
+    }
+
+    fn get_id(&self) -> u64 {
+        self.epoch
+    }
+
+    fn get_full_binary_key_id(key: &u64) -> Vec<u8> {
+        let mut result = vec![StorageType::TreeHead as u8];
+        result.extend_from_slice(&key.to_be_bytes());
+        result
+    }
+
+    fn key_from_full_binary(bin: &[u8]) -> Result<u64, String> {
+        if bin.len() != 9 {
+            return Err("Not the right number of bytes to form a tree head key".to_string());
+        }
+
+        if bin[0] != StorageType::TreeHead as u8 {
+            return Err("Not a tree head key".to_string());
+        }
+
+        let epoch_bytes: [u8; 8] = bin[1..].try_into().expect("Slice with incorrect length");
+        Ok(u64::from_be_bytes(epoch_bytes))
+    }
+}
+
+/// The archived root hash of an epoch, along with when and how it was
+/// published. One record is written per epoch, so the root hash of any past
+/// epoch can be retrieved without reconstructing the tree at that epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct RootHashRecord {
+    /// The epoch published
+    pub epoch: u64,
+    /// The root hash of the tree at the epoch
+    #[cfg_attr(
+        feature = "serde_serialization",
+        serde(
+            serialize_with = "akd_core::utils::serde_helpers::bytes_serialize_hex",
+            deserialize_with = "akd_core::utils::serde_helpers::bytes_deserialize_hex"
+        )
+    )]
+    pub root_hash: crate::Digest,
+    /// When the epoch was published, in seconds since the UNIX epoch
+    pub timestamp: u64,
+    /// The number of value states published in the epoch
+    pub batch_size: u64,
+    /// The metadata the operator attached to the epoch when publishing it (e.g.
+    /// a release tag, a change ticket or the identity of the publisher)
+    #[cfg_attr(feature = "serde_serialization", serde(default))]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl RootHashRecord {
+    /// Serializes the metadata of the epoch, as a sequence of length-prefixed
+    /// keys and values
+    pub fn metadata_to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![];
+        for (key, value) in self.metadata.iter() {
+            for part in [key, value] {
+                result.extend_from_slice(&(part.len() as u32).to_be_bytes());
+                result.extend_from_slice(part.as_bytes());
+            }
+        }
+        result
+    }
+
+    /// Deserializes the metadata of an epoch serialized with
+    /// [RootHashRecord::metadata_to_bytes]
+    pub fn metadata_from_bytes(bytes: &[u8]) -> Result<BTreeMap<String, String>, String> {
+        let mut parts = vec![];
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            if rest.len() < 4 {
+                return Err("Truncated epoch metadata length".to_string());
+            }
+            let (len, tail) = rest.split_at(4);
+            let len = u32::from_be_bytes(len.try_into().expect("Slice with incorrect length"));
+            if tail.len() < len as usize {
+                return Err("Truncated epoch metadata entry".to_string());
+            }
+            let (part, tail) = tail.split_at(len as usize);
+            parts.push(
+                String::from_utf8(part.to_vec())
+                    .map_err(|_| "Epoch metadata is not valid UTF-8".to_string())?,
+            );
+            rest = tail;
+        }
+        if parts.len() % 2 != 0 {
+            return Err("Epoch metadata key without a value".to_string());
+        }
+        let mut metadata = BTreeMap::new();
+        let mut parts = parts.into_iter();
+        while let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+            metadata.insert(key, value);
+        }
+        Ok(metadata)
+    }
+}
+
+impl akd_core::SizeOf for RootHashRecord {
+    fn size_of(&self) -> usize {
+        std::mem::size_of::<u64>() * 3
+            + self.root_hash.len()
+            + self
+                .metadata
+                .iter()
+                .map(|(key, value)| key.len() + value.len())
+                .sum::<usize>()
+    }
+}
+
+impl crate::storage::Storable for RootHashRecord {
+    type StorageKey = u64;
+
+    fn data_type() -> StorageType {
+        StorageType::RootHash
+    }
+
+    fn get_id(&self) -> u64 {
+        self.epoch
+    }
+
+    fn get_full_binary_key_id(key: &u64) -> Vec<u8> {
+        let mut result = vec![StorageType::RootHash as u8];
+        result.extend_from_slice(&key.to_be_bytes());
+        result
+    }
+
+    fn key_from_full_binary(bin: &[u8]) -> Result<u64, String> {
+        if bin.len() != 9 {
+            return Err("Not the right number of bytes to form a root hash key".to_string());
+        }
+
+        if bin[0] != StorageType::RootHash as u8 {
+            return Err("Not a root hash key".to_string());
+        }
+
+        let epoch_bytes: [u8; 8] = bin[1..].try_into().expect("Slice with incorrect length");
+        Ok(u64::from_be_bytes(epoch_bytes))
+    }
+}
+
+/// The key of the (single) [LabelMappingRecord] of a directory
+pub const LABEL_MAPPING_KEY: u8 = 1u8;
+
+/// The id of the [akd_core::label_mapper::LabelMapper] a directory was created
+/// with, checked whenever the directory is opened, see
+/// [crate::Directory::new_with_label_mapper]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct LabelMappingRecord {
+    /// The id of the label mapper
+    #[cfg_attr(
+        feature = "serde_serialization",
+        serde(
+            serialize_with = "akd_core::utils::serde_helpers::bytes_serialize_hex",
+            deserialize_with = "akd_core::utils::serde_helpers::bytes_deserialize_hex"
+        )
+    )]
+    pub mapper_id: Vec<u8>,
+}
+
+impl akd_core::SizeOf for LabelMappingRecord {
+    fn size_of(&self) -> usize {
+        self.mapper_id.len()
+    }
+}
+
+impl crate::storage::Storable for LabelMappingRecord {
+    type StorageKey = u8;
+
+    fn data_type() -> StorageType {
+        StorageType::LabelMapping
+    }
+
+    fn get_id(&self) -> u8 {
+        LABEL_MAPPING_KEY
+    }
+
+    fn get_full_binary_key_id(key: &u8) -> Vec<u8> {
+        vec![StorageType::LabelMapping as u8, *key]
+    }
+
+    fn key_from_full_binary(bin: &[u8]) -> Result<u8, String> {
+        if bin.is_empty() || bin[0] != StorageType::LabelMapping as u8 {
+            return Err("Not a label mapping key".to_string());
+        }
+        Ok(LABEL_MAPPING_KEY)
+    }
+}
+
 impl ValueState {
     pub(crate) fn new(
         username: AkdLabel,
@@ -112,6 +326,7 @@ impl ValueState {
             label,
             epoch,
             username,
+            expiry_epoch: None,
         }
     }
 }
@@ -160,6 +375,12 @@ pub enum DbRecord {
     TreeNode(TreeNodeWithPreviousValue),
     /// The state of the value for a particular key.
     ValueState(ValueState),
+    /// The signed summary of an epoch
+    TreeHead(SignedTreeHead),
+    /// The archived root hash of an epoch
+    RootHash(RootHashRecord),
+    /// The label mapper the directory was created with
+    LabelMapping(LabelMappingRecord),
 }
 
 impl akd_core::SizeOf for DbRecord {
@@ -168,6 +389,9 @@ impl akd_core::SizeOf for DbRecord {
             DbRecord::Azks(azks) => azks.size_of(),
             DbRecord::TreeNode(node) => node.size_of(),
             DbRecord::ValueState(state) => state.size_of(),
+            DbRecord::TreeHead(tree_head) => tree_head.size_of(),
+            DbRecord::RootHash(record) => record.size_of(),
+            DbRecord::LabelMapping(record) => record.size_of(),
         }
     }
 }
@@ -178,6 +402,9 @@ impl Clone for DbRecord {
             DbRecord::Azks(azks) => DbRecord::Azks(azks.clone()),
             DbRecord::TreeNode(node) => DbRecord::TreeNode(node.clone()),
             DbRecord::ValueState(state) => DbRecord::ValueState(state.clone()),
+            DbRecord::TreeHead(tree_head) => DbRecord::TreeHead(tree_head.clone()),
+            DbRecord::RootHash(record) => DbRecord::RootHash(record.clone()),
+            DbRecord::LabelMapping(record) => DbRecord::LabelMapping(record.clone()),
         }
     }
 }
@@ -190,9 +417,53 @@ impl DbRecord {
             DbRecord::Azks(azks) => azks.get_full_binary_id(),
             DbRecord::TreeNode(node) => node.get_full_binary_id(),
             DbRecord::ValueState(state) => state.get_full_binary_id(),
+            DbRecord::TreeHead(tree_head) => tree_head.get_full_binary_id(),
+            DbRecord::RootHash(record) => record.get_full_binary_id(),
+            DbRecord::LabelMapping(record) => record.get_full_binary_id(),
+        }
+    }
+
+    /// The type of the record
+    pub fn data_type(&self) -> StorageType {
+        match &self {
+            DbRecord::Azks(_) => StorageType::Azks,
+            DbRecord::TreeNode(_) => StorageType::TreeNode,
+            DbRecord::ValueState(_) => StorageType::ValueState,
+            DbRecord::TreeHead(_) => StorageType::TreeHead,
+            DbRecord::RootHash(_) => StorageType::RootHash,
+            DbRecord::LabelMapping(_) => StorageType::LabelMapping,
+        }
+    }
+
+    /// The key matched by [crate::storage::Database::iter_by_prefix]: the label value
+    /// of a tree node, the username of a value state, and nothing for the azks,
+    /// tree heads, root hashes and label mapping.
+    pub fn prefix_key(&self) -> &[u8] {
+        match &self {
+            DbRecord::Azks(_)
+            | DbRecord::TreeHead(_)
+            | DbRecord::RootHash(_)
+            | DbRecord::LabelMapping(_) => &[],
+            DbRecord::TreeNode(node) => &node.label.label_val,
+            DbRecord::ValueState(state) => &state.username,
         }
     }
 
+    /// The version of the record which [crate::storage::Database::compare_and_set]
+    /// checks: the latest epoch of the azks, which each publish advances. The other
+    /// records aren't versioned.
+    pub fn version(&self) -> Option<u64> {
+        match &self {
+            DbRecord::Azks(azks) => Some(azks.latest_epoch),
+            _ => None,
+        }
+    }
+
+    /// Compute a serialized id from the record's fields, scoped to the given namespace.
+    pub fn get_full_binary_id_in_namespace(&self, namespace: &[u8]) -> Vec<u8> {
+        crate::storage::namespaced_key(namespace, self.get_full_binary_id())
+    }
+
     /// Returns the priority in which a record type in a transaction should be committed to storage.
     /// A smaller value indicates higher priority in being written first.
     /// An Azks record should always be updated last, so that any concurrent storage readers will
@@ -207,6 +478,42 @@ impl DbRecord {
 
     /* Data Layer Builders */
 
+    /// Build a signed tree head from the properties
+    pub fn build_tree_head(
+        epoch: u64,
+        root_hash: crate::Digest,
+        timestamp: u64,
+        signature: Vec<u8>,
+    ) -> SignedTreeHead {
+        SignedTreeHead {
+            epoch,
+            root_hash,
+            timestamp,
+            signature,
+        }
+    }
+
+    /// Build an archived root hash from the properties
+    pub fn build_root_hash_record(
+        epoch: u64,
+        root_hash: crate::Digest,
+        timestamp: u64,
+        batch_size: u64,
+    ) -> RootHashRecord {
+        RootHashRecord {
+            epoch,
+            root_hash,
+            timestamp,
+            batch_size,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// Build a label mapping record from the properties
+    pub fn build_label_mapping_record(mapper_id: Vec<u8>) -> LabelMappingRecord {
+        LabelMappingRecord { mapper_id }
+    }
+
     /// Build an azks instance from the properties
     pub fn build_azks(latest_epoch: u64, num_nodes: u64) -> Azks {
         Azks {
@@ -289,6 +596,7 @@ impl DbRecord {
             label: NodeLabel::new(label_val, label_len),
             epoch,
             username: AkdLabel(username),
+            expiry_epoch: None,
         }
     }
 }
@@ -9,14 +9,44 @@
 //! Contains the tests for the high-level API (directory, auditor, client)
 
 use crate::{
-    auditor::audit_verify,
-    client::{key_history_verify, lookup_verify},
-    directory::{Directory, PublishCorruption},
+    auditor::{
+        audit_verify, audit_verify_segmented, verify_append_only_manifest,
+        verify_append_only_segment, AuditProgress, AuditVerifier,
+    },
+    client::{
+        batch_lookup_verify, compare_tree_heads, key_history_verify,
+        key_history_verify_with_observer, key_history_verify_with_policy, lookup_at_epochs_verify,
+        lookup_nonexistent_verify, lookup_verify, lookup_verify_against_roots,
+        lookup_verify_at_epoch, lookup_verify_with_observer, lookup_with_consistency_verify,
+        update_count_verify, verify_sample_audit, verify_tree_head, verify_tree_head_for_root,
+        HistoryPolicyViolation, HistoryVerificationPolicy, VerificationError, VerificationObserver,
+        VerificationStep, VerificationTimings,
+    },
+    commitment::HashCommitment,
+    directory::{
+        Directory, DirectoryEvent, DuplicateLabelPolicy, PublishCorruption, PublishOptions,
+        PublishPhase, PublishProgress,
+    },
     ecvrf::{HardCodedAkdVRF, VRFKeyStorage},
-    errors::AkdError,
-    storage::{manager::StorageManager, memory::AsyncInMemoryDatabase, types::DbRecord, Database},
-    AkdLabel, AkdValue, HistoryParams, HistoryVerificationParams, VerifyResult,
+    errors::{AkdError, DirectoryError, ErrorCode, StorageError},
+    integrity::IntegrityIssue,
+    label_mapper::{DomainSeparatedLabelMapper, LabelMapper},
+    proof_cache::DEFAULT_PROOF_CACHE_LIMIT_BYTES,
+    storage::{
+        encrypted::EncryptedDatabase,
+        manager::StorageManager,
+        memory::AsyncInMemoryDatabase,
+        tests::encrypted_storage_tests::XorEncryption,
+        tiered::TieredDatabase,
+        types::{DbRecord, LabelMappingRecord, RootHashRecord, ValueState, LABEL_MAPPING_KEY},
+        Database, StorageUtil,
+    },
+    tree_node::{NodeType, TreeNodeWithPreviousValue},
+    AbsenceProof, AkdLabel, AkdValue, EpochHash, HistoryParams, HistoryProof,
+    HistoryVerificationParams, NodeLabel, ValueDisclosure, VerifyResult,
 };
+use akd_core::SizeOf;
+use std::collections::HashMap;
 
 // A simple test to ensure that the empty tree hashes to the correct value
 #[tokio::test]
@@ -92,6 +122,322 @@ async fn test_simple_publish() -> Result<(), AkdError> {
     Ok(())
 }
 
+// With the async-std runtime, the directory should be driven by async-std's
+// executor alone, publishing and serving proofs which verify
+#[cfg(all(feature = "async_std_runtime", not(feature = "tokio_runtime")))]
+#[test]
+fn test_publish_and_lookup_on_async_std() -> Result<(), AkdError> {
+    async_std::task::block_on(async {
+        let db = AsyncInMemoryDatabase::new();
+        let storage = StorageManager::new(db, None, None, None);
+        let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+        let updates = (0..100)
+            .map(|i| {
+                (
+                    AkdLabel(format!("hello{}", i).into_bytes()),
+                    AkdValue(format!("world{}", i).into_bytes()),
+                )
+            })
+            .collect::<Vec<_>>();
+        akd.publish(updates).await?;
+
+        let vrf_pk = akd.get_public_key().await?;
+        let (proof, root_hash) = akd.lookup(AkdLabel::from_utf8_str("hello7")).await?;
+        lookup_verify(
+            vrf_pk.as_bytes(),
+            root_hash.hash(),
+            AkdLabel::from_utf8_str("hello7"),
+            proof,
+        )?;
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(60);
+        akd.lookup_with_deadline(AkdLabel::from_utf8_str("hello7"), deadline)
+            .await?;
+        Ok(())
+    })
+}
+
+// A batch with several updates for the same label is rejected by default, or
+// only publishes the last update with the last-write-wins policy.
+#[tokio::test]
+async fn test_publish_duplicate_labels() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+    let updates = vec![
+        (
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world"),
+        ),
+        (
+            AkdLabel::from_utf8_str("hello2"),
+            AkdValue::from_utf8_str("world2"),
+        ),
+        (
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world3"),
+        ),
+    ];
+
+    let err = akd.publish(updates.clone()).await.unwrap_err();
+    assert!(matches!(
+        &err,
+        AkdError::Directory(DirectoryError::DuplicateLabel(label))
+            if *label == AkdLabel::from_utf8_str("hello")
+    ));
+    assert_eq!(ErrorCode::InvalidRequest, err.code());
+    assert_eq!(0, akd.get_epoch_hash().epoch());
+
+    let options =
+        PublishOptions::default().with_duplicate_labels(DuplicateLabelPolicy::LastWriteWins);
+    let epoch_hash = akd.publish_with_options(updates, options).await?;
+    let (proof, _) = akd.lookup(AkdLabel::from_utf8_str("hello")).await?;
+    let result = lookup_verify(
+        akd.get_public_key().await?.as_bytes(),
+        epoch_hash.hash(),
+        AkdLabel::from_utf8_str("hello"),
+        proof,
+    )?;
+    assert_eq!(1, result.version);
+    assert_eq!(AkdValue::from_utf8_str("world3"), result.value);
+    Ok(())
+}
+
+// A value published with a TTL carries its expiry epoch, which lookup and key
+// history verification report, and is flagged as expired from that epoch on.
+#[tokio::test]
+async fn test_publish_with_ttl() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+    let vrf_pk = akd.get_public_key().await?;
+
+    let options = PublishOptions::default().with_ttl(AkdLabel::from_utf8_str("hello"), 2);
+    let epoch_hash = akd
+        .publish_with_options(
+            vec![
+                (
+                    AkdLabel::from_utf8_str("hello"),
+                    AkdValue::from_utf8_str("world"),
+                ),
+                (
+                    AkdLabel::from_utf8_str("hello2"),
+                    AkdValue::from_utf8_str("world2"),
+                ),
+            ],
+            options,
+        )
+        .await?;
+
+    let (proof, _) = akd.lookup(AkdLabel::from_utf8_str("hello")).await?;
+    let result = lookup_verify(
+        vrf_pk.as_bytes(),
+        epoch_hash.hash(),
+        AkdLabel::from_utf8_str("hello"),
+        proof,
+    )?;
+    assert_eq!(AkdValue::from_utf8_str("world"), result.value);
+    assert_eq!(Some(3), result.expiry_epoch);
+    assert!(!result.is_expired(2));
+    assert!(result.is_expired(3));
+
+    let (proof, _) = akd.lookup(AkdLabel::from_utf8_str("hello2")).await?;
+    let result = lookup_verify(
+        vrf_pk.as_bytes(),
+        epoch_hash.hash(),
+        AkdLabel::from_utf8_str("hello2"),
+        proof,
+    )?;
+    assert_eq!(AkdValue::from_utf8_str("world2"), result.value);
+    assert_eq!(None, result.expiry_epoch);
+    assert!(!result.is_expired(u64::MAX));
+
+    let epoch_hash = akd
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world3"),
+        )])
+        .await?;
+    let (proof, _) = akd
+        .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
+        .await?;
+    let results = key_history_verify(
+        vrf_pk.as_bytes(),
+        epoch_hash.hash(),
+        epoch_hash.epoch(),
+        AkdLabel::from_utf8_str("hello"),
+        proof,
+        HistoryVerificationParams::default(),
+    )?;
+    assert_eq!(2, results.len());
+    assert_eq!(AkdValue::from_utf8_str("world3"), results[0].value);
+    assert_eq!(None, results[0].expiry_epoch);
+    assert_eq!(AkdValue::from_utf8_str("world"), results[1].value);
+    assert_eq!(Some(3), results[1].expiry_epoch);
+
+    // the expiry epoch can't be dropped from, or added to, a proof
+    let (proof, _) = akd.lookup(AkdLabel::from_utf8_str("hello2")).await?;
+    let mut tampered = proof.clone();
+    tampered.expiry_epoch = Some(10);
+    assert!(lookup_verify(
+        vrf_pk.as_bytes(),
+        epoch_hash.hash(),
+        AkdLabel::from_utf8_str("hello2"),
+        tampered,
+    )
+    .is_err());
+    let (proof, _) = akd
+        .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
+        .await?;
+    let mut tampered = proof.clone();
+    tampered.update_proofs[1].expiry_epoch = None;
+    assert!(key_history_verify(
+        vrf_pk.as_bytes(),
+        epoch_hash.hash(),
+        epoch_hash.epoch(),
+        AkdLabel::from_utf8_str("hello"),
+        tampered,
+        HistoryVerificationParams::default(),
+    )
+    .is_err());
+
+    // a value without an expiry epoch is returned as is, whatever its bytes
+    let mut value = akd_core::VALUE_EXPIRY_DOMAIN.to_vec();
+    value.extend_from_slice(&7u64.to_be_bytes());
+    value.extend_from_slice(b"world4");
+    let epoch_hash = akd
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello2"),
+            AkdValue(value.clone()),
+        )])
+        .await?;
+    let (proof, _) = akd.lookup(AkdLabel::from_utf8_str("hello2")).await?;
+    let result = lookup_verify(
+        vrf_pk.as_bytes(),
+        epoch_hash.hash(),
+        AkdLabel::from_utf8_str("hello2"),
+        proof,
+    )?;
+    assert_eq!(AkdValue(value), result.value);
+    assert_eq!(None, result.expiry_epoch);
+    Ok(())
+}
+
+// A publish reports its progress, and a cancelled publish leaves the directory
+// unchanged
+#[tokio::test]
+async fn test_publish_with_progress() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db.clone());
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let updates = (0..20)
+        .map(|i| {
+            (
+                AkdLabel::from_utf8_str(&format!("hello{}", i)),
+                AkdValue::from_utf8_str(&format!("world{}", i)),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // a cancelled publish writes nothing
+    let (progress, receiver) = tokio::sync::watch::channel(PublishProgress::default());
+    let cancellation = tokio_util::sync::CancellationToken::new();
+    cancellation.cancel();
+    let num_records = db.batch_get_all_direct().await?.len();
+    let err = akd
+        .publish_with_progress(
+            updates.clone(),
+            PublishOptions::default(),
+            &progress,
+            &cancellation,
+        )
+        .await
+        .err();
+    assert!(matches!(
+        err,
+        Some(AkdError::Directory(DirectoryError::PublishCancelled(1)))
+    ));
+    assert_eq!(ErrorCode::Cancelled, err.unwrap().code());
+    assert_eq!(PublishPhase::NotStarted, receiver.borrow().phase);
+    assert_eq!(0, akd.get_epoch_hash().epoch());
+    assert_eq!(num_records, db.batch_get_all_direct().await?.len());
+
+    // the publish can then be run to completion
+    let cancellation = tokio_util::sync::CancellationToken::new();
+    let epoch_hash = akd
+        .publish_with_progress(updates, PublishOptions::default(), &progress, &cancellation)
+        .await?;
+    assert_eq!(1, epoch_hash.epoch());
+    let last = *receiver.borrow();
+    assert_eq!(PublishPhase::Done, last.phase);
+    assert_eq!(20, last.items_inserted);
+    assert!(last.nodes_written >= 20);
+    assert_eq!(19, last.hashes_computed);
+    Ok(())
+}
+
+// A publish preview predicts the changed labels and the root hash of the publish,
+// without committing anything
+#[tokio::test]
+async fn test_preview_publish() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db.clone());
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+    let first = (0..5)
+        .map(|i| {
+            (
+                AkdLabel::from_utf8_str(&format!("hello{}", i)),
+                AkdValue::from_utf8_str(&format!("world{}", i)),
+            )
+        })
+        .collect::<Vec<_>>();
+    let epoch_hash = akd.publish(first.clone()).await?;
+
+    // re-publishing the same values changes nothing
+    let preview = akd.preview_publish(first).await?;
+    assert_eq!(epoch_hash.epoch(), preview.epoch);
+    assert_eq!(epoch_hash.hash(), preview.root_hash);
+    assert!(preview.changed_labels.is_empty());
+    assert_eq!(0, preview.estimated_node_writes);
+
+    let updates = vec![
+        (
+            AkdLabel::from_utf8_str("hello7"),
+            AkdValue::from_utf8_str("world7"),
+        ),
+        (
+            AkdLabel::from_utf8_str("hello1"),
+            AkdValue::from_utf8_str("world1"),
+        ),
+        (
+            AkdLabel::from_utf8_str("hello2"),
+            AkdValue::from_utf8_str("new world"),
+        ),
+    ];
+    let num_records = db.batch_get_all_direct().await?.len();
+    let preview = akd.preview_publish(updates.clone()).await?;
+    assert_eq!(2, preview.epoch);
+    assert_eq!(
+        vec![
+            AkdLabel::from_utf8_str("hello2"),
+            AkdLabel::from_utf8_str("hello7")
+        ],
+        preview.changed_labels
+    );
+    assert!(preview.estimated_node_writes > 0);
+    assert_eq!(num_records, db.batch_get_all_direct().await?.len());
+    assert_eq!(epoch_hash, akd.get_epoch_hash());
+    assert_eq!(1, akd.retrieve_current_azks().await?.get_latest_epoch());
+
+    let epoch_hash = akd.publish(updates).await?;
+    assert_eq!(preview.epoch, epoch_hash.epoch());
+    assert_eq!(preview.root_hash, epoch_hash.hash());
+    Ok(())
+}
+
 // A simple lookup test, for a tree with two elements:
 // ensure that calculation of a lookup proof doesn't throw an error and
 // that the output of akd.lookup verifies on the client.
@@ -177,11 +523,13 @@ async fn test_small_key_history() -> Result<(), AkdError> {
                 epoch: 2,
                 version: 2,
                 value: AkdValue::from_utf8_str("world2"),
+                expiry_epoch: None,
             },
             VerifyResult {
                 epoch: 1,
                 version: 1,
                 value: AkdValue::from_utf8_str("world"),
+                expiry_epoch: None,
             },
         ]
     );
@@ -614,6 +962,141 @@ async fn test_malicious_key_history() -> Result<(), AkdError> {
     Ok(())
 }
 
+// Checks the future marker proofs of a label whose next marker is several
+// powers of two below the current epoch, and that a history proof with the
+// wrong number of future proofs is rejected rather than panicking the verifier.
+#[tokio::test]
+async fn test_key_history_future_markers() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+    // "hello" is at version 1 as of epoch 1, so its next marker is version 2
+    akd.publish(vec![(
+        AkdLabel::from_utf8_str("hello"),
+        AkdValue::from_utf8_str("world"),
+    )])
+    .await?;
+    // By epoch 9, the future markers are versions 2, 4 and 8
+    for epoch in 2..10 {
+        akd.publish(vec![(
+            AkdLabel::from_utf8_str(&format!("other{}", epoch)),
+            AkdValue::from_utf8_str("value"),
+        )])
+        .await?;
+    }
+
+    let (key_history_proof, root_hash) = akd
+        .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
+        .await?;
+    assert_eq!(9, root_hash.epoch());
+    assert_eq!(3, key_history_proof.non_existence_of_future_markers.len());
+    let vrf_pk = akd.get_public_key().await?;
+    let verify = |proof| {
+        key_history_verify(
+            vrf_pk.as_bytes(),
+            root_hash.hash(),
+            root_hash.epoch(),
+            AkdLabel::from_utf8_str("hello"),
+            proof,
+            HistoryVerificationParams::default(),
+        )
+    };
+    verify(key_history_proof.clone())?;
+
+    // the proofs for the next few versions are empty, since version 2 is a marker
+    let malformations: Vec<fn(&mut HistoryProof)> = vec![
+        |proof| {
+            proof.non_existence_of_future_markers.pop();
+        },
+        |proof| {
+            proof.future_marker_vrf_proofs.pop();
+        },
+        |proof| {
+            let extra = proof.non_existence_of_future_markers[0].clone();
+            proof.non_existence_of_next_few.push(extra);
+        },
+        |proof| {
+            proof.next_few_vrf_proofs.push(vec![]);
+        },
+    ];
+    for malform in malformations {
+        let mut proof = key_history_proof.clone();
+        malform(&mut proof);
+        assert!(matches!(
+            verify(proof),
+            Err(VerificationError::HistoryProof(_))
+        ));
+    }
+
+    Ok(())
+}
+
+// This test pins which marker each future marker proof is checked against, for
+// a label whose next marker is several powers of two below the current epoch's
+#[tokio::test]
+async fn test_key_history_future_marker_range() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+    // "hello" is at version 3 as of epoch 3, so its next marker is version 4
+    for epoch in 1..4 {
+        akd.publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str(&format!("world{}", epoch)),
+        )])
+        .await?;
+    }
+    // By epoch 40, the future markers are versions 4, 8, 16 and 32
+    for epoch in 4..41 {
+        akd.publish(vec![(
+            AkdLabel::from_utf8_str(&format!("other{}", epoch)),
+            AkdValue::from_utf8_str("value"),
+        )])
+        .await?;
+    }
+
+    let (key_history_proof, root_hash) = akd
+        .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
+        .await?;
+    assert_eq!(40, root_hash.epoch());
+    assert_eq!(2..=5, akd_core::utils::get_future_marker_powers(3, 40));
+    assert_eq!(4, key_history_proof.non_existence_of_future_markers.len());
+    let vrf_pk = akd.get_public_key().await?;
+    let verify = |proof| {
+        key_history_verify(
+            vrf_pk.as_bytes(),
+            root_hash.hash(),
+            root_hash.epoch(),
+            AkdLabel::from_utf8_str("hello"),
+            proof,
+            HistoryVerificationParams::default(),
+        )
+    };
+    verify(key_history_proof.clone())?;
+
+    // Shifting the proofs by a marker in either direction pairs each of them
+    // with the wrong marker version
+    let shifts: Vec<fn(&mut HistoryProof)> = vec![
+        |proof| {
+            proof.non_existence_of_future_markers.rotate_left(1);
+            proof.future_marker_vrf_proofs.rotate_left(1);
+        },
+        |proof| {
+            proof.non_existence_of_future_markers.rotate_right(1);
+            proof.future_marker_vrf_proofs.rotate_right(1);
+        },
+    ];
+    for shift in shifts {
+        let mut proof = key_history_proof.clone();
+        shift(&mut proof);
+        assert!(verify(proof).is_err());
+    }
+
+    Ok(())
+}
+
 // This test ensures valid audit proofs pass for various epochs and
 // that invalid audit proofs fail.
 #[tokio::test]
@@ -776,76 +1259,192 @@ async fn test_simple_audit() -> Result<(), AkdError> {
     Ok(())
 }
 
+// This test checks that an audit can be verified incrementally, in chunks of
+// epochs, that the verification can be resumed from a checkpoint, and that
+// out-of-order chunks are rejected.
 #[tokio::test]
-async fn test_read_during_publish() -> Result<(), AkdError> {
+async fn test_incremental_audit() -> Result<(), AkdError> {
     let db = AsyncInMemoryDatabase::new();
-    let storage = StorageManager::new_no_cache(db.clone());
+    let storage = StorageManager::new_no_cache(db);
     let vrf = HardCodedAkdVRF {};
     let akd = Directory::<_, _>::new(storage, vrf, false).await?;
 
-    // Publish once
-    akd.publish(vec![
-        (
-            AkdLabel::from_utf8_str("hello"),
+    let mut root_hashes = vec![];
+    for i in 0..5 {
+        akd.publish(vec![(
+            AkdLabel(format!("hello{}", i).into_bytes()),
             AkdValue::from_utf8_str("world"),
-        ),
-        (
-            AkdLabel::from_utf8_str("hello2"),
-            AkdValue::from_utf8_str("world2"),
-        ),
-    ])
-    .await?;
-    // Get the root hash after the first publish
-    let root_hash_1 = akd
-        .get_root_hash(&akd.retrieve_current_azks().await?)
+        )])
         .await?;
-    // Publish updates for the same labels.
-    akd.publish(vec![
-        (
-            AkdLabel::from_utf8_str("hello"),
-            AkdValue::from_utf8_str("world_2"),
-        ),
-        (
-            AkdLabel::from_utf8_str("hello2"),
-            AkdValue::from_utf8_str("world2_2"),
-        ),
-    ])
+        root_hashes.push(
+            akd.get_root_hash(&akd.retrieve_current_azks().await?)
+                .await?,
+        );
+    }
+
+    let progress_updates = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let progress_updates_clone = progress_updates.clone();
+    let mut verifier = AuditVerifier::new(root_hashes.clone()).with_progress_callback(
+        move |progress: &AuditProgress| progress_updates_clone.lock().unwrap().push(*progress),
+    );
+
+    // a chunk which doesn't start at the first epoch should be rejected
+    assert!(verifier.feed(akd.audit(2, 3).await?).await.is_err());
+    let progress = verifier.feed(akd.audit(1, 3).await?).await?;
+    assert_eq!(2, progress.verified);
+    assert_eq!(4, progress.total);
+    assert_eq!(Some(2), progress.last_epoch);
+    assert!(!progress.is_complete());
+    assert_eq!(2, progress_updates.lock().unwrap().len());
+
+    // resume from the checkpoint in a fresh verifier
+    let mut verifier = AuditVerifier::resume(root_hashes.clone(), progress)?;
+    assert!(verifier.feed(akd.audit(1, 2).await?).await.is_err());
+    let progress = verifier.feed(akd.audit(3, 5).await?).await?;
+    assert!(progress.is_complete());
+    assert_eq!(Some(4), progress.last_epoch);
+
+    // no more epochs remain to be verified
+    assert!(verifier.feed(akd.audit(4, 5).await?).await.is_err());
+
+    // a mismatched checkpoint cannot be resumed from
+    assert!(AuditVerifier::resume(root_hashes[..3].to_vec(), progress).is_err());
+
+    Ok(())
+}
+
+// This test checks that a lookup bundled with a consistency proof verifies
+// against an older pinned root hash, and fails against any other root hash.
+#[tokio::test]
+async fn test_lookup_with_consistency() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+    let vrf_pk = akd.get_public_key().await?;
+
+    let mut epoch_hashes = vec![];
+    for i in 0..4 {
+        epoch_hashes.push(
+            akd.publish(vec![(
+                AkdLabel::from_utf8_str("hello"),
+                AkdValue(format!("world{}", i).into_bytes()),
+            )])
+            .await?,
+        );
+    }
+    let pinned = epoch_hashes[1].clone();
+
+    let (proof, current) = akd
+        .lookup_with_consistency(AkdLabel::from_utf8_str("hello"), pinned.epoch())
+        .await?;
+    assert_eq!(epoch_hashes[3], current);
+    assert_eq!(vec![2, 3], proof.consistency_proof.epochs);
+    let result = lookup_with_consistency_verify(
+        vrf_pk.as_bytes(),
+        pinned.clone(),
+        current.clone(),
+        AkdLabel::from_utf8_str("hello"),
+        proof.clone(),
+    )
     .await?;
+    assert_eq!(AkdValue::from_utf8_str("world3"), result.value);
 
-    // Get the root hash after the second publish
-    let root_hash_2 = akd
-        .get_root_hash(&akd.retrieve_current_azks().await?)
+    // pinning the current epoch requires no append-only proof
+    let (same_epoch_proof, _) = akd
+        .lookup_with_consistency(AkdLabel::from_utf8_str("hello"), current.epoch())
         .await?;
+    assert!(same_epoch_proof.consistency_proof.proofs.is_empty());
+    lookup_with_consistency_verify(
+        vrf_pk.as_bytes(),
+        current.clone(),
+        current.clone(),
+        AkdLabel::from_utf8_str("hello"),
+        same_epoch_proof,
+    )
+    .await?;
 
-    // Make the current azks a "checkpoint" to reset to later
-    let checkpoint_azks = akd.retrieve_current_azks().await.unwrap();
+    // a different pinned root hash, or one at a different epoch, is rejected
+    for wrong_pinned in [
+        EpochHash(pinned.epoch(), epoch_hashes[0].hash()),
+        EpochHash(epoch_hashes[0].epoch(), epoch_hashes[0].hash()),
+    ] {
+        assert!(lookup_with_consistency_verify(
+            vrf_pk.as_bytes(),
+            wrong_pinned,
+            current.clone(),
+            AkdLabel::from_utf8_str("hello"),
+            proof.clone(),
+        )
+        .await
+        .is_err());
+    }
 
-    // Publish for the third time
-    akd.publish(vec![
+    // a pinned epoch in the future is rejected
+    assert!(akd
+        .lookup_with_consistency(AkdLabel::from_utf8_str("hello"), current.epoch() + 1)
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+// Checks that proofs from a directory with a non-default commitment scheme verify,
+// and that the scheme changes the committed values (and thus the root hash)
+#[tokio::test]
+async fn test_value_commitment_schemes() -> Result<(), AkdError> {
+    let updates = vec![
         (
             AkdLabel::from_utf8_str("hello"),
-            AkdValue::from_utf8_str("world_3"),
+            AkdValue::from_utf8_str("world"),
         ),
         (
             AkdLabel::from_utf8_str("hello2"),
-            AkdValue::from_utf8_str("world2_3"),
+            AkdValue::from_utf8_str("world2"),
         ),
-    ])
+    ];
+
+    let nonce_akd = Directory::<_, _>::new(
+        StorageManager::new_no_cache(AsyncInMemoryDatabase::new()),
+        HardCodedAkdVRF {},
+        false,
+    )
     .await?;
+    let hash_akd = Directory::<_, _>::new(
+        StorageManager::new_no_cache(AsyncInMemoryDatabase::new()),
+        HardCodedAkdVRF {},
+        false,
+    )
+    .await?
+    .with_value_commitment(HashCommitment::new(b"akd test domain"));
 
-    // Reset the azks record back to previous epoch, to emulate an akd reader
-    // communicating with storage that is in the middle of a publish operation
-    db.set(DbRecord::Azks(checkpoint_azks))
-        .await
-        .expect("Error resetting directory to previous epoch");
+    // the same updates commit to different leaf values under each scheme
+    let nonce_root = nonce_akd.publish(updates.clone()).await?;
+    let first_hash_root = hash_akd.publish(updates).await?;
+    assert_ne!(nonce_root.hash(), first_hash_root.hash());
 
-    // History proof should not contain the third epoch's update but still verify
-    let (history_proof, root_hash) = akd
+    let hash_root = hash_akd
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world3"),
+        )])
+        .await?;
+
+    let vrf_pk = hash_akd.get_public_key().await?;
+    let (lookup_proof, root_hash) = hash_akd.lookup(AkdLabel::from_utf8_str("hello2")).await?;
+    assert_eq!(b"akd test domain".to_vec(), lookup_proof.commitment_proof);
+    lookup_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        AkdLabel::from_utf8_str("hello2"),
+        lookup_proof,
+    )?;
+
+    let (history_proof, root_hash) = hash_akd
         .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
         .await?;
-    // Get the VRF public key
-    let vrf_pk = akd.get_public_key().await?;
-    key_history_verify(
+    assert_eq!(hash_root, root_hash);
+    let results = key_history_verify(
         vrf_pk.as_bytes(),
         root_hash.hash(),
         root_hash.epoch(),
@@ -853,342 +1452,3191 @@ async fn test_read_during_publish() -> Result<(), AkdError> {
         history_proof,
         HistoryVerificationParams::default(),
     )?;
+    assert_eq!(2, results.len());
 
-    // Lookup proof should contain the checkpoint epoch's value and still verify
-    let (lookup_proof, root_hash) = akd.lookup(AkdLabel::from_utf8_str("hello")).await?;
-    assert_eq!(
-        AkdValue::from_utf8_str("world_2"),
-        lookup_proof.plaintext_value
-    );
-    lookup_verify(
+    // without a domain separator, the commitment proofs of opened values are
+    // empty, which doesn't make them redacted
+    let empty_akd = Directory::<_, _>::new(
+        StorageManager::new_no_cache(AsyncInMemoryDatabase::new()),
+        HardCodedAkdVRF {},
+        false,
+    )
+    .await?
+    .with_value_commitment(HashCommitment::new(b""));
+    for value in ["world", "world2"] {
+        empty_akd
+            .publish(vec![(
+                AkdLabel::from_utf8_str("hello"),
+                AkdValue::from_utf8_str(value),
+            )])
+            .await?;
+    }
+    let (history_proof, root_hash) = empty_akd
+        .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
+        .await?;
+    assert!(history_proof
+        .update_proofs
+        .iter()
+        .all(|proof| proof.commitment_proof.is_empty() && !proof.is_redacted()));
+    let results = key_history_verify(
         vrf_pk.as_bytes(),
         root_hash.hash(),
+        root_hash.epoch(),
         AkdLabel::from_utf8_str("hello"),
-        lookup_proof,
+        history_proof,
+        HistoryVerificationParams::default(),
     )?;
-
-    // Audit proof should only work up until checkpoint's epoch
-    let audit_proof = akd.audit(1, 2).await?;
-    audit_verify(vec![root_hash_1, root_hash_2], audit_proof).await?;
-
-    let invalid_audit = akd.audit(2, 3).await;
-    assert!(matches!(invalid_audit, Err(_)));
-
+    assert_eq!(AkdValue::from_utf8_str("world2"), results[0].value);
+    assert_eq!(AkdValue::from_utf8_str("world"), results[1].value);
     Ok(())
 }
 
-// The read-only mode of a directory is meant to simply read from memory.
-// This test makes sure it throws errors appropriately, i.e. when trying to
-// write to a read-only directory and when trying to read a directory when none
-// exists in storage.
+// Checks that repeated lookups are served from the proof cache, that the cache is
+// invalidated by a publish, and that it stays within its memory limit
 #[tokio::test]
-async fn test_directory_read_only_mode() -> Result<(), AkdError> {
+async fn test_lookup_proof_cache() -> Result<(), AkdError> {
     let db = AsyncInMemoryDatabase::new();
     let storage = StorageManager::new_no_cache(db);
     let vrf = HardCodedAkdVRF {};
-    // There is no AZKS object in the storage layer, directory construction should fail
-    let akd = Directory::<_, _>::new(storage.clone(), vrf.clone(), true).await;
-    assert!(matches!(akd, Err(_)));
+    let akd = Directory::<_, _>::new(storage, vrf, false)
+        .await?
+        .with_lookup_proof_cache(DEFAULT_PROOF_CACHE_LIMIT_BYTES);
+    let vrf_pk = akd.get_public_key().await?;
+    let label = AkdLabel::from_utf8_str("hello");
 
-    // now create the AZKS
-    let akd = Directory::<_, _>::new(storage.clone(), vrf.clone(), false).await;
-    assert!(matches!(akd, Ok(_)));
+    akd.publish(vec![(label.clone(), AkdValue::from_utf8_str("world"))])
+        .await?;
+    let (first_proof, first_root) = akd.lookup(label.clone()).await?;
+    let (second_proof, second_root) = akd.lookup(label.clone()).await?;
+    assert_eq!(first_proof, second_proof);
+    assert_eq!(first_root, second_root);
 
-    // create another read-only dir now that the AZKS exists in the storage layer, and try to publish which should fail
-    let akd = Directory::<_, _>::new(storage, vrf, true).await?;
-    assert!(matches!(akd.publish(vec![]).await, Err(_)));
+    let cache = akd.lookup_proof_cache().unwrap();
+    assert_eq!(1, cache.hits());
+    assert_eq!(1, cache.misses());
+    assert_eq!(1, cache.len());
+
+    // a publish empties the cache, and the new value is served
+    akd.publish(vec![(label.clone(), AkdValue::from_utf8_str("world2"))])
+        .await?;
+    assert!(akd.lookup_proof_cache().unwrap().is_empty());
+    let (proof, root_hash) = akd.lookup(label.clone()).await?;
+    assert_eq!(2, root_hash.epoch());
+    let result = lookup_verify(vrf_pk.as_bytes(), root_hash.hash(), label, proof)?;
+    assert_eq!(AkdValue::from_utf8_str("world2"), result.value);
 
+    // a cache with room for about two proofs evicts the least recently used ones
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let labels = (0..4)
+        .map(|i| AkdLabel::from_utf8_str(&format!("user{}", i)))
+        .collect::<Vec<_>>();
+    akd.publish(
+        labels
+            .iter()
+            .map(|label| (label.clone(), AkdValue::from_utf8_str("value")))
+            .collect(),
+    )
+    .await?;
+    let (proof, _) = akd.lookup(labels[0].clone()).await?;
+    let limit = 5 * (labels[0].size_of() + proof.size_of()) / 2;
+    let akd = akd.with_lookup_proof_cache(limit);
+    for label in labels.iter() {
+        akd.lookup(label.clone()).await?;
+    }
+    let cache = akd.lookup_proof_cache().unwrap();
+    assert!(cache.len() < labels.len());
+    assert!(cache.size_bytes() <= limit);
     Ok(())
 }
 
-// This test is meant to test the function poll_for_azks_change
-// which is meant to detect changes in the azks, to prevent inconsistencies
-// between the local cache and storage.
+// Checks that a directory over a tiered database only answers from the fast store
+// once read, and that the slow backend holds every epoch once published
 #[tokio::test]
-async fn test_directory_polling_azks_change() -> Result<(), AkdError> {
-    let db = AsyncInMemoryDatabase::new();
-    let storage = StorageManager::new(db, None, None, None);
+async fn test_tiered_storage() -> Result<(), AkdError> {
+    let slow = AsyncInMemoryDatabase::new();
+    let db = TieredDatabase::new(AsyncInMemoryDatabase::new(), slow.clone())
+        .with_write_behind_threshold(8);
+    let storage = StorageManager::new_no_cache(db.clone());
     let vrf = HardCodedAkdVRF {};
-    // writer will write the AZKS record
-    let writer = Directory::<_, _>::new(storage.clone(), vrf.clone(), false).await?;
+    let akd = Directory::<_, _>::new(storage, vrf.clone(), false).await?;
 
-    writer
-        .publish(vec![
-            (
-                AkdLabel::from_utf8_str("hello"),
-                AkdValue::from_utf8_str("world"),
-            ),
-            (
-                AkdLabel::from_utf8_str("hello2"),
-                AkdValue::from_utf8_str("world2"),
-            ),
-        ])
+    for epoch in 1..4 {
+        let updates = (0..10)
+            .map(|i| {
+                (
+                    AkdLabel::from_utf8_str(&format!("label{}", i)),
+                    AkdValue::from_utf8_str(&format!("value{}", epoch)),
+                )
+            })
+            .collect();
+        let epoch_hash = akd.publish(updates).await?;
+        // the epoch commit is a durability barrier
+        assert_eq!(0, db.pending_count());
+        let recovered = Directory::<_, _>::new(
+            StorageManager::new_no_cache(slow.clone()),
+            vrf.clone(),
+            true,
+        )
         .await?;
+        assert_eq!(epoch_hash, recovered.get_epoch_hash());
+    }
 
-    // reader will not write the AZKS but will be "polling" for AZKS changes
-    let reader = Directory::<_, _>::new(storage, vrf, true).await?;
+    // a fresh fast store is populated from the slow backend
+    let db = TieredDatabase::new(AsyncInMemoryDatabase::new(), slow.clone());
+    let akd = Directory::<_, _>::new(StorageManager::new_no_cache(db.clone()), vrf, true).await?;
+    let (proof, root_hash) = akd.lookup(AkdLabel::from_utf8_str("label3")).await?;
+    let result = lookup_verify(
+        akd.get_public_key().await?.as_bytes(),
+        root_hash.hash(),
+        AkdLabel::from_utf8_str("label3"),
+        proof,
+    )?;
+    assert_eq!(AkdValue::from_utf8_str("value3"), result.value);
+    assert!(!db.fast().batch_get_all_direct().await?.is_empty());
+    Ok(())
+}
 
-    // start the poller
-    let (tx, mut rx) = tokio::sync::mpsc::channel(10);
-    let reader_clone = reader.clone();
-    let _join_handle = tokio::task::spawn(async move {
-        reader_clone
-            .poll_for_azks_changes(tokio::time::Duration::from_millis(100), Some(tx))
-            .await
-    });
+// Checks that directories over tiered databases sharing a slow backend see the
+// epochs published by each other, including a read-only one whose fast store holds
+// the records of an older epoch
+#[tokio::test]
+async fn test_tiered_storage_shared_backend() -> Result<(), AkdError> {
+    let slow = AsyncInMemoryDatabase::new();
+    let vrf = HardCodedAkdVRF {};
+    let tiered = || TieredDatabase::new(AsyncInMemoryDatabase::new(), slow.clone());
+    let first =
+        Directory::<_, _>::new(StorageManager::new_no_cache(tiered()), vrf.clone(), false).await?;
+    let second =
+        Directory::<_, _>::new(StorageManager::new_no_cache(tiered()), vrf.clone(), false).await?;
+    let reader =
+        Directory::<_, _>::new(StorageManager::new_no_cache(tiered()), vrf.clone(), true).await?;
+    let vrf_pk = first.get_public_key().await?;
 
-    // wait for a second to make sure the poller has started
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    first
+        .publish(vec![(
+            AkdLabel::from_utf8_str("first"),
+            AkdValue::from_utf8_str("value"),
+        )])
+        .await?;
+    // the reader's fast store is populated with the records of epoch 1
+    let (_, root_hash) = reader.lookup(AkdLabel::from_utf8_str("first")).await?;
+    assert_eq!(1, root_hash.epoch());
 
-    // verify a lookup proof, which will populate the cache
-    async_poll_helper_proof(&reader, AkdValue::from_utf8_str("world")).await?;
+    // the second writer publishes over the first one's epoch
+    let epoch_hash = second
+        .publish(vec![(
+            AkdLabel::from_utf8_str("second"),
+            AkdValue::from_utf8_str("value"),
+        )])
+        .await?;
+    assert_eq!(2, epoch_hash.epoch());
+    // and the first one over the second one's, although its fast store holds epoch 1
+    let epoch_hash = first
+        .publish(vec![(
+            AkdLabel::from_utf8_str("first"),
+            AkdValue::from_utf8_str("value2"),
+        )])
+        .await?;
+    assert_eq!(3, epoch_hash.epoch());
 
-    // publish epoch 2
-    writer
-        .publish(vec![
-            (
-                AkdLabel::from_utf8_str("hello"),
-                AkdValue::from_utf8_str("world_2"),
-            ),
+    for (label, value) in [("first", "value2"), ("second", "value")] {
+        for directory in [&first, &second, &reader] {
+            let (proof, root_hash) = directory.lookup(AkdLabel::from_utf8_str(label)).await?;
+            assert_eq!(epoch_hash, root_hash);
+            let result = lookup_verify(
+                vrf_pk.as_bytes(),
+                root_hash.hash(),
+                AkdLabel::from_utf8_str(label),
+                proof,
+            )?;
+            assert_eq!(AkdValue::from_utf8_str(value), result.value);
+        }
+    }
+    Ok(())
+}
+
+// Checks that the values are encrypted at rest with an encrypted database, while the
+// proofs are unchanged, and that an encrypted value can't be moved to another record
+#[tokio::test]
+async fn test_encrypted_storage() -> Result<(), AkdError> {
+    let vrf = HardCodedAkdVRF {};
+    let updates = (0..10)
+        .map(|i| {
             (
-                AkdLabel::from_utf8_str("hello2"),
-                AkdValue::from_utf8_str("world2_2"),
-            ),
-        ])
+                AkdLabel::from_utf8_str(&format!("label{}", i)),
+                AkdValue::from_utf8_str(&format!("value{}", i)),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let db = EncryptedDatabase::new(AsyncInMemoryDatabase::new(), XorEncryption(0x5a));
+    let akd = Directory::<_, _>::new(StorageManager::new_no_cache(db.clone()), vrf.clone(), false)
         .await?;
+    let epoch_hash = akd.publish(updates.clone()).await?;
 
-    // assert that the change is picked up in a reasonable time-frame and the cache is flushed
-    let notification = tokio::time::timeout(tokio::time::Duration::from_secs(10), rx.recv()).await;
-    assert!(matches!(notification, Ok(Some(()))));
+    // the root hash is the one of a directory without encryption
+    let plain = Directory::<_, _>::new(
+        StorageManager::new_no_cache(AsyncInMemoryDatabase::new()),
+        vrf,
+        false,
+    )
+    .await?;
+    assert_eq!(epoch_hash, plain.publish(updates).await?);
 
-    async_poll_helper_proof(&reader, AkdValue::from_utf8_str("world_2")).await?;
+    let (proof, root_hash) = akd.lookup(AkdLabel::from_utf8_str("label3")).await?;
+    let result = lookup_verify(
+        akd.get_public_key().await?.as_bytes(),
+        root_hash.hash(),
+        AkdLabel::from_utf8_str("label3"),
+        proof,
+    )?;
+    assert_eq!(AkdValue::from_utf8_str("value3"), result.value);
 
+    // no plaintext value is stored
+    let mut states = db
+        .inner()
+        .batch_get_type_direct::<ValueState>()
+        .await?
+        .into_iter()
+        .filter_map(|record| match record {
+            DbRecord::ValueState(state) => Some(state),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(10, states.len());
+    assert!(states.iter().all(|state| !state
+        .plaintext_val
+        .0
+        .windows(5)
+        .any(|bytes| bytes == b"value")));
+
+    // a value moved to another label doesn't decrypt
+    states.sort_by(|a, b| a.username.cmp(&b.username));
+    let mut moved = states[1].clone();
+    moved.plaintext_val = states[0].plaintext_val.clone();
+    let label = moved.username.clone();
+    db.inner().set(DbRecord::ValueState(moved)).await?;
+    let err = akd.lookup(label).await.unwrap_err();
+    assert!(matches!(
+        err,
+        AkdError::Storage(StorageError::Encryption(_))
+    ));
     Ok(())
 }
 
+// Checks that the publish scheduler batches and collapses updates, and publishes them
+// when flushed, when enough of them are pending, and when shut down
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
 #[tokio::test]
-async fn test_tombstoned_key_history() -> Result<(), AkdError> {
+async fn test_publish_scheduler() -> Result<(), AkdError> {
+    use crate::publish_scheduler::{PublishScheduler, PublishSchedulerConfig};
+
     let db = AsyncInMemoryDatabase::new();
     let storage = StorageManager::new_no_cache(db);
     let vrf = HardCodedAkdVRF {};
-    // epoch 0
-    let akd = Directory::<_, _>::new(storage.clone(), vrf, false).await?;
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+    let mut events = akd.subscribe();
+    let scheduler = PublishScheduler::start(
+        akd.clone(),
+        PublishSchedulerConfig {
+            interval: std::time::Duration::from_secs(3600),
+            max_pending: 3,
+        },
+    );
 
-    // epoch 1
-    akd.publish(vec![(
+    // updates to the same label are collapsed into the latest one
+    scheduler.enqueue(
         AkdLabel::from_utf8_str("hello"),
         AkdValue::from_utf8_str("world"),
-    )])
-    .await?;
-
-    // epoch 2
-    akd.publish(vec![(
+    );
+    scheduler.enqueue(
         AkdLabel::from_utf8_str("hello"),
         AkdValue::from_utf8_str("world2"),
-    )])
-    .await?;
+    );
+    assert_eq!(1, scheduler.pending_count());
+    let epoch_hash = scheduler.flush().await?.unwrap();
+    assert_eq!(1, epoch_hash.epoch());
+    assert_eq!(None, scheduler.flush().await?);
 
-    // epoch 3
-    akd.publish(vec![(
-        AkdLabel::from_utf8_str("hello"),
-        AkdValue::from_utf8_str("world3"),
-    )])
-    .await?;
+    let (proof, _) = akd.lookup(AkdLabel::from_utf8_str("hello")).await?;
+    assert_eq!(AkdValue::from_utf8_str("world2"), proof.plaintext_value);
 
-    // epoch 4
-    akd.publish(vec![(
-        AkdLabel::from_utf8_str("hello"),
-        AkdValue::from_utf8_str("world4"),
-    )])
-    .await?;
+    // reaching the pending limit triggers a publish from the background task
+    for i in 0..3 {
+        scheduler.enqueue(
+            AkdLabel::from_utf8_str(&format!("user{}", i)),
+            AkdValue::from_utf8_str("value"),
+        );
+    }
+    let published = tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        loop {
+            if let Ok(DirectoryEvent::EpochPublished(epoch_hash)) = events.recv().await {
+                if epoch_hash.epoch() == 2 {
+                    return epoch_hash;
+                }
+            }
+        }
+    })
+    .await;
+    assert!(published.is_ok());
+    assert_eq!(0, scheduler.pending_count());
 
-    // epoch 5
-    akd.publish(vec![(
+    // the remaining updates are published on shutdown
+    scheduler.enqueue(
         AkdLabel::from_utf8_str("hello"),
-        AkdValue::from_utf8_str("world5"),
-    )])
-    .await?;
-
-    // Epochs 1-5, we're going to tombstone 1 & 2
+        AkdValue::from_utf8_str("world3"),
+    );
+    let epoch_hash = scheduler.shutdown().await?.unwrap();
+    assert_eq!(3, epoch_hash.epoch());
+    Ok(())
+}
 
-    // Get the VRF public key
-    let vrf_pk = akd.get_public_key().await?;
+// A VRF which holds key retrievals made while a transaction is open until the
+// gate is released, so that a test can pause a publish before it commits
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+#[derive(Clone)]
+struct GatedVrf {
+    storage: StorageManager<AsyncInMemoryDatabase>,
+    gate: std::sync::Arc<tokio::sync::Mutex<()>>,
+    held: std::sync::Arc<tokio::sync::Notify>,
+}
 
-    // tombstone epochs 1 & 2
-    let tombstones = [
-        crate::storage::types::ValueStateKey("hello".as_bytes().to_vec(), 1u64),
-        crate::storage::types::ValueStateKey("hello".as_bytes().to_vec(), 2u64),
-    ];
-    storage.tombstone_value_states(&tombstones).await?;
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+#[async_trait::async_trait]
+impl VRFKeyStorage for GatedVrf {
+    async fn retrieve(&self) -> Result<Vec<u8>, crate::ecvrf::VrfError> {
+        if self.storage.is_transaction_active() {
+            self.held.notify_one();
+            let _released = self.gate.lock().await;
+        }
+        HardCodedAkdVRF {}.retrieve().await
+    }
+}
 
-    // Now get a history proof for this key
-    let (history_proof, root_hash) = akd
-        .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
-        .await?;
-    assert_eq!(5, history_proof.update_proofs.len());
+// A scheduler dropped during a publish lets the publish complete, after which
+// the directory publishes again
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+#[tokio::test]
+async fn test_publish_scheduler_dropped_during_publish() -> Result<(), AkdError> {
+    use crate::publish_scheduler::{PublishScheduler, PublishSchedulerConfig};
 
-    // If we request a proof with tombstones but without saying we're OK with tombstones, throw an err
-    let tombstones = key_history_verify(
-        vrf_pk.as_bytes(),
-        root_hash.hash(),
-        root_hash.epoch(),
-        AkdLabel::from_utf8_str("hello"),
-        history_proof.clone(),
-        HistoryVerificationParams::default(),
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = GatedVrf {
+        storage: storage.clone(),
+        gate: std::sync::Arc::new(tokio::sync::Mutex::new(())),
+        held: std::sync::Arc::new(tokio::sync::Notify::new()),
+    };
+    let akd = Directory::<_, _>::new(storage.clone(), vrf.clone(), false).await?;
+    let gate = vrf.gate.lock().await;
+    let mut epoch_hash = akd.watch_epoch_hash();
+    let scheduler = PublishScheduler::start(
+        akd.clone(),
+        PublishSchedulerConfig {
+            interval: std::time::Duration::from_secs(3600),
+            max_pending: 1,
+        },
     );
-    assert!(matches!(tombstones, Err(_)));
-
-    // We should be able to verify tombstones assuming the client is accepting
-    // of tombstoned states
-    let results = key_history_verify(
-        vrf_pk.as_bytes(),
-        root_hash.hash(),
-        root_hash.epoch(),
-        AkdLabel::from_utf8_str("hello"),
-        history_proof,
-        HistoryVerificationParams::AllowMissingValues,
-    )?;
-    assert_ne!(crate::TOMBSTONE, results[0].value.0);
-    assert_ne!(crate::TOMBSTONE, results[1].value.0);
-    assert_ne!(crate::TOMBSTONE, results[2].value.0);
-    assert_eq!(crate::TOMBSTONE, results[3].value.0);
-    assert_eq!(crate::TOMBSTONE, results[4].value.0);
+    scheduler.enqueue(
+        AkdLabel::from_utf8_str("user"),
+        AkdValue::from_utf8_str("value"),
+    );
+    vrf.held.notified().await;
+    assert!(storage.is_transaction_active());
+    drop(scheduler);
+    drop(gate);
 
+    tokio::time::timeout(std::time::Duration::from_secs(30), async {
+        while epoch_hash.borrow().epoch() == 0 {
+            epoch_hash
+                .changed()
+                .await
+                .expect("The directory was dropped");
+        }
+    })
+    .await
+    .expect("The publish didn't complete");
+    assert!(!storage.is_transaction_active());
+    let epoch_hash = akd
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world"),
+        )])
+        .await?;
+    assert_eq!(2, epoch_hash.epoch());
     Ok(())
 }
 
-// Test coverage on issue #144, verification failures with
-// small trees (<4 nodes) in both the tests below
-// Note that the use of a VRF means that that the label
-// depends on the hash function being used.
-// The below two tests are identical except for the hash function being used.
+// An in-memory database whose reads yield to the executor before reading, and
+// which are delayed by the read delay when it's set, so that a test can observe
+// or time out an operation part way through its reads
+#[derive(Clone)]
+struct SlowDatabase {
+    db: AsyncInMemoryDatabase,
+    read_delay: std::sync::Arc<std::sync::Mutex<Option<std::time::Duration>>>,
+}
 
-// Test lookup in a smaller tree with 2 leaves, using the Blake3 hash function.
-#[tokio::test]
-async fn test_simple_lookup_for_small_tree_blake() -> Result<(), AkdError> {
-    let db = AsyncInMemoryDatabase::new();
-    let storage = StorageManager::new_no_cache(db);
-    let vrf = HardCodedAkdVRF {};
-    // epoch 0
-    let akd = Directory::<_, _>::new(storage, vrf.clone(), false).await?;
+impl SlowDatabase {
+    fn new() -> Self {
+        Self {
+            db: AsyncInMemoryDatabase::new(),
+            read_delay: Default::default(),
+        }
+    }
 
-    // Create a set with 2 updates, (label, value) pairs
-    // ("hello10", "hello10")
-    // ("hello11", "hello11")
-    let mut updates = vec![];
-    for i in 0..1 {
-        updates.push((
-            AkdLabel(format!("hello1{}", i).as_bytes().to_vec()),
-            AkdValue(format!("hello1{}", i).as_bytes().to_vec()),
-        ));
+    fn set_read_delay(&self, delay: Option<std::time::Duration>) {
+        *self.read_delay.lock().unwrap() = delay;
     }
-    // Publish the updates. Now the akd's epoch will be 1.
-    akd.publish(updates).await?;
 
-    // The label we will lookup is "hello10"
-    let target_label = AkdLabel(format!("hello1{}", 0).as_bytes().to_vec());
+    async fn before_read(&self) {
+        tokio::task::yield_now().await;
+        let delay = *self.read_delay.lock().unwrap();
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
 
-    // retrieve the lookup proof
-    let (lookup_proof, root_hash) = akd.lookup(target_label.clone()).await?;
+#[async_trait::async_trait]
+impl Database for SlowDatabase {
+    async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+        self.db.set(record).await
+    }
 
-    // Get the VRF public key
-    let vrf_pk = vrf.get_vrf_public_key().await?;
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        state: crate::storage::DbSetState,
+    ) -> Result<(), StorageError> {
+        self.db.batch_set(records, state).await
+    }
 
-    // perform the "traditional" AKD verification
-    let akd_result = crate::client::lookup_verify(
+    async fn compare_and_set(
+        &self,
+        record: DbRecord,
+        expected_version: Option<u64>,
+        others: Vec<DbRecord>,
+        state: crate::storage::DbSetState,
+    ) -> Result<(), StorageError> {
+        self.db
+            .compare_and_set(record, expected_version, others, state)
+            .await
+    }
+
+    async fn get<St: crate::storage::Storable>(
+        &self,
+        id: &St::StorageKey,
+    ) -> Result<DbRecord, StorageError> {
+        self.before_read().await;
+        self.db.get::<St>(id).await
+    }
+
+    async fn batch_get<St: crate::storage::Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        self.before_read().await;
+        self.db.batch_get::<St>(ids).await
+    }
+
+    async fn batch_delete<St: crate::storage::Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<(), StorageError> {
+        self.db.batch_delete::<St>(ids).await
+    }
+
+    async fn get_user_data(
+        &self,
+        username: &AkdLabel,
+    ) -> Result<crate::storage::types::KeyData, StorageError> {
+        self.before_read().await;
+        self.db.get_user_data(username).await
+    }
+
+    async fn get_user_state(
+        &self,
+        username: &AkdLabel,
+        flag: crate::storage::types::ValueStateRetrievalFlag,
+    ) -> Result<ValueState, StorageError> {
+        self.before_read().await;
+        self.db.get_user_state(username, flag).await
+    }
+
+    async fn get_user_state_versions(
+        &self,
+        usernames: &[AkdLabel],
+        flag: crate::storage::types::ValueStateRetrievalFlag,
+    ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        self.before_read().await;
+        self.db.get_user_state_versions(usernames, flag).await
+    }
+
+    fn iter_by_prefix(
+        &self,
+        storage_type: crate::storage::types::StorageType,
+        key_prefix: &[u8],
+    ) -> crate::storage::RecordStream<'_> {
+        self.db.iter_by_prefix(storage_type, key_prefix)
+    }
+}
+
+// A publish whose future is dropped part way rolls its transaction back, so that
+// the directory can publish again
+#[tokio::test]
+async fn test_dropped_publish_rolls_back() -> Result<(), AkdError> {
+    use core::future::Future;
+    use core::task::Poll;
+
+    // the reads of the publish yield, whether or not it spawns any tasks
+    let db = SlowDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage.clone(), vrf, false).await?;
+
+    let updates = (0..500)
+        .map(|i| {
+            (
+                AkdLabel::from_utf8_str(&format!("user{}", i)),
+                AkdValue::from_utf8_str("value"),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut publish = Box::pin(akd.publish(updates));
+    let started = futures_util::future::poll_fn(|cx| match publish.as_mut().poll(cx) {
+        Poll::Ready(_) => Poll::Ready(false),
+        Poll::Pending if storage.is_transaction_active() => Poll::Ready(true),
+        Poll::Pending => Poll::Pending,
+    })
+    .await;
+    assert!(started, "The publish completed without yielding");
+    drop(publish);
+
+    assert!(!storage.is_transaction_active());
+    let epoch_hash = akd
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world"),
+        )])
+        .await?;
+    assert_eq!(1, epoch_hash.epoch());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_read_during_publish() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db.clone());
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+
+    // Publish once
+    akd.publish(vec![
+        (
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world"),
+        ),
+        (
+            AkdLabel::from_utf8_str("hello2"),
+            AkdValue::from_utf8_str("world2"),
+        ),
+    ])
+    .await?;
+    // Get the root hash after the first publish
+    let root_hash_1 = akd
+        .get_root_hash(&akd.retrieve_current_azks().await?)
+        .await?;
+    // Publish updates for the same labels.
+    akd.publish(vec![
+        (
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world_2"),
+        ),
+        (
+            AkdLabel::from_utf8_str("hello2"),
+            AkdValue::from_utf8_str("world2_2"),
+        ),
+    ])
+    .await?;
+
+    // Get the root hash after the second publish
+    let root_hash_2 = akd
+        .get_root_hash(&akd.retrieve_current_azks().await?)
+        .await?;
+
+    // Make the current azks a "checkpoint" to reset to later
+    let checkpoint_azks = akd.retrieve_current_azks().await.unwrap();
+
+    // Publish for the third time
+    akd.publish(vec![
+        (
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world_3"),
+        ),
+        (
+            AkdLabel::from_utf8_str("hello2"),
+            AkdValue::from_utf8_str("world2_3"),
+        ),
+    ])
+    .await?;
+
+    // Reset the azks record back to previous epoch, to emulate an akd reader
+    // communicating with storage that is in the middle of a publish operation
+    db.set(DbRecord::Azks(checkpoint_azks))
+        .await
+        .expect("Error resetting directory to previous epoch");
+
+    // History proof should not contain the third epoch's update but still verify
+    let (history_proof, root_hash) = akd
+        .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
+        .await?;
+    // Get the VRF public key
+    let vrf_pk = akd.get_public_key().await?;
+    key_history_verify(
         vrf_pk.as_bytes(),
         root_hash.hash(),
-        target_label.clone(),
-        lookup_proof,
+        root_hash.epoch(),
+        AkdLabel::from_utf8_str("hello"),
+        history_proof,
+        HistoryVerificationParams::default(),
     )?;
 
-    // check the two results to make sure they both verify
+    // Lookup proof should contain the checkpoint epoch's value and still verify
+    let (lookup_proof, root_hash) = akd.lookup(AkdLabel::from_utf8_str("hello")).await?;
     assert_eq!(
-        akd_result,
-        VerifyResult {
-            epoch: 1,
-            version: 1,
-            value: AkdValue::from_utf8_str("hello10"),
-        },
+        AkdValue::from_utf8_str("world_2"),
+        lookup_proof.plaintext_value
     );
+    lookup_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        AkdLabel::from_utf8_str("hello"),
+        lookup_proof,
+    )?;
+
+    // Audit proof should only work up until checkpoint's epoch
+    let audit_proof = akd.audit(1, 2).await?;
+    audit_verify(vec![root_hash_1, root_hash_2], audit_proof).await?;
+
+    let invalid_audit = akd.audit(2, 3).await;
+    assert!(matches!(invalid_audit, Err(_)));
 
     Ok(())
 }
 
-// Test lookup in a smaller tree with 2 leaves, using the Sha3 hash function.
-#[cfg(feature = "sha3_256")]
+// The read-only mode of a directory is meant to simply read from memory.
+// This test makes sure it throws errors appropriately, i.e. when trying to
+// write to a read-only directory and when trying to read a directory when none
+// exists in storage.
 #[tokio::test]
-async fn test_simple_lookup_for_small_tree_sha256() -> Result<(), AkdError> {
+async fn test_directory_read_only_mode() -> Result<(), AkdError> {
     let db = AsyncInMemoryDatabase::new();
     let storage = StorageManager::new_no_cache(db);
     let vrf = HardCodedAkdVRF {};
-    // epoch 0
-    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+    // There is no AZKS object in the storage layer, directory construction should fail
+    let akd = Directory::<_, _>::new(storage.clone(), vrf.clone(), true).await;
+    assert!(matches!(akd, Err(_)));
 
-    // Create a set with 2 updates, (label, value) pairs
-    // ("hello10", "hello10")
-    // ("hello11", "hello11")
-    let mut updates = vec![];
-    for i in 0..1 {
-        updates.push((
-            AkdLabel(format!("hello{}", i).as_bytes().to_vec()),
-            AkdValue(format!("hello{}", i).as_bytes().to_vec()),
-        ));
-    }
+    // now create the AZKS
+    let akd = Directory::<_, _>::new(storage.clone(), vrf.clone(), false).await;
+    assert!(matches!(akd, Ok(_)));
 
-    // Publish the updates. Now the akd's epoch will be 1.
-    akd.publish(updates).await?;
+    // create another read-only dir now that the AZKS exists in the storage layer, and try to publish which should fail
+    let akd = Directory::<_, _>::new(storage, vrf, true).await?;
+    assert!(matches!(akd.publish(vec![]).await, Err(_)));
 
-    // The label we will lookup is "hello10"
-    let target_label = AkdLabel(format!("hello{}", 0).as_bytes().to_vec());
+    Ok(())
+}
 
-    // retrieve the lookup proof
-    let lookup_proof = akd.lookup(target_label.clone()).await?;
-    // retrieve the root hash
-    let current_azks = akd.retrieve_current_azks().await?;
-    let root_hash = akd.get_root_hash(&current_azks).await?;
+// Bulk initialization should produce exactly the same tree as publishing
+// the same entries, with lookups, history and later publishes working on top
+// of it, and should refuse to run on a directory which has been published to.
+#[tokio::test]
+async fn test_bulk_initialize() -> Result<(), AkdError> {
+    let entries = (0..100)
+        .map(|i| {
+            (
+                AkdLabel(format!("user{}", i).into_bytes()),
+                AkdValue(format!("value{}", i).into_bytes()),
+            )
+        })
+        .collect::<Vec<_>>();
+    let vrf = HardCodedAkdVRF {};
 
-    // Get the VRF public key
-    let vrf_pk = vrf.get_vrf_public_key().await?;
+    let published_storage = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    let published = Directory::<_, _>::new(published_storage, vrf.clone(), false).await?;
+    let published_hash = published.publish(entries.clone()).await?;
 
-    // perform the "traditional" AKD verification
-    let akd_result = crate::client::lookup_verify(
+    let bulk_storage = StorageManager::new(AsyncInMemoryDatabase::new(), None, None, None);
+    let bulk = Directory::<_, _>::new(bulk_storage, vrf, false).await?;
+    let bulk_hash = bulk.bulk_initialize(entries.clone()).await?;
+    assert_eq!(published_hash.epoch(), bulk_hash.epoch());
+    assert_eq!(published_hash.hash(), bulk_hash.hash());
+
+    let current_azks = bulk.retrieve_current_azks().await?;
+    assert_eq!(
+        published.retrieve_current_azks().await?.num_nodes,
+        current_azks.num_nodes
+    );
+
+    // a bulk initialization can only occur once
+    assert!(bulk.bulk_initialize(entries).await.is_err());
+
+    let vrf_pk = bulk.get_public_key().await?;
+    let (lookup_proof, root_hash) = bulk.lookup(AkdLabel::from_utf8_str("user42")).await?;
+    lookup_verify(
         vrf_pk.as_bytes(),
-        root_hash,
-        target_label.clone(),
+        root_hash.hash(),
+        AkdLabel::from_utf8_str("user42"),
         lookup_proof,
     )?;
 
-    // check the two results to make sure they both verify
-    assert_eq!(
-        akd_result,
-        VerifyResult {
-            epoch: 1,
-            version: 1,
-            value: AkdValue::from_utf8_str("hello0"),
-        },
-    );
+    // a regular publish on top of the bulk-initialized tree
+    bulk.publish(vec![(
+        AkdLabel::from_utf8_str("user42"),
+        AkdValue::from_utf8_str("value42b"),
+    )])
+    .await?;
+    let (history_proof, root_hash) = bulk
+        .key_history(&AkdLabel::from_utf8_str("user42"), HistoryParams::default())
+        .await?;
+    key_history_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        root_hash.epoch(),
+        AkdLabel::from_utf8_str("user42"),
+        history_proof,
+        HistoryVerificationParams::default(),
+    )?;
+
+    let audit_proof = bulk.audit(1, 2).await?;
+    let bulk_hash_2 = bulk
+        .get_root_hash(&bulk.retrieve_current_azks().await?)
+        .await?;
+    audit_verify(vec![bulk_hash.hash(), bulk_hash_2], audit_proof).await?;
 
     Ok(())
 }
 
-/*
-=========== Test Helpers ===========
-*/
+// A bulk initialization overtaken by a publish of another writer fails without
+// writing any of its records, leaving the published tree intact
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+#[tokio::test]
+async fn test_bulk_initialize_conflict() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let bulk_storage = StorageManager::new_no_cache(db.clone());
+    let vrf = GatedVrf {
+        storage: bulk_storage.clone(),
+        gate: std::sync::Arc::new(tokio::sync::Mutex::new(())),
+        held: std::sync::Arc::new(tokio::sync::Notify::new()),
+    };
+    let bulk = Directory::<_, _>::new(bulk_storage.clone(), vrf.clone(), false).await?;
+    let published = Directory::<_, _>::new(
+        StorageManager::new_no_cache(db.clone()),
+        HardCodedAkdVRF {},
+        false,
+    )
+    .await?;
 
-async fn async_poll_helper_proof<T: Database + 'static, V: VRFKeyStorage>(
-    reader: &Directory<T, V>,
-    value: AkdValue,
-) -> Result<(), AkdError> {
-    // reader should read "hello" and this will populate the "cache" a log
-    let (lookup_proof, root_hash) = reader.lookup(AkdLabel::from_utf8_str("hello")).await?;
-    assert_eq!(value, lookup_proof.plaintext_value);
-    let pk = reader.get_public_key().await?;
+    let gate = vrf.gate.lock().await;
+    let entries = (0..50)
+        .map(|i| {
+            (
+                AkdLabel(format!("bulk{}", i).into_bytes()),
+                AkdValue(format!("value{}", i).into_bytes()),
+            )
+        })
+        .collect::<Vec<_>>();
+    let initialization = tokio::spawn(async move { bulk.bulk_initialize(entries).await });
+    tokio::time::timeout(std::time::Duration::from_secs(10), vrf.held.notified())
+        .await
+        .expect("The bulk initialization didn't sign its tree head in a transaction");
+    assert!(bulk_storage.is_transaction_active());
+
+    let epoch_hash = published
+        .publish(vec![(
+            AkdLabel::from_utf8_str("user"),
+            AkdValue::from_utf8_str("value"),
+        )])
+        .await?;
+    drop(gate);
+    assert!(matches!(
+        initialization
+            .await
+            .expect("The bulk initialization panicked"),
+        Err(AkdError::Storage(StorageError::Conflict(_)))
+    ));
+
+    // only the published records are in storage
+    let azks = published.retrieve_current_azks().await?;
+    assert_eq!(1, azks.get_latest_epoch());
+    let nodes = db
+        .batch_get_type_direct::<TreeNodeWithPreviousValue>()
+        .await?;
+    assert_eq!(azks.num_nodes, nodes.len() as u64);
+    assert_eq!(1, db.batch_get_type_direct::<ValueState>().await?.len());
+    let report = published.check_integrity(1).await?;
+    assert!(report.is_ok(), "{:?}", report.issues);
+    let (proof, root_hash) = published.lookup(AkdLabel::from_utf8_str("user")).await?;
+    assert_eq!(epoch_hash, root_hash);
     lookup_verify(
-        pk.as_bytes(),
+        published.get_public_key().await?.as_bytes(),
         root_hash.hash(),
-        AkdLabel::from_utf8_str("hello"),
-        lookup_proof,
+        AkdLabel::from_utf8_str("user"),
+        proof,
     )?;
+
+    Ok(())
+}
+
+// Directories in different namespaces of the same storage backend should be
+// fully independent of each other.
+#[tokio::test]
+async fn test_namespaced_directories() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let vrf = HardCodedAkdVRF {};
+    let akd_a = Directory::<_, _>::new(
+        StorageManager::new_no_cache(db.with_namespace(b"tenant_a").await?),
+        vrf.clone(),
+        false,
+    )
+    .await?;
+    let akd_b = Directory::<_, _>::new(
+        StorageManager::new_no_cache(db.with_namespace(b"tenant_b").await?),
+        vrf.clone(),
+        false,
+    )
+    .await?;
+
+    akd_a
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world"),
+        )])
+        .await?;
+    for i in 0..3 {
+        akd_b
+            .publish(vec![(
+                AkdLabel::from_utf8_str("hello2"),
+                AkdValue(format!("world{}", i).into_bytes()),
+            )])
+            .await?;
+    }
+
+    assert_eq!(1, akd_a.retrieve_current_azks().await?.latest_epoch);
+    assert_eq!(3, akd_b.retrieve_current_azks().await?.latest_epoch);
+    // the un-namespaced handle has no directory at all
+    assert!(
+        Directory::<_, _>::new(StorageManager::new_no_cache(db), vrf, true)
+            .await
+            .is_err()
+    );
+
+    let vrf_pk = akd_a.get_public_key().await?;
+    let (lookup_proof, root_hash) = akd_a.lookup(AkdLabel::from_utf8_str("hello")).await?;
+    lookup_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        AkdLabel::from_utf8_str("hello"),
+        lookup_proof,
+    )?;
+    assert!(akd_a
+        .lookup(AkdLabel::from_utf8_str("hello2"))
+        .await
+        .is_err());
+    assert!(akd_b
+        .lookup(AkdLabel::from_utf8_str("hello"))
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+// The epoch hash held by a directory should follow the publishes made through
+// it or its clones, and be loaded from storage by a new directory.
+#[tokio::test]
+async fn test_epoch_hash_watch() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage.clone(), HardCodedAkdVRF {}, false).await?;
+    let (root_hash, epoch) = crate::directory::get_directory_root_hash_and_ep(&akd).await?;
+    assert_eq!(EpochHash(epoch, root_hash), akd.get_epoch_hash());
+
+    let mut watch = akd.watch_epoch_hash();
+    let epoch_hash = akd
+        .clone()
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world"),
+        )])
+        .await?;
+    watch.changed().await.unwrap();
+    assert_eq!(epoch_hash, *watch.borrow());
+    assert_eq!(epoch_hash, akd.get_epoch_hash());
+
+    let reader = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, true).await?;
+    assert_eq!(epoch_hash, reader.get_epoch_hash());
+    Ok(())
+}
+
+// The root hash of every published epoch should be archived, along with the
+// number of updates it published, and be retrievable once the tree has moved on.
+#[tokio::test]
+async fn test_root_hash_history() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    // epoch 0 has no archived record, and is reconstructed from the tree
+    assert_eq!(akd.get_epoch_hash().1, akd.get_root_hash_at_epoch(0).await?);
+    assert!(akd.root_hash_history(..).await?.is_empty());
+
+    let mut epoch_hashes = vec![];
+    for epoch in 1..=4u64 {
+        let updates = (0..epoch)
+            .map(|i| {
+                (
+                    AkdLabel::from_utf8_str(&format!("user{}", i)),
+                    AkdValue::from_utf8_str(&format!("value{}", epoch)),
+                )
+            })
+            .collect::<Vec<_>>();
+        epoch_hashes.push(akd.publish(updates).await?);
+    }
+
+    let history = akd.root_hash_history(..).await?;
+    assert_eq!(4, history.len());
+    for (record, (batch_size, epoch_hash)) in history.iter().zip((1u64..).zip(epoch_hashes.iter()))
+    {
+        assert_eq!(epoch_hash.0, record.epoch);
+        assert_eq!(epoch_hash.1, record.root_hash);
+        assert_eq!(batch_size, record.batch_size);
+        assert_eq!(
+            akd.get_tree_head(record.epoch).await?.timestamp,
+            record.timestamp
+        );
+    }
+    assert_eq!(
+        vec![2, 3],
+        akd.root_hash_history(2..4)
+            .await?
+            .iter()
+            .map(|record| record.epoch)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(1, akd.root_hash_history(4..=10).await?.len());
+    assert!(akd.root_hash_history(5..).await?.is_empty());
+
+    // the early epochs can no longer be reconstructed from the tree
+    assert_eq!(epoch_hashes[0].1, akd.get_root_hash_at_epoch(1).await?);
+    assert_eq!(epoch_hashes[3].1, akd.get_root_hash_at_epoch(4).await?);
+    assert!(matches!(
+        akd.get_root_hash_at_epoch(5).await,
+        Err(AkdError::Directory(DirectoryError::InvalidEpoch(_)))
+    ));
+    Ok(())
+}
+
+// The metadata attached to an epoch when publishing it should be stored with its
+// archived root hash, and be returned by the root hash queries.
+#[tokio::test]
+async fn test_epoch_metadata() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    assert!(akd.get_epoch_metadata(0).await?.is_empty());
+
+    akd.publish_with_options(
+        vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world"),
+        )],
+        PublishOptions::default()
+            .with_metadata("release", "v1.2.0")
+            .with_metadata("ticket", "CHG-42")
+            .with_metadata("publisher", "alice"),
+    )
+    .await?;
+    akd.publish(vec![(
+        AkdLabel::from_utf8_str("hello"),
+        AkdValue::from_utf8_str("world2"),
+    )])
+    .await?;
+
+    let metadata = akd.get_epoch_metadata(1).await?;
+    assert_eq!(3, metadata.len());
+    assert_eq!(Some("v1.2.0"), metadata.get("release").map(String::as_str));
+    assert_eq!(Some("CHG-42"), metadata.get("ticket").map(String::as_str));
+    assert_eq!(Some("alice"), metadata.get("publisher").map(String::as_str));
+    assert!(akd.get_epoch_metadata(2).await?.is_empty());
+    let history = akd.root_hash_history(..).await?;
+    assert_eq!(metadata, history[0].metadata);
+    assert!(history[1].metadata.is_empty());
+    assert!(matches!(
+        akd.get_epoch_metadata(3).await,
+        Err(AkdError::Directory(DirectoryError::InvalidEpoch(_)))
+    ));
+
+    // the metadata survives its binary encoding
+    let encoded = history[0].metadata_to_bytes();
+    assert_eq!(Ok(metadata), RootHashRecord::metadata_from_bytes(&encoded));
+    assert!(RootHashRecord::metadata_from_bytes(&encoded[..encoded.len() - 1]).is_err());
+    Ok(())
+}
+
+// The estimated proof sizes should be close to the sizes of the actual proofs,
+// the audit estimate being an upper bound.
+#[tokio::test]
+async fn test_estimate_proof_sizes() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    for epoch in 0..3 {
+        let updates = (0..100)
+            .map(|i| {
+                (
+                    AkdLabel::from_utf8_str(&format!("user{}", i)),
+                    AkdValue::from_utf8_str(&format!("value{}", epoch)),
+                )
+            })
+            .collect::<Vec<_>>();
+        akd.publish(updates).await?;
+    }
+
+    let label = AkdLabel::from_utf8_str("user7");
+    let estimate = akd.estimate_lookup_proof_size(label.clone()).await?;
+    let actual = akd.lookup(label).await?.0.size_of();
+    assert!(estimate * 3 >= actual * 2 && estimate * 2 <= actual * 3);
+    assert!(matches!(
+        akd.estimate_lookup_proof_size(AkdLabel::from_utf8_str("unknown"))
+            .await,
+        Err(AkdError::Storage(StorageError::NotFound(_)))
+    ));
+
+    let estimate = akd.estimate_audit_proof_size(1, 3).await?;
+    let proof = akd.audit(1, 3).await?;
+    let actual = proof.epochs.len() * std::mem::size_of::<u64>()
+        + proof
+            .proofs
+            .iter()
+            .flat_map(|proof| proof.inserted.iter().chain(proof.unchanged_nodes.iter()))
+            .map(|node| node.size_of())
+            .sum::<usize>();
+    assert!(estimate >= actual);
+    assert!(matches!(
+        akd.estimate_audit_proof_size(2, 4).await,
+        Err(AkdError::Directory(DirectoryError::InvalidEpoch(_)))
+    ));
+    Ok(())
+}
+
+// A directory backed by an in-memory database should survive a restart through a
+// snapshot of the database.
+#[cfg(feature = "memory_snapshots")]
+#[tokio::test]
+async fn test_memory_snapshot() -> Result<(), AkdError> {
+    let path = std::env::temp_dir().join(format!("akd_snapshot_{}.bin", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    // there is no snapshot on the first start
+    let db = AsyncInMemoryDatabase::load_snapshot(&path)?;
+    let storage = StorageManager::new_no_cache(db.clone());
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    akd.publish(vec![(
+        AkdLabel::from_utf8_str("hello"),
+        AkdValue::from_utf8_str("world"),
+    )])
+    .await?;
+    let epoch_hash = akd
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world2"),
+        )])
+        .await?;
+    db.with_namespace(b"other")
+        .await?
+        .set(DbRecord::Azks(akd.retrieve_current_azks().await?))
+        .await?;
+    db.save_snapshot(&path).await?;
+
+    let restored = AsyncInMemoryDatabase::load_snapshot(&path)?;
+    std::fs::remove_file(&path).expect("Failed to remove the snapshot");
+    assert_eq!(
+        db.batch_get_all_direct().await?.len(),
+        restored.batch_get_all_direct().await?.len()
+    );
+    assert!(restored
+        .with_namespace(b"other")
+        .await?
+        .get::<crate::Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+        .await
+        .is_ok());
+    let storage = StorageManager::new_no_cache(restored);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    assert_eq!(epoch_hash, akd.get_epoch_hash());
+    let (proof, _) = akd.lookup(AkdLabel::from_utf8_str("hello")).await?;
+    assert_eq!(AkdValue::from_utf8_str("world2"), proof.plaintext_value);
+
+    // a corrupted snapshot is rejected
+    std::fs::write(&path, b"not a snapshot").expect("Failed to write the snapshot");
+    let result = AsyncInMemoryDatabase::load_snapshot(&path);
+    std::fs::remove_file(&path).expect("Failed to remove the snapshot");
+    assert!(matches!(result, Err(StorageError::Corruption(_))));
+    Ok(())
+}
+
+// Operations with a deadline should complete if it is far enough, and fail with a
+// typed error once it has passed
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+#[tokio::test]
+async fn test_operation_deadlines() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let label = AkdLabel::from_utf8_str("hello");
+    akd.publish(vec![(label.clone(), AkdValue::from_utf8_str("world"))])
+        .await?;
+    akd.publish(vec![(label.clone(), AkdValue::from_utf8_str("world2"))])
+        .await?;
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(60);
+    akd.lookup_with_deadline(label.clone(), deadline).await?;
+    akd.key_history_with_deadline(&label, HistoryParams::default(), deadline)
+        .await?;
+    akd.audit_with_deadline(1, 2, deadline).await?;
+
+    let passed = std::time::Instant::now();
+    let errors = vec![
+        akd.lookup_with_deadline(label.clone(), passed)
+            .await
+            .unwrap_err(),
+        akd.key_history_with_deadline(&label, HistoryParams::default(), passed)
+            .await
+            .unwrap_err(),
+        akd.audit_with_deadline(1, 2, passed).await.unwrap_err(),
+    ];
+    for err in errors {
+        assert!(matches!(
+            err,
+            AkdError::Directory(DirectoryError::DeadlineExceeded(_))
+        ));
+        assert_eq!(ErrorCode::DeadlineExceeded, err.code());
+        assert!(err.is_retryable());
+    }
+    Ok(())
+}
+
+// An operation whose storage reads stall past its deadline fails promptly once
+// the deadline passes, and the stalled reads themselves time out at the deadline
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+#[tokio::test]
+async fn test_operation_deadlines_with_slow_storage() -> Result<(), AkdError> {
+    use std::time::{Duration, Instant};
+
+    let db = SlowDatabase::new();
+    let storage = StorageManager::new_no_cache(db.clone());
+    let akd = Directory::<_, _>::new(storage.clone(), HardCodedAkdVRF {}, false).await?;
+    let label = AkdLabel::from_utf8_str("hello");
+    akd.publish(vec![(label.clone(), AkdValue::from_utf8_str("world"))])
+        .await?;
+    akd.publish(vec![(label.clone(), AkdValue::from_utf8_str("world2"))])
+        .await?;
+
+    db.set_read_delay(Some(Duration::from_secs(60)));
+    let timeout = Duration::from_millis(200);
+
+    let start = Instant::now();
+    let read = storage
+        .with_deadline(start + timeout)
+        .get::<crate::Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+        .await;
+    assert!(matches!(read, Err(StorageError::Timeout(_))));
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    let start = Instant::now();
+    // each operation reaches its stalled reads, and waits for its deadline at most
+    let errors = vec![
+        akd.lookup_with_deadline(label.clone(), Instant::now() + timeout)
+            .await
+            .unwrap_err(),
+        akd.key_history_with_deadline(&label, HistoryParams::default(), Instant::now() + timeout)
+            .await
+            .unwrap_err(),
+        akd.audit_with_deadline(1, 2, Instant::now() + timeout)
+            .await
+            .unwrap_err(),
+    ];
+    assert!(start.elapsed() < Duration::from_secs(10));
+    for err in errors {
+        assert!(matches!(
+            err,
+            AkdError::Directory(DirectoryError::DeadlineExceeded(_))
+        ));
+    }
+    Ok(())
+}
+
+// A file-backed trust store should persist the last verified root hash of each
+// directory, and reject rollbacks and forks of it
+#[tokio::test]
+async fn test_file_trust_store() -> Result<(), AkdError> {
+    use crate::client::{
+        key_history_verify_with_trust_store, lookup_verify_with_trust_store, FileTrustStore,
+        TrustStore,
+    };
+
+    let path = std::env::temp_dir().join(format!("akd_trust_store_{}.txt", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let mut epoch_hashes = vec![];
+    for i in 0..3 {
+        epoch_hashes.push(
+            akd.publish(vec![(
+                AkdLabel::from_utf8_str("hello"),
+                AkdValue::from_utf8_str(&format!("world{}", i)),
+            )])
+            .await?,
+        );
+    }
+
+    let store = FileTrustStore::new(&path);
+    assert_eq!(None, store.get("dir")?);
+    store.record("dir", epoch_hashes[0].clone())?;
+    store.record("dir", epoch_hashes[1].clone())?;
+    store.record("dir", epoch_hashes[1].clone())?;
+    store.record("other", epoch_hashes[0].clone())?;
+
+    // the state survives a restart of the client
+    let store = FileTrustStore::new(&path);
+    assert_eq!(Some(epoch_hashes[1].clone()), store.get("dir")?);
+    assert_eq!(Some(epoch_hashes[0].clone()), store.get("other")?);
+    let rollback = store.record("dir", epoch_hashes[0].clone());
+    assert!(matches!(
+        rollback,
+        Err(AkdError::Directory(DirectoryError::Verification(
+            VerificationError::Rollback(_)
+        )))
+    ));
+    let fork = store.record(
+        "dir",
+        EpochHash(epoch_hashes[1].epoch(), epoch_hashes[2].hash()),
+    );
+    assert!(matches!(
+        fork,
+        Err(AkdError::Directory(DirectoryError::Verification(
+            VerificationError::Rollback(_)
+        )))
+    ));
+    store.record("dir", epoch_hashes[2].clone())?;
+    assert_eq!(Some(epoch_hashes[2].clone()), store.get("dir")?);
+    assert!(store.record("with space", epoch_hashes[0].clone()).is_err());
+
+    // concurrent records never move the state backwards, whichever order they run in
+    let store = std::sync::Arc::new(store);
+    let handles = epoch_hashes
+        .iter()
+        .rev()
+        .cloned()
+        .map(|epoch_hash| {
+            let store = store.clone();
+            std::thread::spawn(move || {
+                let _ = store.record("concurrent", epoch_hash);
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().expect("Failed to join the recording thread");
+    }
+    assert_eq!(Some(epoch_hashes[2].clone()), store.get("concurrent")?);
+
+    // verifying through the store records the verified root, and rejects proofs
+    // served at an older epoch afterwards
+    let vrf_pk = akd.get_public_key().await?;
+    let label = AkdLabel::from_utf8_str("hello");
+    let (old_proof, old_epoch_hash) = akd.lookup(label.clone()).await?;
+    let result = lookup_verify_with_trust_store(
+        store.as_ref(),
+        "verified",
+        vrf_pk.as_bytes(),
+        old_epoch_hash.clone(),
+        label.clone(),
+        old_proof.clone(),
+    )?;
+    assert_eq!(AkdValue::from_utf8_str("world2"), result.value);
+    assert_eq!(Some(old_epoch_hash.clone()), store.get("verified")?);
+    akd.publish(vec![(label.clone(), AkdValue::from_utf8_str("world3"))])
+        .await?;
+    let (history_proof, epoch_hash) = akd.key_history(&label, HistoryParams::default()).await?;
+    key_history_verify_with_trust_store(
+        store.as_ref(),
+        "verified",
+        vrf_pk.as_bytes(),
+        epoch_hash.clone(),
+        label.clone(),
+        history_proof,
+        HistoryVerificationParams::default(),
+    )?;
+    assert_eq!(Some(epoch_hash), store.get("verified")?);
+    let rollback = lookup_verify_with_trust_store(
+        store.as_ref(),
+        "verified",
+        vrf_pk.as_bytes(),
+        old_epoch_hash,
+        label,
+        old_proof,
+    );
+    assert!(matches!(
+        rollback,
+        Err(AkdError::Directory(DirectoryError::Verification(
+            VerificationError::Rollback(_)
+        )))
+    ));
+
+    std::fs::write(&path, "dir 1 not-hex\n").expect("Failed to write the trust store");
+    let corrupted = store.get("dir");
+    std::fs::remove_file(&path).expect("Failed to remove the trust store");
+    assert!(matches!(
+        corrupted,
+        Err(AkdError::Storage(StorageError::Corruption(_)))
+    ));
+    Ok(())
+}
+
+// Subscribers to a directory should be notified of publishes, lookups and
+// audits, including those performed through a clone of the directory.
+#[tokio::test]
+async fn test_directory_events() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+    let mut events = akd.subscribe();
+
+    let epoch_hash_1 = akd
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world"),
+        )])
+        .await?;
+    let epoch_hash_2 = akd
+        .clone()
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world2"),
+        )])
+        .await?;
+    // re-publishing the same value doesn't publish a new epoch
+    akd.publish(vec![(
+        AkdLabel::from_utf8_str("hello"),
+        AkdValue::from_utf8_str("world2"),
+    )])
+    .await?;
+    akd.lookup(AkdLabel::from_utf8_str("hello")).await?;
+    akd.audit(1, 2).await?;
+
+    assert_eq!(
+        DirectoryEvent::EpochPublished(epoch_hash_1),
+        events.try_recv().unwrap()
+    );
+    assert_eq!(
+        DirectoryEvent::EpochPublished(epoch_hash_2.clone()),
+        events.try_recv().unwrap()
+    );
+    assert_eq!(
+        DirectoryEvent::LookupServed(epoch_hash_2),
+        events.try_recv().unwrap()
+    );
+    assert_eq!(
+        DirectoryEvent::AuditRequested {
+            start_epoch: 1,
+            end_epoch: 2
+        },
+        events.try_recv().unwrap()
+    );
+    assert!(events.try_recv().is_err());
+
+    Ok(())
+}
+
+// This test is meant to test the function poll_for_azks_change
+// which is meant to detect changes in the azks, to prevent inconsistencies
+// between the local cache and storage.
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+#[tokio::test]
+async fn test_directory_polling_azks_change() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new(db, None, None, None);
+    let vrf = HardCodedAkdVRF {};
+    // writer will write the AZKS record
+    let writer = Directory::<_, _>::new(storage.clone(), vrf.clone(), false).await?;
+
+    writer
+        .publish(vec![
+            (
+                AkdLabel::from_utf8_str("hello"),
+                AkdValue::from_utf8_str("world"),
+            ),
+            (
+                AkdLabel::from_utf8_str("hello2"),
+                AkdValue::from_utf8_str("world2"),
+            ),
+        ])
+        .await?;
+
+    // reader will not write the AZKS but will be "polling" for AZKS changes
+    let reader = Directory::<_, _>::new(storage, vrf, true).await?;
+
+    // start the poller
+    let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+    let reader_clone = reader.clone();
+    let _join_handle = tokio::task::spawn(async move {
+        reader_clone
+            .poll_for_azks_changes(tokio::time::Duration::from_millis(100), Some(tx))
+            .await
+    });
+
+    // wait for a second to make sure the poller has started
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    // verify a lookup proof, which will populate the cache
+    async_poll_helper_proof(&reader, AkdValue::from_utf8_str("world")).await?;
+
+    // publish epoch 2
+    writer
+        .publish(vec![
+            (
+                AkdLabel::from_utf8_str("hello"),
+                AkdValue::from_utf8_str("world_2"),
+            ),
+            (
+                AkdLabel::from_utf8_str("hello2"),
+                AkdValue::from_utf8_str("world2_2"),
+            ),
+        ])
+        .await?;
+
+    // assert that the change is picked up in a reasonable time-frame and the cache is flushed
+    let notification = tokio::time::timeout(tokio::time::Duration::from_secs(10), rx.recv()).await;
+    assert!(matches!(notification, Ok(Some(()))));
+
+    async_poll_helper_proof(&reader, AkdValue::from_utf8_str("world_2")).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tombstoned_key_history() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    // epoch 0
+    let akd = Directory::<_, _>::new(storage.clone(), vrf, false).await?;
+
+    // epoch 1
+    akd.publish(vec![(
+        AkdLabel::from_utf8_str("hello"),
+        AkdValue::from_utf8_str("world"),
+    )])
+    .await?;
+
+    // epoch 2
+    akd.publish(vec![(
+        AkdLabel::from_utf8_str("hello"),
+        AkdValue::from_utf8_str("world2"),
+    )])
+    .await?;
+
+    // epoch 3
+    akd.publish(vec![(
+        AkdLabel::from_utf8_str("hello"),
+        AkdValue::from_utf8_str("world3"),
+    )])
+    .await?;
+
+    // epoch 4
+    akd.publish(vec![(
+        AkdLabel::from_utf8_str("hello"),
+        AkdValue::from_utf8_str("world4"),
+    )])
+    .await?;
+
+    // epoch 5
+    akd.publish(vec![(
+        AkdLabel::from_utf8_str("hello"),
+        AkdValue::from_utf8_str("world5"),
+    )])
+    .await?;
+
+    // Epochs 1-5, we're going to tombstone 1 & 2
+
+    // Get the VRF public key
+    let vrf_pk = akd.get_public_key().await?;
+
+    // tombstone epochs 1 & 2
+    let tombstones = [
+        crate::storage::types::ValueStateKey("hello".as_bytes().to_vec(), 1u64),
+        crate::storage::types::ValueStateKey("hello".as_bytes().to_vec(), 2u64),
+    ];
+    storage.tombstone_value_states(&tombstones).await?;
+
+    // Now get a history proof for this key
+    let (history_proof, root_hash) = akd
+        .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
+        .await?;
+    assert_eq!(5, history_proof.update_proofs.len());
+
+    // If we request a proof with tombstones but without saying we're OK with tombstones, throw an err
+    let tombstones = key_history_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        root_hash.epoch(),
+        AkdLabel::from_utf8_str("hello"),
+        history_proof.clone(),
+        HistoryVerificationParams::default(),
+    );
+    assert!(matches!(
+        tombstones,
+        Err(VerificationError::HistoryProof(_))
+    ));
+
+    // We should be able to verify tombstones assuming the client is accepting
+    // of tombstoned states
+    let results = key_history_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        root_hash.epoch(),
+        AkdLabel::from_utf8_str("hello"),
+        history_proof,
+        HistoryVerificationParams::AllowMissingValues,
+    )?;
+    assert_ne!(crate::TOMBSTONE, results[0].value.0);
+    assert_ne!(crate::TOMBSTONE, results[1].value.0);
+    assert_ne!(crate::TOMBSTONE, results[2].value.0);
+    assert_eq!(crate::TOMBSTONE, results[3].value.0);
+    assert_eq!(crate::TOMBSTONE, results[4].value.0);
+
+    Ok(())
+}
+
+// Each requirement of a history verification policy should be enforced, and
+// reported with its own error when violated.
+#[tokio::test]
+async fn test_history_verification_policy() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage.clone(), vrf, false).await?;
+
+    // "hello" is updated at epochs 1, 2 and 6, and the directory moves on to epoch 8
+    for epoch in 1..=8 {
+        let label = if [1, 2, 6].contains(&epoch) {
+            "hello"
+        } else {
+            "hello2"
+        };
+        akd.publish(vec![(
+            AkdLabel::from_utf8_str(label),
+            AkdValue(format!("world{}", epoch).into_bytes()),
+        )])
+        .await?;
+    }
+
+    let vrf_pk = akd.get_public_key().await?;
+    let (history_proof, root_hash) = akd
+        .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
+        .await?;
+    let verify = |policy: HistoryVerificationPolicy| {
+        key_history_verify_with_policy(
+            vrf_pk.as_bytes(),
+            root_hash.hash(),
+            root_hash.epoch(),
+            AkdLabel::from_utf8_str("hello"),
+            history_proof.clone(),
+            policy,
+        )
+    };
+
+    let policy = HistoryVerificationPolicy::new()
+        .require_strict_epoch_monotonicity()
+        .max_update_gap(4)
+        .max_age(2);
+    assert_eq!(3, verify(policy)?.len());
+
+    assert_eq!(
+        Err(VerificationError::HistoryPolicy(
+            HistoryPolicyViolation::UpdateGapExceeded {
+                version: 2,
+                epoch: 2,
+                next_epoch: 6,
+                max_gap: 3,
+            }
+        )),
+        verify(HistoryVerificationPolicy::new().max_update_gap(3))
+    );
+    assert_eq!(
+        Err(VerificationError::HistoryPolicy(
+            HistoryPolicyViolation::StaleHistory {
+                latest_update_epoch: 6,
+                current_epoch: 8,
+                max_age: 1,
+            }
+        )),
+        verify(HistoryVerificationPolicy::new().max_age(1))
+    );
+
+    // tombstone the first version
+    storage
+        .tombstone_value_states(&[crate::storage::types::ValueStateKey(
+            "hello".as_bytes().to_vec(),
+            1u64,
+        )])
+        .await?;
+    let (history_proof, root_hash) = akd
+        .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
+        .await?;
+    let verify = |policy: HistoryVerificationPolicy| {
+        key_history_verify_with_policy(
+            vrf_pk.as_bytes(),
+            root_hash.hash(),
+            root_hash.epoch(),
+            AkdLabel::from_utf8_str("hello"),
+            history_proof.clone(),
+            policy,
+        )
+    };
+    // the default policy rejects the tombstone as it always has
+    assert!(matches!(
+        verify(HistoryVerificationPolicy::new()),
+        Err(VerificationError::HistoryProof(_))
+    ));
+    assert_eq!(
+        Err(VerificationError::HistoryPolicy(
+            HistoryPolicyViolation::TombstoneEncountered {
+                version: 1,
+                epoch: 1,
+            }
+        )),
+        verify(HistoryVerificationPolicy::new().max_age(2))
+    );
+    assert_eq!(
+        3,
+        verify(HistoryVerificationPolicy::new().allow_tombstones(true))?.len()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_key_history_value_disclosure() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+
+    // "hello" is updated at epochs 1 to 4
+    for epoch in 1..=4 {
+        akd.publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue(format!("world{}", epoch).into_bytes()),
+        )])
+        .await?;
+    }
+
+    let vrf_pk = akd.get_public_key().await?;
+    let (history_proof, root_hash) = akd
+        .key_history_with_disclosure(
+            &AkdLabel::from_utf8_str("hello"),
+            HistoryParams::default(),
+            ValueDisclosure::SinceEpoch(3),
+        )
+        .await?;
+    let redacted = history_proof
+        .update_proofs
+        .iter()
+        .map(|proof| proof.is_redacted())
+        .collect::<Vec<_>>();
+    assert_eq!(vec![false, false, true, true], redacted);
+    let verify = |policy: HistoryVerificationPolicy| {
+        key_history_verify_with_policy(
+            vrf_pk.as_bytes(),
+            root_hash.hash(),
+            root_hash.epoch(),
+            AkdLabel::from_utf8_str("hello"),
+            history_proof.clone(),
+            policy,
+        )
+    };
+
+    // the redacted values are accepted, and not returned
+    let results =
+        verify(HistoryVerificationPolicy::new().require_opened(ValueDisclosure::SinceEpoch(3)))?;
+    let values = results
+        .iter()
+        .map(|result| result.value.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            AkdValue::from_utf8_str("world4"),
+            AkdValue::from_utf8_str("world3"),
+            AkdValue(vec![]),
+            AkdValue(vec![]),
+        ],
+        values
+    );
+    verify(HistoryVerificationPolicy::new().require_opened(ValueDisclosure::MostRecent(2)))?;
+    verify(HistoryVerificationPolicy::new().require_opened(ValueDisclosure::None))?;
+
+    // unless the policy requires them to be opened
+    assert_eq!(
+        Err(VerificationError::HistoryPolicy(
+            HistoryPolicyViolation::RedactedValue {
+                version: 2,
+                epoch: 2,
+            }
+        )),
+        verify(HistoryVerificationPolicy::new())
+    );
+    assert_eq!(
+        Err(VerificationError::HistoryPolicy(
+            HistoryPolicyViolation::RedactedValue {
+                version: 2,
+                epoch: 2,
+            }
+        )),
+        verify(HistoryVerificationPolicy::new().require_opened(ValueDisclosure::MostRecent(3)))
+    );
+
+    // a value slipped into a redacted update is never returned
+    let mut tampered = history_proof.clone();
+    tampered.update_proofs[0].plaintext_value = AkdValue::from_utf8_str("forged");
+    tampered.update_proofs[0].commitment_proof = vec![];
+    tampered.update_proofs[0].value_commitment = history_proof.update_proofs[2].value_commitment;
+    let verify_tampered = |tampered: crate::HistoryProof| {
+        key_history_verify_with_policy(
+            vrf_pk.as_bytes(),
+            root_hash.hash(),
+            root_hash.epoch(),
+            AkdLabel::from_utf8_str("hello"),
+            tampered,
+            HistoryVerificationPolicy::new().require_opened(ValueDisclosure::None),
+        )
+    };
+    assert!(matches!(
+        verify_tampered(tampered.clone()),
+        Err(VerificationError::HistoryProof(_))
+    ));
+
+    // nor is a redacted update without the commitment of its value accepted
+    tampered.update_proofs[0].value_commitment = None;
+    assert!(matches!(
+        verify_tampered(tampered),
+        Err(VerificationError::HistoryProof(_))
+    ));
+
+    Ok(())
+}
+
+// Test coverage on issue #144, verification failures with
+// small trees (<4 nodes) in both the tests below
+// Note that the use of a VRF means that that the label
+// depends on the hash function being used.
+// The below two tests are identical except for the hash function being used.
+
+// Test lookup in a smaller tree with 2 leaves, using the Blake3 hash function.
+#[tokio::test]
+async fn test_simple_lookup_for_small_tree_blake() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    // epoch 0
+    let akd = Directory::<_, _>::new(storage, vrf.clone(), false).await?;
+
+    // Create a set with 2 updates, (label, value) pairs
+    // ("hello10", "hello10")
+    // ("hello11", "hello11")
+    let mut updates = vec![];
+    for i in 0..1 {
+        updates.push((
+            AkdLabel(format!("hello1{}", i).as_bytes().to_vec()),
+            AkdValue(format!("hello1{}", i).as_bytes().to_vec()),
+        ));
+    }
+    // Publish the updates. Now the akd's epoch will be 1.
+    akd.publish(updates).await?;
+
+    // The label we will lookup is "hello10"
+    let target_label = AkdLabel(format!("hello1{}", 0).as_bytes().to_vec());
+
+    // retrieve the lookup proof
+    let (lookup_proof, root_hash) = akd.lookup(target_label.clone()).await?;
+
+    // Get the VRF public key
+    let vrf_pk = vrf.get_vrf_public_key().await?;
+
+    // perform the "traditional" AKD verification
+    let akd_result = crate::client::lookup_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        target_label.clone(),
+        lookup_proof,
+    )?;
+
+    // check the two results to make sure they both verify
+    assert_eq!(
+        akd_result,
+        VerifyResult {
+            epoch: 1,
+            version: 1,
+            value: AkdValue::from_utf8_str("hello10"),
+            expiry_epoch: None,
+        },
+    );
+
+    Ok(())
+}
+
+// Test lookup in a smaller tree with 2 leaves, using the Sha3 hash function.
+#[cfg(feature = "sha3_256")]
+#[tokio::test]
+async fn test_simple_lookup_for_small_tree_sha256() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    // epoch 0
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+
+    // Create a set with 2 updates, (label, value) pairs
+    // ("hello10", "hello10")
+    // ("hello11", "hello11")
+    let mut updates = vec![];
+    for i in 0..1 {
+        updates.push((
+            AkdLabel(format!("hello{}", i).as_bytes().to_vec()),
+            AkdValue(format!("hello{}", i).as_bytes().to_vec()),
+        ));
+    }
+
+    // Publish the updates. Now the akd's epoch will be 1.
+    akd.publish(updates).await?;
+
+    // The label we will lookup is "hello10"
+    let target_label = AkdLabel(format!("hello{}", 0).as_bytes().to_vec());
+
+    // retrieve the lookup proof
+    let lookup_proof = akd.lookup(target_label.clone()).await?;
+    // retrieve the root hash
+    let current_azks = akd.retrieve_current_azks().await?;
+    let root_hash = akd.get_root_hash(&current_azks).await?;
+
+    // Get the VRF public key
+    let vrf_pk = vrf.get_vrf_public_key().await?;
+
+    // perform the "traditional" AKD verification
+    let akd_result = crate::client::lookup_verify(
+        vrf_pk.as_bytes(),
+        root_hash,
+        target_label.clone(),
+        lookup_proof,
+    )?;
+
+    // check the two results to make sure they both verify
+    assert_eq!(
+        akd_result,
+        VerifyResult {
+            epoch: 1,
+            version: 1,
+            value: AkdValue::from_utf8_str("hello0"),
+            expiry_epoch: None,
+        },
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_check_integrity() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db.clone());
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+
+    for epoch in 0..2 {
+        let updates = (0..10)
+            .map(|user| {
+                (
+                    AkdLabel::from_utf8_str(&format!("user{}", user)),
+                    AkdValue::from_utf8_str(&format!("value{}.{}", user, epoch)),
+                )
+            })
+            .collect();
+        akd.publish(updates).await?;
+    }
+
+    // a freshly published tree is consistent, at the current and previous epochs
+    let current_azks = akd.retrieve_current_azks().await?;
+    let report = akd.check_integrity(2).await?;
+    assert!(report.is_ok(), "{:?}", report.issues);
+    assert_eq!(akd.get_root_hash(&current_azks).await?, report.root_hash);
+    assert!(report.nodes_checked >= 19);
+    assert!(akd.check_integrity(1).await?.is_ok());
+    assert!(akd.check_integrity(3).await.is_err());
+
+    // corrupt the hash of an interior node
+    let nodes = db
+        .batch_get_type_direct::<TreeNodeWithPreviousValue>()
+        .await?
+        .into_iter()
+        .filter_map(|record| match record {
+            DbRecord::TreeNode(node) => Some(node),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut corrupted = nodes
+        .iter()
+        .find(|node| node.latest_node.node_type == NodeType::Interior)
+        .expect("No interior node found")
+        .clone();
+    corrupted.latest_node.hash[0] ^= 1;
+    let corrupted_label = corrupted.label;
+    db.set(DbRecord::TreeNode(corrupted)).await?;
+
+    // store a leaf which isn't attached to the tree
+    let mut orphan = nodes
+        .iter()
+        .find(|node| node.latest_node.node_type == NodeType::Leaf)
+        .expect("No leaf found")
+        .clone();
+    orphan.label = NodeLabel::new([0xab; 32], 256);
+    orphan.latest_node.label = orphan.label;
+    let orphan_label = orphan.label;
+    db.set(DbRecord::TreeNode(orphan)).await?;
+
+    let report = akd.check_integrity(2).await?;
+    assert!(report.issues.iter().any(|issue| matches!(
+        issue,
+        IntegrityIssue::HashMismatch { label, .. } if *label == corrupted_label
+    )));
+    assert!(report.issues.contains(&IntegrityIssue::Orphaned {
+        label: orphan_label
+    }));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_export_subtree() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db.clone());
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+
+    akd.publish(
+        (0..10)
+            .map(|user| {
+                (
+                    AkdLabel::from_utf8_str(&format!("user{}", user)),
+                    AkdValue::from_utf8_str(&format!("value{}", user)),
+                )
+            })
+            .collect(),
+    )
+    .await?;
+
+    // the whole tree is exported from the root
+    let all_nodes = akd.export_subtree(NodeLabel::root(), 1).await?;
+    let num_stored = db
+        .batch_get_type_direct::<TreeNodeWithPreviousValue>()
+        .await?
+        .len();
+    assert_eq!(num_stored, all_nodes.len());
+
+    // a subtree, with a label which doesn't end on a byte boundary, holds exactly the
+    // nodes below its root
+    for root in [NodeLabel::new([0u8; 32], 3), NodeLabel::new([0xa0; 32], 11)] {
+        let subtree = akd.export_subtree(root, 1).await?;
+        let expected = all_nodes
+            .iter()
+            .filter(|node| root.is_prefix_of(&node.label))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(expected, subtree);
+    }
+    assert!(akd.export_subtree(NodeLabel::root(), 2).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_segmented_audit() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+
+    let mut root_hashes = vec![];
+    for epoch in 0..3 {
+        let updates = (0..50)
+            .filter(|user| user % 3 == 0 || epoch == 0)
+            .map(|user| {
+                (
+                    AkdLabel::from_utf8_str(&format!("user{}", user)),
+                    AkdValue::from_utf8_str(&format!("value{}.{}", user, epoch)),
+                )
+            })
+            .collect();
+        root_hashes.push(akd.publish(updates).await?.hash());
+    }
+
+    let proof = akd.audit_segmented(1, 3, 3).await?;
+    assert_eq!(2, proof.manifest.transitions.len());
+    assert!(proof.segments.len() > 2);
+    audit_verify_segmented(root_hashes.clone(), proof.clone()).await?;
+
+    // the segments can be verified in any order once the manifest is verified
+    verify_append_only_manifest(&root_hashes, &proof.manifest).await?;
+    for segment in proof.segments.iter().rev() {
+        verify_append_only_segment(&proof.manifest, segment).await?;
+    }
+
+    // a missing segment is detected
+    let mut missing = proof.clone();
+    missing.segments.pop();
+    assert!(audit_verify_segmented(root_hashes.clone(), missing)
+        .await
+        .is_err());
+
+    // as is a tampered segment
+    let mut tampered = proof.clone();
+    let segment = tampered
+        .segments
+        .iter_mut()
+        .find(|segment| !segment.proof.inserted.is_empty())
+        .expect("No segment with inserted leaves");
+    segment.proof.inserted[0].hash[0] ^= 1;
+    assert!(audit_verify_segmented(root_hashes.clone(), tampered)
+        .await
+        .is_err());
+
+    // and a manifest which doesn't match the root hashes
+    let mut wrong_hashes = root_hashes.clone();
+    wrong_hashes.swap(1, 2);
+    assert!(verify_append_only_manifest(&wrong_hashes, &proof.manifest)
+        .await
+        .is_err());
+    assert!(akd.audit_segmented(1, 3, 0).await.is_err());
+
+    Ok(())
+}
+
+// An audit proof streamed to a sink under any memory budget reads back as the proof
+// generated in memory
+#[cfg(feature = "protobuf")]
+#[tokio::test]
+async fn test_audit_to_writer() -> Result<(), AkdError> {
+    use crate::auditor::read_append_only_proof;
+
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+
+    let mut root_hashes = vec![
+        crate::directory::get_directory_root_hash_and_ep(&akd)
+            .await?
+            .0,
+    ];
+    for epoch in 0..4 {
+        let updates = (0..40)
+            .filter(|user| user % (epoch + 1) == 0)
+            .map(|user| {
+                (
+                    AkdLabel::from_utf8_str(&format!("user{}", user)),
+                    AkdValue::from_utf8_str(&format!("value{}.{}", user, epoch)),
+                )
+            })
+            .collect();
+        root_hashes.push(akd.publish(updates).await?.hash());
+    }
+
+    let proof = akd.audit(0, 4).await?;
+    let mut sizes = vec![];
+    for max_memory_bytes in [0, 1024, 64 * 1024 * 1024] {
+        let mut bytes = vec![];
+        akd.audit_to_writer(0, 4, max_memory_bytes, &mut bytes)
+            .await?;
+        let streamed = read_append_only_proof(&mut &bytes[..]).await?;
+        assert_eq!(proof, streamed);
+        audit_verify(root_hashes.clone(), streamed).await?;
+        sizes.push(bytes.len());
+    }
+    // the smaller budgets split the proof into more frames
+    assert!(sizes[0] > sizes[1] && sizes[1] > sizes[2]);
+
+    // a truncated stream fails to read
+    let mut bytes = vec![];
+    akd.audit_to_writer(1, 3, 1024, &mut bytes).await?;
+    assert!(read_append_only_proof(&mut &bytes[..bytes.len() - 1])
+        .await
+        .is_err());
+
+    let mut bytes = vec![];
+    assert!(akd.audit_to_writer(3, 3, 1024, &mut bytes).await.is_err());
+    assert!(akd.audit_to_writer(1, 5, 1024, &mut bytes).await.is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "protobuf")]
+#[tokio::test]
+async fn test_serialized_audit() -> Result<(), AkdError> {
+    use crate::auditor::audit_verify_serialized;
+    use crate::local_auditing::generate_audit_blobs;
+    use akd_core::proto::envelope::VersionedProof;
+    use akd_core::verify::StreamingAuditVerifier;
+
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+
+    let mut root_hashes = vec![
+        crate::directory::get_directory_root_hash_and_ep(&akd)
+            .await?
+            .0,
+    ];
+    for epoch in 0..4 {
+        let updates = (0..30)
+            .filter(|user| user % (epoch + 1) == 0)
+            .map(|user| {
+                (
+                    AkdLabel::from_utf8_str(&format!("user{}", user)),
+                    AkdValue::from_utf8_str(&format!("value{}.{}", user, epoch)),
+                )
+            })
+            .collect();
+        root_hashes.push(akd.publish(updates).await?.hash());
+    }
+
+    // from the empty tree, and between non-empty trees
+    for (start, end) in [(0usize, 4usize), (1, 4), (2, 3)] {
+        let proof = akd.audit(start as u64, end as u64).await?;
+        let hashes = &root_hashes[start..=end];
+        audit_verify(hashes.to_vec(), proof.clone()).await?;
+        audit_verify_serialized(hashes, &proof.to_bytes_with_version(0).unwrap())?;
+        audit_verify_serialized(hashes, &proof.to_versioned_bytes().unwrap())?;
+    }
+
+    let proof = akd.audit(1, 4).await?;
+    let hashes = &root_hashes[1..];
+    for blob in generate_audit_blobs(hashes.to_vec(), proof.clone()).unwrap() {
+        blob.verify()?;
+    }
+
+    // the transitions can be verified one at a time, in order
+    let mut verifier = StreamingAuditVerifier::new(hashes.to_vec());
+    let transitions = proof.epochs.iter().zip(proof.proofs.iter());
+    for (epoch, single_proof) in transitions {
+        let bytes = single_proof.to_versioned_bytes().unwrap();
+        assert!(verifier.feed(epoch + 1, &bytes).is_err());
+        verifier.feed(*epoch, &bytes).unwrap();
+    }
+    assert!(verifier.is_complete());
+    assert!(verifier
+        .feed(4, &proof.proofs[0].to_versioned_bytes().unwrap())
+        .is_err());
+
+    // the wrong root hashes are rejected
+    let mut wrong_hashes = hashes.to_vec();
+    wrong_hashes.swap(1, 2);
+    let bytes = proof.to_versioned_bytes().unwrap();
+    assert!(audit_verify_serialized(&wrong_hashes, &bytes).is_err());
+    assert!(audit_verify_serialized(&hashes[..3], &bytes).is_err());
+
+    // as are tampered and truncated proofs
+    let mut tampered = proof.clone();
+    tampered.proofs[1].inserted[0].hash[0] ^= 1;
+    let tampered_bytes = tampered.to_versioned_bytes().unwrap();
+    assert!(audit_verify_serialized(hashes, &tampered_bytes).is_err());
+    assert!(audit_verify_serialized(hashes, &bytes[..bytes.len() - 1]).is_err());
+
+    // nodes which aren't in left-to-right order are rejected
+    let mut reordered = proof;
+    reordered.proofs[1].inserted.reverse();
+    let reordered_bytes = reordered.to_versioned_bytes().unwrap();
+    assert!(audit_verify_serialized(hashes, &reordered_bytes).is_err());
+
+    // a tree with a single leaf below the root
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let empty_hash = crate::directory::get_directory_root_hash_and_ep(&akd)
+        .await?
+        .0;
+    let single_hash = akd
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world"),
+        )])
+        .await?
+        .hash();
+    let proof = akd.audit(0, 1).await?;
+    audit_verify_serialized(
+        &[empty_hash, single_hash],
+        &proof.to_versioned_bytes().unwrap(),
+    )?;
+
+    Ok(())
+}
+
+/*
+=========== Test Helpers ===========
+*/
+
+#[cfg(any(feature = "tokio_runtime", feature = "async_std_runtime"))]
+async fn async_poll_helper_proof<T: Database + 'static, V: VRFKeyStorage>(
+    reader: &Directory<T, V>,
+    value: AkdValue,
+) -> Result<(), AkdError> {
+    // reader should read "hello" and this will populate the "cache" a log
+    let (lookup_proof, root_hash) = reader.lookup(AkdLabel::from_utf8_str("hello")).await?;
+    assert_eq!(value, lookup_proof.plaintext_value);
+    let pk = reader.get_public_key().await?;
+    lookup_verify(
+        pk.as_bytes(),
+        root_hash.hash(),
+        AkdLabel::from_utf8_str("hello"),
+        lookup_proof,
+    )?;
+    Ok(())
+}
+
+// A lookup of a label which doesn't exist returns a verifiable proof of its absence,
+// which is cached like the other lookup proofs
+#[tokio::test]
+async fn test_lookup_nonexistent() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false)
+        .await?
+        .with_lookup_proof_cache(DEFAULT_PROOF_CACHE_LIMIT_BYTES);
+    let vrf_pk = akd.get_public_key().await?;
+    let missing = AkdLabel::from_utf8_str("missing");
+
+    // nothing can be proven before the first publish
+    let err = akd.lookup_nonexistent(missing.clone()).await.unwrap_err();
+    assert_eq!(ErrorCode::InvalidEpoch, err.code());
+
+    let updates = (0..5)
+        .map(|i| {
+            (
+                AkdLabel::from_utf8_str(&format!("hello{}", i)),
+                AkdValue::from_utf8_str(&format!("world{}", i)),
+            )
+        })
+        .collect::<Vec<_>>();
+    akd.publish(updates.clone()).await?;
+    akd.publish(vec![(
+        AkdLabel::from_utf8_str("hello1"),
+        AkdValue::from_utf8_str("world1'"),
+    )])
+    .await?;
+
+    let (proof, root_hash) = akd.lookup_nonexistent(missing.clone()).await?;
+    assert_eq!(2, root_hash.epoch());
+    lookup_nonexistent_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        missing.clone(),
+        proof.clone(),
+    )?;
+    // the proof doesn't hold for another label
+    assert!(lookup_nonexistent_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        AkdLabel::from_utf8_str("other"),
+        proof.clone(),
+    )
+    .is_err());
+
+    // the second lookup is served from the cache
+    let (cached_proof, cached_root_hash) = akd.lookup_nonexistent(missing).await?;
+    assert_eq!(proof, cached_proof);
+    assert_eq!(root_hash, cached_root_hash);
+    assert_eq!(1, akd.lookup_proof_cache().unwrap().hits());
+
+    // the absence of an existing label can't be proven
+    let err = akd
+        .lookup_nonexistent(AkdLabel::from_utf8_str("hello1"))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        &err,
+        AkdError::Directory(DirectoryError::LabelExists(label))
+            if *label == AkdLabel::from_utf8_str("hello1")
+    ));
+    assert_eq!(ErrorCode::InvalidRequest, err.code());
+    Ok(())
+}
+
+// With padding enabled, the proofs of absence of all the labels should have the
+// size of a proof at the padded depth, and still verify
+#[tokio::test]
+async fn test_padded_lookup_nonexistent() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    // with a cache, the paths of the proof are preloaded together
+    let storage = StorageManager::new(db, None, None, None);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false)
+        .await?
+        .with_absence_proof_padding(AbsenceProof::size_at_depth(24));
+    let vrf_pk = akd.get_public_key().await?;
+    let updates = (0..50)
+        .map(|i| {
+            (
+                AkdLabel::from_utf8_str(&format!("hello{}", i)),
+                AkdValue::from_utf8_str(&format!("world{}", i)),
+            )
+        })
+        .collect::<Vec<_>>();
+    akd.publish(updates).await?;
+
+    let mut depths = std::collections::HashSet::new();
+    for i in 0..10 {
+        let missing = AkdLabel::from_utf8_str(&format!("missing{}", i));
+        let (proof, root_hash) = akd.lookup_nonexistent(missing.clone()).await?;
+        assert_eq!(AbsenceProof::size_at_depth(24), proof.size_of());
+        let depth = proof
+            .non_membership_proof
+            .longest_prefix_membership_proof
+            .layer_proofs
+            .len();
+        assert_eq!(
+            AbsenceProof::size_at_depth(depth),
+            proof.size_of() - proof.padding.len()
+        );
+        depths.insert(depth);
+        lookup_nonexistent_verify(vrf_pk.as_bytes(), root_hash.hash(), missing, proof)?;
+    }
+    // the padding hides paths of different depths
+    assert!(depths.len() > 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lookup_at() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let hello = AkdLabel::from_utf8_str("hello");
+    let other = AkdLabel::from_utf8_str("other");
+    for updates in [
+        vec![(hello.clone(), AkdValue::from_utf8_str("world1"))],
+        vec![(other.clone(), AkdValue::from_utf8_str("other1"))],
+        vec![(hello.clone(), AkdValue::from_utf8_str("world2"))],
+        vec![
+            (hello.clone(), AkdValue::from_utf8_str("world3")),
+            (other.clone(), AkdValue::from_utf8_str("other2")),
+        ],
+    ] {
+        akd.publish(updates).await?;
+    }
+    let vrf_pk = akd.get_public_key().await?;
+
+    // a lookup at the latest epoch is the same as a regular lookup
+    let (proof, root_hash) = akd.lookup_at(hello.clone(), 4).await?;
+    assert_eq!(akd.get_epoch_hash(), root_hash);
+    assert_eq!((proof.clone(), root_hash), akd.lookup(hello.clone()).await?);
+
+    // the previous epoch is proven against its archived root hash
+    let (proof, root_hash) = akd.lookup_at(hello.clone(), 3).await?;
+    assert_eq!(3, root_hash.epoch());
+    assert_eq!(akd.get_root_hash_at_epoch(3).await?, root_hash.hash());
+    let result = lookup_verify_at_epoch(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        3,
+        hello.clone(),
+        proof.clone(),
+    )?;
+    assert_eq!(2, result.version);
+    assert_eq!(AkdValue::from_utf8_str("world2"), result.value);
+    assert!(lookup_verify(
+        vrf_pk.as_bytes(),
+        akd.get_epoch_hash().hash(),
+        hello.clone(),
+        proof.clone(),
+    )
+    .is_err());
+    assert!(matches!(
+        lookup_verify_at_epoch(vrf_pk.as_bytes(), root_hash.hash(), 2, hello.clone(), proof),
+        Err(VerificationError::LookupProof(_))
+    ));
+
+    // older states of the tree are no longer retained
+    let err = akd.lookup_at(hello.clone(), 2).await.unwrap_err();
+    assert_eq!(ErrorCode::InvalidEpoch, err.code());
+    let err = akd.lookup_at(hello, 5).await.unwrap_err();
+    assert_eq!(ErrorCode::InvalidEpoch, err.code());
+    Ok(())
+}
+
+// Checks that a lookup proof verifies against a set of candidate roots, reporting
+// the epoch of the root it verified against, and fails if none of them match
+#[tokio::test]
+async fn test_lookup_verify_against_roots() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let hello = AkdLabel::from_utf8_str("hello");
+    let mut roots = vec![];
+    for value in ["world1", "world2", "world3"] {
+        akd.publish(vec![(hello.clone(), AkdValue::from_utf8_str(value))])
+            .await?;
+        let root_hash = akd.get_epoch_hash();
+        roots.push((root_hash.epoch(), root_hash.hash()));
+    }
+    let vrf_pk = akd.get_public_key().await?;
+
+    // the proof of the latest epoch verifies against its root, in any position
+    let (proof, root_hash) = akd.lookup(hello.clone()).await?;
+    let (epoch, result) =
+        lookup_verify_against_roots(vrf_pk.as_bytes(), &roots, hello.clone(), proof.clone())?;
+    assert_eq!(root_hash.epoch(), epoch);
+    assert_eq!(3, result.version);
+    assert_eq!(AkdValue::from_utf8_str("world3"), result.value);
+    roots.reverse();
+    let (epoch, _) =
+        lookup_verify_against_roots(vrf_pk.as_bytes(), &roots, hello.clone(), proof.clone())?;
+    assert_eq!(3, epoch);
+
+    // none of the older roots match
+    assert!(matches!(
+        lookup_verify_against_roots(vrf_pk.as_bytes(), &roots[1..], hello.clone(), proof.clone()),
+        Err(VerificationError::LookupProof(_))
+    ));
+    assert!(matches!(
+        lookup_verify_against_roots(vrf_pk.as_bytes(), &[], hello.clone(), proof.clone()),
+        Err(VerificationError::LookupProof(_))
+    ));
+
+    // the VRF proofs are still checked against the label
+    assert!(lookup_verify_against_roots(
+        vrf_pk.as_bytes(),
+        &roots,
+        AkdLabel::from_utf8_str("other"),
+        proof
+    )
+    .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lookup_at_epochs() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let vrf = HardCodedAkdVRF {};
+    let akd = Directory::<_, _>::new(storage, vrf, false).await?;
+    let vrf_pk = akd.get_public_key().await?;
+    let label = AkdLabel::from_utf8_str("hello");
+
+    // "hello" is published at epochs 2 and 5, other labels at every epoch
+    for epoch in 1..=6u64 {
+        let mut updates = vec![(
+            AkdLabel::from_utf8_str(&format!("other{}", epoch)),
+            AkdValue::from_utf8_str("value"),
+        )];
+        if epoch == 2 || epoch == 5 {
+            updates.push((
+                label.clone(),
+                AkdValue::from_utf8_str(&format!("world{}", epoch)),
+            ));
+        }
+        akd.publish(updates).await?;
+    }
+
+    let verify = |epochs: &[u64], proof, root_hash: EpochHash| {
+        lookup_at_epochs_verify(
+            vrf_pk.as_bytes(),
+            root_hash.hash(),
+            root_hash.epoch(),
+            label.clone(),
+            epochs,
+            proof,
+            HistoryVerificationParams::default(),
+        )
+    };
+
+    // unchanged between epochs 3 and 4, the updates before epoch 3 aren't proven
+    let (proof, root_hash) = akd.lookup_at_epochs(&label, &[4, 3]).await?;
+    assert_eq!(2, proof.update_proofs.len());
+    let results = verify(&[3, 4], proof, root_hash)?;
+    assert_eq!(
+        vec![3, 4],
+        results.iter().map(|r| r.epoch).collect::<Vec<_>>()
+    );
+    assert!(results.iter().all(|r| !r.changed));
+    assert_eq!(
+        Some(AkdValue::from_utf8_str("world2")),
+        results[1].state.as_ref().map(|s| s.value.clone())
+    );
+
+    // changed between epochs 4 and 6
+    let (proof, root_hash) = akd.lookup_at_epochs(&label, &[4, 6]).await?;
+    let results = verify(&[4, 6], proof, root_hash)?;
+    assert!(!results[0].changed);
+    assert!(results[1].changed);
+    assert_eq!(Some(2), results[1].state.as_ref().map(|s| s.version));
+
+    // not yet published at epoch 1
+    let (proof, root_hash) = akd.lookup_at_epochs(&label, &[1, 2]).await?;
+    let results = verify(&[1, 2], proof, root_hash)?;
+    assert_eq!(None, results[0].state);
+    assert!(results[1].changed);
+
+    // a proof which doesn't reach back to an epoch is rejected
+    let (proof, root_hash) = akd.lookup_at_epochs(&label, &[6]).await?;
+    assert!(verify(&[3, 6], proof, root_hash).is_err());
+
+    // epochs after the latest one are rejected
+    assert!(matches!(
+        akd.lookup_at_epochs(&label, &[3, 7]).await,
+        Err(AkdError::Directory(DirectoryError::InvalidEpoch(_)))
+    ));
+    assert!(akd.lookup_at_epochs(&label, &[]).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_count() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let vrf_pk = akd.get_public_key().await?;
+    let label = AkdLabel::from_utf8_str("hello");
+
+    // "hello" is published at epochs 2, 3 and 5, other labels at every epoch
+    for epoch in 1..=6u64 {
+        let mut updates = vec![(
+            AkdLabel::from_utf8_str(&format!("other{}", epoch)),
+            AkdValue::from_utf8_str("value"),
+        )];
+        if [2, 3, 5].contains(&epoch) {
+            updates.push((
+                label.clone(),
+                AkdValue::from_utf8_str(&format!("world{}", epoch)),
+            ));
+        }
+        akd.publish(updates).await?;
+    }
+
+    let verify = |start_epoch, end_epoch, proof, root_hash: EpochHash| {
+        update_count_verify(
+            vrf_pk.as_bytes(),
+            root_hash.hash(),
+            root_hash.epoch(),
+            label.clone(),
+            start_epoch,
+            end_epoch,
+            proof,
+        )
+    };
+
+    for (start_epoch, end_epoch, count) in [(1, 6, 3), (3, 4, 1), (4, 4, 0), (1, 2, 1), (6, 6, 0)] {
+        let (proof, root_hash) = akd.update_count(&label, start_epoch, end_epoch).await?;
+        // none of the values are disclosed
+        assert!(proof
+            .update_proofs
+            .iter()
+            .all(|update| update.is_redacted()));
+        assert_eq!(count, verify(start_epoch, end_epoch, proof, root_hash)?);
+    }
+
+    // a proof leaving out the update in effect before the start epoch is rejected
+    let (mut proof, root_hash) = akd.update_count(&label, 4, 6).await?;
+    assert_eq!(2, proof.update_proofs.len());
+    proof.update_proofs.pop();
+    assert!(matches!(
+        verify(4, 6, proof, root_hash),
+        Err(VerificationError::HistoryProof(_))
+    ));
+
+    // the epoch of a redacted update is bound by its commitment, so the first
+    // update can't be moved to another epoch
+    let (mut proof, root_hash) = akd.update_count(&label, 1, 6).await?;
+    let first = proof.update_proofs.len() - 1;
+    assert_eq!(1, proof.update_proofs[first].version);
+    proof.update_proofs[first].epoch = 1;
+    assert!(matches!(
+        verify(1, 6, proof, root_hash),
+        Err(VerificationError::HistoryProof(_))
+    ));
+    let (mut proof, root_hash) = akd.update_count(&label, 1, 6).await?;
+    proof.update_proofs[first].value_commitment = None;
+    assert!(matches!(
+        verify(1, 6, proof, root_hash),
+        Err(VerificationError::HistoryProof(_))
+    ));
+
+    assert!(matches!(
+        akd.update_count(&label, 4, 3).await,
+        Err(AkdError::Directory(DirectoryError::InvalidEpoch(_)))
+    ));
+    assert!(matches!(
+        akd.update_count(&label, 1, 7).await,
+        Err(AkdError::Directory(DirectoryError::InvalidEpoch(_)))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_error_codes() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage.clone(), HardCodedAkdVRF {}, false).await?;
+    let updates = vec![(
+        AkdLabel::from_utf8_str("hello"),
+        AkdValue::from_utf8_str("world"),
+    )];
+
+    let err = akd
+        .lookup(AkdLabel::from_utf8_str("hello"))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorCode::NotFound, err.code());
+    assert!(!err.is_retryable());
+
+    let err = akd.audit(0, 5).await.unwrap_err();
+    assert_eq!(ErrorCode::InvalidEpoch, err.code());
+
+    // a publish is rejected while another transaction is in progress, but can be retried
+    assert!(storage.begin_transaction());
+    let err = akd.publish(updates.clone()).await.unwrap_err();
+    assert_eq!(ErrorCode::Busy, err.code());
+    assert!(err.is_retryable());
+    storage.rollback_transaction()?;
+    akd.publish(updates.clone()).await?;
+
+    let read_only = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, true).await?;
+    let err = read_only.publish(updates).await.unwrap_err();
+    assert_eq!(ErrorCode::ReadOnly, err.code());
+    assert_eq!("read_only", err.code().as_str());
+
+    // storage failures which may resolve themselves can be retried, unlike
+    // records which are missing or can't be read
+    for (err, code) in [
+        (StorageError::NotFound(String::new()), ErrorCode::NotFound),
+        (
+            StorageError::TypeMismatch(String::new()),
+            ErrorCode::Storage,
+        ),
+        (StorageError::Corruption(String::new()), ErrorCode::Storage),
+        (
+            StorageError::Transient(String::new()),
+            ErrorCode::StorageUnavailable,
+        ),
+        (
+            StorageError::Timeout(String::new()),
+            ErrorCode::StorageUnavailable,
+        ),
+    ] {
+        let err = AkdError::Storage(err);
+        assert_eq!(code, err.code());
+        assert_eq!(code == ErrorCode::StorageUnavailable, err.is_retryable());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_signed_tree_heads() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage.clone(), HardCodedAkdVRF {}, false).await?;
+    let public_key = akd.get_tree_head_public_key().await?;
+
+    let first = akd
+        .bulk_initialize(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("world"),
+        )])
+        .await?;
+    let second = akd
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello2"),
+            AkdValue::from_utf8_str("world2"),
+        )])
+        .await?;
+
+    let (proof, root_hash) = akd.lookup(AkdLabel::from_utf8_str("hello")).await?;
+    let latest = akd.get_latest_tree_head().await?;
+    verify_tree_head_for_root(
+        public_key.as_bytes(),
+        &latest,
+        root_hash.epoch(),
+        root_hash.hash(),
+    )?;
+    lookup_verify(
+        akd.get_public_key().await?.as_bytes(),
+        latest.root_hash,
+        AkdLabel::from_utf8_str("hello"),
+        proof,
+    )?;
+    for epoch_hash in [&first, &second] {
+        let tree_head = akd.get_tree_head(epoch_hash.epoch()).await?;
+        assert_eq!(epoch_hash.hash(), tree_head.root_hash);
+        verify_tree_head(public_key.as_bytes(), &tree_head)?;
+    }
+    let err = akd.get_tree_head(3).await.unwrap_err();
+    assert_eq!(ErrorCode::InvalidEpoch, err.code());
+
+    // the tree heads are persisted, and signed with the same key after a restart
+    let restarted = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, true).await?;
+    assert_eq!(public_key, restarted.get_tree_head_public_key().await?);
+    assert_eq!(latest, restarted.get_latest_tree_head().await?);
+
+    let mut tampered = latest.clone();
+    tampered.timestamp += 1;
+    assert!(matches!(
+        verify_tree_head(public_key.as_bytes(), &tampered),
+        Err(VerificationError::TreeHead(_))
+    ));
+    assert!(verify_tree_head_for_root(public_key.as_bytes(), &latest, 1, first.hash()).is_err());
+
+    // a directory serving a different tree to another client signs a different
+    // root hash for the same epoch, which is detected by comparing the tree heads
+    let forked_storage = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    let forked = Directory::<_, _>::new(forked_storage, HardCodedAkdVRF {}, false).await?;
+    forked
+        .publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str("forked"),
+        )])
+        .await?;
+    let theirs = forked.get_tree_head(1).await?;
+    verify_tree_head(public_key.as_bytes(), &theirs)?;
+    let ours = akd.get_tree_head(1).await?;
+    assert!(matches!(
+        compare_tree_heads(public_key.as_bytes(), &ours, &theirs),
+        Err(VerificationError::TreeHead(_))
+    ));
+    compare_tree_heads(public_key.as_bytes(), &ours, &latest)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sample_audit() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let seed = b"public beacon value";
+
+    // there is nothing to sample in the empty tree
+    let err = akd.sample_audit(0, seed, 1).await.unwrap_err();
+    assert_eq!(ErrorCode::InvalidEpoch, err.code());
+
+    for epoch in 1..3 {
+        let updates = (0..50)
+            .map(|i| {
+                (
+                    AkdLabel::from_utf8_str(&format!("label{}", i)),
+                    AkdValue::from_utf8_str(&format!("value{}", epoch)),
+                )
+            })
+            .collect();
+        akd.publish(updates).await?;
+    }
+    let epoch_hash = akd.get_epoch_hash();
+
+    let proof = akd.sample_audit(2, seed, 8).await?;
+    assert_eq!(8, proof.samples.len());
+    verify_sample_audit(epoch_hash.hash(), 2, seed, 8, &proof)?;
+    // the sample is deterministic, and depends on the seed
+    assert_eq!(proof, akd.sample_audit(2, seed, 8).await?);
+    let other = akd.sample_audit(2, b"another beacon value", 8).await?;
+    assert_ne!(proof.samples, other.samples);
+    assert!(verify_sample_audit(epoch_hash.hash(), 2, seed, 8, &other).is_err());
+    assert!(verify_sample_audit(epoch_hash.hash(), 2, seed, 7, &proof).is_err());
+
+    // the directory can't choose which leaves are sampled
+    let mut tampered = proof.clone();
+    assert_ne!(tampered.samples[0].label, tampered.samples[1].label);
+    tampered.samples.swap(0, 1);
+    assert!(matches!(
+        verify_sample_audit(epoch_hash.hash(), 2, seed, 8, &tampered),
+        Err(VerificationError::SampleAudit(_))
+    ));
+
+    // only the latest epoch can be sampled
+    let err = akd.sample_audit(1, seed, 8).await.unwrap_err();
+    assert_eq!(ErrorCode::InvalidEpoch, err.code());
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct CountingObserver {
+    proof_size: usize,
+    started: Vec<VerificationStep>,
+    completed: Vec<VerificationStep>,
+}
+
+impl VerificationObserver for CountingObserver {
+    fn proof_size(&mut self, size: usize) {
+        self.proof_size = size;
+    }
+
+    fn step_started(&mut self, step: VerificationStep) {
+        self.started.push(step);
+    }
+
+    fn step_completed(&mut self, step: VerificationStep) {
+        self.completed.push(step);
+    }
+}
+
+#[tokio::test]
+async fn test_verification_observer() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    for epoch in 1..4 {
+        akd.publish(vec![(
+            AkdLabel::from_utf8_str("hello"),
+            AkdValue::from_utf8_str(&format!("world{}", epoch)),
+        )])
+        .await?;
+    }
+    let vrf_pk = akd.get_public_key().await?;
+
+    let (lookup_proof, root_hash) = akd.lookup(AkdLabel::from_utf8_str("hello")).await?;
+    let mut observer = CountingObserver::default();
+    let result = lookup_verify_with_observer(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        AkdLabel::from_utf8_str("hello"),
+        lookup_proof.clone(),
+        &mut observer,
+    )?;
+    assert_eq!(
+        lookup_verify(
+            vrf_pk.as_bytes(),
+            root_hash.hash(),
+            AkdLabel::from_utf8_str("hello"),
+            lookup_proof.clone(),
+        )?,
+        result
+    );
+    assert_eq!(lookup_proof.size_of(), observer.proof_size);
+    assert_eq!(observer.started, observer.completed);
+    assert!(observer.started.contains(&VerificationStep::Vrf));
+    assert!(observer.started.contains(&VerificationStep::Membership));
+
+    // a failing step is reported as completed too
+    let mut observer = CountingObserver::default();
+    assert!(lookup_verify_with_observer(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        AkdLabel::from_utf8_str("world"),
+        lookup_proof,
+        &mut observer,
+    )
+    .is_err());
+    assert_eq!(observer.started, observer.completed);
+
+    let (history_proof, root_hash) = akd
+        .key_history(&AkdLabel::from_utf8_str("hello"), HistoryParams::default())
+        .await?;
+    let mut timings = VerificationTimings::new();
+    let results = key_history_verify_with_observer(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        root_hash.epoch(),
+        AkdLabel::from_utf8_str("hello"),
+        history_proof.clone(),
+        HistoryVerificationPolicy::default(),
+        &mut timings,
+    )?;
+    assert_eq!(3, results.len());
+    assert_eq!(history_proof.size_of(), timings.proof_size);
+    let steps: Vec<VerificationStep> = timings.steps.iter().map(|(step, _)| *step).collect();
+    assert_eq!(
+        vec![
+            VerificationStep::HistoryChain,
+            VerificationStep::Vrf,
+            VerificationStep::Membership
+        ],
+        steps
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batch_lookup_compact() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let vrf_pk = akd.get_public_key().await?;
+
+    let labels = (0..10)
+        .map(|i| AkdLabel::from_utf8_str(&format!("user{}", i)))
+        .collect::<Vec<_>>();
+    for epoch in 0..3 {
+        akd.publish(
+            labels
+                .iter()
+                .take(4 + epoch * 3)
+                .map(|label| {
+                    (
+                        label.clone(),
+                        AkdValue::from_utf8_str(&format!("{}", epoch)),
+                    )
+                })
+                .collect(),
+        )
+        .await?;
+    }
+
+    let (proof, root_hash) = akd.batch_lookup_compact(&labels).await?;
+    let (individual_proofs, _) = akd.batch_lookup(&labels).await?;
+    assert!(proof.size_of() < individual_proofs.iter().map(|p| p.size_of()).sum::<usize>());
+
+    let results = batch_lookup_verify(vrf_pk.as_bytes(), root_hash.hash(), &labels, proof.clone())?;
+    for (result, individual) in results.into_iter().zip(individual_proofs) {
+        assert_eq!(
+            (
+                individual.epoch,
+                individual.version,
+                individual.plaintext_value
+            ),
+            (result.epoch, result.version, result.value)
+        );
+    }
+
+    // the labels must be given in the order of the proof
+    let mut swapped = labels.clone();
+    swapped.swap(0, 1);
+    assert!(
+        batch_lookup_verify(vrf_pk.as_bytes(), root_hash.hash(), &swapped, proof.clone()).is_err()
+    );
+    assert!(batch_lookup_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        &labels[1..],
+        proof.clone()
+    )
+    .is_err());
+
+    // the lookups can't be reordered without the batch VRF proof
+    let mut reordered = proof;
+    reordered.lookup_proofs.swap(0, 1);
+    assert!(batch_lookup_verify(vrf_pk.as_bytes(), root_hash.hash(), &swapped, reordered).is_err());
+
+    Ok(())
+}
+
+// A directory with a custom label mapper serves proofs verifying against the mapped
+// labels, and can't be reopened with a different mapper
+#[tokio::test]
+async fn test_label_mapper() -> Result<(), AkdError> {
+    let mapper = DomainSeparatedLabelMapper::new(b"akd-test").with_salted_hashing(b"salt");
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db.clone());
+    let akd = Directory::<_, _>::new_with_label_mapper(
+        storage.clone(),
+        HardCodedAkdVRF {},
+        false,
+        mapper.clone(),
+    )
+    .await?;
+    let vrf_pk = akd.get_public_key().await?;
+    let hello = AkdLabel::from_utf8_str("hello");
+    let updates = vec![
+        (hello.clone(), AkdValue::from_utf8_str("world")),
+        (
+            AkdLabel::from_utf8_str("hello2"),
+            AkdValue::from_utf8_str("world2"),
+        ),
+    ];
+    let first_root_hash = akd.publish(updates.clone()).await?;
+    akd.publish(vec![(hello.clone(), AkdValue::from_utf8_str("world'"))])
+        .await?;
+
+    let (proof, root_hash) = akd.lookup(hello.clone()).await?;
+    let result = lookup_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        mapper.map_label(&hello),
+        proof.clone(),
+    )?;
+    assert_eq!(2, result.version);
+    // the proof doesn't verify against the unmapped label
+    assert!(lookup_verify(vrf_pk.as_bytes(), root_hash.hash(), hello.clone(), proof).is_err());
+
+    let (history_proof, root_hash) = akd.key_history(&hello, HistoryParams::default()).await?;
+    key_history_verify(
+        vrf_pk.as_bytes(),
+        root_hash.hash(),
+        root_hash.epoch(),
+        mapper.map_label(&hello),
+        history_proof,
+        HistoryVerificationParams::default(),
+    )?;
+
+    // the same updates produce another tree under the default mapper
+    let default_akd = Directory::<_, _>::new(
+        StorageManager::new_no_cache(AsyncInMemoryDatabase::new()),
+        HardCodedAkdVRF {},
+        false,
+    )
+    .await?;
+    let default_root_hash = default_akd.publish(updates).await?;
+    assert_ne!(first_root_hash, default_root_hash);
+
+    // the directory can be reopened with the same mapper only
+    Directory::<_, _>::new_with_label_mapper(
+        storage.clone(),
+        HardCodedAkdVRF {},
+        true,
+        mapper.clone(),
+    )
+    .await?;
+    for other in [
+        DomainSeparatedLabelMapper::new(b"akd-test"),
+        DomainSeparatedLabelMapper::new(b"akd-test").with_salted_hashing(b"pepper"),
+    ] {
+        let err = Directory::<_, _>::new_with_label_mapper(
+            storage.clone(),
+            HardCodedAkdVRF {},
+            false,
+            other,
+        )
+        .await
+        .err();
+        assert!(matches!(
+            err,
+            Some(AkdError::Directory(DirectoryError::LabelMapperMismatch(_)))
+        ));
+    }
+    let err = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false)
+        .await
+        .err();
+    assert_eq!(Some(ErrorCode::InvalidRequest), err.map(|err| err.code()));
+
+    // a published directory without a persisted mapper used the default mapper
+    db.batch_delete::<LabelMappingRecord>(&[LABEL_MAPPING_KEY])
+        .await?;
+    let storage = StorageManager::new_no_cache(db);
+    let err = Directory::<_, _>::new_with_label_mapper(
+        storage.clone(),
+        HardCodedAkdVRF {},
+        false,
+        mapper,
+    )
+    .await
+    .err();
+    assert!(matches!(
+        err,
+        Some(AkdError::Directory(DirectoryError::LabelMapperMismatch(_)))
+    ));
+    Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_label_length() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false)
+        .await?
+        .with_max_label_length(8);
+
+    let long_label = AkdLabel::from_utf8_str("a label longer than 8 bytes");
+    let err = akd
+        .publish(vec![
+            (
+                AkdLabel::from_utf8_str("alice"),
+                AkdValue::from_utf8_str("key"),
+            ),
+            (long_label.clone(), AkdValue::from_utf8_str("key")),
+        ])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        AkdError::Directory(DirectoryError::LabelTooLong(27, 8)),
+        err
+    );
+    assert_eq!(ErrorCode::InvalidRequest, err.code());
+    // nothing was published
+    assert_eq!(0, akd.get_epoch_hash().epoch());
+
+    akd.publish(vec![(
+        AkdLabel::from_utf8_str("alice"),
+        AkdValue::from_utf8_str("key"),
+    )])
+    .await?;
+    assert!(matches!(
+        akd.lookup(long_label.clone()).await,
+        Err(AkdError::Directory(DirectoryError::LabelTooLong(27, 8)))
+    ));
+    assert!(matches!(
+        akd.key_history(&long_label, HistoryParams::default()).await,
+        Err(AkdError::Directory(DirectoryError::LabelTooLong(27, 8)))
+    ));
+    akd.lookup(AkdLabel::from_utf8_str("alice")).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_verify_rejects_deep_proofs() -> Result<(), AkdError> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    akd.publish(vec![
+        (
+            AkdLabel::from_utf8_str("alice"),
+            AkdValue::from_utf8_str("key"),
+        ),
+        (
+            AkdLabel::from_utf8_str("bob"),
+            AkdValue::from_utf8_str("key"),
+        ),
+    ])
+    .await?;
+    let vrf_pk = akd.get_public_key().await?;
+    let (proof, epoch_hash) = akd.lookup(AkdLabel::from_utf8_str("alice")).await?;
+
+    // a path longer than the tree can be deep is rejected
+    let mut deep = proof.clone();
+    let layer = deep.existence_proof.layer_proofs[0].clone();
+    deep.existence_proof.layer_proofs = vec![layer; akd_core::MAX_TREE_DEPTH + 1];
+    assert_eq!(
+        Err(VerificationError::ProofTooDeep(
+            akd_core::MAX_TREE_DEPTH + 1
+        )),
+        lookup_verify(
+            vrf_pk.as_bytes(),
+            epoch_hash.hash(),
+            AkdLabel::from_utf8_str("alice"),
+            deep,
+        )
+    );
+
+    // as is a label longer than the tree can be deep
+    let mut deep = proof;
+    deep.freshness_proof.longest_prefix.label_len = u32::MAX;
+    assert_eq!(
+        Err(VerificationError::ProofTooDeep(u32::MAX as usize)),
+        lookup_verify(
+            vrf_pk.as_bytes(),
+            epoch_hash.hash(),
+            AkdLabel::from_utf8_str("alice"),
+            deep,
+        )
+    );
     Ok(())
 }
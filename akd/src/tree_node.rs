@@ -132,7 +132,10 @@ impl TreeNodeWithPreviousValue {
     /// Determine which of the previous + latest nodes to retrieve based on the
     /// target epoch. If it should be older than the latest node, and there is no
     /// previous node, it returns Not Found
-    fn determine_node_to_get(&self, target_epoch: u64) -> Result<TreeNode, StorageError> {
+    pub(crate) fn determine_node_to_get(
+        &self,
+        target_epoch: u64,
+    ) -> Result<TreeNode, StorageError> {
         // If a publish is currently underway, and "some" nodes have been updated to future values
         // our "target_epoch" may point to some older data. Therefore we may need to load a previous
         // version of this node.
@@ -179,9 +182,10 @@ impl TreeNodeWithPreviousValue {
     ) -> Result<TreeNode, StorageError> {
         match storage.get::<Self>(key).await? {
             DbRecord::TreeNode(node) => node.determine_node_to_get(target_epoch),
-            _ => Err(StorageError::NotFound(format!(
-                "TreeNodeWithPreviousValue {:?}",
-                key
+            other => Err(StorageError::TypeMismatch(format!(
+                "Expected TreeNodeWithPreviousValue {:?}, got {:?}",
+                key,
+                other.data_type()
             ))),
         }
     }
@@ -198,7 +202,7 @@ impl TreeNodeWithPreviousValue {
                 let correct_node = node.determine_node_to_get(target_epoch)?;
                 nodes.push(correct_node);
             } else {
-                return Err(StorageError::NotFound(
+                return Err(StorageError::TypeMismatch(
                     "Batch retrieve returned types <> TreeNodeWithPreviousValue".to_string(),
                 ));
             }
@@ -376,6 +380,7 @@ impl TreeNode {
     }
 
     /// Updates the node hash and saves it in storage.
+    #[cfg(test)]
     pub(crate) async fn update_node_hash<S: Database>(
         &mut self,
         storage: &StorageManager<S>,
@@ -398,19 +403,30 @@ impl TreeNode {
                     .get_child_node(storage, Direction::Right, self.last_epoch)
                     .await?;
 
-                // Get merged hashes for the children.
-                let child_hashes = crate::hash::merge(&[
-                    optional_child_state_label_hash(&left_child_state, hash_mode),
-                    optional_child_state_label_hash(&right_child_state, hash_mode),
-                ]);
-                // Store the hash
-                self.hash = child_hashes;
+                self.set_hash_from_children(&left_child_state, &right_child_state, hash_mode);
             }
         }
 
         Ok(())
     }
 
+    /// Sets the hash of a non-leaf node from the given (already hashed)
+    /// children, without touching storage.
+    pub(crate) fn set_hash_from_children(
+        &mut self,
+        left_child_state: &Option<TreeNode>,
+        right_child_state: &Option<TreeNode>,
+        hash_mode: NodeHashingMode,
+    ) {
+        // Get merged hashes for the children.
+        let child_hashes = crate::hash::merge(&[
+            optional_child_state_label_hash(left_child_state, hash_mode),
+            optional_child_state_label_hash(right_child_state, hash_mode),
+        ]);
+        // Store the hash
+        self.hash = child_hashes;
+    }
+
     /// Inserts a child into this node, adding the state to the state at this epoch,
     /// without updating its own hash.
     pub(crate) fn set_child(&mut self, child_node: &mut TreeNode) -> Result<(), TreeNodeError> {
@@ -461,11 +477,9 @@ impl TreeNode {
                     let get_result = Self::get_from_storage(storage, &child_key, epoch).await;
                     match get_result {
                         Ok(node) => Ok(Some(node)),
+                        // the child didn't exist yet at the epoch
                         Err(StorageError::NotFound(_)) => Ok(None),
-                        _ => Err(AkdError::Storage(StorageError::NotFound(format!(
-                            "TreeNode {:?}",
-                            child_key
-                        )))),
+                        Err(err) => Err(AkdError::Storage(err)),
                     }
                 } else {
                     Ok(None)
@@ -482,11 +496,26 @@ impl TreeNode {
             Direction::None => Err(AkdError::TreeNode(TreeNodeError::NoDirection(
                 self.label, None,
             ))),
-            Direction::Left => Ok(self.left_child),
-            Direction::Right => Ok(self.right_child),
+            Direction::Left => Ok(self.left()),
+            Direction::Right => Ok(self.right()),
         }
     }
 
+    /// The label of the left child of this node, if it has one
+    pub fn left(&self) -> Option<NodeLabel> {
+        self.left_child
+    }
+
+    /// The label of the right child of this node, if it has one
+    pub fn right(&self) -> Option<NodeLabel> {
+        self.right_child
+    }
+
+    /// The labels of the existing children of this node, left first
+    pub fn children(&self) -> impl Iterator<Item = NodeLabel> {
+        self.left().into_iter().chain(self.right())
+    }
+
     /* Functions for compression-related operations */
 
     pub(crate) fn get_latest_epoch(&self) -> u64 {
@@ -581,6 +610,172 @@ pub(crate) fn new_leaf_node(label: NodeLabel, value: &Digest, birth_epoch: u64)
     )
 }
 
+/// The version of the compact binary encoding of the tree nodes
+const TREE_NODE_ENCODING_VERSION: u8 = 1;
+/// The length of an encoded [NodeLabel]: its value, followed by its length
+const ENCODED_LABEL_LEN: usize = 32 + 4;
+/// The maximum length of a [NodeLabel], in bits
+const MAX_LABEL_LEN: u32 = 256;
+/// The length of a [TreeNode] in the compact binary encoding
+const ENCODED_TREE_NODE_LEN: usize = 4 * ENCODED_LABEL_LEN + 2 * 8 + 2 + crate::hash::DIGEST_BYTES;
+
+fn encode_label(result: &mut Vec<u8>, label: &NodeLabel) {
+    result.extend_from_slice(&label.label_val);
+    result.extend_from_slice(&label.label_len.to_be_bytes());
+}
+
+fn decode_label(bytes: &[u8]) -> Result<NodeLabel, StorageError> {
+    let val: [u8; 32] = bytes[..32].try_into().expect("Slice with incorrect length");
+    let len: [u8; 4] = bytes[32..ENCODED_LABEL_LEN]
+        .try_into()
+        .expect("Slice with incorrect length");
+    let len = u32::from_be_bytes(len);
+    if len > MAX_LABEL_LEN {
+        return Err(StorageError::Corruption(format!(
+            "Invalid tree node label length {}",
+            len
+        )));
+    }
+    Ok(NodeLabel::new(val, len))
+}
+
+impl TreeNode {
+    /// Encodes the node in a compact fixed-layout binary format, which storage
+    /// backends can use instead of serde: the label, the last and minimum
+    /// descendant epochs, the parent, the node type, a bitmask of the children
+    /// present, the labels of the children (zeroed when absent) and the hash.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(ENCODED_TREE_NODE_LEN);
+        encode_label(&mut result, &self.label);
+        result.extend_from_slice(&self.last_epoch.to_be_bytes());
+        result.extend_from_slice(&self.min_descendant_epoch.to_be_bytes());
+        encode_label(&mut result, &self.parent);
+        result.push(self.node_type as u8);
+        result.push(
+            u8::from(self.left_child.is_some()) | (u8::from(self.right_child.is_some()) << 1),
+        );
+        for child in [self.left_child, self.right_child] {
+            encode_label(&mut result, &child.unwrap_or(EMPTY_LABEL));
+        }
+        result.extend_from_slice(&self.hash);
+        result
+    }
+
+    /// Decodes a node encoded with [TreeNode::to_bytes]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StorageError> {
+        if bytes.len() != ENCODED_TREE_NODE_LEN {
+            return Err(StorageError::Corruption(format!(
+                "Expected {} bytes for a tree node, got {}",
+                ENCODED_TREE_NODE_LEN,
+                bytes.len()
+            )));
+        }
+        let (label, rest) = bytes.split_at(ENCODED_LABEL_LEN);
+        let (last_epoch, rest) = rest.split_at(8);
+        let (min_descendant_epoch, rest) = rest.split_at(8);
+        let (parent, rest) = rest.split_at(ENCODED_LABEL_LEN);
+        let (flags, rest) = rest.split_at(2);
+        let (left_child, rest) = rest.split_at(ENCODED_LABEL_LEN);
+        let (right_child, hash) = rest.split_at(ENCODED_LABEL_LEN);
+
+        let node_type = match flags[0] {
+            1 => NodeType::Leaf,
+            2 => NodeType::Root,
+            3 => NodeType::Interior,
+            other => {
+                return Err(StorageError::Corruption(format!(
+                    "Invalid tree node type {}",
+                    other
+                )))
+            }
+        };
+        if flags[1] > 0b11 {
+            return Err(StorageError::Corruption(format!(
+                "Invalid tree node children {:#b}",
+                flags[1]
+            )));
+        }
+        let left_child = match flags[1] & 0b01 {
+            0 => None,
+            _ => Some(decode_label(left_child)?),
+        };
+        let right_child = match flags[1] & 0b10 {
+            0 => None,
+            _ => Some(decode_label(right_child)?),
+        };
+        Ok(TreeNode {
+            label: decode_label(label)?,
+            last_epoch: u64::from_be_bytes(
+                last_epoch.try_into().expect("Slice with incorrect length"),
+            ),
+            min_descendant_epoch: u64::from_be_bytes(
+                min_descendant_epoch
+                    .try_into()
+                    .expect("Slice with incorrect length"),
+            ),
+            parent: decode_label(parent)?,
+            node_type,
+            left_child,
+            right_child,
+            hash: hash.try_into().expect("Slice with incorrect length"),
+        })
+    }
+}
+
+impl TreeNodeWithPreviousValue {
+    /// The maximum length of a node encoded with [TreeNodeWithPreviousValue::to_bytes]
+    pub const MAX_ENCODED_LEN: usize = 1 + 2 * ENCODED_TREE_NODE_LEN;
+
+    /// Encodes the node in a compact binary format: a version byte, the latest
+    /// node and, if any, the previous node, each encoded with [TreeNode::to_bytes].
+    /// This is how the in-memory and MySQL backends store tree nodes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(Self::MAX_ENCODED_LEN);
+        result.push(TREE_NODE_ENCODING_VERSION);
+        result.extend_from_slice(&self.latest_node.to_bytes());
+        if let Some(previous_node) = &self.previous_node {
+            result.extend_from_slice(&previous_node.to_bytes());
+        }
+        result
+    }
+
+    /// Decodes a node encoded with [TreeNodeWithPreviousValue::to_bytes]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StorageError> {
+        match bytes.split_first() {
+            Some((&TREE_NODE_ENCODING_VERSION, nodes)) => {
+                let (latest_node, previous_node) = match nodes.len() {
+                    ENCODED_TREE_NODE_LEN => (TreeNode::from_bytes(nodes)?, None),
+                    len if len == 2 * ENCODED_TREE_NODE_LEN => {
+                        let (latest, previous) = nodes.split_at(ENCODED_TREE_NODE_LEN);
+                        (
+                            TreeNode::from_bytes(latest)?,
+                            Some(TreeNode::from_bytes(previous)?),
+                        )
+                    }
+                    len => {
+                        return Err(StorageError::Corruption(format!(
+                            "Invalid encoded tree node length {}",
+                            len
+                        )))
+                    }
+                };
+                Ok(TreeNodeWithPreviousValue {
+                    label: latest_node.label,
+                    latest_node,
+                    previous_node,
+                })
+            }
+            Some((version, _)) => Err(StorageError::Corruption(format!(
+                "Unsupported tree node encoding version {}",
+                version
+            ))),
+            None => Err(StorageError::Corruption(
+                "Empty encoded tree node".to_string(),
+            )),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -593,6 +788,52 @@ mod tests {
         label.hash()
     }
 
+    #[test]
+    fn test_compact_encoding() {
+        let mut node = new_interior_node(NodeLabel::new(byte_arr_from_u64(0b1u64 << 63), 1u32), 3);
+        node.right_child = Some(NodeLabel::new(byte_arr_from_u64(0b11u64 << 62), 2u32));
+        node.hash = crate::hash::hash(&EMPTY_VALUE);
+        let leaf = new_leaf_node(
+            NodeLabel::new(byte_arr_from_u64(0b01u64 << 62), 2u32),
+            &crate::hash::hash(&EMPTY_VALUE),
+            5,
+        );
+        assert_eq!(Ok(leaf.clone()), TreeNode::from_bytes(&leaf.to_bytes()));
+        assert_eq!(Ok(node.clone()), TreeNode::from_bytes(&node.to_bytes()));
+
+        let mut updated = node.clone();
+        updated.last_epoch = 4;
+        updated.left_child = Some(leaf.label);
+        for value in [
+            TreeNodeWithPreviousValue::from_tree_node(node),
+            TreeNodeWithPreviousValue {
+                label: updated.label,
+                latest_node: updated,
+                previous_node: Some(new_root_node()),
+            },
+        ] {
+            let bytes = value.to_bytes();
+            assert_eq!(Ok(value), TreeNodeWithPreviousValue::from_bytes(&bytes));
+            assert!(TreeNodeWithPreviousValue::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        }
+
+        let mut bytes = leaf.to_bytes();
+        bytes[2 * ENCODED_LABEL_LEN + 16] = 7;
+        assert!(matches!(
+            TreeNode::from_bytes(&bytes),
+            Err(StorageError::Corruption(_))
+        ));
+        // a label can't be longer than 256 bits
+        let mut bytes = leaf.to_bytes();
+        bytes[32..ENCODED_LABEL_LEN].copy_from_slice(&257u32.to_be_bytes());
+        assert!(matches!(
+            TreeNode::from_bytes(&bytes),
+            Err(StorageError::Corruption(_))
+        ));
+        assert!(TreeNodeWithPreviousValue::from_bytes(&[2]).is_err());
+        assert!(TreeNodeWithPreviousValue::from_bytes(&[]).is_err());
+    }
+
     #[tokio::test]
     async fn test_smallest_descendant_ep() -> Result<(), AkdError> {
         let database = InMemoryDb::new();
@@ -686,6 +927,37 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_child_accessors() -> Result<(), AkdError> {
+        let mut root = new_root_node();
+        assert_eq!(None, root.left());
+        assert_eq!(None, root.right());
+        assert_eq!(0, root.children().count());
+
+        let right_label = NodeLabel::new(byte_arr_from_u64(0b1u64 << 63), 1u32);
+        let mut right_child = new_interior_node(right_label, 1);
+        root.set_child(&mut right_child)?;
+        assert_eq!(None, root.left());
+        assert_eq!(Some(right_label), root.right());
+        assert_eq!(vec![right_label], root.children().collect::<Vec<_>>());
+
+        let left_label = NodeLabel::new(byte_arr_from_u64(0b0u64), 1u32);
+        let mut left_child = new_interior_node(left_label, 1);
+        root.set_child(&mut left_child)?;
+        assert_eq!(Some(left_label), root.left());
+        assert_eq!(
+            vec![left_label, right_label],
+            root.children().collect::<Vec<_>>()
+        );
+
+        // a node with the same label has no direction relative to the parent
+        let mut not_a_child = new_interior_node(left_label, 1);
+        assert!(left_child.set_child(&mut not_a_child).is_err());
+        assert!(root.get_child_label(Direction::None).is_err());
+
+        Ok(())
+    }
+
     // insert_single_leaf tests
     #[tokio::test]
     async fn test_insert_single_leaf_root() -> Result<(), AkdError> {
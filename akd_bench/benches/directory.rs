@@ -0,0 +1,187 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+#[macro_use]
+extern crate criterion;
+
+use akd::storage::{Database, StorageManager};
+use akd_bench::fixtures::{in_memory_storage, populated_directory, user_label, user_updates};
+use akd_mysql::mysql::AsyncMySqlDatabase;
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput};
+use tokio::runtime::Runtime;
+
+const INITIAL_USERS: u64 = 1000;
+const POPULATE_BATCH_SIZE: u64 = 1000;
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+fn publish_throughput(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("Publish throughput");
+    group.sample_size(10);
+
+    for batch_size in [10u64, 100, 1000] {
+        group.throughput(Throughput::Elements(batch_size));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(batch_size),
+            &batch_size,
+            |b, &batch_size| {
+                b.iter_batched(
+                    || {
+                        runtime
+                            .block_on(populated_directory(
+                                in_memory_storage(false),
+                                INITIAL_USERS,
+                                POPULATE_BATCH_SIZE,
+                            ))
+                            .unwrap()
+                    },
+                    |directory| {
+                        runtime
+                            .block_on(directory.publish(user_updates(INITIAL_USERS, batch_size, 0)))
+                            .unwrap();
+                    },
+                    BatchSize::PerIteration,
+                );
+            },
+        );
+    }
+    group.finish();
+}
+
+fn lookup_latency(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("Lookup proof latency");
+
+    for tree_size in [100u64, 1000, 10000] {
+        let directory = runtime
+            .block_on(populated_directory(
+                in_memory_storage(false),
+                tree_size,
+                POPULATE_BATCH_SIZE,
+            ))
+            .unwrap();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(tree_size),
+            &tree_size,
+            |b, &tree_size| {
+                b.iter(|| {
+                    runtime
+                        .block_on(directory.lookup(user_label(tree_size / 2)))
+                        .unwrap()
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+fn audit_proof(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("Audit proof generation");
+    group.sample_size(10);
+
+    for batch_size in [10u64, 100, 1000] {
+        // the audited epoch inserts `batch_size` users on top of the initial ones
+        let directory = runtime
+            .block_on(populated_directory(
+                in_memory_storage(false),
+                INITIAL_USERS,
+                INITIAL_USERS,
+            ))
+            .unwrap();
+        runtime
+            .block_on(directory.publish(user_updates(INITIAL_USERS, batch_size, 0)))
+            .unwrap();
+
+        group.throughput(Throughput::Elements(batch_size));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(batch_size),
+            &batch_size,
+            |b, _| {
+                b.iter(|| runtime.block_on(directory.audit(1, 2)).unwrap());
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_backend_publish<S: Database + 'static>(
+    c: &mut Criterion,
+    runtime: &Runtime,
+    name: &str,
+    storage: impl Fn() -> StorageManager<S>,
+) {
+    let batch_size = 100;
+    let mut group = c.benchmark_group("Storage backends");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(batch_size));
+    group.bench_function(name, |b| {
+        b.iter_batched(
+            || {
+                runtime
+                    .block_on(populated_directory(
+                        storage(),
+                        INITIAL_USERS,
+                        POPULATE_BATCH_SIZE,
+                    ))
+                    .unwrap()
+            },
+            |directory| {
+                runtime
+                    .block_on(directory.publish(user_updates(INITIAL_USERS, batch_size, 0)))
+                    .unwrap();
+                runtime
+                    .block_on(directory.lookup(user_label(INITIAL_USERS / 2)))
+                    .unwrap();
+            },
+            BatchSize::PerIteration,
+        );
+    });
+    group.finish();
+}
+
+fn storage_backends(c: &mut Criterion) {
+    let runtime = runtime();
+    bench_backend_publish(c, &runtime, "In-memory", || in_memory_storage(false));
+    bench_backend_publish(c, &runtime, "In-memory (cached)", || {
+        in_memory_storage(true)
+    });
+
+    // MySQL is only benchmarked when the docker container from
+    // docker-compose.yml is up
+    if AsyncMySqlDatabase::test_guard() {
+        let db = runtime.block_on(AsyncMySqlDatabase::new(
+            "localhost",
+            "bench_db",
+            Some("root"),
+            Some("example"),
+            Some(8001),
+            200,
+        ));
+        bench_backend_publish(c, &runtime, "MySQL (cached)", || {
+            runtime.block_on(db.delete_data()).unwrap();
+            StorageManager::new(db.clone(), None, None, None)
+        });
+    } else {
+        println!("Docker container not running, skipping the MySQL backend benchmark");
+    }
+}
+
+criterion_group!(
+    directory_benches,
+    publish_throughput,
+    lookup_latency,
+    audit_proof,
+    storage_backends
+);
+criterion_main!(directory_benches);
@@ -0,0 +1,62 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Deterministic data sets and pre-populated directories shared by the
+//! benchmarks and the load test
+
+use akd::ecvrf::HardCodedAkdVRF;
+use akd::errors::AkdError;
+use akd::storage::memory::AsyncInMemoryDatabase;
+use akd::storage::{Database, StorageManager};
+use akd::{AkdLabel, AkdValue, Directory};
+
+/// The label of the i-th user of a data set
+pub fn user_label(user: u64) -> AkdLabel {
+    AkdLabel::from_utf8_str(&format!("user_{}", user))
+}
+
+/// The value published for the i-th user in the given round of updates
+pub fn user_value(user: u64, round: u64) -> AkdValue {
+    AkdValue::from_utf8_str(&format!("key_{}_{}", user, round))
+}
+
+/// A batch of updates for the users `first_user..first_user + num_users`
+pub fn user_updates(first_user: u64, num_users: u64, round: u64) -> Vec<(AkdLabel, AkdValue)> {
+    (first_user..first_user + num_users)
+        .map(|user| (user_label(user), user_value(user, round)))
+        .collect()
+}
+
+/// Creates a directory over the given storage, and publishes `num_users` users
+/// split into batches of (at most) `batch_size`, one epoch per batch
+pub async fn populated_directory<S: Database + 'static>(
+    storage: StorageManager<S>,
+    num_users: u64,
+    batch_size: u64,
+) -> Result<Directory<S, HardCodedAkdVRF>, AkdError> {
+    let directory = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let batch_size = batch_size.max(1);
+    let mut first_user = 0;
+    while first_user < num_users {
+        let batch = batch_size.min(num_users - first_user);
+        directory
+            .publish(user_updates(first_user, batch, 0))
+            .await?;
+        first_user += batch;
+    }
+    Ok(directory)
+}
+
+/// A fresh in-memory storage layer, with or without a cache in front of it
+pub fn in_memory_storage(cached: bool) -> StorageManager<AsyncInMemoryDatabase> {
+    let db = AsyncInMemoryDatabase::new();
+    if cached {
+        StorageManager::new(db, None, None, None)
+    } else {
+        StorageManager::new_no_cache(db)
+    }
+}
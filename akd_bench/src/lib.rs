@@ -0,0 +1,23 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Benchmarking and load testing utilities for an auditable key directory (AKD).
+//!
+//! The criterion benchmarks live in `benches/directory.rs` and can be run with
+//! ```bash
+//! cargo bench -p akd_bench
+//! ```
+//! while a parameterized load test against either the in-memory or the MySQL
+//! storage layer can be run with
+//! ```bash
+//! cargo run -p akd_bench --release -- --help
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod fixtures;
+pub mod load_test;
@@ -0,0 +1,229 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! A parameterized load test which can be run against any storage layer. Each
+//! epoch publishes a batch of updates (a mix of new users and updates to existing
+//! ones), followed by a number of lookups of random existing users. Once all the
+//! epochs are published, an audit proof is generated for every pair of
+//! consecutive epochs.
+
+use crate::fixtures::{user_label, user_updates};
+use akd::ecvrf::HardCodedAkdVRF;
+use akd::errors::AkdError;
+use akd::storage::{Database, StorageManager};
+use akd::Directory;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::{Duration, Instant};
+
+/// The parameters of a load test
+#[derive(Debug, Clone)]
+pub struct LoadTestConfig {
+    /// The number of epochs to publish
+    pub num_epochs: u64,
+    /// The number of updates published in each epoch
+    pub batch_size: u64,
+    /// The fraction (in `0.0..=1.0`) of each batch which updates existing users
+    /// rather than adding new ones
+    pub update_ratio: f64,
+    /// The number of lookups issued after each publish
+    pub lookups_per_epoch: u64,
+    /// Whether to generate audit proofs between consecutive epochs
+    pub audit: bool,
+    /// The seed for picking the users to update and look up
+    pub seed: u64,
+}
+
+impl Default for LoadTestConfig {
+    fn default() -> Self {
+        Self {
+            num_epochs: 10,
+            batch_size: 1000,
+            update_ratio: 0.1,
+            lookups_per_epoch: 100,
+            audit: true,
+            seed: 42,
+        }
+    }
+}
+
+/// Latency statistics of a single operation
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    samples: Vec<Duration>,
+}
+
+impl LatencyStats {
+    /// Records the latency of a single call
+    pub fn record(&mut self, latency: Duration) {
+        self.samples.push(latency);
+    }
+
+    /// The number of recorded calls
+    pub fn count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// The sum of all the recorded latencies
+    pub fn total(&self) -> Duration {
+        self.samples.iter().sum()
+    }
+
+    /// The mean latency, or zero if no call was recorded
+    pub fn mean(&self) -> Duration {
+        if self.samples.is_empty() {
+            Duration::default()
+        } else {
+            self.total() / self.samples.len() as u32
+        }
+    }
+
+    /// The latency at the given percentile (in `0.0..=100.0`), or zero if no
+    /// call was recorded
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::default();
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f64).round();
+        sorted[rank as usize]
+    }
+}
+
+impl std::fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} calls, mean {:?}, p50 {:?}, p99 {:?}, max {:?}",
+            self.count(),
+            self.mean(),
+            self.percentile(50.0),
+            self.percentile(99.0),
+            self.percentile(100.0)
+        )
+    }
+}
+
+/// The outcome of a load test
+#[derive(Debug, Clone, Default)]
+pub struct LoadTestReport {
+    /// The number of distinct users in the directory at the end of the test
+    pub num_users: u64,
+    /// Latencies of the publish calls
+    pub publish: LatencyStats,
+    /// Latencies of the lookup calls
+    pub lookup: LatencyStats,
+    /// Latencies of the audit proof generation calls
+    pub audit: LatencyStats,
+    /// The wall-clock duration of the whole test
+    pub elapsed: Duration,
+}
+
+impl LoadTestReport {
+    /// The number of updates published per second, over all the publish calls
+    pub fn publish_throughput(&self, batch_size: u64) -> f64 {
+        let total = self.publish.total().as_secs_f64();
+        if total == 0.0 {
+            0.0
+        } else {
+            (self.publish.count() as u64 * batch_size) as f64 / total
+        }
+    }
+}
+
+impl std::fmt::Display for LoadTestReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Users:   {}", self.num_users)?;
+        writeln!(f, "Publish: {}", self.publish)?;
+        writeln!(f, "Lookup:  {}", self.lookup)?;
+        writeln!(f, "Audit:   {}", self.audit)?;
+        write!(f, "Elapsed: {:?}", self.elapsed)
+    }
+}
+
+/// Runs a load test against a directory backed by the given storage layer.
+/// The storage should be empty, as the test starts from epoch 0.
+pub async fn run_load_test<S: Database + 'static>(
+    storage: StorageManager<S>,
+    config: &LoadTestConfig,
+) -> Result<LoadTestReport, AkdError> {
+    let start = Instant::now();
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let directory = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await?;
+    let mut report = LoadTestReport::default();
+
+    for epoch in 1..=config.num_epochs {
+        let num_updated = if report.num_users == 0 {
+            0
+        } else {
+            ((config.batch_size as f64 * config.update_ratio.clamp(0.0, 1.0)) as u64)
+                .min(report.num_users)
+        };
+        let num_added = config.batch_size - num_updated;
+
+        // updates of existing users are a contiguous (wrapping) range, so a
+        // batch never holds the same user twice
+        let first_updated = rng.gen_range(0..report.num_users.max(1));
+        let mut updates: Vec<_> = (0..num_updated)
+            .flat_map(|i| user_updates((first_updated + i) % report.num_users, 1, epoch))
+            .collect();
+        updates.extend(user_updates(report.num_users, num_added, epoch));
+
+        let timer = Instant::now();
+        directory.publish(updates).await?;
+        report.publish.record(timer.elapsed());
+        report.num_users += num_added;
+
+        if report.num_users > 0 {
+            for _ in 0..config.lookups_per_epoch {
+                let label = user_label(rng.gen_range(0..report.num_users));
+                let timer = Instant::now();
+                directory.lookup(label).await?;
+                report.lookup.record(timer.elapsed());
+            }
+        }
+    }
+
+    if config.audit {
+        for epoch in 1..config.num_epochs {
+            let timer = Instant::now();
+            directory.audit(epoch, epoch + 1).await?;
+            report.audit.record(timer.elapsed());
+        }
+    }
+
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::in_memory_storage;
+
+    #[tokio::test]
+    async fn test_load_test_in_memory() -> Result<(), AkdError> {
+        let config = LoadTestConfig {
+            num_epochs: 4,
+            batch_size: 20,
+            update_ratio: 0.5,
+            lookups_per_epoch: 5,
+            audit: true,
+            seed: 1,
+        };
+        let report = run_load_test(in_memory_storage(false), &config).await?;
+
+        // the first epoch only adds users, the following ones add half a batch each
+        assert_eq!(20 + 3 * 10, report.num_users);
+        assert_eq!(4, report.publish.count());
+        assert_eq!(20, report.lookup.count());
+        assert_eq!(3, report.audit.count());
+        assert!(report.publish.percentile(0.0) <= report.publish.percentile(100.0));
+        Ok(())
+    }
+}
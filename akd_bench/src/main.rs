@@ -0,0 +1,140 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! A command-line load test of an auditable key directory, against either the
+//! in-memory or the MySQL storage layer
+
+use akd::storage::{Database, StorageManager};
+use akd_bench::fixtures::in_memory_storage;
+use akd_bench::load_test::{run_load_test, LoadTestConfig};
+use akd_mysql::mysql::AsyncMySqlDatabase;
+use clap::{ArgEnum, Parser};
+use colored::*;
+use std::time::Duration;
+
+#[derive(ArgEnum, Clone, Debug)]
+enum Backend {
+    Memory,
+    MemoryCached,
+    Mysql,
+}
+
+#[derive(Parser, Debug)]
+#[clap(about = "Run a load test against an AKD directory")]
+struct Cli {
+    /// The storage layer to run the load test against
+    #[clap(arg_enum, long, short = 'b', default_value = "memory")]
+    backend: Backend,
+
+    /// The number of epochs to publish
+    #[clap(long, short = 'e', default_value = "10")]
+    epochs: u64,
+
+    /// The number of updates published in each epoch
+    #[clap(long, short = 's', default_value = "1000")]
+    batch_size: u64,
+
+    /// The fraction of each batch which updates existing users
+    #[clap(long, default_value = "0.1")]
+    update_ratio: f64,
+
+    /// The number of lookups issued after each publish
+    #[clap(long, short = 'l', default_value = "100")]
+    lookups: u64,
+
+    /// Skip the generation of audit proofs
+    #[clap(long)]
+    no_audit: bool,
+
+    /// The seed for picking the users to update and look up
+    #[clap(long, default_value = "42")]
+    seed: u64,
+
+    /// The MySQL endpoint
+    #[clap(long, default_value = "localhost")]
+    mysql_endpoint: String,
+
+    /// The MySQL port
+    #[clap(long, default_value = "8001")]
+    mysql_port: u16,
+
+    /// The MySQL database, whose tables are cleared before the load test
+    #[clap(long, default_value = "default")]
+    mysql_database: String,
+
+    /// The MySQL user
+    #[clap(long, default_value = "root")]
+    mysql_user: String,
+
+    /// The MySQL password
+    #[clap(long, default_value = "example")]
+    mysql_password: String,
+
+    /// The MySQL multi-row insert size
+    #[clap(long, default_value = "100")]
+    mysql_insert_depth: usize,
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let config = LoadTestConfig {
+        num_epochs: cli.epochs,
+        batch_size: cli.batch_size,
+        update_ratio: cli.update_ratio,
+        lookups_per_epoch: cli.lookups,
+        audit: !cli.no_audit,
+        seed: cli.seed,
+    };
+    println!(
+        "Running load test on the {:?} backend with {:?}",
+        cli.backend, config
+    );
+
+    match cli.backend {
+        Backend::Memory => run(in_memory_storage(false), &config).await,
+        Backend::MemoryCached => run(in_memory_storage(true), &config).await,
+        Backend::Mysql => {
+            let db = AsyncMySqlDatabase::new(
+                cli.mysql_endpoint,
+                cli.mysql_database,
+                Some(cli.mysql_user),
+                Some(cli.mysql_password),
+                Some(cli.mysql_port),
+                cli.mysql_insert_depth,
+            )
+            .await;
+            if let Err(error) = db.delete_data().await {
+                println!(
+                    "{}",
+                    format!("Failed to clear the database: {}", error).red()
+                );
+                return;
+            }
+            let storage = StorageManager::new(
+                db,
+                Some(Duration::from_secs(10 * 60)),
+                None,
+                Some(Duration::from_secs(15)),
+            );
+            run(storage, &config).await
+        }
+    }
+}
+
+async fn run<S: Database + 'static>(storage: StorageManager<S>, config: &LoadTestConfig) {
+    match run_load_test(storage, config).await {
+        Ok(report) => {
+            println!("{}", report);
+            println!(
+                "Publish throughput: {:.2} updates/s",
+                report.publish_throughput(config.batch_size)
+            );
+        }
+        Err(error) => println!("{}", format!("Load test failed: {}", error).red()),
+    }
+}
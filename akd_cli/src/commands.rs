@@ -0,0 +1,338 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! The commands of the CLI
+
+use crate::input::{read_updates, InputFormat};
+use crate::storage::{load_file, save_file, StorageConfig, MYSQL_INSERT_DEPTH};
+use crate::vrf::{load_vrf_key, CliVrf, VrfKeySource};
+use crate::CliError;
+use akd::hash::try_parse_digest;
+use akd::storage::{Database, StorageManager};
+use akd::{AkdLabel, AppendOnlyProof, Digest, Directory, LookupProof};
+use akd_mysql::mysql::AsyncMySqlDatabase;
+use std::path::{Path, PathBuf};
+
+/// A command run against a directory
+#[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Creates an empty directory, failing if the storage already holds one
+    Init,
+    /// Publishes the updates listed in a file as a new epoch
+    Publish {
+        /// The file listing the updates
+        file: PathBuf,
+        /// The format of the file
+        #[clap(long, arg_enum, default_value = "auto")]
+        format: InputFormat,
+    },
+    /// Looks up the latest value of a label
+    Lookup {
+        /// The label to look up
+        label: String,
+        /// Writes the lookup proof to the given file
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+    /// Proves and verifies that the directory only grew between two epochs
+    Audit {
+        /// The epoch the audit starts at
+        start_epoch: u64,
+        /// The epoch the audit ends at
+        end_epoch: u64,
+        /// Writes the audit proof to the given file
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+    /// Verifies a proof file written by `lookup` or `audit`, without accessing the directory
+    Verify {
+        /// The proof file
+        proof: PathBuf,
+        /// The root hash the proof is expected to be against (the end epoch's, for an
+        /// audit), hex-encoded
+        #[clap(long)]
+        root_hash: Option<String>,
+    },
+}
+
+impl Command {
+    /// Whether the command changes the records of the directory
+    pub fn modifies_directory(&self) -> bool {
+        matches!(self, Self::Init | Self::Publish { .. })
+    }
+}
+
+/// A proof written to a file, holding everything needed to verify it offline
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
+pub enum ProofFile {
+    /// The proof of a lookup
+    Lookup {
+        /// The label looked up
+        label: String,
+        /// The VRF public key of the directory, hex-encoded
+        vrf_public_key: String,
+        /// The epoch the proof is for
+        epoch: u64,
+        /// The root hash of the epoch, hex-encoded
+        root_hash: String,
+        /// The lookup proof
+        proof: LookupProof,
+    },
+    /// The proof of an audit
+    Audit {
+        /// The epoch the audit starts at
+        start_epoch: u64,
+        /// The epoch the audit ends at
+        end_epoch: u64,
+        /// The root hashes of the epochs audited, from the start epoch to the end
+        /// epoch, hex-encoded
+        root_hashes: Vec<String>,
+        /// The append-only proof
+        proof: AppendOnlyProof,
+    },
+}
+
+/// Runs a command against the directory in the given storage, with the VRF key
+/// loaded from the given source (see [load_vrf_key]), returning the output to display
+pub async fn run(
+    storage: &StorageConfig,
+    vrf_key: Option<&VrfKeySource>,
+    command: Command,
+) -> Result<String, CliError> {
+    if let Command::Verify { proof, root_hash } = &command {
+        return verify(proof, root_hash.as_deref()).await;
+    }
+    let vrf = load_vrf_key(vrf_key, storage).await?;
+
+    match storage {
+        StorageConfig::File(path) => {
+            let db = load_file(path).await?;
+            let output = run_on(db.clone(), vrf, &command).await?;
+            if command.modifies_directory() {
+                save_file(&db, path).await?;
+            }
+            Ok(output)
+        }
+        StorageConfig::MySql {
+            endpoint,
+            database,
+            user,
+            password,
+            port,
+        } => {
+            let db = AsyncMySqlDatabase::new(
+                endpoint.clone(),
+                database.clone(),
+                user.clone(),
+                password.clone(),
+                *port,
+                MYSQL_INSERT_DEPTH,
+            )
+            .await;
+            run_on(db, vrf, &command).await
+        }
+    }
+}
+
+async fn open<S: Database + 'static>(
+    db: S,
+    vrf: &CliVrf,
+    read_only: bool,
+) -> Result<Directory<S, CliVrf>, CliError> {
+    let storage = StorageManager::new_no_cache(db);
+    Ok(Directory::<_, _>::new(storage, vrf.clone(), read_only).await?)
+}
+
+async fn run_on<S: Database + 'static>(
+    db: S,
+    vrf: CliVrf,
+    command: &Command,
+) -> Result<String, CliError> {
+    match command {
+        Command::Init => {
+            if open(db.clone(), &vrf, true).await.is_ok() {
+                return Err(CliError::Input(
+                    "The storage already holds a directory".to_string(),
+                ));
+            }
+            let directory = open(db, &vrf, false).await?;
+            let epoch_hash = directory.get_epoch_hash();
+            Ok(format!(
+                "Initialized the directory at epoch {} with root hash {}\nVRF public key: {}\nTree head public key: {}",
+                epoch_hash.epoch(),
+                hex::encode(epoch_hash.hash()),
+                hex::encode(directory.get_public_key().await?.as_bytes()),
+                hex::encode(directory.get_tree_head_public_key().await?.as_bytes()),
+            ))
+        }
+        Command::Publish { file, format } => {
+            let directory = open(db, &vrf, false).await?;
+            let updates = read_updates(file, *format)?;
+            let num_updates = updates.len();
+            let epoch_hash = directory.publish(updates).await?;
+            Ok(format!(
+                "Published {} updates at epoch {} with root hash {}",
+                num_updates,
+                epoch_hash.epoch(),
+                hex::encode(epoch_hash.hash())
+            ))
+        }
+        Command::Lookup { label, out } => {
+            let directory = open(db, &vrf, true).await?;
+            let vrf_public_key = directory.get_public_key().await?;
+            let (proof, epoch_hash) = directory.lookup(AkdLabel::from_utf8_str(label)).await?;
+            let result = akd::client::lookup_verify(
+                vrf_public_key.as_bytes(),
+                epoch_hash.hash(),
+                AkdLabel::from_utf8_str(label),
+                proof.clone(),
+            )
+            .map_err(|err| CliError::Verification(err.to_string()))?;
+            if let Some(out) = out {
+                write_proof_file(
+                    out,
+                    &ProofFile::Lookup {
+                        label: label.clone(),
+                        vrf_public_key: hex::encode(vrf_public_key.as_bytes()),
+                        epoch: epoch_hash.epoch(),
+                        root_hash: hex::encode(epoch_hash.hash()),
+                        proof,
+                    },
+                )
+                .await?;
+            }
+            Ok(format!(
+                "{} at epoch {}: version {} published at epoch {}, value {}",
+                label,
+                epoch_hash.epoch(),
+                result.version,
+                result.epoch,
+                String::from_utf8_lossy(&result.value)
+            ))
+        }
+        Command::Audit {
+            start_epoch,
+            end_epoch,
+            out,
+        } => {
+            let directory = open(db, &vrf, true).await?;
+            let proof = directory.audit(*start_epoch, *end_epoch).await?;
+            let mut root_hashes = vec![];
+            for epoch in *start_epoch..=*end_epoch {
+                root_hashes.push(directory.get_root_hash_at_epoch(epoch).await?);
+            }
+            akd::auditor::audit_verify(root_hashes.clone(), proof.clone()).await?;
+            if let Some(out) = out {
+                write_proof_file(
+                    out,
+                    &ProofFile::Audit {
+                        start_epoch: *start_epoch,
+                        end_epoch: *end_epoch,
+                        root_hashes: root_hashes.iter().map(hex::encode).collect(),
+                        proof,
+                    },
+                )
+                .await?;
+            }
+            Ok(format!(
+                "Verified the audit from epoch {} to epoch {}",
+                start_epoch, end_epoch
+            ))
+        }
+        Command::Verify { proof, root_hash } => verify(proof, root_hash.as_deref()).await,
+    }
+}
+
+async fn write_proof_file(path: &Path, proof: &ProofFile) -> Result<(), CliError> {
+    let contents = serde_json::to_vec_pretty(proof)
+        .map_err(|err| CliError::Io(format!("Failed to encode the proof: {}", err)))?;
+    tokio::fs::write(path, contents)
+        .await
+        .map_err(|err| CliError::Io(format!("Failed to write {}: {}", path.display(), err)))
+}
+
+async fn verify(path: &Path, pinned_root_hash: Option<&str>) -> Result<String, CliError> {
+    let contents = tokio::fs::read(path)
+        .await
+        .map_err(|err| CliError::Io(format!("Failed to read {}: {}", path.display(), err)))?;
+    let proof_file: ProofFile = serde_json::from_slice(&contents)
+        .map_err(|err| CliError::Input(format!("{}: {}", path.display(), err)))?;
+
+    match proof_file {
+        ProofFile::Lookup {
+            label,
+            vrf_public_key,
+            epoch,
+            root_hash,
+            proof,
+        } => {
+            let root_hash = check_pinned_root_hash(&root_hash, pinned_root_hash)?;
+            let vrf_public_key = hex::decode(&vrf_public_key)
+                .map_err(|err| CliError::Input(format!("Invalid VRF public key: {}", err)))?;
+            let result = akd::client::lookup_verify(
+                &vrf_public_key,
+                root_hash,
+                AkdLabel::from_utf8_str(&label),
+                proof,
+            )
+            .map_err(|err| CliError::Verification(err.to_string()))?;
+            Ok(format!(
+                "Verified the lookup of {} at epoch {}: version {} published at epoch {}, value {}",
+                label,
+                epoch,
+                result.version,
+                result.epoch,
+                String::from_utf8_lossy(&result.value)
+            ))
+        }
+        ProofFile::Audit {
+            start_epoch,
+            end_epoch,
+            root_hashes,
+            proof,
+        } => {
+            let last = root_hashes
+                .last()
+                .ok_or_else(|| CliError::Input("The audit has no root hashes".to_string()))?;
+            check_pinned_root_hash(last, pinned_root_hash)?;
+            let root_hashes = root_hashes
+                .iter()
+                .map(|root_hash| decode_digest(root_hash))
+                .collect::<Result<Vec<_>, _>>()?;
+            akd::auditor::audit_verify(root_hashes, proof)
+                .await
+                .map_err(|err| CliError::Verification(err.to_string()))?;
+            Ok(format!(
+                "Verified the audit from epoch {} to epoch {}",
+                start_epoch, end_epoch
+            ))
+        }
+    }
+}
+
+fn decode_digest(value: &str) -> Result<Digest, CliError> {
+    let bytes = hex::decode(value)
+        .map_err(|err| CliError::Input(format!("Invalid root hash {}: {}", value, err)))?;
+    try_parse_digest(&bytes).map_err(CliError::Input)
+}
+
+/// Decodes the root hash of a proof file, checking that it is the pinned root hash
+fn check_pinned_root_hash(root_hash: &str, pinned: Option<&str>) -> Result<Digest, CliError> {
+    let root_hash = decode_digest(root_hash)?;
+    if let Some(pinned) = pinned {
+        if decode_digest(pinned)? != root_hash {
+            return Err(CliError::Verification(format!(
+                "The proof is against root hash {}, not the pinned root hash {}",
+                hex::encode(root_hash),
+                pinned
+            )));
+        }
+    }
+    Ok(root_hash)
+}
@@ -0,0 +1,93 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Parsing of the files listing the updates to publish
+
+use crate::CliError;
+use akd::{AkdLabel, AkdValue};
+use std::path::Path;
+
+/// The format of a file listing updates
+#[derive(clap::ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Determined by the extension of the file, `.jsonl` for JSON Lines and CSV otherwise
+    Auto,
+    /// `label,value` rows, with an optional `label,value` header
+    Csv,
+    /// One `{"label": "...", "value": "..."}` object per line
+    Jsonl,
+}
+
+/// An update in a JSON Lines file
+#[derive(serde::Deserialize)]
+struct JsonUpdate {
+    label: String,
+    value: String,
+}
+
+/// Reads the updates listed in a file, in order
+pub fn read_updates(
+    path: &Path,
+    format: InputFormat,
+) -> Result<Vec<(AkdLabel, AkdValue)>, CliError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| CliError::Io(format!("Failed to read {}: {}", path.display(), err)))?;
+    let format = match format {
+        InputFormat::Auto => match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") => InputFormat::Jsonl,
+            _ => InputFormat::Csv,
+        },
+        format => format,
+    };
+    match format {
+        InputFormat::Jsonl => parse_jsonl(&contents),
+        _ => parse_csv(&contents),
+    }
+}
+
+/// Parses `label,value` rows, skipping a `label,value` header if present
+pub fn parse_csv(contents: &str) -> Result<Vec<(AkdLabel, AkdValue)>, CliError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(contents.as_bytes());
+    let mut updates = vec![];
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|err| CliError::Input(format!("Row {}: {}", i + 1, err)))?;
+        if record.len() != 2 {
+            return Err(CliError::Input(format!(
+                "Row {}: expected 2 fields (label,value), found {}",
+                i + 1,
+                record.len()
+            )));
+        }
+        if i == 0 && &record[0] == "label" && &record[1] == "value" {
+            continue;
+        }
+        updates.push((
+            AkdLabel::from_utf8_str(&record[0]),
+            AkdValue::from_utf8_str(&record[1]),
+        ));
+    }
+    Ok(updates)
+}
+
+/// Parses one `{"label": "...", "value": "..."}` object per line, skipping blank lines
+pub fn parse_jsonl(contents: &str) -> Result<Vec<(AkdLabel, AkdValue)>, CliError> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let update: JsonUpdate = serde_json::from_str(line)
+                .map_err(|err| CliError::Input(format!("Line {}: {}", i + 1, err)))?;
+            Ok((
+                AkdLabel::from_utf8_str(&update.label),
+                AkdValue::from_utf8_str(&update.value),
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,102 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! A command-line interface to an auditable key directory, for operators and for
+//! debugging without writing Rust.
+//!
+//! The `akd-cli` binary runs a single [Command] against a directory stored in one
+//! of the backends of [StorageConfig]: a JSON file holding all the records (the
+//! default), or a MySQL database. For example
+//!
+//! ```bash
+//! # create the directory, and publish the updates listed in a file
+//! akd-cli --file directory.json init
+//! akd-cli --file directory.json publish updates.csv
+//!
+//! # look up a label, saving the proof, and verify it offline
+//! akd-cli --file directory.json lookup alice --out alice.json
+//! akd-cli verify alice.json --root-hash <hex>
+//!
+//! # prove and verify that the directory only grew between two epochs
+//! akd-cli --mysql-endpoint localhost --mysql-port 8001 --vrf-key-file vrf.key audit 1 5
+//! ```
+//!
+//! # Updates
+//!
+//! The updates to publish are read from a CSV file with `label,value` rows (with an
+//! optional `label,value` header), or from a JSON Lines file with one
+//! `{"label": "...", "value": "..."}` object per line, see [input::read_updates].
+//!
+//! # Proof files
+//!
+//! The lookup and audit proofs dumped with `--out` are [ProofFile]s, which hold
+//! everything needed to verify the proof offline with the `verify` command. The
+//! root hash a proof file claims can be pinned with `--root-hash`, e.g. to the root
+//! hash of a tree head obtained independently.
+//!
+//! # VRF key
+//!
+//! The VRF private key of the directory is given hex-encoded with `--vrf-key`, or in
+//! a file with `--vrf-key-file`. A directory stored in MySQL requires one of them,
+//! while a directory stored in a file, meant for demos and debugging, falls back to
+//! the hard-coded key of [akd::ecvrf::HardCodedAkdVRF], see [vrf::load_vrf_key].
+
+#![warn(missing_docs)]
+
+pub mod commands;
+pub mod input;
+pub mod storage;
+pub mod vrf;
+
+#[cfg(test)]
+mod tests;
+
+pub use commands::{run, Command, ProofFile};
+pub use input::InputFormat;
+pub use storage::StorageConfig;
+pub use vrf::VrfKeySource;
+
+use akd::errors::AkdError;
+use std::fmt;
+
+/// An error running a [Command]
+#[derive(Debug)]
+pub enum CliError {
+    /// The directory failed to serve the request
+    Akd(AkdError),
+    /// Reading or writing a file failed
+    Io(String),
+    /// A file couldn't be parsed
+    Input(String),
+    /// A proof failed to verify
+    Verification(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Akd(err) => write!(f, "Directory error: {}", err),
+            Self::Io(err) => write!(f, "I/O error: {}", err),
+            Self::Input(err) => write!(f, "Invalid input: {}", err),
+            Self::Verification(err) => write!(f, "Verification failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<AkdError> for CliError {
+    fn from(err: AkdError) -> Self {
+        Self::Akd(err)
+    }
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err.to_string())
+    }
+}
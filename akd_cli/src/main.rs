@@ -0,0 +1,82 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! A command-line interface to initialize, publish to, query and audit an
+//! auditable key directory (see the [akd_cli] crate documentation).
+
+use akd_cli::{run, Command, StorageConfig, VrfKeySource};
+use clap::Parser;
+use std::path::PathBuf;
+
+/// AKD directory CLI
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Arguments {
+    /// The JSON file storing the directory, unless a MySQL endpoint is given
+    #[clap(long, default_value = "akd_directory.json")]
+    file: PathBuf,
+
+    /// The host of the MySQL server storing the directory
+    #[clap(long)]
+    mysql_endpoint: Option<String>,
+
+    /// The name of the MySQL database
+    #[clap(long, default_value = "default")]
+    mysql_database: String,
+
+    /// The MySQL user
+    #[clap(long)]
+    mysql_user: Option<String>,
+
+    /// The password of the MySQL user
+    #[clap(long)]
+    mysql_password: Option<String>,
+
+    /// The port of the MySQL server
+    #[clap(long)]
+    mysql_port: Option<u16>,
+
+    /// The hex-encoded VRF private key of the directory
+    #[clap(long, conflicts_with = "vrf-key-file")]
+    vrf_key: Option<String>,
+
+    /// A file holding the hex-encoded VRF private key of the directory
+    #[clap(long)]
+    vrf_key_file: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Arguments::parse();
+    let storage = match args.mysql_endpoint {
+        Some(endpoint) => StorageConfig::MySql {
+            endpoint,
+            database: args.mysql_database,
+            user: args.mysql_user,
+            password: args.mysql_password,
+            port: args.mysql_port,
+        },
+        None => StorageConfig::File(args.file),
+    };
+
+    let vrf_key = match (args.vrf_key, args.vrf_key_file) {
+        (Some(key), _) => Some(VrfKeySource::Hex(key)),
+        (None, Some(path)) => Some(VrfKeySource::File(path)),
+        (None, None) => None,
+    };
+
+    match run(&storage, vrf_key.as_ref(), args.command).await {
+        Ok(output) => println!("{}", output),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
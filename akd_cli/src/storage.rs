@@ -0,0 +1,71 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! The storage backends a directory can be operated on
+
+use crate::CliError;
+use akd::storage::memory::AsyncInMemoryDatabase;
+use akd::storage::types::DbRecord;
+use akd::storage::{Database, StorageUtil};
+use std::path::{Path, PathBuf};
+
+/// The number of rows inserted per statement by the MySQL backend
+pub const MYSQL_INSERT_DEPTH: usize = 100;
+
+/// Where the records of a directory are stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageConfig {
+    /// A JSON file holding all the records of the directory, which are loaded
+    /// in memory to run a command and written back if the command changed them
+    File(PathBuf),
+    /// A MySQL database
+    MySql {
+        /// The host of the database server
+        endpoint: String,
+        /// The name of the database
+        database: String,
+        /// The user to connect as
+        user: Option<String>,
+        /// The password of the user
+        password: Option<String>,
+        /// The port of the database server
+        port: Option<u16>,
+    },
+}
+
+/// Loads the records of a directory stored in a file into memory. A file which
+/// doesn't exist holds no records.
+pub async fn load_file(path: &Path) -> Result<AsyncInMemoryDatabase, CliError> {
+    let db = AsyncInMemoryDatabase::new();
+    if !path.exists() {
+        return Ok(db);
+    }
+    let contents = tokio::fs::read(path)
+        .await
+        .map_err(|err| CliError::Io(format!("Failed to read {}: {}", path.display(), err)))?;
+    let records: Vec<DbRecord> = serde_json::from_slice(&contents)
+        .map_err(|err| CliError::Input(format!("{}: {}", path.display(), err)))?;
+    db.batch_set(records, akd::storage::DbSetState::General)
+        .await
+        .map_err(|err| CliError::Akd(err.into()))?;
+    Ok(db)
+}
+
+/// Writes all the records of a directory to a file, replacing it atomically
+pub async fn save_file(db: &AsyncInMemoryDatabase, path: &Path) -> Result<(), CliError> {
+    let records = db
+        .batch_get_all_direct()
+        .await
+        .map_err(|err| CliError::Akd(err.into()))?;
+    let contents = serde_json::to_vec(&records)
+        .map_err(|err| CliError::Io(format!("Failed to encode the records: {}", err)))?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tokio::fs::write(&tmp, contents).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
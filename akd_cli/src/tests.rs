@@ -0,0 +1,202 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Tests of the CLI commands, against a directory stored in a file
+
+use crate::input::{parse_csv, parse_jsonl};
+use crate::{run, CliError, Command, InputFormat, ProofFile, StorageConfig, VrfKeySource};
+
+use akd::{AkdLabel, AkdValue};
+use std::path::Path;
+
+fn publish(file: &Path) -> Command {
+    Command::Publish {
+        file: file.to_path_buf(),
+        format: InputFormat::Auto,
+    }
+}
+
+#[test]
+fn test_parse_updates() {
+    let updates = parse_csv("label,value\nalice,\"key, 1\"\nbob,key2\n").unwrap();
+    assert_eq!(
+        vec![
+            (
+                AkdLabel::from_utf8_str("alice"),
+                AkdValue::from_utf8_str("key, 1")
+            ),
+            (
+                AkdLabel::from_utf8_str("bob"),
+                AkdValue::from_utf8_str("key2")
+            ),
+        ],
+        updates
+    );
+    assert!(matches!(parse_csv("alice\n"), Err(CliError::Input(_))));
+
+    let updates = parse_jsonl("{\"label\": \"alice\", \"value\": \"key1\"}\n\n").unwrap();
+    assert_eq!(
+        vec![(
+            AkdLabel::from_utf8_str("alice"),
+            AkdValue::from_utf8_str("key1")
+        )],
+        updates
+    );
+    assert!(matches!(
+        parse_jsonl("{\"label\": \"alice\"}"),
+        Err(CliError::Input(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_commands() -> Result<(), CliError> {
+    let dir = tempfile::tempdir()?;
+    let storage = StorageConfig::File(dir.path().join("directory.json"));
+
+    // a directory must be initialized before it can be read
+    let lookup = Command::Lookup {
+        label: "alice".to_string(),
+        out: None,
+    };
+    assert!(matches!(
+        run(&storage, None, lookup.clone()).await,
+        Err(CliError::Akd(_))
+    ));
+    run(&storage, None, Command::Init).await?;
+    assert!(matches!(
+        run(&storage, None, Command::Init).await,
+        Err(CliError::Input(_))
+    ));
+
+    // each publish is persisted to the file
+    let csv = dir.path().join("updates.csv");
+    std::fs::write(&csv, "alice,key1\nbob,key1\n")?;
+    run(&storage, None, publish(&csv)).await?;
+    let jsonl = dir.path().join("updates.jsonl");
+    std::fs::write(&jsonl, "{\"label\": \"alice\", \"value\": \"key2\"}\n")?;
+    let output = run(&storage, None, publish(&jsonl)).await?;
+    assert!(output.contains("at epoch 2"));
+    let output = run(&storage, None, lookup).await?;
+    assert!(output.contains("version 2 published at epoch 2, value key2"));
+
+    // the proofs written to files verify offline
+    let lookup_proof = dir.path().join("lookup.json");
+    run(
+        &storage,
+        None,
+        Command::Lookup {
+            label: "alice".to_string(),
+            out: Some(lookup_proof.clone()),
+        },
+    )
+    .await?;
+    let audit_proof = dir.path().join("audit.json");
+    run(
+        &storage,
+        None,
+        Command::Audit {
+            start_epoch: 1,
+            end_epoch: 2,
+            out: Some(audit_proof.clone()),
+        },
+    )
+    .await?;
+    let root_hash = match serde_json::from_slice(&std::fs::read(&lookup_proof)?).unwrap() {
+        ProofFile::Lookup { root_hash, .. } => root_hash,
+        other => panic!("Unexpected proof file {:?}", other),
+    };
+    for proof in [&lookup_proof, &audit_proof] {
+        let verify = Command::Verify {
+            proof: proof.to_path_buf(),
+            root_hash: Some(root_hash.clone()),
+        };
+        // verifying doesn't need the directory
+        run(
+            &StorageConfig::File(dir.path().join("missing.json")),
+            None,
+            verify,
+        )
+        .await?;
+    }
+
+    // a proof against another root hash is rejected
+    let verify = Command::Verify {
+        proof: lookup_proof,
+        root_hash: Some(hex::encode([0u8; akd::DIGEST_BYTES])),
+    };
+    assert!(matches!(
+        run(&storage, None, verify).await,
+        Err(CliError::Verification(_))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_vrf_key_source() -> Result<(), CliError> {
+    let dir = tempfile::tempdir()?;
+    let mysql = StorageConfig::MySql {
+        endpoint: "localhost".to_string(),
+        database: "default".to_string(),
+        user: None,
+        password: None,
+        port: None,
+    };
+
+    // a directory stored in MySQL needs a configured VRF key
+    assert!(matches!(
+        run(&mysql, None, Command::Init).await,
+        Err(CliError::Input(_))
+    ));
+    assert!(matches!(
+        run(
+            &mysql,
+            Some(&VrfKeySource::Hex("00".to_string())),
+            Command::Init
+        )
+        .await,
+        Err(CliError::Input(_))
+    ));
+
+    // the directory is operated with the configured key
+    let key = "b8ebd7f8d1a55d7e8b57d5e54bd4c8c2a9e9e0cb66d89a2a6bd4e4fd7cd2c2a8";
+    let key_file = dir.path().join("vrf.key");
+    std::fs::write(&key_file, format!("{}\n", key))?;
+    let storage = StorageConfig::File(dir.path().join("directory.json"));
+    run(&storage, Some(&VrfKeySource::File(key_file)), Command::Init).await?;
+    let csv = dir.path().join("updates.csv");
+    std::fs::write(&csv, "alice,key1\n")?;
+    run(
+        &storage,
+        Some(&VrfKeySource::Hex(key.to_string())),
+        publish(&csv),
+    )
+    .await?;
+    let lookup_proof = dir.path().join("lookup.json");
+    run(
+        &storage,
+        Some(&VrfKeySource::Hex(key.to_string())),
+        Command::Lookup {
+            label: "alice".to_string(),
+            out: Some(lookup_proof.clone()),
+        },
+    )
+    .await?;
+    let vrf = akd::ecvrf::HardCodedAkdVRF {};
+    let hard_coded_public_key = hex::encode(
+        akd::ecvrf::VRFKeyStorage::get_vrf_public_key(&vrf)
+            .await
+            .map_err(|err| CliError::Input(err.to_string()))?
+            .as_bytes(),
+    );
+    match serde_json::from_slice(&std::fs::read(&lookup_proof)?).unwrap() {
+        ProofFile::Lookup { vrf_public_key, .. } => {
+            assert_ne!(hard_coded_public_key, vrf_public_key)
+        }
+        other => panic!("Unexpected proof file {:?}", other),
+    }
+    Ok(())
+}
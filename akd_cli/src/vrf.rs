@@ -0,0 +1,75 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! The VRF private key a directory is operated with
+
+use crate::storage::StorageConfig;
+use crate::CliError;
+use akd::ecvrf::{HardCodedAkdVRF, VRFKeyStorage, VrfError};
+use std::path::PathBuf;
+
+/// Where the VRF private key of the directory is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VrfKeySource {
+    /// The hex-encoded key itself
+    Hex(String),
+    /// A file holding the hex-encoded key
+    File(PathBuf),
+}
+
+/// The VRF private key of the directory, loaded from a [VrfKeySource]
+#[derive(Clone)]
+pub struct CliVrf {
+    key: Vec<u8>,
+}
+
+#[async_trait::async_trait]
+impl VRFKeyStorage for CliVrf {
+    async fn retrieve(&self) -> Result<Vec<u8>, VrfError> {
+        Ok(self.key.clone())
+    }
+}
+
+/// Loads the VRF private key from the given source. Without a source, only a
+/// directory stored in a file, which is loaded in memory for demos and debugging,
+/// falls back to the hard-coded key of [HardCodedAkdVRF]: a directory stored in
+/// MySQL needs its key configured, as anyone knowing the key can compute the
+/// labels of the directory.
+pub async fn load_vrf_key(
+    source: Option<&VrfKeySource>,
+    storage: &StorageConfig,
+) -> Result<CliVrf, CliError> {
+    let key = match (source, storage) {
+        (Some(VrfKeySource::Hex(key)), _) => decode_key(key)?,
+        (Some(VrfKeySource::File(path)), _) => {
+            let contents = tokio::fs::read_to_string(path).await.map_err(|err| {
+                CliError::Io(format!("Failed to read {}: {}", path.display(), err))
+            })?;
+            decode_key(&contents)?
+        }
+        (None, StorageConfig::File(_)) => HardCodedAkdVRF {}
+            .retrieve()
+            .await
+            .map_err(|err| CliError::Input(err.to_string()))?,
+        (None, StorageConfig::MySql { .. }) => return Err(CliError::Input(
+            "A directory stored in MySQL needs a VRF key, given with --vrf-key or --vrf-key-file"
+                .to_string(),
+        )),
+    };
+
+    let vrf = CliVrf { key };
+    // check that the key is a valid VRF private key before using it
+    vrf.get_vrf_private_key()
+        .await
+        .map_err(|err| CliError::Input(format!("Invalid VRF key: {}", err)))?;
+    Ok(vrf)
+}
+
+fn decode_key(key: &str) -> Result<Vec<u8>, CliError> {
+    hex::decode(key.trim())
+        .map_err(|err| CliError::Input(format!("Invalid VRF key encoding: {}", err)))
+}
@@ -34,6 +34,14 @@
 //! cargo build --no-default-features --features sha3_256
 //! ```
 //!
+//! ## Lite verification
+//!
+//! For devices which can't afford heap-allocated proofs, the [lite] module verifies lookup
+//! proofs held in fixed-size structures, using only stack buffers. It accepts the same
+//! proofs as [verify::lookup_verify], except for those exceeding its size limits (labels
+//! and values over 256 bytes, and proofs deeper than [lite::DEFAULT_MAX_DEPTH] layers by
+//! default), which it rejects.
+//!
 
 #![warn(missing_docs)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
@@ -42,6 +50,7 @@ extern crate alloc;
 
 // Re-expose the core functionality from akd_core (verifications, types, etc)
 pub use akd_core::verify;
+pub use akd_core::verify::lite;
 pub use akd_core::*;
 
 #[cfg(feature = "protobuf")]
@@ -50,4 +59,7 @@ pub use akd_core::proto::*;
 #[cfg(feature = "wasm")]
 pub mod wasm;
 #[cfg(feature = "wasm")]
-pub use wasm::lookup_verify;
+pub use wasm::{audit_verify, lookup_verify};
+
+#[cfg(test)]
+mod tests;
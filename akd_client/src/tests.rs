@@ -0,0 +1,344 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Test vectors shared by the full and the lite lookup verification, which must
+//! accept and reject exactly the same proofs within the limits of the lite
+//! verification, and of which the lite verification rejects the others as too large
+
+use akd::storage::memory::AsyncInMemoryDatabase;
+use akd::storage::StorageManager;
+use akd::{AkdLabel, AkdValue, Directory};
+
+use crate::ecvrf::HardCodedAkdVRF;
+use crate::hash::{merge, Digest};
+use crate::lite::{
+    LiteLookupProof, LiteMembershipProof, LiteVerificationError, DEFAULT_MAX_DEPTH,
+    MAX_LABEL_BYTES, MAX_VALUE_BYTES,
+};
+use crate::{Direction, LayerProof, LookupProof, MembershipProof, Node, NodeLabel};
+use core::convert::TryFrom;
+
+struct LookupTestVector {
+    name: String,
+    vrf_public_key: Vec<u8>,
+    root_hash: Digest,
+    label: AkdLabel,
+    proof: LookupProof,
+    valid: bool,
+    within_lite_limits: bool,
+}
+
+type ProofMutation = (&'static str, fn(&mut LookupProof));
+
+fn flip_byte(bytes: &mut [u8]) {
+    bytes[bytes.len() / 2] ^= 1;
+}
+
+fn proof_mutations() -> Vec<ProofMutation> {
+    vec![
+        ("value", |p| flip_byte(&mut p.plaintext_value.0)),
+        ("epoch", |p| p.epoch += 1),
+        ("version", |p| p.version += 1),
+        ("commitment proof", |p| flip_byte(&mut p.commitment_proof)),
+        ("existence vrf proof", |p| {
+            flip_byte(&mut p.existence_vrf_proof)
+        }),
+        ("marker vrf proof", |p| flip_byte(&mut p.marker_vrf_proof)),
+        ("freshness vrf proof", |p| {
+            flip_byte(&mut p.freshness_vrf_proof)
+        }),
+        ("existence hash", |p| {
+            flip_byte(&mut p.existence_proof.hash_val)
+        }),
+        ("existence label", |p| {
+            p.existence_proof.label = p.freshness_proof.label
+        }),
+        ("existence sibling", |p| {
+            if let Some(layer) = p.existence_proof.layer_proofs.last_mut() {
+                flip_byte(&mut layer.siblings[0].hash);
+            }
+        }),
+        ("existence direction", |p| {
+            if let Some(layer) = p.existence_proof.layer_proofs.last_mut() {
+                layer.direction = match layer.direction {
+                    Direction::Left => Direction::Right,
+                    _ => Direction::Left,
+                };
+            }
+        }),
+        ("existence missing layer", |p| {
+            p.existence_proof.layer_proofs.pop();
+        }),
+        ("marker hash", |p| flip_byte(&mut p.marker_proof.hash_val)),
+        ("freshness child", |p| {
+            flip_byte(&mut p.freshness_proof.longest_prefix_children[0].hash)
+        }),
+        ("freshness longest prefix", |p| {
+            p.freshness_proof.longest_prefix.label_len += 1
+        }),
+        ("freshness label", |p| {
+            p.freshness_proof.label = p.existence_proof.label
+        }),
+    ]
+}
+
+/// Generates valid proofs from a directory (for users with one or several
+/// versions, so that the marker and existence proofs differ), along with
+/// every mutation of each of them
+async fn lookup_test_vectors() -> Vec<LookupTestVector> {
+    let db = AsyncInMemoryDatabase::new();
+    let storage = StorageManager::new_no_cache(db);
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false)
+        .await
+        .expect("Failed to construct directory");
+    let vrf_public_key = akd
+        .get_public_key()
+        .await
+        .expect("Failed to get VRF public key")
+        .as_bytes()
+        .to_vec();
+
+    for epoch in 0..3 {
+        let updates = (0..10)
+            .filter(|user| *user < 3 || epoch == 0)
+            .map(|user| {
+                (
+                    AkdLabel::from_utf8_str(&format!("user{}", user)),
+                    AkdValue::from_utf8_str(&format!("value{}.{}", user, epoch)),
+                )
+            })
+            .collect();
+        akd.publish(updates)
+            .await
+            .expect("Failed to publish test data");
+    }
+
+    let mut vectors = vec![];
+    for user in [0, 5, 9] {
+        let label = AkdLabel::from_utf8_str(&format!("user{}", user));
+        let (proof, root_hash) = akd
+            .lookup(label.clone())
+            .await
+            .expect("Failed to lookup test data");
+        let name = format!("user{}", user);
+
+        for (mutation, mutate) in proof_mutations() {
+            let mut mutated = proof.clone();
+            mutate(&mut mutated);
+            vectors.push(LookupTestVector {
+                name: format!("{} with mutated {}", name, mutation),
+                vrf_public_key: vrf_public_key.clone(),
+                root_hash: root_hash.hash(),
+                label: label.clone(),
+                proof: mutated,
+                valid: false,
+                within_lite_limits: true,
+            });
+        }
+
+        let mut wrong_root = root_hash.hash();
+        flip_byte(&mut wrong_root);
+        vectors.push(LookupTestVector {
+            name: format!("{} with wrong root hash", name),
+            vrf_public_key: vrf_public_key.clone(),
+            root_hash: wrong_root,
+            label: label.clone(),
+            proof: proof.clone(),
+            valid: false,
+            within_lite_limits: true,
+        });
+        vectors.push(LookupTestVector {
+            name: format!("{} with wrong label", name),
+            vrf_public_key: vrf_public_key.clone(),
+            root_hash: root_hash.hash(),
+            label: AkdLabel::from_utf8_str("someone else"),
+            proof: proof.clone(),
+            valid: false,
+            within_lite_limits: true,
+        });
+        vectors.push(LookupTestVector {
+            name,
+            vrf_public_key: vrf_public_key.clone(),
+            root_hash: root_hash.hash(),
+            label,
+            proof,
+            valid: true,
+            within_lite_limits: true,
+        });
+    }
+
+    // labels and values at the size limits of the lite verification, and just over
+    let limits = [
+        (MAX_LABEL_BYTES, 5, true),
+        (MAX_LABEL_BYTES + 1, 5, false),
+        (5, MAX_VALUE_BYTES, true),
+        (5, MAX_VALUE_BYTES + 1, false),
+    ];
+    let limit_updates = limits
+        .iter()
+        .enumerate()
+        .map(|(i, &(label_len, value_len, _))| {
+            (
+                AkdLabel(vec![b'a' + i as u8; label_len]),
+                AkdValue(vec![b'v'; value_len]),
+            )
+        })
+        .collect::<Vec<_>>();
+    akd.publish(limit_updates.clone())
+        .await
+        .expect("Failed to publish test data");
+    for ((label, value), (label_len, value_len, within_lite_limits)) in
+        limit_updates.into_iter().zip(limits)
+    {
+        let (proof, root_hash) = akd
+            .lookup(label.clone())
+            .await
+            .expect("Failed to lookup test data");
+        assert_eq!(value, proof.plaintext_value);
+        vectors.push(LookupTestVector {
+            name: format!("{}-byte label with {}-byte value", label_len, value_len),
+            vrf_public_key: vrf_public_key.clone(),
+            root_hash: root_hash.hash(),
+            label,
+            proof,
+            valid: true,
+            within_lite_limits,
+        });
+    }
+    vectors
+}
+
+/// Builds a membership proof of the given number of layers, each parent having
+/// the proven node on its left, along with the root hash it verifies against
+fn membership_proof_of_depth(depth: usize) -> (MembershipProof, Digest) {
+    let leaf = NodeLabel::new([0u8; 32], depth as u32);
+    let hash_val = crate::hash::hash(b"value");
+    let mut current_hash = merge(&[hash_val, leaf.hash()]);
+    let mut layer_proofs = Vec::new();
+    for len in (0..depth as u32).rev() {
+        let parent = leaf.get_prefix(len);
+        let sibling = Node {
+            label: leaf.get_sibling_prefix(len + 1),
+            hash: crate::hash::hash(&len.to_be_bytes()),
+        };
+        let sibling_hash = merge(&[sibling.hash, sibling.label.hash()]);
+        current_hash = merge(&[merge(&[current_hash, sibling_hash]), parent.hash()]);
+        layer_proofs.push(LayerProof {
+            label: parent,
+            siblings: [sibling],
+            direction: Direction::Left,
+        });
+    }
+    layer_proofs.reverse();
+    (
+        MembershipProof {
+            label: leaf,
+            hash_val,
+            layer_proofs,
+        },
+        current_hash,
+    )
+}
+
+fn lite_verify(vector: &LookupTestVector) -> Result<(), LiteVerificationError> {
+    let proof = LiteLookupProof::<64>::try_from(&vector.proof)?;
+    let result = crate::lite::lookup_verify(
+        &vector.vrf_public_key,
+        vector.root_hash,
+        &vector.label,
+        &proof,
+    )?;
+    assert_eq!(vector.proof.epoch, result.epoch);
+    assert_eq!(vector.proof.version, result.version);
+    assert_eq!(
+        &vector.proof.plaintext_value[..],
+        proof.plaintext_value.as_slice()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_full_lookup_vectors() {
+    for vector in lookup_test_vectors().await {
+        let result = crate::verify::lookup_verify(
+            &vector.vrf_public_key,
+            vector.root_hash,
+            vector.label.clone(),
+            vector.proof.clone(),
+        );
+        assert_eq!(
+            vector.valid,
+            result.is_ok(),
+            "{}: {:?}",
+            vector.name,
+            result
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_lite_lookup_vectors() {
+    for vector in lookup_test_vectors().await {
+        let result = lite_verify(&vector);
+        if vector.within_lite_limits {
+            assert_eq!(
+                vector.valid,
+                result.is_ok(),
+                "{}: {:?}",
+                vector.name,
+                result
+            );
+        } else {
+            assert_eq!(
+                Err(LiteVerificationError::TooLarge),
+                result,
+                "{}",
+                vector.name
+            );
+        }
+    }
+}
+
+#[test]
+fn test_membership_depth_limits() {
+    // the full verification accepts proofs on either side of the lite maximum depth
+    for depth in [DEFAULT_MAX_DEPTH, DEFAULT_MAX_DEPTH + 1] {
+        let (proof, root_hash) = membership_proof_of_depth(depth);
+        crate::verify::verify_membership(root_hash, &proof)
+            .unwrap_or_else(|err| panic!("depth {}: {:?}", depth, err));
+    }
+
+    let (proof, root_hash) = membership_proof_of_depth(DEFAULT_MAX_DEPTH);
+    let lite = LiteMembershipProof::<DEFAULT_MAX_DEPTH>::try_from(&proof)
+        .expect("Failed to convert a proof at the maximum depth");
+    assert_eq!(Ok(()), crate::lite::verify_membership(root_hash, &lite));
+
+    let (proof, _) = membership_proof_of_depth(DEFAULT_MAX_DEPTH + 1);
+    assert_eq!(
+        Err(LiteVerificationError::TooLarge),
+        LiteMembershipProof::<DEFAULT_MAX_DEPTH>::try_from(&proof).map(|_| ())
+    );
+}
+
+#[tokio::test]
+async fn test_lite_limits() {
+    let vectors = lookup_test_vectors().await;
+    let vector = vectors.iter().find(|vector| vector.valid).unwrap();
+
+    // a proof deeper than the maximum depth is rejected upfront
+    assert_eq!(
+        Err(LiteVerificationError::TooLarge),
+        LiteLookupProof::<1>::try_from(&vector.proof).map(|_| ())
+    );
+
+    let mut proof = vector.proof.clone();
+    proof.existence_vrf_proof.pop();
+    assert_eq!(
+        Err(LiteVerificationError::Malformed),
+        LiteLookupProof::<64>::try_from(&proof).map(|_| ())
+    );
+}
@@ -137,6 +137,65 @@ pub fn lookup_verify(
     }
 }
 
+/// Splits the concatenated root hashes of the audited epochs
+fn parse_hashes(hashes: &[u8]) -> Result<Vec<crate::hash::Digest>, String> {
+    let chunks = hashes.chunks_exact(crate::hash::DIGEST_BYTES);
+    if !chunks.remainder().is_empty() {
+        return Err(format!(
+            "The root hashes should be {} bytes each, got {} bytes in total",
+            crate::hash::DIGEST_BYTES,
+            hashes.len()
+        ));
+    }
+    chunks.map(crate::hash::try_parse_digest).collect()
+}
+
+#[wasm_bindgen]
+/// Verify an audit proof in WebAssembly, given the concatenated root hashes of
+/// all the audited epochs and the protobuf encoded append-only proof
+pub fn audit_verify(hashes: &[u8], audit_proof: &[u8]) -> Result<(), String> {
+    crate::verify::audit_verify_serialized(&parse_hashes(hashes)?, audit_proof)
+        .map_err(|error| error.to_string())
+}
+
+/// Verifies an audit in WebAssembly one epoch transition at a time, as the proof
+/// of each transition is received, for monitors which stream large audits
+#[wasm_bindgen]
+pub struct AuditVerifier {
+    inner: crate::verify::StreamingAuditVerifier,
+}
+
+#[wasm_bindgen]
+impl AuditVerifier {
+    /// Construct a verifier for the concatenated root hashes of the audited epochs
+    #[wasm_bindgen(constructor)]
+    pub fn new(hashes: &[u8]) -> Result<AuditVerifier, String> {
+        Ok(Self {
+            inner: crate::verify::StreamingAuditVerifier::new(parse_hashes(hashes)?),
+        })
+    }
+
+    /// Verify the protobuf encoded proof of the transition from `epoch` to the
+    /// next epoch, which must follow the last verified transition
+    pub fn feed(&mut self, epoch: u64, proof: &[u8]) -> Result<(), String> {
+        self.inner
+            .feed(epoch, proof)
+            .map_err(|error| error.to_string())
+    }
+
+    /// Get the number of epoch transitions verified so far
+    #[wasm_bindgen(getter)]
+    pub fn verified(&self) -> usize {
+        self.inner.verified()
+    }
+
+    /// Get whether all the epoch transitions have been verified
+    #[wasm_bindgen(getter, js_name = isComplete)]
+    pub fn is_complete(&self) -> bool {
+        self.inner.is_complete()
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     extern crate wasm_bindgen_test;
@@ -195,4 +254,46 @@ pub mod tests {
         );
         assert!(result.is_ok());
     }
+
+    #[wasm_bindgen_test]
+    async fn test_simple_wasm_audit() {
+        let db = AsyncInMemoryDatabase::new();
+        let storage = StorageManager::new_no_cache(db);
+        let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false)
+            .await
+            .expect("Failed to construct directory");
+
+        let mut hashes = vec![];
+        for epoch in 1..=4 {
+            akd.publish(vec![(
+                AkdLabel::from_utf8_str(&format!("hello{}", epoch)),
+                AkdValue::from_utf8_str("world"),
+            )])
+            .await
+            .expect("Failed to publish test data");
+            hashes.extend_from_slice(&akd.get_epoch_hash().hash());
+        }
+        let audit_proof = akd.audit(1, 4).await.expect("Failed to generate audit");
+
+        let encoded_proof_bytes = crate::proto::specs::types::AppendOnlyProof::from(&audit_proof)
+            .write_to_bytes()
+            .expect("Failed to encode audit proof");
+        assert!(audit_verify(&hashes, &encoded_proof_bytes).is_ok());
+        assert!(audit_verify(&hashes[1..], &encoded_proof_bytes).is_err());
+
+        // the proof of each transition is verified as it's received
+        let mut verifier = AuditVerifier::new(&hashes).expect("Invalid hashes");
+        for (epoch, proof) in audit_proof.epochs.iter().zip(audit_proof.proofs.iter()) {
+            assert!(!verifier.is_complete());
+            let encoded_proof_bytes =
+                crate::proto::specs::types::SingleAppendOnlyProof::from(proof)
+                    .write_to_bytes()
+                    .expect("Failed to encode append-only proof");
+            verifier
+                .feed(*epoch, &encoded_proof_bytes)
+                .expect("Failed to verify transition");
+        }
+        assert_eq!(3, verifier.verified());
+        assert!(verifier.is_complete());
+    }
 }
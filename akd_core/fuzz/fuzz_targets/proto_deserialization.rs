@@ -0,0 +1,30 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Feeds arbitrary bytes into the protobuf deserializers of the proof types,
+//! which must reject malformed input with an error rather than panicking.
+//!
+//! Run with `cargo fuzz run proto_deserialization` from the `akd_core` directory
+
+#![no_main]
+
+use akd_core::proto::specs::types;
+use libfuzzer_sys::fuzz_target;
+use protobuf::Message;
+use std::convert::TryFrom;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(proof) = types::LookupProof::parse_from_bytes(data) {
+        let _ = akd_core::LookupProof::try_from(&proof);
+    }
+    if let Ok(proof) = types::HistoryProof::parse_from_bytes(data) {
+        let _ = akd_core::HistoryProof::try_from(&proof);
+    }
+    if let Ok(proof) = types::AppendOnlyProof::parse_from_bytes(data) {
+        let _ = akd_core::AppendOnlyProof::try_from(&proof);
+    }
+});
@@ -0,0 +1,96 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Commitment schemes for the values stored in the leaves of the tree.
+//!
+//! Every scheme commits to a value as
+//! `commitment = H(i2osp_array(value), i2osp_array(nonce))`, and differs only in
+//! how the nonce is derived. The nonce is returned to clients as the commitment
+//! proof of lookup and history proofs, so the clients verify every scheme the same way.
+//!
+//! ⚠️ **Warning**: The nonces of existing leaves are re-derived when generating
+//! proofs, so the commitment scheme of a directory must not change over its lifetime.
+
+use crate::hash::Digest;
+use crate::utils::{generate_commitment_from_nonce_client, get_commitment_nonce};
+use crate::{AkdValue, NodeLabel};
+
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
+
+/// A scheme for committing to the values stored in the tree
+pub trait ValueCommitment: Send + Sync {
+    /// Computes the nonce which opens the commitment to a value. This is
+    /// returned to the clients as the commitment proof.
+    fn get_nonce(
+        &self,
+        commitment_key: &[u8],
+        label: &NodeLabel,
+        version: u64,
+        value: &AkdValue,
+    ) -> Vec<u8>;
+
+    /// Computes the commitment to a value, which is stored in the tree
+    fn commit(
+        &self,
+        commitment_key: &[u8],
+        label: &NodeLabel,
+        version: u64,
+        value: &AkdValue,
+    ) -> Digest {
+        let nonce = self.get_nonce(commitment_key, label, version, value);
+        generate_commitment_from_nonce_client(value, &nonce)
+    }
+}
+
+/// The hiding commitments of SEEMless, whose nonces are derived from the server's
+/// commitment key, the label, the version, and the value (see [get_commitment_nonce]).
+/// A value can't be learned from the tree without its nonce, which is only
+/// handed out alongside the value itself. This is the default scheme.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NonceCommitment;
+
+impl ValueCommitment for NonceCommitment {
+    fn get_nonce(
+        &self,
+        commitment_key: &[u8],
+        label: &NodeLabel,
+        version: u64,
+        value: &AkdValue,
+    ) -> Vec<u8> {
+        get_commitment_nonce(commitment_key, label, version, value).to_vec()
+    }
+}
+
+/// Plain hashing of the values, optionally separated by a fixed domain tag
+/// which takes the place of the nonce. The commitments are cheaper to compute
+/// but **not hiding**: anyone holding the tree can test a guess of a value.
+#[derive(Debug, Clone, Default)]
+pub struct HashCommitment {
+    domain_separator: Vec<u8>,
+}
+
+impl HashCommitment {
+    /// Plain hashing, with the given domain separation tag
+    pub fn new(domain_separator: &[u8]) -> Self {
+        Self {
+            domain_separator: domain_separator.to_vec(),
+        }
+    }
+}
+
+impl ValueCommitment for HashCommitment {
+    fn get_nonce(
+        &self,
+        _commitment_key: &[u8],
+        _label: &NodeLabel,
+        _version: u64,
+        _value: &AkdValue,
+    ) -> Vec<u8> {
+        self.domain_separator.clone()
+    }
+}
@@ -12,11 +12,14 @@ use super::VrfError;
 use alloc::format;
 #[cfg(feature = "nostd")]
 use alloc::string::ToString;
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
 use core::convert::TryFrom;
 use curve25519_dalek::{
     constants::ED25519_BASEPOINT_POINT,
     edwards::{CompressedEdwardsY, EdwardsPoint},
     scalar::Scalar as ed25519_Scalar,
+    traits::MultiscalarMul,
 };
 
 /// The length of a node-label's value field in bytes.
@@ -41,11 +44,16 @@ const ZERO: u8 = 0x00;
 const ONE: u8 = 0x01;
 const TWO: u8 = 0x02;
 const THREE: u8 = 0x03;
+const FOUR: u8 = 0x04;
 
 /// The number of bytes of [`Output`]
 pub const OUTPUT_LENGTH: usize = 64;
 /// The number of bytes of [`Proof`]
 pub const PROOF_LENGTH: usize = 80;
+/// The number of bytes of a [`BatchProof`] for each of its inputs
+pub const BATCH_PROOF_GAMMA_LENGTH: usize = 32;
+/// The number of bytes of a [`BatchProof`], in addition to those for each input
+pub const BATCH_PROOF_BASE_LENGTH: usize = 48;
 
 /// An ECVRF private key
 #[derive(Debug)]
@@ -134,6 +142,41 @@ impl VRFExpandedPrivateKey {
         }
     }
 
+    /// Produces a single proof for several inputs (using the expanded private key),
+    /// see [`BatchProof`]
+    pub fn prove_batch<A: AsRef<[u8]>>(&self, pk: &VRFPublicKey, alphas: &[A]) -> BatchProof {
+        let h_points = alphas
+            .iter()
+            .map(|alpha| pk.hash_to_curve(alpha.as_ref()))
+            .collect::<Vec<_>>();
+        let gammas = h_points
+            .iter()
+            .map(|h_point| h_point * self.key)
+            .collect::<Vec<_>>();
+
+        let (m_point, z_point) = combine_points(pk, &h_points, &gammas);
+        let m_point_bytes = m_point.compress().to_bytes();
+        let k_scalar = ed25519_Scalar::from_bytes_mod_order_wide(&nonce_generation_bytes(
+            self.nonce,
+            &m_point_bytes,
+        ));
+        let c_scalar = hash_points(
+            pk.0,
+            &m_point_bytes,
+            &[
+                z_point,
+                &curve25519_dalek::constants::ED25519_BASEPOINT_TABLE * &k_scalar,
+                m_point * k_scalar,
+            ],
+        );
+
+        BatchProof {
+            gammas,
+            c: c_scalar,
+            s: k_scalar + c_scalar * self.key,
+        }
+    }
+
     /// Directly evaluate the VRF for an input, without producing a proof (using the expanded private key)
     pub fn evaluate(&self, pk: &VRFPublicKey, alpha: &[u8]) -> Output {
         let h_point = pk.hash_to_curve(alpha);
@@ -214,6 +257,54 @@ impl VRFPublicKey {
         }
     }
 
+    /// Given a [`BatchProof`] and the inputs it was produced for, returns the outputs
+    /// of the inputs (in the same order) if the proof is valid for the inputs and
+    /// public key
+    pub fn verify_batch<A: AsRef<[u8]>>(
+        &self,
+        proof: &BatchProof,
+        alphas: &[A],
+    ) -> Result<Vec<Output>, VrfError> {
+        if proof.gammas.len() != alphas.len() {
+            return Err(VrfError::Verification(format!(
+                "The batch proof is for {} inputs, but {} were given",
+                proof.gammas.len(),
+                alphas.len()
+            )));
+        }
+        let h_points = alphas
+            .iter()
+            .map(|alpha| self.hash_to_curve(alpha.as_ref()))
+            .collect::<Vec<_>>();
+        let pk_point = match CompressedEdwardsY::from_slice(self.as_bytes()).decompress() {
+            Some(pt) => pt,
+            None => {
+                return Err(VrfError::Verification(
+                    "Failed to decompress public key into Edwards point".to_string(),
+                ))
+            }
+        };
+
+        let (m_point, z_point) = combine_points(self, &h_points, &proof.gammas);
+        let cprime = hash_points(
+            self.0,
+            &m_point.compress().to_bytes(),
+            &[
+                z_point,
+                ED25519_BASEPOINT_POINT * proof.s - pk_point * proof.c,
+                m_point * proof.s - z_point * proof.c,
+            ],
+        );
+
+        if proof.c == cprime {
+            Ok(proof.gammas.iter().map(gamma_to_output).collect())
+        } else {
+            Err(VrfError::Verification(
+                "The batch proof failed to verify for this public key".to_string(),
+            ))
+        }
+    }
+
     pub(super) fn hash_to_curve(&self, alpha: &[u8]) -> EdwardsPoint {
         let mut result = [0u8; 32];
         let mut counter = 0;
@@ -317,6 +408,83 @@ impl TryFrom<&[u8]> for Proof {
     }
 }
 
+/// A VRF proof for several inputs at once, which is much smaller than one [`Proof`]
+/// per input. It holds the gamma point of each input, along with a single proof that
+/// a random linear combination of the gammas (with weights derived from all the
+/// inputs and gammas) uses the same private key as the public key, which implies
+/// that each of them does.
+#[derive(Clone)]
+pub struct BatchProof {
+    gammas: Vec<EdwardsPoint>,
+    c: ed25519_Scalar,
+    s: ed25519_Scalar,
+}
+
+impl BatchProof {
+    /// The number of inputs the proof is for
+    pub fn len(&self) -> usize {
+        self.gammas.len()
+    }
+
+    /// Whether the proof is for no inputs
+    pub fn is_empty(&self) -> bool {
+        self.gammas.is_empty()
+    }
+
+    /// Converts a BatchProof into bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(
+            BATCH_PROOF_BASE_LENGTH + self.gammas.len() * BATCH_PROOF_GAMMA_LENGTH,
+        );
+        for gamma in self.gammas.iter() {
+            ret.extend_from_slice(&gamma.compress().to_bytes());
+        }
+        ret.extend_from_slice(&self.c.to_bytes()[..16]);
+        ret.extend_from_slice(&self.s.to_bytes());
+        ret
+    }
+}
+
+impl TryFrom<&[u8]> for BatchProof {
+    type Error = VrfError;
+
+    fn try_from(bytes: &[u8]) -> Result<BatchProof, VrfError> {
+        let invalid_length =
+            || VrfError::Verification(format!("Invalid batch proof length {}", bytes.len()));
+        let gamma_bytes_length = bytes
+            .len()
+            .checked_sub(BATCH_PROOF_BASE_LENGTH)
+            .ok_or_else(invalid_length)?;
+        let (gamma_bytes, scalar_bytes) = bytes.split_at(gamma_bytes_length);
+        let gamma_chunks = gamma_bytes.chunks_exact(BATCH_PROOF_GAMMA_LENGTH);
+        if !gamma_chunks.remainder().is_empty() {
+            return Err(invalid_length());
+        }
+        let gammas = gamma_chunks
+            .map(|chunk| {
+                CompressedEdwardsY::from_slice(chunk)
+                    .decompress()
+                    .ok_or_else(|| {
+                        VrfError::Verification(
+                            "Failed to decompress gamma into Edwards Point".to_string(),
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut c_buf = [0u8; 32];
+        c_buf[..16].copy_from_slice(&scalar_bytes[..16]);
+        let mut s_buf = [0u8; 32];
+        s_buf.copy_from_slice(&scalar_bytes[16..]);
+
+        Ok(BatchProof {
+            gammas,
+            c: ed25519_Scalar::from_bits(c_buf),
+            s: ed25519_Scalar::from_bits(s_buf),
+        })
+    }
+}
+
 /// The ECVRF output produced from the proof
 pub struct Output([u8; OUTPUT_LENGTH]);
 
@@ -365,6 +533,34 @@ pub(super) fn nonce_generation_bytes(nonce: [u8; 32], h_point_bytes: &[u8]) -> [
     k_buf
 }
 
+/// Combines the hashed inputs and the gammas of a batch with the same weights, which are
+/// derived from all of them so that they can't be chosen to cancel out invalid gammas
+fn combine_points(
+    pk: &VRFPublicKey,
+    h_points: &[EdwardsPoint],
+    gammas: &[EdwardsPoint],
+) -> (EdwardsPoint, EdwardsPoint) {
+    let mut hash = Sha512::new().chain([SUITE, FOUR]).chain(pk.as_bytes());
+    for point in h_points.iter().chain(gammas.iter()) {
+        hash = hash.chain(point.compress().to_bytes());
+    }
+    let seed = hash.finalize();
+
+    let weights = (0..h_points.len() as u64)
+        .map(|i| {
+            let mut weight = [0u8; 32];
+            weight[..16].copy_from_slice(
+                &Sha512::new().chain(seed).chain(i.to_le_bytes()).finalize()[..16],
+            );
+            ed25519_Scalar::from_bits(weight)
+        })
+        .collect::<Vec<_>>();
+    (
+        EdwardsPoint::multiscalar_mul(&weights, h_points),
+        EdwardsPoint::multiscalar_mul(&weights, gammas),
+    )
+}
+
 pub(super) fn hash_points(
     pk: ed25519_PublicKey,
     h_point_bytes: &[u8],
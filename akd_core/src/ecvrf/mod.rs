@@ -26,7 +26,7 @@ mod ecvrf_impl;
 mod traits;
 // export the functionality we want visible
 pub use crate::ecvrf::ecvrf_impl::{
-    Output, Proof, VRFExpandedPrivateKey, VRFPrivateKey, VRFPublicKey,
+    BatchProof, Output, Proof, VRFExpandedPrivateKey, VRFPrivateKey, VRFPublicKey, PROOF_LENGTH,
 };
 pub use crate::ecvrf::traits::VRFKeyStorage;
 #[cfg(feature = "nostd")]
@@ -275,6 +275,43 @@ fn test_output_from_proof() {
     }
 }
 
+#[test]
+fn test_batch_prove_and_verify() {
+    for tv in TESTVECTORS.iter() {
+        let sk = from_string!(VRFPrivateKey, tv.SK);
+        let pk = from_string!(VRFPublicKey, tv.PK);
+        let alphas: [&[u8]; 3] = [tv.alpha, b"second input", b"third input"];
+        let proof = VRFExpandedPrivateKey::from(&sk).prove_batch(&pk, &alphas);
+        let bytes = proof.to_bytes();
+        assert_eq!(
+            BATCH_PROOF_BASE_LENGTH + 3 * BATCH_PROOF_GAMMA_LENGTH,
+            bytes.len()
+        );
+
+        // the outputs match those of the individual proofs
+        let outputs = pk
+            .verify_batch(&BatchProof::try_from(&bytes[..]).unwrap(), &alphas)
+            .unwrap();
+        assert_eq!(tv.beta, to_string!(outputs[0]));
+        for (output, alpha) in outputs.iter().zip(alphas.iter()) {
+            assert_eq!(to_string!(output), to_string!(sk.evaluate(alpha)));
+        }
+
+        // the proof doesn't verify for other inputs, or with any bit flipped
+        assert!(pk.verify_batch(&proof, &alphas[..2]).is_err());
+        assert!(pk
+            .verify_batch(&proof, &[tv.alpha, b"third input", b"second input"])
+            .is_err());
+        for i in 0..bytes.len() {
+            let mut tampered = bytes.clone();
+            tampered[i] ^= 1;
+            if let Ok(tampered) = BatchProof::try_from(&tampered[..]) {
+                assert!(pk.verify_batch(&tampered, &alphas).is_err());
+            }
+        }
+    }
+}
+
 #[test]
 fn test_publickey_clone() {
     // PublicKey has its own implementation of Clone
@@ -7,7 +7,9 @@
 
 //! This module implements traits for managing ECVRF, mainly pertaining to storage
 //! of public and private keys
-use super::{Output, Proof, VRFExpandedPrivateKey, VRFPrivateKey, VRFPublicKey, VrfError};
+use super::{
+    BatchProof, Output, Proof, VRFExpandedPrivateKey, VRFPrivateKey, VRFPublicKey, VrfError,
+};
 use crate::{AkdLabel, NodeLabel, VersionFreshness};
 
 #[cfg(feature = "nostd")]
@@ -118,6 +120,24 @@ pub trait VRFKeyStorage: Clone + Sync + Send {
         key.prove(&hashed_label)
     }
 
+    /// Retrieve a single proof for a collection of (label, freshness, version) arguments,
+    /// see [BatchProof]
+    async fn get_label_batch_proof(
+        &self,
+        labels: &[(AkdLabel, VersionFreshness, u64)],
+    ) -> Result<BatchProof, VrfError> {
+        let key = self.get_vrf_private_key().await?;
+        let expanded_key = VRFExpandedPrivateKey::from(&key);
+        let pk = VRFPublicKey::from(&key);
+        let hashed_labels = labels
+            .iter()
+            .map(|(label, freshness, version)| {
+                crate::utils::get_hash_from_label_input(label, *freshness, *version)
+            })
+            .collect::<Vec<_>>();
+        Ok(expanded_key.prove_batch(&pk, &hashed_labels))
+    }
+
     /// Retrieve the output for a specific label, with a supplied private key
     fn get_label_with_key_helper(
         expanded_private_key: &VRFExpandedPrivateKey,
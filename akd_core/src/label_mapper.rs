@@ -0,0 +1,110 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Mappings of the labels of a directory to the inputs of its VRF.
+//!
+//! The [NodeLabel]s of a label's versions are the VRF outputs on
+//! `H(i2osp_array(label) || freshness || version)` (see `utils::get_hash_from_label_input`),
+//! where the label is first mapped by the directory's [LabelMapper]. A mapper can
+//! separate the labels of a directory from those of any other directory sharing the
+//! VRF key with a domain tag, and additionally hash the labels with a per-directory
+//! salt.
+//!
+//! The labels only enter the verification of the proofs through the VRF, so a client
+//! of a directory with a custom mapper verifies the proofs for a label by passing the
+//! mapped label, i.e. `mapper.map_label(&label)`, to the verification functions.
+//!
+//! ⚠️ **Warning**: The node labels of existing leaves are re-derived when generating
+//! proofs, so the mapper of a directory must not change over its lifetime. The
+//! directory persists the [LabelMapper::id] of its mapper, and refuses to open
+//! with a different one.
+//!
+//! [NodeLabel]: crate::NodeLabel
+
+use crate::utils::i2osp_array;
+use crate::AkdLabel;
+
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
+
+/// A mapping of the labels of a directory to the inputs of its VRF
+pub trait LabelMapper: Send + Sync {
+    /// A stable identifier of the mapping along with its parameters, which must
+    /// differ between any two mappings producing different inputs. It is persisted
+    /// by the directory, so it must not reveal any secret parameter.
+    fn id(&self) -> Vec<u8>;
+
+    /// Maps a label to the label input into the VRF
+    fn map_label(&self, label: &AkdLabel) -> AkdLabel;
+}
+
+/// The identity mapping, under which the labels are input into the VRF as is.
+/// This is the default mapping.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultLabelMapper;
+
+impl LabelMapper for DefaultLabelMapper {
+    fn id(&self) -> Vec<u8> {
+        b"default".to_vec()
+    }
+
+    fn map_label(&self, label: &AkdLabel) -> AkdLabel {
+        label.clone()
+    }
+}
+
+/// Prefixes the labels with a domain separation tag, as
+/// `i2osp_array(tag) || label`, or with salted hashing, replaces them with
+/// `H(i2osp_array(tag) || i2osp_array(salt) || label)`
+#[derive(Debug, Clone, Default)]
+pub struct DomainSeparatedLabelMapper {
+    domain_separator: Vec<u8>,
+    salt: Option<Vec<u8>>,
+}
+
+impl DomainSeparatedLabelMapper {
+    /// Domain separation of the labels, with the given tag
+    pub fn new(domain_separator: &[u8]) -> Self {
+        Self {
+            domain_separator: domain_separator.to_vec(),
+            salt: None,
+        }
+    }
+
+    /// Hashes the labels with the given per-directory salt, so that the labels are
+    /// input into the VRF with a fixed length
+    pub fn with_salted_hashing(mut self, salt: &[u8]) -> Self {
+        self.salt = Some(salt.to_vec());
+        self
+    }
+}
+
+impl LabelMapper for DomainSeparatedLabelMapper {
+    fn id(&self) -> Vec<u8> {
+        match &self.salt {
+            None => [&b"domain-separated:"[..], &self.domain_separator].concat(),
+            // the salt is committed to rather than persisted as is
+            Some(salt) => [
+                &b"salted-hash:"[..],
+                &self.domain_separator,
+                b":",
+                &crate::hash::hash(&i2osp_array(salt)),
+            ]
+            .concat(),
+        }
+    }
+
+    fn map_label(&self, label: &AkdLabel) -> AkdLabel {
+        let tag = i2osp_array(&self.domain_separator);
+        match &self.salt {
+            None => AkdLabel([&tag[..], label].concat()),
+            Some(salt) => AkdLabel(
+                crate::hash::hash(&[&tag[..], &i2osp_array(salt), label].concat()).to_vec(),
+            ),
+        }
+    }
+}
@@ -50,7 +50,8 @@
 //! computed as: `value_stored_in_node = H(commitment, epoch)`
 //!
 //! Here, `commitment_key` is a secret random value held by the server for the purposes of generating
-//! these commitments.
+//! these commitments. This is the default [commitment::NonceCommitment] scheme, and other ways of
+//! deriving the nonce can be plugged in through the [commitment::ValueCommitment] trait.
 //!
 //! A client can then verify that the value stored in the tree is the same as the value they are expecting
 //! upon requesting a [LookupProof] or [HistoryProof] which includes this commitment prononceof, and can then
@@ -69,8 +70,10 @@ extern crate alloc;
 #[cfg(all(feature = "protobuf", not(feature = "nostd")))]
 pub mod proto;
 
+pub mod commitment;
 pub mod ecvrf;
 pub mod hash;
+pub mod label_mapper;
 pub mod utils;
 pub mod verify;
 
@@ -80,3 +83,9 @@ pub use types::*;
 /// The arity of the tree. Should EXACTLY match the ARITY within
 /// the AKD crate (i.e. akd::ARITY)
 pub const ARITY: usize = 2;
+
+/// The maximum depth of the tree, in bits. The labels of the leaves are the
+/// 256-bit outputs of the VRF, and each node's label extends its parent's by at
+/// least one bit, so no path through the tree (and so no membership proof) is
+/// longer than this. Verifiers reject proofs exceeding it.
+pub const MAX_TREE_DEPTH: usize = 256;
@@ -0,0 +1,134 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Versioned envelopes for serialized proofs. An envelope is a header of
+//! `[ENVELOPE_MAGIC, version, proof kind]` followed by the protobuf encoding
+//! of the proof.
+//!
+//! Proofs serialized before envelopes were introduced are the bare protobuf
+//! encoding, which we refer to as version [LEGACY_VERSION]. These are still
+//! accepted when parsing, and can still be produced for peers which haven't
+//! been upgraded yet. They can't be confused with an envelope, since the first
+//! byte of an encoded proof is the tag of a field numbered below 16, which
+//! never has its high bit set, whereas [ENVELOPE_MAGIC] does.
+
+use super::{specs, ConversionError};
+
+use core::convert::TryFrom;
+use protobuf::Message;
+
+/// The first byte of every enveloped proof
+pub const ENVELOPE_MAGIC: u8 = 0xAD;
+/// The version of bare, un-enveloped, protobuf encoded proofs
+pub const LEGACY_VERSION: u8 = 0;
+/// The envelope version which is written by default
+pub const CURRENT_VERSION: u8 = 1;
+
+const HEADER_LEN: usize = 3;
+
+/// The kind of proof carried by an envelope
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ProofKind {
+    /// A [crate::LookupProof]
+    Lookup = 1,
+    /// A [crate::HistoryProof]
+    History = 2,
+    /// A [crate::AppendOnlyProof]
+    AppendOnly = 3,
+    /// A [crate::SingleAppendOnlyProof]
+    SingleAppendOnly = 4,
+}
+
+/// Returns the version of a serialized proof, which is [LEGACY_VERSION] for
+/// bare protobuf encodings
+pub fn peek_version(bytes: &[u8]) -> Result<u8, ConversionError> {
+    match bytes {
+        [ENVELOPE_MAGIC, version, _, ..] => Ok(*version),
+        [ENVELOPE_MAGIC, ..] => Err(ConversionError::Deserialization(
+            "Truncated proof envelope header".to_string(),
+        )),
+        _ => Ok(LEGACY_VERSION),
+    }
+}
+
+/// A proof which can be serialized to and parsed from a versioned envelope
+pub trait VersionedProof: Sized {
+    /// The kind of proof, recorded in the envelope
+    const KIND: ProofKind;
+
+    /// Serializes the proof in an envelope of the [CURRENT_VERSION]
+    fn to_versioned_bytes(&self) -> Result<Vec<u8>, ConversionError> {
+        self.to_bytes_with_version(CURRENT_VERSION)
+    }
+
+    /// Serializes the proof with a given version, to communicate with peers
+    /// which don't support the current version yet. [LEGACY_VERSION] produces
+    /// the bare protobuf encoding.
+    fn to_bytes_with_version(&self, version: u8) -> Result<Vec<u8>, ConversionError>;
+
+    /// Parses a proof from either an envelope of a supported version, or from
+    /// its bare protobuf encoding
+    fn from_versioned_bytes(bytes: &[u8]) -> Result<Self, ConversionError>;
+}
+
+pub(super) fn open_envelope(bytes: &[u8], kind: ProofKind) -> Result<&[u8], ConversionError> {
+    match peek_version(bytes)? {
+        LEGACY_VERSION => Ok(bytes),
+        CURRENT_VERSION => {
+            if bytes[2] != kind as u8 {
+                return Err(ConversionError::Deserialization(format!(
+                    "Expected a proof of kind {:?} ({}), but the envelope holds kind {}",
+                    kind, kind as u8, bytes[2]
+                )));
+            }
+            Ok(&bytes[HEADER_LEN..])
+        }
+        version => Err(ConversionError::UnsupportedVersion(version)),
+    }
+}
+
+fn seal_envelope(
+    payload: Vec<u8>,
+    kind: ProofKind,
+    version: u8,
+) -> Result<Vec<u8>, ConversionError> {
+    match version {
+        LEGACY_VERSION => Ok(payload),
+        CURRENT_VERSION => {
+            let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+            bytes.extend_from_slice(&[ENVELOPE_MAGIC, version, kind as u8]);
+            bytes.extend(payload);
+            Ok(bytes)
+        }
+        version => Err(ConversionError::UnsupportedVersion(version)),
+    }
+}
+
+macro_rules! versioned_proof {
+    ($type:ident, $kind:expr) => {
+        impl VersionedProof for crate::$type {
+            const KIND: ProofKind = $kind;
+
+            fn to_bytes_with_version(&self, version: u8) -> Result<Vec<u8>, ConversionError> {
+                let payload = specs::types::$type::from(self).write_to_bytes()?;
+                seal_envelope(payload, Self::KIND, version)
+            }
+
+            fn from_versioned_bytes(bytes: &[u8]) -> Result<Self, ConversionError> {
+                let payload = open_envelope(bytes, Self::KIND)?;
+                let proto = specs::types::$type::parse_from_bytes(payload)?;
+                Self::try_from(&proto)
+            }
+        }
+    };
+}
+
+versioned_proof!(LookupProof, ProofKind::Lookup);
+versioned_proof!(HistoryProof, ProofKind::History);
+versioned_proof!(AppendOnlyProof, ProofKind::AppendOnly);
+versioned_proof!(SingleAppendOnlyProof, ProofKind::SingleAppendOnly);
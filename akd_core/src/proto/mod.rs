@@ -12,6 +12,9 @@
 // Setup the protobuf specs
 pub mod specs;
 
+pub mod envelope;
+pub mod view;
+
 #[cfg(test)]
 mod tests;
 
@@ -29,6 +32,8 @@ pub enum ConversionError {
     Deserialization(String),
     /// A core protobuf error occurred
     Protobuf(String),
+    /// The serialized proof has a version which isn't supported
+    UnsupportedVersion(u8),
 }
 
 impl From<protobuf::Error> for ConversionError {
@@ -45,6 +50,9 @@ impl core::fmt::Display for ConversionError {
         let code = match &self {
             ConversionError::Deserialization(msg) => format!("(Deserialization) - {}", msg),
             ConversionError::Protobuf(msg) => format!("(Protobuf) - {}", msg),
+            ConversionError::UnsupportedVersion(version) => {
+                format!("(Version) - Unsupported proof version {}", version)
+            }
         };
         write!(f, "Type conversion error {}", code)
     }
@@ -328,6 +336,7 @@ impl From<&crate::LookupProof> for specs::types::LookupProof {
             freshness_vrf_proof: Some(input.freshness_vrf_proof.clone()),
             freshness_proof: MessageField::some((&input.freshness_proof).into()),
             commitment_proof: Some(input.commitment_proof.clone()),
+            expiry_epoch: input.expiry_epoch,
             ..Default::default()
         }
     }
@@ -359,6 +368,7 @@ impl TryFrom<&specs::types::LookupProof> for crate::LookupProof {
             freshness_vrf_proof: input.freshness_vrf_proof().to_vec(),
             freshness_proof: input.freshness_proof.as_ref().unwrap().try_into()?,
             commitment_proof: input.commitment_proof().to_vec(),
+            expiry_epoch: input.expiry_epoch,
         })
     }
 }
@@ -383,6 +393,8 @@ impl From<&crate::UpdateProof> for specs::types::UpdateProof {
                     .map(|p| p.into()),
             ),
             commitment_proof: Some(input.commitment_proof.clone()),
+            value_commitment: input.value_commitment.map(|c| c.to_vec()),
+            expiry_epoch: input.expiry_epoch,
             ..Default::default()
         }
     }
@@ -407,6 +419,10 @@ impl TryFrom<&specs::types::UpdateProof> for crate::UpdateProof {
             .as_ref()
             .map(|item| item.try_into())
             .transpose()?;
+        let value_commitment = match &input.value_commitment {
+            Some(commitment) => Some(hash_from_bytes!(commitment)),
+            None => None,
+        };
 
         Ok(Self {
             epoch: input.epoch(),
@@ -417,6 +433,8 @@ impl TryFrom<&specs::types::UpdateProof> for crate::UpdateProof {
             previous_version_vrf_proof,
             previous_version_stale_at_ep,
             commitment_proof: input.commitment_proof().to_vec(),
+            value_commitment,
+            expiry_epoch: input.expiry_epoch,
         })
     }
 }
@@ -142,6 +142,7 @@ fn test_convert_lookup_proof() {
             },
         },
         commitment_proof: random_hash().to_vec(),
+        expiry_epoch: Some(rng.gen()),
     };
 
     let protobuf: LookupProof = (&original).into();
@@ -176,6 +177,8 @@ fn test_convert_update_proof() {
             }],
         }),
         commitment_proof: random_hash().to_vec(),
+        value_commitment: Some(random_hash()),
+        expiry_epoch: Some(rng.gen()),
     };
 
     let protobuf: UpdateProof = (&original).into();
@@ -228,6 +231,8 @@ fn test_convert_history_proof() {
                 }],
             }),
             commitment_proof: random_hash().to_vec(),
+            value_commitment: None,
+            expiry_epoch: None,
         }
     }
 
@@ -308,3 +313,186 @@ fn test_minimum_encoding_label_bytes() {
     assert_eq!(half_label, decode_minimized_label(&min_half_label));
     assert_eq!(zero_label, decode_minimized_label(&min_zero_label));
 }
+
+#[test]
+fn test_versioned_envelope() {
+    use super::envelope::*;
+    use protobuf::Message;
+
+    let single = crate::SingleAppendOnlyProof {
+        inserted: vec![random_node(), random_node()],
+        unchanged_nodes: vec![random_node()],
+    };
+    let original = crate::AppendOnlyProof {
+        proofs: vec![single.clone()],
+        epochs: vec![thread_rng().gen()],
+    };
+
+    // current version round trip
+    let bytes = original.to_versioned_bytes().unwrap();
+    assert_eq!(
+        &[ENVELOPE_MAGIC, CURRENT_VERSION, ProofKind::AppendOnly as u8],
+        &bytes[..3]
+    );
+    assert_eq!(Ok(CURRENT_VERSION), peek_version(&bytes));
+    assert_eq!(
+        original,
+        crate::AppendOnlyProof::from_versioned_bytes(&bytes).unwrap()
+    );
+
+    // bare protobuf encodings are still accepted, and can still be produced
+    let legacy_bytes = AppendOnlyProof::from(&original).write_to_bytes().unwrap();
+    assert_eq!(Ok(LEGACY_VERSION), peek_version(&legacy_bytes));
+    assert_eq!(
+        legacy_bytes,
+        original.to_bytes_with_version(LEGACY_VERSION).unwrap()
+    );
+    assert_eq!(
+        original,
+        crate::AppendOnlyProof::from_versioned_bytes(&legacy_bytes).unwrap()
+    );
+
+    // a proof of another kind is rejected
+    let single_bytes = single.to_versioned_bytes().unwrap();
+    assert!(crate::AppendOnlyProof::from_versioned_bytes(&single_bytes).is_err());
+    assert_eq!(
+        single,
+        crate::SingleAppendOnlyProof::from_versioned_bytes(&single_bytes).unwrap()
+    );
+
+    // unknown versions and truncated headers are rejected
+    let mut future_bytes = bytes.clone();
+    future_bytes[1] = CURRENT_VERSION + 1;
+    assert_eq!(
+        Err(ConversionError::UnsupportedVersion(CURRENT_VERSION + 1)),
+        crate::AppendOnlyProof::from_versioned_bytes(&future_bytes)
+    );
+    assert_eq!(
+        Err(ConversionError::UnsupportedVersion(CURRENT_VERSION + 1)),
+        original.to_bytes_with_version(CURRENT_VERSION + 1)
+    );
+    assert!(crate::AppendOnlyProof::from_versioned_bytes(&bytes[..2]).is_err());
+}
+
+fn random_proof_node() -> crate::Node {
+    crate::Node {
+        label: crate::NodeLabel::new(random_hash(), thread_rng().gen_range(1, 257)),
+        hash: random_hash(),
+    }
+}
+
+fn view_to_proof(
+    view: &view::AppendOnlyProofView<'_>,
+) -> Result<crate::AppendOnlyProof, ConversionError> {
+    let mut proofs = vec![];
+    for single in view.proofs() {
+        let single = single?;
+        proofs.push(crate::SingleAppendOnlyProof {
+            inserted: single.inserted().collect::<Result<_, _>>()?,
+            unchanged_nodes: single.unchanged_nodes().collect::<Result<_, _>>()?,
+        });
+    }
+    Ok(crate::AppendOnlyProof {
+        proofs,
+        epochs: view.epochs().collect::<Result<_, _>>()?,
+    })
+}
+
+#[test]
+fn test_append_only_proof_view() {
+    use super::envelope::VersionedProof;
+    use protobuf::Message;
+
+    let original = crate::AppendOnlyProof {
+        proofs: vec![
+            crate::SingleAppendOnlyProof {
+                inserted: vec![random_proof_node(), random_proof_node()],
+                unchanged_nodes: vec![random_proof_node()],
+            },
+            crate::SingleAppendOnlyProof {
+                inserted: vec![],
+                unchanged_nodes: vec![random_proof_node(), random_proof_node()],
+            },
+        ],
+        epochs: vec![7, 8],
+    };
+
+    let bytes = AppendOnlyProof::from(&original).write_to_bytes().unwrap();
+    let view = view::AppendOnlyProofView::new(&bytes).unwrap();
+    assert_eq!(Ok(original.clone()), view_to_proof(&view));
+
+    let versioned_bytes = original.to_versioned_bytes().unwrap();
+    let view = view::AppendOnlyProofView::new(&versioned_bytes).unwrap();
+    assert_eq!(Ok(original.clone()), view_to_proof(&view));
+
+    let single_bytes = original.proofs[0].to_versioned_bytes().unwrap();
+    let single = view::SingleAppendOnlyProofView::new(&single_bytes).unwrap();
+    assert_eq!(
+        original.proofs[0].inserted,
+        single.inserted().collect::<Result<Vec<_>, _>>().unwrap()
+    );
+    assert_eq!(
+        original.proofs[0].unchanged_nodes,
+        single
+            .unchanged_nodes()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    );
+
+    // packed epochs are accepted as well
+    let mut packed = vec![];
+    for proof in original.proofs.iter() {
+        let proof_bytes = SingleAppendOnlyProof::from(proof).write_to_bytes().unwrap();
+        let mut len = proof_bytes.len();
+        packed.push(0x0a);
+        while len >= 0x80 {
+            packed.push((len as u8) | 0x80);
+            len >>= 7;
+        }
+        packed.push(len as u8);
+        packed.extend(proof_bytes);
+    }
+    packed.extend([0x12, 0x02, 7, 8]);
+    let view = view::AppendOnlyProofView::new(&packed).unwrap();
+    assert_eq!(Ok(original), view_to_proof(&view));
+}
+
+#[test]
+fn test_append_only_proof_view_malformed() {
+    use protobuf::Message;
+
+    let original = crate::AppendOnlyProof {
+        proofs: vec![crate::SingleAppendOnlyProof {
+            inserted: vec![random_proof_node()],
+            unchanged_nodes: vec![random_proof_node(), random_proof_node()],
+        }],
+        epochs: vec![3],
+    };
+    let bytes = AppendOnlyProof::from(&original).write_to_bytes().unwrap();
+
+    // no truncation of the buffer decodes to the original proof
+    for len in 0..bytes.len() {
+        let decoded =
+            view::AppendOnlyProofView::new(&bytes[..len]).and_then(|view| view_to_proof(&view));
+        assert_ne!(Ok(original.clone()), decoded);
+    }
+
+    // lengths pointing past the end of the buffer are rejected
+    assert!(view::AppendOnlyProofView::new(&[0x0a, 0xff, 0xff, 0xff, 0xff, 0x0f]).is_err());
+    assert!(view::SingleAppendOnlyProofView::new(&[0x0a, 0x05, 0x0a]).is_err());
+    assert!(view::SingleAppendOnlyProofView::new(&[0x08, 0xff]).is_err());
+
+    // as are nodes with missing fields or oversized labels
+    let missing_hash = [0x0a, 0x04, 0x0a, 0x02, 0x10, 0x01];
+    let single = view::SingleAppendOnlyProofView::new(&missing_hash).unwrap();
+    assert!(matches!(single.inserted().next(), Some(Err(_))));
+    let mut node = Node::from(&random_proof_node());
+    node.label.mut_or_insert_default().set_label_len(257);
+    let single = SingleAppendOnlyProof {
+        inserted: vec![node],
+        ..Default::default()
+    };
+    let single_bytes = single.write_to_bytes().unwrap();
+    let single = view::SingleAppendOnlyProofView::new(&single_bytes).unwrap();
+    assert!(matches!(single.inserted().next(), Some(Err(_))));
+}
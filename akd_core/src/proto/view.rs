@@ -0,0 +1,374 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Zero-copy views of protobuf-encoded append-only proofs.
+//!
+//! Parsing an [crate::AppendOnlyProof] with the generated protobuf types allocates
+//! every node of the proof (along with its label and hash buffers) before it can be
+//! converted, which for large epochs takes a multiple of the proof size in memory.
+//! The views here instead walk the serialized buffer directly, decoding one
+//! [crate::Node] at a time on demand. Every length read from the buffer is bounds
+//! checked, so a truncated or malformed buffer results in a [ConversionError] rather
+//! than a panic.
+//!
+//! Both the bare protobuf encodings and the versioned envelopes of
+//! [super::envelope] are accepted.
+
+use super::envelope::{open_envelope, ProofKind};
+use super::ConversionError;
+use crate::hash::Digest;
+use crate::{Node, NodeLabel};
+
+use core::convert::TryInto;
+
+const WIRE_TYPE_VARINT: u8 = 0;
+const WIRE_TYPE_FIXED64: u8 = 1;
+const WIRE_TYPE_LENGTH_DELIMITED: u8 = 2;
+const WIRE_TYPE_FIXED32: u8 = 5;
+
+const MAX_VARINT_BYTES: usize = 10;
+const MAX_LABEL_BYTES: usize = 32;
+const MAX_LABEL_BITS: u32 = 256;
+
+// SingleAppendOnlyProof fields
+const FIELD_INSERTED: u32 = 1;
+const FIELD_UNCHANGED_NODES: u32 = 2;
+// AppendOnlyProof fields
+const FIELD_PROOFS: u32 = 1;
+const FIELD_EPOCHS: u32 = 2;
+// Node fields
+const FIELD_NODE_LABEL: u32 = 1;
+const FIELD_NODE_HASH: u32 = 2;
+// NodeLabel fields
+const FIELD_LABEL_VAL: u32 = 1;
+const FIELD_LABEL_LEN: u32 = 2;
+
+fn malformed(msg: &str) -> ConversionError {
+    ConversionError::Deserialization(format!("Malformed protobuf buffer: {}", msg))
+}
+
+/// The value of a field of a protobuf message
+#[derive(Clone, Copy)]
+enum FieldValue<'a> {
+    Varint(u64),
+    Fixed,
+    Bytes(&'a [u8]),
+}
+
+/// Reads the fields of a protobuf message from its encoding, one at a time
+#[derive(Clone)]
+struct WireReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> WireReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn read_varint(&mut self) -> Result<u64, ConversionError> {
+        let mut value = 0u64;
+        for i in 0..MAX_VARINT_BYTES {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| malformed("truncated varint"))?;
+            self.position += 1;
+            value |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(malformed("varint longer than 10 bytes"))
+    }
+
+    fn read_slice(&mut self, len: usize) -> Result<&'a [u8], ConversionError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| malformed("field length exceeds the buffer"))?;
+        let slice = &self.data[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    /// Reads the next field number and value, or None at the end of the message
+    fn next_field(&mut self) -> Option<Result<(u32, FieldValue<'a>), ConversionError>> {
+        if self.is_empty() {
+            return None;
+        }
+        let result = self.read_field();
+        if result.is_err() {
+            // stop at the first error, rather than reading garbage
+            self.position = self.data.len();
+        }
+        Some(result)
+    }
+
+    fn read_field(&mut self) -> Result<(u32, FieldValue<'a>), ConversionError> {
+        let key = self.read_varint()?;
+        let field: u32 = (key >> 3)
+            .try_into()
+            .map_err(|_| malformed("field number out of range"))?;
+        if field == 0 {
+            return Err(malformed("field number 0"));
+        }
+        let value = match (key & 0x7) as u8 {
+            WIRE_TYPE_VARINT => FieldValue::Varint(self.read_varint()?),
+            WIRE_TYPE_FIXED64 => {
+                self.read_slice(8)?;
+                FieldValue::Fixed
+            }
+            WIRE_TYPE_LENGTH_DELIMITED => {
+                let len: usize = self
+                    .read_varint()?
+                    .try_into()
+                    .map_err(|_| malformed("field length out of range"))?;
+                FieldValue::Bytes(self.read_slice(len)?)
+            }
+            WIRE_TYPE_FIXED32 => {
+                self.read_slice(4)?;
+                FieldValue::Fixed
+            }
+            other => return Err(malformed(&format!("unsupported wire type {}", other))),
+        };
+        Ok((field, value))
+    }
+
+    /// Walks all the fields of the message, checking their bounds
+    fn validate(mut self) -> Result<(), ConversionError> {
+        while let Some(field) = self.next_field() {
+            field?;
+        }
+        Ok(())
+    }
+}
+
+fn expect_bytes<'a>(value: FieldValue<'a>, name: &str) -> Result<&'a [u8], ConversionError> {
+    match value {
+        FieldValue::Bytes(bytes) => Ok(bytes),
+        _ => Err(malformed(&format!("{} is not length-delimited", name))),
+    }
+}
+
+fn expect_varint(value: FieldValue<'_>, name: &str) -> Result<u64, ConversionError> {
+    match value {
+        FieldValue::Varint(value) => Ok(value),
+        _ => Err(malformed(&format!("{} is not a varint", name))),
+    }
+}
+
+/// Decodes a Node message. As with the generated code, repeated occurrences of
+/// the label are merged, and the last occurrence of a scalar field wins.
+fn decode_node(data: &[u8]) -> Result<Node, ConversionError> {
+    let mut label_val: Option<&[u8]> = None;
+    let mut label_len: Option<u64> = None;
+    let mut hash: Option<&[u8]> = None;
+
+    let mut reader = WireReader::new(data);
+    while let Some(field) = reader.next_field() {
+        match field? {
+            (FIELD_NODE_LABEL, value) => {
+                let mut label_reader = WireReader::new(expect_bytes(value, "Node.label")?);
+                while let Some(label_field) = label_reader.next_field() {
+                    match label_field? {
+                        (FIELD_LABEL_VAL, value) => {
+                            label_val = Some(expect_bytes(value, "NodeLabel.label_val")?)
+                        }
+                        (FIELD_LABEL_LEN, value) => {
+                            label_len = Some(expect_varint(value, "NodeLabel.label_len")?)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            (FIELD_NODE_HASH, value) => hash = Some(expect_bytes(value, "Node.hash")?),
+            _ => {}
+        }
+    }
+
+    let (label_val, label_len, hash) = match (label_val, label_len, hash) {
+        (Some(label_val), Some(label_len), Some(hash)) => (label_val, label_len, hash),
+        _ => {
+            return Err(ConversionError::Deserialization(
+                "Required field missing in Node. 'label' and 'hash' are required".to_string(),
+            ))
+        }
+    };
+    if label_val.len() > MAX_LABEL_BYTES || label_len > u64::from(MAX_LABEL_BITS) {
+        return Err(ConversionError::Deserialization(format!(
+            "Invalid node label of {} bits ({} bytes)",
+            label_len,
+            label_val.len()
+        )));
+    }
+    let mut val = [0u8; 32];
+    val[..label_val.len()].copy_from_slice(label_val);
+    let hash: Digest =
+        crate::hash::try_parse_digest(hash).map_err(ConversionError::Deserialization)?;
+
+    Ok(Node {
+        label: NodeLabel::new(val, label_len as u32),
+        hash,
+    })
+}
+
+/// Iterates over the nodes of one of the repeated fields of a serialized
+/// [crate::SingleAppendOnlyProof], decoding them one at a time
+#[derive(Clone)]
+pub struct NodeIter<'a> {
+    reader: WireReader<'a>,
+    field: u32,
+}
+
+impl<'a> Iterator for NodeIter<'a> {
+    type Item = Result<Node, ConversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.reader.next_field()? {
+                Err(err) => return Some(Err(err)),
+                Ok((field, value)) if field == self.field => {
+                    return Some(expect_bytes(value, "Node").and_then(decode_node));
+                }
+                Ok(_) => {}
+            }
+        }
+    }
+}
+
+/// A view of a protobuf-encoded [crate::SingleAppendOnlyProof], e.g. the contents
+/// of an audit blob, which decodes its nodes on demand
+#[derive(Clone, Copy, Debug)]
+pub struct SingleAppendOnlyProofView<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> SingleAppendOnlyProofView<'a> {
+    /// Creates a view of the given buffer, checking that its fields are within bounds
+    pub fn new(data: &'a [u8]) -> Result<Self, ConversionError> {
+        let data = open_envelope(data, ProofKind::SingleAppendOnly)?;
+        WireReader::new(data).validate()?;
+        Ok(Self { data })
+    }
+
+    /// The leaves inserted in the epoch, in the order they were serialized
+    pub fn inserted(&self) -> NodeIter<'a> {
+        NodeIter {
+            reader: WireReader::new(self.data),
+            field: FIELD_INSERTED,
+        }
+    }
+
+    /// The nodes left unchanged by the epoch, in the order they were serialized
+    pub fn unchanged_nodes(&self) -> NodeIter<'a> {
+        NodeIter {
+            reader: WireReader::new(self.data),
+            field: FIELD_UNCHANGED_NODES,
+        }
+    }
+}
+
+/// Iterates over the proofs of a serialized [crate::AppendOnlyProof]
+#[derive(Clone)]
+pub struct SingleAppendOnlyProofIter<'a> {
+    reader: WireReader<'a>,
+}
+
+impl<'a> Iterator for SingleAppendOnlyProofIter<'a> {
+    type Item = Result<SingleAppendOnlyProofView<'a>, ConversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.reader.next_field()? {
+                Err(err) => return Some(Err(err)),
+                Ok((FIELD_PROOFS, value)) => {
+                    return Some(
+                        expect_bytes(value, "AppendOnlyProof.proofs")
+                            .and_then(SingleAppendOnlyProofView::new),
+                    );
+                }
+                Ok(_) => {}
+            }
+        }
+    }
+}
+
+/// Iterates over the epochs of a serialized [crate::AppendOnlyProof], which may
+/// be either packed or unpacked
+#[derive(Clone)]
+pub struct EpochIter<'a> {
+    reader: WireReader<'a>,
+    packed: Option<WireReader<'a>>,
+}
+
+impl<'a> Iterator for EpochIter<'a> {
+    type Item = Result<u64, ConversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(packed) = self.packed.as_mut() {
+                if !packed.is_empty() {
+                    let epoch = packed.read_varint();
+                    if epoch.is_err() {
+                        self.packed = None;
+                    }
+                    return Some(epoch);
+                }
+                self.packed = None;
+            }
+            match self.reader.next_field()? {
+                Err(err) => return Some(Err(err)),
+                Ok((FIELD_EPOCHS, FieldValue::Varint(epoch))) => return Some(Ok(epoch)),
+                Ok((FIELD_EPOCHS, FieldValue::Bytes(bytes))) => {
+                    self.packed = Some(WireReader::new(bytes));
+                }
+                Ok((FIELD_EPOCHS, FieldValue::Fixed)) => {
+                    return Some(Err(malformed("AppendOnlyProof.epochs is not a varint")));
+                }
+                Ok(_) => {}
+            }
+        }
+    }
+}
+
+/// A view of a protobuf-encoded [crate::AppendOnlyProof], which decodes its
+/// proofs and their nodes on demand
+#[derive(Clone, Copy, Debug)]
+pub struct AppendOnlyProofView<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> AppendOnlyProofView<'a> {
+    /// Creates a view of the given buffer, checking that its fields are within bounds
+    pub fn new(data: &'a [u8]) -> Result<Self, ConversionError> {
+        let data = open_envelope(data, ProofKind::AppendOnly)?;
+        WireReader::new(data).validate()?;
+        Ok(Self { data })
+    }
+
+    /// The proofs of each epoch transition, in the order they were serialized
+    pub fn proofs(&self) -> SingleAppendOnlyProofIter<'a> {
+        SingleAppendOnlyProofIter {
+            reader: WireReader::new(self.data),
+        }
+    }
+
+    /// The starting epoch of each proof, in the order they were serialized
+    pub fn epochs(&self) -> EpochIter<'a> {
+        EpochIter {
+            reader: WireReader::new(self.data),
+            packed: None,
+        }
+    }
+}
@@ -16,6 +16,7 @@ use crate::hash::Digest;
 #[cfg(feature = "serde_serialization")]
 use crate::utils::serde_helpers::{
     bytes_deserialize_hex, bytes_serialize_hex, digest_deserialize, digest_serialize,
+    optional_digest_deserialize, optional_digest_serialize,
 };
 use crate::ARITY;
 
@@ -205,6 +206,10 @@ impl AkdValue {
     }
 }
 
+/// The domain separator of the commitments to values published with an expiry
+/// epoch, see [crate::utils::bind_expiry]
+pub const VALUE_EXPIRY_DOMAIN: &[u8; 16] = b"akd_value_expiry";
+
 /// The value to be hashed every time an empty node's hash is to be considered
 pub const EMPTY_VALUE: [u8; 1] = [0u8];
 
@@ -273,6 +278,14 @@ pub struct LayerProof {
     pub direction: Direction,
 }
 
+impl SizeOf for LayerProof {
+    fn size_of(&self) -> usize {
+        self.label.size_of()
+            + self.siblings.iter().map(|s| s.size_of()).sum::<usize>()
+            + self.direction.size_of()
+    }
+}
+
 /// Merkle proof of membership of a [`NodeLabel`] with a particular hash
 /// value in the tree at a given epoch
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -296,6 +309,14 @@ pub struct MembershipProof {
     pub layer_proofs: Vec<LayerProof>,
 }
 
+impl SizeOf for MembershipProof {
+    fn size_of(&self) -> usize {
+        self.label.size_of()
+            + self.hash_val.len()
+            + self.layer_proofs.iter().map(|p| p.size_of()).sum::<usize>()
+    }
+}
+
 /// Merkle Patricia proof of non-membership for a [`NodeLabel`] in the tree
 /// at a given epoch.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -314,6 +335,19 @@ pub struct NonMembershipProof {
     pub longest_prefix_membership_proof: MembershipProof,
 }
 
+impl SizeOf for NonMembershipProof {
+    fn size_of(&self) -> usize {
+        self.label.size_of()
+            + self.longest_prefix.size_of()
+            + self
+                .longest_prefix_children
+                .iter()
+                .map(|c| c.size_of())
+                .sum::<usize>()
+            + self.longest_prefix_membership_proof.size_of()
+    }
+}
+
 /// Proof that a given label was at a particular state at the given epoch.
 /// This means we need to show that the state and version we are claiming for this node must have been:
 /// * committed in the tree,
@@ -346,6 +380,112 @@ pub struct LookupProof {
     pub freshness_proof: NonMembershipProof,
     /// Proof for commitment value derived from raw AkdLabel and AkdValue
     pub commitment_proof: Vec<u8>,
+    /// The epoch at which the value expires, if it was published with one. It's
+    /// committed to along with the value (see [crate::utils::bind_expiry]).
+    #[cfg_attr(feature = "serde_serialization", serde(default))]
+    pub expiry_epoch: Option<u64>,
+}
+
+impl UpdateProof {
+    /// Whether the value of this update is redacted: it is committed to in the
+    /// tree (as shown by the existence proof), but not opened, i.e. neither the
+    /// plaintext value nor its commitment proof are disclosed, only the
+    /// commitment itself (see [UpdateProof::value_commitment]). An opened value
+    /// may have an empty commitment proof, e.g. with a [crate::commitment::HashCommitment]
+    /// without a domain separator, so it's the commitment which marks the update
+    /// as redacted.
+    pub fn is_redacted(&self) -> bool {
+        self.value_commitment.is_some()
+    }
+}
+
+impl SizeOf for LookupProof {
+    fn size_of(&self) -> usize {
+        2 * core::mem::size_of::<u64>()
+            + self.plaintext_value.size_of()
+            + self.existence_vrf_proof.len()
+            + self.existence_proof.size_of()
+            + self.marker_vrf_proof.len()
+            + self.marker_proof.size_of()
+            + self.freshness_vrf_proof.len()
+            + self.freshness_proof.size_of()
+            + self.commitment_proof.len()
+            + self.expiry_epoch.map_or(0, |_| core::mem::size_of::<u64>())
+    }
+}
+
+/// The proof that a label has never been published to the directory, sent in
+/// response to a lookup of a label which doesn't exist. Every published label has
+/// its first version in the tree, so it is enough to show that the label of the
+/// first version is not a member of the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct AbsenceProof {
+    /// VRF proof for the label corresponding to the first version
+    pub vrf_proof: Vec<u8>,
+    /// Non-membership proof of the label of the first version
+    pub non_membership_proof: NonMembershipProof,
+    /// Zero bytes padding the proof to a constant size, so that the size of the
+    /// proof doesn't reveal the depth of the label's path in the tree. It isn't
+    /// covered by the verification.
+    #[cfg_attr(feature = "serde_serialization", serde(default))]
+    pub padding: Vec<u8>,
+}
+
+impl SizeOf for AbsenceProof {
+    fn size_of(&self) -> usize {
+        self.vrf_proof.len() + self.non_membership_proof.size_of() + self.padding.len()
+    }
+}
+
+impl AbsenceProof {
+    /// The size (as given by [SizeOf]) of an unpadded proof whose longest prefix
+    /// has `depth` ancestors in the tree, which is the size to pad the proofs to
+    /// for the proofs of labels up to this depth to be indistinguishable
+    pub fn size_at_depth(depth: usize) -> usize {
+        let node = Node {
+            label: NodeLabel::root(),
+            hash: crate::hash::EMPTY_DIGEST,
+        };
+        let layer_proof = LayerProof {
+            label: NodeLabel::root(),
+            siblings: [node; ARITY - 1],
+            direction: Direction::Left,
+        };
+        crate::verify::lite::VRF_PROOF_BYTES
+            + NodeLabel::root().size_of() * 3
+            + node.size_of() * ARITY
+            + crate::hash::DIGEST_BYTES
+            + layer_proof.size_of() * depth
+    }
+}
+
+/// The proof of a lookup of several labels at once. The VRF proofs of the lookups
+/// are left empty, and instead a single batch VRF proof covers the existence, marker
+/// and freshness labels of all of them (in this order, for each lookup in turn).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct BatchLookupProof {
+    /// The lookup proofs, without their VRF proofs
+    pub lookup_proofs: Vec<LookupProof>,
+    /// The batch VRF proof for the labels of all the lookups
+    pub vrf_proof: Vec<u8>,
+}
+
+impl SizeOf for BatchLookupProof {
+    fn size_of(&self) -> usize {
+        self.lookup_proofs
+            .iter()
+            .map(|proof| proof.size_of())
+            .sum::<usize>()
+            + self.vrf_proof.len()
+    }
 }
 
 /// A vector of UpdateProofs are sent as the proof to a history query for a particular key.
@@ -377,6 +517,44 @@ pub struct UpdateProof {
     pub previous_version_stale_at_ep: Option<MembershipProof>,
     /// Proof for commitment value derived from raw AkdLabel and AkdValue
     pub commitment_proof: Vec<u8>,
+    /// The commitment to the value when the value is redacted, which the
+    /// existence proof is checked against in place of the value and its
+    /// commitment proof. This is None when the value is opened.
+    #[cfg_attr(
+        feature = "serde_serialization",
+        serde(
+            default,
+            serialize_with = "optional_digest_serialize",
+            deserialize_with = "optional_digest_deserialize"
+        )
+    )]
+    pub value_commitment: Option<Digest>,
+    /// The epoch at which the value expires, if it was published with one (see
+    /// [LookupProof::expiry_epoch]). This is None when the value is redacted.
+    #[cfg_attr(feature = "serde_serialization", serde(default))]
+    pub expiry_epoch: Option<u64>,
+}
+
+impl SizeOf for UpdateProof {
+    fn size_of(&self) -> usize {
+        2 * core::mem::size_of::<u64>()
+            + self.plaintext_value.size_of()
+            + self.existence_vrf_proof.len()
+            + self.existence_at_ep.size_of()
+            + self
+                .previous_version_vrf_proof
+                .as_ref()
+                .map_or(0, |proof| proof.len())
+            + self
+                .previous_version_stale_at_ep
+                .as_ref()
+                .map_or(0, |proof| proof.size_of())
+            + self.commitment_proof.len()
+            + self
+                .value_commitment
+                .map_or(0, |commitment| commitment.len())
+            + self.expiry_epoch.map_or(0, |_| core::mem::size_of::<u64>())
+    }
 }
 
 /// This proof is just an array of [`UpdateProof`]s.
@@ -398,6 +576,67 @@ pub struct HistoryProof {
     pub non_existence_of_future_markers: Vec<NonMembershipProof>,
 }
 
+impl SizeOf for HistoryProof {
+    fn size_of(&self) -> usize {
+        self.update_proofs
+            .iter()
+            .map(|proof| proof.size_of())
+            .sum::<usize>()
+            + self
+                .next_few_vrf_proofs
+                .iter()
+                .map(|proof| proof.len())
+                .sum::<usize>()
+            + self
+                .non_existence_of_next_few
+                .iter()
+                .map(|proof| proof.size_of())
+                .sum::<usize>()
+            + self
+                .future_marker_vrf_proofs
+                .iter()
+                .map(|proof| proof.len())
+                .sum::<usize>()
+            + self
+                .non_existence_of_future_markers
+                .iter()
+                .map(|proof| proof.size_of())
+                .sum::<usize>()
+    }
+}
+
+/// Which of the values of a [HistoryProof] are disclosed (opened), the others
+/// being redacted. The updates are counted from the most recent one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub enum ValueDisclosure {
+    /// All the values are opened
+    #[default]
+    All,
+    /// Only the values of the N most recent updates are opened
+    MostRecent(usize),
+    /// Only the values of the updates since the given epoch (inclusive) are opened
+    SinceEpoch(u64),
+    /// None of the values are opened
+    None,
+}
+
+impl ValueDisclosure {
+    /// Whether the value of the update at the given position (0 being the most
+    /// recent update) and epoch is opened
+    pub fn is_opened(&self, position: usize, epoch: u64) -> bool {
+        match self {
+            Self::All => true,
+            Self::MostRecent(count) => position < *count,
+            Self::SinceEpoch(since) => epoch >= *since,
+            Self::None => false,
+        }
+    }
+}
+
 /// The payload that is outputted as a result of successful verification of
 /// a [LookupProof] or [HistoryProof]. This includes the fields containing the
 /// epoch that the leaf was published in, the version corresponding to the value,
@@ -414,6 +653,34 @@ pub struct VerifyResult {
     pub version: u64,
     /// The plaintext value associated with the record
     pub value: AkdValue,
+    /// The epoch at which the value expires, if it was published with one
+    pub expiry_epoch: Option<u64>,
+}
+
+impl VerifyResult {
+    /// Whether the value has expired at the given epoch, i.e. whether the epoch
+    /// is at or after its expiry epoch
+    pub fn is_expired(&self, epoch: u64) -> bool {
+        matches!(self.expiry_epoch, Some(expiry_epoch) if epoch >= expiry_epoch)
+    }
+}
+
+/// The state of a label at one of the epochs of a multi-epoch lookup, as
+/// output by the verification of the corresponding [HistoryProof]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct EpochLookupResult {
+    /// The epoch the label was looked up at
+    pub epoch: u64,
+    /// The update in effect at the epoch, or `None` if the label had not
+    /// been published yet
+    pub state: Option<VerifyResult>,
+    /// Whether the update in effect differs from the one in effect at the
+    /// previous epoch looked up (always false for the first epoch)
+    pub changed: bool,
 }
 
 /// Proof that no leaves were deleted from the initial epoch.
@@ -449,3 +716,210 @@ pub struct AppendOnlyProof {
     /// Epochs over which this audit is being performed
     pub epochs: Vec<u64>,
 }
+
+/// The nodes of a [SingleAppendOnlyProof] whose labels start with a given prefix,
+/// which can be verified independently of the rest of the proof against the
+/// [AppendOnlyManifest] of a [SegmentedAppendOnlyProof]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct AppendOnlyProofSegment {
+    /// The epoch at which the transition covered by this segment starts
+    pub epoch: u64,
+    /// The label prefix shared by all the nodes of the segment
+    pub prefix: NodeLabel,
+    /// The nodes of the proof below the prefix
+    pub proof: SingleAppendOnlyProof,
+}
+
+/// The topmost node below the prefix of an [AppendOnlyProofSegment], before and
+/// after the epoch transition
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct AppendOnlySegmentSummary {
+    /// The label prefix of the segment
+    pub prefix: NodeLabel,
+    /// The topmost node below the prefix before the transition, if there was any
+    pub start: Option<Node>,
+    /// The topmost node below the prefix after the transition
+    pub end: Node,
+}
+
+/// Links the segments of a single epoch transition to the root hashes
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct AppendOnlyTransitionManifest {
+    /// The epoch at which the transition starts
+    pub epoch: u64,
+    /// The unchanged nodes whose labels are shorter than the segment prefixes
+    pub shared_nodes: Vec<Node>,
+    /// The summaries of the (non-empty) segments of the transition
+    pub segments: Vec<AppendOnlySegmentSummary>,
+}
+
+/// The manifest of a [SegmentedAppendOnlyProof]. The shared nodes and the topmost
+/// nodes of the segments of each transition hash to the root hashes before and
+/// after the transition, so that the segments can then be verified in any order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct AppendOnlyManifest {
+    /// The length (in bits) of the label prefixes of the segments
+    pub prefix_bits: u32,
+    /// One manifest per epoch transition being audited
+    pub transitions: Vec<AppendOnlyTransitionManifest>,
+}
+
+/// An [AppendOnlyProof] split into segments by label prefix, which can be verified
+/// in any order, possibly by several machines in parallel
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct SegmentedAppendOnlyProof {
+    /// Links the segments to the root hashes
+    pub manifest: AppendOnlyManifest,
+    /// The segments of all the epoch transitions
+    pub segments: Vec<AppendOnlyProofSegment>,
+}
+
+/// A [LookupProof] against the current root hash, along with an
+/// [AppendOnlyProof] showing that the current root extends an older
+/// root hash which was pinned by the client. The append-only proof is
+/// empty if the pinned epoch is the current epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct LookupWithConsistencyProof {
+    /// The lookup proof at the current epoch
+    pub lookup_proof: LookupProof,
+    /// The append-only proof from the pinned epoch to the current epoch
+    pub consistency_proof: AppendOnlyProof,
+}
+
+/// The domain separator of the labels targeted by the samples of a [SampleAuditProof]
+pub const SAMPLE_AUDIT_DOMAIN: &[u8] = b"akd_sample_audit";
+
+/// Membership proofs for a deterministic random sample of the leaves of the
+/// tree at an epoch, enabling a cheap probabilistic audit by parties which can't
+/// verify full append-only proofs.
+///
+/// Each sample targets a pseudorandom label derived from a public seed (see
+/// [SampleAuditProof::sample_target]), and the leaf sampled is the one reached
+/// by descending the tree along the bits of the target, so that the directory
+/// can't choose which leaves are sampled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct SampleAuditProof {
+    /// The epoch of the tree sampled
+    pub epoch: u64,
+    /// The public seed the samples are derived from
+    #[cfg_attr(
+        feature = "serde_serialization",
+        serde(
+            serialize_with = "bytes_serialize_hex",
+            deserialize_with = "bytes_deserialize_hex"
+        )
+    )]
+    pub seed: Vec<u8>,
+    /// The membership proofs of the leaves sampled, in the order of the samples
+    pub samples: Vec<MembershipProof>,
+}
+
+impl SizeOf for SampleAuditProof {
+    fn size_of(&self) -> usize {
+        core::mem::size_of::<u64>()
+            + self.seed.len()
+            + self.samples.iter().map(|s| s.size_of()).sum::<usize>()
+    }
+}
+
+impl SampleAuditProof {
+    /// The label targeted by the sample at the given index, for the given seed and epoch
+    pub fn sample_target(seed: &[u8], epoch: u64, index: u64) -> NodeLabel {
+        let mut input = Vec::with_capacity(
+            SAMPLE_AUDIT_DOMAIN.len() + seed.len() + 2 * core::mem::size_of::<u64>(),
+        );
+        input.extend_from_slice(SAMPLE_AUDIT_DOMAIN);
+        input.extend_from_slice(&epoch.to_be_bytes());
+        input.extend_from_slice(&index.to_be_bytes());
+        input.extend_from_slice(seed);
+        let hash = crate::hash::hash(&input);
+        let mut label_val = [0u8; 32];
+        label_val.copy_from_slice(&hash[..32]);
+        NodeLabel::new(label_val, 256)
+    }
+}
+
+/// The domain separator of the message signed in a [SignedTreeHead]
+pub const TREE_HEAD_SIGNATURE_DOMAIN: &[u8] = b"akd_signed_tree_head";
+
+/// A summary of the directory at an epoch, signed by the directory. Tree heads
+/// can be gossiped and compared between clients: two validly signed tree heads
+/// with the same epoch but different root hashes show that the directory has
+/// served a split view.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct SignedTreeHead {
+    /// The epoch summarized
+    pub epoch: u64,
+    /// The root hash of the tree at the epoch
+    #[cfg_attr(
+        feature = "serde_serialization",
+        serde(
+            serialize_with = "digest_serialize",
+            deserialize_with = "digest_deserialize"
+        )
+    )]
+    pub root_hash: Digest,
+    /// When the epoch was published, in seconds since the UNIX epoch
+    pub timestamp: u64,
+    /// The directory's ed25519 signature over [SignedTreeHead::signing_message]
+    #[cfg_attr(
+        feature = "serde_serialization",
+        serde(
+            serialize_with = "bytes_serialize_hex",
+            deserialize_with = "bytes_deserialize_hex"
+        )
+    )]
+    pub signature: Vec<u8>,
+}
+
+impl SizeOf for SignedTreeHead {
+    fn size_of(&self) -> usize {
+        core::mem::size_of::<u64>() * 2 + self.root_hash.len() + self.signature.len()
+    }
+}
+
+impl SignedTreeHead {
+    /// The message signed by the directory for the given summary
+    pub fn signing_message(epoch: u64, root_hash: &Digest, timestamp: u64) -> Vec<u8> {
+        let mut message = Vec::with_capacity(
+            TREE_HEAD_SIGNATURE_DOMAIN.len() + root_hash.len() + 2 * core::mem::size_of::<u64>(),
+        );
+        message.extend_from_slice(TREE_HEAD_SIGNATURE_DOMAIN);
+        message.extend_from_slice(&epoch.to_be_bytes());
+        message.extend_from_slice(root_hash);
+        message.extend_from_slice(&timestamp.to_be_bytes());
+        message
+    }
+}
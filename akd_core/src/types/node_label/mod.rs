@@ -15,7 +15,7 @@ use crate::{Direction, SizeOf};
 use crate::utils::serde_helpers::{bytes_deserialize_hex, bytes_serialize_hex};
 #[cfg(feature = "nostd")]
 use alloc::vec::Vec;
-use core::convert::{TryFrom, TryInto};
+use core::convert::TryInto;
 
 #[cfg(test)]
 mod tests;
@@ -132,7 +132,7 @@ impl NodeLabel {
     /// * label.get_bit_at(5) = 0
     /// * label.get_bit_at(6) = 0
     /// * label.get_bit_at(7) = 0
-    fn get_bit_at(&self, index: u32) -> u8 {
+    pub fn get_bit_at(&self, index: u32) -> u8 {
         if index >= self.label_len {
             return 0;
         }
@@ -245,6 +245,9 @@ impl NodeLabel {
         if other.get_prefix(self.get_len()) != *self {
             return Direction::None;
         }
-        Direction::try_from(other.get_bit_at(self.get_len())).unwrap()
+        match other.get_bit_at(self.get_len()) {
+            0u8 => Direction::Left,
+            _ => Direction::Right,
+        }
     }
 }
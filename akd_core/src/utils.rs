@@ -20,6 +20,16 @@ pub fn get_marker_version(version: u64) -> u64 {
     64 - (version.leading_zeros() as u64) - 1
 }
 
+/// The powers of two of the marker versions whose absence a history proof
+/// for a label at `last_version` shows at `current_epoch`: from the next
+/// marker after `last_version` up to and including the current epoch's
+pub fn get_future_marker_powers(
+    last_version: u64,
+    current_epoch: u64,
+) -> core::ops::RangeInclusive<u64> {
+    get_marker_version(last_version) + 1..=get_marker_version(current_epoch)
+}
+
 /// Corresponds to the I2OSP() function from RFC8017, prepending the length of
 /// a byte array to the byte array (so that it is ready for serialization and hashing)
 ///
@@ -37,10 +47,37 @@ pub(crate) fn generate_commitment_from_nonce_client(
     crate::hash::hash(&[i2osp_array(value), i2osp_array(nonce)].concat())
 }
 
-/// Hash a leaf epoch and proof with a given [AkdValue]
-pub(crate) fn hash_leaf_with_value(value: &crate::AkdValue, epoch: u64, proof: &[u8]) -> Digest {
+/// Hash a leaf epoch and proof with a given [AkdValue], and the epoch at which it
+/// expires if it was published with one
+pub(crate) fn hash_leaf_with_value(
+    value: &crate::AkdValue,
+    expiry_epoch: Option<u64>,
+    epoch: u64,
+    proof: &[u8],
+) -> Digest {
     let commitment = crate::utils::generate_commitment_from_nonce_client(value, proof);
-    crate::hash::merge_with_int(commitment, epoch)
+    crate::hash::merge_with_int(bind_expiry(commitment, expiry_epoch), epoch)
+}
+
+/// The commitment to a value published with the given expiry epoch, given the
+/// commitment to the value itself: commitment = H([VALUE_EXPIRY_DOMAIN] ||
+/// commitment || expiry_epoch). The domain separator can't be the start of
+/// `i2osp_array(value)`, so the commitment is distinct from the commitment to any
+/// value without an expiry epoch, which is left as is.
+///
+/// [VALUE_EXPIRY_DOMAIN]: crate::VALUE_EXPIRY_DOMAIN
+pub fn bind_expiry(commitment: Digest, expiry_epoch: Option<u64>) -> Digest {
+    match expiry_epoch {
+        None => commitment,
+        Some(expiry_epoch) => {
+            let domain = crate::VALUE_EXPIRY_DOMAIN;
+            let mut data = [0u8; crate::VALUE_EXPIRY_DOMAIN.len() + crate::hash::DIGEST_BYTES + 8];
+            data[..domain.len()].copy_from_slice(domain);
+            data[domain.len()..domain.len() + commitment.len()].copy_from_slice(&commitment);
+            data[domain.len() + commitment.len()..].copy_from_slice(&expiry_epoch.to_be_bytes());
+            crate::hash::hash(&data)
+        }
+    }
 }
 
 /// Used by the server to produce a commitment proof for an AkdLabel, version, and AkdValue.
@@ -162,4 +199,28 @@ pub mod serde_helpers {
         let buf = <Vec<u8> as serde_bytes::Deserialize>::deserialize(deserializer)?;
         crate::hash::try_parse_digest(&buf).map_err(serde::de::Error::custom)
     }
+
+    /// Serialize an optional digest
+    pub fn optional_digest_serialize<S>(
+        x: &Option<[u8; crate::hash::DIGEST_BYTES]>,
+        s: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde_bytes::Serialize;
+        x.map(|digest| digest.to_vec()).serialize(s)
+    }
+
+    /// Deserialize an optional digest
+    pub fn optional_digest_deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<[u8; crate::hash::DIGEST_BYTES]>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let buf = <Option<Vec<u8>> as serde_bytes::Deserialize>::deserialize(deserializer)?;
+        buf.map(|buf| crate::hash::try_parse_digest(&buf).map_err(serde::de::Error::custom))
+            .transpose()
+    }
 }
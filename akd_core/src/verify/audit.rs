@@ -0,0 +1,277 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Verification of protobuf-encoded audit (append-only) proofs. The proofs are
+//! verified as they're decoded, without building the trees in memory, and the
+//! verification is synchronous, so it doesn't depend on any async runtime and can
+//! run in WebAssembly.
+
+use super::VerificationError;
+use crate::hash::{hash, merge, merge_with_int, Digest};
+use crate::proto::view::{AppendOnlyProofView, NodeIter, SingleAppendOnlyProofView};
+use crate::proto::ConversionError;
+use crate::{Direction, Node, NodeLabel, EMPTY_LABEL, EMPTY_VALUE};
+
+/// Verifies a protobuf-encoded [crate::AppendOnlyProof] (bare or in a versioned
+/// envelope), given the root hashes of all the audited epochs, without
+/// deserializing it. Auditing an epoch takes memory proportional to the depth of
+/// the tree rather than to the size of the proof. This requires the nodes of each
+/// proof to be serialized in left-to-right order, as they are by the directory.
+pub fn audit_verify_serialized(hashes: &[Digest], proof: &[u8]) -> Result<(), VerificationError> {
+    let view = AppendOnlyProofView::new(proof).map_err(malformed_proof)?;
+    let epochs = view.epochs().count();
+    let proofs = view.proofs().count();
+    if epochs + 1 != hashes.len() || proofs != epochs {
+        return Err(VerificationError::AuditProof(format!(
+            "The proof has {} epochs and {} proofs, but should have one less than \
+            the number of hashes ({}) of both",
+            epochs,
+            proofs,
+            hashes.len()
+        )));
+    }
+
+    let mut verifier = StreamingAuditVerifier::new(hashes.to_vec());
+    for (single_proof, epoch) in view.proofs().zip(view.epochs()) {
+        let epoch = epoch.map_err(malformed_proof)?;
+        let single_proof = single_proof.map_err(malformed_proof)?;
+        verifier.feed_view(epoch, &single_proof)?;
+    }
+    Ok(())
+}
+
+/// Verifies a protobuf-encoded [crate::SingleAppendOnlyProof] (bare or in a
+/// versioned envelope) of the transition to `epoch`, without deserializing it.
+/// See [audit_verify_serialized].
+pub fn verify_consecutive_append_only_serialized(
+    proof: &[u8],
+    start_hash: Digest,
+    end_hash: Digest,
+    epoch: u64,
+) -> Result<(), VerificationError> {
+    let view = SingleAppendOnlyProofView::new(proof).map_err(malformed_proof)?;
+    verify_consecutive_append_only_view(&view, start_hash, end_hash, epoch)
+}
+
+/// Verifies an audit one epoch transition at a time, as the protobuf-encoded
+/// [crate::SingleAppendOnlyProof] of each transition is received, so that only the
+/// proof of the current transition is held in memory.
+///
+/// ```ignore
+/// let mut verifier = StreamingAuditVerifier::new(hashes);
+/// for (epoch, proof) in transitions {
+///     verifier.feed(epoch, &proof)?;
+/// }
+/// assert!(verifier.is_complete());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamingAuditVerifier {
+    hashes: Vec<Digest>,
+    verified: usize,
+    last_epoch: Option<u64>,
+}
+
+impl StreamingAuditVerifier {
+    /// Creates a verifier for the given root hashes, one per audited epoch
+    pub fn new(hashes: Vec<Digest>) -> Self {
+        Self {
+            hashes,
+            verified: 0,
+            last_epoch: None,
+        }
+    }
+
+    /// Verifies the proof of the transition from `epoch` to the next epoch, which
+    /// must immediately follow the last verified transition. On failure, the
+    /// verifier is left at the last verified transition.
+    pub fn feed(&mut self, epoch: u64, proof: &[u8]) -> Result<(), VerificationError> {
+        let view = SingleAppendOnlyProofView::new(proof).map_err(malformed_proof)?;
+        self.feed_view(epoch, &view)
+    }
+
+    fn feed_view(
+        &mut self,
+        epoch: u64,
+        proof: &SingleAppendOnlyProofView<'_>,
+    ) -> Result<(), VerificationError> {
+        if self.is_complete() {
+            return Err(VerificationError::AuditProof(format!(
+                "All the {} epoch transitions have already been verified",
+                self.total()
+            )));
+        }
+        if let Some(last_epoch) = self.last_epoch {
+            if epoch != last_epoch + 1 {
+                return Err(VerificationError::AuditProof(format!(
+                    "Expected a proof starting at epoch {}, but got epoch {}",
+                    last_epoch + 1,
+                    epoch
+                )));
+            }
+        }
+        let i = self.verified;
+        verify_consecutive_append_only_view(proof, self.hashes[i], self.hashes[i + 1], epoch + 1)?;
+        self.verified += 1;
+        self.last_epoch = Some(epoch);
+        Ok(())
+    }
+
+    /// The number of epoch transitions verified so far
+    pub fn verified(&self) -> usize {
+        self.verified
+    }
+
+    /// The total number of epoch transitions covered by the audited hashes
+    pub fn total(&self) -> usize {
+        self.hashes.len().saturating_sub(1)
+    }
+
+    /// Whether all the epoch transitions have been verified
+    pub fn is_complete(&self) -> bool {
+        self.verified == self.total()
+    }
+}
+
+fn malformed_proof(err: ConversionError) -> VerificationError {
+    VerificationError::AuditProof(format!("Malformed serialized proof: {}", err))
+}
+
+fn verify_consecutive_append_only_view(
+    proof: &SingleAppendOnlyProofView<'_>,
+    start_hash: Digest,
+    end_hash: Digest,
+    epoch: u64,
+) -> Result<(), VerificationError> {
+    let mut unchanged_nodes = proof.unchanged_nodes();
+    let mut inserted = proof.inserted();
+    let next_node = |nodes: &mut NodeIter<'_>| nodes.next().transpose().map_err(malformed_proof);
+
+    // the unchanged nodes are merged with the inserted leaves in a single pass
+    let mut start = OrderedRootHasher::default();
+    let mut end = OrderedRootHasher::default();
+    let mut next_unchanged = next_node(&mut unchanged_nodes)?;
+    let mut next_inserted = next_node(&mut inserted)?;
+    loop {
+        match (next_unchanged, next_inserted) {
+            (None, None) => break,
+            (Some(unchanged), Some(leaf)) if !is_left_of(leaf.label, unchanged.label) => {
+                start.push(unchanged)?;
+                end.push(unchanged)?;
+                next_unchanged = next_node(&mut unchanged_nodes)?;
+            }
+            (Some(unchanged), None) => {
+                start.push(unchanged)?;
+                end.push(unchanged)?;
+                next_unchanged = next_node(&mut unchanged_nodes)?;
+            }
+            (_, Some(leaf)) => {
+                end.push(Node {
+                    label: leaf.label,
+                    hash: merge_with_int(leaf.hash, epoch),
+                })?;
+                next_inserted = next_node(&mut inserted)?;
+            }
+        }
+    }
+
+    if start.finish() != start_hash || end.finish() != end_hash {
+        return Err(VerificationError::AuditProof(format!(
+            "The proof of the transition to epoch {} doesn't match the root hashes",
+            epoch
+        )));
+    }
+    Ok(())
+}
+
+/// Whether the first label is to the left of the second one in the tree
+fn is_left_of(first: NodeLabel, second: NodeLabel) -> bool {
+    first.get_longest_common_prefix(second).get_dir(second) == Direction::Right
+}
+
+fn merge_digest_with_label_hash(digest: &Digest, label: NodeLabel) -> Digest {
+    merge(&[*digest, label.hash()])
+}
+
+/// Computes the root hash of the tree built from a set of nodes, given the nodes
+/// in left-to-right order. Only the right-most path of the tree is kept in
+/// memory, each subtree being hashed as soon as no further node can be inserted
+/// into it.
+#[derive(Default)]
+struct OrderedRootHasher {
+    /// The labels of the subtrees on the right-most path, along with their hashes
+    /// merged with their labels
+    path: Vec<(NodeLabel, Digest)>,
+    last: Option<NodeLabel>,
+}
+
+impl OrderedRootHasher {
+    fn push(&mut self, node: Node) -> Result<(), VerificationError> {
+        if node.label.label_len == 0 {
+            return Err(VerificationError::AuditProof(
+                "A proof node can't have the root label".to_string(),
+            ));
+        }
+        if let Some(last) = self.last {
+            let prefix = last.get_longest_common_prefix(node.label);
+            if prefix == last || prefix == node.label || !is_left_of(last, node.label) {
+                return Err(VerificationError::AuditProof(format!(
+                    "The proof node {:?} doesn't follow {:?} in left-to-right order",
+                    node.label, last
+                )));
+            }
+            // the subtrees below the prefix shared with the new node are complete
+            while self.path.len() >= 2 {
+                let (left, right) = (
+                    self.path[self.path.len() - 2].0,
+                    self.path[self.path.len() - 1].0,
+                );
+                if left.get_longest_common_prefix(right).label_len <= prefix.label_len {
+                    break;
+                }
+                self.merge_last_two();
+            }
+        }
+        self.last = Some(node.label);
+        self.path.push((
+            node.label,
+            merge_digest_with_label_hash(&node.hash, node.label),
+        ));
+        Ok(())
+    }
+
+    fn merge_last_two(&mut self) {
+        if let (Some((right_label, right_hash)), Some((left_label, left_hash))) =
+            (self.path.pop(), self.path.pop())
+        {
+            let label = left_label.get_longest_common_prefix(right_label);
+            let hash = merge(&[left_hash, right_hash]);
+            self.path
+                .push((label, merge_digest_with_label_hash(&hash, label)));
+        }
+    }
+
+    fn finish(mut self) -> Digest {
+        while self.path.len() >= 2 {
+            self.merge_last_two();
+        }
+        let root = NodeLabel::root();
+        match self.path.pop() {
+            None => merge_digest_with_label_hash(&hash(&EMPTY_VALUE), root),
+            // the topmost subtree is the root itself
+            Some((label, hash)) if label.label_len == 0 => hash,
+            Some((label, digest)) => {
+                let empty_node_hash = merge(&[hash(&EMPTY_VALUE), EMPTY_LABEL.hash()]);
+                let empty = merge_digest_with_label_hash(&empty_node_hash, EMPTY_LABEL);
+                let children = match root.get_dir(label) {
+                    Direction::Left => [digest, empty],
+                    _ => [empty, digest],
+                };
+                merge_digest_with_label_hash(&merge(&children), root)
+            }
+        }
+    }
+}
@@ -9,23 +9,32 @@
 
 use super::VerificationError;
 
-use crate::ecvrf::{Proof, VrfError};
+use crate::ecvrf::{BatchProof, Proof, VrfError};
 use crate::hash::{build_and_hash_layer, merge, Digest};
 use crate::{
     AkdLabel, MembershipProof, NodeLabel, NonMembershipProof, VersionFreshness, ARITY, EMPTY_LABEL,
+    MAX_TREE_DEPTH,
 };
 
 #[cfg(feature = "nostd")]
 use alloc::format;
 #[cfg(feature = "nostd")]
 use alloc::string::ToString;
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
 use core::convert::TryFrom;
 
-/// Verify the membership proof
+/// Verifies the membership proof with respect to the root hash: that the leaf
+/// with the proof's label and hash is in the tree. This only checks the Merkle
+/// path, and neither the VRF proof of the label nor the value commitment. A
+/// proof with more layers, or a label longer, than [MAX_TREE_DEPTH] is rejected.
 pub fn verify_membership(
     root_hash: Digest,
     proof: &MembershipProof,
 ) -> Result<(), VerificationError> {
+    check_depth(proof.layer_proofs.len())?;
+    check_depth(proof.label.label_len as usize)?;
+
     let mut current_hash = merge(&[proof.hash_val, proof.label.hash()]);
 
     for parent in proof.layer_proofs.iter().rev() {
@@ -47,11 +56,18 @@ pub fn verify_membership(
     }
 }
 
-/// Verifies the non-membership proof with respect to the root hash
+/// Verifies the non-membership proof with respect to the root hash: that no
+/// leaf with the proof's label is in the tree. As with [verify_membership],
+/// the VRF proof of the label is not checked.
 pub fn verify_nonmembership(
     root_hash: Digest,
     proof: &NonMembershipProof,
 ) -> Result<(), VerificationError> {
+    check_depth(proof.longest_prefix.label_len as usize)?;
+    for child in proof.longest_prefix_children.iter() {
+        check_depth(child.label.label_len as usize)?;
+    }
+
     let mut verified = true;
 
     let mut lcp_real = proof.longest_prefix_children[0].label;
@@ -100,6 +116,15 @@ pub fn verify_nonmembership(
     Ok(())
 }
 
+/// Rejects a proof whose depth (the number of layers of a path, or the length of
+/// a label in bits) exceeds [MAX_TREE_DEPTH], before any work proportional to it
+fn check_depth(depth: usize) -> Result<(), VerificationError> {
+    if depth > MAX_TREE_DEPTH {
+        return Err(VerificationError::ProofTooDeep(depth));
+    }
+    Ok(())
+}
+
 /// This function is called to verify that a given [NodeLabel] is indeed
 /// the VRF for a given version (fresh or stale) for a [AkdLabel].
 /// Hence, it also takes as input the server's public key.
@@ -127,3 +152,26 @@ pub(crate) fn verify_label(
     }
     Ok(())
 }
+
+/// Verifies a batch VRF proof for a collection of (label, freshness, version) inputs,
+/// returning the [NodeLabel] of each of the inputs, in the same order
+pub(crate) fn verify_batch_labels(
+    vrf_public_key: &[u8],
+    inputs: &[(&AkdLabel, VersionFreshness, u64)],
+    vrf_proof: &[u8],
+) -> Result<Vec<NodeLabel>, VerificationError> {
+    let vrf_pk = crate::ecvrf::VRFPublicKey::try_from(vrf_public_key)?;
+    let hashed_labels = inputs
+        .iter()
+        .map(|(akd_label, freshness, version)| {
+            crate::utils::get_hash_from_label_input(akd_label, *freshness, *version)
+        })
+        .collect::<Vec<_>>();
+
+    let proof = BatchProof::try_from(vrf_proof)?;
+    let outputs = vrf_pk.verify_batch(&proof, &hashed_labels)?;
+    Ok(outputs
+        .iter()
+        .map(|output| NodeLabel::new(output.to_truncated_bytes(), 256))
+        .collect())
+}
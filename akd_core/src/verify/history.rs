@@ -8,11 +8,15 @@
 //! Verification of key history proofs
 
 use super::base::{verify_label, verify_membership, verify_nonmembership};
+use super::telemetry::{observe, NoopObserver, VerificationObserver, VerificationStep};
 use super::VerificationError;
 use crate::utils::hash_leaf_with_value;
 
 use crate::hash::{hash, merge_with_int, Digest};
-use crate::{AkdLabel, HistoryProof, UpdateProof, VerifyResult, VersionFreshness};
+use crate::{
+    AkdLabel, AkdValue, EpochLookupResult, HistoryProof, SizeOf, UpdateProof, ValueDisclosure,
+    VerifyResult, VersionFreshness,
+};
 #[cfg(feature = "nostd")]
 use alloc::format;
 #[cfg(feature = "nostd")]
@@ -37,6 +41,173 @@ impl Default for HistoryVerificationParams {
     }
 }
 
+/// A policy for history proof verification, built up from a set of
+/// requirements on top of the base verification checks. Each requirement that
+/// is violated by a proof is reported as a distinct [HistoryPolicyViolation].
+///
+/// ```
+/// use akd_core::verify::history::HistoryVerificationPolicy;
+/// use akd_core::ValueDisclosure;
+///
+/// let policy = HistoryVerificationPolicy::new()
+///     .require_strict_epoch_monotonicity()
+///     .max_update_gap(1000)
+///     .max_age(10)
+///     .allow_tombstones(true)
+///     .require_opened(ValueDisclosure::MostRecent(1));
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HistoryVerificationPolicy {
+    allow_tombstones: bool,
+    strict_epoch_monotonicity: bool,
+    max_update_gap: Option<u64>,
+    max_age: Option<u64>,
+    disclosure: ValueDisclosure,
+}
+
+impl HistoryVerificationPolicy {
+    /// A policy with no requirements beyond the base verification checks,
+    /// which does not accept tombstoned values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether tombstoned values are accepted, in which case their hash is
+    /// taken at face value rather than checked against the leaf node hash
+    pub fn allow_tombstones(mut self, allow: bool) -> Self {
+        self.allow_tombstones = allow;
+        self
+    }
+
+    /// Require that consecutive updates occurred in strictly decreasing
+    /// epochs, i.e. that no two versions were published in the same epoch
+    pub fn require_strict_epoch_monotonicity(mut self) -> Self {
+        self.strict_epoch_monotonicity = true;
+        self
+    }
+
+    /// Require that no more than `epochs` epochs elapsed between any two
+    /// consecutive updates
+    pub fn max_update_gap(mut self, epochs: u64) -> Self {
+        self.max_update_gap = Some(epochs);
+        self
+    }
+
+    /// Require that the most recent update occurred no more than `epochs`
+    /// epochs before the epoch the proof was generated at
+    pub fn max_age(mut self, epochs: u64) -> Self {
+        self.max_age = Some(epochs);
+        self
+    }
+
+    /// Require that the values selected by `disclosure` are opened, accepting
+    /// redacted values for the other updates (see [UpdateProof::is_redacted]).
+    /// By default, all the values must be opened.
+    pub fn require_opened(mut self, disclosure: ValueDisclosure) -> Self {
+        self.disclosure = disclosure;
+        self
+    }
+}
+
+impl From<HistoryVerificationParams> for HistoryVerificationPolicy {
+    fn from(params: HistoryVerificationParams) -> Self {
+        match params {
+            HistoryVerificationParams::Default => Self::new(),
+            HistoryVerificationParams::AllowMissingValues => Self::new().allow_tombstones(true),
+        }
+    }
+}
+
+/// A requirement of a [HistoryVerificationPolicy] which a history proof
+/// failed to satisfy
+#[derive(Debug, Eq, PartialEq)]
+pub enum HistoryPolicyViolation {
+    /// A version was published in the same epoch as the version after it
+    NonMonotonicEpoch {
+        /// The version in violation
+        version: u64,
+        /// The epoch both versions were published in
+        epoch: u64,
+    },
+    /// Too many epochs elapsed between two consecutive versions
+    UpdateGapExceeded {
+        /// The earlier of the two versions
+        version: u64,
+        /// The epoch of the earlier version
+        epoch: u64,
+        /// The epoch of the next version
+        next_epoch: u64,
+        /// The maximum allowed gap
+        max_gap: u64,
+    },
+    /// A tombstoned value was encountered, but tombstones are not accepted.
+    /// Under the default policy, a tombstone is instead reported as a value
+    /// which doesn't match its existence proof, as it was before policies.
+    TombstoneEncountered {
+        /// The tombstoned version
+        version: u64,
+        /// The epoch of the tombstoned version
+        epoch: u64,
+    },
+    /// The most recent update is older than allowed
+    StaleHistory {
+        /// The epoch of the most recent update
+        latest_update_epoch: u64,
+        /// The epoch the proof was generated at
+        current_epoch: u64,
+        /// The maximum allowed age
+        max_age: u64,
+    },
+    /// The value of an update which must be opened is redacted
+    RedactedValue {
+        /// The redacted version
+        version: u64,
+        /// The epoch of the redacted version
+        epoch: u64,
+    },
+}
+
+impl core::fmt::Display for HistoryPolicyViolation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NonMonotonicEpoch { version, epoch } => write!(
+                f,
+                "Version {} was published in the same epoch ({}) as the next version",
+                version, epoch
+            ),
+            Self::UpdateGapExceeded {
+                version,
+                epoch,
+                next_epoch,
+                max_gap,
+            } => write!(
+                f,
+                "Version {} (epoch {}) and the next version (epoch {}) are more than {} epochs apart",
+                version, epoch, next_epoch, max_gap
+            ),
+            Self::TombstoneEncountered { version, epoch } => write!(
+                f,
+                "Version {} at epoch {} is tombstoned, but tombstones are not accepted",
+                version, epoch
+            ),
+            Self::StaleHistory {
+                latest_update_epoch,
+                current_epoch,
+                max_age,
+            } => write!(
+                f,
+                "The latest update (epoch {}) is more than {} epochs older than the current epoch {}",
+                latest_update_epoch, max_age, current_epoch
+            ),
+            Self::RedactedValue { version, epoch } => write!(
+                f,
+                "The value of version {} at epoch {} is redacted, but must be opened",
+                version, epoch
+            ),
+        }
+    }
+}
+
 /// Verifies a key history proof, given the corresponding sequence of hashes.
 /// Returns a vector of whether the validity of a hash could be verified.
 /// When false, the value <=> hash validity at the position could not be
@@ -49,6 +220,52 @@ pub fn key_history_verify(
     proof: HistoryProof,
     params: HistoryVerificationParams,
 ) -> Result<Vec<VerifyResult>, VerificationError> {
+    key_history_verify_with_policy(
+        vrf_public_key,
+        root_hash,
+        current_epoch,
+        akd_key,
+        proof,
+        params.into(),
+    )
+}
+
+/// Verifies a key history proof as in [key_history_verify], additionally
+/// enforcing the requirements of the given [HistoryVerificationPolicy].
+/// Requirement violations are returned as [VerificationError::HistoryPolicy].
+/// The updates whose values are redacted, as allowed by the policy, are
+/// returned with an empty value.
+pub fn key_history_verify_with_policy(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    current_epoch: u64,
+    akd_key: AkdLabel,
+    proof: HistoryProof,
+    policy: HistoryVerificationPolicy,
+) -> Result<Vec<VerifyResult>, VerificationError> {
+    key_history_verify_with_observer(
+        vrf_public_key,
+        root_hash,
+        current_epoch,
+        akd_key,
+        proof,
+        policy,
+        &mut NoopObserver,
+    )
+}
+
+/// Verifies a key history proof as in [key_history_verify_with_policy], reporting
+/// the size of the proof and the steps of the verification to the given observer
+pub fn key_history_verify_with_observer(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    current_epoch: u64,
+    akd_key: AkdLabel,
+    proof: HistoryProof,
+    policy: HistoryVerificationPolicy,
+    observer: &mut dyn VerificationObserver,
+) -> Result<Vec<VerifyResult>, VerificationError> {
+    observer.proof_size(proof.size_of());
     let mut results = Vec::new();
     let mut last_version = 0;
 
@@ -63,21 +280,25 @@ pub fn key_history_verify(
     }
 
     // Check that the sent proofs are for a contiguous sequence of decreasing versions
-    for count in 0..num_proofs {
-        if count > 0 {
-            // Make sure this proof is for a version 1 more than the previous one.
-            if proof.update_proofs[count].version + 1 != proof.update_proofs[count - 1].version {
-                return Err(VerificationError::HistoryProof(format!("Why did you give me consecutive update proofs without version numbers decrementing by 1? Version {} = {}; version {} = {}",
-                count, proof.update_proofs[count].version,
-                count-1, proof.update_proofs[count-1].version
-                )));
+    observe(observer, VerificationStep::HistoryChain, || {
+        for count in 0..num_proofs {
+            if count > 0 {
+                // Make sure this proof is for a version 1 more than the previous one.
+                if proof.update_proofs[count].version + 1 != proof.update_proofs[count - 1].version
+                {
+                    return Err(VerificationError::HistoryProof(format!("Why did you give me consecutive update proofs without version numbers decrementing by 1? Version {} = {}; version {} = {}",
+                    count, proof.update_proofs[count].version,
+                    count-1, proof.update_proofs[count-1].version
+                    )));
+                }
             }
         }
-    }
+        Ok(())
+    })?;
 
     // Verify all individual update proofs
     let mut maybe_previous_update_epoch = None;
-    for update_proof in proof.update_proofs.into_iter() {
+    for (position, update_proof) in proof.update_proofs.into_iter().enumerate() {
         // Get the highest version sent among the update proofs.
         last_version = if update_proof.version > last_version {
             update_proof.version
@@ -85,61 +306,158 @@ pub fn key_history_verify(
             last_version
         };
 
-        if let Some(previous_update_epoch) = maybe_previous_update_epoch {
-            // Make sure this this epoch is more than the previous epoch you checked
-            if update_proof.epoch > previous_update_epoch {
-                return Err(VerificationError::HistoryProof(format!(
-                    "Why are your versions decreasing in updates and epochs not?!,
-                    epoch = {}, previous epoch = {}",
-                    update_proof.epoch, previous_update_epoch
-                )));
+        observe(observer, VerificationStep::HistoryChain, || {
+            if let Some(previous_update_epoch) = maybe_previous_update_epoch {
+                // Make sure this this epoch is more than the previous epoch you checked
+                if update_proof.epoch > previous_update_epoch {
+                    return Err(VerificationError::HistoryProof(format!(
+                        "Why are your versions decreasing in updates and epochs not?!,
+                        epoch = {}, previous epoch = {}",
+                        update_proof.epoch, previous_update_epoch
+                    )));
+                }
+                if policy.strict_epoch_monotonicity && update_proof.epoch == previous_update_epoch {
+                    return Err(VerificationError::HistoryPolicy(
+                        HistoryPolicyViolation::NonMonotonicEpoch {
+                            version: update_proof.version,
+                            epoch: update_proof.epoch,
+                        },
+                    ));
+                }
+                if let Some(max_gap) = policy.max_update_gap {
+                    if previous_update_epoch - update_proof.epoch > max_gap {
+                        return Err(VerificationError::HistoryPolicy(
+                            HistoryPolicyViolation::UpdateGapExceeded {
+                                version: update_proof.version,
+                                epoch: update_proof.epoch,
+                                next_epoch: previous_update_epoch,
+                                max_gap,
+                            },
+                        ));
+                    }
+                }
+            } else if let Some(max_age) = policy.max_age {
+                // The first proof is for the most recent update
+                if current_epoch.saturating_sub(update_proof.epoch) > max_age {
+                    return Err(VerificationError::HistoryPolicy(
+                        HistoryPolicyViolation::StaleHistory {
+                            latest_update_epoch: update_proof.epoch,
+                            current_epoch,
+                            max_age,
+                        },
+                    ));
+                }
             }
-        }
-        maybe_previous_update_epoch = Some(update_proof.epoch);
-        let result =
-            verify_single_update_proof(root_hash, vrf_public_key, update_proof, &akd_key, params)?;
+            maybe_previous_update_epoch = Some(update_proof.epoch);
+            if update_proof.is_redacted()
+                && policy.disclosure.is_opened(position, update_proof.epoch)
+            {
+                return Err(VerificationError::HistoryPolicy(
+                    HistoryPolicyViolation::RedactedValue {
+                        version: update_proof.version,
+                        epoch: update_proof.epoch,
+                    },
+                ));
+            }
+            Ok(())
+        })?;
+        let result = verify_single_update_proof(
+            root_hash,
+            vrf_public_key,
+            update_proof,
+            &akd_key,
+            &policy,
+            observer,
+        )?;
         results.push(result);
     }
 
     // Get the least and greatest marker entries for the current version
-    let next_marker = crate::utils::get_marker_version(last_version) + 1;
-    let final_marker = crate::utils::get_marker_version(current_epoch);
+    let future_markers = crate::utils::get_future_marker_powers(last_version, current_epoch);
+    let next_marker = *future_markers.start();
 
     // ***** Future checks below ***************************
+    // Make sure a proof was sent for each of the future entries and markers checked below
+    let next_marker_version = 1u64.checked_shl(next_marker as u32).ok_or_else(|| {
+        VerificationError::HistoryProof(format!(
+            "Version {} of user {:?} has no next marker",
+            last_version, akd_key
+        ))
+    })?;
+    let num_next_few = next_marker_version - (last_version + 1);
+    if proof.non_existence_of_next_few.len() as u64 != num_next_few
+        || proof.next_few_vrf_proofs.len() as u64 != num_next_few
+    {
+        return Err(VerificationError::HistoryProof(format!(
+            "Expected {} next few proofs of user {:?} at epoch {:?}, got {} non-existence and {} VRF proofs",
+            num_next_few,
+            akd_key,
+            current_epoch,
+            proof.non_existence_of_next_few.len(),
+            proof.next_few_vrf_proofs.len()
+        )));
+    }
+    let num_future_markers = future_markers.clone().count() as u64;
+    if proof.non_existence_of_future_markers.len() as u64 != num_future_markers
+        || proof.future_marker_vrf_proofs.len() as u64 != num_future_markers
+    {
+        return Err(VerificationError::HistoryProof(format!(
+            "Expected {} future marker proofs of user {:?} at epoch {:?}, got {} non-existence and {} VRF proofs",
+            num_future_markers,
+            akd_key,
+            current_epoch,
+            proof.non_existence_of_future_markers.len(),
+            proof.future_marker_vrf_proofs.len()
+        )));
+    }
+
     // Verify the VRFs and non-membership of future entries, up to the next marker
-    for (i, ver) in (last_version + 1..(1 << next_marker)).enumerate() {
+    for (i, ver) in (last_version + 1..next_marker_version).enumerate() {
         let pf = &proof.non_existence_of_next_few[i];
         let vrf_pf = &proof.next_few_vrf_proofs[i];
         let ver_label = pf.label;
-        verify_label(
-            vrf_public_key,
-            &akd_key,
-            VersionFreshness::Fresh,
-            ver,
-            vrf_pf,
-            ver_label,
-        )?;
-        if verify_nonmembership(root_hash, pf).is_err() {
+        observe(observer, VerificationStep::Vrf, || {
+            verify_label(
+                vrf_public_key,
+                &akd_key,
+                VersionFreshness::Fresh,
+                ver,
+                vrf_pf,
+                ver_label,
+            )
+        })?;
+        if observe(observer, VerificationStep::Membership, || {
+            verify_nonmembership(root_hash, pf)
+        })
+        .is_err()
+        {
             return Err(VerificationError::HistoryProof(format!("Non-existence of next few proof of user {:?}'s version {:?} at epoch {:?} does not verify",
             &akd_key, ver, current_epoch)));
         }
     }
 
-    // Verify the VRFs and non-membership proofs for future markers
-    for (i, pow) in (next_marker + 1..final_marker).enumerate() {
+    // Verify the VRFs and non-membership proofs for future markers, starting with the
+    // next marker itself (which isn't covered by the next few entries above)
+    for (i, pow) in future_markers.enumerate() {
         let ver = 1 << pow;
         let pf = &proof.non_existence_of_future_markers[i];
         let vrf_pf = &proof.future_marker_vrf_proofs[i];
         let ver_label = pf.label;
-        verify_label(
-            vrf_public_key,
-            &akd_key,
-            VersionFreshness::Fresh,
-            ver,
-            vrf_pf,
-            ver_label,
-        )?;
-        if verify_nonmembership(root_hash, pf).is_err() {
+        observe(observer, VerificationStep::Vrf, || {
+            verify_label(
+                vrf_public_key,
+                &akd_key,
+                VersionFreshness::Fresh,
+                ver,
+                vrf_pf,
+                ver_label,
+            )
+        })?;
+        if observe(observer, VerificationStep::Membership, || {
+            verify_nonmembership(root_hash, pf)
+        })
+        .is_err()
+        {
             return Err(VerificationError::HistoryProof(format!("Non-existence of future marker proof of user {:?}'s version {:?} at epoch {:?} does not verify",
             akd_key, ver, current_epoch)));
         }
@@ -148,48 +466,198 @@ pub fn key_history_verify(
     Ok(results)
 }
 
+/// Verifies the proof returned for a lookup of a label at several epochs, which
+/// is a [HistoryProof] starting from the update in effect at the earliest epoch.
+/// Returns the state of the label at each of the given epochs (in increasing
+/// order), and whether it changed since the previous one.
+pub fn lookup_at_epochs_verify(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    current_epoch: u64,
+    akd_key: AkdLabel,
+    epochs: &[u64],
+    proof: HistoryProof,
+    params: HistoryVerificationParams,
+) -> Result<Vec<EpochLookupResult>, VerificationError> {
+    let mut epochs = epochs.to_vec();
+    epochs.sort_unstable();
+    epochs.dedup();
+    match epochs.last() {
+        None => {
+            return Err(VerificationError::HistoryProof(
+                "No epochs to look up were given".to_string(),
+            ))
+        }
+        Some(&last) if last > current_epoch => {
+            return Err(VerificationError::HistoryProof(format!(
+                "Epoch {} is after the epoch of the proof {}",
+                last, current_epoch
+            )))
+        }
+        _ => {}
+    }
+
+    // ordered from the most recent update to the oldest
+    let updates = key_history_verify(
+        vrf_public_key,
+        root_hash,
+        current_epoch,
+        akd_key,
+        proof,
+        params,
+    )?;
+    let oldest = &updates[updates.len() - 1];
+
+    let mut results = Vec::new();
+    let mut previous_version = None;
+    for epoch in epochs {
+        let state = updates.iter().find(|update| update.epoch <= epoch).cloned();
+        if state.is_none() && oldest.version != 1 {
+            // the proof starts after the epoch, and doesn't prove that there was
+            // no earlier update
+            return Err(VerificationError::HistoryProof(format!(
+                "The oldest update in the proof (version {} at epoch {}) is after epoch {}",
+                oldest.version, oldest.epoch, epoch
+            )));
+        }
+        let version = state.as_ref().map(|state| state.version);
+        results.push(EpochLookupResult {
+            epoch,
+            changed: matches!(previous_version, Some(previous) if previous != version),
+            state,
+        });
+        previous_version = Some(version);
+    }
+    Ok(results)
+}
+
+/// Verifies the proof returned for the number of updates of a label between two
+/// epochs (see `Directory::update_count`), which is a [HistoryProof] starting from
+/// the update in effect before `start_epoch`. The values of the updates must all
+/// be redacted. Returns the number of updates published in the epochs from
+/// `start_epoch` to `end_epoch` (inclusive).
+pub fn update_count_verify(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    current_epoch: u64,
+    akd_key: AkdLabel,
+    start_epoch: u64,
+    end_epoch: u64,
+    proof: HistoryProof,
+) -> Result<u64, VerificationError> {
+    if start_epoch > end_epoch || end_epoch > current_epoch {
+        return Err(VerificationError::HistoryProof(format!(
+            "Invalid epoch range {} to {} for a proof at epoch {}",
+            start_epoch, end_epoch, current_epoch
+        )));
+    }
+
+    // none of the values are opened, so that an update can't pass for a tombstone
+    // by leaving out its commitment
+    if let Some(update) = proof
+        .update_proofs
+        .iter()
+        .find(|update| !update.is_redacted())
+    {
+        return Err(VerificationError::HistoryProof(format!(
+            "The value of version {} at epoch {} is not redacted",
+            update.version, update.epoch
+        )));
+    }
+
+    // ordered from the most recent update to the oldest
+    let updates = key_history_verify_with_policy(
+        vrf_public_key,
+        root_hash,
+        current_epoch,
+        akd_key,
+        proof,
+        HistoryVerificationPolicy::new()
+            .allow_tombstones(true)
+            .require_opened(ValueDisclosure::None),
+    )?;
+    let oldest = &updates[updates.len() - 1];
+    if oldest.version != 1 && oldest.epoch >= start_epoch {
+        // an update in the range may have been left out before the oldest one
+        return Err(VerificationError::HistoryProof(format!(
+            "The oldest update in the proof (version {} at epoch {}) is not before epoch {}",
+            oldest.version, oldest.epoch, start_epoch
+        )));
+    }
+    Ok(updates
+        .iter()
+        .filter(|update| update.epoch >= start_epoch && update.epoch <= end_epoch)
+        .count() as u64)
+}
+
 /// Verifies a single update proof
 fn verify_single_update_proof(
     root_hash: Digest,
     vrf_public_key: &[u8],
     proof: UpdateProof,
     uname: &AkdLabel,
-    params: HistoryVerificationParams,
+    policy: &HistoryVerificationPolicy,
+    observer: &mut dyn VerificationObserver,
 ) -> Result<VerifyResult, VerificationError> {
     let epoch = proof.epoch;
     let version = proof.version;
     let existence_at_ep = &proof.existence_at_ep;
 
-    let value_hash_valid = match (params, &proof.plaintext_value) {
-        (HistoryVerificationParams::AllowMissingValues, bytes) if bytes.0 == crate::TOMBSTONE => {
-            // A tombstone was encountered, we need to just take the
-            // hash of the value at "face value" since we don't have
-            // the real value available
-            true
-        }
-        (_, bytes) => {
-            // No tombstone so hash the value found, and compare to the existence proof's value
-            hash_leaf_with_value(bytes, proof.epoch, &proof.commitment_proof)
-                == existence_at_ep.hash_val
+    observe(observer, VerificationStep::HistoryChain, || {
+        let value_hash_valid = if let Some(commitment) = proof.value_commitment {
+            // The value is redacted: it isn't opened, only its commitment is checked
+            // against the existence proof
+            merge_with_int(commitment, epoch) == existence_at_ep.hash_val
+        } else {
+            match (policy.allow_tombstones, &proof.plaintext_value) {
+                (true, bytes) if bytes.0 == crate::TOMBSTONE => {
+                    // A tombstone was encountered, we need to just take the
+                    // hash of the value at "face value" since we don't have
+                    // the real value available
+                    true
+                }
+                (false, bytes)
+                    if bytes.0 == crate::TOMBSTONE
+                        && *policy != HistoryVerificationPolicy::default() =>
+                {
+                    return Err(VerificationError::HistoryPolicy(
+                        HistoryPolicyViolation::TombstoneEncountered { version, epoch },
+                    ));
+                }
+                (_, bytes) => {
+                    // No tombstone so hash the value found, and compare to the existence proof's value
+                    hash_leaf_with_value(
+                        bytes,
+                        proof.expiry_epoch,
+                        proof.epoch,
+                        &proof.commitment_proof,
+                    ) == existence_at_ep.hash_val
+                }
+            }
+        };
+        if !value_hash_valid {
+            return Err(VerificationError::HistoryProof(
+                "Hash of plaintext value did not match existence proof hash".to_string(),
+            ));
         }
-    };
-    if !value_hash_valid {
-        return Err(VerificationError::HistoryProof(
-            "Hash of plaintext value did not match existence proof hash".to_string(),
-        ));
-    }
+        Ok(())
+    })?;
 
     // ***** PART 1 ***************************
     // Verify the VRF and membership proof for the corresponding label for the version being updated to.
-    verify_label(
-        vrf_public_key,
-        uname,
-        VersionFreshness::Fresh,
-        version,
-        &proof.existence_vrf_proof,
-        existence_at_ep.label,
-    )?;
-    verify_membership(root_hash, existence_at_ep)?;
+    observe(observer, VerificationStep::Vrf, || {
+        verify_label(
+            vrf_public_key,
+            uname,
+            VersionFreshness::Fresh,
+            version,
+            &proof.existence_vrf_proof,
+            existence_at_ep.label,
+        )
+    })?;
+    observe(observer, VerificationStep::Membership, || {
+        verify_membership(root_hash, existence_at_ep)
+    })?;
 
     // ***** PART 2 ***************************
     // Edge case here! We need to account for version = 1 where the previous version won't have a proof.
@@ -214,7 +682,9 @@ fn verify_single_update_proof(
                 epoch
             )));
         }
-        verify_membership(root_hash, previous_version_stale_at_ep)?;
+        observe(observer, VerificationStep::Membership, || {
+            verify_membership(root_hash, previous_version_stale_at_ep)
+        })?;
 
         // Verify the VRF for the stale label corresponding to the previous version for this username
         let previous_version_vrf_proof =
@@ -226,19 +696,27 @@ fn verify_single_update_proof(
                     epoch
                 ))
             })?;
-        verify_label(
-            vrf_public_key,
-            uname,
-            VersionFreshness::Stale,
-            version - 1,
-            previous_version_vrf_proof,
-            previous_version_stale_at_ep.label,
-        )?;
+        observe(observer, VerificationStep::Vrf, || {
+            verify_label(
+                vrf_public_key,
+                uname,
+                VersionFreshness::Stale,
+                version - 1,
+                previous_version_vrf_proof,
+                previous_version_stale_at_ep.label,
+            )
+        })?;
     }
 
+    let (value, expiry_epoch) = if proof.is_redacted() {
+        (AkdValue(Vec::new()), None)
+    } else {
+        (proof.plaintext_value, proof.expiry_epoch)
+    };
     Ok(VerifyResult {
         epoch: proof.epoch,
         version: proof.version,
-        value: proof.plaintext_value,
+        value,
+        expiry_epoch,
     })
 }
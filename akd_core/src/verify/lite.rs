@@ -0,0 +1,449 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Lite verification of lookup proofs, for constrained devices.
+//!
+//! The proofs are held in fixed-size structures, whose capacity is bounded by the
+//! constants of this module and by the maximum tree depth `D` (a const generic,
+//! defaulting to [DEFAULT_MAX_DEPTH]), and every intermediate value is built in a
+//! fixed-size stack buffer. Apart from the messages of failed VRF checks, the
+//! verification never touches the heap.
+//!
+//! Within its limits, the verification accepts exactly the proofs accepted by
+//! [super::lookup_verify], which is checked against a shared set of test vectors.
+//! The limits are the acceptance differences: proofs for a label longer than
+//! [MAX_LABEL_BYTES], with a value longer than [MAX_VALUE_BYTES] or a commitment
+//! nonce longer than [MAX_NONCE_BYTES], or with a membership proof of more than `D`
+//! layers are rejected with [LiteVerificationError::TooLarge], while
+//! [super::lookup_verify] accepts them up to [crate::MAX_TREE_DEPTH] layers.
+//!
+//! A [LiteLookupProof] is (roughly) `3 * 110 * D` bytes, i.e. about 21KB for the
+//! default depth. Lowering `D` shrinks the proof, at the cost of rejecting trees
+//! with deeper leaves (which are exponentially unlikely with VRF labels).
+
+use crate::ecvrf::{Output, Proof, VRFPublicKey};
+use crate::hash::{hash, merge, merge_with_int, Digest};
+use crate::utils::bind_expiry;
+use crate::{
+    Direction, LookupProof, MembershipProof, Node, NodeLabel, NonMembershipProof, VersionFreshness,
+    ARITY, EMPTY_LABEL,
+};
+
+use core::convert::TryFrom;
+
+/// The default maximum number of layers of a membership proof
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+/// The maximum length of a label, in bytes
+pub const MAX_LABEL_BYTES: usize = 256;
+/// The maximum length of a value, in bytes
+pub const MAX_VALUE_BYTES: usize = 256;
+/// The maximum length of a commitment nonce, in bytes
+pub const MAX_NONCE_BYTES: usize = 64;
+/// The length of a VRF proof, in bytes
+pub const VRF_PROOF_BYTES: usize = 80;
+
+const NODE_LABEL_BYTES: usize = 4 + 32;
+
+/// The reasons for a lite verification to fail
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LiteVerificationError {
+    /// A proof element doesn't fit in the fixed-size structures
+    TooLarge,
+    /// A proof element has an invalid length
+    Malformed,
+    /// A VRF proof failed to verify, or doesn't correspond to the proven label
+    Vrf,
+    /// The commitment to the value doesn't match the existence proof
+    Commitment,
+    /// A membership proof failed to verify
+    MembershipProof,
+    /// A non-membership proof failed to verify
+    NonMembershipProof,
+}
+
+impl core::fmt::Display for LiteVerificationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let code = match &self {
+            LiteVerificationError::TooLarge => "(Too large) - proof exceeds the lite limits",
+            LiteVerificationError::Malformed => "(Malformed) - invalid proof element length",
+            LiteVerificationError::Vrf => "(VRF) - VRF proof did not verify",
+            LiteVerificationError::Commitment => {
+                "(Commitment) - value commitment did not match existence proof"
+            }
+            LiteVerificationError::MembershipProof => "(Membership proof) - did not verify",
+            LiteVerificationError::NonMembershipProof => "(Non-membership proof) - did not verify",
+        };
+        write!(f, "Lite verification error {}", code)
+    }
+}
+
+/// A byte string of at most `N` bytes
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LiteBytes<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> LiteBytes<N> {
+    /// Copies the given bytes, failing if there are more than `N`
+    pub fn new(data: &[u8]) -> Result<Self, LiteVerificationError> {
+        if data.len() > N {
+            return Err(LiteVerificationError::TooLarge);
+        }
+        let mut bytes = [0u8; N];
+        bytes[..data.len()].copy_from_slice(data);
+        Ok(Self {
+            bytes,
+            len: data.len(),
+        })
+    }
+
+    /// The contained bytes
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// A layer of a [LiteMembershipProof], see [crate::LayerProof]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LiteLayerProof {
+    /// The parent's label
+    pub label: NodeLabel,
+    /// Siblings of the parent
+    pub siblings: [Node; ARITY - 1],
+    /// The direction
+    pub direction: Direction,
+}
+
+const EMPTY_LAYER: LiteLayerProof = LiteLayerProof {
+    label: EMPTY_LABEL,
+    siblings: [Node {
+        label: EMPTY_LABEL,
+        hash: crate::hash::EMPTY_DIGEST,
+    }; ARITY - 1],
+    direction: Direction::None,
+};
+
+/// A [MembershipProof] of at most `D` layers
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LiteMembershipProof<const D: usize = DEFAULT_MAX_DEPTH> {
+    /// The node label
+    pub label: NodeLabel,
+    /// The hash of the value
+    pub hash_val: Digest,
+    layers: [LiteLayerProof; D],
+    num_layers: usize,
+}
+
+impl<const D: usize> LiteMembershipProof<D> {
+    /// Builds a proof from its layers, ordered from the root down
+    pub fn new(
+        label: NodeLabel,
+        hash_val: Digest,
+        layer_proofs: &[LiteLayerProof],
+    ) -> Result<Self, LiteVerificationError> {
+        if layer_proofs.len() > D {
+            return Err(LiteVerificationError::TooLarge);
+        }
+        let mut layers = [EMPTY_LAYER; D];
+        layers[..layer_proofs.len()].copy_from_slice(layer_proofs);
+        Ok(Self {
+            label,
+            hash_val,
+            layers,
+            num_layers: layer_proofs.len(),
+        })
+    }
+
+    /// The layers of the proof, ordered from the root down
+    pub fn layer_proofs(&self) -> &[LiteLayerProof] {
+        &self.layers[..self.num_layers]
+    }
+}
+
+impl<const D: usize> TryFrom<&MembershipProof> for LiteMembershipProof<D> {
+    type Error = LiteVerificationError;
+
+    fn try_from(proof: &MembershipProof) -> Result<Self, Self::Error> {
+        if proof.layer_proofs.len() > D {
+            return Err(LiteVerificationError::TooLarge);
+        }
+        let mut layers = [EMPTY_LAYER; D];
+        for (layer, full) in layers.iter_mut().zip(proof.layer_proofs.iter()) {
+            *layer = LiteLayerProof {
+                label: full.label,
+                siblings: full.siblings,
+                direction: full.direction,
+            };
+        }
+        Ok(Self {
+            label: proof.label,
+            hash_val: proof.hash_val,
+            layers,
+            num_layers: proof.layer_proofs.len(),
+        })
+    }
+}
+
+/// A [NonMembershipProof] whose membership proof has at most `D` layers
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LiteNonMembershipProof<const D: usize = DEFAULT_MAX_DEPTH> {
+    /// The label in question
+    pub label: NodeLabel,
+    /// The longest prefix in the tree
+    pub longest_prefix: NodeLabel,
+    /// The children of the longest prefix
+    pub longest_prefix_children: [Node; ARITY],
+    /// The membership proof of the longest prefix
+    pub longest_prefix_membership_proof: LiteMembershipProof<D>,
+}
+
+impl<const D: usize> TryFrom<&NonMembershipProof> for LiteNonMembershipProof<D> {
+    type Error = LiteVerificationError;
+
+    fn try_from(proof: &NonMembershipProof) -> Result<Self, Self::Error> {
+        Ok(Self {
+            label: proof.label,
+            longest_prefix: proof.longest_prefix,
+            longest_prefix_children: proof.longest_prefix_children,
+            longest_prefix_membership_proof: LiteMembershipProof::try_from(
+                &proof.longest_prefix_membership_proof,
+            )?,
+        })
+    }
+}
+
+/// A [LookupProof] held in fixed-size structures
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LiteLookupProof<const D: usize = DEFAULT_MAX_DEPTH> {
+    /// The epoch of this record
+    pub epoch: u64,
+    /// The plaintext value in question
+    pub plaintext_value: LiteBytes<MAX_VALUE_BYTES>,
+    /// The version of the record
+    pub version: u64,
+    /// VRF proof for the label corresponding to this version
+    pub existence_vrf_proof: [u8; VRF_PROOF_BYTES],
+    /// Record existence proof
+    pub existence_proof: LiteMembershipProof<D>,
+    /// VRF proof for the marker preceding (less than or equal to) this version
+    pub marker_vrf_proof: [u8; VRF_PROOF_BYTES],
+    /// Existence at specific marker
+    pub marker_proof: LiteMembershipProof<D>,
+    /// VRF proof for the label corresponding to this version being stale
+    pub freshness_vrf_proof: [u8; VRF_PROOF_BYTES],
+    /// Freshness proof (non member at previous epoch)
+    pub freshness_proof: LiteNonMembershipProof<D>,
+    /// Proof for commitment value derived from raw AkdLabel and AkdValue
+    pub commitment_proof: LiteBytes<MAX_NONCE_BYTES>,
+    /// The epoch at which the value expires, if it was published with one
+    pub expiry_epoch: Option<u64>,
+}
+
+fn vrf_proof_bytes(bytes: &[u8]) -> Result<[u8; VRF_PROOF_BYTES], LiteVerificationError> {
+    <[u8; VRF_PROOF_BYTES]>::try_from(bytes).map_err(|_| LiteVerificationError::Malformed)
+}
+
+impl<const D: usize> TryFrom<&LookupProof> for LiteLookupProof<D> {
+    type Error = LiteVerificationError;
+
+    fn try_from(proof: &LookupProof) -> Result<Self, Self::Error> {
+        Ok(Self {
+            epoch: proof.epoch,
+            plaintext_value: LiteBytes::new(&proof.plaintext_value)?,
+            version: proof.version,
+            existence_vrf_proof: vrf_proof_bytes(&proof.existence_vrf_proof)?,
+            existence_proof: LiteMembershipProof::try_from(&proof.existence_proof)?,
+            marker_vrf_proof: vrf_proof_bytes(&proof.marker_vrf_proof)?,
+            marker_proof: LiteMembershipProof::try_from(&proof.marker_proof)?,
+            freshness_vrf_proof: vrf_proof_bytes(&proof.freshness_vrf_proof)?,
+            freshness_proof: LiteNonMembershipProof::try_from(&proof.freshness_proof)?,
+            commitment_proof: LiteBytes::new(&proof.commitment_proof)?,
+            expiry_epoch: proof.expiry_epoch,
+        })
+    }
+}
+
+/// The result of a successful lite lookup verification. The verified value is
+/// the [LiteLookupProof::plaintext_value] of the proof.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LiteVerifyResult {
+    /// The epoch of this record
+    pub epoch: u64,
+    /// Version at this update
+    pub version: u64,
+    /// The epoch at which the value expires, if it was published with one
+    pub expiry_epoch: Option<u64>,
+}
+
+/// Hashes a [NodeLabel], as [NodeLabel::hash] does
+fn hash_label(label: &NodeLabel) -> Digest {
+    let mut buffer = [0u8; NODE_LABEL_BYTES];
+    buffer[..4].copy_from_slice(&label.label_len.to_be_bytes());
+    buffer[4..].copy_from_slice(&label.label_val);
+    hash(&buffer)
+}
+
+/// Appends `i2osp_array(data)` to the buffer at the given offset, returning the new offset
+fn write_i2osp(buffer: &mut [u8], offset: usize, data: &[u8]) -> usize {
+    let end = offset + 8 + data.len();
+    buffer[offset..offset + 8].copy_from_slice(&(data.len() as u64).to_be_bytes());
+    buffer[offset + 8..end].copy_from_slice(data);
+    end
+}
+
+/// Verifies the membership proof, as [super::verify_membership] does
+pub fn verify_membership<const D: usize>(
+    root_hash: Digest,
+    proof: &LiteMembershipProof<D>,
+) -> Result<(), LiteVerificationError> {
+    let mut current_hash = merge(&[proof.hash_val, hash_label(&proof.label)]);
+
+    for parent in proof.layer_proofs().iter().rev() {
+        let sibling = &parent.siblings[0];
+        let sibling_hash = merge(&[sibling.hash, hash_label(&sibling.label)]);
+        let children = match parent.direction {
+            Direction::Left => [current_hash, sibling_hash],
+            Direction::Right => [sibling_hash, current_hash],
+            Direction::None => return Err(LiteVerificationError::MembershipProof),
+        };
+        current_hash = merge(&[merge(&children), hash_label(&parent.label)]);
+    }
+
+    if current_hash == root_hash {
+        Ok(())
+    } else {
+        Err(LiteVerificationError::MembershipProof)
+    }
+}
+
+/// Verifies the non-membership proof, as [super::verify_nonmembership] does
+pub fn verify_nonmembership<const D: usize>(
+    root_hash: Digest,
+    proof: &LiteNonMembershipProof<D>,
+) -> Result<(), LiteVerificationError> {
+    let children = &proof.longest_prefix_children;
+    let mut lcp_real = children[0].label;
+    for child in children.iter() {
+        lcp_real = lcp_real.get_longest_common_prefix(child.label);
+    }
+    if lcp_real == EMPTY_LABEL {
+        lcp_real = NodeLabel {
+            label_val: [0u8; 32],
+            label_len: 0,
+        };
+    }
+
+    let lcp_hash = merge(&[
+        merge(&[children[0].hash, hash_label(&children[0].label)]),
+        merge(&[children[1].hash, hash_label(&children[1].label)]),
+    ]);
+    if lcp_hash != proof.longest_prefix_membership_proof.hash_val {
+        return Err(LiteVerificationError::NonMembershipProof);
+    }
+
+    verify_membership(root_hash, &proof.longest_prefix_membership_proof)?;
+
+    if proof.longest_prefix != lcp_real {
+        return Err(LiteVerificationError::NonMembershipProof);
+    }
+    Ok(())
+}
+
+/// Verifies that a [NodeLabel] is the VRF output for a version of an AkdLabel
+fn verify_label(
+    vrf_public_key: &VRFPublicKey,
+    akd_label: &[u8],
+    freshness: VersionFreshness,
+    version: u64,
+    vrf_proof: &[u8; VRF_PROOF_BYTES],
+    node_label: NodeLabel,
+) -> Result<(), LiteVerificationError> {
+    // H(i2osp_array(label) || freshness || version), see utils::get_hash_from_label_input
+    let mut buffer = [0u8; 8 + MAX_LABEL_BYTES + 1 + 8];
+    let mut offset = write_i2osp(&mut buffer, 0, akd_label);
+    buffer[offset] = freshness as u8;
+    offset += 1;
+    buffer[offset..offset + 8].copy_from_slice(&version.to_be_bytes());
+    let hashed_label = hash(&buffer[..offset + 8]);
+
+    let proof = Proof::try_from(&vrf_proof[..]).map_err(|_| LiteVerificationError::Vrf)?;
+    vrf_public_key
+        .verify(&proof, &hashed_label)
+        .map_err(|_| LiteVerificationError::Vrf)?;
+    let output: Output = (&proof).into();
+
+    if NodeLabel::new(output.to_truncated_bytes(), 256) != node_label {
+        return Err(LiteVerificationError::Vrf);
+    }
+    Ok(())
+}
+
+/// Verifies a lookup with respect to the root_hash, accepting the proofs accepted
+/// by [super::lookup_verify] which are within the limits of this module
+pub fn lookup_verify<const D: usize>(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    akd_label: &[u8],
+    proof: &LiteLookupProof<D>,
+) -> Result<LiteVerifyResult, LiteVerificationError> {
+    if akd_label.len() > MAX_LABEL_BYTES {
+        return Err(LiteVerificationError::TooLarge);
+    }
+    if proof.version == 0 {
+        return Err(LiteVerificationError::Malformed);
+    }
+    let vrf_public_key =
+        VRFPublicKey::try_from(vrf_public_key).map_err(|_| LiteVerificationError::Vrf)?;
+    let version = proof.version;
+    let marker_version = 1 << crate::utils::get_marker_version(version);
+
+    // H(H(i2osp_array(value) || i2osp_array(nonce)) || epoch), see utils::hash_leaf_with_value
+    let mut buffer = [0u8; 8 + MAX_VALUE_BYTES + 8 + MAX_NONCE_BYTES];
+    let offset = write_i2osp(&mut buffer, 0, proof.plaintext_value.as_slice());
+    let offset = write_i2osp(&mut buffer, offset, proof.commitment_proof.as_slice());
+    let commitment = bind_expiry(hash(&buffer[..offset]), proof.expiry_epoch);
+    if merge_with_int(commitment, proof.epoch) != proof.existence_proof.hash_val {
+        return Err(LiteVerificationError::Commitment);
+    }
+
+    verify_label(
+        &vrf_public_key,
+        akd_label,
+        VersionFreshness::Fresh,
+        version,
+        &proof.existence_vrf_proof,
+        proof.existence_proof.label,
+    )?;
+    verify_membership(root_hash, &proof.existence_proof)?;
+
+    verify_label(
+        &vrf_public_key,
+        akd_label,
+        VersionFreshness::Fresh,
+        marker_version,
+        &proof.marker_vrf_proof,
+        proof.marker_proof.label,
+    )?;
+    verify_membership(root_hash, &proof.marker_proof)?;
+
+    verify_label(
+        &vrf_public_key,
+        akd_label,
+        VersionFreshness::Stale,
+        version,
+        &proof.freshness_vrf_proof,
+        proof.freshness_proof.label,
+    )?;
+    verify_nonmembership(root_hash, &proof.freshness_proof)?;
+
+    Ok(LiteVerifyResult {
+        epoch: proof.epoch,
+        version: proof.version,
+        expiry_epoch: proof.expiry_epoch,
+    })
+}
@@ -7,14 +7,22 @@
 
 //! Verification of lookup proofs
 
-use super::base::{verify_label, verify_membership, verify_nonmembership};
+use super::base::{verify_batch_labels, verify_label, verify_membership, verify_nonmembership};
+use super::telemetry::{observe, NoopObserver, VerificationObserver, VerificationStep};
 use super::VerificationError;
 use crate::utils::hash_leaf_with_value;
 
+use crate::ecvrf::VrfError;
 use crate::hash::Digest;
-use crate::{AkdLabel, LookupProof, VerifyResult, VersionFreshness};
+use crate::{
+    AbsenceProof, AkdLabel, BatchLookupProof, LookupProof, SizeOf, VerifyResult, VersionFreshness,
+};
+#[cfg(feature = "nostd")]
+use alloc::format;
 #[cfg(feature = "nostd")]
 use alloc::string::ToString;
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
 
 /// Verifies a lookup with respect to the root_hash
 pub fn lookup_verify(
@@ -23,60 +31,208 @@ pub fn lookup_verify(
     akd_label: AkdLabel,
     proof: LookupProof,
 ) -> Result<VerifyResult, VerificationError> {
-    let version = proof.version;
+    lookup_verify_with_observer(
+        vrf_public_key,
+        root_hash,
+        akd_label,
+        proof,
+        &mut NoopObserver,
+    )
+}
 
-    let marker_version = 1 << crate::utils::get_marker_version(version);
-    let existence_proof = proof.existence_proof;
-    let marker_proof = proof.marker_proof;
-    let freshness_proof = proof.freshness_proof;
+/// Verifies a lookup as in [lookup_verify], reporting the size of the proof
+/// and the steps of the verification to the given observer
+pub fn lookup_verify_with_observer(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    akd_label: AkdLabel,
+    proof: LookupProof,
+    observer: &mut dyn VerificationObserver,
+) -> Result<VerifyResult, VerificationError> {
+    observer.proof_size(proof.size_of());
+    observe(observer, VerificationStep::Vrf, || {
+        verify_lookup_labels(vrf_public_key, &akd_label, &proof)
+    })?;
 
-    let fresh_label = existence_proof.label;
+    observe(observer, VerificationStep::Membership, || {
+        verify_lookup_against_root(root_hash, &proof)
+    })
+}
 
-    if hash_leaf_with_value(&proof.plaintext_value, proof.epoch, &proof.commitment_proof)
-        != existence_proof.hash_val
-    {
-        return Err(VerificationError::LookupProof(
-            "Hash of plaintext value did not match existence proof hash".to_string(),
-        ));
+/// Verifies a lookup with respect to any of several candidate roots, given as
+/// (epoch, root_hash) pairs, such as the recent tree heads gossiped to a client
+/// while a new root is still propagating. Candidates for epochs before the one
+/// at which the proven version was published are skipped. Returns the epoch of
+/// the first candidate, in the order given, that the proof verified against.
+pub fn lookup_verify_against_roots(
+    vrf_public_key: &[u8],
+    candidate_roots: &[(u64, Digest)],
+    akd_label: AkdLabel,
+    proof: LookupProof,
+) -> Result<(u64, VerifyResult), VerificationError> {
+    verify_lookup_labels(vrf_public_key, &akd_label, &proof)?;
+
+    for (epoch, root_hash) in candidate_roots {
+        if proof.epoch > *epoch {
+            continue;
+        }
+        if let Ok(result) = verify_lookup_against_root(*root_hash, &proof) {
+            return Ok((*epoch, result));
+        }
     }
+    Err(VerificationError::LookupProof(format!(
+        "The proof did not verify against any of the {} candidate roots",
+        candidate_roots.len()
+    )))
+}
 
+/// Verifies a lookup of a label at a past epoch (see `Directory::lookup_at`) with
+/// respect to the root hash of that epoch, checking that the version proven was
+/// published no later than the epoch
+pub fn lookup_verify_at_epoch(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    epoch: u64,
+    akd_label: AkdLabel,
+    proof: LookupProof,
+) -> Result<VerifyResult, VerificationError> {
+    if proof.epoch > epoch {
+        return Err(VerificationError::LookupProof(format!(
+            "The proof is for version {} published at epoch {}, after epoch {}",
+            proof.version, proof.epoch, epoch
+        )));
+    }
+    lookup_verify(vrf_public_key, root_hash, akd_label, proof)
+}
+
+/// Verifies that a label has never been published, with respect to the root_hash,
+/// given the proof returned by a lookup of a label which doesn't exist (see
+/// `Directory::lookup_nonexistent`)
+pub fn lookup_nonexistent_verify(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    akd_label: AkdLabel,
+    proof: AbsenceProof,
+) -> Result<(), VerificationError> {
     verify_label(
         vrf_public_key,
         &akd_label,
         VersionFreshness::Fresh,
-        version,
-        &proof.existence_vrf_proof,
-        fresh_label,
+        1,
+        &proof.vrf_proof,
+        proof.non_membership_proof.label,
     )?;
-    verify_membership(root_hash, &existence_proof)?;
+    verify_nonmembership(root_hash, &proof.non_membership_proof)
+}
+
+/// Verifies a lookup of several labels with respect to the root_hash, where the
+/// labels of all the lookups are covered by a single batch VRF proof. Returns the
+/// result of each lookup, in the order of the labels.
+pub fn batch_lookup_verify(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    akd_labels: &[AkdLabel],
+    proof: BatchLookupProof,
+) -> Result<Vec<VerifyResult>, VerificationError> {
+    if akd_labels.len() != proof.lookup_proofs.len() {
+        return Err(VerificationError::LookupProof(format!(
+            "The proof contains {} lookups, but {} labels were given",
+            proof.lookup_proofs.len(),
+            akd_labels.len()
+        )));
+    }
 
-    let marker_label = marker_proof.label;
+    let inputs = akd_labels
+        .iter()
+        .zip(proof.lookup_proofs.iter())
+        .flat_map(|(akd_label, lookup)| {
+            let marker_version = 1 << crate::utils::get_marker_version(lookup.version);
+            [
+                (akd_label, VersionFreshness::Fresh, lookup.version),
+                (akd_label, VersionFreshness::Fresh, marker_version),
+                (akd_label, VersionFreshness::Stale, lookup.version),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let node_labels = verify_batch_labels(vrf_public_key, &inputs, &proof.vrf_proof)?;
+
+    let mut results = Vec::new();
+    for (lookup, expected) in proof.lookup_proofs.into_iter().zip(node_labels.chunks(3)) {
+        let labels = [
+            lookup.existence_proof.label,
+            lookup.marker_proof.label,
+            lookup.freshness_proof.label,
+        ];
+        if labels[..] != *expected {
+            return Err(VerificationError::Vrf(VrfError::Verification(
+                "The outputs of the batch proof did NOT match the supplied labels".to_string(),
+            )));
+        }
+        results.push(verify_lookup_against_root(root_hash, &lookup)?);
+    }
+    Ok(results)
+}
+
+/// Verifies that the labels of the tree proofs of a lookup are the VRF outputs
+/// for the label and version proven
+fn verify_lookup_labels(
+    vrf_public_key: &[u8],
+    akd_label: &AkdLabel,
+    proof: &LookupProof,
+) -> Result<(), VerificationError> {
+    let marker_version = 1 << crate::utils::get_marker_version(proof.version);
     verify_label(
         vrf_public_key,
-        &akd_label,
+        akd_label,
+        VersionFreshness::Fresh,
+        proof.version,
+        &proof.existence_vrf_proof,
+        proof.existence_proof.label,
+    )?;
+    verify_label(
+        vrf_public_key,
+        akd_label,
         VersionFreshness::Fresh,
         marker_version,
         &proof.marker_vrf_proof,
-        marker_label,
+        proof.marker_proof.label,
     )?;
-
-    verify_membership(root_hash, &marker_proof)?;
-
-    let stale_label = freshness_proof.label;
     verify_label(
         vrf_public_key,
-        &akd_label,
+        akd_label,
         VersionFreshness::Stale,
-        version,
+        proof.version,
         &proof.freshness_vrf_proof,
-        stale_label,
-    )?;
+        proof.freshness_proof.label,
+    )
+}
+
+/// Verifies the value and tree proofs of a lookup, once the labels they are for
+/// have been verified
+fn verify_lookup_against_root(
+    root_hash: Digest,
+    proof: &LookupProof,
+) -> Result<VerifyResult, VerificationError> {
+    if hash_leaf_with_value(
+        &proof.plaintext_value,
+        proof.expiry_epoch,
+        proof.epoch,
+        &proof.commitment_proof,
+    ) != proof.existence_proof.hash_val
+    {
+        return Err(VerificationError::LookupProof(
+            "Hash of plaintext value did not match existence proof hash".to_string(),
+        ));
+    }
 
-    verify_nonmembership(root_hash, &freshness_proof)?;
+    verify_membership(root_hash, &proof.existence_proof)?;
+    verify_membership(root_hash, &proof.marker_proof)?;
+    verify_nonmembership(root_hash, &proof.freshness_proof)?;
 
     Ok(VerifyResult {
         epoch: proof.epoch,
         version: proof.version,
-        value: proof.plaintext_value,
+        value: proof.plaintext_value.clone(),
+        expiry_epoch: proof.expiry_epoch,
     })
 }
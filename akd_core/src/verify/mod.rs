@@ -7,9 +7,16 @@
 
 //! This module contains verification calls for different proofs contained in the AKD crate
 
+#[cfg(all(feature = "protobuf", not(feature = "nostd")))]
+pub mod audit;
 pub mod base;
 pub mod history;
+pub mod lite;
 pub mod lookup;
+pub mod sample;
+pub mod telemetry;
+#[cfg(feature = "vrf")]
+pub mod tree_head;
 
 #[cfg(feature = "nostd")]
 use alloc::format;
@@ -29,6 +36,19 @@ pub enum VerificationError {
     LookupProof(String),
     /// Error verifying a history proof
     HistoryProof(String),
+    /// A history proof did not satisfy the requested verification policy
+    HistoryPolicy(history::HistoryPolicyViolation),
+    /// Error verifying a signed tree head
+    TreeHead(String),
+    /// Error verifying a sample audit proof
+    SampleAudit(String),
+    /// Error verifying an audit proof
+    AuditProof(String),
+    /// A proof was deeper than [crate::MAX_TREE_DEPTH], with the given depth
+    ProofTooDeep(usize),
+    /// The directory served a root hash older than, or conflicting with, one the
+    /// client had already verified
+    Rollback(String),
     /// Error hashing during verification
     Hash(crate::hash::HashError),
     /// Error verifying a VRF proof
@@ -48,6 +68,16 @@ impl core::fmt::Display for VerificationError {
             }
             VerificationError::LookupProof(err) => format!("(Lookup proof) - {}", err),
             VerificationError::HistoryProof(err) => format!("(History proof) - {}", err),
+            VerificationError::HistoryPolicy(err) => format!("(History policy) - {}", err),
+            VerificationError::TreeHead(err) => format!("(Tree head) - {}", err),
+            VerificationError::SampleAudit(err) => format!("(Sample audit) - {}", err),
+            VerificationError::AuditProof(err) => format!("(Audit proof) - {}", err),
+            VerificationError::ProofTooDeep(depth) => format!(
+                "(Proof depth) - depth {} exceeds the maximum of {}",
+                depth,
+                crate::MAX_TREE_DEPTH
+            ),
+            VerificationError::Rollback(err) => format!("(Rollback) - {}", err),
             VerificationError::Hash(hash) => hash.to_string(),
             #[cfg(feature = "vrf")]
             VerificationError::Vrf(vrf) => vrf.to_string(),
@@ -87,6 +117,23 @@ impl From<protobuf::Error> for VerificationError {
 }
 
 // Re-export the necessary verification functions
+#[cfg(all(feature = "protobuf", not(feature = "nostd")))]
+pub use audit::{
+    audit_verify_serialized, verify_consecutive_append_only_serialized, StreamingAuditVerifier,
+};
 pub use base::{verify_membership, verify_nonmembership};
-pub use history::{key_history_verify, HistoryVerificationParams};
-pub use lookup::lookup_verify;
+pub use history::{
+    key_history_verify, key_history_verify_with_observer, key_history_verify_with_policy,
+    lookup_at_epochs_verify, update_count_verify, HistoryPolicyViolation,
+    HistoryVerificationParams, HistoryVerificationPolicy,
+};
+pub use lookup::{
+    batch_lookup_verify, lookup_nonexistent_verify, lookup_verify, lookup_verify_against_roots,
+    lookup_verify_at_epoch, lookup_verify_with_observer,
+};
+pub use sample::verify_sample_audit;
+#[cfg(not(feature = "nostd"))]
+pub use telemetry::VerificationTimings;
+pub use telemetry::{NoopObserver, VerificationObserver, VerificationStep};
+#[cfg(feature = "vrf")]
+pub use tree_head::{compare_tree_heads, verify_tree_head, verify_tree_head_for_root};
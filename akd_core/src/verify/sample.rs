@@ -0,0 +1,72 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Verification of [SampleAuditProof]s, the random spot checks of the tree at an epoch
+
+use super::base::verify_membership;
+use super::VerificationError;
+
+use crate::hash::Digest;
+use crate::{Direction, SampleAuditProof, EMPTY_LABEL};
+
+#[cfg(feature = "nostd")]
+use alloc::format;
+
+/// Verifies a sample audit proof against the root hash of the tree at the given
+/// epoch, for the given public seed and number of samples. Each sample must be a
+/// valid membership proof of a leaf, reached by descending the tree along the bits
+/// of the label targeted by the sample (deviating only where a node has a single
+/// child).
+pub fn verify_sample_audit(
+    root_hash: Digest,
+    epoch: u64,
+    seed: &[u8],
+    num_samples: usize,
+    proof: &SampleAuditProof,
+) -> Result<(), VerificationError> {
+    if proof.epoch != epoch || proof.seed != seed {
+        return Err(VerificationError::SampleAudit(format!(
+            "The proof samples epoch {} with seed {}, expected epoch {} with seed {}",
+            proof.epoch,
+            hex::encode(&proof.seed),
+            epoch,
+            hex::encode(seed)
+        )));
+    }
+    if proof.samples.len() != num_samples {
+        return Err(VerificationError::SampleAudit(format!(
+            "Expected {} samples, got {}",
+            num_samples,
+            proof.samples.len()
+        )));
+    }
+
+    for (index, sample) in proof.samples.iter().enumerate() {
+        // leaves have full length labels, which interior nodes can't have
+        if sample.label.get_len() != 256 {
+            return Err(VerificationError::SampleAudit(format!(
+                "Sample {} is not a leaf: {}",
+                index, sample.label
+            )));
+        }
+        let target = SampleAuditProof::sample_target(seed, epoch, index as u64);
+        for layer in sample.layer_proofs.iter() {
+            let expected = match target.get_bit_at(layer.label.get_len()) {
+                0u8 => Direction::Left,
+                _ => Direction::Right,
+            };
+            if layer.direction != expected && layer.siblings[0].label != EMPTY_LABEL {
+                return Err(VerificationError::SampleAudit(format!(
+                    "Sample {} deviates from its target at node {}",
+                    index, layer.label
+                )));
+            }
+        }
+        verify_membership(root_hash, sample)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,115 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Hooks reporting the progress of proof verification, e.g. for collecting
+//! verification performance metrics in client applications
+
+#[cfg(not(feature = "nostd"))]
+use std::time::{Duration, Instant};
+
+/// A step of proof verification, reported to a [VerificationObserver]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VerificationStep {
+    /// Verifying the VRF proofs of the labels of the tree
+    Vrf,
+    /// Verifying the membership and non-membership proofs against the root hash
+    Membership,
+    /// Walking the chain of updates of a key history, checking their versions,
+    /// epochs and values
+    HistoryChain,
+}
+
+/// Hooks called while verifying a proof, see [super::lookup::lookup_verify_with_observer]
+/// and [super::history::key_history_verify_with_observer].
+///
+/// The steps are reported when they start and complete, so that the observer can
+/// time them with the clock of its platform. A step may be reported several times
+/// for a single proof, e.g. once per update of a key history. All the methods do
+/// nothing by default.
+pub trait VerificationObserver {
+    /// Called with the size of the proof, in bytes, before it is verified
+    fn proof_size(&mut self, _size: usize) {}
+
+    /// Called when a step of the verification starts
+    fn step_started(&mut self, _step: VerificationStep) {}
+
+    /// Called when a step of the verification completes, successfully or not
+    fn step_completed(&mut self, _step: VerificationStep) {}
+}
+
+/// A [VerificationObserver] ignoring all the hooks
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopObserver;
+
+impl VerificationObserver for NoopObserver {}
+
+/// A [VerificationObserver] measuring the total time spent in each step with
+/// [std::time::Instant]. Note that some platforms, such as `wasm32-unknown-unknown`,
+/// have no such clock: an observer using the platform's own clock should be
+/// implemented there instead.
+#[cfg(not(feature = "nostd"))]
+#[derive(Debug, Clone, Default)]
+pub struct VerificationTimings {
+    /// The size of the last proof verified, in bytes
+    pub proof_size: usize,
+    /// The total time spent in each step, in the order the steps first started
+    pub steps: Vec<(VerificationStep, Duration)>,
+    started: Vec<(VerificationStep, Instant)>,
+}
+
+#[cfg(not(feature = "nostd"))]
+impl VerificationTimings {
+    /// Creates an observer with no time measured
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The total time spent in the given step
+    pub fn total(&self, step: VerificationStep) -> Duration {
+        self.steps
+            .iter()
+            .find(|(s, _)| *s == step)
+            .map(|(_, duration)| *duration)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(not(feature = "nostd"))]
+impl VerificationObserver for VerificationTimings {
+    fn proof_size(&mut self, size: usize) {
+        self.proof_size = size;
+    }
+
+    fn step_started(&mut self, step: VerificationStep) {
+        self.started.push((step, Instant::now()));
+    }
+
+    fn step_completed(&mut self, step: VerificationStep) {
+        let position = match self.started.iter().rposition(|(s, _)| *s == step) {
+            Some(position) => position,
+            None => return,
+        };
+        let (_, start) = self.started.remove(position);
+        let elapsed = start.elapsed();
+        match self.steps.iter_mut().find(|(s, _)| *s == step) {
+            Some((_, total)) => *total += elapsed,
+            None => self.steps.push((step, elapsed)),
+        }
+    }
+}
+
+/// Runs a step of the verification, reporting it to the observer
+pub(crate) fn observe<T>(
+    observer: &mut dyn VerificationObserver,
+    step: VerificationStep,
+    f: impl FnOnce() -> T,
+) -> T {
+    observer.step_started(step);
+    let out = f();
+    observer.step_completed(step);
+    out
+}
@@ -0,0 +1,78 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Verification of [SignedTreeHead]s, and their comparison for detecting split views
+
+use super::VerificationError;
+
+use crate::hash::Digest;
+use crate::SignedTreeHead;
+
+#[cfg(feature = "nostd")]
+use alloc::format;
+use core::convert::TryFrom;
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+
+/// Verifies the directory's signature of a tree head, given the directory's
+/// tree head public key
+pub fn verify_tree_head(
+    public_key: &[u8],
+    tree_head: &SignedTreeHead,
+) -> Result<(), VerificationError> {
+    let public_key = PublicKey::from_bytes(public_key).map_err(|err| {
+        VerificationError::TreeHead(format!("Invalid tree head public key: {}", err))
+    })?;
+    let signature = Signature::try_from(&tree_head.signature[..]).map_err(|err| {
+        VerificationError::TreeHead(format!("Malformed tree head signature: {}", err))
+    })?;
+    let message =
+        SignedTreeHead::signing_message(tree_head.epoch, &tree_head.root_hash, tree_head.timestamp);
+    public_key.verify(&message, &signature).map_err(|_| {
+        VerificationError::TreeHead(format!(
+            "Signature of the tree head at epoch {} did not verify",
+            tree_head.epoch
+        ))
+    })
+}
+
+/// Verifies a tree head along with the root hash served with a proof at the
+/// same epoch, e.g. the [crate::LookupProof] verified against it
+pub fn verify_tree_head_for_root(
+    public_key: &[u8],
+    tree_head: &SignedTreeHead,
+    epoch: u64,
+    root_hash: Digest,
+) -> Result<(), VerificationError> {
+    verify_tree_head(public_key, tree_head)?;
+    if tree_head.epoch != epoch || tree_head.root_hash != root_hash {
+        return Err(VerificationError::TreeHead(format!(
+            "The tree head at epoch {} does not match the root hash served at epoch {}",
+            tree_head.epoch, epoch
+        )));
+    }
+    Ok(())
+}
+
+/// Compares two tree heads, e.g. one received by this client and one gossiped
+/// by another. Both signatures are verified, and an error is returned if the
+/// tree heads are for the same epoch but with different root hashes, which
+/// proves that the directory has served a split view.
+pub fn compare_tree_heads(
+    public_key: &[u8],
+    ours: &SignedTreeHead,
+    theirs: &SignedTreeHead,
+) -> Result<(), VerificationError> {
+    verify_tree_head(public_key, ours)?;
+    verify_tree_head(public_key, theirs)?;
+    if ours.epoch == theirs.epoch && ours.root_hash != theirs.root_hash {
+        return Err(VerificationError::TreeHead(format!(
+            "Split view detected: two different root hashes were signed for epoch {}",
+            ours.epoch
+        )));
+    }
+    Ok(())
+}
@@ -10,7 +10,7 @@
 use super::storage::{EpochSummary, ProofIndexCacheOption};
 
 use akd::Digest;
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Result};
 use clap::{Parser, Subcommand};
 use log::{error, info, warn};
 use rustyrepl::ReplCommandProcessor;
@@ -31,19 +31,14 @@ fn format_qr_record(p_hash: Digest, c_hash: Digest, epoch: u64) -> Vec<u8> {
 }
 
 pub async fn audit_epoch(blob: akd::local_auditing::AuditBlob, qr: bool) -> Result<()> {
-    // decode the proof
-    let (epoch, p_hash, c_hash, proof) = blob.decode().map_err(|err| anyhow!("{:?}", err))?;
-
-    // verify it
-    if let Err(akd_error) = akd::auditor::audit_verify(
-        vec![p_hash, c_hash],
-        akd::AppendOnlyProof {
-            proofs: vec![proof],
-            epochs: vec![epoch],
-        },
-    )
-    .await
-    {
+    let (epoch, p_hash, c_hash) = (
+        blob.name.epoch,
+        blob.name.previous_hash,
+        blob.name.current_hash,
+    );
+
+    // verify the proof, directly from its encoding
+    if let Err(akd_error) = blob.verify() {
         warn!(
             "Audit proof for epoch {} failed to verify with error {}",
             epoch, akd_error
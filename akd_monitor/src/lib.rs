@@ -0,0 +1,89 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! A monitor detecting split views of an auditable key directory.
+//!
+//! Each publish of an [akd::Directory] produces a [akd::SignedTreeHead]. A directory
+//! which shows different trees to different clients must either sign two different
+//! root hashes for the same epoch, or be unable to prove that the tree seen by one
+//! client is an append-only extension of the tree seen by another. The [Monitor]
+//! collects the tree heads served from several vantage points (see [TreeHeadSource]),
+//! and raises a [MonitorAlert] whenever
+//!
+//! * a tree head isn't validly signed by the directory
+//! * two tree heads have different root hashes for the same epoch
+//! * a tree head can't be proven to extend the latest earlier tree head seen from
+//!   any vantage point
+//! * a vantage point goes back to an earlier epoch
+//!
+//! # Vantage points
+//!
+//! [DirectorySource] queries a [akd::Directory] in-process, while [HttpSource] queries
+//! a directory server over HTTP. The server is expected to serve the JSON
+//! (`serde_serialization`) encodings of
+//!
+//! * the latest [akd::SignedTreeHead] at `GET <endpoint>/tree_head`
+//! * the [akd::AppendOnlyProof] between two epochs at `GET <endpoint>/audit/<start>/<end>`
+//!
+//! Other vantage points (e.g. tree heads gossiped by clients) can be added by
+//! implementing [TreeHeadSource].
+//!
+//! # Daemon
+//!
+//! The `akd_monitor` binary polls a set of HTTP endpoints periodically, and logs the
+//! alerts raised. For example
+//!
+//! ```bash
+//! cargo run -p akd_monitor -- --public-key <hex> \
+//!     --endpoint http://dir1.example.com --endpoint http://dir2.example.com
+//! ```
+//!
+//! where the public key is the directory's tree head public key (see
+//! [akd::Directory::get_tree_head_public_key]), hex-encoded.
+
+#![warn(missing_docs)]
+
+pub mod monitor;
+pub mod sources;
+
+#[cfg(test)]
+mod tests;
+
+pub use monitor::{Monitor, MonitorAlert};
+pub use sources::{DirectorySource, HttpSource, TreeHeadSource};
+
+use akd::errors::AkdError;
+use std::fmt;
+
+/// An error querying a [TreeHeadSource]
+#[derive(Debug)]
+pub enum MonitorError {
+    /// The directory failed to serve the request
+    Akd(AkdError),
+    /// The HTTP request failed, or returned an error status
+    Http(String),
+    /// The response couldn't be decoded
+    Serialization(String),
+}
+
+impl fmt::Display for MonitorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Akd(err) => write!(f, "Directory error: {}", err),
+            Self::Http(err) => write!(f, "HTTP error: {}", err),
+            Self::Serialization(err) => write!(f, "Serialization error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MonitorError {}
+
+impl From<AkdError> for MonitorError {
+    fn from(err: AkdError) -> Self {
+        Self::Akd(err)
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! A daemon periodically collecting the signed tree heads served by a set of
+//! directory endpoints over HTTP, and reporting any split view detected (see the
+//! [akd_monitor] crate documentation).
+
+use akd_monitor::{HttpSource, Monitor};
+use clap::Parser;
+use std::time::Duration;
+
+/// AKD split-view monitor
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Arguments {
+    /// The directory's tree head public key, hex-encoded
+    #[clap(long)]
+    public_key: String,
+
+    /// The HTTP endpoints serving the directory's tree heads (at least 1)
+    #[clap(long = "endpoint", required = true)]
+    endpoints: Vec<String>,
+
+    /// The number of seconds between polls of the endpoints
+    #[clap(long, default_value = "60")]
+    interval: u64,
+
+    /// Poll the endpoints once, exiting with an error if a split view is detected
+    #[clap(long)]
+    once: bool,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Arguments::parse();
+    let public_key = match hex::decode(&args.public_key) {
+        Ok(public_key) => public_key,
+        Err(err) => {
+            eprintln!("Invalid public key: {}", err);
+            std::process::exit(2);
+        }
+    };
+
+    let mut monitor = args
+        .endpoints
+        .iter()
+        .fold(Monitor::new(&public_key), |monitor, endpoint| {
+            monitor.with_source(HttpSource::new(endpoint))
+        });
+    let mut interval = tokio::time::interval(Duration::from_secs(args.interval));
+    loop {
+        interval.tick().await;
+        let alerts = monitor.poll().await;
+        for alert in alerts.iter() {
+            eprintln!("ALERT: {}", alert);
+        }
+        if let Some(tree_head) = monitor.latest_tree_head() {
+            println!(
+                "Consistent up to epoch {} (root hash {})",
+                tree_head.epoch,
+                hex::encode(tree_head.root_hash)
+            );
+        }
+        if args.once {
+            if alerts.iter().any(|alert| alert.is_split_view()) {
+                std::process::exit(1);
+            }
+            break;
+        }
+    }
+}
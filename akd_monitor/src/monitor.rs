@@ -0,0 +1,268 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Collection and cross-checking of the tree heads served by several vantage points
+
+use crate::TreeHeadSource;
+
+use akd::{Digest, EpochHash, SignedTreeHead};
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// An inconsistency detected by the [Monitor]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorAlert {
+    /// A vantage point could not be queried
+    Unreachable {
+        /// The name of the vantage point
+        source: String,
+        /// The error querying it
+        error: String,
+    },
+    /// A vantage point served a tree head which isn't validly signed by the directory
+    InvalidTreeHead {
+        /// The name of the vantage point
+        source: String,
+        /// The epoch of the tree head
+        epoch: u64,
+        /// The verification error
+        error: String,
+    },
+    /// A vantage point served a tree head older than one it served before
+    Rollback {
+        /// The name of the vantage point
+        source: String,
+        /// The epoch of the tree head served
+        epoch: u64,
+        /// The epoch of the latest tree head served before
+        previous_epoch: u64,
+    },
+    /// Two validly signed tree heads have different root hashes for the same epoch
+    Fork {
+        /// The epoch of the tree heads
+        epoch: u64,
+        /// The vantage point the first tree head was seen from
+        first_source: String,
+        /// The root hash of the first tree head
+        first_root_hash: Digest,
+        /// The vantage point the conflicting tree head was seen from
+        second_source: String,
+        /// The root hash of the conflicting tree head
+        second_root_hash: Digest,
+    },
+    /// A vantage point could not prove that its tree head extends the latest
+    /// earlier tree head seen (from any vantage point)
+    NotAppendOnly {
+        /// The name of the vantage point
+        source: String,
+        /// The vantage point the earlier tree head was seen from
+        anchor_source: String,
+        /// The epoch of the earlier tree head
+        start_epoch: u64,
+        /// The epoch of the tree head served
+        end_epoch: u64,
+        /// The error retrieving or verifying the proof
+        error: String,
+    },
+}
+
+impl MonitorAlert {
+    /// Whether the alert proves that the directory served a split view, as opposed
+    /// to a vantage point being unavailable or serving invalid data
+    pub fn is_split_view(&self) -> bool {
+        matches!(self, Self::Fork { .. } | Self::NotAppendOnly { .. })
+    }
+}
+
+impl fmt::Display for MonitorAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreachable { source, error } => {
+                write!(f, "{} is unreachable: {}", source, error)
+            }
+            Self::InvalidTreeHead {
+                source,
+                epoch,
+                error,
+            } => write!(
+                f,
+                "{} served an invalid tree head for epoch {}: {}",
+                source, epoch, error
+            ),
+            Self::Rollback {
+                source,
+                epoch,
+                previous_epoch,
+            } => write!(
+                f,
+                "{} rolled back from epoch {} to epoch {}",
+                source, previous_epoch, epoch
+            ),
+            Self::Fork {
+                epoch,
+                first_source,
+                first_root_hash,
+                second_source,
+                second_root_hash,
+            } => write!(
+                f,
+                "Fork at epoch {}: {} served root hash {} but {} served root hash {}",
+                epoch,
+                first_source,
+                hex::encode(first_root_hash),
+                second_source,
+                hex::encode(second_root_hash)
+            ),
+            Self::NotAppendOnly {
+                source,
+                anchor_source,
+                start_epoch,
+                end_epoch,
+                error,
+            } => write!(
+                f,
+                "{} could not prove that epoch {} extends epoch {} seen from {}: {}",
+                source, end_epoch, start_epoch, anchor_source, error
+            ),
+        }
+    }
+}
+
+/// Collects the tree heads served by a set of vantage points and cross-checks them.
+///
+/// Every tree head collected is verified against the directory's tree head public
+/// key and compared with the tree heads of the same epoch seen before. Each vantage
+/// point must then prove, with an audit proof, that its tree head extends the latest
+/// earlier tree head seen from any vantage point, so that a fork is detected even if
+/// the vantage points are never queried at the same epoch.
+pub struct Monitor {
+    public_key: Vec<u8>,
+    sources: Vec<Box<dyn TreeHeadSource>>,
+    /// The first tree head seen for each epoch, along with the source it was seen from
+    seen: BTreeMap<u64, (String, SignedTreeHead)>,
+    /// The latest consistent tree head served by each source
+    latest: HashMap<usize, SignedTreeHead>,
+}
+
+impl Monitor {
+    /// Creates a monitor, given the tree head public key of the directory
+    /// (see [akd::Directory::get_tree_head_public_key])
+    pub fn new(public_key: &[u8]) -> Self {
+        Self {
+            public_key: public_key.to_vec(),
+            sources: vec![],
+            seen: BTreeMap::new(),
+            latest: HashMap::new(),
+        }
+    }
+
+    /// Adds a vantage point to collect tree heads from
+    pub fn with_source<T: TreeHeadSource + 'static>(mut self, source: T) -> Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// The latest tree head seen from any vantage point which was found consistent
+    pub fn latest_tree_head(&self) -> Option<&SignedTreeHead> {
+        self.latest.values().max_by_key(|tree_head| tree_head.epoch)
+    }
+
+    /// Collects the latest tree head of every vantage point, returning the alerts raised
+    pub async fn poll(&mut self) -> Vec<MonitorAlert> {
+        let mut alerts = vec![];
+        for index in 0..self.sources.len() {
+            if let Err(alert) = self.poll_source(index).await {
+                warn!("{}", alert);
+                alerts.push(alert);
+            }
+        }
+        self.prune();
+        alerts
+    }
+
+    async fn poll_source(&mut self, index: usize) -> Result<(), MonitorAlert> {
+        let source = &self.sources[index];
+        let name = source.name();
+        let tree_head =
+            source
+                .latest_tree_head()
+                .await
+                .map_err(|err| MonitorAlert::Unreachable {
+                    source: name.clone(),
+                    error: err.to_string(),
+                })?;
+        akd::verify::verify_tree_head(&self.public_key, &tree_head).map_err(|err| {
+            MonitorAlert::InvalidTreeHead {
+                source: name.clone(),
+                epoch: tree_head.epoch,
+                error: err.to_string(),
+            }
+        })?;
+
+        if let Some(previous) = self.latest.get(&index) {
+            if tree_head.epoch < previous.epoch {
+                return Err(MonitorAlert::Rollback {
+                    source: name,
+                    epoch: tree_head.epoch,
+                    previous_epoch: previous.epoch,
+                });
+            }
+        }
+
+        if let Some((first_source, first)) = self.seen.get(&tree_head.epoch) {
+            if first.root_hash != tree_head.root_hash {
+                return Err(MonitorAlert::Fork {
+                    epoch: tree_head.epoch,
+                    first_source: first_source.clone(),
+                    first_root_hash: first.root_hash,
+                    second_source: name,
+                    second_root_hash: tree_head.root_hash,
+                });
+            }
+        } else if let Some((_, (anchor_source, anchor))) =
+            self.seen.range(..tree_head.epoch).next_back()
+        {
+            let not_append_only = |error: String| MonitorAlert::NotAppendOnly {
+                source: name.clone(),
+                anchor_source: anchor_source.clone(),
+                start_epoch: anchor.epoch,
+                end_epoch: tree_head.epoch,
+                error,
+            };
+            let proof = source
+                .audit_proof(anchor.epoch, tree_head.epoch)
+                .await
+                .map_err(|err| not_append_only(err.to_string()))?;
+            akd::auditor::verify_append_only_chain(
+                EpochHash(anchor.epoch, anchor.root_hash),
+                EpochHash(tree_head.epoch, tree_head.root_hash),
+                proof,
+            )
+            .await
+            .map_err(|err| not_append_only(err.to_string()))?;
+        }
+
+        info!("{} is consistent at epoch {}", name, tree_head.epoch);
+        self.seen
+            .entry(tree_head.epoch)
+            .or_insert_with(|| (name, tree_head.clone()));
+        self.latest.insert(index, tree_head);
+        Ok(())
+    }
+
+    /// Forgets the tree heads older than the latest one of every source, since
+    /// the sources can't serve them again without a rollback
+    fn prune(&mut self) {
+        if self.latest.len() < self.sources.len() {
+            return;
+        }
+        if let Some(oldest) = self.latest.values().map(|tree_head| tree_head.epoch).min() {
+            self.seen = self.seen.split_off(&oldest);
+        }
+    }
+}
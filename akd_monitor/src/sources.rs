@@ -0,0 +1,130 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! The vantage points from which the tree heads of a directory are collected
+
+use crate::MonitorError;
+
+use akd::ecvrf::VRFKeyStorage;
+use akd::storage::Database;
+use akd::{AppendOnlyProof, Directory, SignedTreeHead};
+use async_trait::async_trait;
+use hyper::client::HttpConnector;
+use hyper::{Client, StatusCode, Uri};
+use serde::de::DeserializeOwned;
+
+/// A vantage point serving the tree heads of a directory
+#[async_trait]
+pub trait TreeHeadSource: Send + Sync {
+    /// A name identifying the vantage point in alerts
+    fn name(&self) -> String;
+
+    /// Retrieves the latest tree head
+    async fn latest_tree_head(&self) -> Result<SignedTreeHead, MonitorError>;
+
+    /// Retrieves the proof that the tree at `end_epoch` is an append-only
+    /// extension of the tree at `start_epoch`
+    async fn audit_proof(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> Result<AppendOnlyProof, MonitorError>;
+}
+
+/// A vantage point querying a [Directory] in-process
+pub struct DirectorySource<S: Database, V> {
+    name: String,
+    directory: Directory<S, V>,
+}
+
+impl<S: Database + 'static, V: VRFKeyStorage> DirectorySource<S, V> {
+    /// Queries the given directory, identified by the given name
+    pub fn new(name: &str, directory: Directory<S, V>) -> Self {
+        Self {
+            name: name.to_string(),
+            directory,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Database + 'static, V: VRFKeyStorage> TreeHeadSource for DirectorySource<S, V> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn latest_tree_head(&self) -> Result<SignedTreeHead, MonitorError> {
+        Ok(self.directory.get_latest_tree_head().await?)
+    }
+
+    async fn audit_proof(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> Result<AppendOnlyProof, MonitorError> {
+        Ok(self.directory.audit(start_epoch, end_epoch).await?)
+    }
+}
+
+/// A vantage point querying a directory server over HTTP (see the [crate]
+/// documentation for the endpoints expected)
+pub struct HttpSource {
+    endpoint: String,
+    client: Client<HttpConnector>,
+}
+
+impl HttpSource {
+    /// Queries the server at the given endpoint, e.g. `http://127.0.0.1:8080/akd`
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            client: Client::new(),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, MonitorError> {
+        let uri = format!("{}/{}", self.endpoint, path)
+            .parse::<Uri>()
+            .map_err(|err| MonitorError::Http(err.to_string()))?;
+        let response = self
+            .client
+            .get(uri.clone())
+            .await
+            .map_err(|err| MonitorError::Http(err.to_string()))?;
+        if response.status() != StatusCode::OK {
+            return Err(MonitorError::Http(format!(
+                "GET {} returned {}",
+                uri,
+                response.status()
+            )));
+        }
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|err| MonitorError::Http(err.to_string()))?;
+        serde_json::from_slice(&body).map_err(|err| MonitorError::Serialization(err.to_string()))
+    }
+}
+
+#[async_trait]
+impl TreeHeadSource for HttpSource {
+    fn name(&self) -> String {
+        self.endpoint.clone()
+    }
+
+    async fn latest_tree_head(&self) -> Result<SignedTreeHead, MonitorError> {
+        self.get("tree_head").await
+    }
+
+    async fn audit_proof(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> Result<AppendOnlyProof, MonitorError> {
+        self.get(&format!("audit/{}/{}", start_epoch, end_epoch))
+            .await
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Tests of the split-view monitor
+
+use crate::{DirectorySource, Monitor, MonitorAlert, MonitorError, TreeHeadSource};
+
+use akd::ecvrf::HardCodedAkdVRF;
+use akd::errors::AkdError;
+use akd::storage::memory::AsyncInMemoryDatabase;
+use akd::storage::StorageManager;
+use akd::{AkdLabel, AkdValue, AppendOnlyProof, Directory, SignedTreeHead};
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+
+type InMemoryDirectory = Directory<AsyncInMemoryDatabase, HardCodedAkdVRF>;
+
+async fn new_directory() -> Result<InMemoryDirectory, AkdError> {
+    let storage = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false).await
+}
+
+async fn publish(directory: &InMemoryDirectory, label: &str, value: &str) -> Result<(), AkdError> {
+    directory
+        .publish(vec![(
+            AkdLabel::from_utf8_str(label),
+            AkdValue::from_utf8_str(value),
+        )])
+        .await?;
+    Ok(())
+}
+
+/// Serves a tree head set by the test, delegating the audit proofs to a directory
+struct ScriptedSource {
+    tree_head: Arc<Mutex<SignedTreeHead>>,
+    directory: InMemoryDirectory,
+}
+
+#[async_trait]
+impl TreeHeadSource for ScriptedSource {
+    fn name(&self) -> String {
+        "scripted".to_string()
+    }
+
+    async fn latest_tree_head(&self) -> Result<SignedTreeHead, MonitorError> {
+        Ok(self.tree_head.lock().unwrap().clone())
+    }
+
+    async fn audit_proof(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+    ) -> Result<AppendOnlyProof, MonitorError> {
+        Ok(self.directory.audit(start_epoch, end_epoch).await?)
+    }
+}
+
+#[tokio::test]
+async fn test_consistent_sources() -> Result<(), AkdError> {
+    let directory = new_directory().await?;
+    let public_key = directory.get_tree_head_public_key().await?;
+    publish(&directory, "hello", "world").await?;
+
+    let mut monitor = Monitor::new(public_key.as_bytes())
+        .with_source(DirectorySource::new("first", directory.clone()))
+        .with_source(DirectorySource::new("second", directory.clone()));
+    assert_eq!(Vec::<MonitorAlert>::new(), monitor.poll().await);
+
+    for epoch in 2..5 {
+        publish(&directory, "hello", &format!("world{}", epoch)).await?;
+        assert_eq!(Vec::<MonitorAlert>::new(), monitor.poll().await);
+    }
+    assert_eq!(Some(4), monitor.latest_tree_head().map(|head| head.epoch));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fork_detection() -> Result<(), AkdError> {
+    // two trees signed by the same directory key
+    let directory = new_directory().await?;
+    let forked = new_directory().await?;
+    let public_key = directory.get_tree_head_public_key().await?;
+    publish(&directory, "hello", "world").await?;
+    publish(&forked, "hello", "forked").await?;
+
+    let mut monitor = Monitor::new(public_key.as_bytes())
+        .with_source(DirectorySource::new("honest", directory.clone()))
+        .with_source(DirectorySource::new("forked", forked.clone()));
+    let alerts = monitor.poll().await;
+    assert_eq!(1, alerts.len());
+    assert!(matches!(
+        &alerts[0],
+        MonitorAlert::Fork { epoch: 1, first_source, second_source, .. }
+            if first_source == "honest" && second_source == "forked"
+    ));
+    assert!(alerts[0].is_split_view());
+
+    // the fork is still detected once the vantage points are at different epochs
+    publish(&forked, "hello", "forked2").await?;
+    let alerts = monitor.poll().await;
+    assert_eq!(1, alerts.len());
+    assert!(matches!(
+        &alerts[0],
+        MonitorAlert::NotAppendOnly { source, start_epoch: 1, end_epoch: 2, .. }
+            if source == "forked"
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invalid_and_rolled_back_tree_heads() -> Result<(), AkdError> {
+    let directory = new_directory().await?;
+    let public_key = directory.get_tree_head_public_key().await?;
+    publish(&directory, "hello", "world").await?;
+    publish(&directory, "hello", "world2").await?;
+
+    let tree_head = Arc::new(Mutex::new(directory.get_tree_head(2).await?));
+    let mut monitor = Monitor::new(public_key.as_bytes()).with_source(ScriptedSource {
+        tree_head: tree_head.clone(),
+        directory: directory.clone(),
+    });
+    assert_eq!(Vec::<MonitorAlert>::new(), monitor.poll().await);
+
+    *tree_head.lock().unwrap() = directory.get_tree_head(1).await?;
+    let alerts = monitor.poll().await;
+    assert_eq!(
+        vec![MonitorAlert::Rollback {
+            source: "scripted".to_string(),
+            epoch: 1,
+            previous_epoch: 2,
+        }],
+        alerts
+    );
+    assert!(!alerts[0].is_split_view());
+
+    let mut tampered = directory.get_tree_head(2).await?;
+    tampered.epoch = 3;
+    *tree_head.lock().unwrap() = tampered;
+    let alerts = monitor.poll().await;
+    assert!(matches!(
+        alerts[..],
+        [MonitorAlert::InvalidTreeHead { epoch: 3, .. }]
+    ));
+    assert_eq!(Some(2), monitor.latest_tree_head().map(|head| head.epoch));
+    Ok(())
+}
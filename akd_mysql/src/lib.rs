@@ -14,6 +14,18 @@
 //! by the AKD directory logic at a good performance level. At reasonable scale, on a decent MySQL instance, one can expect publishing 100K records
 //! in approximately 10-20 minutes.
 //!
+//! # Upgrading
+//! The tables are created when the database is connected to, if they don't exist yet.
+//! The tables created by an earlier version of this crate remain readable:
+//! - The tree node tables, which held a column per field of the latest and previous
+//!   nodes, are read and written in that layout, while the tables created since store
+//!   each node in its compact encoding, in the `node` column. The legacy tables are
+//!   converted by calling [mysql::AsyncMySqlDatabase::migrate_tree_nodes], which alters
+//!   them, and so needs the `ALTER` privilege and the directory not to be published to
+//!   meanwhile.
+//! - The `expiry_epoch` column is added to the user data table on the first connection
+//!   after an upgrade, the values stored before it being left without an expiry.
+//!
 
 #![warn(missing_docs)]
 #![allow(clippy::multiple_crate_versions)]
@@ -23,5 +35,7 @@ pub mod mysql;
 
 pub mod mysql_storables;
 
+pub mod sharding;
+
 #[cfg(test)]
 mod mysql_db_tests;
@@ -7,14 +7,21 @@
 
 //! This module implements operations for a simple asynchronized mysql database
 
-use crate::mysql_storables::MySqlStorable;
+use crate::mysql_storables::{
+    legacy_tree_node_from_row, MySqlStorable, Tables, TreeNodeLayout, LEGACY_TREE_NODE_COLUMNS,
+};
+use crate::sharding::ShardMap;
 use akd::errors::StorageError;
-use akd::storage::types::{DbRecord, KeyData, StorageType, ValueState, ValueStateRetrievalFlag};
-use akd::storage::{Database, Storable};
+use akd::storage::types::{
+    DbRecord, KeyData, LabelMappingRecord, RootHashRecord, StorageType, ValueState,
+    ValueStateRetrievalFlag,
+};
+use akd::storage::{check_version, Database, RecordStream, Storable};
 use akd::tree_node::TreeNodeWithPreviousValue;
 use akd::NodeLabel;
-use akd::{AkdLabel, AkdValue};
+use akd::{AkdLabel, AkdValue, Azks};
 use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream, StreamExt};
 use log::{debug, error, info, warn};
 use mysql_async::prelude::*;
 use mysql_async::*;
@@ -28,11 +35,22 @@ use tokio::time::Instant;
 
 type MySqlError = mysql_async::Error;
 
-const TABLE_AZKS: &str = crate::mysql_storables::TABLE_AZKS;
-const TABLE_HISTORY_TREE_NODES: &str = crate::mysql_storables::TABLE_HISTORY_TREE_NODES;
-const TABLE_USER: &str = crate::mysql_storables::TABLE_USER;
 const TEMP_IDS_TABLE: &str = crate::mysql_storables::TEMP_IDS_TABLE;
 
+// The MySQL server error codes reported as transient storage errors
+const ER_CON_COUNT_ERROR: u16 = 1040;
+const ER_SERVER_SHUTDOWN: u16 = 1053;
+const ER_LOCK_WAIT_TIMEOUT: u16 = 1205;
+const ER_LOCK_DEADLOCK: u16 = 1213;
+const ER_QUERY_TIMEOUT: u16 = 3024;
+
+// The longest namespace whose tables' names fit in MySQL's 64 character limit,
+// as the tables are prefixed with "ns_" and the hex-encoded namespace
+const MAX_NAMESPACE_LEN: usize = 23;
+
+// The number of rows migrated to a new table layout at a time
+const MIGRATION_BATCH_SIZE: usize = 1000;
+
 const MAXIMUM_SQL_TIER_CONNECTION_TIMEOUT_SECS: u64 = 300;
 const SQL_RECONNECTION_DELAY_SECS: u64 = 5;
 
@@ -65,6 +83,14 @@ pub struct AsyncMySqlDatabase {
     write_call_stats: Arc<tokio::sync::RwLock<HashMap<String, u64>>>,
 
     tunable_insert_depth: usize,
+
+    shard_map: Arc<ShardMap>,
+    /// The prefix of the names of the tables of the namespace of this handle,
+    /// see [Database::with_namespace]
+    table_prefix: String,
+    /// The layout of the tree node table of each shard, detected when the tables
+    /// are set up
+    tree_node_layouts: Arc<std::sync::RwLock<Vec<TreeNodeLayout>>>,
 }
 
 impl std::fmt::Display for AsyncMySqlDatabase {
@@ -100,6 +126,10 @@ impl Clone for AsyncMySqlDatabase {
             write_call_stats: self.write_call_stats.clone(),
 
             tunable_insert_depth: self.tunable_insert_depth,
+
+            shard_map: self.shard_map.clone(),
+            table_prefix: self.table_prefix.clone(),
+            tree_node_layouts: self.tree_node_layouts.clone(),
         }
     }
 }
@@ -114,9 +144,33 @@ impl<'a> AsyncMySqlDatabase {
         password: Option<T>,
         port: Option<u16>,
         depth: usize,
+    ) -> Self {
+        Self::new_with_shard_map(
+            endpoint,
+            database,
+            user,
+            password,
+            port,
+            depth,
+            ShardMap::single(),
+        )
+        .await
+    }
+
+    /// Creates a new mysql database, where the tree nodes are partitioned
+    /// across tables according to the provided [ShardMap]. The same shard map
+    /// must be used every time the database is opened.
+    pub async fn new_with_shard_map<T: Into<String>>(
+        endpoint: T,
+        database: T,
+        user: Option<T>,
+        password: Option<T>,
+        port: Option<u16>,
+        depth: usize,
+        shard_map: ShardMap,
     ) -> Self {
         let dport = port.unwrap_or(3306u16);
-        let mut builder = OptsBuilder::default()
+        let builder = OptsBuilder::default()
             .ip_or_hostname(endpoint)
             .db_name(Option::from(database))
             .user(user)
@@ -129,7 +183,9 @@ impl<'a> AsyncMySqlDatabase {
         // Exception to issue 139. This call SHOULD panic if we cannot create a connection pool
         // object to fail the entire app. It'll fail very early as we need to create the db
         // prior to the directory
-        let pool = Self::new_connection_pool(&opts, &healthy).await.unwrap();
+        let (pool, tree_node_layouts) = Self::new_connection_pool(&opts, &healthy, &shard_map, "")
+            .await
+            .unwrap();
 
         Self {
             opts,
@@ -139,9 +195,33 @@ impl<'a> AsyncMySqlDatabase {
             write_call_stats: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
 
             tunable_insert_depth: depth,
+
+            shard_map: Arc::new(shard_map),
+            table_prefix: String::new(),
+            tree_node_layouts: Arc::new(std::sync::RwLock::new(tree_node_layouts)),
         }
     }
 
+    /// The partitioning of tree nodes across tables used by this database
+    pub fn shard_map(&self) -> &ShardMap {
+        &self.shard_map
+    }
+
+    /// The tables of the namespace of this handle, targeting the tree node table
+    /// of the given shard
+    fn tables(&self, shard: usize) -> Tables {
+        let layout = self.tree_node_layouts.read().unwrap()[shard];
+        Tables::new(&self.table_prefix, &self.shard_map.table_name(shard))
+            .with_tree_node_layout(layout)
+    }
+
+    /// The tree node tables of all the shards, in the namespace of this handle
+    fn tree_node_tables(&self) -> Vec<String> {
+        (0..self.shard_map.num_shards())
+            .map(|shard| self.tables(shard).tree_nodes().to_string())
+            .collect()
+    }
+
     /// Determine if the db connection is healthy at present
     pub async fn is_healthy(&self) -> bool {
         let is_healthy_guard = self.is_healthy.read().await;
@@ -228,8 +308,15 @@ impl<'a> AsyncMySqlDatabase {
         // Grab early write lock so no new queries can be initiated before
         // connection pool is refreshed.
         let mut connection_pool_guard = self.pool.write().await;
-        let pool = Self::new_connection_pool(&self.opts, &self.is_healthy).await?;
+        let (pool, tree_node_layouts) = Self::new_connection_pool(
+            &self.opts,
+            &self.is_healthy,
+            &self.shard_map,
+            &self.table_prefix,
+        )
+        .await?;
         *connection_pool_guard = pool;
+        *self.tree_node_layouts.write().unwrap() = tree_node_layouts;
 
         Ok(())
     }
@@ -237,7 +324,9 @@ impl<'a> AsyncMySqlDatabase {
     async fn new_connection_pool(
         opts: &mysql_async::Opts,
         is_healthy: &Arc<tokio::sync::RwLock<bool>>,
-    ) -> core::result::Result<mysql_async::Pool, StorageError> {
+        shard_map: &ShardMap,
+        table_prefix: &str,
+    ) -> core::result::Result<(mysql_async::Pool, Vec<TreeNodeLayout>), StorageError> {
         let start = Instant::now();
         let mut attempts = 1;
 
@@ -248,13 +337,13 @@ impl<'a> AsyncMySqlDatabase {
             let conn = pool.get_conn().await;
 
             if let Ok(_conn) = conn {
-                match Self::setup_database(_conn).await {
-                    Ok(()) => {
+                match Self::setup_database(_conn, shard_map, table_prefix).await {
+                    Ok(tree_node_layouts) => {
                         // set the healthy flag to true
                         let mut is_healthy_guard = is_healthy.write().await;
                         *is_healthy_guard = true;
 
-                        return Ok(pool);
+                        return Ok((pool, tree_node_layouts));
                     }
                     Err(_err) => {
                         #[cfg(test)]
@@ -271,7 +360,7 @@ impl<'a> AsyncMySqlDatabase {
                     start.elapsed().as_secs()
                 );
                 error!("{}", message);
-                return Err(StorageError::Connection(message));
+                return Err(StorageError::Timeout(message));
             }
 
             warn!(
@@ -290,47 +379,193 @@ impl<'a> AsyncMySqlDatabase {
         }
     }
 
-    async fn setup_database(mut conn: mysql_async::Conn) -> core::result::Result<(), MySqlError> {
+    /// Creates the tables which don't exist yet, and returns the layout of the
+    /// tree node table of each shard
+    async fn setup_database(
+        mut conn: mysql_async::Conn,
+        shard_map: &ShardMap,
+        table_prefix: &str,
+    ) -> core::result::Result<Vec<TreeNodeLayout>, MySqlError> {
+        let tables = Tables::new(table_prefix, &shard_map.table_name(0));
         let mut tx: mysql_async::Transaction<'_> =
             conn.start_transaction(TxOpts::default()).await?;
         // AZKS table
         let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
-            + TABLE_AZKS
+            + &tables.azks()
             + "` (`key` SMALLINT UNSIGNED NOT NULL, `epoch` BIGINT UNSIGNED NOT NULL,"
             + " `num_nodes` BIGINT UNSIGNED NOT NULL, PRIMARY KEY (`key`))";
         tx.query_drop(command).await?;
 
-        // History tree nodes table
+        // History tree nodes table(s), one per shard. The tables created by an earlier
+        // version of this crate keep their legacy layout, until they're migrated with
+        // [Self::migrate_tree_nodes]
+        let mut tree_node_layouts = Vec::with_capacity(shard_map.num_shards());
+        for table in shard_map.table_names() {
+            let tables = Tables::new(table_prefix, &table);
+            let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
+                + tables.tree_nodes()
+                + "` (`label_len` INT UNSIGNED NOT NULL, `label_val` VARBINARY(32) NOT NULL,"
+                + " `node` VARBINARY("
+                + &TreeNodeWithPreviousValue::MAX_ENCODED_LEN.to_string()
+                + ") NOT NULL,"
+                + " PRIMARY KEY (`label_len`, `label_val`))";
+            tx.query_drop(command).await?;
+            tree_node_layouts.push(
+                if Self::has_column(&mut tx, tables.tree_nodes(), "last_epoch").await? {
+                    TreeNodeLayout::Legacy
+                } else {
+                    TreeNodeLayout::Compact
+                },
+            );
+        }
+
+        // User data table
+        let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
+            + &tables.users()
+            + "` (`username` VARBINARY(256) NOT NULL, `epoch` BIGINT UNSIGNED NOT NULL, `version` BIGINT UNSIGNED NOT NULL,"
+            + " `node_label_val` VARBINARY(32) NOT NULL, `node_label_len` INT UNSIGNED NOT NULL, `data` VARBINARY(2000),"
+            + " `expiry_epoch` BIGINT UNSIGNED, PRIMARY KEY(`username`, `epoch`))";
+        tx.query_drop(command).await?;
+        // the table may have been created before values could expire
+        if !Self::has_column(&mut tx, &tables.users(), "expiry_epoch").await? {
+            info!("Adding the expiry epoch column to {}", tables.users());
+            let command = "ALTER TABLE `".to_owned()
+                + &tables.users()
+                + "` ADD COLUMN `expiry_epoch` BIGINT UNSIGNED NULL";
+            tx.query_drop(command).await?;
+        }
+
+        // Signed tree heads table
         let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
-            + TABLE_HISTORY_TREE_NODES
-            + "` (`label_len` INT UNSIGNED NOT NULL, `label_val` VARBINARY(32) NOT NULL,"
-            + " `last_epoch` BIGINT UNSIGNED NOT NULL,"
-            + " `least_descendant_ep` BIGINT UNSIGNED NOT NULL, `parent_label_len` INT UNSIGNED NOT NULL,"
-            + " `parent_label_val` VARBINARY(32) NOT NULL, `node_type` SMALLINT UNSIGNED NOT NULL,"
-            + " `left_child_len` INT UNSIGNED, `left_child_label_val` VARBINARY(32),"
-            + " `right_child_len` INT UNSIGNED, `right_child_label_val` VARBINARY(32), `hash` VARBINARY("
+            + &tables.tree_heads()
+            + "` (`epoch` BIGINT UNSIGNED NOT NULL, `root_hash` VARBINARY("
             + &akd::DIGEST_BYTES.to_string()
-            + ") NOT NULL,"
-            + " `p_last_epoch` BIGINT UNSIGNED, `p_least_descendant_ep` BIGINT UNSIGNED, "
-            + " `p_parent_label_len` INT UNSIGNED, `p_parent_label_val` VARBINARY(32), "
-            + " `p_node_type` SMALLINT UNSIGNED, `p_left_child_len` INT UNSIGNED, `p_left_child_label_val` VARBINARY(32), "
-            + " `p_right_child_len` INT UNSIGNED, `p_right_child_label_val` VARBINARY(32), `p_hash` VARBINARY("
+            + ") NOT NULL, `timestamp` BIGINT UNSIGNED NOT NULL, `signature` VARBINARY(64) NOT NULL,"
+            + " PRIMARY KEY(`epoch`))";
+        tx.query_drop(command).await?;
+
+        // Root hash archive table
+        let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
+            + &tables.root_hashes()
+            + "` (`epoch` BIGINT UNSIGNED NOT NULL, `root_hash` VARBINARY("
             + &akd::DIGEST_BYTES.to_string()
-            + "),"
-            + " PRIMARY KEY (`label_len`, `label_val`))";
+            + ") NOT NULL, `timestamp` BIGINT UNSIGNED NOT NULL, `batch_size` BIGINT UNSIGNED NOT NULL,"
+            + " `metadata` BLOB NOT NULL,"
+            + " PRIMARY KEY(`epoch`))";
         tx.query_drop(command).await?;
 
-        // User data table
+        // Label mapping table
         let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
-            + TABLE_USER
-            + "` (`username` VARBINARY(256) NOT NULL, `epoch` BIGINT UNSIGNED NOT NULL, `version` BIGINT UNSIGNED NOT NULL,"
-            + " `node_label_val` VARBINARY(32) NOT NULL, `node_label_len` INT UNSIGNED NOT NULL, `data` VARBINARY(2000),"
-            + " PRIMARY KEY(`username`, `epoch`))";
+            + &tables.label_mapping()
+            + "` (`key` SMALLINT UNSIGNED NOT NULL, `mapper_id` VARBINARY(2000) NOT NULL,"
+            + " PRIMARY KEY (`key`))";
         tx.query_drop(command).await?;
 
         // if we got here, we're good to commit. Transaction's will auto-rollback when memory freed if commit wasn't done.
         tx.commit().await?;
-        Ok(())
+        Ok(tree_node_layouts)
+    }
+
+    /// Whether the table has the column, in the database of the connection
+    async fn has_column<Q: Queryable>(
+        conn: &mut Q,
+        table: &str,
+        column: &str,
+    ) -> core::result::Result<bool, MySqlError> {
+        let count: Option<u64> = conn
+            .exec_first(
+                "SELECT COUNT(*) FROM information_schema.COLUMNS WHERE `TABLE_SCHEMA` = DATABASE()
+                AND `TABLE_NAME` = :table AND `COLUMN_NAME` = :column",
+                params! { "table" => table, "column" => column },
+            )
+            .await?;
+        Ok(count.unwrap_or(0) > 0)
+    }
+
+    /// Migrates the tree node tables created by an earlier version of this crate, with a
+    /// column per field of the latest and previous nodes, to store each node in its
+    /// compact encoding, in the `node` column. Returns the number of nodes migrated, none
+    /// if the tables already are in the compact layout.
+    ///
+    /// The tables are otherwise read and written in their legacy layout, so the migration
+    /// is only run on request: it alters the tables, so the database user needs the `ALTER`
+    /// privilege, and the directory must not be published to in the meantime. The other
+    /// handles to the database keep using the legacy layout until they're reconnected. The
+    /// nodes are re-encoded in batches, so that an interrupted migration can be run again.
+    pub async fn migrate_tree_nodes(&self) -> core::result::Result<u64, MySqlError> {
+        let mut conn = self.get_connection().await?;
+        let mut num_migrated = 0;
+        for shard in 0..self.shard_map.num_shards() {
+            let tables = self.tables(shard);
+            let table = tables.tree_nodes();
+            if !Self::has_column(&mut conn, table, "last_epoch").await? {
+                self.tree_node_layouts.write().unwrap()[shard] = TreeNodeLayout::Compact;
+                continue;
+            }
+            info!(
+                "Migrating the tree nodes of {} to their compact encoding",
+                table
+            );
+            if !Self::has_column(&mut conn, table, "node").await? {
+                let command = format!(
+                    "ALTER TABLE `{}` ADD COLUMN `node` VARBINARY({}) NULL",
+                    table,
+                    TreeNodeWithPreviousValue::MAX_ENCODED_LEN
+                );
+                conn.query_drop(command).await?;
+            }
+
+            // the nodes are visited in the order of their labels, which key the table
+            let select = format!(
+                "SELECT `label_len`, `label_val`, `{}` FROM `{}`
+                WHERE (`label_len`, `label_val`) > (:label_len, :label_val)
+                ORDER BY `label_len`, `label_val` LIMIT {}",
+                LEGACY_TREE_NODE_COLUMNS.join("`, `"),
+                table,
+                MIGRATION_BATCH_SIZE
+            );
+            let update = format!(
+                "UPDATE `{}` SET `node` = :node WHERE `label_len` = :label_len AND `label_val` = :label_val",
+                table
+            );
+            let mut last = (0u32, vec![]);
+            loop {
+                let rows: Vec<Row> = conn
+                    .exec(
+                        select.as_str(),
+                        params! { "label_len" => last.0, "label_val" => last.1.clone() },
+                    )
+                    .await?;
+                if rows.is_empty() {
+                    break;
+                }
+                let mut params = Vec::with_capacity(rows.len());
+                for mut row in rows {
+                    let node = legacy_tree_node_from_row(&mut row)?;
+                    last = (node.label.label_len, node.label.label_val.to_vec());
+                    params.push(params! {
+                        "label_len" => node.label.label_len,
+                        "label_val" => node.label.label_val,
+                        "node" => node.to_bytes(),
+                    });
+                }
+                num_migrated += params.len() as u64;
+                let mut tx = conn.start_transaction(TxOpts::default()).await?;
+                tx.exec_batch(update.as_str(), params).await?;
+                tx.commit().await?;
+            }
+
+            let command = format!(
+                "ALTER TABLE `{}` MODIFY `node` VARBINARY({}) NOT NULL, DROP COLUMN `{}`",
+                table,
+                TreeNodeWithPreviousValue::MAX_ENCODED_LEN,
+                LEGACY_TREE_NODE_COLUMNS.join("`, DROP COLUMN `")
+            );
+            conn.query_drop(command).await?;
+            self.tree_node_layouts.write().unwrap()[shard] = TreeNodeLayout::Compact;
+            info!("Migrated the tree nodes of {}", table);
+        }
+        Ok(num_migrated)
     }
 
     /// Delete all the data in the tables
@@ -338,15 +573,27 @@ impl<'a> AsyncMySqlDatabase {
         let mut conn = self.get_connection().await?;
         let mut tx = conn.start_transaction(TxOpts::default()).await?;
 
-        let command = "DELETE FROM `".to_owned() + TABLE_AZKS + "`";
+        let tables = self.tables(0);
+        let command = "DELETE FROM `".to_owned() + &tables.azks() + "`";
+        tx.query_drop(command).await?;
+
+        let command = "DELETE FROM `".to_owned() + &tables.users() + "`";
         tx.query_drop(command).await?;
 
-        let command = "DELETE FROM `".to_owned() + TABLE_USER + "`";
+        let command = "DELETE FROM `".to_owned() + &tables.tree_heads() + "`";
         tx.query_drop(command).await?;
 
-        let command = "DELETE FROM `".to_owned() + TABLE_HISTORY_TREE_NODES + "`";
+        let command = "DELETE FROM `".to_owned() + &tables.root_hashes() + "`";
         tx.query_drop(command).await?;
 
+        let command = "DELETE FROM `".to_owned() + &tables.label_mapping() + "`";
+        tx.query_drop(command).await?;
+
+        for table in self.tree_node_tables() {
+            let command = "DELETE FROM `".to_owned() + &table + "`";
+            tx.query_drop(command).await?;
+        }
+
         tx.commit().await?;
 
         Ok(())
@@ -357,15 +604,27 @@ impl<'a> AsyncMySqlDatabase {
         let mut conn = self.get_connection().await?;
         let mut tx = conn.start_transaction(TxOpts::default()).await?;
 
-        let command = "DROP TABLE IF EXISTS `".to_owned() + TABLE_AZKS + "`";
+        let tables = self.tables(0);
+        let command = "DROP TABLE IF EXISTS `".to_owned() + &tables.azks() + "`";
+        tx.query_drop(command).await?;
+
+        let command = "DROP TABLE IF EXISTS `".to_owned() + &tables.users() + "`";
         tx.query_drop(command).await?;
 
-        let command = "DROP TABLE IF EXISTS `".to_owned() + TABLE_USER + "`";
+        let command = "DROP TABLE IF EXISTS `".to_owned() + &tables.tree_heads() + "`";
         tx.query_drop(command).await?;
 
-        let command = "DROP TABLE IF EXISTS `".to_owned() + TABLE_HISTORY_TREE_NODES + "`";
+        let command = "DROP TABLE IF EXISTS `".to_owned() + &tables.root_hashes() + "`";
         tx.query_drop(command).await?;
 
+        let command = "DROP TABLE IF EXISTS `".to_owned() + &tables.label_mapping() + "`";
+        tx.query_drop(command).await?;
+
+        for table in self.tree_node_tables() {
+            let command = "DROP TABLE IF EXISTS `".to_owned() + &table + "`";
+            tx.query_drop(command).await?;
+        }
+
         tx.commit().await?;
 
         Ok(())
@@ -380,9 +639,13 @@ impl<'a> AsyncMySqlDatabase {
         self.record_call_stats('w', "internal_set".to_string(), "".to_string())
             .await;
 
-        let statement_text = record.set_statement();
+        let tables = match &record {
+            DbRecord::TreeNode(node) => self.tables(self.shard_map.shard_for_label(&node.label)),
+            _ => self.tables(0),
+        };
+        let statement_text = record.set_statement(&tables);
         let params = record
-            .set_params()
+            .set_params(&tables)
             .ok_or_else(|| Error::Other("Failed to construct MySQL parameters block".into()))?;
 
         let out = match trans {
@@ -400,10 +663,12 @@ impl<'a> AsyncMySqlDatabase {
         Ok(())
     }
 
-    /// NOTE: This is assuming all of the DB records have been narrowed down to a single record type!
+    /// NOTE: This is assuming all of the DB records have been narrowed down to a single record type,
+    /// and tree nodes to a single shard, whose tables are provided
     async fn internal_batch_set(
         &self,
         records: Vec<DbRecord>,
+        tables: &Tables,
         mut trans: mysql_async::Transaction<'a>,
     ) -> core::result::Result<mysql_async::Transaction<'a>, MySqlError> {
         if records.is_empty() {
@@ -420,10 +685,10 @@ impl<'a> AsyncMySqlDatabase {
                 if batch.is_empty() {
                     Ok(BatchMode::None)
                 } else if batch.len() < self.tunable_insert_depth {
-                    DbRecord::set_batch_params(batch)
+                    DbRecord::set_batch_params(batch, tables)
                         .map(|out| BatchMode::Partial(out, batch.len()))
                 } else {
-                    DbRecord::set_batch_params(batch).map(BatchMode::Full)
+                    DbRecord::set_batch_params(batch, tables).map(BatchMode::Full)
                 }
             })
             .collect::<Result<Vec<_>>>()?;
@@ -431,12 +696,19 @@ impl<'a> AsyncMySqlDatabase {
         let head = &records[0];
         let statement = |i: usize| -> String {
             match &head {
-                DbRecord::Azks(_) => DbRecord::set_batch_statement::<akd::Azks>(i),
+                DbRecord::Azks(_) => DbRecord::set_batch_statement::<akd::Azks>(i, tables),
                 DbRecord::TreeNode(_) => {
-                    DbRecord::set_batch_statement::<TreeNodeWithPreviousValue>(i)
+                    DbRecord::set_batch_statement::<TreeNodeWithPreviousValue>(i, tables)
                 }
                 DbRecord::ValueState(_) => {
-                    DbRecord::set_batch_statement::<akd::storage::types::ValueState>(i)
+                    DbRecord::set_batch_statement::<akd::storage::types::ValueState>(i, tables)
+                }
+                DbRecord::TreeHead(_) => {
+                    DbRecord::set_batch_statement::<akd::SignedTreeHead>(i, tables)
+                }
+                DbRecord::RootHash(_) => DbRecord::set_batch_statement::<RootHashRecord>(i, tables),
+                DbRecord::LabelMapping(_) => {
+                    DbRecord::set_batch_statement::<LabelMappingRecord>(i, tables)
                 }
             }
         };
@@ -579,93 +851,141 @@ impl<'a> AsyncMySqlDatabase {
         false
     }
 
-    async fn get_direct<St: Storable>(
+    /// Retrieve a batch of records by id from a single table, where the
+    /// records are tree nodes of a single shard
+    async fn internal_batch_get<St: Storable>(
         &self,
-        id: &St::StorageKey,
-    ) -> core::result::Result<DbRecord, StorageError> {
-        self.record_call_stats(
-            'r',
-            "get_direct:".to_string(),
-            format!("{:?}", St::data_type()),
-        )
-        .await;
+        ids: &[St::StorageKey],
+        tables: &Tables,
+    ) -> core::result::Result<Vec<DbRecord>, MySqlError> {
+        let key_set_vec: Vec<_> = ids.to_vec();
 
-        let result = async {
-            let mut conn = self.get_connection().await?;
-            let statement = DbRecord::get_specific_statement::<St>();
-            let params = DbRecord::get_specific_params::<St>(id);
-            let out = match params {
-                Some(p) => match conn.exec_first(statement, p).await {
-                    Err(err) => Err(err),
-                    Ok(result) => Ok(result),
-                },
-                None => match conn.query_first(statement).await {
-                    Err(err) => Err(err),
-                    Ok(result) => Ok(result),
-                },
-            };
+        let mut conn = self.get_connection().await?;
 
-            let result = self.check_for_infra_error(out)?;
-            if let Some(mut row) = result {
-                // return result
-                let record = DbRecord::from_row::<St>(&mut row)?;
-                return Ok::<Option<DbRecord>, MySqlError>(Some(record));
+        let results = if let Some(create_table_cmd) = DbRecord::get_batch_create_temp_table::<St>()
+        {
+            // Create the temp table of ids
+            let out = conn.query_drop(create_table_cmd).await;
+            self.check_for_infra_error(out)?;
+
+            // Fill temp table with the requested ids
+            let mut tx = conn.start_transaction(TxOpts::default()).await?;
+            tx.query_drop("SET autocommit=0").await?;
+            tx.query_drop("SET unique_checks=0").await?;
+            tx.query_drop("SET foreign_key_checks=0").await?;
+
+            let mut fallout: Option<Vec<_>> = None;
+            let mut params = vec![];
+            for batch in key_set_vec.chunks(self.tunable_insert_depth) {
+                if batch.len() < self.tunable_insert_depth {
+                    fallout = Some(batch.to_vec());
+                } else if let Some(p) = DbRecord::get_multi_row_specific_params::<St>(batch) {
+                    params.push(p);
+                } else {
+                    return Err(MySqlError::Other(
+                        "Unable to generate type-specific MySQL parameters".into(),
+                    ));
+                }
             }
-            Ok::<Option<DbRecord>, MySqlError>(None)
-        };
 
-        match result.await {
-            Ok(Some(r)) => Ok(r),
-            Ok(None) => Err(StorageError::NotFound(format!(
-                "{:?} {:?}",
-                St::data_type(),
-                id
-            ))),
-            Err(error) => {
-                error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {}", error)))
+            // insert the batches of size = MYSQL_EXTENDED_INSERT_DEPTH
+            if !params.is_empty() {
+                let fill_statement =
+                    DbRecord::get_batch_fill_temp_table::<St>(Some(self.tunable_insert_depth));
+                let out = tx.exec_batch(fill_statement, params).await;
+                self.check_for_infra_error(out)?;
+                // We would need the statement for it. (Possibly) No need for close here.
+                // See https://docs.rs/mysql_async/0.28.1/mysql_async/struct.Opts.html#caveats.
+                // tx.close().await?;
             }
-        }
-    }
-}
 
-#[async_trait]
-impl Database for AsyncMySqlDatabase {
-    /// Storage a record in the data layer
-    async fn set(&self, record: DbRecord) -> core::result::Result<(), StorageError> {
-        match self.internal_set(record, None).await {
-            Ok(_) => Ok(()),
-            Err(error) => {
-                error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {}", error)))
+            // insert the remainder as a final statement
+            if let Some(remainder) = fallout {
+                let remainder_stmt =
+                    DbRecord::get_batch_fill_temp_table::<St>(Some(remainder.len()));
+                let params_batch = DbRecord::get_multi_row_specific_params::<St>(&remainder);
+                if let Some(pb) = params_batch {
+                    let out = tx.exec_drop(remainder_stmt, pb).await;
+                    self.check_for_infra_error(out)?;
+                } else {
+                    return Err(MySqlError::Other(
+                        "Unable to generate type-specific MySQL parameters".into(),
+                    ));
+                }
             }
-        }
+
+            tx.query_drop("SET autocommit=1").await?;
+            tx.query_drop("SET unique_checks=1").await?;
+            tx.query_drop("SET foreign_key_checks=1").await?;
+            tx.commit().await?;
+
+            // Query the records which intersect (INNER JOIN) with the temp table of ids
+            let query = DbRecord::get_batch_statement::<St>(tables);
+            let out = conn.query_iter(query).await;
+            let result = self.check_for_infra_error(out)?;
+
+            let out = result
+                .reduce_and_drop(vec![], |mut acc, mut row| {
+                    if let Ok(result) = DbRecord::from_row::<St>(&mut row) {
+                        acc.push(result);
+                    }
+                    acc
+                })
+                .await?;
+
+            // drop the temp table of ids
+            let t_out = conn
+                .query_drop(format!("DROP TEMPORARY TABLE `{}`", TEMP_IDS_TABLE))
+                .await;
+            self.check_for_infra_error(t_out)?;
+
+            out
+        } else {
+            // no results (i.e. AZKS table doesn't support "get by batch ids")
+            vec![]
+        };
+
+        Ok(results)
     }
 
-    async fn batch_set(
+    /// Writes the records in a single transaction, grouped by type (and tree nodes
+    /// by shard). If an expected version is given, the records include an azks,
+    /// which is only written, along with the other records, if the version of the
+    /// stored azks matches, see [Database::compare_and_set]
+    async fn internal_batch_set_checked(
         &self,
         records: Vec<DbRecord>,
-        _state: akd::storage::DbSetState,
+        expected_version: Option<Option<u64>>,
     ) -> core::result::Result<(), StorageError> {
-        if records.is_empty() {
-            // nothing to do, save the cycles
-            return Ok(());
-        }
-
-        // generate batches by type
+        // generate batches by type, and tree nodes by shard
         let mut groups = std::collections::HashMap::new();
         for record in records {
             match &record {
                 DbRecord::Azks(_) => groups
-                    .entry(StorageType::Azks)
+                    .entry((StorageType::Azks, 0))
                     .or_insert_with(Vec::new)
                     .push(record),
-                DbRecord::TreeNode(_) => groups
-                    .entry(StorageType::TreeNode)
+                DbRecord::TreeNode(node) => groups
+                    .entry((
+                        StorageType::TreeNode,
+                        self.shard_map.shard_for_label(&node.label),
+                    ))
                     .or_insert_with(Vec::new)
                     .push(record),
                 DbRecord::ValueState(_) => groups
-                    .entry(StorageType::ValueState)
+                    .entry((StorageType::ValueState, 0))
+                    .or_insert_with(Vec::new)
+                    .push(record),
+                DbRecord::TreeHead(_) => groups
+                    .entry((StorageType::TreeHead, 0))
+                    .or_insert_with(Vec::new)
+                    .push(record),
+                DbRecord::RootHash(_) => groups
+                    .entry((StorageType::RootHash, 0))
+                    .or_insert_with(Vec::new)
+                    .push(record),
+                DbRecord::LabelMapping(_) => groups
+                    .entry((StorageType::LabelMapping, 0))
                     .or_insert_with(Vec::new)
                     .push(record),
             }
@@ -680,7 +1000,28 @@ impl Database for AsyncMySqlDatabase {
             tx.query_drop("SET unique_checks=0").await?;
             tx.query_drop("SET foreign_key_checks=0").await?;
 
-            for (_key, mut value) in groups.into_iter() {
+            // the stored azks is locked until the transaction completes, so that no
+            // other writer can update it in between the check and the writes
+            if let Some(expected_version) = expected_version {
+                let statement =
+                    DbRecord::get_specific_statement::<Azks>(&self.tables(0)) + " FOR UPDATE";
+                let out = tx.query_first(statement).await;
+                let stored = match self.check_for_infra_error(out)? {
+                    Some(mut row) => Some(DbRecord::from_row::<Azks>(&mut row)?),
+                    None => None,
+                };
+                let azks = groups
+                    .get(&(StorageType::Azks, 0))
+                    .and_then(|records| records.last());
+                if let Some(azks) = azks {
+                    if let Err(err) = check_version(azks, expected_version, stored.as_ref()) {
+                        tx.rollback().await?;
+                        return Ok(Err(err));
+                    }
+                }
+            }
+
+            for ((_, shard), mut value) in groups.into_iter() {
                 if !value.is_empty() {
                     // Sort the records to match db-layer sorting which will help with insert performance
                     value.sort_by(|a, b| match &a {
@@ -704,7 +1045,9 @@ impl Database for AsyncMySqlDatabase {
                         _ => Ordering::Equal,
                     });
                     // execute the multi-batch insert statement(s)
-                    tx = self.internal_batch_set(value, tx).await?;
+                    tx = self
+                        .internal_batch_set(value, &self.tables(shard), tx)
+                        .await?;
                 }
             }
 
@@ -713,17 +1056,216 @@ impl Database for AsyncMySqlDatabase {
             tx.query_drop("SET foreign_key_checks=1").await?;
 
             tx.commit().await?;
-            Ok::<(), MySqlError>(())
+            Ok::<_, MySqlError>(Ok(()))
         };
         match result.await {
+            Ok(result) => result,
+            Err(error) => {
+                error!("MySQL error {}", error);
+                Err(to_storage_error(error))
+            }
+        }
+    }
+
+    async fn get_direct<St: Storable>(
+        &self,
+        id: &St::StorageKey,
+    ) -> core::result::Result<DbRecord, StorageError> {
+        self.record_call_stats(
+            'r',
+            "get_direct:".to_string(),
+            format!("{:?}", St::data_type()),
+        )
+        .await;
+
+        let result = async {
+            let mut conn = self.get_connection().await?;
+            let tables = self.tables(self.shard_map.shard_for_key::<St>(id));
+            let statement = DbRecord::get_specific_statement::<St>(&tables);
+            let params = DbRecord::get_specific_params::<St>(id);
+            let out = match params {
+                Some(p) => match conn.exec_first(statement, p).await {
+                    Err(err) => Err(err),
+                    Ok(result) => Ok(result),
+                },
+                None => match conn.query_first(statement).await {
+                    Err(err) => Err(err),
+                    Ok(result) => Ok(result),
+                },
+            };
+
+            let result = self.check_for_infra_error(out)?;
+            if let Some(mut row) = result {
+                // return result
+                let record = DbRecord::from_row::<St>(&mut row)?;
+                return Ok::<Option<DbRecord>, MySqlError>(Some(record));
+            }
+            Ok::<Option<DbRecord>, MySqlError>(None)
+        };
+
+        match result.await {
+            Ok(Some(r)) => Ok(r),
+            Ok(None) => Err(StorageError::NotFound(format!(
+                "{:?} {:?}",
+                St::data_type(),
+                id
+            ))),
+            Err(error) => {
+                error!("MySQL error {}", error);
+                Err(to_storage_error(error))
+            }
+        }
+    }
+}
+
+/// Converts a MySQL error into a storage error. The failures of the connection
+/// to the database, and the server errors which may resolve themselves, are
+/// reported as [StorageError::Connection], [StorageError::Transient] or
+/// [StorageError::Timeout] so that they can be told apart as transient
+fn to_storage_error(error: MySqlError) -> StorageError {
+    let message = format!("MySQL Error {}", error);
+    match error {
+        MySqlError::Driver(mysql_async::DriverError::FromRow { .. }) => {
+            StorageError::Corruption(message)
+        }
+        MySqlError::Driver(_) | MySqlError::Io(_) => StorageError::Connection(message),
+        MySqlError::Server(server_error) => match server_error.code {
+            ER_LOCK_WAIT_TIMEOUT | ER_QUERY_TIMEOUT => StorageError::Timeout(message),
+            ER_CON_COUNT_ERROR | ER_SERVER_SHUTDOWN | ER_LOCK_DEADLOCK => {
+                StorageError::Transient(message)
+            }
+            _ => StorageError::Other(message),
+        },
+        _ => StorageError::Other(message),
+    }
+}
+
+/// The smallest key which is greater than every key starting with the prefix,
+/// or None if there is no such key (i.e. the prefix is all 0xFF)
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut upper = prefix.to_vec();
+    while let Some(last) = upper.pop() {
+        if last < u8::MAX {
+            upper.push(last + 1);
+            return Some(upper);
+        }
+    }
+    None
+}
+
+impl AsyncMySqlDatabase {
+    /// The shards which may hold tree nodes whose label value starts with the prefix
+    fn shards_for_prefix(&self, key_prefix: &[u8]) -> Vec<usize> {
+        match key_prefix.first() {
+            None => (0..self.shard_map.num_shards()).collect(),
+            // labels are 32 bytes, so longer prefixes can't match any node
+            Some(_) if key_prefix.len() > 32 => vec![],
+            Some(first_byte) => {
+                let mut label_val = [0u8; 32];
+                label_val[0] = *first_byte;
+                vec![self
+                    .shard_map
+                    .shard_for_label(&NodeLabel::new(label_val, 256))]
+            }
+        }
+    }
+
+    /// Retrieve the records of a single table whose key starts with the prefix, with a
+    /// range scan of the table. The rows are streamed off a connection which is held
+    /// until the stream is consumed or dropped.
+    async fn internal_get_by_prefix<St: Storable>(
+        &self,
+        tables: &Tables,
+        key_prefix: &[u8],
+    ) -> core::result::Result<
+        BoxStream<'static, core::result::Result<DbRecord, MySqlError>>,
+        MySqlError,
+    > {
+        self.record_call_stats(
+            'r',
+            "get_by_prefix:".to_string(),
+            format!("{:?}", St::data_type()),
+        )
+        .await;
+
+        let conn = self.get_connection().await?;
+        let upper_bound = prefix_upper_bound(key_prefix);
+        let statement = DbRecord::get_prefix_statement::<St>(tables, upper_bound.is_some());
+        let params: Params = match (St::data_type(), upper_bound) {
+            // the azks, tree heads, root hashes and label mapping have an empty key, so only match the
+            // empty prefix
+            (
+                StorageType::Azks
+                | StorageType::TreeHead
+                | StorageType::RootHash
+                | StorageType::LabelMapping,
+                _,
+            ) if !key_prefix.is_empty() => return Ok(stream::empty().boxed()),
+            (
+                StorageType::Azks
+                | StorageType::TreeHead
+                | StorageType::RootHash
+                | StorageType::LabelMapping,
+                _,
+            ) => Params::Empty,
+            (_, Some(upper)) => params! { "lower" => key_prefix, "upper" => upper },
+            (_, None) => params! { "lower" => key_prefix },
+        };
+        // the connection is given to the stream, so that it outlives this call
+        let out = statement.with(params).stream::<Row, _>(conn).await;
+        let rows = self.check_for_infra_error(out)?;
+
+        Ok(rows
+            .filter_map(|row| async move {
+                match row {
+                    Ok(mut row) => DbRecord::from_row::<St>(&mut row).ok().map(Ok),
+                    Err(err) => Some(Err(err)),
+                }
+            })
+            .boxed())
+    }
+}
+
+#[async_trait]
+impl Database for AsyncMySqlDatabase {
+    /// Storage a record in the data layer
+    async fn set(&self, record: DbRecord) -> core::result::Result<(), StorageError> {
+        match self.internal_set(record, None).await {
             Ok(_) => Ok(()),
             Err(error) => {
                 error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {}", error)))
+                Err(to_storage_error(error))
             }
         }
     }
 
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        _state: akd::storage::DbSetState,
+    ) -> core::result::Result<(), StorageError> {
+        if records.is_empty() {
+            // nothing to do, save the cycles
+            return Ok(());
+        }
+        self.internal_batch_set_checked(records, None).await
+    }
+
+    async fn compare_and_set(
+        &self,
+        record: DbRecord,
+        expected_version: Option<u64>,
+        mut others: Vec<DbRecord>,
+        _state: akd::storage::DbSetState,
+    ) -> core::result::Result<(), StorageError> {
+        if !matches!(record, DbRecord::Azks(_)) {
+            return check_version(&record, expected_version, None);
+        }
+        others.push(record);
+        self.internal_batch_set_checked(others, Some(expected_version))
+            .await
+    }
+
     /// Retrieve a stored record from the data layer
     async fn get<St: Storable>(
         &self,
@@ -744,95 +1286,24 @@ impl Database for AsyncMySqlDatabase {
             return Ok(map);
         }
 
-        let result = async {
-            let key_set_vec: Vec<_> = ids.to_vec();
-
-            let mut conn = self.get_connection().await?;
-
-            let results = if let Some(create_table_cmd) =
-                DbRecord::get_batch_create_temp_table::<St>()
-            {
-                // Create the temp table of ids
-                let out = conn.query_drop(create_table_cmd).await;
-                self.check_for_infra_error(out)?;
-
-                // Fill temp table with the requested ids
-                let mut tx = conn.start_transaction(TxOpts::default()).await?;
-                tx.query_drop("SET autocommit=0").await?;
-                tx.query_drop("SET unique_checks=0").await?;
-                tx.query_drop("SET foreign_key_checks=0").await?;
-
-                let mut fallout: Option<Vec<_>> = None;
-                let mut params = vec![];
-                for batch in key_set_vec.chunks(self.tunable_insert_depth) {
-                    if batch.len() < self.tunable_insert_depth {
-                        fallout = Some(batch.to_vec());
-                    } else if let Some(p) = DbRecord::get_multi_row_specific_params::<St>(batch) {
-                        params.push(p);
-                    } else {
-                        return Err(MySqlError::Other(
-                            "Unable to generate type-specific MySQL parameters".into(),
-                        ));
-                    }
-                }
-
-                // insert the batches of size = MYSQL_EXTENDED_INSERT_DEPTH
-                if !params.is_empty() {
-                    let fill_statement =
-                        DbRecord::get_batch_fill_temp_table::<St>(Some(self.tunable_insert_depth));
-                    let out = tx.exec_batch(fill_statement, params).await;
-                    self.check_for_infra_error(out)?;
-                    // We would need the statement for it. (Possibly) No need for close here.
-                    // See https://docs.rs/mysql_async/0.28.1/mysql_async/struct.Opts.html#caveats.
-                    // tx.close().await?;
-                }
-
-                // insert the remainder as a final statement
-                if let Some(remainder) = fallout {
-                    let remainder_stmt =
-                        DbRecord::get_batch_fill_temp_table::<St>(Some(remainder.len()));
-                    let params_batch = DbRecord::get_multi_row_specific_params::<St>(&remainder);
-                    if let Some(pb) = params_batch {
-                        let out = tx.exec_drop(remainder_stmt, pb).await;
-                        self.check_for_infra_error(out)?;
-                    } else {
-                        return Err(MySqlError::Other(
-                            "Unable to generate type-specific MySQL parameters".into(),
-                        ));
-                    }
-                }
-
-                tx.query_drop("SET autocommit=1").await?;
-                tx.query_drop("SET unique_checks=1").await?;
-                tx.query_drop("SET foreign_key_checks=1").await?;
-                tx.commit().await?;
-
-                // Query the records which intersect (INNER JOIN) with the temp table of ids
-                let query = DbRecord::get_batch_statement::<St>();
-                let out = conn.query_iter(query).await;
-                let result = self.check_for_infra_error(out)?;
-
-                let out = result
-                    .reduce_and_drop(vec![], |mut acc, mut row| {
-                        if let Ok(result) = DbRecord::from_row::<St>(&mut row) {
-                            acc.push(result);
-                        }
-                        acc
-                    })
-                    .await?;
-
-                // drop the temp table of ids
-                let t_out = conn
-                    .query_drop(format!("DROP TEMPORARY TABLE `{}`", TEMP_IDS_TABLE))
-                    .await;
-                self.check_for_infra_error(t_out)?;
-
-                out
-            } else {
-                // no results (i.e. AZKS table doesn't support "get by batch ids")
-                vec![]
-            };
+        // tree nodes are retrieved from each of their shards separately
+        let mut shards: HashMap<usize, Vec<St::StorageKey>> = HashMap::new();
+        for id in ids {
+            shards
+                .entry(self.shard_map.shard_for_key::<St>(id))
+                .or_insert_with(Vec::new)
+                .push(id.clone());
+        }
 
+        let result = async {
+            let mut results = vec![];
+            for (shard, shard_ids) in shards {
+                results.append(
+                    &mut self
+                        .internal_batch_get::<St>(&shard_ids, &self.tables(shard))
+                        .await?,
+                );
+            }
             Ok::<Vec<DbRecord>, mysql_async::Error>(results)
         };
 
@@ -851,13 +1322,63 @@ impl Database for AsyncMySqlDatabase {
             }
             Err(error) => {
                 error!("MySQL error {}", error);
-                return Err(StorageError::Other(format!("MySQL Error {}", error)));
+                return Err(to_storage_error(error));
             }
         }
 
         Ok(map)
     }
 
+    async fn batch_delete<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> core::result::Result<(), StorageError> {
+        if ids.is_empty() {
+            // nothing to delete, save the cycles
+            return Ok(());
+        }
+
+        // tree nodes are deleted from each of their shards separately
+        let mut shards: HashMap<usize, Vec<Params>> = HashMap::new();
+        for id in ids {
+            let params = DbRecord::get_specific_params::<St>(id).ok_or_else(|| {
+                StorageError::Other("Unable to generate type-specific MySQL parameters".into())
+            })?;
+            shards
+                .entry(self.shard_map.shard_for_key::<St>(id))
+                .or_default()
+                .push(params);
+        }
+
+        self.record_call_stats(
+            'w',
+            "batch_delete".to_string(),
+            format!("{:?}", St::data_type()),
+        )
+        .await;
+
+        let result = async {
+            let mut conn = self.get_connection().await?;
+            let mut tx = conn.start_transaction(TxOpts::default()).await?;
+            for (shard, params) in shards {
+                let statement = DbRecord::delete_specific_statement::<St>(&self.tables(shard));
+                for batch in params.chunks(self.tunable_insert_depth) {
+                    let out = tx.exec_batch(statement.as_str(), batch.to_vec()).await;
+                    self.check_for_infra_error(out)?;
+                }
+            }
+            tx.commit().await?;
+            Ok::<(), MySqlError>(())
+        };
+        match result.await {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                error!("MySQL error {}", error);
+                Err(to_storage_error(error))
+            }
+        }
+    }
+
     async fn get_user_data(
         &self,
         username: &AkdLabel,
@@ -871,9 +1392,9 @@ impl Database for AsyncMySqlDatabase {
         let result = async {
             let mut conn = self.get_connection().await?;
             let statement_text =
-                "SELECT `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data` FROM `"
+                "SELECT `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`, `expiry_epoch` FROM `"
                     .to_owned()
-                    + TABLE_USER
+                    + &self.tables(0).users()
                     + "` WHERE `username` = :the_user";
             let mut result = conn
                 .exec_iter(statement_text, params! { "the_user" => username.0.clone() })
@@ -887,6 +1408,7 @@ impl Database for AsyncMySqlDatabase {
                         Some(node_label_val),
                         Some(node_label_len),
                         Some(data),
+                        Some(expiry_epoch),
                     ) = (
                         row.take(0),
                         row.take(1),
@@ -894,6 +1416,7 @@ impl Database for AsyncMySqlDatabase {
                         row.take::<Vec<u8>, _>(3),
                         row.take(4),
                         row.take(5),
+                        row.take(6),
                     ) {
                         // explicitly check the array length for safety
                         if node_label_val.len() == 32 {
@@ -907,6 +1430,7 @@ impl Database for AsyncMySqlDatabase {
                                 },
                                 plaintext_val: AkdValue(data),
                                 username: AkdLabel(username),
+                                expiry_epoch,
                             });
                         }
                     }
@@ -925,7 +1449,7 @@ impl Database for AsyncMySqlDatabase {
             Ok(output) => Ok(output),
             Err(error) => {
                 error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {}", error)))
+                Err(to_storage_error(error))
             }
         }
     }
@@ -941,9 +1465,9 @@ impl Database for AsyncMySqlDatabase {
         let result = async {
             let mut conn = self.get_connection().await?;
             let mut statement_text =
-                "SELECT `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data` FROM `"
+                "SELECT `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`, `expiry_epoch` FROM `"
                     .to_owned()
-                    + TABLE_USER
+                    + &self.tables(0).users()
                     + "` WHERE `username` = :the_user";
             let mut params_map = vec![("the_user", Value::from(&username.0))];
             // apply the specific filter
@@ -977,6 +1501,7 @@ impl Database for AsyncMySqlDatabase {
                         Some(node_label_val),
                         Some(node_label_len),
                         Some(data),
+                        Some(expiry_epoch),
                     ) = (
                         row.take(0),
                         row.take(1),
@@ -984,6 +1509,7 @@ impl Database for AsyncMySqlDatabase {
                         row.take::<Vec<_>, _>(3),
                         row.take(4),
                         row.take(5),
+                        row.take(6),
                     ) {
                         // explicitly check the array length for safety
                         if node_label_val.len() == 32 {
@@ -997,6 +1523,7 @@ impl Database for AsyncMySqlDatabase {
                                 },
                                 plaintext_val: AkdValue(data),
                                 username: AkdLabel(username),
+                                expiry_epoch,
                             });
                         }
                     }
@@ -1014,7 +1541,7 @@ impl Database for AsyncMySqlDatabase {
             Ok(None) => Err(StorageError::NotFound(format!("ValueState {:?}", username))),
             Err(error) => {
                 error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {}", error)))
+                Err(to_storage_error(error))
             }
         }
     }
@@ -1154,7 +1681,10 @@ impl Database for AsyncMySqlDatabase {
                     ON epochs.`username` = full.`username`
                     AND epochs.`epoch` = full.`epoch`
                 ",
-                TABLE_USER, epoch_grouping, TABLE_USER, filter
+                self.tables(0).users(),
+                epoch_grouping,
+                self.tables(0).users(),
+                filter
             );
 
             let out = if params_map.is_empty() {
@@ -1204,8 +1734,97 @@ impl Database for AsyncMySqlDatabase {
             Ok(()) => Ok(results),
             Err(error) => {
                 error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {}", error)))
+                Err(to_storage_error(error))
             }
         }
     }
+
+    fn iter_by_prefix(&self, storage_type: StorageType, key_prefix: &[u8]) -> RecordStream<'_> {
+        let key_prefix = key_prefix.to_vec();
+        // only tree nodes are sharded, the other types are read from a single table
+        let shards = match storage_type {
+            StorageType::TreeNode => self.shards_for_prefix(&key_prefix),
+            _ => vec![0],
+        };
+
+        stream::iter(shards)
+            .then(move |shard| {
+                let key_prefix = key_prefix.clone();
+                async move {
+                    let tables = self.tables(shard);
+                    let out = match storage_type {
+                        StorageType::Azks => {
+                            self.internal_get_by_prefix::<Azks>(&tables, &key_prefix)
+                                .await
+                        }
+                        StorageType::TreeNode => {
+                            self.internal_get_by_prefix::<TreeNodeWithPreviousValue>(
+                                &tables,
+                                &key_prefix,
+                            )
+                            .await
+                        }
+                        StorageType::ValueState => {
+                            self.internal_get_by_prefix::<ValueState>(&tables, &key_prefix)
+                                .await
+                        }
+                        StorageType::TreeHead => {
+                            self.internal_get_by_prefix::<akd::SignedTreeHead>(&tables, &key_prefix)
+                                .await
+                        }
+                        StorageType::RootHash => {
+                            self.internal_get_by_prefix::<RootHashRecord>(&tables, &key_prefix)
+                                .await
+                        }
+                        StorageType::LabelMapping => {
+                            self.internal_get_by_prefix::<LabelMappingRecord>(&tables, &key_prefix)
+                                .await
+                        }
+                    };
+                    match out {
+                        Ok(records) => records,
+                        Err(error) => stream::once(async { Err(error) }).boxed(),
+                    }
+                }
+            })
+            .flatten()
+            .map(|record| {
+                record.map_err(|error| {
+                    error!("MySQL error {}", error);
+                    to_storage_error(error)
+                })
+            })
+            .boxed()
+    }
+
+    /// Creates a handle to the same database, scoped to the given namespace: its
+    /// records are kept in their own set of tables, named with a prefix derived
+    /// from the namespace, which are created if they don't exist yet. The empty
+    /// namespace uses the plain table names, so that existing data remains
+    /// readable.
+    async fn with_namespace(&self, namespace: &[u8]) -> core::result::Result<Self, StorageError> {
+        if namespace.len() > MAX_NAMESPACE_LEN {
+            return Err(StorageError::Other(format!(
+                "Namespaces are limited to {} bytes in MySQL, got {}",
+                MAX_NAMESPACE_LEN,
+                namespace.len()
+            )));
+        }
+        let table_prefix = if namespace.is_empty() {
+            String::new()
+        } else {
+            let encoded: String = namespace.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("ns_{}_", encoded)
+        };
+
+        let conn = self.get_connection().await.map_err(to_storage_error)?;
+        let tree_node_layouts = Self::setup_database(conn, &self.shard_map, &table_prefix)
+            .await
+            .map_err(to_storage_error)?;
+
+        let mut db = self.clone();
+        db.table_prefix = table_prefix;
+        db.tree_node_layouts = Arc::new(std::sync::RwLock::new(tree_node_layouts));
+        Ok(db)
+    }
 }
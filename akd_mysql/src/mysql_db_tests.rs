@@ -6,9 +6,14 @@
 // License, Version 2.0 found in the LICENSE-APACHE file in the root directory
 // of this source tree.
 
+use akd::storage::types::StorageType;
+use akd::storage::Database;
+use futures_util::StreamExt;
+use mysql_async::prelude::*;
 use serial_test::serial;
 
 use crate::mysql::*;
+use crate::sharding::ShardMap;
 // use serial_test::serial;
 
 // *** Tests *** //
@@ -58,3 +63,392 @@ async fn test_mysql_db() {
         println!("WARN: Skipping MySQL test due to test guard noting that the docker container appears to not be running.");
     }
 }
+
+#[tokio::test]
+#[serial]
+async fn test_sharded_mysql_db() {
+    akd::test_utils::init_logger(log::Level::Info);
+    if AsyncMySqlDatabase::test_guard() {
+        if let Err(error) = AsyncMySqlDatabase::create_test_db(
+            "localhost",
+            Option::from("root"),
+            Option::from("example"),
+            Option::from(8001),
+        )
+        .await
+        {
+            panic!("Error creating test database: {}", error);
+        }
+
+        let shard_map = ShardMap::uniform(4).expect("Failed to create shard map");
+        let mysql_db = AsyncMySqlDatabase::new_with_shard_map(
+            "localhost",
+            "test_db",
+            Option::from("root"),
+            Option::from("example"),
+            Option::from(8001),
+            200,
+            shard_map,
+        )
+        .await;
+
+        if let Err(error) = mysql_db.delete_data().await {
+            println!("Error cleaning mysql prior to test suite: {}", error);
+        }
+
+        // The test cases
+        akd::storage::tests::run_test_cases_for_storage_impl(&mysql_db).await;
+
+        // clean the test infra
+        if let Err(mysql_async::Error::Server(error)) = mysql_db.drop_tables().await {
+            println!(
+                "ERROR: Failed to clean MySQL test database with error {}",
+                error
+            );
+        }
+    } else {
+        println!("WARN: Skipping MySQL test due to test guard noting that the docker container appears to not be running.");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_namespaced_mysql_db() {
+    akd::test_utils::init_logger(log::Level::Info);
+    if AsyncMySqlDatabase::test_guard() {
+        if let Err(error) = AsyncMySqlDatabase::create_test_db(
+            "localhost",
+            Option::from("root"),
+            Option::from("example"),
+            Option::from(8001),
+        )
+        .await
+        {
+            panic!("Error creating test database: {}", error);
+        }
+
+        let mysql_db = AsyncMySqlDatabase::new(
+            "localhost",
+            "test_db",
+            Option::from("root"),
+            Option::from("example"),
+            Option::from(8001),
+            200,
+        )
+        .await;
+        let namespaced_db = mysql_db
+            .with_namespace(b"tenant")
+            .await
+            .expect("Failed to create namespaced tables");
+        assert!(mysql_db.with_namespace(&[0u8; 24]).await.is_err());
+
+        for db in [&mysql_db, &namespaced_db] {
+            if let Err(error) = db.delete_data().await {
+                println!("Error cleaning mysql prior to test suite: {}", error);
+            }
+        }
+
+        // The test cases
+        akd::storage::tests::run_test_cases_for_storage_impl(&namespaced_db).await;
+
+        // the records of the namespace aren't visible outside of it
+        assert!(mysql_db
+            .get::<akd::Azks>(&akd::append_only_zks::DEFAULT_AZKS_KEY)
+            .await
+            .is_err());
+        assert!(mysql_db
+            .iter_by_prefix(StorageType::TreeNode, &[])
+            .next()
+            .await
+            .is_none());
+
+        // clean the test infra
+        for db in [&mysql_db, &namespaced_db] {
+            if let Err(mysql_async::Error::Server(error)) = db.drop_tables().await {
+                println!(
+                    "ERROR: Failed to clean MySQL test database with error {}",
+                    error
+                );
+            }
+        }
+    } else {
+        println!("WARN: Skipping MySQL test due to test guard noting that the docker container appears to not be running.");
+    }
+}
+
+// The tables created by an earlier version of the crate remain readable in their
+// legacy layout, until the tree nodes are explicitly migrated
+#[tokio::test]
+#[serial]
+async fn test_mysql_db_migration() {
+    akd::test_utils::init_logger(log::Level::Info);
+    if AsyncMySqlDatabase::test_guard() {
+        if let Err(error) = AsyncMySqlDatabase::create_test_db(
+            "localhost",
+            Option::from("root"),
+            Option::from("example"),
+            Option::from(8001),
+        )
+        .await
+        {
+            panic!("Error creating test database: {}", error);
+        }
+        let opts = mysql_async::OptsBuilder::default()
+            .ip_or_hostname("localhost")
+            .db_name(Some("test_db"))
+            .user(Some("root"))
+            .pass(Some("example"))
+            .tcp_port(8001);
+        let mut conn = mysql_async::Conn::new(opts)
+            .await
+            .expect("Failed to connect to the test database");
+
+        // the tables of the namespace, in their legacy layout
+        let prefix = "ns_6c6567616379_";
+        let tree_nodes = format!("{}history", prefix);
+        conn.query_drop(format!("DROP TABLE IF EXISTS `{}`", tree_nodes))
+            .await
+            .expect("Failed to drop the tree node table");
+        conn.query_drop(format!(
+            "CREATE TABLE `{}` (`label_len` INT UNSIGNED NOT NULL, `label_val` VARBINARY(32) NOT NULL,
+            `last_epoch` BIGINT UNSIGNED NOT NULL, `least_descendant_ep` BIGINT UNSIGNED NOT NULL,
+            `parent_label_len` INT UNSIGNED NOT NULL, `parent_label_val` VARBINARY(32) NOT NULL,
+            `node_type` SMALLINT UNSIGNED NOT NULL, `left_child_len` INT UNSIGNED,
+            `left_child_label_val` VARBINARY(32), `right_child_len` INT UNSIGNED,
+            `right_child_label_val` VARBINARY(32), `hash` VARBINARY(32) NOT NULL,
+            `p_last_epoch` BIGINT UNSIGNED, `p_least_descendant_ep` BIGINT UNSIGNED,
+            `p_parent_label_len` INT UNSIGNED, `p_parent_label_val` VARBINARY(32),
+            `p_node_type` SMALLINT UNSIGNED, `p_left_child_len` INT UNSIGNED,
+            `p_left_child_label_val` VARBINARY(32), `p_right_child_len` INT UNSIGNED,
+            `p_right_child_label_val` VARBINARY(32), `p_hash` VARBINARY(32),
+            PRIMARY KEY (`label_len`, `label_val`))",
+            tree_nodes
+        ))
+        .await
+        .expect("Failed to create the legacy tree node table");
+        let label = akd::NodeLabel::new([1u8; 32], 8);
+        let child = akd::NodeLabel::new([1u8; 32], 256);
+        conn.exec_drop(
+            format!(
+                "INSERT INTO `{}` (`label_len`, `label_val`, `last_epoch`, `least_descendant_ep`,
+                `parent_label_len`, `parent_label_val`, `node_type`, `left_child_len`,
+                `left_child_label_val`, `hash`, `p_last_epoch`, `p_least_descendant_ep`,
+                `p_parent_label_len`, `p_parent_label_val`, `p_node_type`, `p_hash`)
+                VALUES (8, :label_val, 2, 1, 0, :root_val, 2, 256, :label_val, :hash, 1, 1, 0,
+                :root_val, 2, :p_hash)",
+                tree_nodes
+            ),
+            params! {
+                "label_val" => label.label_val,
+                "root_val" => akd::NodeLabel::root().label_val,
+                "hash" => [2u8; 32],
+                "p_hash" => [3u8; 32],
+            },
+        )
+        .await
+        .expect("Failed to insert the legacy tree node");
+
+        let users = format!("{}users", prefix);
+        conn.query_drop(format!("DROP TABLE IF EXISTS `{}`", users))
+            .await
+            .expect("Failed to drop the user data table");
+        conn.query_drop(format!(
+            "CREATE TABLE `{}` (`username` VARBINARY(256) NOT NULL, `epoch` BIGINT UNSIGNED NOT NULL,
+            `version` BIGINT UNSIGNED NOT NULL, `node_label_val` VARBINARY(32) NOT NULL,
+            `node_label_len` INT UNSIGNED NOT NULL, `data` VARBINARY(2000),
+            PRIMARY KEY(`username`, `epoch`))",
+            users
+        ))
+        .await
+        .expect("Failed to create the legacy user data table");
+        conn.exec_drop(
+            format!(
+                "INSERT INTO `{}` (`username`, `epoch`, `version`, `node_label_val`, `node_label_len`,
+                `data`) VALUES (:username, 1, 1, :label_val, 256, :data)",
+                users
+            ),
+            params! {
+                "username" => b"user".to_vec(),
+                "label_val" => child.label_val,
+                "data" => b"value".to_vec(),
+            },
+        )
+        .await
+        .expect("Failed to insert the legacy user state");
+
+        let mysql_db = AsyncMySqlDatabase::new(
+            "localhost",
+            "test_db",
+            Option::from("root"),
+            Option::from("example"),
+            Option::from(8001),
+            200,
+        )
+        .await;
+        let namespaced_db = mysql_db
+            .with_namespace(b"legacy")
+            .await
+            .expect("Failed to set up the namespaced tables");
+
+        let expected = akd::storage::types::DbRecord::build_tree_node_with_previous_value(
+            label.label_val,
+            8,
+            2,
+            1,
+            akd::NodeLabel::root().label_val,
+            0,
+            2,
+            Some(child),
+            None,
+            [2u8; 32],
+            Some(1),
+            Some(1),
+            Some(akd::NodeLabel::root().label_val),
+            Some(0),
+            Some(2),
+            None,
+            None,
+            Some([3u8; 32]),
+        );
+        let mut written = expected.clone();
+        written.label = child;
+        written.latest_node.hash = [4u8; 32];
+        written.previous_node = None;
+        let mut expected = vec![
+            akd::storage::types::DbRecord::TreeNode(expected),
+            akd::storage::types::DbRecord::TreeNode(written.clone()),
+        ];
+        expected.sort_by_key(|record| record.get_full_binary_id());
+        let keys = vec![
+            akd::tree_node::NodeKey(label),
+            akd::tree_node::NodeKey(child),
+        ];
+
+        // the legacy table is left as is, and nodes are read and written in its layout
+        namespaced_db
+            .set(akd::storage::types::DbRecord::TreeNode(written))
+            .await
+            .expect("Failed to write to the legacy tree node table");
+        let columns: Vec<String> = conn
+            .exec(
+                "SELECT `COLUMN_NAME` FROM information_schema.COLUMNS
+                WHERE `TABLE_SCHEMA` = DATABASE() AND `TABLE_NAME` = :table
+                ORDER BY `ORDINAL_POSITION`",
+                params! { "table" => tree_nodes.clone() },
+            )
+            .await
+            .expect("Failed to get the columns of the tree node table");
+        assert!(columns.contains(&"last_epoch".to_string()));
+        assert!(!columns.contains(&"node".to_string()));
+        let mut nodes = namespaced_db
+            .batch_get::<akd::tree_node::TreeNodeWithPreviousValue>(&keys)
+            .await
+            .expect("Failed to get the legacy tree nodes");
+        nodes.sort_by_key(|record| record.get_full_binary_id());
+        assert_eq!(expected, nodes);
+
+        // which are then migrated on request
+        assert_eq!(
+            2,
+            namespaced_db
+                .migrate_tree_nodes()
+                .await
+                .expect("Failed to migrate the tree nodes")
+        );
+        let columns: Vec<String> = conn
+            .exec(
+                "SELECT `COLUMN_NAME` FROM information_schema.COLUMNS
+                WHERE `TABLE_SCHEMA` = DATABASE() AND `TABLE_NAME` = :table
+                ORDER BY `ORDINAL_POSITION`",
+                params! { "table" => tree_nodes.clone() },
+            )
+            .await
+            .expect("Failed to get the columns of the tree node table");
+        assert_eq!(vec!["label_len", "label_val", "node"], columns);
+        for db in [
+            namespaced_db.clone(),
+            mysql_db
+                .with_namespace(b"legacy")
+                .await
+                .expect("Failed to set up the namespaced tables"),
+        ] {
+            let mut nodes = db
+                .batch_get::<akd::tree_node::TreeNodeWithPreviousValue>(&keys)
+                .await
+                .expect("Failed to get the migrated tree nodes");
+            nodes.sort_by_key(|record| record.get_full_binary_id());
+            assert_eq!(expected, nodes);
+        }
+        assert_eq!(
+            0,
+            namespaced_db
+                .migrate_tree_nodes()
+                .await
+                .expect("Failed to migrate the tree nodes again")
+        );
+        assert_eq!(
+            akd::storage::types::DbRecord::build_user_state(
+                b"user".to_vec(),
+                b"value".to_vec(),
+                1,
+                256,
+                child.label_val,
+                1
+            ),
+            namespaced_db
+                .get_user_state(
+                    &akd::AkdLabel::from_utf8_str("user"),
+                    akd::storage::types::ValueStateRetrievalFlag::MaxEpoch
+                )
+                .await
+                .expect("Failed to get the user state")
+        );
+
+        // clean the test infra
+        if let Err(mysql_async::Error::Server(error)) = namespaced_db.drop_tables().await {
+            println!(
+                "ERROR: Failed to clean MySQL test database with error {}",
+                error
+            );
+        }
+    } else {
+        println!("WARN: Skipping MySQL test due to test guard noting that the docker container appears to not be running.");
+    }
+}
+
+#[test]
+fn test_shard_map() {
+    let single = ShardMap::single();
+    assert_eq!(1, single.num_shards());
+    assert_eq!("history", single.table_name(0));
+
+    let shard_map = ShardMap::uniform(4).expect("Failed to create shard map");
+    assert_eq!(&[0u8, 64, 128, 192], shard_map.boundaries());
+    assert_eq!(
+        vec!["history_0", "history_1", "history_2", "history_3"],
+        shard_map.table_names()
+    );
+    for (first_byte, shard) in [(0u8, 0usize), (63, 0), (64, 1), (191, 2), (255, 3)] {
+        let mut label_val = [0u8; 32];
+        label_val[0] = first_byte;
+        assert_eq!(
+            shard,
+            shard_map.shard_for_label(&akd::NodeLabel::new(label_val, 256))
+        );
+    }
+    // the root (empty label) lives in the first shard
+    assert_eq!(0, shard_map.shard_for_label(&akd::NodeLabel::root()));
+
+    assert_eq!(256, ShardMap::uniform(256).unwrap().num_shards());
+    assert!(ShardMap::uniform(0).is_err());
+    assert!(ShardMap::uniform(257).is_err());
+    assert!(ShardMap::from_boundaries(vec![]).is_err());
+    assert!(ShardMap::from_boundaries(vec![1, 2]).is_err());
+    assert!(ShardMap::from_boundaries(vec![0, 10, 10]).is_err());
+    assert_eq!(
+        2,
+        ShardMap::from_boundaries(vec![0, 10, 200])
+            .unwrap()
+            .shard_for_label(&akd::NodeLabel::new([255u8; 32], 256))
+    );
+}
@@ -9,10 +9,9 @@
 
 use std::convert::TryInto;
 
-use akd::storage::types::{DbRecord, StorageType};
+use akd::storage::types::{DbRecord, RootHashRecord, StorageType, LABEL_MAPPING_KEY};
 use akd::storage::Storable;
 use akd::tree_node::{NodeKey, TreeNodeWithPreviousValue};
-use akd::NodeLabel;
 use mysql_async::prelude::*;
 use mysql_async::*;
 
@@ -21,35 +20,216 @@ type MySqlError = mysql_async::Error;
 pub(crate) const TABLE_AZKS: &str = "azks";
 pub(crate) const TABLE_HISTORY_TREE_NODES: &str = "history";
 pub(crate) const TABLE_USER: &str = "users";
+pub(crate) const TABLE_TREE_HEADS: &str = "tree_heads";
+pub(crate) const TABLE_ROOT_HASHES: &str = "root_hashes";
+pub(crate) const TABLE_LABEL_MAPPING: &str = "label_mapping";
 pub(crate) const TEMP_IDS_TABLE: &str = "temp_ids_table";
 
 const SELECT_AZKS_DATA: &str = "`epoch`, `num_nodes`";
-const SELECT_HISTORY_TREE_NODE_DATA: &str =
-    "`label_len`, `label_val`, `last_epoch`, `least_descendant_ep`, `parent_label_len`, `parent_label_val`, `node_type`, `left_child_len`, `left_child_label_val`, `right_child_len`, `right_child_label_val`, `hash`, `p_last_epoch`, `p_least_descendant_ep`, `p_parent_label_len`, `p_parent_label_val`, `p_node_type`, `p_left_child_len`, `p_left_child_label_val`, `p_right_child_len`, `p_right_child_label_val`, `p_hash`";
 const SELECT_USER_DATA: &str =
-    "`username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`";
+    "`username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`, `expiry_epoch`";
+const SELECT_TREE_HEAD_DATA: &str = "`epoch`, `root_hash`, `timestamp`, `signature`";
+const SELECT_ROOT_HASH_DATA: &str = "`epoch`, `root_hash`, `timestamp`, `batch_size`, `metadata`";
+const SELECT_LABEL_MAPPING_DATA: &str = "`mapper_id`";
 
+/// How the nodes are stored in a tree node table, after the `label_len` and
+/// `label_val` columns keying it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TreeNodeLayout {
+    /// A column per field of the latest and previous nodes, see
+    /// [LEGACY_TREE_NODE_COLUMNS]. The tables created by earlier versions of this
+    /// crate keep this layout until they're migrated.
+    Legacy,
+    /// Each node in its compact encoding, in the `node` column
+    Compact,
+}
+
+impl TreeNodeLayout {
+    /// The columns holding the node
+    pub(crate) fn columns(self) -> &'static [&'static str] {
+        match self {
+            TreeNodeLayout::Legacy => &LEGACY_TREE_NODE_COLUMNS,
+            TreeNodeLayout::Compact => &["node"],
+        }
+    }
+
+    /// The values of the [columns](Self::columns) for the node
+    pub(crate) fn values(self, node: &TreeNodeWithPreviousValue) -> Vec<Value> {
+        match self {
+            TreeNodeLayout::Legacy => {
+                let latest = &node.latest_node;
+                let previous = node.previous_node.as_ref();
+                vec![
+                    Value::from(latest.last_epoch),
+                    Value::from(latest.min_descendant_epoch),
+                    Value::from(latest.parent.label_len),
+                    Value::from(latest.parent.label_val),
+                    Value::from(latest.node_type as u8),
+                    Value::from(latest.left_child.map(|lc| lc.label_len)),
+                    Value::from(latest.left_child.map(|lc| lc.label_val)),
+                    Value::from(latest.right_child.map(|rc| rc.label_len)),
+                    Value::from(latest.right_child.map(|rc| rc.label_val)),
+                    Value::from(latest.hash),
+                    Value::from(previous.map(|p| p.last_epoch)),
+                    Value::from(previous.map(|p| p.min_descendant_epoch)),
+                    Value::from(previous.map(|p| p.parent.label_len)),
+                    Value::from(previous.map(|p| p.parent.label_val)),
+                    Value::from(previous.map(|p| p.node_type as u8)),
+                    Value::from(previous.and_then(|p| p.left_child).map(|lc| lc.label_len)),
+                    Value::from(previous.and_then(|p| p.left_child).map(|lc| lc.label_val)),
+                    Value::from(previous.and_then(|p| p.right_child).map(|rc| rc.label_len)),
+                    Value::from(previous.and_then(|p| p.right_child).map(|rc| rc.label_val)),
+                    Value::from(previous.map(|p| p.hash)),
+                ]
+            }
+            TreeNodeLayout::Compact => vec![Value::from(node.to_bytes())],
+        }
+    }
+
+    /// The selected columns of a tree node, of the table with the given alias
+    /// (or none, if empty)
+    fn select(self, alias: &str) -> String {
+        ["label_len", "label_val"]
+            .iter()
+            .chain(self.columns())
+            .map(|column| format!("{}`{}`", alias, column))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The named parameters of the columns of a tree node, suffixed with the
+    /// given index of the node in its batch
+    fn placeholders(self, suffix: &str) -> String {
+        ["label_len", "label_val"]
+            .iter()
+            .chain(self.columns())
+            .map(|column| format!(":{}{}", column, suffix))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The assignments of the node's columns on a duplicate key, to the named
+    /// parameters or to the inserted row's columns when `from_new` is set
+    fn updates(self, from_new: bool) -> String {
+        self.columns()
+            .iter()
+            .map(|column| {
+                if from_new {
+                    format!("`{}` = new.`{}`", column, column)
+                } else {
+                    format!("`{}` = :{}", column, column)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The named parameters of the node, suffixed with the given index of the
+    /// node in its batch
+    fn params(self, node: &TreeNodeWithPreviousValue, suffix: &str) -> Vec<(String, Value)> {
+        let mut params = vec![
+            (
+                format!("label_len{}", suffix),
+                Value::from(node.label.label_len),
+            ),
+            (
+                format!("label_val{}", suffix),
+                Value::from(node.label.label_val),
+            ),
+        ];
+        params.extend(
+            self.columns()
+                .iter()
+                .map(|column| format!("{}{}", column, suffix))
+                .zip(self.values(node)),
+        );
+        params
+    }
+}
+
+/// The tables a statement targets: those of the namespace of the database, whose
+/// names are prefixed with the namespace's (the empty namespace using the plain
+/// names), and among them the (shard) table of the tree nodes, in its layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Tables {
+    prefix: String,
+    tree_nodes: String,
+    tree_node_layout: TreeNodeLayout,
+}
+
+impl Tables {
+    /// The tables with the given name prefix, targeting the given tree node table
+    /// (without the prefix), in the compact layout
+    pub(crate) fn new(prefix: &str, tree_node_table: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            tree_nodes: format!("{}{}", prefix, tree_node_table),
+            tree_node_layout: TreeNodeLayout::Compact,
+        }
+    }
+
+    /// The same tables, with the tree node table in the given layout
+    pub(crate) fn with_tree_node_layout(mut self, layout: TreeNodeLayout) -> Self {
+        self.tree_node_layout = layout;
+        self
+    }
+
+    pub(crate) fn tree_node_layout(&self) -> TreeNodeLayout {
+        self.tree_node_layout
+    }
+
+    pub(crate) fn azks(&self) -> String {
+        format!("{}{}", self.prefix, TABLE_AZKS)
+    }
+
+    pub(crate) fn tree_nodes(&self) -> &str {
+        &self.tree_nodes
+    }
+
+    pub(crate) fn users(&self) -> String {
+        format!("{}{}", self.prefix, TABLE_USER)
+    }
+
+    pub(crate) fn tree_heads(&self) -> String {
+        format!("{}{}", self.prefix, TABLE_TREE_HEADS)
+    }
+
+    pub(crate) fn root_hashes(&self) -> String {
+        format!("{}{}", self.prefix, TABLE_ROOT_HASHES)
+    }
+
+    pub(crate) fn label_mapping(&self) -> String {
+        format!("{}{}", self.prefix, TABLE_LABEL_MAPPING)
+    }
+}
+
+/// Record handling for the MySQL tables. The statements take the [Tables] to
+/// target.
 pub(crate) trait MySqlStorable {
-    fn set_statement(&self) -> String;
+    fn set_statement(&self, tables: &Tables) -> String;
+
+    fn set_params(&self, tables: &Tables) -> Option<mysql_async::Params>;
 
-    fn set_params(&self) -> Option<mysql_async::Params>;
+    fn set_batch_statement<St: Storable>(items: usize, tables: &Tables) -> String;
 
-    fn set_batch_statement<St: Storable>(items: usize) -> String;
+    fn set_batch_params(items: &[DbRecord], tables: &Tables) -> Result<mysql_async::Params>;
 
-    fn set_batch_params(items: &[DbRecord]) -> Result<mysql_async::Params>;
+    fn get_statement<St: Storable>(tables: &Tables) -> String;
 
-    fn get_statement<St: Storable>() -> String;
+    fn get_prefix_statement<St: Storable>(tables: &Tables, bounded: bool) -> String;
 
     fn get_batch_create_temp_table<St: Storable>() -> Option<String>;
 
     fn get_batch_fill_temp_table<St: Storable>(num_items: Option<usize>) -> String;
 
-    fn get_batch_statement<St: Storable>() -> String;
+    fn get_batch_statement<St: Storable>(tables: &Tables) -> String;
 
-    fn get_specific_statement<St: Storable>() -> String;
+    fn get_specific_statement<St: Storable>(tables: &Tables) -> String;
 
     fn get_specific_params<St: Storable>(key: &St::StorageKey) -> Option<mysql_async::Params>;
 
+    fn delete_specific_statement<St: Storable>(tables: &Tables) -> String;
+
     fn get_multi_row_specific_params<St: Storable>(
         keys: &[St::StorageKey],
     ) -> Option<mysql_async::Params>;
@@ -60,135 +240,76 @@ pub(crate) trait MySqlStorable {
 }
 
 impl MySqlStorable for DbRecord {
-    fn set_statement(&self) -> String {
+    fn set_statement(&self, tables: &Tables) -> String {
         match &self {
             DbRecord::Azks(_) => format!("INSERT INTO `{}` (`key`, {})
             VALUES (:key, :epoch, :num_nodes)
             ON DUPLICATE KEY UPDATE
                 `epoch` = :epoch
-                , `num_nodes` = :num_nodes", TABLE_AZKS, SELECT_AZKS_DATA),
+                , `num_nodes` = :num_nodes", tables.azks(), SELECT_AZKS_DATA),
             DbRecord::TreeNode(_) => format!("INSERT INTO `{}` ({})
-            VALUES (:label_len
-                , :label_val
-                , :last_epoch
-                , :least_descendant_ep
-                , :parent_label_len
-                , :parent_label_val
-                , :node_type
-                , :left_child_len
-                , :left_child_label_val
-                , :right_child_len
-                , :right_child_label_val
-                , :hash
-                , :p_last_epoch
-                , :p_least_descendant_ep
-                , :p_parent_label_len
-                , :p_parent_label_val
-                , :p_node_type
-                , :p_left_child_len
-                , :p_left_child_label_val
-                , :p_right_child_len
-                , :p_right_child_label_val
-                , :p_hash)
+            VALUES ({})
+            ON DUPLICATE KEY UPDATE {}", tables.tree_nodes(), tables.tree_node_layout().select(""), tables.tree_node_layout().placeholders(""), tables.tree_node_layout().updates(false)),
+            DbRecord::ValueState(_) => format!("INSERT INTO `{}` ({}) VALUES (:username, :epoch, :version, :node_label_val, :node_label_len, :data, :expiry_epoch)", tables.users(), SELECT_USER_DATA),
+            DbRecord::TreeHead(_) => format!("INSERT INTO `{}` ({}) VALUES (:epoch, :root_hash, :timestamp, :signature)", tables.tree_heads(), SELECT_TREE_HEAD_DATA),
+            DbRecord::RootHash(_) => format!("INSERT INTO `{}` ({}) VALUES (:epoch, :root_hash, :timestamp, :batch_size, :metadata)", tables.root_hashes(), SELECT_ROOT_HASH_DATA),
+            DbRecord::LabelMapping(_) => format!("INSERT INTO `{}` (`key`, {})
+            VALUES (:key, :mapper_id)
             ON DUPLICATE KEY UPDATE
-                `label_len` = :label_len
-                , `label_val` = :label_val
-                , `last_epoch` = :last_epoch
-                , `least_descendant_ep` = :least_descendant_ep
-                , `parent_label_len` = :parent_label_len
-                , `parent_label_val` = :parent_label_val
-                , `node_type` = :node_type
-                , `left_child_len` = :left_child_len
-                , `left_child_label_val` = :left_child_label_val
-                , `right_child_len` = :right_child_len
-                , `right_child_label_val` = :right_child_label_val
-                , `hash` = :hash
-                , `p_last_epoch` = :p_last_epoch
-                , `p_least_descendant_ep` = :p_least_descendant_ep
-                , `p_parent_label_len` = :p_parent_label_len
-                , `p_parent_label_val` = :p_parent_label_val
-                , `p_node_type` = :p_node_type
-                , `p_left_child_len` = :p_left_child_len
-                , `p_left_child_label_val` = :p_left_child_label_val
-                , `p_right_child_len` = :p_right_child_len
-                , `p_right_child_label_val` = :p_right_child_label_val
-                , `p_hash` = :p_hash", TABLE_HISTORY_TREE_NODES, SELECT_HISTORY_TREE_NODE_DATA),
-            DbRecord::ValueState(_) => format!("INSERT INTO `{}` ({}) VALUES (:username, :epoch, :version, :node_label_val, :node_label_len, :data)", TABLE_USER, SELECT_USER_DATA),
+                `mapper_id` = :mapper_id", tables.label_mapping(), SELECT_LABEL_MAPPING_DATA),
         }
     }
 
-    fn set_params(&self) -> Option<mysql_async::Params> {
+    fn set_params(&self, tables: &Tables) -> Option<mysql_async::Params> {
         match &self {
             DbRecord::Azks(azks) => Some(
                 params! { "key" => 1u8, "epoch" => azks.latest_epoch, "num_nodes" => azks.num_nodes },
             ),
-            DbRecord::TreeNode(node) => Some(params! {
-                "label_len" => node.label.label_len,
-                "label_val" => node.label.label_val,
-                // "Latest" node values
-                "last_epoch" => node.latest_node.last_epoch,
-                "least_descendant_ep" => node.latest_node.min_descendant_epoch,
-                "parent_label_len" => node.latest_node.parent.label_len,
-                "parent_label_val" => node.latest_node.parent.label_val,
-                "node_type" => node.latest_node.node_type as u8,
-                "left_child_len" => node.latest_node.left_child.map(|lc| lc.label_len),
-                "left_child_label_val" => node.latest_node.left_child.map(|lc| lc.label_val),
-                "right_child_len" => node.latest_node.right_child.map(|rc| rc.label_len),
-                "right_child_label_val" => node.latest_node.right_child.map(|rc| rc.label_val),
-                "hash" => node.latest_node.hash,
-                // "Previous" node values
-                "p_last_epoch" => node.previous_node.clone().map(|a| a.last_epoch),
-                "p_least_descendant_ep" => node.previous_node.clone().map(|a| a.min_descendant_epoch),
-                "p_parent_label_len" => node.previous_node.clone().map(|a| a.parent.label_len),
-                "p_parent_label_val" => node.previous_node.clone().map(|a| a.parent.label_val),
-                "p_node_type" => node.previous_node.clone().map(|a| a.node_type as u8),
-                "p_left_child_len" => node.previous_node.clone().and_then(|a| a.left_child.map(|lc| lc.label_len)),
-                "p_left_child_label_val" => node.previous_node.clone().and_then(|a| a.left_child.map(|lc| lc.label_val)),
-                "p_right_child_len" => node.previous_node.clone().and_then(|a| a.right_child.map(|rc| rc.label_len)),
-                "p_right_child_label_val" => node.previous_node.clone().and_then(|a| a.right_child.map(|rc| rc.label_val)),
-                "p_hash" => node.previous_node.clone().map(|a| a.hash),
-            }),
+            DbRecord::TreeNode(node) => Some(mysql_async::Params::from(
+                tables.tree_node_layout().params(node, ""),
+            )),
             DbRecord::ValueState(state) => Some(
-                params! { "username" => state.get_id().0, "epoch" => state.epoch, "version" => state.version, "node_label_len" => state.label.label_len, "node_label_val" => state.label.label_val, "data" => state.plaintext_val.0.clone() },
+                params! { "username" => state.get_id().0, "epoch" => state.epoch, "version" => state.version, "node_label_len" => state.label.label_len, "node_label_val" => state.label.label_val, "data" => state.plaintext_val.0.clone(), "expiry_epoch" => state.expiry_epoch },
+            ),
+            DbRecord::TreeHead(tree_head) => Some(
+                params! { "epoch" => tree_head.epoch, "root_hash" => tree_head.root_hash, "timestamp" => tree_head.timestamp, "signature" => tree_head.signature.clone() },
+            ),
+            DbRecord::RootHash(record) => Some(
+                params! { "epoch" => record.epoch, "root_hash" => record.root_hash, "timestamp" => record.timestamp, "batch_size" => record.batch_size, "metadata" => record.metadata_to_bytes() },
+            ),
+            DbRecord::LabelMapping(record) => Some(
+                params! { "key" => LABEL_MAPPING_KEY, "mapper_id" => record.mapper_id.clone() },
             ),
         }
     }
 
-    fn set_batch_statement<St: Storable>(items: usize) -> String {
+    fn set_batch_statement<St: Storable>(items: usize, tables: &Tables) -> String {
         let mut parts = "".to_string();
         for i in 0..items {
             match St::data_type() {
                 StorageType::TreeNode => {
                     parts = format!(
-                        "{}(:label_len{}
-                            , :label_val{}
-                            , :last_epoch{}
-                            , :least_descendant_ep{}
-                            , :parent_label_len{}
-                            , :parent_label_val{}
-                            , :node_type{}
-                            , :left_child_len{}
-                            , :left_child_label_val{}
-                            , :right_child_len{}
-                            , :right_child_label_val{}
-                            , :hash{}
-                            , :p_last_epoch{}
-                            , :p_least_descendant_ep{}
-                            , :p_parent_label_len{}
-                            , :p_parent_label_val{}
-                            , :p_node_type{}
-                            , :p_left_child_len{}
-                            , :p_left_child_label_val{}
-                            , :p_right_child_len{}
-                            , :p_right_child_label_val{}
-                            , :p_hash{})",
-                        parts, i, i, i, i, i, i, i, i, i, i, i, i, i, i, i, i, i, i, i, i, i, i
+                        "{}({})",
+                        parts,
+                        tables.tree_node_layout().placeholders(&i.to_string())
                     );
                 }
                 StorageType::ValueState => {
                     parts = format!(
-                        "{}(:username{}, :epoch{}, :version{}, :node_label_val{}, :node_label_len{}, :data{})",
-                        parts, i, i, i, i, i, i
+                        "{}(:username{}, :epoch{}, :version{}, :node_label_val{}, :node_label_len{}, :data{}, :expiry_epoch{})",
+                        parts, i, i, i, i, i, i, i
+                    );
+                }
+                StorageType::TreeHead => {
+                    parts = format!(
+                        "{}(:epoch{}, :root_hash{}, :timestamp{}, :signature{})",
+                        parts, i, i, i, i
+                    );
+                }
+                StorageType::RootHash => {
+                    parts = format!(
+                        "{}(:epoch{}, :root_hash{}, :timestamp{}, :batch_size{}, :metadata{})",
+                        parts, i, i, i, i, i
                     );
                 }
                 _ => {
@@ -206,50 +327,65 @@ impl MySqlStorable for DbRecord {
                 "INSERT INTO `{}` (`key`, {})
             VALUES (:key, :epoch, :num_nodes) as new
             ON DUPLICATE KEY UPDATE `epoch` = new.epoch, `num_nodes` = new.num_nodes",
-                TABLE_AZKS, SELECT_AZKS_DATA
+                tables.azks(),
+                SELECT_AZKS_DATA
             ),
             StorageType::TreeNode => format!(
                 "INSERT INTO `{}` ({})
             VALUES {} as new
-            ON DUPLICATE KEY UPDATE
-                `label_len` = new.label_len
-                , `label_val` = new.label_val
-                , `least_descendant_ep` = new.least_descendant_ep
-                , `last_epoch` = new.last_epoch
-                , `parent_label_len` = new.parent_label_len
-                , `parent_label_val` = new.parent_label_val
-                , `node_type` = new.node_type
-                , `left_child_len` = new.left_child_len
-                , `left_child_label_val` = new.left_child_label_val
-                , `right_child_len` = new.right_child_len
-                , `right_child_label_val` = new.right_child_label_val
-                , `hash` = new.hash
-                , `p_last_epoch` = new.p_last_epoch
-                , `p_least_descendant_ep` = new.p_least_descendant_ep
-                , `p_parent_label_len` = new.p_parent_label_len
-                , `p_parent_label_val` = new.p_parent_label_val
-                , `p_node_type` = new.p_node_type
-                , `p_left_child_len` = new.p_left_child_len
-                , `p_left_child_label_val` = new.p_left_child_label_val
-                , `p_right_child_len` = new.p_right_child_len
-                , `p_right_child_label_val` = new.p_right_child_label_val
-                , `p_hash` = new.p_hash",
-                TABLE_HISTORY_TREE_NODES, SELECT_HISTORY_TREE_NODE_DATA, parts
+            ON DUPLICATE KEY UPDATE {}",
+                tables.tree_nodes(),
+                tables.tree_node_layout().select(""),
+                parts,
+                tables.tree_node_layout().updates(true)
             ),
             StorageType::ValueState => format!(
                 "INSERT INTO `{}` ({})
             VALUES {} as new
             ON DUPLICATE KEY UPDATE
                 `data` = new.data
+                , `expiry_epoch` = new.expiry_epoch
                 , `node_label_val` = new.node_label_val
                 , `node_label_len` = new.node_label_len
                 , `version` = new.version",
-                TABLE_USER, SELECT_USER_DATA, parts
+                tables.users(),
+                SELECT_USER_DATA,
+                parts
+            ),
+            StorageType::TreeHead => format!(
+                "INSERT INTO `{}` ({})
+            VALUES {} as new
+            ON DUPLICATE KEY UPDATE
+                `root_hash` = new.root_hash
+                , `timestamp` = new.timestamp
+                , `signature` = new.signature",
+                tables.tree_heads(),
+                SELECT_TREE_HEAD_DATA,
+                parts
+            ),
+            StorageType::RootHash => format!(
+                "INSERT INTO `{}` ({})
+            VALUES {} as new
+            ON DUPLICATE KEY UPDATE
+                `root_hash` = new.root_hash
+                , `timestamp` = new.timestamp
+                , `batch_size` = new.batch_size
+                , `metadata` = new.metadata",
+                tables.root_hashes(),
+                SELECT_ROOT_HASH_DATA,
+                parts
+            ),
+            StorageType::LabelMapping => format!(
+                "INSERT INTO `{}` (`key`, {})
+            VALUES (:key, :mapper_id) as new
+            ON DUPLICATE KEY UPDATE `mapper_id` = new.mapper_id",
+                tables.label_mapping(),
+                SELECT_LABEL_MAPPING_DATA
             ),
         }
     }
 
-    fn set_batch_params(items: &[DbRecord]) -> Result<mysql_async::Params> {
+    fn set_batch_params(items: &[DbRecord], tables: &Tables) -> Result<mysql_async::Params> {
         let param_batch = items
             .iter()
             .enumerate()
@@ -260,110 +396,7 @@ impl MySqlStorable for DbRecord {
                     ("num_nodes".to_string(), Value::from(azks.num_nodes)),
                 ]),
                 DbRecord::TreeNode(node) => {
-                    let pnode = &node.previous_node;
-                    Ok(vec![
-                        (
-                            format!("label_len{}", idx),
-                            Value::from(node.label.label_len),
-                        ),
-                        (
-                            format!("label_val{}", idx),
-                            Value::from(node.label.label_val),
-                        ),
-                        (
-                            format!("last_epoch{}", idx),
-                            Value::from(node.latest_node.last_epoch),
-                        ),
-                        (
-                            format!("least_descendant_ep{}", idx),
-                            Value::from(node.latest_node.min_descendant_epoch),
-                        ),
-                        (
-                            format!("parent_label_len{}", idx),
-                            Value::from(node.latest_node.parent.label_len),
-                        ),
-                        (
-                            format!("parent_label_val{}", idx),
-                            Value::from(node.latest_node.parent.label_val),
-                        ),
-                        (
-                            format!("node_type{}", idx),
-                            Value::from(node.latest_node.node_type as u8),
-                        ),
-                        (
-                            format!("left_child_len{}", idx),
-                            Value::from(node.latest_node.left_child.map(|lc| lc.label_len)),
-                        ),
-                        (
-                            format!("left_child_label_val{}", idx),
-                            Value::from(node.latest_node.left_child.map(|lc| lc.label_val)),
-                        ),
-                        (
-                            format!("right_child_len{}", idx),
-                            Value::from(node.latest_node.right_child.map(|rc| rc.label_len)),
-                        ),
-                        (
-                            format!("right_child_label_val{}", idx),
-                            Value::from(node.latest_node.right_child.map(|rc| rc.label_val)),
-                        ),
-                        (format!("hash{}", idx), Value::from(node.latest_node.hash)),
-                        (
-                            format!("p_last_epoch{}", idx),
-                            Value::from(pnode.clone().map(|a| a.last_epoch)),
-                        ),
-                        (
-                            format!("p_least_descendant_ep{}", idx),
-                            Value::from(pnode.clone().map(|a| a.min_descendant_epoch)),
-                        ),
-                        (
-                            format!("p_parent_label_len{}", idx),
-                            Value::from(pnode.clone().map(|a| a.parent.label_len)),
-                        ),
-                        (
-                            format!("p_parent_label_val{}", idx),
-                            Value::from(pnode.clone().map(|a| a.parent.label_val)),
-                        ),
-                        (
-                            format!("p_node_type{}", idx),
-                            Value::from(pnode.clone().map(|a| a.node_type as u8)),
-                        ),
-                        (
-                            format!("p_left_child_len{}", idx),
-                            Value::from(
-                                pnode
-                                    .clone()
-                                    .and_then(|a| a.left_child.map(|lc| lc.label_len)),
-                            ),
-                        ),
-                        (
-                            format!("p_left_child_label_val{}", idx),
-                            Value::from(
-                                pnode
-                                    .clone()
-                                    .and_then(|a| a.left_child.map(|lc| lc.label_val)),
-                            ),
-                        ),
-                        (
-                            format!("p_right_child_len{}", idx),
-                            Value::from(
-                                pnode
-                                    .clone()
-                                    .and_then(|a| a.right_child.map(|rc| rc.label_len)),
-                            ),
-                        ),
-                        (
-                            format!("p_right_child_label_val{}", idx),
-                            Value::from(
-                                pnode
-                                    .clone()
-                                    .and_then(|a| a.right_child.map(|rc| rc.label_val)),
-                            ),
-                        ),
-                        (
-                            format!("p_hash{}", idx),
-                            Value::from(pnode.clone().map(|a| a.hash)),
-                        ),
-                    ])
+                    Ok(tables.tree_node_layout().params(node, &idx.to_string()))
                 }
                 DbRecord::ValueState(state) => Ok(vec![
                     (format!("username{}", idx), Value::from(state.get_id().0)),
@@ -381,6 +414,42 @@ impl MySqlStorable for DbRecord {
                         format!("data{}", idx),
                         Value::from(state.plaintext_val.0.clone()),
                     ),
+                    (
+                        format!("expiry_epoch{}", idx),
+                        Value::from(state.expiry_epoch),
+                    ),
+                ]),
+                DbRecord::TreeHead(tree_head) => Ok(vec![
+                    (format!("epoch{}", idx), Value::from(tree_head.epoch)),
+                    (
+                        format!("root_hash{}", idx),
+                        Value::from(tree_head.root_hash),
+                    ),
+                    (
+                        format!("timestamp{}", idx),
+                        Value::from(tree_head.timestamp),
+                    ),
+                    (
+                        format!("signature{}", idx),
+                        Value::from(tree_head.signature.clone()),
+                    ),
+                ]),
+                DbRecord::RootHash(record) => Ok(vec![
+                    (format!("epoch{}", idx), Value::from(record.epoch)),
+                    (format!("root_hash{}", idx), Value::from(record.root_hash)),
+                    (format!("timestamp{}", idx), Value::from(record.timestamp)),
+                    (format!("batch_size{}", idx), Value::from(record.batch_size)),
+                    (
+                        format!("metadata{}", idx),
+                        Value::from(record.metadata_to_bytes()),
+                    ),
+                ]),
+                DbRecord::LabelMapping(record) => Ok(vec![
+                    ("key".to_string(), Value::from(LABEL_MAPPING_KEY)),
+                    (
+                        "mapper_id".to_string(),
+                        Value::from(record.mapper_id.clone()),
+                    ),
                 ]),
             })
             .into_iter()
@@ -391,20 +460,60 @@ impl MySqlStorable for DbRecord {
         Ok(mysql_async::Params::from(param_batch))
     }
 
-    fn get_statement<St: Storable>() -> String {
+    fn get_statement<St: Storable>(tables: &Tables) -> String {
         match St::data_type() {
-            StorageType::Azks => format!("SELECT {} FROM `{}`", SELECT_AZKS_DATA, TABLE_AZKS),
+            StorageType::Azks => format!("SELECT {} FROM `{}`", SELECT_AZKS_DATA, tables.azks()),
             StorageType::TreeNode => format!(
                 "SELECT {} FROM `{}`",
-                SELECT_HISTORY_TREE_NODE_DATA, TABLE_HISTORY_TREE_NODES
+                tables.tree_node_layout().select(""),
+                tables.tree_nodes()
+            ),
+            StorageType::ValueState => {
+                format!("SELECT {} FROM `{}`", SELECT_USER_DATA, tables.users())
+            }
+            StorageType::TreeHead => format!(
+                "SELECT {} FROM `{}`",
+                SELECT_TREE_HEAD_DATA,
+                tables.tree_heads()
+            ),
+            StorageType::RootHash => format!(
+                "SELECT {} FROM `{}`",
+                SELECT_ROOT_HASH_DATA,
+                tables.root_hashes()
+            ),
+            StorageType::LabelMapping => format!(
+                "SELECT {} FROM `{}`",
+                SELECT_LABEL_MAPPING_DATA,
+                tables.label_mapping()
             ),
-            StorageType::ValueState => format!("SELECT {} FROM `{}`", SELECT_USER_DATA, TABLE_USER),
         }
     }
 
+    fn get_prefix_statement<St: Storable>(tables: &Tables, bounded: bool) -> String {
+        let column = match St::data_type() {
+            StorageType::Azks
+            | StorageType::TreeHead
+            | StorageType::RootHash
+            | StorageType::LabelMapping => return Self::get_statement::<St>(tables),
+            StorageType::TreeNode => "label_val",
+            StorageType::ValueState => "username",
+        };
+        let upper_bound = if bounded {
+            format!(" AND `{}` < :upper", column)
+        } else {
+            String::new()
+        };
+        format!(
+            "{} WHERE `{}` >= :lower{}",
+            Self::get_statement::<St>(tables),
+            column,
+            upper_bound
+        )
+    }
+
     fn get_batch_create_temp_table<St: Storable>() -> Option<String> {
         match St::data_type() {
-            StorageType::Azks => None,
+            StorageType::Azks | StorageType::LabelMapping => None,
             StorageType::TreeNode => {
                 Some(
                     format!(
@@ -421,12 +530,20 @@ impl MySqlStorable for DbRecord {
                     )
                 )
             },
+            StorageType::TreeHead | StorageType::RootHash => {
+                Some(
+                    format!(
+                        "CREATE TEMPORARY TABLE `{}`(`epoch` BIGINT UNSIGNED NOT NULL, PRIMARY KEY(`epoch`))",
+                        TEMP_IDS_TABLE
+                    )
+                )
+            },
         }
     }
 
     fn get_batch_fill_temp_table<St: Storable>(num_items: Option<usize>) -> String {
         let mut statement = match St::data_type() {
-            StorageType::Azks => "".to_string(),
+            StorageType::Azks | StorageType::LabelMapping => "".to_string(),
             StorageType::TreeNode => {
                 format!(
                     "INSERT INTO `{}` (`label_len`, `label_val`) VALUES ",
@@ -439,17 +556,21 @@ impl MySqlStorable for DbRecord {
                     TEMP_IDS_TABLE
                 )
             }
+            StorageType::TreeHead | StorageType::RootHash => {
+                format!("INSERT INTO `{}` (`epoch`) VALUES ", TEMP_IDS_TABLE)
+            }
         };
         if let Some(item_count) = num_items {
             for i in 0..item_count {
                 let append = match St::data_type() {
-                    StorageType::Azks => String::from(""),
+                    StorageType::Azks | StorageType::LabelMapping => String::from(""),
                     StorageType::TreeNode => {
                         format!("(:label_len{}, :label_val{})", i, i)
                     }
                     StorageType::ValueState => {
                         format!("(:username{}, :epoch{})", i, i)
                     }
+                    StorageType::TreeHead | StorageType::RootHash => format!("(:epoch{})", i),
                 };
                 statement = format!("{}{}", statement, append);
 
@@ -460,48 +581,34 @@ impl MySqlStorable for DbRecord {
             }
         } else {
             statement += match St::data_type() {
-                StorageType::Azks => "",
+                StorageType::Azks | StorageType::LabelMapping => "",
                 StorageType::TreeNode => "(:label_len, :label_val)",
                 StorageType::ValueState => "(:username, :epoch)",
+                StorageType::TreeHead | StorageType::RootHash => "(:epoch)",
             };
         }
         statement
     }
 
-    fn get_batch_statement<St: Storable>() -> String {
+    fn get_batch_statement<St: Storable>(tables: &Tables) -> String {
         match St::data_type() {
             StorageType::Azks => {
-                format!("SELECT {} FROM `{}` LIMIT 1", SELECT_AZKS_DATA, TABLE_AZKS)
+                format!(
+                    "SELECT {} FROM `{}` LIMIT 1",
+                    SELECT_AZKS_DATA,
+                    tables.azks()
+                )
             }
             StorageType::TreeNode => {
                 format!(
-                    "SELECT
-                        a.`label_len`
-                        , a.`label_val`
-                        , a.`last_epoch`
-                        , a.`least_descendant_ep`
-                        , a.`parent_label_len`
-                        , a.`parent_label_val`
-                        , a.`node_type`
-                        , a.`left_child_len`
-                        , a.`left_child_label_val`
-                        , a.`right_child_len`
-                        , a.`right_child_label_val`
-                        , a.`hash`, a.`p_last_epoch`
-                        , a.`p_least_descendant_ep`
-                        , a.`p_parent_label_len`
-                        , a.`p_parent_label_val`
-                        , a.`p_node_type`
-                        , a.`p_left_child_len`
-                        , a.`p_left_child_label_val`
-                        , a.`p_right_child_len`
-                        , a.`p_right_child_label_val`
-                        , a.`p_hash`
+                    "SELECT {}
                     FROM `{}` a
                     INNER JOIN {} ids
                         ON ids.`label_len` = a.`label_len`
                         AND ids.`label_val` = a.`label_val`",
-                    TABLE_HISTORY_TREE_NODES, TEMP_IDS_TABLE
+                    tables.tree_node_layout().select("a."),
+                    tables.tree_nodes(),
+                    TEMP_IDS_TABLE
                 )
             }
             StorageType::ValueState => {
@@ -513,35 +620,120 @@ impl MySqlStorable for DbRecord {
                         , a.`node_label_val`
                         , a.`node_label_len`
                         , a.`data`
+                        , a.`expiry_epoch`
                     FROM `{}` a
                     INNER JOIN {} ids
                         ON ids.`username` = a.`username`
                         AND ids.`epoch` = a.`epoch`",
-                    TABLE_USER, TEMP_IDS_TABLE
+                    tables.users(),
+                    TEMP_IDS_TABLE
                 )
             }
+            StorageType::TreeHead => {
+                format!(
+                    "SELECT
+                        a.`epoch`
+                        , a.`root_hash`
+                        , a.`timestamp`
+                        , a.`signature`
+                    FROM `{}` a
+                    INNER JOIN {} ids
+                        ON ids.`epoch` = a.`epoch`",
+                    tables.tree_heads(),
+                    TEMP_IDS_TABLE
+                )
+            }
+            StorageType::RootHash => {
+                format!(
+                    "SELECT
+                        a.`epoch`
+                        , a.`root_hash`
+                        , a.`timestamp`
+                        , a.`batch_size`
+                        , a.`metadata`
+                    FROM `{}` a
+                    INNER JOIN {} ids
+                        ON ids.`epoch` = a.`epoch`",
+                    tables.root_hashes(),
+                    TEMP_IDS_TABLE
+                )
+            }
+            StorageType::LabelMapping => format!(
+                "SELECT {} FROM `{}` LIMIT 1",
+                SELECT_LABEL_MAPPING_DATA,
+                tables.label_mapping()
+            ),
         }
     }
 
-    fn get_specific_statement<St: Storable>() -> String {
+    fn get_specific_statement<St: Storable>(tables: &Tables) -> String {
         match St::data_type() {
             StorageType::Azks => {
-                format!("SELECT {} FROM `{}` LIMIT 1", SELECT_AZKS_DATA, TABLE_AZKS)
+                format!(
+                    "SELECT {} FROM `{}` LIMIT 1",
+                    SELECT_AZKS_DATA,
+                    tables.azks()
+                )
             }
             StorageType::TreeNode => format!(
                 "SELECT {} FROM `{}` WHERE `label_len` = :label_len AND `label_val` = :label_val",
-                SELECT_HISTORY_TREE_NODE_DATA, TABLE_HISTORY_TREE_NODES
+                tables.tree_node_layout().select(""),
+                tables.tree_nodes()
             ),
             StorageType::ValueState => format!(
                 "SELECT {} FROM `{}` WHERE `username` = :username AND `epoch` = :epoch",
-                SELECT_USER_DATA, TABLE_USER
+                SELECT_USER_DATA,
+                tables.users()
+            ),
+            StorageType::TreeHead => format!(
+                "SELECT {} FROM `{}` WHERE `epoch` = :epoch",
+                SELECT_TREE_HEAD_DATA,
+                tables.tree_heads()
+            ),
+            StorageType::RootHash => format!(
+                "SELECT {} FROM `{}` WHERE `epoch` = :epoch",
+                SELECT_ROOT_HASH_DATA,
+                tables.root_hashes()
+            ),
+            StorageType::LabelMapping => format!(
+                "SELECT {} FROM `{}` LIMIT 1",
+                SELECT_LABEL_MAPPING_DATA,
+                tables.label_mapping()
             ),
         }
     }
 
+    fn delete_specific_statement<St: Storable>(tables: &Tables) -> String {
+        // takes the same parameters as the specific get statement
+        match St::data_type() {
+            StorageType::Azks => format!("DELETE FROM `{}`", tables.azks()),
+            StorageType::TreeNode => format!(
+                "DELETE FROM `{}` WHERE `label_len` = :label_len AND `label_val` = :label_val",
+                tables.tree_nodes()
+            ),
+            StorageType::ValueState => format!(
+                "DELETE FROM `{}` WHERE `username` = :username AND `epoch` = :epoch",
+                tables.users()
+            ),
+            StorageType::TreeHead => {
+                format!(
+                    "DELETE FROM `{}` WHERE `epoch` = :epoch",
+                    tables.tree_heads()
+                )
+            }
+            StorageType::RootHash => {
+                format!(
+                    "DELETE FROM `{}` WHERE `epoch` = :epoch",
+                    tables.root_hashes()
+                )
+            }
+            StorageType::LabelMapping => format!("DELETE FROM `{}`", tables.label_mapping()),
+        }
+    }
+
     fn get_specific_params<St: Storable>(key: &St::StorageKey) -> Option<mysql_async::Params> {
         match St::data_type() {
-            StorageType::Azks => None,
+            StorageType::Azks | StorageType::LabelMapping => None,
             StorageType::TreeNode => {
                 let bin = St::get_full_binary_key_id(key);
                 if let Ok(back) = TreeNodeWithPreviousValue::key_from_full_binary(&bin) {
@@ -564,6 +756,26 @@ impl MySqlStorable for DbRecord {
                     None
                 }
             }
+            StorageType::TreeHead => {
+                let bin = St::get_full_binary_key_id(key);
+                if let Ok(epoch) = akd::SignedTreeHead::key_from_full_binary(&bin) {
+                    Some(params! {
+                        "epoch" => epoch
+                    })
+                } else {
+                    None
+                }
+            }
+            StorageType::RootHash => {
+                let bin = St::get_full_binary_key_id(key);
+                if let Ok(epoch) = RootHashRecord::key_from_full_binary(&bin) {
+                    Some(params! {
+                        "epoch" => epoch
+                    })
+                } else {
+                    None
+                }
+            }
         }
     }
 
@@ -571,7 +783,7 @@ impl MySqlStorable for DbRecord {
         keys: &[St::StorageKey],
     ) -> Option<mysql_async::Params> {
         match St::data_type() {
-            StorageType::Azks => None,
+            StorageType::Azks | StorageType::LabelMapping => None,
             StorageType::TreeNode => {
                 let pvec = keys
                     .iter()
@@ -612,6 +824,34 @@ impl MySqlStorable for DbRecord {
                     .collect::<Vec<_>>();
                 Some(mysql_async::Params::from(pvec))
             }
+            StorageType::TreeHead => {
+                let pvec = keys
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, key)| {
+                        let bin = St::get_full_binary_key_id(key);
+                        // Since these are constructed from a safe key, they should never fail
+                        // so we'll leave the unwrap to simplify
+                        let epoch = akd::SignedTreeHead::key_from_full_binary(&bin).unwrap();
+                        (format!("epoch{}", idx), Value::from(epoch))
+                    })
+                    .collect::<Vec<_>>();
+                Some(mysql_async::Params::from(pvec))
+            }
+            StorageType::RootHash => {
+                let pvec = keys
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, key)| {
+                        let bin = St::get_full_binary_key_id(key);
+                        // Since these are constructed from a safe key, they should never fail
+                        // so we'll leave the unwrap to simplify
+                        let epoch = RootHashRecord::key_from_full_binary(&bin).unwrap();
+                        (format!("epoch{}", idx), Value::from(epoch))
+                    })
+                    .collect::<Vec<_>>();
+                Some(mysql_async::Params::from(pvec))
+            }
         }
     }
 
@@ -627,39 +867,6 @@ impl MySqlStorable for DbRecord {
             })
         }
 
-        fn optional_child_label(
-            child_val: Option<Value>,
-            child_len: Option<Value>,
-        ) -> core::result::Result<Option<NodeLabel>, MySqlError> {
-            match (child_val, child_len) {
-                (Some(Value::NULL), _) => Ok(None),
-                (_, Some(Value::NULL)) => Ok(None),
-                (None, _) => Ok(None),
-                (_, None) => Ok(None),
-                (Some(possible_bytes), Some(possible_u32)) => {
-                    match (
-                        from_value_opt::<u32>(possible_u32),
-                        from_value_opt::<Vec<u8>>(possible_bytes),
-                    ) {
-                        (Ok(len), Ok(val)) => Ok(Some(NodeLabel::new(
-                            val.try_into().map_err(|_| cast_err())?,
-                            len,
-                        ))),
-                        (Err(_len_err), _) => Err(Error::from(mysql_async::ServerError {
-                            state: "".to_string(),
-                            code: 0,
-                            message: "Error decoding length".to_string(),
-                        })),
-                        (_, Err(_val_err)) => Err(Error::from(mysql_async::ServerError {
-                            state: "".to_string(),
-                            code: 0,
-                            message: "Error decoding value".to_string(),
-                        })),
-                    }
-                }
-            }
-        }
-
         match St::data_type() {
             StorageType::Azks => {
                 // epoch, num_nodes
@@ -669,111 +876,29 @@ impl MySqlStorable for DbRecord {
                 }
             }
             StorageType::TreeNode => {
-                // A NOTE ABOUT THE SYNTAX HERE: The outer-most Some(**) of the row.take(..) indicates the COLUMN EXISTS, not that it actually has a value.
-                // Therefore the signature of these fields you want is Some(Option<value>) for a nullable column. The inner option type is not necessary
-                // for types which have NOT NULL in the SQL definition
-                if let (
-                    Some(Ok(label_len)),
-                    Some(Ok(label_val)),
-                    Some(Ok(last_epoch)),
-                    Some(Ok(least_descendant_ep)),
-                    Some(Ok(parent_label_len)),
-                    Some(Ok(parent_label_val)),
-                    Some(Ok(node_type)),
-                    left_child_len_res,
-                    left_child_val_res,
-                    right_child_len_res,
-                    right_child_val_res,
-                    Some(Ok(hash)),
-                    Some(p_last_epoch),
-                    Some(p_least_descendant_ep),
-                    Some(p_parent_label_len),
-                    Some(p_parent_label_val),
-                    Some(p_node_type),
-                    p_left_child_len,
-                    p_left_child_label_val,
-                    p_right_child_len,
-                    p_right_child_label_val,
-                    Some(p_hash),
-                ) = (
-                    row.take_opt(0),
-                    row.take_opt(1),
-                    row.take_opt(2),
-                    row.take_opt(3),
-                    row.take_opt(4),
-                    row.take_opt(5),
-                    row.take_opt(6),
-                    row.take(7),
-                    row.take(8),
-                    row.take(9),
-                    row.take(10),
-                    row.take_opt(11),
-                    row.take(12),
-                    row.take(13),
-                    row.take(14),
-                    row.take(15),
-                    row.take(16),
-                    row.take(17),
-                    row.take(18),
-                    row.take(19),
-                    row.take(20),
-                    row.take(21),
-                ) {
-                    let left_child = optional_child_label(left_child_val_res, left_child_len_res)?;
-                    let right_child =
-                        optional_child_label(right_child_val_res, right_child_len_res)?;
-                    let p_left_child =
-                        optional_child_label(p_left_child_label_val, p_left_child_len)?;
-                    let p_right_child =
-                        optional_child_label(p_right_child_label_val, p_right_child_len)?;
-
-                    let label_val_vec: Vec<u8> = label_val;
-
-                    let parent_label_val_vec: Vec<u8> = parent_label_val;
-                    let prev_parent_label_val_vec: Option<Vec<u8>> = p_parent_label_val;
-                    let hash_vec: Vec<u8> = hash;
-                    let prev_hash_vec: Option<Vec<u8>> = p_hash;
-
-                    let massaged_prev_parent_label_val: Option<[u8; 32]> =
-                        match prev_parent_label_val_vec {
-                            Some(v) => Some(v.try_into().map_err(|_| cast_err())?),
-                            None => None,
-                        };
-                    let massaged_hash_vec: akd::Digest =
-                        akd::hash::try_parse_digest(&hash_vec).map_err(|_| cast_err())?;
-                    let massaged_prev_hash_vec: Option<akd::Digest> = match prev_hash_vec {
-                        Some(v) => match akd::hash::try_parse_digest(&v).map_err(|_| cast_err()) {
-                            Ok(r) => Some(r),
-                            Err(err) => return Err(err),
-                        },
-                        None => None,
-                    };
-
-                    let node = DbRecord::build_tree_node_with_previous_value(
-                        label_val_vec.try_into().map_err(|_| cast_err())?,
-                        label_len,
-                        last_epoch,
-                        least_descendant_ep,
-                        parent_label_val_vec.try_into().map_err(|_| cast_err())?,
-                        parent_label_len,
-                        node_type,
-                        left_child,
-                        right_child,
-                        massaged_hash_vec,
-                        p_last_epoch,
-                        p_least_descendant_ep,
-                        massaged_prev_parent_label_val,
-                        p_parent_label_len,
-                        p_node_type,
-                        p_left_child,
-                        p_right_child,
-                        massaged_prev_hash_vec,
-                    );
+                // `label_len`, `label_val`, and the node in either layout, see [TreeNodeLayout]
+                if !row
+                    .columns_ref()
+                    .iter()
+                    .any(|column| column.name_str() == "node")
+                {
+                    return Ok(DbRecord::TreeNode(legacy_tree_node_from_row(row)?));
+                }
+                // the label is only stored in its own columns to key the table, the
+                // node is decoded from its compact encoding
+                if let Some(Ok(node)) = row.take_opt::<Vec<u8>, _>(2) {
+                    let node = TreeNodeWithPreviousValue::from_bytes(&node).map_err(|err| {
+                        MySqlError::from(mysql_async::ServerError {
+                            state: "".to_string(),
+                            code: 0,
+                            message: err.to_string(),
+                        })
+                    })?;
                     return Ok(DbRecord::TreeNode(node));
                 }
             }
             StorageType::ValueState => {
-                // `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`
+                // `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`, `expiry_epoch`
                 if let (
                     Some(Ok(username)),
                     Some(Ok(epoch)),
@@ -781,6 +906,7 @@ impl MySqlStorable for DbRecord {
                     Some(Ok(node_label_val)),
                     Some(Ok(node_label_len)),
                     Some(Ok(data)),
+                    Some(Ok(expiry_epoch)),
                 ) = (
                     row.take_opt(0),
                     row.take_opt(1),
@@ -788,9 +914,10 @@ impl MySqlStorable for DbRecord {
                     row.take_opt(3),
                     row.take_opt(4),
                     row.take_opt(5),
+                    row.take_opt(6),
                 ) {
                     let node_label_val_vec: Vec<u8> = node_label_val;
-                    let state = DbRecord::build_user_state(
+                    let mut state = DbRecord::build_user_state(
                         username,
                         data,
                         version,
@@ -798,12 +925,210 @@ impl MySqlStorable for DbRecord {
                         node_label_val_vec.try_into().map_err(|_| cast_err())?,
                         epoch,
                     );
+                    state.expiry_epoch = expiry_epoch;
                     return Ok(DbRecord::ValueState(state));
                 }
             }
+            StorageType::TreeHead => {
+                // `epoch`, `root_hash`, `timestamp`, `signature`
+                if let (
+                    Some(Ok(epoch)),
+                    Some(Ok(root_hash)),
+                    Some(Ok(timestamp)),
+                    Some(Ok(signature)),
+                ) = (
+                    row.take_opt(0),
+                    row.take_opt(1),
+                    row.take_opt(2),
+                    row.take_opt(3),
+                ) {
+                    let root_hash_vec: Vec<u8> = root_hash;
+                    let tree_head = DbRecord::build_tree_head(
+                        epoch,
+                        akd::hash::try_parse_digest(&root_hash_vec).map_err(|_| cast_err())?,
+                        timestamp,
+                        signature,
+                    );
+                    return Ok(DbRecord::TreeHead(tree_head));
+                }
+            }
+            StorageType::RootHash => {
+                // `epoch`, `root_hash`, `timestamp`, `batch_size`, `metadata`
+                if let (
+                    Some(Ok(epoch)),
+                    Some(Ok(root_hash)),
+                    Some(Ok(timestamp)),
+                    Some(Ok(batch_size)),
+                    Some(Ok(metadata)),
+                ) = (
+                    row.take_opt(0),
+                    row.take_opt(1),
+                    row.take_opt(2),
+                    row.take_opt(3),
+                    row.take_opt(4),
+                ) {
+                    let root_hash_vec: Vec<u8> = root_hash;
+                    let metadata_vec: Vec<u8> = metadata;
+                    let mut record = DbRecord::build_root_hash_record(
+                        epoch,
+                        akd::hash::try_parse_digest(&root_hash_vec).map_err(|_| cast_err())?,
+                        timestamp,
+                        batch_size,
+                    );
+                    record.metadata = RootHashRecord::metadata_from_bytes(&metadata_vec)
+                        .map_err(|_| cast_err())?;
+                    return Ok(DbRecord::RootHash(record));
+                }
+            }
+            StorageType::LabelMapping => {
+                // mapper_id
+                if let Some(Ok(mapper_id)) = row.take_opt(0) {
+                    let record = DbRecord::build_label_mapping_record(mapper_id);
+                    return Ok(DbRecord::LabelMapping(record));
+                }
+            }
         }
         // fallback
         let err = MySqlError::Driver(mysql_async::DriverError::FromRow { row: row.clone() });
         Err(err)
     }
 }
+
+/// The columns of a tree node table created before the nodes were stored in their
+/// compact encoding, with a column per field of the latest and previous nodes
+pub(crate) const LEGACY_TREE_NODE_COLUMNS: [&str; 20] = [
+    "last_epoch",
+    "least_descendant_ep",
+    "parent_label_len",
+    "parent_label_val",
+    "node_type",
+    "left_child_len",
+    "left_child_label_val",
+    "right_child_len",
+    "right_child_label_val",
+    "hash",
+    "p_last_epoch",
+    "p_least_descendant_ep",
+    "p_parent_label_len",
+    "p_parent_label_val",
+    "p_node_type",
+    "p_left_child_len",
+    "p_left_child_label_val",
+    "p_right_child_len",
+    "p_right_child_label_val",
+    "p_hash",
+];
+
+/// Decodes a tree node from a row of a legacy tree node table, selecting
+/// `label_len`, `label_val` and then the [LEGACY_TREE_NODE_COLUMNS] in order
+pub(crate) fn legacy_tree_node_from_row(
+    row: &mut mysql_async::Row,
+) -> core::result::Result<TreeNodeWithPreviousValue, MySqlError> {
+    fn cast_err() -> MySqlError {
+        MySqlError::from(mysql_async::ServerError {
+            state: "".to_string(),
+            code: 0,
+            message: "Failed to cast label:val into [u8; 32]".to_string(),
+        })
+    }
+
+    fn optional_child_label(
+        child_val: Option<Value>,
+        child_len: Option<Value>,
+    ) -> core::result::Result<Option<akd::NodeLabel>, MySqlError> {
+        match (child_val, child_len) {
+            (Some(Value::NULL), _) | (_, Some(Value::NULL)) | (None, _) | (_, None) => Ok(None),
+            (Some(val), Some(len)) => {
+                match (from_value_opt::<u32>(len), from_value_opt::<Vec<u8>>(val)) {
+                    (Ok(len), Ok(val)) => Ok(Some(akd::NodeLabel::new(
+                        val.try_into().map_err(|_| cast_err())?,
+                        len,
+                    ))),
+                    _ => Err(cast_err()),
+                }
+            }
+        }
+    }
+
+    fn optional_bytes<const N: usize>(
+        value: Option<Vec<u8>>,
+    ) -> core::result::Result<Option<[u8; N]>, MySqlError> {
+        value
+            .map(|value| value.try_into().map_err(|_| cast_err()))
+            .transpose()
+    }
+
+    // The outer-most Some(..) of row.take(..) indicates the column exists, and the
+    // inner option whether the nullable column has a value
+    if let (
+        Some(label_len),
+        Some(label_val),
+        Some(last_epoch),
+        Some(least_descendant_ep),
+        Some(parent_label_len),
+        Some(parent_label_val),
+        Some(node_type),
+        left_child_len,
+        left_child_val,
+        right_child_len,
+        right_child_val,
+        Some(hash),
+        Some(p_last_epoch),
+        Some(p_least_descendant_ep),
+        Some(p_parent_label_len),
+        Some(p_parent_label_val),
+        Some(p_node_type),
+        p_left_child_len,
+        p_left_child_val,
+        p_right_child_len,
+        p_right_child_val,
+        Some(p_hash),
+    ) = (
+        row.take::<u32, _>(0),
+        row.take::<Vec<u8>, _>(1),
+        row.take(2),
+        row.take(3),
+        row.take(4),
+        row.take::<Vec<u8>, _>(5),
+        row.take(6),
+        row.take(7),
+        row.take(8),
+        row.take(9),
+        row.take(10),
+        row.take::<Vec<u8>, _>(11),
+        row.take(12),
+        row.take(13),
+        row.take(14),
+        row.take::<Option<Vec<u8>>, _>(15),
+        row.take(16),
+        row.take(17),
+        row.take(18),
+        row.take(19),
+        row.take(20),
+        row.take::<Option<Vec<u8>>, _>(21),
+    ) {
+        return Ok(DbRecord::build_tree_node_with_previous_value(
+            label_val.try_into().map_err(|_| cast_err())?,
+            label_len,
+            last_epoch,
+            least_descendant_ep,
+            parent_label_val.try_into().map_err(|_| cast_err())?,
+            parent_label_len,
+            node_type,
+            optional_child_label(left_child_val, left_child_len)?,
+            optional_child_label(right_child_val, right_child_len)?,
+            akd::hash::try_parse_digest(&hash).map_err(|_| cast_err())?,
+            p_last_epoch,
+            p_least_descendant_ep,
+            optional_bytes(p_parent_label_val)?,
+            p_parent_label_len,
+            p_node_type,
+            optional_child_label(p_left_child_val, p_left_child_len)?,
+            optional_child_label(p_right_child_val, p_right_child_len)?,
+            optional_bytes(p_hash)?,
+        ));
+    }
+    Err(MySqlError::Driver(mysql_async::DriverError::FromRow {
+        row: row.clone(),
+    }))
+}
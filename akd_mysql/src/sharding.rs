@@ -0,0 +1,137 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! This module implements the horizontal partitioning of tree node rows across
+//! multiple MySQL tables, based on the first byte of the node's label.
+//!
+//! A [ShardMap] is a list of boundaries, where shard `i` holds every node whose
+//! label starts with a byte in `boundaries[i]..boundaries[i + 1]` (the last shard
+//! extending to `u8::MAX`). A single shard corresponds to the regular, unsharded,
+//! table layout.
+//!
+//! ⚠️ **Warning**: The shard map determines where nodes are read from, so it must
+//! not change over the lifetime of a database. Re-opening an existing database with
+//! a different shard map will not migrate rows between tables.
+
+use akd::errors::StorageError;
+use akd::storage::types::StorageType;
+use akd::storage::Storable;
+use akd::tree_node::TreeNodeWithPreviousValue;
+use akd::NodeLabel;
+
+use crate::mysql_storables::TABLE_HISTORY_TREE_NODES;
+
+/// The maximum number of shards, one per possible value of a label's first byte
+pub const MAX_SHARDS: usize = 256;
+
+/// Describes how the tree node rows are partitioned across tables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardMap {
+    boundaries: Vec<u8>,
+}
+
+impl Default for ShardMap {
+    fn default() -> Self {
+        Self::single()
+    }
+}
+
+impl ShardMap {
+    /// A single shard, holding all the tree nodes in one table
+    pub fn single() -> Self {
+        Self {
+            boundaries: vec![0u8],
+        }
+    }
+
+    /// Splits the range of label prefixes into `num_shards` shards of
+    /// (approximately) equal size
+    pub fn uniform(num_shards: usize) -> Result<Self, StorageError> {
+        if num_shards == 0 || num_shards > MAX_SHARDS {
+            return Err(StorageError::Other(format!(
+                "The number of shards must be between 1 and {}, got {}",
+                MAX_SHARDS, num_shards
+            )));
+        }
+        let boundaries = (0..num_shards)
+            .map(|i| (i * MAX_SHARDS / num_shards) as u8)
+            .collect();
+        Ok(Self { boundaries })
+    }
+
+    /// Builds a shard map from the first label byte of each shard. The
+    /// boundaries must start at 0 and be strictly increasing.
+    pub fn from_boundaries(boundaries: Vec<u8>) -> Result<Self, StorageError> {
+        if boundaries.first() != Some(&0u8) {
+            return Err(StorageError::Other(
+                "The first shard boundary must be 0".to_string(),
+            ));
+        }
+        if boundaries.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(StorageError::Other(format!(
+                "Shard boundaries must be strictly increasing, got {:?}",
+                boundaries
+            )));
+        }
+        Ok(Self { boundaries })
+    }
+
+    /// The number of shards
+    pub fn num_shards(&self) -> usize {
+        self.boundaries.len()
+    }
+
+    /// The first label byte of each shard
+    pub fn boundaries(&self) -> &[u8] {
+        &self.boundaries
+    }
+
+    /// The shard holding the node with the given label
+    pub fn shard_for_label(&self, label: &NodeLabel) -> usize {
+        // boundaries[0] is 0, so at least one boundary is always <= the byte
+        self.boundaries
+            .partition_point(|boundary| *boundary <= label.label_val[0])
+            - 1
+    }
+
+    /// The name of the table holding the given shard. A single shard uses
+    /// the unsharded table name.
+    pub fn table_name(&self, shard: usize) -> String {
+        if self.num_shards() == 1 {
+            TABLE_HISTORY_TREE_NODES.to_string()
+        } else {
+            format!("{}_{}", TABLE_HISTORY_TREE_NODES, shard)
+        }
+    }
+
+    /// The names of the tables holding all of the shards
+    pub fn table_names(&self) -> Vec<String> {
+        (0..self.num_shards())
+            .map(|shard| self.table_name(shard))
+            .collect()
+    }
+
+    /// The name of the table holding the node with the given label
+    pub fn table_for_label(&self, label: &NodeLabel) -> String {
+        self.table_name(self.shard_for_label(label))
+    }
+
+    /// The shard of a storage key. Records other than tree nodes are not
+    /// sharded and always map to the first shard.
+    pub(crate) fn shard_for_key<St: Storable>(&self, key: &St::StorageKey) -> usize {
+        match St::data_type() {
+            StorageType::TreeNode => {
+                let bin = St::get_full_binary_key_id(key);
+                match TreeNodeWithPreviousValue::key_from_full_binary(&bin) {
+                    Ok(node_key) => self.shard_for_label(&node_key.0),
+                    Err(_) => 0,
+                }
+            }
+            _ => 0,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Replays a commitment log against a fresh in-memory directory, checking that
+//! every epoch results in the archived root hash (see [akd_test_tools::replay]).
+//! Example command:
+//!
+//!   cargo run --bin replay -- --log commitments.yaml
+//!
+
+use std::fs::File;
+
+use akd::ecvrf::HardCodedAkdVRF;
+use akd::storage::{memory::AsyncInMemoryDatabase, StorageManager};
+use akd_test_tools::replay::{read_log, replay};
+use clap::Parser;
+
+/// Commitment log replay tool
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Arguments {
+    /// The commitment log to replay
+    #[clap(long)]
+    log: String,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Arguments::parse();
+    let log = match File::open(&args.log)
+        .map_err(|err| err.to_string())
+        .and_then(|file| read_log(file).map_err(|err| err.to_string()))
+    {
+        Ok(log) => log,
+        Err(err) => {
+            eprintln!("Failed to read {}: {}", args.log, err);
+            std::process::exit(2);
+        }
+    };
+
+    let storage = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    match replay(&storage, &HardCodedAkdVRF {}, log).await {
+        Ok(report) => println!(
+            "Replayed {} epochs ({} updates) in {:?}, root hash {:?}",
+            report.epochs_replayed, report.updates_replayed, report.elapsed, report.root_hash
+        ),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
@@ -7,4 +7,10 @@
 
 pub mod fixture_generator;
 
+pub mod proof_mutation;
+
 pub mod test_suites;
+
+pub mod soak;
+
+pub mod replay;
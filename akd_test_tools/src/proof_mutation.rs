@@ -0,0 +1,322 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Programmatic corruption of proofs, used to check that the client
+//! verification rejects any tampering with a valid proof.
+//!
+//! Each of the `*_mutations` functions takes a valid proof and returns a set of
+//! corrupted copies of it (bit flips, node swaps, epoch tampering, ...), each
+//! of which must fail to verify. The `assert_*_mutations_fail` helpers run the
+//! corresponding verification on every mutation and panic if any of them
+//! verifies.
+
+use akd::{
+    AkdLabel, AppendOnlyProof, Digest, HistoryProof, HistoryVerificationParams, LookupProof,
+    MembershipProof, NonMembershipProof,
+};
+
+/// A corrupted copy of a proof, along with a description of the corruption
+pub struct Mutation<T> {
+    /// What was corrupted in the proof
+    pub description: String,
+    /// The corrupted proof
+    pub proof: T,
+}
+
+/// Flips the lowest bit of the first byte, or appends a byte if there are none
+fn flip_bit(bytes: &mut Vec<u8>) {
+    match bytes.first_mut() {
+        Some(byte) => *byte ^= 1,
+        None => bytes.push(1),
+    }
+}
+
+fn flip_digest_bit(digest: &mut Digest) {
+    digest[0] ^= 1;
+}
+
+/// Accumulates the mutations of a proof, dropping any which leave it unchanged
+struct Mutations<'a, T: Clone + PartialEq> {
+    original: &'a T,
+    mutations: Vec<Mutation<T>>,
+}
+
+impl<'a, T: Clone + PartialEq> Mutations<'a, T> {
+    fn new(original: &'a T) -> Self {
+        Self {
+            original,
+            mutations: vec![],
+        }
+    }
+
+    fn add(&mut self, description: &str, mutate: impl FnOnce(&mut T)) {
+        let mut proof = self.original.clone();
+        mutate(&mut proof);
+        if proof != *self.original {
+            self.mutations.push(Mutation {
+                description: description.to_string(),
+                proof,
+            });
+        }
+    }
+}
+
+/// Returns the corruptions of a valid [LookupProof]
+pub fn lookup_proof_mutations(proof: &LookupProof) -> Vec<Mutation<LookupProof>> {
+    let mut mutations = Mutations::new(proof);
+    mutations.add("epoch incremented", |p| p.epoch += 1);
+    mutations.add("version incremented", |p| p.version += 1);
+    mutations.add("plaintext value bit flipped", |p| {
+        flip_bit(&mut p.plaintext_value.0)
+    });
+    mutations.add("commitment proof bit flipped", |p| {
+        flip_bit(&mut p.commitment_proof)
+    });
+    mutations.add("existence VRF proof bit flipped", |p| {
+        flip_bit(&mut p.existence_vrf_proof)
+    });
+    mutations.add("marker VRF proof bit flipped", |p| {
+        flip_bit(&mut p.marker_vrf_proof)
+    });
+    mutations.add("freshness VRF proof bit flipped", |p| {
+        flip_bit(&mut p.freshness_vrf_proof)
+    });
+    add_membership_mutations(&mut mutations, "existence proof", |p| {
+        &mut p.existence_proof
+    });
+    add_membership_mutations(&mut mutations, "marker proof", |p| &mut p.marker_proof);
+    add_non_membership_mutations(&mut mutations, "freshness proof", |p| {
+        &mut p.freshness_proof
+    });
+    mutations.add("existence and marker proofs swapped", |p| {
+        std::mem::swap(&mut p.existence_proof, &mut p.marker_proof)
+    });
+    mutations.mutations
+}
+
+/// Returns the corruptions of a valid [HistoryProof]
+pub fn history_proof_mutations(proof: &HistoryProof) -> Vec<Mutation<HistoryProof>> {
+    let mut mutations = Mutations::new(proof);
+    for i in 0..proof.update_proofs.len() {
+        mutations.add(&format!("update {}: epoch incremented", i), |p| {
+            p.update_proofs[i].epoch += 1
+        });
+        mutations.add(&format!("update {}: version incremented", i), |p| {
+            p.update_proofs[i].version += 1
+        });
+        mutations.add(&format!("update {}: plaintext value bit flipped", i), |p| {
+            flip_bit(&mut p.update_proofs[i].plaintext_value.0)
+        });
+        mutations.add(
+            &format!("update {}: commitment proof bit flipped", i),
+            |p| flip_bit(&mut p.update_proofs[i].commitment_proof),
+        );
+        mutations.add(
+            &format!("update {}: existence VRF proof bit flipped", i),
+            |p| flip_bit(&mut p.update_proofs[i].existence_vrf_proof),
+        );
+        add_membership_mutations(
+            &mut mutations,
+            &format!("update {}: existence proof", i),
+            move |p| &mut p.update_proofs[i].existence_at_ep,
+        );
+        mutations.add(
+            &format!("update {}: previous version VRF proof bit flipped", i),
+            |p| {
+                if let Some(vrf_proof) = p.update_proofs[i].previous_version_vrf_proof.as_mut() {
+                    flip_bit(vrf_proof);
+                }
+            },
+        );
+        mutations.add(
+            &format!(
+                "update {}: previous version stale proof hash bit flipped",
+                i
+            ),
+            |p| {
+                if let Some(stale_proof) = p.update_proofs[i].previous_version_stale_at_ep.as_mut()
+                {
+                    flip_digest_bit(&mut stale_proof.hash_val);
+                }
+            },
+        );
+    }
+    mutations.add("first two updates swapped", |p| {
+        if p.update_proofs.len() > 1 {
+            p.update_proofs.swap(0, 1);
+        }
+    });
+    for i in 0..proof.next_few_vrf_proofs.len() {
+        mutations.add(&format!("next few VRF proof {} bit flipped", i), |p| {
+            flip_bit(&mut p.next_few_vrf_proofs[i])
+        });
+        add_non_membership_mutations(
+            &mut mutations,
+            &format!("next few non-existence proof {}", i),
+            move |p| &mut p.non_existence_of_next_few[i],
+        );
+    }
+    for i in 0..proof.future_marker_vrf_proofs.len() {
+        mutations.add(&format!("future marker VRF proof {} bit flipped", i), |p| {
+            flip_bit(&mut p.future_marker_vrf_proofs[i])
+        });
+        add_non_membership_mutations(
+            &mut mutations,
+            &format!("future marker non-existence proof {}", i),
+            move |p| &mut p.non_existence_of_future_markers[i],
+        );
+    }
+    mutations.mutations
+}
+
+/// Returns the corruptions of a valid [AppendOnlyProof]
+pub fn append_only_proof_mutations(proof: &AppendOnlyProof) -> Vec<Mutation<AppendOnlyProof>> {
+    let mut mutations = Mutations::new(proof);
+    for i in 0..proof.proofs.len() {
+        if !proof.proofs[i].inserted.is_empty() {
+            mutations.add(&format!("proof {}: epoch incremented", i), |p| {
+                p.epochs[i] += 1
+            });
+        }
+        mutations.add(&format!("proof {}: inserted hash bit flipped", i), |p| {
+            if let Some(node) = p.proofs[i].inserted.first_mut() {
+                flip_digest_bit(&mut node.hash);
+            }
+        });
+        mutations.add(&format!("proof {}: unchanged hash bit flipped", i), |p| {
+            if let Some(node) = p.proofs[i].unchanged_nodes.first_mut() {
+                flip_digest_bit(&mut node.hash);
+            }
+        });
+        mutations.add(&format!("proof {}: inserted hashes swapped", i), |p| {
+            let inserted = &mut p.proofs[i].inserted;
+            if inserted.len() > 1 && inserted[0].hash != inserted[1].hash {
+                let hash = inserted[0].hash;
+                inserted[0].hash = inserted[1].hash;
+                inserted[1].hash = hash;
+            }
+        });
+        mutations.add(&format!("proof {}: inserted node dropped", i), |p| {
+            p.proofs[i].inserted.pop();
+        });
+        mutations.add(
+            &format!("proof {}: unchanged node moved to inserted", i),
+            |p| {
+                if let Some(node) = p.proofs[i].unchanged_nodes.pop() {
+                    p.proofs[i].inserted.push(node);
+                }
+            },
+        );
+    }
+    mutations.add("first two proofs swapped", |p| {
+        if p.proofs.len() > 1 {
+            p.proofs.swap(0, 1);
+        }
+    });
+    mutations.mutations
+}
+
+fn add_membership_mutations<T: Clone + PartialEq>(
+    mutations: &mut Mutations<'_, T>,
+    name: &str,
+    access: impl Fn(&mut T) -> &mut MembershipProof + Copy,
+) {
+    mutations.add(&format!("{}: hash bit flipped", name), |p| {
+        flip_digest_bit(&mut access(p).hash_val)
+    });
+    mutations.add(&format!("{}: label bit flipped", name), |p| {
+        access(p).label.label_val[0] ^= 1
+    });
+    mutations.add(&format!("{}: sibling hash bit flipped", name), |p| {
+        if let Some(layer) = access(p).layer_proofs.first_mut() {
+            flip_digest_bit(&mut layer.siblings[0].hash);
+        }
+    });
+    mutations.add(&format!("{}: layer dropped", name), |p| {
+        access(p).layer_proofs.pop();
+    });
+}
+
+fn add_non_membership_mutations<T: Clone + PartialEq>(
+    mutations: &mut Mutations<'_, T>,
+    name: &str,
+    access: impl Fn(&mut T) -> &mut NonMembershipProof + Copy,
+) {
+    mutations.add(&format!("{}: children swapped", name), |p| {
+        access(p).longest_prefix_children.swap(0, 1)
+    });
+    mutations.add(&format!("{}: child hash bit flipped", name), |p| {
+        flip_digest_bit(&mut access(p).longest_prefix_children[0].hash)
+    });
+    add_membership_mutations(
+        mutations,
+        &format!("{}: longest prefix membership proof", name),
+        move |p| &mut access(p).longest_prefix_membership_proof,
+    );
+}
+
+/// Asserts that every corruption of a valid [LookupProof] fails to verify
+pub fn assert_lookup_mutations_fail(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    akd_label: &AkdLabel,
+    proof: &LookupProof,
+) {
+    for mutation in lookup_proof_mutations(proof) {
+        if akd::client::lookup_verify(vrf_public_key, root_hash, akd_label.clone(), mutation.proof)
+            .is_ok()
+        {
+            panic!(
+                "Lookup proof with corruption \"{}\" still verified",
+                mutation.description
+            );
+        }
+    }
+}
+
+/// Asserts that every corruption of a valid [HistoryProof] fails to verify
+pub fn assert_history_mutations_fail(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    current_epoch: u64,
+    akd_label: &AkdLabel,
+    proof: &HistoryProof,
+    params: HistoryVerificationParams,
+) {
+    for mutation in history_proof_mutations(proof) {
+        if akd::client::key_history_verify(
+            vrf_public_key,
+            root_hash,
+            current_epoch,
+            akd_label.clone(),
+            mutation.proof,
+            params,
+        )
+        .is_ok()
+        {
+            panic!(
+                "History proof with corruption \"{}\" still verified",
+                mutation.description
+            );
+        }
+    }
+}
+
+/// Asserts that every corruption of a valid [AppendOnlyProof] fails to verify
+pub async fn assert_audit_mutations_fail(hashes: Vec<Digest>, proof: &AppendOnlyProof) {
+    for mutation in append_only_proof_mutations(proof) {
+        if akd::auditor::audit_verify(hashes.clone(), mutation.proof)
+            .await
+            .is_ok()
+        {
+            panic!(
+                "Append-only proof with corruption \"{}\" still verified",
+                mutation.description
+            );
+        }
+    }
+}
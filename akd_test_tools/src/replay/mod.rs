@@ -0,0 +1,174 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Rebuilds a directory from a commitment log, for validating disaster recovery.
+//!
+//! A commitment log records, for every epoch published, the updates published to
+//! reach the epoch along with the [RootHashRecord] archived by the directory for it.
+//! Replaying the log publishes the updates, epoch by epoch, against a fresh storage
+//! and checks that each epoch results in the archived root hash. Since the leaves
+//! of the tree commit to the values with a key derived from the VRF key, the log
+//! must be replayed with the VRF key of the original directory.
+//!
+//! The log is stored as a stream of YAML documents, one per epoch, see [read_log]
+//! and [write_log].
+
+use akd::ecvrf::VRFKeyStorage;
+use akd::errors::AkdError;
+use akd::storage::types::RootHashRecord;
+use akd::storage::{Database, StorageManager};
+use akd::{AkdLabel, AkdValue, Digest, Directory};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+/// The record of one epoch in a commitment log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// The updates published to reach the epoch
+    pub updates: Vec<(AkdLabel, AkdValue)>,
+    /// The root hash archived by the directory for the epoch
+    pub archived: RootHashRecord,
+}
+
+/// The reason a replay failed
+#[derive(Debug)]
+pub enum ReplayError {
+    /// An operation on the directory failed
+    Akd(AkdError),
+    /// The log could not be read or written
+    Log(String),
+    /// The log skips or repeats an epoch, or the storage isn't empty
+    UnexpectedEpoch {
+        /// The epoch the next entry should be for
+        expected: u64,
+        /// The epoch of the next entry
+        found: u64,
+    },
+    /// Replaying an epoch resulted in a different root hash than the archived one
+    RootHashMismatch {
+        /// The epoch replayed
+        epoch: u64,
+        /// The archived root hash
+        expected: Digest,
+        /// The root hash of the replayed epoch
+        actual: Digest,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Akd(err) => write!(f, "Replay failed: {}", err),
+            Self::Log(message) => write!(f, "Invalid commitment log: {}", message),
+            Self::UnexpectedEpoch { expected, found } => write!(
+                f,
+                "Expected a log entry for epoch {}, found epoch {}",
+                expected, found
+            ),
+            Self::RootHashMismatch {
+                epoch,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Root hash mismatch at epoch {}: archived {:?}, replayed {:?}",
+                epoch, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<AkdError> for ReplayError {
+    fn from(err: AkdError) -> Self {
+        Self::Akd(err)
+    }
+}
+
+/// The outcome of a successful replay
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    /// The number of epochs replayed
+    pub epochs_replayed: u64,
+    /// The total number of updates published
+    pub updates_replayed: u64,
+    /// The root hash of the last epoch replayed
+    pub root_hash: Digest,
+    /// How long the replay took
+    pub elapsed: Duration,
+}
+
+/// Reads a commitment log, in increasing epoch order
+pub fn read_log<R: Read>(reader: R) -> Result<Vec<LogEntry>, ReplayError> {
+    serde_yaml::Deserializer::from_reader(reader)
+        .map(|document| {
+            LogEntry::deserialize(document).map_err(|err| ReplayError::Log(err.to_string()))
+        })
+        .collect()
+}
+
+/// Writes a commitment log, one YAML document per entry
+pub fn write_log<W: Write>(mut writer: W, entries: &[LogEntry]) -> Result<(), ReplayError> {
+    for entry in entries {
+        serde_yaml::to_writer(&mut writer, entry)
+            .map_err(|err| ReplayError::Log(err.to_string()))?;
+        writeln!(writer).map_err(|err| ReplayError::Log(err.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Replays a commitment log against the given storage, which must be empty, checking
+/// the root hash of every epoch against the archived one. The replay stops at the
+/// first epoch which doesn't match.
+pub async fn replay<S: Database + 'static, V: VRFKeyStorage>(
+    storage: &StorageManager<S>,
+    vrf: &V,
+    log: Vec<LogEntry>,
+) -> Result<ReplayReport, ReplayError> {
+    let start = Instant::now();
+    let dir = Directory::<_, _>::new(storage.clone(), vrf.clone(), false).await?;
+    let azks = dir.retrieve_current_azks().await?;
+    let mut report = ReplayReport {
+        root_hash: dir.get_root_hash(&azks).await?,
+        ..Default::default()
+    };
+    let mut epoch = azks.get_latest_epoch();
+
+    for entry in log {
+        if entry.archived.epoch != epoch + 1 {
+            return Err(ReplayError::UnexpectedEpoch {
+                expected: epoch + 1,
+                found: entry.archived.epoch,
+            });
+        }
+        let num_updates = entry.updates.len() as u64;
+        let epoch_hash = dir.publish(entry.updates).await?;
+        epoch = epoch_hash.epoch();
+        if epoch != entry.archived.epoch || epoch_hash.hash() != entry.archived.root_hash {
+            return Err(ReplayError::RootHashMismatch {
+                epoch: entry.archived.epoch,
+                expected: entry.archived.root_hash,
+                actual: epoch_hash.hash(),
+            });
+        }
+        debug!("Replayed epoch {} ({} updates)", epoch, num_updates);
+        report.epochs_replayed += 1;
+        report.updates_replayed += num_updates;
+        report.root_hash = epoch_hash.hash();
+    }
+
+    report.elapsed = start.elapsed();
+    info!("Replay completed: {:?}", report);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,84 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! Tests of the commitment log replay
+
+use akd::directory::Directory;
+use akd::ecvrf::HardCodedAkdVRF;
+use akd::storage::{memory::AsyncInMemoryDatabase, StorageManager};
+use akd::{AkdLabel, AkdValue};
+
+use crate::replay::{read_log, replay, write_log, LogEntry, ReplayError};
+
+async fn publish_log(num_epochs: u64) -> Vec<LogEntry> {
+    let storage = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    let akd = Directory::<_, _>::new(storage, HardCodedAkdVRF {}, false)
+        .await
+        .unwrap();
+    let mut updates = vec![];
+    for epoch in 1..=num_epochs {
+        let batch = (0..epoch)
+            .map(|user| {
+                (
+                    AkdLabel::from_utf8_str(&format!("user{}", user)),
+                    AkdValue::from_utf8_str(&format!("value{}", epoch)),
+                )
+            })
+            .collect::<Vec<_>>();
+        akd.publish(batch.clone()).await.unwrap();
+        updates.push(batch);
+    }
+    let archived = akd.root_hash_history(1..).await.unwrap();
+    updates
+        .into_iter()
+        .zip(archived)
+        .map(|(updates, archived)| LogEntry { updates, archived })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_replay() {
+    let log = publish_log(5).await;
+    let mut buffer = vec![];
+    write_log(&mut buffer, &log).unwrap();
+    assert_eq!(log, read_log(&buffer[..]).unwrap());
+
+    let storage = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    let report = replay(&storage, &HardCodedAkdVRF {}, log.clone())
+        .await
+        .unwrap();
+    assert_eq!(5, report.epochs_replayed);
+    assert_eq!(15, report.updates_replayed);
+    assert_eq!(log[4].archived.root_hash, report.root_hash);
+
+    // a tampered update results in a different root hash
+    let mut tampered = log.clone();
+    tampered[2].updates[0].1 = AkdValue::from_utf8_str("tampered");
+    let storage = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    let err = replay(&storage, &HardCodedAkdVRF {}, tampered)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ReplayError::RootHashMismatch { epoch: 3, .. }
+    ));
+
+    // the log must not skip an epoch
+    let mut gapped = log;
+    gapped.remove(1);
+    let storage = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    let err = replay(&storage, &HardCodedAkdVRF {}, gapped)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ReplayError::UnexpectedEpoch {
+            expected: 2,
+            found: 3
+        }
+    ));
+}
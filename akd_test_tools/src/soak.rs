@@ -0,0 +1,336 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is licensed under both the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree and the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree.
+
+//! A long-running soak test of a storage-backed directory.
+//!
+//! The driver continuously publishes random updates, and in between serves and
+//! verifies lookups, key histories and audits, periodically restarting the directory
+//! over the same storage. The published values are tracked in memory, and the
+//! following invariants are checked throughout:
+//!
+//! * every publish advances the directory by exactly one epoch
+//! * the chain of root hashes verifies with the audit proofs
+//! * lookups and histories verify, and serve the latest published value
+//! * the history of a label never regresses to an older version
+//! * a restarted directory serves the same root hash as before the restart
+//!
+//! Failures can be injected through [SoakHooks], which are given the storage before
+//! each publish and restart. A publish failing with a retryable error (see
+//! [AkdError::is_retryable]) is retried, any other error ends the soak test.
+
+use akd::ecvrf::VRFKeyStorage;
+use akd::errors::AkdError;
+use akd::storage::{Database, StorageManager};
+use akd::{AkdLabel, AkdValue, Directory, EpochHash, HistoryParams, HistoryVerificationParams};
+use async_trait::async_trait;
+use log::{debug, info};
+use rand::rngs::StdRng;
+use rand::seq::IteratorRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The parameters of a soak test
+#[derive(Debug, Clone)]
+pub struct SoakConfig {
+    /// The soak test stops after this long
+    pub duration: Duration,
+    /// The soak test stops after this many epochs, if set
+    pub max_epochs: Option<u64>,
+    /// The number of distinct labels updated over the soak test
+    pub num_users: usize,
+    /// The number of labels updated in each epoch
+    pub updates_per_epoch: usize,
+    /// The number of lookups verified after each publish
+    pub lookups_per_epoch: usize,
+    /// The number of key histories verified after each publish
+    pub histories_per_epoch: usize,
+    /// An audit proof since the previous audit is verified every this many epochs
+    pub audit_every: u64,
+    /// The directory is restarted every this many epochs
+    pub restart_every: u64,
+    /// The number of times a publish failing with a retryable error is retried
+    pub max_publish_retries: usize,
+    /// The seed of the random choices, to reproduce a run
+    pub seed: Option<u64>,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(60),
+            max_epochs: None,
+            num_users: 100,
+            updates_per_epoch: 10,
+            lookups_per_epoch: 5,
+            histories_per_epoch: 2,
+            audit_every: 1,
+            restart_every: 10,
+            max_publish_retries: 5,
+            seed: None,
+        }
+    }
+}
+
+/// Hooks into a soak test, which may inject failures. All of them do nothing by default.
+#[async_trait]
+pub trait SoakHooks<S: Database>: Send + Sync {
+    /// Called before each attempt to publish the given epoch. Returning an error
+    /// fails the attempt as if the publish itself had failed.
+    async fn before_publish(
+        &self,
+        _storage: &StorageManager<S>,
+        _epoch: u64,
+    ) -> Result<(), AkdError> {
+        Ok(())
+    }
+
+    /// Called after each successful publish
+    async fn after_publish(&self, _storage: &StorageManager<S>, _epoch_hash: &EpochHash) {}
+
+    /// Called before the directory is restarted at the given epoch
+    async fn before_restart(&self, _storage: &StorageManager<S>, _epoch: u64) {}
+}
+
+/// Hooks which don't inject any failure
+pub struct NoFailures;
+
+impl<S: Database> SoakHooks<S> for NoFailures {}
+
+/// The reason a soak test stopped early
+#[derive(Debug)]
+pub enum SoakError {
+    /// An operation on the directory failed
+    Akd(AkdError),
+    /// An invariant of the directory was violated
+    Invariant {
+        /// The epoch at which the violation was detected
+        epoch: u64,
+        /// A description of the violation
+        message: String,
+    },
+}
+
+impl fmt::Display for SoakError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Akd(err) => write!(f, "Soak test failed: {}", err),
+            Self::Invariant { epoch, message } => {
+                write!(
+                    f,
+                    "Soak test invariant violated at epoch {}: {}",
+                    epoch, message
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SoakError {}
+
+impl From<AkdError> for SoakError {
+    fn from(err: AkdError) -> Self {
+        Self::Akd(err)
+    }
+}
+
+impl From<akd::verify::VerificationError> for SoakError {
+    fn from(err: akd::verify::VerificationError) -> Self {
+        Self::Akd(err.into())
+    }
+}
+
+/// The operations performed by a successful soak test
+#[derive(Debug, Clone, Default)]
+pub struct SoakReport {
+    /// The number of epochs published
+    pub epochs_published: u64,
+    /// The number of publish attempts which failed and were retried
+    pub publish_retries: u64,
+    /// The number of lookup proofs verified
+    pub lookups_verified: u64,
+    /// The number of history proofs verified
+    pub histories_verified: u64,
+    /// The number of audit proofs verified
+    pub audits_verified: u64,
+    /// The number of times the directory was restarted
+    pub restarts: u64,
+    /// How long the soak test ran for
+    pub elapsed: Duration,
+}
+
+fn check(condition: bool, epoch: u64, message: impl FnOnce() -> String) -> Result<(), SoakError> {
+    if condition {
+        Ok(())
+    } else {
+        Err(SoakError::Invariant {
+            epoch,
+            message: message(),
+        })
+    }
+}
+
+/// Runs a soak test against the given storage, which must be empty, until the
+/// configured duration elapses or the maximum number of epochs is published.
+pub async fn soak_test<S: Database + 'static, V: VRFKeyStorage>(
+    storage: &StorageManager<S>,
+    vrf: &V,
+    config: &SoakConfig,
+    hooks: &dyn SoakHooks<S>,
+) -> Result<SoakReport, SoakError> {
+    let start = Instant::now();
+    let mut rng = match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut report = SoakReport::default();
+
+    let mut dir = Directory::<_, _>::new(storage.clone(), vrf.clone(), false).await?;
+    let vrf_pk = dir.get_public_key().await?;
+    let azks = dir.retrieve_current_azks().await?;
+    let mut epoch = azks.get_latest_epoch();
+    check(epoch == 0, epoch, || {
+        "The soak test must start from an empty storage".to_string()
+    })?;
+    let mut root_hashes = vec![dir.get_root_hash(&azks).await?];
+    let mut last_audit = 0u64;
+
+    let users = (0..config.num_users)
+        .map(|user| AkdLabel::from_utf8_str(&format!("soak-user-{}", user)))
+        .collect::<Vec<_>>();
+    // the latest version and value published for each label
+    let mut published = HashMap::<AkdLabel, (u64, AkdValue)>::new();
+    // the latest version served in the history of each label
+    let mut served_versions = HashMap::<AkdLabel, u64>::new();
+
+    while start.elapsed() < config.duration
+        && !matches!(config.max_epochs, Some(max) if epoch >= max)
+    {
+        let next_epoch = epoch + 1;
+        let updates = users
+            .iter()
+            .choose_multiple(&mut rng, config.updates_per_epoch)
+            .into_iter()
+            .map(|label| {
+                let nonce: u64 = rng.gen();
+                let value = AkdValue::from_utf8_str(&format!("{}-{}", next_epoch, nonce));
+                (label.clone(), value)
+            })
+            .collect::<Vec<_>>();
+
+        let mut attempt = 0;
+        let epoch_hash = loop {
+            let result = match hooks.before_publish(storage, next_epoch).await {
+                Ok(()) => dir.publish(updates.clone()).await,
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(epoch_hash) => break epoch_hash,
+                Err(err) if err.is_retryable() && attempt < config.max_publish_retries => {
+                    debug!("Retrying the publish of epoch {}: {}", next_epoch, err);
+                    attempt += 1;
+                    report.publish_retries += 1;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        };
+        check(epoch_hash.epoch() == next_epoch, next_epoch, || {
+            format!("The publish resulted in epoch {}", epoch_hash.epoch())
+        })?;
+        epoch = next_epoch;
+        report.epochs_published += 1;
+        root_hashes.push(epoch_hash.hash());
+        for (label, value) in updates {
+            let version = published.get(&label).map_or(0, |(version, _)| *version) + 1;
+            published.insert(label, (version, value));
+        }
+        hooks.after_publish(storage, &epoch_hash).await;
+
+        for label in published
+            .keys()
+            .choose_multiple(&mut rng, config.lookups_per_epoch)
+        {
+            let (proof, root_hash) = dir.lookup(label.clone()).await?;
+            check(root_hash == epoch_hash, epoch, || {
+                format!("Lookup of {:?} served at {:?}", label, root_hash)
+            })?;
+            let result = akd::client::lookup_verify(
+                vrf_pk.as_bytes(),
+                root_hash.hash(),
+                label.clone(),
+                proof,
+            )?;
+            let (version, value) = &published[label];
+            check(
+                result.version == *version && result.value == *value,
+                epoch,
+                || format!("Lookup of {:?} served version {}", label, result.version),
+            )?;
+            report.lookups_verified += 1;
+        }
+
+        for label in published
+            .keys()
+            .choose_multiple(&mut rng, config.histories_per_epoch)
+        {
+            let (proof, root_hash) = dir.key_history(label, HistoryParams::default()).await?;
+            let results = akd::client::key_history_verify(
+                vrf_pk.as_bytes(),
+                root_hash.hash(),
+                root_hash.epoch(),
+                label.clone(),
+                proof,
+                HistoryVerificationParams::default(),
+            )?;
+            let latest = results[0].version;
+            let previous = served_versions.insert(label.clone(), latest).unwrap_or(0);
+            check(latest >= previous, epoch, || {
+                format!(
+                    "History of {:?} regressed from version {} to {}",
+                    label, previous, latest
+                )
+            })?;
+            let (version, value) = &published[label];
+            check(
+                latest == *version
+                    && results[0].value == *value
+                    && results.len() as u64 == *version,
+                epoch,
+                || format!("History of {:?} served version {}", label, latest),
+            )?;
+            report.histories_verified += 1;
+        }
+
+        if epoch - last_audit >= config.audit_every {
+            let proof = dir.audit(last_audit, epoch).await?;
+            let hashes = root_hashes[last_audit as usize..=epoch as usize].to_vec();
+            akd::auditor::audit_verify(hashes, proof).await?;
+            last_audit = epoch;
+            report.audits_verified += 1;
+        }
+
+        if epoch % config.restart_every == 0 {
+            hooks.before_restart(storage, epoch).await;
+            storage.flush_cache().await;
+            dir = Directory::<_, _>::new(storage.clone(), vrf.clone(), false).await?;
+            let azks = dir.retrieve_current_azks().await?;
+            let root_hash = dir.get_root_hash(&azks).await?;
+            check(
+                azks.get_latest_epoch() == epoch && root_hash == epoch_hash.hash(),
+                epoch,
+                || "The restarted directory serves a different root hash".to_string(),
+            )?;
+            report.restarts += 1;
+            info!("Soak test restarted the directory at epoch {}", epoch);
+        }
+    }
+
+    report.elapsed = start.elapsed();
+    info!("Soak test completed: {:?}", report);
+    Ok(report)
+}
@@ -7,18 +7,26 @@ extern crate thread_id;
 // License, Version 2.0 found in the LICENSE-APACHE file in the root directory
 // of this source tree.
 
+use crate::proof_mutation;
 use akd::ecvrf::VRFKeyStorage;
-use akd::storage::Database;
+use akd::errors::StorageError;
+use akd::storage::memory::AsyncInMemoryDatabase;
+use akd::storage::types::{DbRecord, KeyData, StorageType, ValueState, ValueStateRetrievalFlag};
+use akd::storage::{Database, DbSetState, RecordStream, Storable, StorageManager};
 use akd::Directory;
 use akd::HistoryParams;
-use akd::{AkdLabel, AkdValue};
+use akd::{AkdLabel, AkdValue, EpochHash};
+use async_trait::async_trait;
 use rand::distributions::Alphanumeric;
 use rand::seq::IteratorRandom;
 use rand::{thread_rng, Rng};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 /// The suite of tests to run against a fully-instantated and storage-backed directory.
 /// This will publish 3 epochs of ```num_users``` records and
-/// perform 10 random lookup proofs + 2 random history proofs + and audit proof from epochs 1u64 -> 2u64
+/// perform 10 random lookup proofs + 2 random history proofs + and audit proof from epochs 1u64 -> 2u64,
+/// checking that corrupted versions of each of the proofs fail to verify
 pub async fn directory_test_suite<S: Database + 'static, V: VRFKeyStorage>(
     mysql_db: &akd::storage::StorageManager<S>,
     num_users: usize,
@@ -70,11 +78,17 @@ pub async fn directory_test_suite<S: Database + 'static, V: VRFKeyStorage>(
                         if let Err(error) = akd::client::lookup_verify(
                             vrf_pk.as_bytes(),
                             root_hash.hash(),
-                            key,
-                            proof,
+                            key.clone(),
+                            proof.clone(),
                         ) {
                             panic!("Lookup proof failed to verify {:?}", error);
                         }
+                        proof_mutation::assert_lookup_mutations_fail(
+                            vrf_pk.as_bytes(),
+                            root_hash.hash(),
+                            &key,
+                            &proof,
+                        );
                     }
                 }
             }
@@ -90,12 +104,20 @@ pub async fn directory_test_suite<S: Database + 'static, V: VRFKeyStorage>(
                             vrf_pk.as_bytes(),
                             root_hash.hash(),
                             root_hash.epoch(),
-                            key,
-                            proof,
+                            key.clone(),
+                            proof.clone(),
                             akd::HistoryVerificationParams::default(),
                         ) {
                             panic!("History proof failed to verify {:?}", error);
                         }
+                        proof_mutation::assert_history_mutations_fail(
+                            vrf_pk.as_bytes(),
+                            root_hash.hash(),
+                            root_hash.epoch(),
+                            &key,
+                            &proof,
+                            akd::HistoryVerificationParams::default(),
+                        );
                     }
                 }
             }
@@ -115,10 +137,12 @@ pub async fn directory_test_suite<S: Database + 'static, V: VRFKeyStorage>(
                     match (start_root_hash, end_root_hash) {
                         (Ok(start), Ok(end)) => {
                             if let Err(error) =
-                                akd::auditor::audit_verify(vec![*start, *end], proof).await
+                                akd::auditor::audit_verify(vec![*start, *end], proof.clone()).await
                             {
                                 panic!("Error validating audit proof {:?}", error);
                             }
+                            proof_mutation::assert_audit_mutations_fail(vec![*start, *end], &proof)
+                                .await;
                         }
                         (Err(err), _) => {
                             panic!("Error retrieving root hash at epoch {:?}", err);
@@ -132,3 +156,280 @@ pub async fn directory_test_suite<S: Database + 'static, V: VRFKeyStorage>(
         }
     }
 }
+
+/// A [Database] which crashes once a number of tree nodes have been written to it, as if
+/// the process had been killed mid-write. The batch crossing the limit is torn: its records
+/// are written up to the last tree node within the limit, and the rest of it is lost, as is
+/// every write after it. Reads are passed through to the wrapped database.
+#[derive(Clone)]
+pub struct CrashingDatabase<Db: Database> {
+    db: Db,
+    remaining_node_writes: Arc<Mutex<Option<usize>>>,
+}
+
+impl<Db: Database> CrashingDatabase<Db> {
+    /// Wraps a database, crashing after `node_writes` tree nodes have been written to it
+    pub fn new(db: Db, node_writes: usize) -> Self {
+        Self {
+            db,
+            remaining_node_writes: Arc::new(Mutex::new(Some(node_writes))),
+        }
+    }
+
+    /// Whether the database has crashed
+    pub fn crashed(&self) -> bool {
+        self.remaining_node_writes.lock().unwrap().is_none()
+    }
+
+    /// Counts the tree nodes of a batch towards the limit, returning the number of
+    /// leading records of the batch written before the database crashed, if it did
+    fn crashes_on(&self, records: &[DbRecord]) -> Option<usize> {
+        let mut remaining = self.remaining_node_writes.lock().unwrap();
+        let budget = match *remaining {
+            Some(budget) => budget,
+            None => return Some(0),
+        };
+        let mut node_writes = 0;
+        for (i, record) in records.iter().enumerate() {
+            if matches!(record, DbRecord::TreeNode(_)) {
+                if node_writes == budget {
+                    *remaining = None;
+                    return Some(i);
+                }
+                node_writes += 1;
+            }
+        }
+        *remaining = Some(budget - node_writes);
+        None
+    }
+
+    /// Writes the leading records of a batch torn by a crash, then fails
+    async fn write_torn(
+        &self,
+        mut records: Vec<DbRecord>,
+        written: usize,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        records.truncate(written);
+        if !records.is_empty() {
+            self.db.batch_set(records, state).await?;
+        }
+        Err(crash_error())
+    }
+}
+
+fn crash_error() -> StorageError {
+    StorageError::Connection("The database crashed".to_string())
+}
+
+#[async_trait]
+impl<Db: Database + 'static> Database for CrashingDatabase<Db> {
+    async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+        self.batch_set(vec![record], DbSetState::General).await
+    }
+
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        if let Some(written) = self.crashes_on(&records) {
+            return self.write_torn(records, written, state).await;
+        }
+        self.db.batch_set(records, state).await
+    }
+
+    async fn compare_and_set(
+        &self,
+        record: DbRecord,
+        expected_version: Option<u64>,
+        others: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        // the record compared is written last, so it's lost along with the batch
+        if let Some(written) = self.crashes_on(&others) {
+            return self.write_torn(others, written, state).await;
+        }
+        self.db
+            .compare_and_set(record, expected_version, others, state)
+            .await
+    }
+
+    async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
+        self.db.get::<St>(id).await
+    }
+
+    async fn batch_get<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        self.db.batch_get::<St>(ids).await
+    }
+
+    async fn batch_delete<St: Storable>(&self, ids: &[St::StorageKey]) -> Result<(), StorageError> {
+        if self.crashed() {
+            return Err(crash_error());
+        }
+        self.db.batch_delete::<St>(ids).await
+    }
+
+    async fn get_user_data(&self, username: &AkdLabel) -> Result<KeyData, StorageError> {
+        self.db.get_user_data(username).await
+    }
+
+    async fn get_user_state(
+        &self,
+        username: &AkdLabel,
+        flag: ValueStateRetrievalFlag,
+    ) -> Result<ValueState, StorageError> {
+        self.db.get_user_state(username, flag).await
+    }
+
+    async fn get_user_state_versions(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+    ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        self.db.get_user_state_versions(usernames, flag).await
+    }
+
+    fn iter_by_prefix(&self, storage_type: StorageType, key_prefix: &[u8]) -> RecordStream<'_> {
+        self.db.iter_by_prefix(storage_type, key_prefix)
+    }
+
+    async fn with_namespace(&self, namespace: &[u8]) -> Result<Self, StorageError> {
+        Ok(Self {
+            db: self.db.with_namespace(namespace).await?,
+            remaining_node_writes: self.remaining_node_writes.clone(),
+        })
+    }
+}
+
+/// Checks that the latest value of each label looks up and verifies against the root hash
+async fn assert_latest_values<S: Database + 'static, V: VRFKeyStorage>(
+    dir: &Directory<S, V>,
+    root_hash: &EpochHash,
+    values: &[(AkdLabel, AkdValue)],
+) {
+    let vrf_pk = dir.get_public_key().await.unwrap();
+    for (label, value) in values {
+        let (proof, lookup_root_hash) = dir
+            .lookup(label.clone())
+            .await
+            .unwrap_or_else(|error| panic!("Error looking up user information {:?}", error));
+        assert_eq!(root_hash, &lookup_root_hash);
+        match akd::client::lookup_verify(vrf_pk.as_bytes(), root_hash.hash(), label.clone(), proof)
+        {
+            Ok(result) => assert_eq!(value, &result.value),
+            Err(error) => panic!("Lookup proof failed to verify {:?}", error),
+        }
+    }
+}
+
+/// The suite of crash-recovery tests to run against a storage-backed directory. After an
+/// initial epoch of ```num_users``` records, every crash point publishes another epoch
+/// of updates to all the users, killing the publish after that many tree nodes were written.
+/// The directory is then restarted over the same storage, and must either have fully
+/// rolled back to the previous epoch, in which case publishing the updates again must
+/// succeed, or have completed the publish. Either way, the directory must end up with the
+/// same root hash as a directory which never crashed, and serve verifying lookups and audits.
+pub async fn crash_recovery_test_suite<S: Database + 'static, V: VRFKeyStorage>(
+    db: &S,
+    num_users: usize,
+    crash_points: &[usize],
+    vrf: &V,
+) {
+    let users = (0..num_users)
+        .map(|_| {
+            AkdLabel::from_utf8_str(
+                &thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(30)
+                    .collect::<String>(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let updates = |epoch: usize| {
+        users
+            .iter()
+            .map(|user| (user.clone(), AkdValue(format!("{}", epoch).into_bytes())))
+            .collect::<Vec<_>>()
+    };
+
+    // the directory which never crashes, publishing the same updates
+    let reference = Directory::<_, _>::new(
+        StorageManager::new_no_cache(AsyncInMemoryDatabase::new()),
+        vrf.clone(),
+        false,
+    )
+    .await
+    .unwrap();
+    let dir = Directory::<_, _>::new(StorageManager::new_no_cache(db.clone()), vrf.clone(), false)
+        .await
+        .unwrap_or_else(|error| panic!("Error initializing directory: {:?}", error));
+    let mut root_hash = dir
+        .publish(updates(1))
+        .await
+        .unwrap_or_else(|error| panic!("Error publishing batch {:?}", error));
+    assert_eq!(reference.publish(updates(1)).await.unwrap(), root_hash);
+    let mut root_hashes = vec![root_hash.hash()];
+
+    for (i, crash_point) in crash_points.iter().enumerate() {
+        let epoch = i + 2;
+        let expected_root_hash = reference.publish(updates(epoch)).await.unwrap();
+
+        let crashing_db = CrashingDatabase::new(db.clone(), *crash_point);
+        let crashing_dir = Directory::<_, _>::new(
+            StorageManager::new_no_cache(crashing_db.clone()),
+            vrf.clone(),
+            false,
+        )
+        .await
+        .unwrap();
+        let published = crashing_dir.publish(updates(epoch)).await;
+        assert_eq!(published.is_err(), crashing_db.crashed());
+        drop(crashing_dir);
+
+        // restart the directory over the storage left by the crash
+        let dir =
+            Directory::<_, _>::new(StorageManager::new_no_cache(db.clone()), vrf.clone(), false)
+                .await
+                .unwrap_or_else(|error| panic!("Error restarting directory: {:?}", error));
+        let azks = dir.retrieve_current_azks().await.unwrap();
+        let restarted_root_hash = EpochHash(
+            azks.get_latest_epoch(),
+            dir.get_root_hash(&azks).await.unwrap(),
+        );
+        if published.is_err() {
+            log::info!("Publish crashed after {} node writes", crash_point);
+            // the publish was fully rolled back
+            assert_eq!(root_hash, restarted_root_hash);
+            assert_latest_values(&dir, &root_hash, &updates(epoch - 1)).await;
+            // and resumes
+            root_hash = dir
+                .publish(updates(epoch))
+                .await
+                .unwrap_or_else(|error| panic!("Error resuming publish {:?}", error));
+        } else {
+            root_hash = restarted_root_hash;
+        }
+        assert_eq!(expected_root_hash, root_hash);
+        assert_latest_values(&dir, &root_hash, &updates(epoch)).await;
+        root_hashes.push(root_hash.hash());
+    }
+
+    // audit the whole chain of epochs, across the crashes
+    if root_hashes.len() > 1 {
+        let dir =
+            Directory::<_, _>::new(StorageManager::new_no_cache(db.clone()), vrf.clone(), false)
+                .await
+                .unwrap();
+        let proof = dir
+            .audit(1, root_hash.epoch())
+            .await
+            .unwrap_or_else(|error| panic!("Error perform audit proof retrieval {:?}", error));
+        if let Err(error) = akd::auditor::audit_verify(root_hashes, proof).await {
+            panic!("Error validating audit proof {:?}", error);
+        }
+    }
+}
@@ -5,8 +5,11 @@
 // License, Version 2.0 found in the LICENSE-APACHE file in the root directory
 // of this source tree.
 
+use akd::errors::{AkdError, StorageError};
 use akd::{ecvrf::HardCodedAkdVRF, storage::StorageManager};
+use akd_test_tools::soak::{soak_test, NoFailures, SoakConfig, SoakHooks};
 use log::info;
+use std::sync::atomic::Ordering;
 
 type InMemoryDb = akd::storage::memory::AsyncInMemoryDatabase;
 
@@ -49,3 +52,76 @@ async fn test_directory_operations_with_caching() {
 
     info!("\n\n******** Finished In-Memory Directory Operations (w/caching) Integration Test ********\n\n");
 }
+
+#[tokio::test]
+async fn test_crash_recovery() {
+    crate::test_util::log_init(log::Level::Info);
+
+    info!("\n\n******** Starting In-Memory Crash Recovery Integration Test ********\n\n");
+
+    akd_test_tools::test_suites::crash_recovery_test_suite::<_, HardCodedAkdVRF>(
+        &InMemoryDb::new(),
+        50,
+        &[0, 1, 25, 100, usize::MAX],
+        &HardCodedAkdVRF {},
+    )
+    .await;
+
+    info!("\n\n******** Finished In-Memory Crash Recovery Integration Test ********\n\n");
+}
+
+/// Fails the first attempt to publish each epoch with a transient storage error
+struct FlakyPublishes {
+    failed_epoch: std::sync::atomic::AtomicU64,
+}
+
+#[async_trait::async_trait]
+impl SoakHooks<InMemoryDb> for FlakyPublishes {
+    async fn before_publish(
+        &self,
+        _storage: &StorageManager<InMemoryDb>,
+        epoch: u64,
+    ) -> Result<(), AkdError> {
+        if self.failed_epoch.swap(epoch, Ordering::SeqCst) == epoch {
+            Ok(())
+        } else {
+            Err(AkdError::Storage(StorageError::Connection(format!(
+                "Injected failure at epoch {}",
+                epoch
+            ))))
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_soak() {
+    crate::test_util::log_init(log::Level::Info);
+
+    let config = SoakConfig {
+        max_epochs: Some(12),
+        num_users: 50,
+        restart_every: 4,
+        audit_every: 3,
+        seed: Some(42),
+        ..Default::default()
+    };
+
+    let storage_manager = StorageManager::new(InMemoryDb::new(), None, None, None);
+    let report = soak_test(&storage_manager, &HardCodedAkdVRF {}, &config, &NoFailures)
+        .await
+        .unwrap();
+    assert_eq!(12, report.epochs_published);
+    assert_eq!(3, report.restarts);
+    assert_eq!(4, report.audits_verified);
+    assert_eq!(0, report.publish_retries);
+
+    let storage_manager = StorageManager::new_no_cache(InMemoryDb::new());
+    let hooks = FlakyPublishes {
+        failed_epoch: std::sync::atomic::AtomicU64::new(0),
+    };
+    let report = soak_test(&storage_manager, &HardCodedAkdVRF {}, &config, &hooks)
+        .await
+        .unwrap();
+    assert_eq!(12, report.epochs_published);
+    assert_eq!(12, report.publish_retries);
+}
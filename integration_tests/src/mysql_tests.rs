@@ -132,6 +132,64 @@ async fn test_directory_operations_with_caching() {
     info!("\n\n******** Completed MySQL Directory Operations (w/caching) Integration Test ********\n\n");
 }
 
+#[tokio::test]
+#[serial_test::serial]
+async fn test_crash_recovery() {
+    crate::test_util::log_init(log::Level::Info);
+
+    info!("\n\n******** Starting MySQL Crash Recovery Integration Test ********\n\n");
+
+    if AsyncMySqlDatabase::test_guard() {
+        // create the "test" database
+        if let Err(error) = AsyncMySqlDatabase::create_test_db(
+            "localhost",
+            Option::from("root"),
+            Option::from("example"),
+            Option::from(8001),
+        )
+        .await
+        {
+            panic!("Error creating test database: {}", error);
+        }
+
+        // connect to the newly created test db
+        let mysql_db = AsyncMySqlDatabase::new(
+            "localhost",
+            "test_db",
+            Option::from("root"),
+            Option::from("example"),
+            Option::from(8001),
+            200,
+        )
+        .await;
+
+        // delete all data from the db
+        if let Err(error) = mysql_db.delete_data().await {
+            error!("Error cleaning mysql prior to test suite: {}", error);
+        }
+
+        akd_test_tools::test_suites::crash_recovery_test_suite::<_, HardCodedAkdVRF>(
+            &mysql_db,
+            50,
+            &[0, 25, usize::MAX],
+            &HardCodedAkdVRF {},
+        )
+        .await;
+
+        // clean the test infra
+        if let Err(mysql_async::Error::Server(error)) = mysql_db.drop_tables().await {
+            error!(
+                "ERROR: Failed to clean MySQL test database with error {}",
+                error
+            );
+        }
+    } else {
+        warn!("WARN: Skipping MySQL test due to test guard noting that the docker container appears to not be running.");
+    }
+
+    info!("\n\n******** Completed MySQL Crash Recovery Integration Test ********\n\n");
+}
+
 #[tokio::test]
 #[serial_test::serial]
 async fn test_lookups() {
@@ -186,3 +244,70 @@ async fn test_lookups() {
 
     info!("\n\n******** Completed MySQL Lookup Tests ********\n\n");
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_soak() {
+    crate::test_util::log_init(log::Level::Info);
+
+    info!("\n\n******** Starting MySQL Soak Test ********\n\n");
+
+    if AsyncMySqlDatabase::test_guard() {
+        // create the "test" database
+        if let Err(error) = AsyncMySqlDatabase::create_test_db(
+            "localhost",
+            Option::from("root"),
+            Option::from("example"),
+            Option::from(8001),
+        )
+        .await
+        {
+            panic!("Error creating test database: {}", error);
+        }
+
+        // connect to the newly created test db
+        let mysql_db = AsyncMySqlDatabase::new(
+            "localhost",
+            "test_db",
+            Option::from("root"),
+            Option::from("example"),
+            Option::from(8001),
+            200,
+        )
+        .await;
+
+        // delete all data from the db
+        if let Err(error) = mysql_db.delete_data().await {
+            error!("Error cleaning mysql prior to test suite: {}", error);
+        }
+
+        let config = akd_test_tools::soak::SoakConfig {
+            max_epochs: Some(10),
+            restart_every: 5,
+            ..Default::default()
+        };
+        let storage_manager = StorageManager::new(mysql_db.clone(), None, None, None);
+        if let Err(error) = akd_test_tools::soak::soak_test(
+            &storage_manager,
+            &HardCodedAkdVRF {},
+            &config,
+            &akd_test_tools::soak::NoFailures,
+        )
+        .await
+        {
+            panic!("{}", error);
+        }
+
+        // clean the test infra
+        if let Err(mysql_async::Error::Server(error)) = mysql_db.drop_tables().await {
+            error!(
+                "ERROR: Failed to clean MySQL test database with error {}",
+                error
+            );
+        }
+    } else {
+        warn!("WARN: Skipping MySQL test due to test guard noting that the docker container appears to not be running.");
+    }
+
+    info!("\n\n******** Completed MySQL Soak Test ********\n\n");
+}